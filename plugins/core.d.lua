@@ -4,6 +4,16 @@
 plugin = { name = "Core", version = "1.0", author = "VoUDP core", description = "Core functionality and LSP stub" }
 Core = {}
 
+-- plugins list what they may do in `plugin.capabilities`, anything else fails:
+--   chat      replies and broadcasts
+--   kick      kicking users
+--   moderate  Core.move, Core.set_mute, Core.set_deafen and ctx:move_to
+--   audio     on_audio
+--   storage   the io library, dofile, loadfile and os.remove/rename/tmpname
+--   network   Core.http_get
+-- os.execute, os.exit, io.popen, package.loadlib and native modules through require are
+-- never available
+
 --- Check if string s starts with prefix
 ---@param s string
 ---@param prefix string
@@ -30,4 +40,65 @@ function Core.error(message) end
 Core.LOOPBACK = ""
 
 --- @type string Protocol version
-Core.PROTOCOL_VERSION = ""
\ No newline at end of file
+Core.PROTOCOL_VERSION = ""
+
+--- Add a slash command, only while the plugin loads. The handler's return
+--- value is the reply, raising an error fails the command
+---@param name string e.g. "/roll"
+---@param usage string e.g. "/roll [sides]"
+---@param admin_only boolean
+---@param handler fun(ctx): string?
+function Core.register_command(name, usage, admin_only, handler) end
+
+--- Say something in chat, to one channel or to everyone on the server
+---@param message string
+---@param channel_id integer?
+function Core.broadcast(message, channel_id) end
+
+--- Call fn once, after a delay
+---@param seconds number
+---@param fn fun()
+---@return integer id for Core.cancel
+function Core.schedule(seconds, fn) return 0 end
+
+--- Call fn over and over, seconds apart
+---@param seconds number
+---@param fn fun()
+---@return integer id for Core.cancel
+function Core.every(seconds, fn) return 0 end
+
+--- Stop a scheduled or repeating call
+---@param id integer
+---@return boolean false if it wasn't pending
+function Core.cancel(id) return false end
+
+--- Every channel, as { id, name, users } where users is how many are in it
+---@return table[]
+function Core.list_channels() return {} end
+
+--- Who's connected, as { mask, addr, channel_id, muted, deafened, admin }
+---@param channel_id integer? only this channel, everyone if nil
+---@return table[]
+function Core.list_users(channel_id) return {} end
+
+--- Move someone to another channel
+---@param mask string
+---@param channel_id integer
+function Core.move(mask, channel_id) end
+
+--- Mute someone from the server side, they can't unmute themselves
+---@param mask string
+---@param muted boolean
+function Core.set_mute(mask, muted) end
+
+--- Deafen someone from the server side, they can't undeafen themselves
+---@param mask string
+---@param deafened boolean
+function Core.set_deafen(mask, deafened) end
+
+--- GET a plain http url in the background. fn gets { status, body } on a
+--- later tick, or nil and an error. Only hosts the operator allowed with
+--- --plugin-http-host can be reached
+---@param url string
+---@param fn fun(response: { status: integer, body: string }?, err: string?)
+function Core.http_get(url, fn) end
@@ -4,7 +4,8 @@ plugin = {
     name = "Test",
     version = "1.0",
     author = "spixa",
-    description = "Test plugin"
+    description = "Test plugin",
+    capabilities = { "chat", "kick" }
 }
 
 local bad_words = {
@@ -3,7 +3,8 @@ plugin = {
     name="Welcomer",
     version="0.1",
     author="spixa",
-    description="Show welcome message when remote joins server"
+    description="Show welcome message when remote joins server",
+    capabilities={"chat", "moderate"}
 }
 
 -- configurations
@@ -13,8 +14,12 @@ local only_general = true
 
 function on_join(ctx) 
     if only_general and ctx:get_channel_id() ~= "1" then
-        Core.warn("Prevented " .. ctx:get_addr() .. " from joining because you can only join the default channel as per the config")
-        ctx:cancel() -- only allow joining general
+        Core.warn("Moved " .. (ctx:get_mask() or ctx:get_addr()) .. " to the default channel because you can only join the default channel as per the config")
+        ctx:move_to(1) -- only allow joining general
+    end
+
+    if ctx:is_rejoining() then
+        return -- only greet once
     end
 
     ctx:reply(string.format(welcome_msg, server_name, Core.system_time()))
@@ -1,16 +1,39 @@
 use anyhow::Result;
 use chrono::Local;
-use clap::{Parser, Subcommand};
+use clap::{Parser, Subcommand, ValueEnum};
 use log::Level;
 use pretty_env_logger::env_logger::fmt::Color;
-use std::io::Write;
+use std::{io::Write, sync::Arc};
 
 use voudp::{
-    client::{self, ClientState},
-    music::MusicClientState,
-    server::{Clipping, ServerConfig, ServerState},
+    client::{self, ClientBuilder, ClientState},
+    music::{self, MusicClientState},
+    prefs::ClientPrefs,
+    probe::QualityProfile,
+    protocol,
+    server::{self, Clipping, ServerConfig, ServerState},
+    storage::{FileStorage, Storage},
+    vad,
 };
 
+#[cfg(feature = "sled")]
+use voudp::storage::SledStorage;
+
+#[cfg(feature = "telemetry")]
+use voudp::telemetry::{TelemetryConfig, TelemetrySink};
+
+#[cfg(feature = "tls-console")]
+use voudp::console_tls::TlsConsoleConfig;
+
+#[derive(Clone, Copy, ValueEnum)]
+enum StorageBackend {
+    /// A directory with one file per entry
+    File,
+    /// A sled database
+    #[cfg(feature = "sled")]
+    Sled,
+}
+
 /// A lightweight UDP VoIP system with server/client/music modes
 #[derive(Parser)]
 #[clap(
@@ -72,37 +95,215 @@ enum Mode {
         #[clap(long, default_value_t = 50)]
         tickrate: u32,
 
+        /// Spread simultaneous talkers across the stereo field
+        #[clap(long)]
+        stereo_panning: bool,
+
+        /// DC removal filter coefficient
+        #[clap(long, default_value_t = 0.995)]
+        dc_alpha: f32,
+
+        /// High-pass cutoff in Hz (80-120 works well for voice), disabled if not set
+        #[clap(long)]
+        highpass_hz: Option<f32>,
+
+        /// RMS level under which a frame is treated as silence
+        #[clap(long, default_value_t = 0.001)]
+        silence_threshold: f32,
+
+        /// New joins accepted per second before clients are asked to retry (0 = unlimited)
+        #[clap(long, default_value_t = 20)]
+        max_joins_per_sec: u32,
+
+        /// Don't tell a channel when an operator starts monitoring it
+        #[clap(long)]
+        quiet_monitoring: bool,
+
+        /// Gain of channels a user listens to without joining, relative to their own
+        #[clap(long, default_value_t = 0.5)]
+        subscription_gain: f32,
+
+        /// Directory lua plugins are loaded from, see plugins.txt in it for the load order
+        #[clap(long, default_value = server::DEFAULT_PLUGIN_DIR)]
+        plugin_dir: std::path::PathBuf,
+
+        /// Host plugins may fetch from with Core.http_get, subdomains included (repeatable)
+        #[clap(long = "plugin-http-host")]
+        plugin_http_hosts: Vec<String>,
+
+        /// Where persistent server data (notes, ...) is kept
+        #[clap(long, default_value = server::DEFAULT_DATA_DIR)]
+        data_dir: std::path::PathBuf,
+
+        /// Storage backend for persistent data
+        #[clap(long, value_enum, default_value_t = StorageBackend::File)]
+        storage: StorageBackend,
+
+        /// Opt in to anonymous usage statistics, appended to this file
+        #[cfg(feature = "telemetry")]
+        #[clap(long)]
+        telemetry_file: Option<std::path::PathBuf>,
+
+        /// Opt in to anonymous usage statistics, posted to this http:// endpoint
+        #[cfg(feature = "telemetry")]
+        #[clap(long, conflicts_with = "telemetry_file")]
+        telemetry_endpoint: Option<String>,
+
+        /// Seconds between usage statistics reports
+        #[cfg(feature = "telemetry")]
+        #[clap(long, default_value_t = 3600)]
+        telemetry_interval_secs: u64,
+
+        /// Also take admin consoles over TCP with TLS on this port
+        #[cfg(feature = "tls-console")]
+        #[clap(long, requires_all = ["console_tls_cert", "console_tls_key"])]
+        console_tls_port: Option<u16>,
+
+        /// PEM certificate chain of the TLS console listener, its own certificate first
+        #[cfg(feature = "tls-console")]
+        #[clap(long)]
+        console_tls_cert: Option<std::path::PathBuf>,
+
+        /// PEM private key of the TLS console listener
+        #[cfg(feature = "tls-console")]
+        #[clap(long)]
+        console_tls_key: Option<std::path::PathBuf>,
+
         #[clap(long)]
         phrase: String,
     },
 
     /// Start a client that captures and streams microphone audio
     Client {
-        /// Address to connect to (e.g., 127.0.0.1:37549)
+        /// Address to connect to (e.g., 127.0.0.1:37549), the last server if not set
         #[clap(long)]
-        connect: String,
+        connect: Option<String>,
 
-        /// ID of the channel to connect to
-        #[clap(long, default_value_t = 1)]
-        channel_id: u32,
+        /// ID of the channel to connect to, the last channel if not set
+        #[clap(long)]
+        channel_id: Option<u32>,
 
         #[clap(long)]
         phrase: String,
+
+        /// Name of the microphone to use, see `devices`
+        #[clap(long)]
+        input_device: Option<String>,
+
+        /// Name of the speaker to use, see `devices`
+        #[clap(long)]
+        output_device: Option<String>,
+
+        /// Skip the bandwidth probe and use this voice quality (low, medium, high, max)
+        #[clap(long)]
+        quality: Option<String>,
+
+        /// Mic level (frame RMS) needed before audio is sent, 0 sends everything
+        #[clap(long, default_value_t = vad::DEFAULT_VAD_THRESHOLD)]
+        vad_threshold: f32,
+
+        /// Echo the mic back for this many seconds before joining, to check the devices
+        #[clap(long)]
+        mic_test: Option<u64>,
+
+        /// Where client settings (away message, ...) are kept
+        #[clap(long, default_value = client::DEFAULT_SETTINGS_DIR)]
+        settings_dir: std::path::PathBuf,
+
+        /// Opus frame length in ms (10, 20 or 40), has to match the server's tickrate
+        #[clap(long, default_value_t = 20)]
+        frame_ms: u32,
+
+        /// Let opus skip frames of background noise
+        #[clap(long)]
+        dtx: bool,
+
+        /// Mic channels to capture (1 or 2), the device default if not set
+        #[clap(long)]
+        capture_channels: Option<u16>,
+
+        /// Frames of audio buffered each way before the oldest is dropped
+        #[clap(long, default_value_t = 10)]
+        buffer_frames: usize,
+
+        /// Cancel speaker echo picked up by the mic, for use without headphones
+        #[clap(long)]
+        aec: bool,
+
+        /// Where the last server, nick, devices, ... are remembered between runs
+        #[clap(long)]
+        prefs: Option<std::path::PathBuf>,
     },
 
-    /// Start a client that streams audio from a file
+    /// List audio input and output devices
+    Devices,
+
+    /// Start a client that streams audio from files, type pause/skip/stop/seek/loop to control it
     Music {
         /// Address to connect to
         #[clap(long)]
         connect: String,
 
         /// ID of the channel to connect to
-        #[clap(long, default_value_t = 1)]
+        #[clap(long, default_value_t = protocol::DEFAULT_CHANNEL_ID)]
         channel_id: u32,
 
-        /// Path to file to stream
+        /// File, directory or http:// stream to play, can be given more than once
+        #[clap(long)]
+        file: Vec<std::path::PathBuf>,
+
+        /// An .m3u playlist to stream, after any files
+        #[clap(long)]
+        playlist: Option<std::path::PathBuf>,
+
+        /// Play the tracks in a random order
+        #[clap(long)]
+        shuffle: bool,
+
+        /// Play each track over and over until it's skipped
+        #[clap(long, conflicts_with = "loop_playlist")]
+        loop_track: bool,
+
+        /// Start over from the first track once the last one is done
+        #[clap(long)]
+        loop_playlist: bool,
+
+        /// Let listeners queue what's in this directory (and http streams) with #play,
+        /// #skip and #queue. The player then waits for requests instead of quitting
+        #[clap(long)]
+        library: Option<std::path::PathBuf>,
+
+        /// Playback volume in percent, listeners can change it with #volume
+        #[clap(long, default_value_t = 50)]
+        volume: u8,
+
+        /// Play tracks as loud as they were mastered instead of evening them out
+        #[clap(long)]
+        no_normalize: bool,
+
+        /// Say in chat what's playing (from the track's tags) when it starts
+        #[clap(long)]
+        announce: bool,
+
+        /// Show a progress bar and warn when sending falls behind
+        #[clap(long)]
+        progress: bool,
+
+        /// Stream raw PCM piped into stdin instead, e.g. from ffmpeg
         #[clap(long)]
-        file: String,
+        stdin: bool,
+
+        /// Sample format of the piped PCM (s16le or f32le)
+        #[clap(long, default_value = "s16le")]
+        pcm_format: String,
+
+        /// Sample rate of the piped PCM
+        #[clap(long, default_value_t = 48000)]
+        pcm_rate: u32,
+
+        /// Channels of the piped PCM (1 or 2)
+        #[clap(long, default_value_t = 2)]
+        pcm_channels: usize,
 
         #[clap(long)]
         phrase: String,
@@ -117,19 +318,153 @@ fn main() -> Result<()> {
             connect,
             channel_id,
             phrase,
+            input_device,
+            output_device,
+            quality,
+            vad_threshold,
+            mic_test,
+            settings_dir,
+            frame_ms,
+            dtx,
+            capture_channels,
+            buffer_frames,
+            aec,
+            prefs,
         } => {
-            let mut client = ClientState::new(&connect, channel_id, &phrase.into_bytes())?;
+            let prefs_path = prefs.or_else(ClientPrefs::default_path);
+            let mut prefs = match &prefs_path {
+                Some(path) => ClientPrefs::load(path)?,
+                None => ClientPrefs::default(),
+            };
+            let connect = connect
+                .or_else(|| prefs.server.clone())
+                .ok_or_else(|| anyhow::anyhow!("no server to connect to, pass --connect"))?;
+
+            let mut builder = ClientBuilder::new(&connect, phrase.as_bytes())
+                .channel(
+                    channel_id
+                        .or(prefs.channel_id)
+                        .unwrap_or(protocol::DEFAULT_CHANNEL_ID),
+                )
+                .frame_ms(frame_ms)
+                .dtx(dtx)
+                .buffer_frames(buffer_frames);
+            if let Some(channels) = capture_channels {
+                builder = builder.capture_channels(channels);
+            }
+            let client = builder.build()?;
+            client.apply_prefs(&prefs);
+            client.use_settings(Arc::new(FileStorage::open(&settings_dir)));
+            if let Some(name) = quality {
+                let profile = QualityProfile::by_name(&name)
+                    .ok_or_else(|| anyhow::anyhow!("unknown quality '{name}'"))?;
+                client.set_quality(Some(profile));
+            }
+            client.set_vad_threshold(vad_threshold);
+            client.set_echo_cancellation(aec);
+            if let Some(secs) = mic_test {
+                client.mic_test(std::time::Duration::from_secs(secs))?;
+            }
+            if let Some(name) = input_device {
+                client.select_input_device(&name)?;
+            }
+            if let Some(name) = output_device {
+                client.select_output_device(&name)?;
+            }
+            client.handshake(client::HANDSHAKE_TIMEOUT)?;
             client.run(client::Mode::Repl)?;
+
+            if let Some(path) = prefs_path {
+                client.update_prefs(&mut prefs);
+                prefs.server = Some(connect);
+                if let Err(e) = prefs.save(&path) {
+                    log::warn!("couldn't save preferences to {}: {e}", path.display());
+                }
+            }
+        }
+
+        Mode::Devices => {
+            println!("Input devices:");
+            for name in ClientState::input_devices()? {
+                println!("  {name}");
+            }
+            println!("Output devices:");
+            for name in ClientState::output_devices()? {
+                println!("  {name}");
+            }
         }
 
         Mode::Music {
             connect,
             channel_id,
             file,
+            playlist,
+            shuffle,
+            loop_track,
+            loop_playlist,
+            library,
+            volume,
+            no_normalize,
+            announce,
+            progress,
+            stdin,
+            pcm_format,
+            pcm_rate,
+            pcm_channels,
             phrase,
         } => {
             let mut client = MusicClientState::new(&connect, channel_id, &phrase.into_bytes())?;
-            client.run(file)?;
+            client.handshake(client::HANDSHAKE_TIMEOUT)?;
+            client.set_volume(volume);
+            client.set_normalize(!no_normalize);
+            client.set_announce(announce);
+            if progress && let Some(events) = client.events() {
+                std::thread::spawn(move || print_progress(events));
+            }
+
+            if stdin {
+                let format = music::PcmFormat::by_name(&pcm_format)
+                    .ok_or_else(|| anyhow::anyhow!("unknown pcm format '{pcm_format}'"))?;
+                // stdin is the audio, so there's no typing commands
+                client.run_pcm(std::io::stdin().lock(), format, pcm_rate, pcm_channels)?;
+            } else {
+                let mut tracks = vec![];
+                for path in &file {
+                    tracks.extend(music::tracks(path)?);
+                }
+                if let Some(path) = playlist {
+                    tracks.extend(music::read_playlist(&path)?);
+                }
+                if tracks.is_empty() && library.is_none() {
+                    anyhow::bail!("nothing to play, pass --file, --playlist, --library or --stdin");
+                }
+                client.set_shuffle(shuffle);
+                if loop_track {
+                    client.set_repeat(music::Repeat::Track);
+                } else if loop_playlist {
+                    client.set_repeat(music::Repeat::Playlist);
+                }
+                if let Some(dir) = library {
+                    client.set_library(dir);
+                }
+
+                let transport = client.transport();
+                std::thread::spawn(move || {
+                    for line in std::io::stdin().lines().map_while(Result::ok) {
+                        match music::Transport::parse(&line) {
+                            Some(command) => {
+                                if transport.send(command).is_err() {
+                                    break;
+                                }
+                            }
+                            None => println!(
+                                "pause, resume, skip, stop, seek <secs|m:ss> or loop [off|track|playlist]"
+                            ),
+                        }
+                    }
+                });
+                client.run(tracks)?;
+            }
         }
 
         Mode::Server {
@@ -144,6 +479,29 @@ fn main() -> Result<()> {
             throttle_millis,
             sample_rate,
             tickrate,
+            stereo_panning,
+            dc_alpha,
+            highpass_hz,
+            silence_threshold,
+            max_joins_per_sec,
+            quiet_monitoring,
+            subscription_gain,
+            plugin_dir,
+            plugin_http_hosts,
+            data_dir,
+            storage,
+            #[cfg(feature = "telemetry")]
+            telemetry_file,
+            #[cfg(feature = "telemetry")]
+            telemetry_endpoint,
+            #[cfg(feature = "telemetry")]
+            telemetry_interval_secs,
+            #[cfg(feature = "tls-console")]
+            console_tls_port,
+            #[cfg(feature = "tls-console")]
+            console_tls_cert,
+            #[cfg(feature = "tls-console")]
+            console_tls_key,
             phrase,
         } => {
             let config = ServerConfig {
@@ -162,10 +520,49 @@ fn main() -> Result<()> {
                 throttle_millis,
                 sample_rate,
                 tickrate,
+                stereo_panning,
+                dc_alpha,
+                highpass_hz,
+                silence_threshold,
+                max_joins_per_sec,
+                announce_monitoring: !quiet_monitoring,
+                subscription_gain,
                 ..Default::default()
             };
             init_logger();
-            let mut server = ServerState::new(config, &phrase.into_bytes())?;
+            let storage: Arc<dyn Storage> = match storage {
+                StorageBackend::File => Arc::new(FileStorage::open(&data_dir)),
+                #[cfg(feature = "sled")]
+                StorageBackend::Sled => Arc::new(SledStorage::open(&data_dir)?),
+            };
+            let mut server = ServerState::with_storage(config, &phrase.into_bytes(), storage)?;
+            server.set_plugin_dir(plugin_dir);
+            server.allow_plugin_hosts(plugin_http_hosts);
+
+            #[cfg(feature = "telemetry")]
+            {
+                let sink = telemetry_file
+                    .map(TelemetrySink::File)
+                    .or(telemetry_endpoint.map(TelemetrySink::Http));
+                if let Some(sink) = sink {
+                    server.enable_telemetry(TelemetryConfig {
+                        sink,
+                        interval: std::time::Duration::from_secs(telemetry_interval_secs),
+                    });
+                }
+            }
+
+            #[cfg(feature = "tls-console")]
+            if let (Some(port), Some(cert_chain), Some(key)) =
+                (console_tls_port, console_tls_cert, console_tls_key)
+            {
+                server.listen_tls_console(&TlsConsoleConfig {
+                    bind: ([0, 0, 0, 0], port).into(),
+                    cert_chain,
+                    key,
+                })?;
+            }
+
             server.run();
         }
     }
@@ -173,6 +570,42 @@ fn main() -> Result<()> {
     Ok(())
 }
 
+// one line that keeps being redrawn, underruns get their own
+fn print_progress(events: std::sync::mpsc::Receiver<music::MusicEvent>) {
+    const WIDTH: usize = 30;
+    let time = |d: std::time::Duration| format!("{}:{:02}", d.as_secs() / 60, d.as_secs() % 60);
+
+    for event in events {
+        match event {
+            music::MusicEvent::Progress(progress) => {
+                let (bar, total) = match progress.total {
+                    Some(total) if !total.is_zero() => {
+                        let done =
+                            (progress.elapsed.as_secs_f64() / total.as_secs_f64()).clamp(0.0, 1.0);
+                        let filled = (done * WIDTH as f64) as usize;
+                        (
+                            format!("[{}{}] ", "=".repeat(filled), " ".repeat(WIDTH - filled)),
+                            format!("/{}", time(total)),
+                        )
+                    }
+                    _ => (String::new(), String::new()),
+                };
+                print!(
+                    "\r{} {bar}{}{total} {} kbps, {} packets\x1b[K",
+                    progress.track,
+                    time(progress.elapsed),
+                    progress.bitrate / 1000,
+                    progress.packets_sent,
+                );
+                let _ = std::io::stdout().flush();
+            }
+            music::MusicEvent::Underrun(behind) => {
+                println!("\nFalling behind by {}ms", behind.as_millis());
+            }
+        }
+    }
+}
+
 fn init_logger() {
     pretty_env_logger::formatted_builder()
         .format(|buf, record| {
@@ -0,0 +1,210 @@
+// The console's input line: up/down history, Ctrl-R reverse search and tab
+// completion of command names the server said we may run.
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+
+pub enum Edit {
+    None,
+    /// Enter was pressed on this line
+    Submit(String),
+    /// Tab matched several commands
+    Candidates(Vec<String>),
+}
+
+struct Search {
+    query: String,
+    // index of the match in history
+    found: Option<usize>,
+}
+
+pub struct LineEditor {
+    line: String,
+    history: Vec<String>,
+    // index into history while browsing with up/down
+    browsing: Option<usize>,
+    // what was typed before browsing started
+    draft: String,
+    search: Option<Search>,
+    commands: Vec<String>,
+}
+
+impl LineEditor {
+    pub fn new() -> Self {
+        Self {
+            line: String::new(),
+            history: vec![],
+            browsing: None,
+            draft: String::new(),
+            search: None,
+            commands: vec![],
+        }
+    }
+
+    pub fn set_commands(&mut self, commands: impl IntoIterator<Item = String>) {
+        self.commands = commands.into_iter().collect();
+        self.commands.sort();
+        self.commands.dedup();
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.line.is_empty() && self.search.is_none()
+    }
+
+    /// The prompt and what's shown after it
+    pub fn prompt(&self) -> (String, &str) {
+        match &self.search {
+            Some(search) => (
+                format!("(reverse-i-search)`{}': ", search.query),
+                search.found.map_or("", |i| &self.history[i]),
+            ),
+            None => ("> ".into(), &self.line),
+        }
+    }
+
+    pub fn key(&mut self, key: KeyEvent) -> Edit {
+        let ctrl = key.modifiers.contains(KeyModifiers::CONTROL);
+        if self.search.is_some() {
+            return self.search_key(key.code, ctrl);
+        }
+
+        match key.code {
+            KeyCode::Char('r') if ctrl => {
+                self.search = Some(Search {
+                    query: String::new(),
+                    found: None,
+                });
+            }
+            KeyCode::Char(c) => {
+                self.browsing = None;
+                self.line.push(c);
+            }
+            KeyCode::Backspace => {
+                self.browsing = None;
+                self.line.pop();
+            }
+            KeyCode::Up => self.older(),
+            KeyCode::Down => self.newer(),
+            KeyCode::Tab => return self.complete(),
+            KeyCode::Enter => return self.submit(),
+            _ => {}
+        }
+        Edit::None
+    }
+
+    fn search_key(&mut self, code: KeyCode, ctrl: bool) -> Edit {
+        let Some(search) = &mut self.search else {
+            return Edit::None;
+        };
+
+        match code {
+            // the next older match
+            KeyCode::Char('r') if ctrl => {
+                let before = search.found.unwrap_or(self.history.len());
+                if let Some(found) = find(&self.history[..before], &search.query) {
+                    search.found = Some(found);
+                }
+            }
+            KeyCode::Char('g') if ctrl => self.search = None,
+            KeyCode::Esc => self.search = None,
+            KeyCode::Char(c) => {
+                search.query.push(c);
+                search.found = find(&self.history, &search.query);
+            }
+            KeyCode::Backspace => {
+                search.query.pop();
+                search.found = find(&self.history, &search.query);
+            }
+            KeyCode::Enter => {
+                self.accept_search();
+                return self.submit();
+            }
+            // anything else keeps the match to edit it
+            _ => self.accept_search(),
+        }
+        Edit::None
+    }
+
+    fn accept_search(&mut self) {
+        if let Some(Search {
+            found: Some(found), ..
+        }) = self.search.take()
+        {
+            self.line = self.history[found].clone();
+        }
+        self.browsing = None;
+    }
+
+    fn older(&mut self) {
+        let index = match self.browsing {
+            Some(index) => index.saturating_sub(1),
+            None if self.history.is_empty() => return,
+            None => {
+                self.draft = std::mem::take(&mut self.line);
+                self.history.len() - 1
+            }
+        };
+        self.browsing = Some(index);
+        self.line = self.history[index].clone();
+    }
+
+    fn newer(&mut self) {
+        match self.browsing {
+            Some(index) if index + 1 < self.history.len() => {
+                self.browsing = Some(index + 1);
+                self.line = self.history[index + 1].clone();
+            }
+            Some(_) => {
+                self.browsing = None;
+                self.line = std::mem::take(&mut self.draft);
+            }
+            None => {}
+        }
+    }
+
+    // only the command name is completed, arguments are up to the user
+    fn complete(&mut self) -> Edit {
+        if self.line.contains(char::is_whitespace) {
+            return Edit::None;
+        }
+
+        let candidates = self
+            .commands
+            .iter()
+            .filter(|cmd| cmd.starts_with(&self.line))
+            .cloned()
+            .collect::<Vec<_>>();
+        match &candidates[..] {
+            [] => Edit::None,
+            [only] => {
+                self.line = format!("{only} ");
+                Edit::None
+            }
+            [first, rest @ ..] => {
+                let common = rest.iter().fold(first.chars().count(), |common, cmd| {
+                    first
+                        .chars()
+                        .zip(cmd.chars())
+                        .take(common)
+                        .take_while(|(a, b)| a == b)
+                        .count()
+                });
+                self.line = first.chars().take(common).collect();
+                Edit::Candidates(candidates)
+            }
+        }
+    }
+
+    fn submit(&mut self) -> Edit {
+        let line = std::mem::take(&mut self.line);
+        self.browsing = None;
+        self.draft.clear();
+        if !line.trim().is_empty() && self.history.last() != Some(&line) {
+            self.history.push(line.clone());
+        }
+        Edit::Submit(line)
+    }
+}
+
+// the newest entry of `history` containing `query`
+fn find(history: &[String], query: &str) -> Option<usize> {
+    history.iter().rposition(|line| line.contains(query))
+}
@@ -1,15 +1,18 @@
 use std::{
-    io::{Write, stdout},
-    net::ToSocketAddrs,
+    collections::{HashSet, VecDeque},
+    io::{ErrorKind, Write, stdout},
+    net::{SocketAddr, ToSocketAddrs},
+    path::{Path, PathBuf},
     thread,
     time::{Duration, Instant},
 };
 
+use clap::Parser;
 use crossterm::{
     cursor::{Hide, MoveTo, Show},
     event::{self, Event, KeyCode, KeyModifiers},
     execute,
-    style::{Color, ResetColor, SetForegroundColor},
+    style::{Color, ResetColor, SetBackgroundColor, SetForegroundColor},
     terminal::{
         Clear, ClearType, EnterAlternateScreen, LeaveAlternateScreen, disable_raw_mode,
         enable_raw_mode, size,
@@ -18,69 +21,379 @@ use crossterm::{
 
 use voudp::socket::SecureUdpSocket;
 use voudp::util::{self};
-use voudp::{protocol::VOUDP_SALT, socket};
+use voudp::{
+    console_msg::{ConsoleMessage, ConsoleMessageKind},
+    protocol::{self, ClientPacketType, ConsoleAuth, ConsoleRole, VOUDP_SALT},
+    socket,
+};
+
+mod input;
+mod script;
+
+use input::{Edit, LineEditor};
+
+// how often the dashboard pane asks for `status`
+const STATUS_REFRESH: Duration = Duration::from_secs(2);
+// the dashboard pane is left out on narrower terminals
+const STATUS_PANE_WIDTH: u16 = 40;
+const MIN_WIDTH_FOR_PANE: u16 = 80;
+
+// the server is taken for gone after this long without answering a keepalive
+const DISCONNECT_AFTER: Duration = Duration::from_secs(5);
+// how often a lost console tries logging back in
+const RELOGIN_EVERY: Duration = Duration::from_secs(3);
+
+// handled by the console itself, offered for completion next to the server's
+const LOCAL_COMMANDS: [&str; 4] = [":hide", ":show", "quit", "source"];
+
+// `/` search through the logs
+struct Find {
+    query: String,
+    // nothing older matched the last try
+    missed: bool,
+}
+
+/// Remote console for a voudp server
+#[derive(Parser)]
+#[clap(name = "voudp-console", version = "0.1", author = "spixa")]
+struct Cli {
+    /// Run the console commands in this file one by one, printing their
+    /// replies, and exit instead of opening the TUI
+    #[clap(long)]
+    script: Option<PathBuf>,
+}
 
 enum LogMsg {
-    Line(String),
+    Packet(Vec<u8>),
     Shutdown,
 }
 
 struct Console {
-    logs: Vec<String>,
-    input: String,
+    logs: Vec<(Color, String)>,
+    input: LineEditor,
+    // message types dropped with `:hide`
+    hidden: HashSet<String>,
+    // the command list asked for on login, not worth logging
+    awaiting_commands: bool,
+    // lines scrolled up from the newest one
+    scroll: usize,
+    // jump to new lines as they come in
+    follow: bool,
+    find: Option<Find>,
+    // the last search, its lines stand out
+    highlight: Option<String>,
+    // the last `status` table, shown in the dashboard pane
+    status: Vec<Vec<(String, String)>>,
+    show_status: bool,
+    // `status` queries sent for the pane, their replies aren't logged
+    awaiting_status: usize,
+    // what's left of a `source`d script
+    script: VecDeque<script::Line>,
+    // the script command waiting on its reply, and when it was sent
+    running_script: Option<(script::Line, Instant)>,
+    // since when the server stopped answering
+    disconnected: Option<Instant>,
+    login_denied: bool,
 }
 
 impl Console {
     fn new() -> Self {
         Self {
             logs: Vec::new(),
-            input: String::new(),
+            input: LineEditor::new(),
+            hidden: HashSet::new(),
+            awaiting_commands: true,
+            scroll: 0,
+            follow: true,
+            find: None,
+            highlight: None,
+            status: vec![],
+            show_status: true,
+            awaiting_status: 0,
+            script: VecDeque::new(),
+            running_script: None,
+            disconnected: None,
+            login_denied: false,
         }
     }
 
-    fn push_log(&mut self, line: impl Into<String>) {
+    fn push_log(&mut self, color: Color, line: impl Into<String>) {
         const MAX_LOGS: usize = 10_000; // prevent unbounded memory growth
-        self.logs.push(line.into());
+        self.logs.push((color, line.into()));
         if self.logs.len() > MAX_LOGS {
             self.logs.drain(..self.logs.len() - MAX_LOGS);
         }
+
+        // the view stays put unless it follows the tail
+        if self.follow {
+            self.scroll = 0;
+        } else {
+            self.scroll = (self.scroll + 1).min(self.logs.len().saturating_sub(1));
+        }
+    }
+
+    fn page_up(&mut self, page: usize) {
+        self.follow = false;
+        self.scroll = (self.scroll + page).min(self.logs.len().saturating_sub(1));
+    }
+
+    fn page_down(&mut self, page: usize) {
+        self.scroll = self.scroll.saturating_sub(page);
+        if self.scroll == 0 {
+            self.follow = true;
+        }
+    }
+
+    fn toggle_follow(&mut self) {
+        self.follow = !self.follow;
+        if self.follow {
+            self.scroll = 0;
+        }
+    }
+
+    // keys while typing a `/` search, Enter jumps to the next older match
+    fn find_key(&mut self, code: KeyCode) {
+        let Some(find) = &mut self.find else {
+            return;
+        };
+
+        match code {
+            KeyCode::Esc => {
+                self.find = None;
+                self.highlight = None;
+            }
+            KeyCode::Char(c) => {
+                find.query.push(c);
+                find.missed = false;
+            }
+            KeyCode::Backspace => {
+                find.query.pop();
+                find.missed = false;
+            }
+            KeyCode::Enter if !find.query.is_empty() => {
+                // strictly above the newest line on screen
+                let newest = self.logs.len().saturating_sub(self.scroll + 1);
+                match self.logs[..newest]
+                    .iter()
+                    .rposition(|(_, line)| line.contains(&find.query))
+                {
+                    Some(found) => {
+                        find.missed = false;
+                        self.follow = false;
+                        self.scroll = self.logs.len() - 1 - found;
+                    }
+                    None => find.missed = true,
+                }
+                self.highlight = Some(find.query.clone());
+            }
+            _ => {}
+        }
+    }
+
+    fn push_message(&mut self, msg: ConsoleMessage) {
+        if let Some(commands) = msg.get("commands") {
+            let commands = commands.split_whitespace().chain(LOCAL_COMMANDS);
+            self.input.set_commands(commands.map(String::from));
+            if std::mem::take(&mut self.awaiting_commands) {
+                return;
+            }
+        }
+        if !msg.rows.is_empty() {
+            self.status = msg.rows.clone();
+            if self.awaiting_status > 0 {
+                self.awaiting_status -= 1;
+                return;
+            }
+        }
+        if msg.kind == ConsoleMessageKind::Error
+            && let Some((line, _)) = self.running_script.take()
+        {
+            self.stop_script(format!("Script stopped at {}", line.origin));
+        } else if msg.kind == ConsoleMessageKind::Reply {
+            self.running_script = None;
+        }
+        if self.hidden.contains(msg.kind.name()) {
+            return;
+        }
+
+        let time = chrono::DateTime::from_timestamp_millis(msg.time as i64)
+            .map(|time| {
+                time.with_timezone(&chrono::Local)
+                    .format("%H:%M:%S")
+                    .to_string()
+            })
+            .unwrap_or_else(|| "--:--:--".into());
+        match msg.kind {
+            ConsoleMessageKind::Keepalive => {}
+            ConsoleMessageKind::Reply | ConsoleMessageKind::Error => {
+                let color = if msg.kind == ConsoleMessageKind::Error {
+                    Color::Red
+                } else {
+                    Color::White
+                };
+                for line in msg.text().lines() {
+                    self.push_log(color, format!("[{time}] {line}"));
+                }
+            }
+            // one column per field
+            ConsoleMessageKind::Event(event) => {
+                let fields = msg
+                    .payload
+                    .iter()
+                    .map(|(key, value)| format!("{key}={value:<8}"))
+                    .collect::<Vec<_>>();
+                self.push_log(
+                    Color::Cyan,
+                    format!("[{time}] {:<12} {}", event.name(), fields.join(" ")),
+                );
+            }
+        }
+    }
+
+    fn source(&mut self, path: &str) {
+        match script::load(Path::new(path)) {
+            Ok(lines) => {
+                self.push_log(
+                    Color::DarkGrey,
+                    format!("Running {} commands from {path}", lines.len()),
+                );
+                self.script.extend(lines);
+            }
+            Err(e) => self.push_log(Color::Red, format!("Could not source {path}: {e}")),
+        }
+    }
+
+    fn stop_script(&mut self, why: String) {
+        self.script.clear();
+        self.running_script = None;
+        self.push_log(Color::Red, why);
+    }
+
+    // the next script command once the last one got its reply
+    fn next_script_command(&mut self) -> Option<String> {
+        if let Some((line, sent)) = &self.running_script {
+            if sent.elapsed() >= script::REPLY_TIMEOUT {
+                let why = format!("Script stopped, no reply to {}", line.origin);
+                self.stop_script(why);
+            }
+            return None;
+        }
+
+        let line = self.script.pop_front()?;
+        let command = line.command.clone();
+        self.running_script = Some((line, Instant::now()));
+        Some(command)
+    }
+
+    // `:hide <type>` and `:show <type>`, never sent to the server
+    fn filter(&mut self, cmd: &str) {
+        match cmd.split_whitespace().collect::<Vec<_>>()[..] {
+            [":hide", kind] => {
+                self.hidden.insert(kind.to_string());
+            }
+            [":show", kind] => {
+                self.hidden.remove(kind);
+            }
+            _ => {
+                self.push_log(Color::Red, "usage: :hide <type> or :show <type>");
+                return;
+            }
+        }
+        let mut hidden = self.hidden.iter().cloned().collect::<Vec<_>>();
+        hidden.sort();
+        let hiding = if hidden.is_empty() {
+            "Hiding nothing".to_string()
+        } else {
+            format!("Hiding {}", hidden.join(", "))
+        };
+        self.push_log(Color::DarkGrey, hiding);
     }
 }
 
 fn render(console: &Console) -> std::io::Result<()> {
     let mut out = stdout();
     let (w, h) = size()?;
-    let log_height = h.saturating_sub(1) as usize;
+    let pane = console.show_status && w >= MIN_WIDTH_FOR_PANE;
+    let w = if pane { w - STATUS_PANE_WIDTH - 1 } else { w };
+
+    // a banner over the top line while the server is gone
+    let banner = console.disconnected.is_some() as u16;
+
+    // a status line above the input while scrolled back
+    let scrolled = console.scroll > 0 || !console.follow;
+    let log_height = h.saturating_sub(if scrolled { 2 } else { 1 } + banner) as usize;
 
     execute!(out, Hide, MoveTo(0, 0), Clear(ClearType::All))?;
 
-    let start = console.logs.len().saturating_sub(log_height);
+    let end = console.logs.len() - console.scroll.min(console.logs.len());
+    let start = end.saturating_sub(log_height);
+
+    if let Some(since) = console.disconnected {
+        let banner = format!(
+            " DISCONNECTED for {}s, trying to log back in... ",
+            since.elapsed().as_secs()
+        );
+        execute!(
+            out,
+            MoveTo(0, 0),
+            SetForegroundColor(Color::White),
+            SetBackgroundColor(Color::DarkRed)
+        )?;
+        write!(out, "{:<w$}", banner, w = w as usize)?;
+        execute!(out, ResetColor)?;
+    }
 
-    for (i, line) in console.logs[start..].iter().enumerate() {
-        execute!(out, MoveTo(0, i as u16))?; // go to i'th line
+    for (i, (color, line)) in console.logs[start..end].iter().enumerate() {
+        execute!(out, MoveTo(0, i as u16 + banner))?; // go to i'th line
 
         // UTF-8 safe truncation
         let trunc: String = line.chars().take(w as usize).collect();
 
-        // decoded voudp-aux packet:
-        let color = if trunc.starts_with("voudp-aux") {
-            Color::White
-        } else if trunc.starts_with("Executing") {
-            Color::DarkGrey
-        } else {
-            Color::Green
+        let color = match &console.highlight {
+            Some(query) if line.contains(query.as_str()) => Color::Magenta,
+            _ => *color,
         };
-
         execute!(out, SetForegroundColor(color))?;
         write!(out, "{trunc}")?;
         execute!(out, ResetColor)?;
     }
 
+    if pane {
+        render_status(console, w + 1, h.saturating_sub(1))?;
+    }
+
+    if scrolled {
+        let status = format!(
+            "-- {} lines up, follow {}, PgUp/PgDn to scroll, Ctrl-T to follow --",
+            console.scroll,
+            if console.follow { "on" } else { "off" }
+        );
+        execute!(out, MoveTo(0, h.saturating_sub(2)))?;
+        execute!(out, SetForegroundColor(Color::DarkGrey))?;
+        write!(
+            out,
+            "{}",
+            status.chars().take(w as usize).collect::<String>()
+        )?;
+        execute!(out, ResetColor)?;
+    }
+
     // render input on bottom line (never wraps)
     execute!(out, MoveTo(0, h - 1))?;
-    let input: String = console.input.chars().take(w as usize).collect();
+    let (prompt, line) = match &console.find {
+        Some(find) if find.missed => ("/".to_string(), format!("{} (no older match)", find.query)),
+        Some(find) => ("/".to_string(), find.query.clone()),
+        None => {
+            let (prompt, line) = console.input.prompt();
+            (prompt, line.to_string())
+        }
+    };
+    let input: String = line
+        .chars()
+        .take((w as usize).saturating_sub(prompt.chars().count()))
+        .collect();
     execute!(out, SetForegroundColor(Color::Yellow))?;
-    write!(out, "> ")?;
+    write!(out, "{prompt}")?;
     execute!(out, ResetColor)?;
     write!(out, "{input}")?;
 
@@ -88,7 +401,101 @@ fn render(console: &Console) -> std::io::Result<()> {
     Ok(())
 }
 
+// a login step from the server while logging back in, `token` is replaced once
+// it hands out a new session
+fn relogin(
+    console: &mut Console,
+    socket: &SecureUdpSocket,
+    server: SocketAddr,
+    password: &str,
+    packet: &[u8],
+    token: &mut Vec<u8>,
+) {
+    if console.disconnected.is_none() || packet.len() < 2 {
+        return;
+    }
+
+    let payload = &packet[2..];
+    match ConsoleAuth::try_from(packet[1]) {
+        Ok(ConsoleAuth::Challenge) => {
+            let proof = protocol::console_proof(password, payload);
+            let answer = protocol::create_console_auth(ConsoleAuth::Proof, &proof);
+            let _ = socket.send_reliable(answer, server);
+        }
+        Ok(ConsoleAuth::Session) => {
+            *token = payload.to_vec();
+            console.disconnected = None;
+            console.login_denied = false;
+            console.awaiting_status = 0;
+            console.awaiting_commands = true;
+            console.push_log(Color::Green, "Logged back in");
+            let commands = protocol::create_console_command(token, "commands");
+            let _ = socket.send_to(&commands, server);
+        }
+        // every retry would say the same
+        Ok(ConsoleAuth::Denied) if !console.login_denied => {
+            console.login_denied = true;
+            console.push_log(
+                Color::Red,
+                "The server turned the login down, has the password changed?",
+            );
+        }
+        _ => {}
+    }
+}
+
+// the dashboard pane, right of the logs from column `x` down to row `h`
+fn render_status(console: &Console, x: u16, h: u16) -> std::io::Result<()> {
+    let mut out = stdout();
+    let width = STATUS_PANE_WIDTH as usize;
+    let field = |row: &[(String, String)], key: &str| {
+        row.iter()
+            .find(|(got, _)| got == key)
+            .map_or(String::new(), |(_, value)| value.clone())
+    };
+
+    let mut lines = vec![(Color::Yellow, "status (Ctrl-D to hide)".to_string())];
+    if console.status.is_empty() {
+        lines.push((Color::DarkGrey, "waiting for the server...".into()));
+    }
+    for row in &console.status {
+        if field(row, "row") == "channel" {
+            let name = format!("#{} ({})", field(row, "name"), field(row, "channel"));
+            let users = field(row, "users");
+            lines.push((Color::White, format!("{name:<w$}{users:>5}", w = width - 5)));
+        } else {
+            let mask = match field(row, "mask") {
+                mask if mask.is_empty() => "-".to_string(),
+                mask => mask,
+            };
+            lines.push((
+                Color::Cyan,
+                format!(
+                    "  {mask:<18}{:>8}k{:>9}%",
+                    field(row, "kbps"),
+                    field(row, "loss")
+                ),
+            ));
+        }
+    }
+
+    for y in 0..h {
+        execute!(out, MoveTo(x - 1, y), SetForegroundColor(Color::DarkGrey))?;
+        write!(out, "│")?;
+        if let Some((color, line)) = lines.get(y as usize) {
+            execute!(out, SetForegroundColor(*color))?;
+            write!(out, "{}", line.chars().take(width).collect::<String>())?;
+        }
+        execute!(out, ResetColor)?;
+    }
+    Ok(())
+}
+
 fn main() -> Result<(), std::io::Error> {
+    let cli = Cli::parse();
+    // read before logging in, a typo shouldn't cost a session
+    let script = cli.script.map(|path| script::load(&path)).transpose()?;
+
     let ip: String = {
         let input = util::ask("Enter address (default 127.0.0.1:37549): ");
         if input.trim().is_empty() {
@@ -116,6 +523,15 @@ fn main() -> Result<(), std::io::Error> {
         }
     };
 
+    let role = if util::ask("Attach as a read-only observer? (y/N): ")
+        .trim()
+        .eq_ignore_ascii_case("y")
+    {
+        ConsoleRole::Observer
+    } else {
+        ConsoleRole::Admin
+    };
+
     println!("Generating key...");
 
     let key = socket::derive_key_from_phrase(phrase.as_bytes(), VOUDP_SALT);
@@ -128,9 +544,14 @@ fn main() -> Result<(), std::io::Error> {
         .find(|a| a.is_ipv4())
         .unwrap();
 
-    let mut register_packet = vec![0xff];
-    register_packet.extend_from_slice(password.as_bytes());
-    let _ = socket.send_to(&register_packet, server_addr);
+    println!("Logging in...");
+    let mut token = util::console_login(&socket, server_addr, &password, role)?;
+
+    if let Some(script) = script {
+        let ok = script::run_headless(&socket, server_addr, &token, &script)?;
+        let _ = socket.send_to(&[0x03], server_addr);
+        std::process::exit(if ok { 0 } else { 1 });
+    }
 
     // terminal setup
     enable_raw_mode()?;
@@ -158,24 +579,31 @@ fn main() -> Result<(), std::io::Error> {
 
         thread::spawn(move || {
             loop {
+                socket.tick_reliable();
                 let mut buf = [0u8; 2048];
                 match socket.recv_from(&mut buf) {
                     Ok((len, addr)) => {
-                        if server_addr == addr && len > 0 {
-                            if let Ok(string) = String::from_utf8(buf[..len].to_vec()) {
-                                if tx.send(LogMsg::Line(string)).is_err() {
-                                    break;
-                                }
-                            } else if tx.send(LogMsg::Line("CORRUPTED MESSAGE".into())).is_err() {
-                                break;
-                            }
+                        if server_addr == addr
+                            && len > 0
+                            && tx.send(LogMsg::Packet(buf[..len].to_vec())).is_err()
+                        {
+                            break;
                         }
                     }
-                    Err(ref e) if e.0.kind() == std::io::ErrorKind::WouldBlock => {
+                    // a server going away isn't the console's end, it comes back
+                    Err((ref e, _))
+                        if matches!(
+                            e.kind(),
+                            ErrorKind::WouldBlock
+                                | ErrorKind::InvalidData
+                                | ErrorKind::ConnectionReset
+                                | ErrorKind::ConnectionRefused
+                        ) =>
+                    {
                         thread::sleep(Duration::from_millis(50));
                     }
-                    Err(e) => {
-                        let _ = tx.send(LogMsg::Line(format!("SOCKET ERROR: {}", e.0)));
+                    Err((e, _)) => {
+                        eprintln!("SOCKET ERROR: {e}");
                         let _ = tx.send(LogMsg::Shutdown);
                         break;
                     }
@@ -187,28 +615,105 @@ fn main() -> Result<(), std::io::Error> {
     }
 
     let mut console = Console::new();
-    console.push_log("Connected to server");
+    console.push_log(Color::Green, "Connected to server");
+    // for tab completion
+    let _ = socket.send_to(
+        &protocol::create_console_command(&token, "commands"),
+        server_addr,
+    );
 
     let mut running = true;
 
+    // a screenful of logs, minus the input and status lines
+    let page_len = || size().map_or(20, |(_, h)| h.saturating_sub(2).max(1) as usize);
+
     let mut timer = Instant::now();
+    let mut last_heard = Instant::now();
+    let mut last_hello = Instant::now();
+    let mut status_timer: Option<Instant> = None;
     while running {
         // drain logs from recv thread
         while let Ok(msg) = rx.try_recv() {
             match msg {
-                LogMsg::Line(line) => console.push_log(format!(
-                    "voudp-aux [{server_addr}] <-> [{}] recv: {line}",
-                    socket.local_addr(),
-                )),
+                LogMsg::Packet(packet) => {
+                    last_heard = Instant::now();
+                    if packet[0] == ClientPacketType::RegisterConsole as u8 {
+                        relogin(
+                            &mut console,
+                            &socket,
+                            server_addr,
+                            &password,
+                            &packet,
+                            &mut token,
+                        );
+                        continue;
+                    }
+
+                    let line = String::from_utf8_lossy(&packet);
+                    match ConsoleMessage::parse(&line) {
+                        Ok(msg) => console.push_message(msg),
+                        Err(e) => console.push_log(
+                            Color::DarkYellow,
+                            format!("voudp-aux [{server_addr}] sent a bad message ({e}): {line}"),
+                        ),
+                    }
+                }
                 LogMsg::Shutdown => running = false,
             }
         }
 
-        if timer.elapsed() >= Duration::from_secs(1) {
+        if console.disconnected.is_none() && timer.elapsed() >= Duration::from_secs(1) {
             let _ = socket.send_to(&[0x04], server_addr);
             timer = Instant::now();
         }
 
+        if console.disconnected.is_none() && last_heard.elapsed() >= DISCONNECT_AFTER {
+            console.disconnected = Some(Instant::now());
+            if !console.script.is_empty() || console.running_script.is_some() {
+                console.stop_script("Script stopped, the server went away".into());
+            }
+            console.push_log(Color::Red, "Lost the server, trying to log back in...");
+        }
+        if console.disconnected.is_some() && last_hello.elapsed() >= RELOGIN_EVERY {
+            // whatever it remembers of the old server is stale now
+            socket.forget_peer(server_addr);
+            let hello = protocol::create_console_auth(ConsoleAuth::Hello, &[role as u8]);
+            let _ = socket.send_reliable(hello, server_addr);
+            last_hello = Instant::now();
+        }
+
+        // hold commands until there's a session to run them in
+        if console.disconnected.is_some() {
+            render(&console)?;
+            if event::poll(Duration::from_millis(50))?
+                && let Event::Key(key) = event::read()?
+                && key.code == KeyCode::Char('c')
+                && key.modifiers.contains(KeyModifiers::CONTROL)
+            {
+                running = false;
+            }
+            continue;
+        }
+
+        if let Some(cmd) = console.next_script_command() {
+            console.push_log(Color::DarkGrey, format!("Executing '{cmd}' as console"));
+            let packet = protocol::create_console_command(&token, &cmd);
+            let _ = socket.send_to(&packet, server_addr);
+        }
+
+        // only while the pane is up, with one query in flight unless it got lost
+        let since_status = status_timer.map_or(Duration::MAX, |sent: Instant| sent.elapsed());
+        if console.awaiting_status > 0 && since_status >= STATUS_REFRESH * 3 {
+            console.awaiting_status = 0;
+        }
+        if console.show_status && console.awaiting_status == 0 && since_status >= STATUS_REFRESH {
+            let status = protocol::create_console_command(&token, "status");
+            if socket.send_to(&status, server_addr).is_ok() {
+                console.awaiting_status += 1;
+            }
+            status_timer = Some(Instant::now());
+        }
+
         render(&console)?;
 
         if event::poll(Duration::from_millis(50))? {
@@ -219,27 +724,50 @@ fn main() -> Result<(), std::io::Error> {
                         let _ = socket.send_to(&[0x03], server_addr);
                         running = false;
                     }
-                    KeyCode::Char(c) => console.input.push(c),
-                    KeyCode::Backspace => {
-                        console.input.pop();
+                    KeyCode::PageUp => console.page_up(page_len()),
+                    KeyCode::PageDown => console.page_down(page_len()),
+                    KeyCode::Char('t') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                        console.toggle_follow();
+                    }
+                    KeyCode::Char('d') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                        console.show_status = !console.show_status;
                     }
-                    KeyCode::Enter => {
-                        let cmd = std::mem::take(&mut console.input);
+                    _ if console.find.is_some() => console.find_key(key.code),
+                    KeyCode::Char('/') if console.input.is_empty() => {
+                        console.find = Some(Find {
+                            query: String::new(),
+                            missed: false,
+                        });
+                    }
+                    _ => match console.input.key(key) {
+                        Edit::None => {}
+                        Edit::Candidates(candidates) => {
+                            console.push_log(Color::DarkGrey, candidates.join("  "));
+                        }
+                        Edit::Submit(cmd) => {
+                            if cmd.starts_with(':') {
+                                console.filter(&cmd);
+                                continue;
+                            }
+                            if let Some(path) = cmd.strip_prefix("source ") {
+                                console.source(path.trim());
+                                continue;
+                            }
 
-                        // echo locally
-                        console.push_log(format!("Executing '{cmd}' as console"));
+                            // echo locally
+                            console
+                                .push_log(Color::DarkGrey, format!("Executing '{cmd}' as console"));
 
-                        // send to server
-                        let mut packet = vec![0x0d];
-                        packet.extend_from_slice(cmd.as_bytes());
-                        let _ = socket.send_to(&packet, server_addr);
+                            // send to server
+                            let packet = protocol::create_console_command(&token, &cmd);
+                            let _ = socket.send_to(&packet, server_addr);
 
-                        if cmd.trim() == "quit" {
-                            let _ = socket.send_to(&[0x03], server_addr);
-                            running = false;
+                            if cmd.trim() == "quit" {
+                                let _ = socket.send_to(&[0x03], server_addr);
+                                running = false;
+                            }
                         }
-                    }
-                    _ => {}
+                    },
                 },
                 Event::Resize(_, _) => {
                     // just redraw on resize
@@ -0,0 +1,118 @@
+// Console scripts: one command per line, blank lines and `#` comments are
+// skipped, `source <file>` pulls another script in. Run headless with
+// `--script <file>` or from the prompt with `source <file>`, either way one
+// command at a time and stopping at the first error.
+use std::{
+    fs, io,
+    net::SocketAddr,
+    path::Path,
+    thread,
+    time::{Duration, Instant},
+};
+
+use voudp::{
+    console_msg::{ConsoleMessage, ConsoleMessageKind},
+    protocol,
+    socket::SecureUdpSocket,
+};
+
+// how long a command gets to answer before the script gives up
+pub const REPLY_TIMEOUT: Duration = Duration::from_secs(5);
+// scripts sourcing scripts sourcing scripts...
+const MAX_DEPTH: usize = 8;
+
+/// A command and where it came from, for error messages
+pub struct Line {
+    pub origin: String,
+    pub command: String,
+}
+
+pub fn load(path: &Path) -> io::Result<Vec<Line>> {
+    load_nested(path, 0)
+}
+
+fn load_nested(path: &Path, depth: usize) -> io::Result<Vec<Line>> {
+    if depth >= MAX_DEPTH {
+        return Err(io::Error::other(format!(
+            "{} is sourced more than {MAX_DEPTH} deep",
+            path.display()
+        )));
+    }
+
+    let text = fs::read_to_string(path)
+        .map_err(|e| io::Error::new(e.kind(), format!("{}: {e}", path.display())))?;
+    let mut lines = vec![];
+    for (n, line) in text.lines().enumerate() {
+        let command = line.trim();
+        if command.is_empty() || command.starts_with('#') {
+            continue;
+        }
+
+        match command.strip_prefix("source ") {
+            // relative to the script doing the sourcing
+            Some(nested) => {
+                let nested = path.parent().unwrap_or(Path::new("")).join(nested.trim());
+                lines.extend(load_nested(&nested, depth + 1)?);
+            }
+            None => lines.push(Line {
+                origin: format!("{}:{}", path.display(), n + 1),
+                command: command.to_string(),
+            }),
+        }
+    }
+    Ok(lines)
+}
+
+/// Runs `lines` without the TUI, printing every reply. `false` if one failed
+pub fn run_headless(
+    socket: &SecureUdpSocket,
+    server: SocketAddr,
+    token: &[u8],
+    lines: &[Line],
+) -> io::Result<bool> {
+    for line in lines {
+        println!("> {}", line.command);
+        let packet = protocol::create_console_command(token, &line.command);
+        socket.send_reliable(packet, server)?;
+
+        let Some(reply) = wait_for_reply(socket, server) else {
+            eprintln!("{}: no reply to '{}'", line.origin, line.command);
+            return Ok(false);
+        };
+        if reply.kind == ConsoleMessageKind::Error {
+            eprintln!("{}: {}", line.origin, reply.text());
+            return Ok(false);
+        }
+        for text in reply.text().lines() {
+            println!("{text}");
+        }
+    }
+    Ok(true)
+}
+
+// the next reply or error from `server`, anything else is skipped
+fn wait_for_reply(socket: &SecureUdpSocket, server: SocketAddr) -> Option<ConsoleMessage> {
+    let deadline = Instant::now() + REPLY_TIMEOUT;
+    let mut buf = [0u8; 8192];
+
+    while Instant::now() < deadline {
+        socket.tick_reliable();
+        match socket.recv_from(&mut buf) {
+            Ok((size, addr)) if addr == server && size > 0 => {
+                let Ok(msg) = ConsoleMessage::parse(&String::from_utf8_lossy(&buf[..size])) else {
+                    continue;
+                };
+                if matches!(
+                    msg.kind,
+                    ConsoleMessageKind::Reply | ConsoleMessageKind::Error
+                ) {
+                    return Some(msg);
+                }
+            }
+            Ok(_) => {}
+            Err(_) => thread::sleep(Duration::from_millis(5)),
+        }
+    }
+
+    None
+}
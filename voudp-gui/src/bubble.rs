@@ -1,5 +1,4 @@
 use chrono::Local;
-use egui::Color32;
 
 pub fn parse_chat_message(msg: &str) -> Option<(String, String, String)> {
     if !msg.starts_with("[#") {
@@ -72,11 +71,15 @@ pub fn badge(ui: &mut egui::Ui, text: impl Into<String>, color: egui::Color32) {
         });
 }
 
-pub fn connection_activity_wifi(ui: &mut egui::Ui, size: f32, color: egui::Color32) {
+pub fn connection_activity_wifi(
+    ui: &mut egui::Ui,
+    size: f32,
+    color: egui::Color32,
+) -> egui::Response {
     let arc_count = 3;
     let segments = 90;
 
-    let (rect, _) = ui.allocate_exact_size(egui::vec2(size, size), egui::Sense::hover());
+    let (rect, response) = ui.allocate_exact_size(egui::vec2(size, size), egui::Sense::hover());
 
     let painter = ui.painter_at(rect);
     let center = rect.center();
@@ -117,8 +120,5 @@ pub fn connection_activity_wifi(ui: &mut egui::Ui, size: f32, color: egui::Color
 
     // ===== BASE DOT (ALWAYS VISIBLE) =====
     painter.circle_filled(origin, dot_radius, color);
-}
-
-fn _name_color(_: &str) -> egui::Color32 {
-    Color32::YELLOW
+    response
 }
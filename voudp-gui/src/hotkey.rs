@@ -0,0 +1,68 @@
+// The push-to-talk key system-wide, so it works while another window has focus
+use eframe::egui;
+use global_hotkey::{GlobalHotKeyEvent, GlobalHotKeyManager, HotKeyState, hotkey::HotKey};
+use log::warn;
+
+pub struct GlobalTalkKey {
+    manager: GlobalHotKeyManager,
+    hotkey: Option<HotKey>,
+    // what `hotkey` was made from, to skip rebinding the same key
+    key: Option<egui::Key>,
+    held: bool,
+}
+
+impl GlobalTalkKey {
+    /// `None` where the platform won't hand out global hotkeys
+    pub fn new() -> Option<Self> {
+        match GlobalHotKeyManager::new() {
+            Ok(manager) => Some(Self {
+                manager,
+                hotkey: None,
+                key: None,
+                held: false,
+            }),
+            Err(e) => {
+                warn!("Global push-to-talk is unavailable: {e}");
+                None
+            }
+        }
+    }
+
+    /// Swaps the registered key for `key`, or drops it for `None`. A no-op
+    /// when `key` is already the one registered
+    pub fn bind(&mut self, key: Option<egui::Key>) {
+        if key == self.key {
+            return;
+        }
+        self.key = key;
+        if let Some(old) = self.hotkey.take() {
+            let _ = self.manager.unregister(old);
+        }
+        self.held = false;
+
+        let Some(key) = key else {
+            return;
+        };
+        let hotkey = match key.name().parse::<HotKey>() {
+            Ok(hotkey) => hotkey,
+            Err(e) => {
+                warn!("{} can't be a global push-to-talk key: {e}", key.name());
+                return;
+            }
+        };
+        match self.manager.register(hotkey) {
+            Ok(()) => self.hotkey = Some(hotkey),
+            Err(e) => warn!("Could not register {} globally: {e}", key.name()),
+        }
+    }
+
+    /// Whether the key is down, going by the presses and releases seen so far
+    pub fn held(&mut self) -> bool {
+        for event in GlobalHotKeyEvent::receiver().try_iter() {
+            if self.hotkey.is_some_and(|hotkey| hotkey.id() == event.id()) {
+                self.held = event.state() == HotKeyState::Pressed;
+            }
+        }
+        self.held
+    }
+}
@@ -1,4 +1,9 @@
 mod bubble;
+#[cfg(feature = "global-hotkey")]
+mod hotkey;
+mod notify;
+#[cfg(feature = "tray")]
+mod tray;
 
 use anyhow::Result;
 use chrono::{DateTime, Local};
@@ -7,21 +12,35 @@ use eframe::{NativeOptions, egui};
 use egui::{Color32, Id, RichText, Stroke};
 
 use std::{
+    cell::RefCell,
+    collections::HashMap,
     fs::File,
     io::{self, Read, Write},
-    sync::{Arc, Mutex, RwLock, atomic::Ordering, mpsc::TryRecvError},
-    thread::{self, JoinHandle},
-    time::Instant,
+    path::{Path, PathBuf},
+    rc::Rc,
+    sync::{Arc, Mutex, RwLock, mpsc::Receiver},
+    thread::JoinHandle,
+    time::{Duration, Instant},
 };
 
 use voudp::{
-    client::{self, ClientState, GlobalListState, Message},
+    client::{
+        self, AudioDevices, AudioLevels, AudioOptions, ClientEvent, ClientState, ConnectionStats,
+        DisconnectReason, GlobalListState, Loopback, OwnedEvent,
+    },
+    prefs::ClientPrefs,
+    protocol::{self, DEFAULT_CHANNEL_ID},
     socket::SecureUdpSocket,
-    util::{CommandResult, ServerCommand},
+    soundboard::{self, Clip, ClipMode},
+    storage::FileStorage,
+    util::{self, CommandResult, ServerCommand},
 };
 
-use crate::bubble::{
-    badge, bubble_ui, connection_activity_wifi, parse_chat_message, parse_system_message,
+use crate::{
+    bubble::{
+        badge, bubble_ui, connection_activity_wifi, parse_chat_message, parse_system_message,
+    },
+    notify::{Alert, Notifier},
 };
 
 fn main() -> Result<()> {
@@ -34,7 +53,7 @@ fn main() -> Result<()> {
     eframe::run_native(
         "VoUDP GUI Client",
         options,
-        Box::new(|_cc| Box::new(GuiClientApp::default())),
+        Box::new(|_cc| Box::new(GuiApp::default())),
     )
     .unwrap();
 
@@ -43,19 +62,66 @@ fn main() -> Result<()> {
 
 type LogVec = Arc<RwLock<Vec<(String, Color32, DateTime<Local>)>>>;
 
+// one tab per server, each with its own client, channels and chat. The tray
+// and the global talk key are the window's and follow the tab on screen
+struct GuiApp {
+    tabs: Vec<GuiClientApp>,
+    active: usize,
+    // one copy for every tab, only written out from here so tabs don't
+    // overwrite each other's changes
+    prefs: Rc<RefCell<ClientPrefs>>,
+    #[cfg(feature = "global-hotkey")]
+    global_talk_key: Option<hotkey::GlobalTalkKey>,
+    #[cfg(feature = "tray")]
+    tray: Option<tray::Tray>,
+}
+
 struct GuiClientApp {
     global_list: GlobalListState,
     command_list: Vec<ServerCommand>,
     socket: Option<SecureUdpSocket>,
     current_channel_id: u32,
     address: String,
-    chan_id_text: String,
+    // joined on connect, the channel we were last in
+    channel_id: u32,
     phrase: String,
+    show_phrase: bool,
     is_connected: bool,
     muted: bool,
     deafened: bool,
-    client: Option<Arc<Mutex<ClientState>>>,
+    away: bool,
+    // kept across reconnects, it's about the user's setup not the server
+    echo_cancel: bool,
+    // push-to-talk, the mic only goes out while this is held
+    talk_key: Option<egui::Key>,
+    // the next key pressed becomes the talk key
+    binding_talk_key: bool,
+    // soundboard buttons, found once at startup
+    clips: Vec<PathBuf>,
+    client: Option<Arc<ClientState>>,
+    events: Option<Receiver<OwnedEvent>>,
+    // masks we currently hear
+    talkers: Vec<String>,
+    notifier: Notifier,
+    // desktop notifications only show while we're in the background
+    focused: bool,
+    // the mic opened on its own, for the meter before connecting and for
+    // hearing ourselves back while testing it
+    mic_check: Option<Loopback>,
+    mic_levels: Arc<AudioLevels>,
+    mic_echo: bool,
+    // why the mic wouldn't open, kept so it isn't retried every frame
+    mic_error: Option<String>,
+    // the user in our channel whose volume controls are open in the list
+    expanded_user: Option<String>,
+    // volumes users had before we muted them, for unmuting
+    unmuted_volumes: HashMap<String, f32>,
     client_thread: Option<JoinHandle<()>>,
+    // set while a dropped connection is being brought back
+    reconnect: Option<Reconnect>,
+    // the handshake started by the connect button, it blocks so it gets a
+    // thread like reconnect attempts do
+    connecting: Option<JoinHandle<io::Result<ClientState>>>,
     error: ErrorWindow,
     input: String,
     nick: String,
@@ -64,7 +130,10 @@ struct GuiClientApp {
     show_command_suggestions: bool,
     selected_suggestion: usize,
     filter_text: String,
-    ping: u16,
+    stats: ConnectionStats,
+    prefs: Rc<RefCell<ClientPrefs>>,
+    // set when this tab changed the prefs, for `GuiApp` to save them
+    prefs_changed: bool,
 }
 
 #[derive(Default, PartialEq, Eq)]
@@ -80,15 +149,25 @@ enum CommandAction {
     ShowNickWarning,
 }
 
+struct Reconnect {
+    // why we were dropped, for the banner
+    reason: String,
+    // attempts that failed so far
+    attempt: u32,
+    next: Instant,
+    // the attempt in flight, handshakes block so they get a thread
+    pending: Option<JoinHandle<io::Result<ClientState>>>,
+}
+
 #[derive(Default)]
 struct ErrorWindow {
     show: ShowMode,
     message: String,
 }
 
-impl Default for GuiClientApp {
-    fn default() -> Self {
-        let (address, phrase, chan_id_text) = if let Ok(mut file) = File::open(".voudp") {
+impl GuiClientApp {
+    fn new(prefs: Rc<RefCell<ClientPrefs>>) -> Self {
+        let (address, phrase, channel_id) = if let Ok(mut file) = File::open(".voudp") {
             let mut data = String::new();
             file.read_to_string(&mut data).ok();
 
@@ -96,26 +175,42 @@ impl Default for GuiClientApp {
                 let split = data.split_whitespace().collect::<Vec<&str>>();
 
                 if split.len() >= 3 {
-                    (split[0].into(), split[1].into(), split[2].into())
+                    (
+                        split[0].into(),
+                        split[1].into(),
+                        split[2].parse().unwrap_or(DEFAULT_CHANNEL_ID),
+                    )
                 } else {
                     (
                         "127.0.0.1:37549".to_string(),
                         "".to_string(),
-                        "1".to_string(),
+                        DEFAULT_CHANNEL_ID,
                     )
                 }
             } else {
                 (
                     "127.0.0.1:37549".to_string(),
                     "".to_string(),
-                    "1".to_string(),
+                    DEFAULT_CHANNEL_ID,
                 )
             }
         } else {
             (
                 "127.0.0.1:37549".to_string(),
                 "".to_string(),
-                "1".to_string(),
+                DEFAULT_CHANNEL_ID,
+            )
+        };
+
+        // the phrase stays in .voudp, everything else prefers the prefs
+        let (address, channel_id, talk_key, muted, deafened) = {
+            let prefs = prefs.borrow();
+            (
+                prefs.server.clone().unwrap_or(address),
+                prefs.channel_id.unwrap_or(channel_id),
+                prefs.push_to_talk.as_deref().and_then(egui::Key::from_name),
+                prefs.muted,
+                prefs.deafened,
             )
         };
 
@@ -126,17 +221,36 @@ impl Default for GuiClientApp {
                 channels: vec![],
                 last_updated: Instant::now(),
                 current_channel: 0,
+                filter: 0,
             },
             command_list: vec![],
             socket: None,
-            chan_id_text,
+            channel_id,
             phrase,
+            show_phrase: false,
             is_connected: false,
-            muted: false,
-            deafened: false,
+            muted,
+            deafened,
+            away: false,
+            echo_cancel: false,
+            talk_key,
+            binding_talk_key: false,
+            clips: soundboard::find_clips(Path::new(soundboard::DEFAULT_CLIPS_DIR)),
             nicked: false,
             client: None,
+            events: None,
+            talkers: vec![],
+            notifier: Notifier::default(),
+            focused: true,
+            mic_check: None,
+            mic_levels: Arc::new(AudioLevels::default()),
+            mic_echo: false,
+            mic_error: None,
+            expanded_user: None,
+            unmuted_volumes: HashMap::new(),
             client_thread: None,
+            reconnect: None,
+            connecting: None,
             error: Default::default(),
             logs: Default::default(),
             input: Default::default(),
@@ -144,12 +258,151 @@ impl Default for GuiClientApp {
             show_command_suggestions: false,
             selected_suggestion: 0,
             filter_text: String::new(),
-            ping: u16::MAX,
+            stats: ConnectionStats::default(),
+            prefs,
+            prefs_changed: false,
+        }
+    }
+}
+
+impl Default for GuiApp {
+    fn default() -> Self {
+        let prefs = ClientPrefs::default_path()
+            .and_then(|path| ClientPrefs::load(&path).ok())
+            .unwrap_or_default();
+        let prefs = Rc::new(RefCell::new(prefs));
+        Self {
+            tabs: vec![GuiClientApp::new(prefs.clone())],
+            active: 0,
+            prefs,
+            #[cfg(feature = "global-hotkey")]
+            global_talk_key: hotkey::GlobalTalkKey::new(),
+            #[cfg(feature = "tray")]
+            tray: tray::Tray::new(),
         }
     }
 }
-impl eframe::App for GuiClientApp {
+
+impl eframe::App for GuiApp {
+    fn on_exit(&mut self, _gl: Option<&eframe::glow::Context>) {
+        // the tab on screen goes last, it's the server we come back to
+        for (i, tab) in self.tabs.iter_mut().enumerate() {
+            if i != self.active {
+                tab.store_prefs();
+            }
+        }
+        self.tabs[self.active].store_prefs();
+        self.save_prefs();
+    }
+
     fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+        self.tab_bar(ctx);
+        self.tabs[self.active].ui(ctx);
+
+        #[cfg(feature = "global-hotkey")]
+        let global_held = match &mut self.global_talk_key {
+            Some(global) => {
+                global.bind(self.tabs[self.active].talk_key);
+                global.held()
+            }
+            None => false,
+        };
+        #[cfg(not(feature = "global-hotkey"))]
+        let global_held = false;
+        // tabs in the background keep their calls and chat going
+        let mut prefs_changed = false;
+        for (i, tab) in self.tabs.iter_mut().enumerate() {
+            tab.poll(ctx, i == self.active, global_held);
+            prefs_changed |= std::mem::take(&mut tab.prefs_changed);
+        }
+        if prefs_changed {
+            self.save_prefs();
+        }
+        #[cfg(feature = "tray")]
+        self.handle_tray(ctx);
+
+        ctx.request_repaint_after(Duration::from_millis(16));
+    }
+}
+
+impl GuiApp {
+    fn tab_bar(&mut self, ctx: &egui::Context) {
+        let mut close = None;
+        egui::TopBottomPanel::top("server_tabs").show(ctx, |ui| {
+            ui.horizontal(|ui| {
+                for (i, tab) in self.tabs.iter().enumerate() {
+                    let title = RichText::new(tab.title()).color(if tab.reconnect.is_some() {
+                        Color32::YELLOW
+                    } else {
+                        ui.visuals().text_color()
+                    });
+                    ui.selectable_value(&mut self.active, i, title);
+                    if self.tabs.len() > 1
+                        && ui
+                            .small_button("×")
+                            .on_hover_text("Close, disconnecting first")
+                            .clicked()
+                    {
+                        close = Some(i);
+                    }
+                    ui.separator();
+                }
+                if ui
+                    .button("+")
+                    .on_hover_text("Connect to another server")
+                    .clicked()
+                {
+                    self.tabs.push(GuiClientApp::new(self.prefs.clone()));
+                    self.active = self.tabs.len() - 1;
+                }
+            });
+        });
+
+        if let Some(i) = close {
+            self.tabs.remove(i).disconnect();
+            if self.active > i || self.active == self.tabs.len() {
+                self.active -= 1;
+            }
+            self.save_prefs();
+        }
+    }
+
+    fn save_prefs(&self) {
+        if let Some(path) = ClientPrefs::default_path() {
+            let _ = self.prefs.borrow().save(&path);
+        }
+    }
+
+    // keeps the tray icon on the mute state of the server on screen and does
+    // what its menu was asked to there
+    #[cfg(feature = "tray")]
+    fn handle_tray(&mut self, ctx: &egui::Context) {
+        let Some(tray) = &mut self.tray else {
+            return;
+        };
+        let tab = &mut self.tabs[self.active];
+        tray.show(tray::TrayState {
+            connected: tab.is_connected,
+            muted: tab.muted,
+            deafened: tab.deafened,
+        });
+        for action in tray.actions() {
+            match action {
+                tray::TrayAction::Show => {
+                    ctx.send_viewport_cmd(egui::ViewportCommand::Minimized(false));
+                    ctx.send_viewport_cmd(egui::ViewportCommand::Focus);
+                }
+                tray::TrayAction::ToggleMute => tab.toggle_muted(),
+                tray::TrayAction::ToggleDeafen => tab.toggle_deafened(),
+                tray::TrayAction::Disconnect if tab.is_connected => tab.leave(),
+                tray::TrayAction::Disconnect => {}
+            }
+        }
+    }
+}
+
+impl GuiClientApp {
+    fn ui(&mut self, ctx: &egui::Context) {
         match self.error.show {
             ShowMode::ShowError => {
                 egui::Window::new("Connection Error")
@@ -294,7 +547,10 @@ impl eframe::App for GuiClientApp {
             ShowMode::DontShow => {}
         }
 
-        if !self.is_connected {
+        if !self.is_connected && self.reconnect.is_none() {
+            if self.mic_check.is_none() && self.mic_error.is_none() {
+                self.open_mic_check(false);
+            }
             egui::CentralPanel::default().show(ctx, |ui| {
                 let available = ui.available_size();
                 ui.vertical_centered(|ui| {
@@ -333,14 +589,17 @@ impl eframe::App for GuiClientApp {
 
                                 ui.add_space(8.0);
 
-                                // ----- Server Password -----
+                                // ----- Server Passphrase -----
                                 ui.horizontal(|ui| {
-                                    ui.label(RichText::new("🔑").size(18.0));
+                                    ui.label(RichText::new("🔑").size(18.0)).on_hover_text(
+                                        "The server's passphrase, every packet is encrypted \
+                                         with a key derived from it",
+                                    );
                                     ui.add_space(4.0);
 
                                     let text_edit = egui::TextEdit::singleline(&mut self.phrase)
-                                        .hint_text("usually 'voudp'")
-                                        .password(true)
+                                        .hint_text("passphrase, usually 'voudp'")
+                                        .password(!self.show_phrase)
                                         .desired_width(220.0)
                                         .frame(false);
 
@@ -352,38 +611,26 @@ impl eframe::App for GuiClientApp {
                                         .show(ui, |ui| {
                                             ui.add(text_edit);
                                         });
+                                    ui.toggle_value(&mut self.show_phrase, "👁")
+                                        .on_hover_text("Show the passphrase");
                                 });
 
                                 ui.add_space(8.0);
-
-                                // ----- Channel ID -----
-                                ui.horizontal(|ui| {
-                                    ui.label(RichText::new("🔗").size(18.0));
-                                    ui.add_space(4.0);
-
-                                    let text_edit =
-                                        egui::TextEdit::singleline(&mut self.chan_id_text)
-                                            .hint_text("ID")
-                                            .char_limit(2)
-                                            .desired_width(60.0)
-                                            .frame(false);
-
-                                    egui::Frame::none()
-                                        .fill(Color32::from_gray(30))
-                                        .stroke(egui::Stroke::new(1.0, Color32::GRAY))
-                                        .rounding(6.0)
-                                        .inner_margin(egui::Margin::symmetric(6.0, 4.0))
-                                        .show(ui, |ui| {
-                                            ui.add(text_edit);
-                                        });
-                                });
+                                self.mic_check_ui(ui);
 
                                 ui.add_space(15.0);
 
                                 // ----- Connect Button -----
                                 let connect_size = [150.0, 32.0];
                                 let connect_color = Color32::from_rgb(60, 120, 240); // clean blue
-                                if ui
+                                if self.connecting.is_some() {
+                                    ui.horizontal(|ui| {
+                                        ui.spinner();
+                                        ui.label(
+                                            RichText::new("connecting…").color(Color32::YELLOW),
+                                        );
+                                    });
+                                } else if ui
                                     .add_sized(
                                         connect_size,
                                         egui::Button::new(
@@ -396,41 +643,18 @@ impl eframe::App for GuiClientApp {
                                     .clicked()
                                 {
                                     // ----- Connection logic -----
-                                    let chan_id = match self.chan_id_text.parse::<u32>() {
-                                        Ok(num) => num,
-                                        Err(_) => {
-                                            self.error.show = ShowMode::ShowError;
-                                            self.error.message = "Bad channel ID".into();
-                                            return;
-                                        }
-                                    };
-
-                                    match ClientState::new(
-                                        &self.address,
-                                        chan_id,
-                                        &self.phrase.clone().into_bytes(),
-                                    ) {
-                                        Ok(state) => {
-                                            self.socket = Some(state.socket.clone());
-                                            let arc_state = Arc::new(Mutex::new(state));
-                                            let thread_state = arc_state.clone();
-                                            let handle = std::thread::spawn(move || {
-                                                let _ = thread_state
-                                                    .lock()
-                                                    .unwrap()
-                                                    .run(client::Mode::Gui);
-                                            });
-
-                                            self.client_thread = Some(handle);
-                                            self.client = Some(arc_state);
-                                            self.is_connected = true;
-                                        }
-                                        Err(e) => {
-                                            self.error.show = ShowMode::ShowError;
-                                            self.error.message =
-                                                format!("Failed to connect to the server: {}", e);
-                                        }
-                                    }
+                                    // the client opens the mic itself
+                                    self.mic_check = None;
+                                    self.mic_echo = false;
+                                    let address = self.address.clone();
+                                    let channel_id = self.channel_id;
+                                    let phrase = self.phrase.clone().into_bytes();
+                                    self.connecting = Some(std::thread::spawn(move || {
+                                        let state =
+                                            ClientState::new(&address, channel_id, &phrase)?;
+                                        state.handshake(client::HANDSHAKE_TIMEOUT)?;
+                                        Ok(state)
+                                    }));
 
                                     // self.request_global_list();
 
@@ -450,7 +674,7 @@ impl eframe::App for GuiClientApp {
                                         let _ = writeln!(
                                             file,
                                             "{} {} {}",
-                                            self.address, self.phrase, self.chan_id_text
+                                            self.address, self.phrase, self.channel_id
                                         );
 
                                         let _ = file.flush();
@@ -463,8 +687,35 @@ impl eframe::App for GuiClientApp {
                 });
             });
         } else {
-            self.update_global_list();
-            self.update_command_list();
+            if let Some(reconnect) = &self.reconnect {
+                egui::TopBottomPanel::top("reconnect_banner").show(ctx, |ui| {
+                    ui.horizontal(|ui| {
+                        ui.spinner();
+                        let wait = reconnect.next.saturating_duration_since(Instant::now());
+                        let status = if reconnect.pending.is_some() || wait.is_zero() {
+                            "reconnecting…".to_string()
+                        } else {
+                            format!("reconnecting in {}s…", wait.as_secs() + 1)
+                        };
+                        ui.label(
+                            RichText::new(format!("{}, {status}", reconnect.reason))
+                                .color(Color32::YELLOW),
+                        );
+                        if reconnect.attempt > 0 {
+                            ui.label(
+                                RichText::new(format!("{} failed attempt(s)", reconnect.attempt))
+                                    .small()
+                                    .color(Color32::GRAY),
+                            );
+                        }
+                    });
+                });
+            }
+
+            self.stats = self
+                .client
+                .as_ref()
+                .map_or(self.stats, |client| client.stats());
 
             if self.input.starts_with('/') && self.command_list.is_empty() {
                 self.request_command_list();
@@ -498,6 +749,10 @@ impl eframe::App for GuiClientApp {
                         .map(|c| c.unmasked_count as usize + c.masked_users.len())
                         .sum::<usize>();
                     let total_channels = self.global_list.channels.len();
+                    let quality = self
+                        .client
+                        .as_ref()
+                        .map(|client| *client.quality.lock().unwrap());
 
                     // ===== Stats =====
                     egui::Frame::group(ui.style())
@@ -522,6 +777,18 @@ impl eframe::App for GuiClientApp {
                                             .size(16.0),
                                     );
                                 });
+                                if let Some(quality) = quality {
+                                    ui.separator();
+                                    ui.vertical(|ui| {
+                                        ui.label(
+                                            RichText::new("Quality").small().color(Color32::GRAY),
+                                        );
+                                        ui.label(
+                                            RichText::new(quality.profile.name).strong().size(16.0),
+                                        )
+                                        .on_hover_text(quality.to_string());
+                                    });
+                                }
                             });
                         });
 
@@ -557,13 +824,13 @@ impl eframe::App for GuiClientApp {
                                     ui.style().visuals.extreme_bg_color
                                 };
 
-                                let response = egui::Frame::none()
+                                let header = egui::Frame::none()
                                     .fill(bg)
                                     .rounding(10.0)
                                     .inner_margin(egui::Margin::symmetric(10.0, 8.0))
                                     .show(ui, |ui| {
                                         // ----- Header -----
-                                        ui.horizontal(|ui| {
+                                        let header = ui.horizontal(|ui| {
                                             ui.label(
                                                 RichText::new(format!("#{}", channel.name))
                                                     .strong()
@@ -597,6 +864,7 @@ impl eframe::App for GuiClientApp {
                                                 },
                                             );
                                         });
+                                        let header = header.response.interact(egui::Sense::click());
 
                                         ui.add_space(4.0);
                                         ui.separator();
@@ -612,29 +880,92 @@ impl eframe::App for GuiClientApp {
                                         } else {
                                             for (name, muted, deafened) in &channel.masked_users {
                                                 ui.horizontal(|ui| {
+                                                    // talkers are only sent for our channel
+                                                    let talking =
+                                                        is_current && self.talkers.contains(name);
                                                     let status_color = match (*muted, *deafened) {
+                                                        _ if talking => {
+                                                            let time = ui.input(|i| i.time);
+                                                            let pulse = 0.75
+                                                                + 0.25 * (time * 6.0).sin() as f32;
+                                                            Color32::from_rgb(60, 230, 90)
+                                                                .gamma_multiply(pulse)
+                                                        }
                                                         (true, true) => Color32::RED,
                                                         (true, false) => {
                                                             Color32::from_rgb(100, 150, 255)
                                                         }
                                                         (false, true) => Color32::YELLOW,
-                                                        (false, false) => Color32::GREEN,
+                                                        (false, false) => Color32::DARK_GRAY,
                                                     };
                                                     ui.label(
                                                         RichText::new("•")
                                                             .size(15.0)
                                                             .color(status_color),
                                                     );
-                                                    ui.label(
-                                                        RichText::new(name)
-                                                            .strong()
-                                                            .color(Color32::GRAY),
+                                                    let label = ui.add(
+                                                        egui::Label::new(
+                                                            RichText::new(name).strong().color(
+                                                                if talking {
+                                                                    Color32::LIGHT_GREEN
+                                                                } else {
+                                                                    Color32::GRAY
+                                                                },
+                                                            ),
+                                                        )
+                                                        .sense(egui::Sense::click()),
                                                     );
+                                                    if is_current && let Some(client) = &self.client
+                                                    {
+                                                        if label.clicked() {
+                                                            self.expanded_user = match &self
+                                                                .expanded_user
+                                                            {
+                                                                Some(open) if open == name => None,
+                                                                _ => Some(name.clone()),
+                                                            };
+                                                        }
+                                                        label
+                                                            .on_hover_text(
+                                                                "Click or right click for volume",
+                                                            )
+                                                            .context_menu(|ui| {
+                                                                user_volume_menu(
+                                                                    ui,
+                                                                    client,
+                                                                    name,
+                                                                    &mut self.unmuted_volumes,
+                                                                )
+                                                            });
+                                                    }
                                                     ui.with_layout(
                                                         egui::Layout::right_to_left(
                                                             egui::Align::Center,
                                                         ),
                                                         |ui| {
+                                                            let volume = self
+                                                                .client
+                                                                .as_ref()
+                                                                .filter(|_| is_current)
+                                                                .map_or(1.0, |client| {
+                                                                    client.user_volume(name)
+                                                                });
+                                                            if volume == 0.0 {
+                                                                badge(
+                                                                    ui,
+                                                                    "muted for you",
+                                                                    Color32::LIGHT_RED,
+                                                                );
+                                                            } else if volume != 1.0 {
+                                                                badge(
+                                                                    ui,
+                                                                    format!(
+                                                                        "{:.0}%",
+                                                                        volume * 100.0
+                                                                    ),
+                                                                    Color32::GRAY,
+                                                                );
+                                                            }
                                                             if *deafened {
                                                                 badge(
                                                                     ui,
@@ -654,18 +985,37 @@ impl eframe::App for GuiClientApp {
                                                         },
                                                     );
                                                 });
+                                                if is_current
+                                                    && self.expanded_user.as_ref() == Some(name)
+                                                    && let Some(client) = &self.client
+                                                {
+                                                    ui.indent(name, |ui| {
+                                                        user_volume_controls(
+                                                            ui,
+                                                            client,
+                                                            name,
+                                                            &mut self.unmuted_volumes,
+                                                        )
+                                                    });
+                                                }
                                             }
                                         }
+                                        header
                                     })
-                                    .response;
+                                    .inner;
 
-                                // Make the entire card clickable
-                                if !is_current && response.clicked() {
+                                // Double-click the header to switch
+                                if !is_current && header.double_clicked() {
                                     self.join_channel(channel.channel_id);
                                 }
+                                let header = if is_current {
+                                    header
+                                } else {
+                                    header.on_hover_text("Double-click to join")
+                                };
 
                                 // Context menu
-                                response.context_menu(|ui| {
+                                header.context_menu(|ui| {
                                     if !is_current && ui.button("Join channel").clicked() {
                                         self.join_channel(channel.channel_id);
                                         ui.close_menu();
@@ -684,10 +1034,11 @@ impl eframe::App for GuiClientApp {
 
                     ui.horizontal(|ui| {
                         if !self.muted {
-                            connection_activity_wifi(ui, 18.0, Color32::LIGHT_GREEN);
+                            connection_activity_wifi(ui, 18.0, quality_color(&self.stats))
+                                .on_hover_text(quality_verdict(&self.stats));
 
                             let idevice_name = if let Some(client) = &self.client {
-                                client.lock().unwrap().devices.lock().unwrap().input.clone()
+                                client.devices.lock().unwrap().input.clone()
                             } else {
                                 String::new()
                             };
@@ -707,19 +1058,31 @@ impl eframe::App for GuiClientApp {
 
                     ui.horizontal(|ui| {
                         // ----- Ping -----
-                        if self.ping != u16::MAX {
-                            let color = match self.ping {
-                                p if p < 125 => Color32::LIGHT_GREEN,
-                                p if p < 250 => Color32::YELLOW,
-                                _ => Color32::RED,
-                            };
-                            ui.label(RichText::new("📡").size(18.0).color(color));
+                        if let Some(ping) = self.stats.rtt_ms {
+                            let color = quality_color(&self.stats);
+                            let stats = format!("{}\n{}", self.stats, quality_verdict(&self.stats));
+                            ui.label(RichText::new("📡").size(18.0).color(color))
+                                .on_hover_text(&stats);
                             ui.label(RichText::new("Ping: ").size(14.0).color(Color32::WHITE));
+                            ui.label(RichText::new(format!("{ping} ms")).size(14.0).color(color))
+                                .on_hover_text(&stats);
+                            if self.stats.loss > 0.0 {
+                                ui.label(
+                                    RichText::new(format!("{:.0}% loss", self.stats.loss * 100.0))
+                                        .size(14.0)
+                                        .color(color),
+                                )
+                                .on_hover_text(&stats);
+                            }
                             ui.label(
-                                RichText::new(format!("{} ms", self.ping))
-                                    .size(14.0)
-                                    .color(color),
-                            );
+                                RichText::new(format!(
+                                    "↑{:.0} ↓{:.0} kbps",
+                                    self.stats.send_kbps, self.stats.recv_kbps
+                                ))
+                                .size(14.0)
+                                .color(Color32::GRAY),
+                            )
+                            .on_hover_text(&stats);
                         }
                         ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
                             let btn_size = [60.0, 25.0]; // slightly smaller buttons
@@ -739,18 +1102,7 @@ impl eframe::App for GuiClientApp {
                                 )
                                 .clicked()
                             {
-                                self.deafened = !self.deafened;
-                                if let Some(client) = &self.client {
-                                    client.lock().unwrap().set_deafened(self.deafened);
-                                }
-                                if self.deafened {
-                                    self.write_log("[Speaker] deafened".into(), Color32::RED);
-                                } else {
-                                    self.write_log(
-                                        "[Speaker] undeafened".into(),
-                                        Color32::LIGHT_GREEN,
-                                    );
-                                }
+                                self.toggle_deafened();
                             }
 
                             ui.add_space(2.0); // small gap between buttons
@@ -770,23 +1122,228 @@ impl eframe::App for GuiClientApp {
                                 )
                                 .clicked()
                             {
-                                self.muted = !self.muted;
-                                if let Some(client) = &self.client {
-                                    client.lock().unwrap().set_muted(self.muted);
+                                self.toggle_muted();
+                            }
+                            ui.add_space(2.0);
+
+                            // Push-to-talk button
+                            let ptt_color = if self.talk_key.is_some() || self.binding_talk_key {
+                                Color32::from_rgb(60, 120, 240)
+                            } else {
+                                ui.visuals().widgets.inactive.bg_fill
+                            };
+                            let ptt_text = match (self.binding_talk_key, self.talk_key) {
+                                (true, _) => "...",
+                                (false, Some(key)) => key.name(),
+                                (false, None) => "PTT",
+                            };
+                            if ui
+                                .add_sized(
+                                    btn_size,
+                                    egui::Button::new(RichText::new(ptt_text).strong())
+                                        .fill(ptt_color)
+                                        .rounding(6.0),
+                                )
+                                .on_hover_text(
+                                    "Push-to-talk: click, then press the key to hold while \
+                                     talking. Escape turns it off",
+                                )
+                                .clicked()
+                            {
+                                self.binding_talk_key = !self.binding_talk_key;
+                            }
+                            ui.add_space(2.0);
+
+                            // Away button
+                            let away_color = if self.away {
+                                Color32::from_rgb(60, 120, 240)
+                            } else {
+                                ui.visuals().widgets.inactive.bg_fill
+                            };
+                            if ui
+                                .add_sized(
+                                    btn_size,
+                                    egui::Button::new(RichText::new("Away").strong())
+                                        .fill(away_color)
+                                        .rounding(6.0),
+                                )
+                                .clicked()
+                            {
+                                self.away = !self.away;
+                                if self.away {
+                                    if let Some(client) = &self.client {
+                                        client.set_away();
+                                    }
+                                    self.write_log("[Status] away".into(), Color32::YELLOW);
+                                } else {
+                                    let missed = match &self.client {
+                                        Some(client) => client.set_back(),
+                                        None => vec![],
+                                    };
+                                    self.write_log(
+                                        format!(
+                                            "[Status] back, {} whisper(s) while you were away",
+                                            missed.len()
+                                        ),
+                                        Color32::LIGHT_GREEN,
+                                    );
+                                    for dm in missed {
+                                        self.logs.write().unwrap().push((
+                                            format!("[Whisper] {}: {}", dm.from, dm.message),
+                                            Color32::from_rgb(220, 160, 255),
+                                            dm.at,
+                                        ));
+                                    }
                                 }
-                                if self.muted {
-                                    self.write_log("[Microphone] muted".into(), Color32::RED);
+                            }
+                            ui.add_space(2.0);
+
+                            // Record button
+                            let recording = self
+                                .client
+                                .as_ref()
+                                .is_some_and(|client| client.is_recording());
+                            let rec_color = if recording {
+                                Color32::from_rgb(200, 50, 50)
+                            } else {
+                                ui.visuals().widgets.inactive.bg_fill
+                            };
+                            if ui
+                                .add_sized(
+                                    btn_size,
+                                    egui::Button::new(RichText::new("Rec").strong())
+                                        .fill(rec_color)
+                                        .rounding(6.0),
+                                )
+                                .on_hover_text("Record the call, your voice included")
+                                .clicked()
+                                && let Some(client) = self.client.clone()
+                            {
+                                if recording {
+                                    match client.stop_recording() {
+                                        Ok(Some(path)) => self.write_log(
+                                            format!("[Recording] saved {}", path.display()),
+                                            Color32::LIGHT_GREEN,
+                                        ),
+                                        Ok(None) => {}
+                                        Err(e) => self.write_log(
+                                            format!("[Recording] couldn't save: {e}"),
+                                            Color32::RED,
+                                        ),
+                                    }
                                 } else {
+                                    match client.start_recording(None, true) {
+                                        Ok(path) => self.write_log(
+                                            format!("[Recording] recording to {}", path.display()),
+                                            Color32::YELLOW,
+                                        ),
+                                        Err(e) => self.write_log(
+                                            format!("[Recording] couldn't start: {e}"),
+                                            Color32::RED,
+                                        ),
+                                    }
+                                }
+                            }
+                            ui.add_space(2.0);
+
+                            // Echo cancellation button
+                            let aec_color = if self.echo_cancel {
+                                Color32::from_rgb(60, 120, 240)
+                            } else {
+                                ui.visuals().widgets.inactive.bg_fill
+                            };
+                            if ui
+                                .add_sized(
+                                    btn_size,
+                                    egui::Button::new(RichText::new("AEC").strong())
+                                        .fill(aec_color)
+                                        .rounding(6.0),
+                                )
+                                .on_hover_text("Cancel speaker echo, for use without headphones")
+                                .clicked()
+                            {
+                                self.echo_cancel = !self.echo_cancel;
+                                if let Some(client) = &self.client {
+                                    client.set_echo_cancellation(self.echo_cancel);
+                                }
+                                if self.echo_cancel {
                                     self.write_log(
-                                        "[Microphone] unmuted".into(),
+                                        "[Speaker] echo cancellation on".into(),
                                         Color32::LIGHT_GREEN,
                                     );
+                                } else {
+                                    self.write_log(
+                                        "[Speaker] echo cancellation off".into(),
+                                        Color32::YELLOW,
+                                    );
                                 }
                             }
                             ui.add_space(2.0);
+                            ui.menu_button(RichText::new("🔔").size(16.0), |ui| {
+                                self.notify_menu(ui)
+                            })
+                            .response
+                            .on_hover_text("Notifications");
+                            ui.menu_button(RichText::new("🎤").size(16.0), |ui| {
+                                self.mic_check_ui(ui)
+                            })
+                            .response
+                            .on_hover_text("Microphone check");
+                            ui.add_space(2.0);
+                            self.level_meter(ui);
                             self.talking_indicator(ui);
+                            self.transmit_indicator(ui);
                         });
                     });
+
+                    // ----- Soundboard -----
+                    if !self.clips.is_empty() {
+                        ui.add_space(2.0);
+                        ui.horizontal_wrapped(|ui| {
+                            ui.label(RichText::new("🔊").size(14.0))
+                                .on_hover_text(format!(
+                                    "Clips in ./{}",
+                                    soundboard::DEFAULT_CLIPS_DIR
+                                ));
+                            let playing = self
+                                .client
+                                .as_ref()
+                                .and_then(|client| client.playing_clip());
+
+                            for path in self.clips.clone() {
+                                let name = path
+                                    .file_stem()
+                                    .map(|stem| stem.to_string_lossy().into_owned())
+                                    .unwrap_or_default();
+                                let fill =
+                                    if playing.as_ref().is_some_and(|clip| clip.name() == name) {
+                                        Color32::from_rgb(60, 120, 240)
+                                    } else {
+                                        ui.visuals().widgets.inactive.bg_fill
+                                    };
+                                if ui
+                                    .add(egui::Button::new(&name).fill(fill).rounding(6.0))
+                                    .clicked()
+                                    && let Some(client) = self.client.clone()
+                                {
+                                    match Clip::load(&path) {
+                                        Ok(clip) => client.play_clip(clip, ClipMode::Mix),
+                                        Err(e) => self.write_log(
+                                            format!("[Soundboard] couldn't play {name}: {e}"),
+                                            Color32::RED,
+                                        ),
+                                    }
+                                }
+                            }
+
+                            if playing.is_some()
+                                && ui.add(egui::Button::new("Stop").rounding(6.0)).clicked()
+                                && let Some(client) = &self.client
+                            {
+                                client.stop_clip();
+                            }
+                        });
+                    }
                 });
 
             egui::CentralPanel::default().show(ctx, |ui| {
@@ -808,14 +1365,7 @@ impl eframe::App for GuiClientApp {
                         )
                         .clicked()
                     {
-                        self.disconnect();
-                        self.write_log(
-                            format!(
-                                "Sent EOF to {}. It is now handling our departure",
-                                self.address
-                            ),
-                            Color32::YELLOW,
-                        );
+                        self.leave();
                     }
 
                     // ----- Renick -----
@@ -864,6 +1414,15 @@ impl eframe::App for GuiClientApp {
                         ui.spacing_mut().item_spacing = egui::vec2(0.0, 4.0);
 
                         let logs = self.logs.read().unwrap();
+                        // mute and deafen state of who's talking in our channel
+                        let states = self
+                            .global_list
+                            .channels
+                            .iter()
+                            .filter(|channel| channel.channel_id == self.current_channel_id)
+                            .flat_map(|channel| &channel.masked_users)
+                            .map(|(name, muted, deafened)| (name.as_str(), (*muted, *deafened)))
+                            .collect::<HashMap<_, _>>();
 
                         for (msg, color, time) in logs.iter() {
                             let is_self = *color == Color32::LIGHT_BLUE || *color == Color32::BLUE;
@@ -920,6 +1479,16 @@ impl eframe::App for GuiClientApp {
                                 };
 
                                 let channel_label = format!("{} ", name);
+                                let (muted, deafened) =
+                                    states.get(name.as_str()).copied().unwrap_or_default();
+                                let state_badges = |ui: &mut egui::Ui| {
+                                    if muted {
+                                        badge(ui, "muted", Color32::from_rgb(120, 160, 255));
+                                    }
+                                    if deafened {
+                                        badge(ui, "deafened", Color32::YELLOW);
+                                    }
+                                };
                                 if is_self {
                                     ui.with_layout(
                                         egui::Layout::right_to_left(egui::Align::TOP),
@@ -930,6 +1499,7 @@ impl eframe::App for GuiClientApp {
                                                     .color(Color32::LIGHT_YELLOW)
                                                     .size(13.0),
                                             );
+                                            state_badges(ui);
                                             ui.add_space(4.0);
                                         },
                                     );
@@ -941,6 +1511,7 @@ impl eframe::App for GuiClientApp {
                                                 .color(Color32::from_rgb(150, 150, 150))
                                                 .size(13.0),
                                         );
+                                        state_badges(ui);
                                         ui.add_space(4.0);
                                     });
                                 }
@@ -1059,132 +1630,498 @@ impl eframe::App for GuiClientApp {
                     });
             });
         }
+    }
 
-        // TODO: merge this with the upper block
-        // === Update chat logs ===
-        {
-            let Some(client) = self.client.clone() else {
-                ctx.request_repaint_after(std::time::Duration::from_millis(16));
-                return;
-            };
-            let client = client.lock().unwrap();
-            let Some(ref rx) = client.rx else {
-                ctx.request_repaint_after(std::time::Duration::from_millis(16));
-                return;
-            };
-            match rx.try_recv() {
-                Ok((msg, time)) => match msg {
-                    Message::JoinMessage(name) => {
-                        self.logs.write().unwrap().push((
-                            format!("{name} joined the channel"),
-                            Color32::YELLOW,
-                            time,
-                        ));
-                    }
-                    Message::LeaveMessage(name) => {
-                        self.logs.write().unwrap().push((
-                            format!("{name} left the channel"),
-                            Color32::YELLOW,
-                            time,
-                        ));
-                    }
-                    Message::Renick(old, new) => {
-                        self.logs.write().unwrap().push((
-                            format!("{old} is now known as {new}"),
-                            Color32::YELLOW,
-                            time,
-                        ));
-                    }
-                    Message::ChatMessage(name, content, is_self) => {
-                        let channel = {
-                            let id = self.current_channel_id;
-
-                            self.global_list
-                                .channels
-                                .iter()
-                                .filter(|channel| channel.channel_id == id)
-                                .next_back()
-                                .map(|info| info.name.clone())
-                                .unwrap_or(String::from("unknown"))
-                        };
+    // what has to keep going whether or not the tab is on screen
+    fn poll(&mut self, ctx: &egui::Context, active: bool, global_held: bool) {
+        // a background tab is worth a desktop notification even with focus
+        self.focused = active && ctx.input(|i| i.focused);
+        // only the tab on screen holds the mic for its meter
+        if !active {
+            self.mic_check = None;
+            self.mic_echo = false;
+        }
 
-                        self.logs.write().unwrap().push((
-                            format!("[#{channel}] {name}: {content}"),
-                            if is_self {
-                                Color32::LIGHT_BLUE
-                            } else {
-                                Color32::WHITE
-                            },
-                            time,
-                        ));
-                    }
-                    Message::Broadcast(src, content) => {
-                        self.logs.write().unwrap().push((
-                            format!("[{src}] {content}"),
-                            Color32::LIGHT_GREEN,
-                            time,
-                        ));
-                    }
-                    Message::Command(result) => {
-                        type Cr = CommandResult;
-                        match result {
-                            Cr::Success(content) => {
-                                self.logs.write().unwrap().push((
-                                    format!("[Command Success] {content}"),
-                                    Color32::LIGHT_GREEN,
-                                    time,
-                                ));
-                            }
-                            Cr::Error(content) => {
-                                self.logs.write().unwrap().push((
-                                    format!("[Command Fail] {content}"),
-                                    Color32::LIGHT_RED,
-                                    time,
-                                ));
-                            }
-                            Cr::Silent => {}
-                        }
-                    }
-                    Message::Kick(msg) => {
-                        drop(client);
-                        self.disconnect();
+        // === Handle client events ===
+        // checked first, so whatever it sent before stopping is handled below
+        let stopped = self
+            .client_thread
+            .as_ref()
+            .is_some_and(|handle| handle.is_finished());
+        let events = match &self.events {
+            Some(rx) => rx.try_iter().collect::<Vec<_>>(),
+            None => vec![],
+        };
+        for (event, time) in events {
+            self.handle_event(event, time);
+        }
+        // the client stopped without saying why, nothing more is coming
+        if stopped && self.is_connected {
+            self.connection_lost("Connection lost: the client stopped".into());
+        }
+        self.poll_connecting();
+        self.poll_reconnect();
+        self.push_to_talk(ctx, active, global_held);
+    }
 
-                        self.error.message = msg;
-                        self.error.show = ShowMode::ShowError;
-                    }
-                },
-                Err(TryRecvError::Empty) => thread::yield_now(),
-                Err(TryRecvError::Disconnected) => {}
+    // the server's address once there's one, for the tab
+    fn title(&self) -> String {
+        if self.is_connected || self.reconnect.is_some() {
+            self.address.clone()
+        } else {
+            "New connection".into()
+        }
+    }
+
+    fn handle_event(&mut self, event: ClientEvent, time: DateTime<Local>) {
+        let log = |line: String, color: Color32| {
+            self.logs.write().unwrap().push((line, color, time));
+        };
+
+        match event {
+            ClientEvent::UserJoined(name) => {
+                log(format!("{name} joined the channel"), Color32::YELLOW);
+                self.notifier.alert(
+                    self.client.as_deref(),
+                    &self.prefs.borrow().notify,
+                    Alert::Join,
+                    &format!("{name} joined"),
+                    "",
+                    self.focused,
+                );
+            }
+            ClientEvent::UserLeft(name) => {
+                log(format!("{name} left the channel"), Color32::YELLOW);
+                self.notifier.alert(
+                    self.client.as_deref(),
+                    &self.prefs.borrow().notify,
+                    Alert::Join,
+                    &format!("{name} left"),
+                    "",
+                    self.focused,
+                );
+            }
+            ClientEvent::Renamed { old, new } => {
+                log(format!("{old} is now known as {new}"), Color32::YELLOW);
+            }
+            ClientEvent::Chat {
+                from,
+                message,
+                is_self,
+            } => {
+                let channel = self
+                    .global_list
+                    .channels
+                    .iter()
+                    .rfind(|channel| channel.channel_id == self.current_channel_id)
+                    .map(|info| info.name.clone())
+                    .unwrap_or(String::from("unknown"));
+
+                let color = if is_self {
+                    Color32::LIGHT_BLUE
+                } else {
+                    Color32::WHITE
+                };
+                log(format!("[#{channel}] {from}: {message}"), color);
+                if !is_self && self.nicked && util::mentions(&message, &self.nick) {
+                    self.notifier.alert(
+                        self.client.as_deref(),
+                        &self.prefs.borrow().notify,
+                        Alert::Mention,
+                        &format!("{from} in #{channel}"),
+                        &message,
+                        self.focused,
+                    );
+                }
+            }
+            ClientEvent::Broadcast { from, message } => {
+                log(format!("[{from}] {message}"), Color32::LIGHT_GREEN);
+            }
+            ClientEvent::Command { command, result } => match result {
+                CommandResult::Success(content) => {
+                    log(format!("[{command}] {content}"), Color32::LIGHT_GREEN);
+                }
+                CommandResult::Error(content) => {
+                    log(format!("[{command} failed] {content}"), Color32::LIGHT_RED);
+                }
+                CommandResult::Silent => {}
+            },
+            ClientEvent::Whisper { from, message } => {
+                log(
+                    format!("[Whisper] {from}: {message}"),
+                    Color32::from_rgb(220, 160, 255),
+                );
+                self.notifier.alert(
+                    self.client.as_deref(),
+                    &self.prefs.borrow().notify,
+                    Alert::Whisper,
+                    &format!("{from} whispers"),
+                    &message,
+                    self.focused,
+                );
+            }
+            ClientEvent::Notice(notice) => {
+                log(
+                    format!("[Server] {}", notice.render()),
+                    Color32::LIGHT_YELLOW,
+                );
+            }
+            ClientEvent::ListUpdated { channels, current } => {
+                self.global_list.channels = channels;
+                self.global_list.last_updated = Instant::now();
+                self.global_list.current_channel = current;
+                self.current_channel_id = current;
+            }
+            ClientEvent::CommandsUpdated(commands) => self.command_list = commands,
+            ClientEvent::TalkersChanged(talkers) => self.talkers = talkers,
+            ClientEvent::NickRequired => {
+                self.nicked = false;
+                self.error.show = ShowMode::ShowMaskScreen;
+                self.error.message = "You need to set a nickname first!".to_string();
+            }
+            ClientEvent::Error(e) => eprintln!("error: {e}"),
+            // only a lost connection is worth trying again
+            ClientEvent::Disconnected(DisconnectReason::ConnectionLost(e)) => {
+                self.connection_lost(format!("Connection lost: {e}"));
+            }
+            ClientEvent::Disconnected(reason) => {
+                self.disconnect();
+
+                self.error.message = match reason {
+                    DisconnectReason::Kicked(reason) => reason,
+                    DisconnectReason::IncorrectPhrase => "Incorrect phrase".into(),
+                    DisconnectReason::ConnectionLost(e) => format!("Connection lost: {e}"),
+                };
+                self.error.show = ShowMode::ShowError;
             }
         }
+    }
 
-        ctx.request_repaint_after(std::time::Duration::from_millis(16));
+    // the disconnect button, as opposed to the server or network dropping us
+    fn leave(&mut self) {
+        let reconnecting = self.reconnect.is_some();
+        self.disconnect();
+        if reconnecting {
+            self.write_log("[Connection] stopped reconnecting".into(), Color32::YELLOW);
+            return;
+        }
+        self.write_log(
+            format!(
+                "Sent EOF to {}. It is now handling our departure",
+                self.address
+            ),
+            Color32::YELLOW,
+        );
+    }
+
+    // takes over a client that finished its handshake
+    fn start_client(&mut self, state: ClientState) {
+        state.use_settings(Arc::new(FileStorage::open(Path::new(
+            client::DEFAULT_SETTINGS_DIR,
+        ))));
+        state.set_echo_cancellation(self.echo_cancel);
+        state.apply_prefs(&self.prefs.borrow());
+        if let Some(nick) = &self.prefs.borrow().nick {
+            self.nick = nick.clone();
+            self.nicked = true;
+        }
+        self.socket = Some(state.socket.clone());
+        self.events = state.events();
+        let arc_state = Arc::new(state);
+        let thread_state = arc_state.clone();
+        let handle = std::thread::spawn(move || {
+            let _ = thread_state.run(client::Mode::Gui);
+        });
+
+        self.client_thread = Some(handle);
+        self.client = Some(arc_state);
+        self.is_connected = true;
+        self.store_prefs();
+    }
+
+    // keeps the chat on screen and starts bringing the connection back, the
+    // prefs saved on the way out carry our nick and channel over
+    fn connection_lost(&mut self, reason: String) {
+        self.disconnect();
+        self.write_log(format!("[Connection] {reason}"), Color32::LIGHT_RED);
+        self.reconnect = Some(Reconnect {
+            reason,
+            attempt: 0,
+            next: Instant::now(),
+            pending: None,
+        });
+    }
+
+    // takes the client once the connect button's handshake is through
+    fn poll_connecting(&mut self) {
+        match self.connecting.take() {
+            Some(pending) if pending.is_finished() => match pending.join() {
+                Ok(Ok(state)) => self.start_client(state),
+                result => {
+                    let reason = match result {
+                        Ok(Err(e)) => e.to_string(),
+                        _ => "the handshake panicked".into(),
+                    };
+                    self.error.show = ShowMode::ShowError;
+                    self.error.message = format!("Failed to connect to the server: {reason}");
+                }
+            },
+            pending => self.connecting = pending,
+        }
+    }
+
+    // starts the next attempt once its time comes and takes the client of
+    // one that got through
+    fn poll_reconnect(&mut self) {
+        let Some(reconnect) = &mut self.reconnect else {
+            return;
+        };
+        match reconnect.pending.take() {
+            Some(pending) if pending.is_finished() => match pending.join() {
+                Ok(Ok(state)) => {
+                    self.reconnect = None;
+                    self.start_client(state);
+                    self.write_log("[Connection] reconnected".into(), Color32::LIGHT_GREEN);
+                }
+                result => {
+                    reconnect.attempt += 1;
+                    reconnect.next = Instant::now() + reconnect_delay(reconnect.attempt);
+                    if let Ok(Err(e)) = result {
+                        log::warn!("Reconnect attempt {} failed: {e}", reconnect.attempt);
+                    }
+                }
+            },
+            Some(pending) => reconnect.pending = Some(pending),
+            None if Instant::now() >= reconnect.next => {
+                let address = self.address.clone();
+                let channel_id = self.channel_id;
+                let phrase = self.phrase.clone().into_bytes();
+                reconnect.pending = Some(std::thread::spawn(move || {
+                    let state = ClientState::new(&address, channel_id, &phrase)?;
+                    state.handshake(client::HANDSHAKE_TIMEOUT)?;
+                    Ok(state)
+                }));
+            }
+            None => {}
+        }
     }
-}
 
-impl GuiClientApp {
     fn disconnect(&mut self) {
+        self.store_prefs();
         if let Some(client) = &self.client {
-            client.lock().unwrap().disconnect();
+            // reconnecting goes back where we were
+            self.channel_id = client.channel_id();
+            client.disconnect();
         }
 
         if let Some(handle) = self.client_thread.take() {
             handle.join().ok();
         }
         self.is_connected = false;
+        self.reconnect = None;
+        self.socket = None;
+        self.away = false;
         self.nicked = false;
         self.nick = String::new();
         self.client = None;
+        self.events = None;
+        self.talkers.clear();
+        self.expanded_user = None;
+        self.mic_check = None;
+        self.mic_echo = false;
+        self.mic_error = None;
+    }
+    fn toggle_muted(&mut self) {
+        self.muted = !self.muted;
+        if let Some(client) = &self.client {
+            client.set_muted(self.muted);
+        }
+        if self.muted {
+            self.write_log("[Microphone] muted".into(), Color32::RED);
+        } else {
+            self.write_log("[Microphone] unmuted".into(), Color32::LIGHT_GREEN);
+        }
+    }
+
+    fn toggle_deafened(&mut self) {
+        self.deafened = !self.deafened;
+        if let Some(client) = &self.client {
+            client.set_deafened(self.deafened);
+        }
+        if self.deafened {
+            self.write_log("[Speaker] deafened".into(), Color32::RED);
+        } else {
+            self.write_log("[Speaker] undeafened".into(), Color32::LIGHT_GREEN);
+        }
+    }
+
+    // binds the talk key once one is pressed, then keeps the client's gate in
+    // step with it. Only the tab on screen hears the key
+    fn push_to_talk(&mut self, ctx: &egui::Context, active: bool, global_held: bool) {
+        if self.binding_talk_key && active {
+            let pressed = ctx.input(|i| {
+                i.events.iter().find_map(|event| match event {
+                    egui::Event::Key {
+                        key, pressed: true, ..
+                    } => Some(*key),
+                    _ => None,
+                })
+            });
+            if let Some(key) = pressed {
+                self.binding_talk_key = false;
+                self.talk_key = (key != egui::Key::Escape).then_some(key);
+                match self.talk_key {
+                    Some(key) => self.write_log(
+                        format!("[Microphone] hold {} to talk", key.name()),
+                        Color32::LIGHT_GREEN,
+                    ),
+                    None => self.write_log("[Microphone] push-to-talk off".into(), Color32::YELLOW),
+                }
+                self.store_prefs();
+            }
+        }
+
+        let Some(client) = &self.client else {
+            return;
+        };
+        client.set_push_to_talk(self.talk_key.is_some());
+        let Some(key) = self.talk_key else {
+            return;
+        };
+        // typing in the chat box isn't talking
+        let held = active
+            && !self.binding_talk_key
+            && (global_held || !ctx.wants_keyboard_input() && ctx.input(|i| i.key_down(key)));
+        client.set_talk_key(held);
     }
+
+    // opens the mic for the meter, playing it back through the speakers with
+    // `play_back`. Connected, the call is already metering it
+    fn open_mic_check(&mut self, play_back: bool) {
+        // the old streams have to let go of the devices first
+        self.mic_check = None;
+        self.mic_echo = false;
+        let loopback = match &self.client {
+            Some(client) if play_back => client.start_loopback(),
+            Some(_) => return,
+            None => {
+                let prefs = self.prefs.borrow();
+                let devices = Mutex::new(AudioDevices {
+                    preferred_input: prefs.input_device.clone(),
+                    preferred_output: prefs.output_device.clone(),
+                    ..Default::default()
+                });
+                ClientState::check_mic(
+                    &devices,
+                    AudioOptions::default(),
+                    &self.mic_levels,
+                    play_back,
+                )
+            }
+        };
+        match loopback {
+            Ok(loopback) => {
+                self.mic_check = Some(loopback);
+                self.mic_echo = play_back;
+                self.mic_error = None;
+            }
+            Err(e) => self.mic_error = Some(format!("{e:#}")),
+        }
+    }
+
+    // the mic's live level and a loopback, for finding out why nobody hears us
+    fn mic_check_ui(&mut self, ui: &mut egui::Ui) {
+        let input = match &self.client {
+            Some(client) => client.levels.input.rms_dbfs(),
+            None => self.mic_levels.input.rms_dbfs(),
+        };
+        ui.horizontal(|ui| {
+            ui.label(RichText::new("🎤").size(18.0));
+            ui.add(vu_bar(input).desired_width(180.0).text(if input > -99.0 {
+                format!("{input:.0} dB")
+            } else {
+                "silent".into()
+            }));
+            let test = if self.mic_echo {
+                "Stop test"
+            } else {
+                "Test mic"
+            };
+            if ui
+                .button(test)
+                .on_hover_text("Play the microphone back through the speakers")
+                .clicked()
+            {
+                self.open_mic_check(!self.mic_echo);
+            }
+        });
+        if let Some(e) = &self.mic_error {
+            ui.label(
+                RichText::new(format!("The microphone won't open: {e}"))
+                    .small()
+                    .color(Color32::LIGHT_RED),
+            );
+        }
+    }
+
+    // what to be notified about and with what sound
+    fn notify_menu(&mut self, ui: &mut egui::Ui) {
+        let changed = {
+            let notify = &mut self.prefs.borrow_mut().notify;
+            ui.label(RichText::new("Notify me about").strong());
+            let mut changed = ui.checkbox(&mut notify.mentions, "Mentions").changed();
+            changed |= ui.checkbox(&mut notify.whispers, "Whispers").changed();
+            changed |= ui.checkbox(&mut notify.joins, "Joins and leaves").changed();
+
+            ui.separator();
+            ui.label("Sound file, the chime when empty");
+            let mut sound = notify.sound.clone().unwrap_or_default();
+            let edit = ui.text_edit_singleline(&mut sound);
+            if edit.changed() {
+                notify.sound = Some(sound.trim().to_string()).filter(|path| !path.is_empty());
+            }
+            changed | edit.lost_focus()
+        };
+
+        if ui.button("Test").clicked() {
+            self.notifier.notify(
+                self.client.as_deref(),
+                &self.prefs.borrow().notify,
+                "VoUDP",
+                "This is what notifications look like",
+                false,
+            );
+        }
+        if changed {
+            self.store_prefs();
+        }
+    }
+
+    // only with push-to-talk on, whether the mic is going out
+    fn transmit_indicator(&mut self, ui: &mut egui::Ui) {
+        let (Some(client), Some(key)) = (&self.client, self.talk_key) else {
+            return;
+        };
+        if client.is_transmitting() {
+            ui.label(
+                RichText::new("● transmitting")
+                    .size(12.0)
+                    .color(Color32::from_rgb(60, 200, 90)),
+            );
+        } else {
+            ui.label(
+                RichText::new(format!("hold {} to talk", key.name()))
+                    .size(12.0)
+                    .color(Color32::GRAY),
+            );
+        }
+    }
+
     fn talking_indicator(&mut self, ui: &mut egui::Ui) -> egui::Response {
         let is_talking = self.client.clone();
 
         let is_talking = match is_talking {
-            Some(a) => a
-                .lock()
-                .unwrap()
-                .talking
-                .load(std::sync::atomic::Ordering::Relaxed),
+            Some(a) => a.talking.load(std::sync::atomic::Ordering::Relaxed),
             None => false,
         };
 
@@ -1211,27 +2148,41 @@ impl GuiClientApp {
         response
     }
 
+    fn level_meter(&mut self, ui: &mut egui::Ui) -> egui::Response {
+        let (input, output) = match &self.client {
+            Some(client) => {
+                let levels = client.levels.clone();
+                (levels.input.rms_dbfs(), levels.output.rms_dbfs())
+            }
+            None => (f32::NEG_INFINITY, f32::NEG_INFINITY),
+        };
+        ui.add(vu_bar(input).desired_width(60.0))
+            .on_hover_text(format!(
+                "Microphone {input:.1} dBFS, speaker {output:.1} dBFS"
+            ))
+    }
+
     fn write_log(&mut self, log: String, color: Color32) {
         self.logs.write().unwrap().push((log, color, Local::now()));
     }
 
     fn request_global_list(&self) {
         if let Some(client) = &self.client {
-            let packet = vec![0x05]; // Request global list
-            client.lock().unwrap().send(&packet);
+            let filter = client.list.lock().unwrap().filter;
+            client.send(&protocol::create_list_page_request(0, 0, filter));
         }
     }
 
     fn request_command_list(&self) {
         if let Some(client) = &self.client {
             let packet = vec![0x0c]; // Request global list
-            client.lock().unwrap().send(&packet);
+            client.send(&packet);
         }
     }
 
     fn join_channel(&self, id: u32) {
         if let Some(client) = &self.client
-            && let Err(e) = client.lock().unwrap().join(id)
+            && let Err(e) = client.join_channel(id)
         {
             eprintln!(
                 "we faced an error when trying to join channel {}: {}",
@@ -1241,51 +2192,12 @@ impl GuiClientApp {
         self.request_global_list();
     }
 
-    fn update_global_list(&mut self) {
-        if let Some(client) = &self.client {
-            let client = client.lock().unwrap();
-            let list_state = client.list.lock().unwrap();
-            let ping = client.ping.load(Ordering::Relaxed);
-
-            self.global_list.channels = list_state.channels.clone();
-            self.global_list.last_updated = Instant::now();
-            self.global_list.current_channel = list_state.current_channel;
-            self.current_channel_id = list_state.current_channel;
-            self.ping = ping;
-        }
-    }
-
-    fn update_command_list(&mut self) {
-        if let Some(client) = &self.client {
-            let client = client.lock().unwrap();
-            let list_state = client.cmd_list.lock().unwrap();
-            self.command_list = list_state.to_vec();
-        }
-    }
-
     fn handle_command_nav(&mut self, ctx: &egui::Context, input_id: egui::Id) -> bool {
         if !self.show_command_suggestions || self.command_list.is_empty() {
             return false;
         }
 
-        let filter_text = self.filter_text.clone();
-        let filtered_commands: Vec<&ServerCommand> = self
-            .command_list
-            .iter()
-            .filter(|cmd| {
-                let name_match = cmd.name[1..]
-                    .to_lowercase()
-                    .starts_with(&filter_text.to_lowercase());
-
-                let alias_match = cmd.aliases.iter().any(|alias| {
-                    alias[1..]
-                        .to_lowercase()
-                        .starts_with(&filter_text.to_lowercase())
-                });
-
-                name_match || alias_match
-            })
-            .collect();
+        let filtered_commands = util::complete_command(&self.command_list, &self.filter_text);
 
         let filtered_count = filtered_commands.len();
         if filtered_count == 0 {
@@ -1341,24 +2253,7 @@ impl GuiClientApp {
     }
 
     fn get_filtered_commands(&self) -> Vec<&ServerCommand> {
-        if self.filter_text.is_empty() {
-            return self.command_list.iter().collect();
-        }
-
-        self.command_list
-            .iter()
-            .filter(|cmd| {
-                let name_match = cmd.name[1..]
-                    .to_lowercase()
-                    .starts_with(&self.filter_text.to_lowercase());
-                let alias_match = cmd.aliases.iter().any(|alias| {
-                    alias[1..]
-                        .to_lowercase()
-                        .starts_with(&self.filter_text.to_lowercase())
-                });
-                name_match || alias_match
-            })
-            .collect()
+        util::complete_command(&self.command_list, &self.filter_text)
     }
 
     fn show_command_suggestions_ui(&mut self, ui: &mut egui::Ui, input_id: egui::Id) {
@@ -1540,6 +2435,12 @@ impl GuiClientApp {
         self.show_command_suggestions = false;
         self.selected_suggestion = 0;
 
+        // keep what they typed so they can finish it
+        if let Some(usage) = util::usage_hint(&self.command_list, &self.input) {
+            self.write_log(format!("usage: {usage}"), Color32::YELLOW);
+            return;
+        }
+
         let mut msg = vec![0x0d];
         msg.extend_from_slice(self.input.as_bytes());
 
@@ -1585,14 +2486,109 @@ impl GuiClientApp {
     }
 
     fn set_nick(&mut self) {
-        let mut nick = vec![0x04];
-        nick.extend_from_slice(self.nick.as_bytes());
-
         let client = match &self.client {
-            Some(client) => client.lock().unwrap(),
+            Some(client) => client,
             None => return,
         };
 
-        client.send(&nick);
+        client.set_nick(&self.nick);
     }
+
+    // remembers the server and whatever the client has now for the next start
+    fn store_prefs(&mut self) {
+        let mut prefs = self.prefs.borrow_mut();
+        if let Some(client) = &self.client {
+            // volumes are per user, the ones set in other tabs stay
+            let mut volumes = std::mem::take(&mut prefs.volumes);
+            client.update_prefs(&mut prefs);
+            volumes.append(&mut prefs.volumes);
+            prefs.volumes = volumes;
+            prefs.server = Some(self.address.clone());
+        }
+        prefs.push_to_talk = self.talk_key.map(|key| key.name().to_string());
+        self.prefs_changed = true;
+    }
+}
+
+// a second, doubling up to half a minute, with up to 50% on top so everyone
+// dropped by a server restart doesn't come back at once
+fn reconnect_delay(attempt: u32) -> Duration {
+    let delay = Duration::from_secs(1 << attempt.min(5)).min(Duration::from_secs(30));
+    delay.mul_f32(1.0 + rand::random::<f32>() * 0.5)
+}
+
+// -60 dBFS and below shows as empty, red when close to clipping
+fn vu_bar(dbfs: f32) -> egui::ProgressBar {
+    egui::ProgressBar::new(((dbfs + 60.0) / 60.0).clamp(0.0, 1.0)).fill(if dbfs > -6.0 {
+        Color32::RED
+    } else {
+        Color32::from_rgb(60, 200, 90)
+    })
+}
+
+// green, yellow or red for how the network is doing, gray before the first ping
+fn quality_color(stats: &ConnectionStats) -> Color32 {
+    match (stats.rtt_ms, stats.loss) {
+        (None, _) => Color32::GRAY,
+        (Some(p), l) if p < 125 && l < 0.02 => Color32::LIGHT_GREEN,
+        (Some(p), l) if p < 250 && l < 0.1 => Color32::YELLOW,
+        _ => Color32::RED,
+    }
+}
+
+// whether crackle is down to our network or comes from further away
+fn quality_verdict(stats: &ConnectionStats) -> &'static str {
+    match (stats.rtt_ms, stats.loss) {
+        (None, _) => "Waiting for the first ping",
+        (_, l) if l >= 0.02 => "Audio is being lost on the way, check your network",
+        (Some(p), _) if p >= 250 => "The server is far away or your network is slow",
+        _ => "The network is fine",
+    }
+}
+
+// right click menu on a user in our channel
+fn user_volume_menu(
+    ui: &mut egui::Ui,
+    client: &ClientState,
+    mask: &str,
+    unmuted: &mut HashMap<String, f32>,
+) {
+    ui.label(RichText::new(format!("{mask}'s volume")).strong());
+    user_volume_controls(ui, client, mask, unmuted);
+}
+
+// only changes what we hear. `unmuted` keeps the volume a user had before
+// being muted, to go back to
+fn user_volume_controls(
+    ui: &mut egui::Ui,
+    client: &ClientState,
+    mask: &str,
+    unmuted: &mut HashMap<String, f32>,
+) {
+    let mut volume = client.user_volume(mask);
+    if ui
+        .add(
+            egui::Slider::new(&mut volume, 0.0..=protocol::MAX_TALKER_GAIN)
+                .custom_formatter(|v, _| format!("{:.0}%", v * 100.0)),
+        )
+        .changed()
+    {
+        client.set_user_volume(mask, volume);
+    }
+
+    ui.horizontal(|ui| {
+        let mut muted = volume == 0.0;
+        if ui.checkbox(&mut muted, "Mute for me").changed() {
+            if muted {
+                unmuted.insert(mask.to_string(), volume);
+                client.set_user_volume(mask, 0.0);
+            } else {
+                client.set_user_volume(mask, unmuted.remove(mask).unwrap_or(1.0));
+            }
+        }
+        if volume != 1.0 && ui.button("Reset").clicked() {
+            unmuted.remove(mask);
+            client.set_user_volume(mask, 1.0);
+        }
+    });
 }
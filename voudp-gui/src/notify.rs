@@ -0,0 +1,99 @@
+// Sounds and desktop notifications for the events the user picked
+use std::path::Path;
+
+use log::warn;
+use voudp::{away, client::ClientState, prefs::NotifyPrefs, soundboard::Clip};
+
+#[derive(Clone, Copy)]
+pub enum Alert {
+    Mention,
+    Whisper,
+    Join,
+}
+
+pub struct Notifier {
+    chime: Clip,
+    // the user's sound and the path it came from, loaded on first use
+    sound: Option<(String, Option<Clip>)>,
+}
+
+impl Default for Notifier {
+    fn default() -> Self {
+        Self {
+            chime: Clip::from_samples("chime", away::chime(48000)),
+            sound: None,
+        }
+    }
+}
+
+impl Notifier {
+    /// Notifies about `alert` if `prefs` asks for it
+    pub fn alert(
+        &mut self,
+        client: Option<&ClientState>,
+        prefs: &NotifyPrefs,
+        alert: Alert,
+        title: &str,
+        body: &str,
+        focused: bool,
+    ) {
+        let wanted = match alert {
+            Alert::Mention => prefs.mentions,
+            Alert::Whisper => prefs.whispers,
+            Alert::Join => prefs.joins,
+        };
+        if wanted {
+            self.notify(client, prefs, title, body, focused);
+        }
+    }
+
+    /// Plays the sound and, unless the window has focus, shows `title` and
+    /// `body` on the desktop
+    pub fn notify(
+        &mut self,
+        client: Option<&ClientState>,
+        prefs: &NotifyPrefs,
+        title: &str,
+        body: &str,
+        focused: bool,
+    ) {
+        if let Some(client) = client {
+            client.play_alert(self.sound(prefs));
+        }
+        if !focused {
+            desktop(title, body);
+        }
+    }
+
+    // the user's sound, or the chime when there's none or it won't load
+    fn sound(&mut self, prefs: &NotifyPrefs) -> &Clip {
+        let Some(path) = &prefs.sound else {
+            return &self.chime;
+        };
+        if self.sound.as_ref().is_none_or(|(loaded, _)| loaded != path) {
+            let clip = Clip::load(Path::new(path))
+                .inspect_err(|e| warn!("Could not load the notification sound {path}: {e:#}"))
+                .ok();
+            self.sound = Some((path.clone(), clip));
+        }
+        match &self.sound {
+            Some((_, Some(clip))) => clip,
+            _ => &self.chime,
+        }
+    }
+}
+
+#[cfg(feature = "desktop-notify")]
+fn desktop(title: &str, body: &str) {
+    let mut notification = notify_rust::Notification::new();
+    notification.summary(title).body(body).appname("VoUDP");
+    // talking to the notification daemon can block, the UI shouldn't
+    std::thread::spawn(move || {
+        if let Err(e) = notification.show() {
+            warn!("Could not show a desktop notification: {e}");
+        }
+    });
+}
+
+#[cfg(not(feature = "desktop-notify"))]
+fn desktop(_title: &str, _body: &str) {}
@@ -0,0 +1,196 @@
+// A tray icon with quick mute, deafen and disconnect, so the window can stay
+// minimized during a call
+use anyhow::Result;
+use log::warn;
+use tray_icon::{
+    Icon, TrayIcon, TrayIconBuilder,
+    menu::{CheckMenuItem, Menu, MenuEvent, MenuItem, PredefinedMenuItem},
+};
+
+const SHOW_ID: &str = "show";
+const MUTE_ID: &str = "mute";
+const DEAFEN_ID: &str = "deafen";
+const DISCONNECT_ID: &str = "disconnect";
+
+/// What the icon and the menu's checkmarks show
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct TrayState {
+    pub connected: bool,
+    pub muted: bool,
+    pub deafened: bool,
+}
+
+pub enum TrayAction {
+    Show,
+    ToggleMute,
+    ToggleDeafen,
+    Disconnect,
+}
+
+pub struct Tray {
+    // linux keeps the icon on a gtk thread, it only hears about changes
+    #[cfg(target_os = "linux")]
+    states: std::sync::mpsc::Sender<TrayState>,
+    #[cfg(not(target_os = "linux"))]
+    widgets: Widgets,
+    shown: Option<TrayState>,
+}
+
+impl Tray {
+    /// `None` where there's no tray to put an icon in
+    #[cfg(target_os = "linux")]
+    pub fn new() -> Option<Self> {
+        use std::{
+            sync::mpsc::{self, RecvTimeoutError},
+            time::Duration,
+        };
+
+        let (states, rx) = mpsc::channel::<TrayState>();
+        let (ready_tx, ready_rx) = mpsc::channel();
+        std::thread::spawn(move || {
+            let widgets = gtk::init()
+                .map_err(anyhow::Error::from)
+                .and_then(|()| Widgets::build());
+            let widgets = match widgets {
+                Ok(widgets) => {
+                    let _ = ready_tx.send(true);
+                    widgets
+                }
+                Err(e) => {
+                    warn!("The tray icon is unavailable: {e:#}");
+                    let _ = ready_tx.send(false);
+                    return;
+                }
+            };
+            // runs until the app drops its end
+            loop {
+                while gtk::events_pending() {
+                    gtk::main_iteration();
+                }
+                match rx.recv_timeout(Duration::from_millis(50)) {
+                    Ok(state) => widgets.show(state),
+                    Err(RecvTimeoutError::Timeout) => {}
+                    Err(RecvTimeoutError::Disconnected) => break,
+                }
+            }
+        });
+
+        ready_rx.recv().unwrap_or(false).then_some(Self {
+            states,
+            shown: None,
+        })
+    }
+
+    /// `None` where there's no tray to put an icon in
+    #[cfg(not(target_os = "linux"))]
+    pub fn new() -> Option<Self> {
+        match Widgets::build() {
+            Ok(widgets) => Some(Self {
+                widgets,
+                shown: None,
+            }),
+            Err(e) => {
+                warn!("The tray icon is unavailable: {e:#}");
+                None
+            }
+        }
+    }
+
+    /// Brings the icon and menu in line with `state`, a no-op when they are
+    pub fn show(&mut self, state: TrayState) {
+        if self.shown == Some(state) {
+            return;
+        }
+        self.shown = Some(state);
+        #[cfg(target_os = "linux")]
+        let _ = self.states.send(state);
+        #[cfg(not(target_os = "linux"))]
+        self.widgets.show(state);
+    }
+
+    /// The menu items clicked since the last call
+    pub fn actions(&self) -> Vec<TrayAction> {
+        MenuEvent::receiver()
+            .try_iter()
+            .filter_map(|event| match event.id.as_ref() {
+                SHOW_ID => Some(TrayAction::Show),
+                MUTE_ID => Some(TrayAction::ToggleMute),
+                DEAFEN_ID => Some(TrayAction::ToggleDeafen),
+                DISCONNECT_ID => Some(TrayAction::Disconnect),
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+struct Widgets {
+    icon: TrayIcon,
+    mute: CheckMenuItem,
+    deafen: CheckMenuItem,
+    disconnect: MenuItem,
+}
+
+impl Widgets {
+    fn build() -> Result<Self> {
+        let mute = CheckMenuItem::with_id(MUTE_ID, "Mute", true, false, None);
+        let deafen = CheckMenuItem::with_id(DEAFEN_ID, "Deafen", true, false, None);
+        let disconnect = MenuItem::with_id(DISCONNECT_ID, "Disconnect", false, None);
+        let menu = Menu::with_items(&[
+            &MenuItem::with_id(SHOW_ID, "Show VoUDP", true, None),
+            &PredefinedMenuItem::separator(),
+            &mute,
+            &deafen,
+            &disconnect,
+        ])?;
+
+        let icon = TrayIconBuilder::new()
+            .with_menu(Box::new(menu))
+            .with_tooltip("VoUDP")
+            .build()?;
+        Ok(Self {
+            icon,
+            mute,
+            deafen,
+            disconnect,
+        })
+    }
+
+    fn show(&self, state: TrayState) {
+        let (tooltip, color) = match state {
+            TrayState {
+                connected: false, ..
+            } => ("VoUDP, not connected", [128, 128, 128]),
+            TrayState {
+                muted: true,
+                deafened: true,
+                ..
+            } => ("VoUDP, muted and deafened", [220, 50, 50]),
+            TrayState { muted: true, .. } => ("VoUDP, muted", [100, 150, 255]),
+            TrayState { deafened: true, .. } => ("VoUDP, deafened", [230, 200, 40]),
+            _ => ("VoUDP, live", [60, 200, 90]),
+        };
+        if let Err(e) = self.icon.set_icon(dot(color)) {
+            warn!("Could not update the tray icon: {e}");
+        }
+        let _ = self.icon.set_tooltip(Some(tooltip));
+        self.mute.set_checked(state.muted);
+        self.deafen.set_checked(state.deafened);
+        self.disconnect.set_enabled(state.connected);
+    }
+}
+
+// a filled circle in `color`, the same colors as the user list's status dots
+fn dot(color: [u8; 3]) -> Option<Icon> {
+    const SIZE: u32 = 32;
+    let radius = SIZE as f32 / 2.0 - 2.0;
+    let rgba = (0..SIZE * SIZE)
+        .flat_map(|i| {
+            let x = (i % SIZE) as f32 - SIZE as f32 / 2.0 + 0.5;
+            let y = (i / SIZE) as f32 - SIZE as f32 / 2.0 + 0.5;
+            // a pixel of antialiasing at the edge
+            let alpha = (radius - (x * x + y * y).sqrt() + 0.5).clamp(0.0, 1.0);
+            [color[0], color[1], color[2], (alpha * 255.0) as u8]
+        })
+        .collect();
+    Icon::from_rgba(rgba, SIZE, SIZE).ok()
+}
@@ -0,0 +1,85 @@
+use criterion::{BenchmarkId, Criterion, black_box, criterion_group, criterion_main};
+use voudp::mixer;
+
+const FRAME: usize = 960 * 2; // 20ms of 48kHz stereo
+
+fn talkers(count: usize) -> Vec<Vec<f32>> {
+    (0..count)
+        .map(|t| {
+            (0..FRAME)
+                .map(|i| ((i * (t + 1)) as f32 * 0.001).sin() * 0.5)
+                .collect()
+        })
+        .collect()
+}
+
+// what Channel::mix used to do: one sample at a time, every listener summing
+// every other talker
+fn naive(talkers: &[Vec<f32>]) -> f32 {
+    let mut checksum = 0.0;
+    for listener in 0..talkers.len() {
+        let gain = 1.0 / ((talkers.len() - 1) as f32).sqrt();
+        let mut mix = vec![0.0f32; FRAME];
+        for (t, buf) in talkers.iter().enumerate() {
+            if t == listener {
+                continue;
+            }
+            for (i, sample) in buf.iter().enumerate() {
+                mix[i] += sample * gain;
+            }
+        }
+        mix.iter_mut().for_each(|s| *s = s.clamp(-1.0, 1.0));
+        checksum += mix[0];
+    }
+    checksum
+}
+
+fn vectorized(talkers: &[Vec<f32>]) -> f32 {
+    let mut total = vec![0.0f32; FRAME];
+    for buf in talkers {
+        mixer::accumulate(&mut total, buf, 1.0);
+    }
+
+    let mut checksum = 0.0;
+    let mut mix = vec![0.0f32; FRAME];
+    for own in talkers {
+        let gain = 1.0 / ((talkers.len() - 1) as f32).sqrt();
+        mixer::mix_excluding(&mut mix, &total, Some(own), gain);
+        mixer::hard_clip(&mut mix);
+        checksum += mix[0];
+    }
+    checksum
+}
+
+fn bench_mixing(c: &mut Criterion) {
+    let mut group = c.benchmark_group("channel_mix");
+    for count in [10, 50, 100] {
+        let input = talkers(count);
+        group.bench_with_input(BenchmarkId::new("naive", count), &input, |b, input| {
+            b.iter(|| naive(black_box(input)))
+        });
+        group.bench_with_input(BenchmarkId::new("vectorized", count), &input, |b, input| {
+            b.iter(|| vectorized(black_box(input)))
+        });
+    }
+    group.finish();
+}
+
+fn bench_accumulate(c: &mut Criterion) {
+    let input = talkers(1).remove(0);
+    let mut mix = vec![0.0f32; FRAME];
+
+    c.bench_function("accumulate_scalar", |b| {
+        b.iter(|| {
+            for (i, sample) in black_box(&input).iter().enumerate() {
+                mix[i] += sample * 0.5;
+            }
+        })
+    });
+    c.bench_function("accumulate_lanes", |b| {
+        b.iter(|| mixer::accumulate(&mut mix, black_box(&input), 0.5))
+    });
+}
+
+criterion_group!(benches, bench_mixing, bench_accumulate);
+criterion_main!(benches);
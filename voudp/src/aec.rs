@@ -0,0 +1,115 @@
+// Acoustic echo cancellation for people on speakers. An NLMS filter learns
+// how what we play comes back in through the mic and subtracts its estimate
+// from everything captured, so the channel doesn't hear itself.
+use std::collections::VecDeque;
+
+/// Filter length, covers ~21ms of echo path at 48kHz
+pub const DEFAULT_AEC_TAPS: usize = 1024;
+
+// NLMS step size, higher adapts faster but settles less
+const STEP: f32 = 0.5;
+// keeps the normalization sane while the speaker is near silent
+const REGULARIZATION: f32 = 1e-3;
+// mic louder than this much of the recent far end means someone is talking
+// over it, the filter is frozen so it doesn't learn their voice (Geigel)
+const DOUBLE_TALK_RATIO: f32 = 0.6;
+// one second of played audio the mic hasn't caught up with, past that the
+// two streams have drifted apart and it's better to start over
+const MAX_PENDING: usize = 48000;
+
+pub struct EchoCanceller {
+    enabled: bool,
+    weights: Vec<f32>,
+    // far end samples lined up with the mic, newest first
+    history: VecDeque<f32>,
+    energy: f32,
+    // played but not yet heard by the mic
+    pending: VecDeque<f32>,
+}
+
+impl EchoCanceller {
+    pub fn new(taps: usize) -> Self {
+        Self {
+            enabled: false,
+            weights: vec![0.0; taps],
+            history: VecDeque::from(vec![0.0; taps]),
+            energy: 0.0,
+            pending: VecDeque::new(),
+        }
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// Turning it on starts from scratch, the echo path may have changed
+    pub fn set_enabled(&mut self, enabled: bool) {
+        if enabled && !self.enabled {
+            self.reset();
+        }
+        self.enabled = enabled;
+    }
+
+    pub fn reset(&mut self) {
+        self.weights.fill(0.0);
+        self.history.iter_mut().for_each(|x| *x = 0.0);
+        self.energy = 0.0;
+        self.pending.clear();
+    }
+
+    /// Feeds interleaved audio that was just sent to the speakers
+    pub fn playback(&mut self, data: &[f32], channels: usize) {
+        if !self.enabled {
+            return;
+        }
+
+        self.pending.extend(
+            data.chunks(channels)
+                .map(|frame| frame.iter().sum::<f32>() / frame.len() as f32),
+        );
+        if self.pending.len() > MAX_PENDING {
+            self.reset();
+        }
+    }
+
+    /// Removes the echo from interleaved mic audio in place
+    pub fn capture(&mut self, data: &mut [f32], channels: usize) {
+        if !self.enabled {
+            return;
+        }
+
+        for frame in data.chunks_mut(channels) {
+            let near = frame.iter().sum::<f32>() / frame.len() as f32;
+            let far = self.pending.pop_front().unwrap_or(0.0);
+
+            let oldest = self.history.pop_back().unwrap_or(0.0);
+            self.history.push_front(far);
+            self.energy = (self.energy + far * far - oldest * oldest).max(0.0);
+
+            let mut estimate = 0.0;
+            let mut peak = 0.0f32;
+            for (w, x) in self.weights.iter().zip(&self.history) {
+                estimate += w * x;
+                peak = peak.max(x.abs());
+            }
+
+            let error = near - estimate;
+            if near.abs() < DOUBLE_TALK_RATIO * peak || peak == 0.0 {
+                let step = STEP * error / (self.energy + REGULARIZATION);
+                for (w, x) in self.weights.iter_mut().zip(&self.history) {
+                    *w += step * x;
+                }
+            }
+
+            for sample in frame {
+                *sample -= estimate;
+            }
+        }
+    }
+}
+
+impl Default for EchoCanceller {
+    fn default() -> Self {
+        Self::new(DEFAULT_AEC_TAPS)
+    }
+}
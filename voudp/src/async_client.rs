@@ -0,0 +1,230 @@
+// Client for bots and other async applications. It never touches audio, a
+// single tokio task owns the socket, keeps the connection alive and turns
+// packets into the same `ClientEvent`s the blocking client sends.
+use std::{
+    io,
+    time::{Duration, Instant},
+};
+
+use anyhow::{Context, Result};
+use chrono::Local;
+use tokio::{
+    sync::mpsc::{self, UnboundedReceiver, UnboundedSender},
+    task::JoinHandle,
+    time,
+};
+
+use crate::{
+    client::{self, ClientEvent, DisconnectReason, OwnedEvent},
+    protocol::{self, ClientPacketType, FromPacket},
+    socket::{self, SecureUdpSocket},
+    util::{
+        ChannelInfo, ChannelRoster, CommandListPacket, GlobalListPacket, ListPagePacket,
+        RetryAfterPacket, RosterUpdate, ServerCommand,
+    },
+};
+
+// how long the task naps when the socket has nothing for it
+const IDLE_POLL: Duration = Duration::from_millis(5);
+// list requests double as keepalives, the server drops quiet clients
+const REFRESH_INTERVAL: Duration = Duration::from_secs(1);
+
+pub struct AsyncClient {
+    socket: SecureUdpSocket,
+    events: UnboundedReceiver<OwnedEvent>,
+    task: JoinHandle<()>,
+}
+
+impl AsyncClient {
+    /// Connects to `addr` and joins `channel_id`. Must be called from inside
+    /// a tokio runtime.
+    pub async fn connect(addr: &str, channel_id: u32, phrase: &[u8]) -> Result<Self> {
+        // deliberately slow, so it goes on the blocking pool
+        let phrase = phrase.to_vec();
+        let key = tokio::task::spawn_blocking(move || {
+            socket::derive_key_from_phrase(&phrase, protocol::VOUDP_SALT)
+        })
+        .await?;
+
+        let addr = tokio::net::lookup_host(addr)
+            .await?
+            .find(|addr| addr.is_ipv4())
+            .context("no valid IPv4 address found")?;
+
+        let socket = SecureUdpSocket::create("0.0.0.0:0".into(), key)?;
+        socket.connect(addr)?;
+
+        let handshake_socket = socket.clone();
+        tokio::task::spawn_blocking(move || {
+            client::handshake(&handshake_socket, client::HANDSHAKE_TIMEOUT).map(|_| ())
+        })
+        .await??;
+        socket.send(&join_packet(channel_id))?;
+
+        let (tx, events) = mpsc::unbounded_channel();
+        let task = tokio::spawn(network_task(socket.clone(), tx));
+
+        Ok(Self {
+            socket,
+            events,
+            task,
+        })
+    }
+
+    /// The next event, `None` once the connection is gone and every event
+    /// before that was handed out
+    pub async fn next_event(&mut self) -> Option<OwnedEvent> {
+        self.events.recv().await
+    }
+
+    pub async fn send_chat(&self, message: &str) -> io::Result<()> {
+        let mut packet = vec![ClientPacketType::Chat as u8];
+        packet.extend_from_slice(message.as_bytes());
+        self.socket.send(&packet).map(|_| ())
+    }
+
+    pub async fn set_nick(&self, nick: &str) -> io::Result<()> {
+        let mut packet = vec![ClientPacketType::Mask as u8];
+        packet.extend_from_slice(nick.as_bytes());
+        self.socket.send(&packet).map(|_| ())
+    }
+
+    pub async fn send_command(&self, command: &str) -> io::Result<()> {
+        let mut packet = vec![ClientPacketType::Cmd as u8];
+        packet.extend_from_slice(command.as_bytes());
+        self.socket.send(&packet).map(|_| ())
+    }
+
+    pub async fn whisper(&self, to: &str, message: &str) -> io::Result<()> {
+        self.socket
+            .send(&protocol::create_dm_packet(to, message))
+            .map(|_| ())
+    }
+
+    pub async fn join(&self, channel_id: u32) -> io::Result<()> {
+        self.socket.send(&join_packet(channel_id)).map(|_| ())
+    }
+
+    pub async fn disconnect(self) {
+        let _ = self.socket.send(&[ClientPacketType::Eof as u8]);
+    }
+}
+
+impl Drop for AsyncClient {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+fn join_packet(channel_id: u32) -> Vec<u8> {
+    let mut packet = vec![ClientPacketType::Join as u8];
+    packet.extend_from_slice(&channel_id.to_be_bytes());
+    packet
+}
+
+async fn network_task(socket: SecureUdpSocket, tx: UnboundedSender<OwnedEvent>) {
+    let emit = |event: ClientEvent| tx.send((event, Local::now())).is_ok();
+
+    let mut recv_buf = [0u8; 2048];
+    let mut roster = ChannelRoster::new();
+    let mut list: (Vec<ChannelInfo>, u32) = (vec![], 0);
+    let mut commands: Vec<ServerCommand> = vec![];
+    let mut last_refresh: Option<Instant> = None;
+    let mut retry_join: Option<(Instant, u32)> = None;
+
+    let mut update_list = |parsed: GlobalListPacket| {
+        if list.0 == parsed.channels && list.1 == parsed.current {
+            return true;
+        }
+        list = (parsed.channels, parsed.current);
+        emit(ClientEvent::ListUpdated {
+            channels: list.0.clone(),
+            current: list.1,
+        })
+    };
+
+    loop {
+        socket.tick_reliable();
+
+        if last_refresh.is_none_or(|at| at.elapsed() >= REFRESH_INTERVAL) {
+            let _ = socket.send(&protocol::create_list_page_request(0, 0, 0));
+            let _ = socket.send(&protocol::create_sync_commands_request());
+            last_refresh = Some(Instant::now());
+        }
+
+        if let Some((at, channel_id)) = retry_join
+            && Instant::now() >= at
+        {
+            let _ = socket.send(&join_packet(channel_id));
+            retry_join = None;
+        }
+
+        type Cpt = ClientPacketType;
+        let size = match socket.recv_from(&mut recv_buf) {
+            Ok((size, _)) if size > 1 || client::is_nick_required(&recv_buf[..size]) => size,
+            Ok(_) => continue,
+            Err(e) if e.0.kind() == io::ErrorKind::WouldBlock => {
+                // nobody is listening anymore
+                if tx.is_closed() {
+                    return;
+                }
+                time::sleep(IDLE_POLL).await;
+                continue;
+            }
+            Err(e) if e.0.kind() == io::ErrorKind::InvalidData => continue,
+            Err(e) if e.0.kind() == io::ErrorKind::Unsupported => {
+                emit(ClientEvent::Disconnected(DisconnectReason::IncorrectPhrase));
+                return;
+            }
+            Err(e) => {
+                emit(ClientEvent::Disconnected(DisconnectReason::ConnectionLost(
+                    e.0.to_string(),
+                )));
+                return;
+            }
+        };
+
+        let packet = &recv_buf[..size];
+        let delivered = match Cpt::try_from(packet[0]) {
+            Ok(Cpt::List) => match GlobalListPacket::deserialize(&packet[1..]) {
+                Ok(parsed) => update_list(parsed),
+                Err(_) => emit(ClientEvent::Error("bad list".into())),
+            },
+            Ok(Cpt::ListPage) => match ListPagePacket::deserialize(packet) {
+                Ok(page) => match roster.apply(page) {
+                    RosterUpdate::Next(offset) => {
+                        let _ = socket.send(&protocol::create_list_page_request(offset, 0, 0));
+                        true
+                    }
+                    RosterUpdate::Complete(parsed) => update_list(parsed),
+                    RosterUpdate::Ignored => true,
+                },
+                Err(_) => emit(ClientEvent::Error("bad list page".into())),
+            },
+            Ok(Cpt::SyncCommands) => match CommandListPacket::deserialize(&packet[1..]) {
+                Ok(parsed) if parsed.commands != commands => {
+                    commands = parsed.commands;
+                    emit(ClientEvent::CommandsUpdated(commands.clone()))
+                }
+                _ => true,
+            },
+            Ok(Cpt::RetryAfter) => {
+                if let Ok(retry) = RetryAfterPacket::deserialize(packet) {
+                    retry_join = Some((Instant::now() + retry.backoff(), retry.channel_id));
+                }
+                true
+            }
+            Ok(Cpt::Kick) => {
+                let reason =
+                    String::from_utf8(packet[1..].to_vec()).unwrap_or("Unknown reason".into());
+                emit(ClientEvent::Disconnected(DisconnectReason::Kicked(reason)));
+                return;
+            }
+            _ => client::decode_event(packet).is_none_or(emit),
+        };
+
+        if !delivered {
+            return;
+        }
+    }
+}
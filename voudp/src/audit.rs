@@ -0,0 +1,159 @@
+use std::{collections::HashMap, sync::Arc};
+
+use chrono::{DateTime, Local};
+use log::{error, warn};
+
+use crate::storage::Storage;
+
+/// A moderator note attached to a mask. Notes are append-only so the history
+/// of an incident stays intact across moderators and restarts.
+#[derive(Debug, Clone)]
+pub struct Note {
+    pub author: String,
+    pub text: String,
+    pub created: DateTime<Local>,
+}
+
+const NAMESPACE: &str = "notes";
+
+/// Moderation notes keyed by mask, persisted through the server's storage
+pub struct AuditLog {
+    storage: Arc<dyn Storage>,
+    notes: HashMap<String, Vec<Note>>,
+}
+
+impl AuditLog {
+    pub fn new(storage: Arc<dyn Storage>) -> Self {
+        let mut log = Self {
+            storage,
+            notes: HashMap::new(),
+        };
+
+        let subjects = log.storage.list(NAMESPACE).unwrap_or_else(|e| {
+            error!("Failed to list stored notes: {e}");
+            vec![]
+        });
+
+        for subject in subjects {
+            match log.storage.get(NAMESPACE, &subject) {
+                Ok(Some(data)) => {
+                    let notes = String::from_utf8_lossy(&data)
+                        .lines()
+                        .filter_map(|line| {
+                            let note = parse_line(line).map(|(_, note)| note);
+                            if note.is_none() {
+                                warn!("Skipping malformed note about {subject}: {line}");
+                            }
+                            note
+                        })
+                        .collect();
+                    log.notes.insert(subject, notes);
+                }
+                Ok(None) => {}
+                Err(e) => error!("Failed to read notes about {subject}: {e}"),
+            }
+        }
+
+        log
+    }
+
+    pub fn add_note(&mut self, subject: &str, author: &str, text: &str) {
+        let note = Note {
+            author: author.to_string(),
+            text: text.to_string(),
+            created: Local::now(),
+        };
+
+        self.notes
+            .entry(subject.to_string())
+            .or_default()
+            .push(note);
+        self.persist(subject);
+    }
+
+    pub fn notes(&self, subject: &str) -> &[Note] {
+        self.notes.get(subject).map(Vec::as_slice).unwrap_or(&[])
+    }
+
+    fn persist(&self, subject: &str) {
+        let data = self
+            .notes(subject)
+            .iter()
+            .map(|note| format_line(subject, note))
+            .collect::<Vec<_>>()
+            .join("\n");
+
+        if let Err(e) = self.storage.put(NAMESPACE, subject, data.as_bytes()) {
+            error!("Failed to persist notes about {subject}: {e}");
+        }
+    }
+}
+
+pub fn format_notes(subject: &str, notes: &[Note]) -> String {
+    if notes.is_empty() {
+        return format!("no notes about {subject}");
+    }
+
+    let mut out = format!("{} note(s) about {subject}:", notes.len());
+    for note in notes {
+        out.push_str(&format!(
+            "\n  [{}] {}: {}",
+            note.created.format("%Y-%m-%d %H:%M"),
+            note.author,
+            note.text
+        ));
+    }
+    out
+}
+
+fn format_line(subject: &str, note: &Note) -> String {
+    format!(
+        "{}\t{}\t{}\t{}",
+        note.created.to_rfc3339(),
+        escape(subject),
+        escape(&note.author),
+        escape(&note.text)
+    )
+}
+
+fn parse_line(line: &str) -> Option<(String, Note)> {
+    let mut fields = line.splitn(4, '\t');
+    let created = DateTime::parse_from_rfc3339(fields.next()?).ok()?;
+    let subject = unescape(fields.next()?);
+    let author = unescape(fields.next()?);
+    let text = unescape(fields.next()?);
+
+    Some((
+        subject,
+        Note {
+            author,
+            text,
+            created: created.with_timezone(&Local),
+        },
+    ))
+}
+
+// notes are stored one per line, so tabs and newlines have to be escaped
+fn escape(s: &str) -> String {
+    s.replace('\\', "\\\\")
+        .replace('\t', "\\t")
+        .replace('\n', "\\n")
+}
+
+fn unescape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('t') => out.push('\t'),
+            Some('n') => out.push('\n'),
+            Some(other) => out.push(other),
+            None => out.push('\\'),
+        }
+    }
+    out
+}
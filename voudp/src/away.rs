@@ -0,0 +1,165 @@
+// Client side away mode. While away, whispers get one automatic reply per
+// sender and are kept until the user comes back, when they are handed over
+// as a "while you were away" summary.
+use std::{collections::HashSet, f32::consts::PI, sync::Arc};
+
+use chrono::{DateTime, Local};
+use log::error;
+
+use crate::storage::{Storage, StorageResult};
+
+const NAMESPACE: &str = "settings";
+const MESSAGE_KEY: &str = "away_message";
+const CHIME_KEY: &str = "dm_chime";
+
+pub const DEFAULT_AWAY_MESSAGE: &str = "I'm away right now, I'll get back to you later";
+
+/// Keeps a flood of whispers from growing the summary forever
+pub const MAX_MISSED: usize = 100;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct AwaySettings {
+    /// Sent back to whoever whispers us while we're away
+    pub message: String,
+    /// Play a short chime when a whisper comes in
+    pub chime: bool,
+}
+
+impl Default for AwaySettings {
+    fn default() -> Self {
+        Self {
+            message: DEFAULT_AWAY_MESSAGE.into(),
+            chime: true,
+        }
+    }
+}
+
+impl AwaySettings {
+    /// Missing or unreadable entries fall back to the defaults
+    pub fn load(storage: &dyn Storage) -> Self {
+        let mut settings = Self::default();
+
+        match storage.get(NAMESPACE, MESSAGE_KEY) {
+            Ok(Some(data)) => settings.message = String::from_utf8_lossy(&data).into_owned(),
+            Ok(None) => {}
+            Err(e) => error!("Failed to read the away message: {e}"),
+        }
+        match storage.get(NAMESPACE, CHIME_KEY) {
+            Ok(Some(data)) => settings.chime = data != b"off",
+            Ok(None) => {}
+            Err(e) => error!("Failed to read the chime setting: {e}"),
+        }
+
+        settings
+    }
+
+    pub fn save(&self, storage: &dyn Storage) -> StorageResult<()> {
+        storage.put(NAMESPACE, MESSAGE_KEY, self.message.as_bytes())?;
+        storage.put(
+            NAMESPACE,
+            CHIME_KEY,
+            if self.chime { b"on" } else { b"off" },
+        )
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct MissedDm {
+    pub from: String,
+    pub message: String,
+    pub at: DateTime<Local>,
+}
+
+pub struct Away {
+    storage: Option<Arc<dyn Storage>>,
+    settings: AwaySettings,
+    away: bool,
+    missed: Vec<MissedDm>,
+    // senders that already got the auto reply, so two away users can't
+    // keep answering each other
+    replied: HashSet<String>,
+}
+
+impl Away {
+    /// Settings are read from and written back to `storage` when there is one
+    pub fn new(storage: Option<Arc<dyn Storage>>) -> Self {
+        let settings = storage
+            .as_deref()
+            .map(AwaySettings::load)
+            .unwrap_or_default();
+
+        Self {
+            storage,
+            settings,
+            away: false,
+            missed: vec![],
+            replied: HashSet::new(),
+        }
+    }
+
+    pub fn settings(&self) -> &AwaySettings {
+        &self.settings
+    }
+
+    pub fn update_settings(&mut self, settings: AwaySettings) {
+        if let Some(storage) = &self.storage
+            && let Err(e) = settings.save(storage.as_ref())
+        {
+            error!("Failed to save away settings: {e}");
+        }
+        self.settings = settings;
+    }
+
+    pub fn is_away(&self) -> bool {
+        self.away
+    }
+
+    pub fn set_away(&mut self) {
+        self.away = true;
+    }
+
+    /// Leaves away mode and returns what came in meanwhile, oldest first
+    pub fn back(&mut self) -> Vec<MissedDm> {
+        self.away = false;
+        self.replied.clear();
+        std::mem::take(&mut self.missed)
+    }
+
+    /// Records a whisper, returning the auto reply to send back if any
+    pub fn on_dm(&mut self, from: &str, message: &str, at: DateTime<Local>) -> Option<String> {
+        if !self.away {
+            return None;
+        }
+
+        if self.missed.len() < MAX_MISSED {
+            self.missed.push(MissedDm {
+                from: from.into(),
+                message: message.into(),
+                at,
+            });
+        }
+
+        if self.settings.message.is_empty() || !self.replied.insert(from.into()) {
+            return None;
+        }
+        Some(self.settings.message.clone())
+    }
+}
+
+/// Two rising notes, interleaved stereo at `sample_rate`
+pub fn chime(sample_rate: u32) -> Vec<f32> {
+    let note = (sample_rate as f32 * 0.09) as usize;
+
+    [880.0, 1320.0]
+        .iter()
+        .flat_map(|&freq| {
+            (0..note).flat_map(move |n| {
+                let t = n as f32 / sample_rate as f32;
+                // quick attack, exponential decay
+                let env = (n as f32 / 48.0).min(1.0) * (-t * 30.0).exp();
+                let s = 0.25 * env * (2.0 * PI * freq * t).sin();
+                [s, s]
+            })
+        })
+        .collect()
+}
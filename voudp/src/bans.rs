@@ -0,0 +1,97 @@
+// Addresses kept off the server with /ban. Bans are by IP since a client's port
+// changes with every connection, and persisted through the server's storage so
+// they outlive restarts.
+use std::{collections::HashMap, net::IpAddr, sync::Arc};
+
+use log::{error, warn};
+
+use crate::storage::Storage;
+
+const NAMESPACE: &str = "bans";
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Ban {
+    /// Who was connected from the address when it was banned
+    pub mask: String,
+    pub reason: String,
+}
+
+pub struct BanList {
+    storage: Arc<dyn Storage>,
+    bans: HashMap<IpAddr, Ban>,
+}
+
+impl BanList {
+    pub fn new(storage: Arc<dyn Storage>) -> Self {
+        let mut list = Self {
+            storage,
+            bans: HashMap::new(),
+        };
+
+        let keys = list.storage.list(NAMESPACE).unwrap_or_else(|e| {
+            error!("Failed to list stored bans: {e}");
+            vec![]
+        });
+
+        for key in keys {
+            let Ok(ip) = key.parse::<IpAddr>() else {
+                warn!("Skipping ban of something that isn't an address: {key}");
+                continue;
+            };
+            match list.storage.get(NAMESPACE, &key) {
+                Ok(Some(data)) => {
+                    let data = String::from_utf8_lossy(&data);
+                    let (mask, reason) = data.split_once('\n').unwrap_or((&data, ""));
+                    list.bans.insert(
+                        ip,
+                        Ban {
+                            mask: mask.to_string(),
+                            reason: reason.to_string(),
+                        },
+                    );
+                }
+                Ok(None) => {}
+                Err(e) => error!("Failed to read the ban of {ip}: {e}"),
+            }
+        }
+
+        list
+    }
+
+    pub fn ban(&mut self, ip: IpAddr, mask: &str, reason: &str) {
+        let data = format!("{mask}\n{reason}");
+        if let Err(e) = self
+            .storage
+            .put(NAMESPACE, &ip.to_string(), data.as_bytes())
+        {
+            error!("Failed to persist the ban of {ip}: {e}");
+        }
+        self.bans.insert(
+            ip,
+            Ban {
+                mask: mask.to_string(),
+                reason: reason.to_string(),
+            },
+        );
+    }
+
+    /// Returns whether `ip` was banned
+    pub fn unban(&mut self, ip: IpAddr) -> bool {
+        if let Err(e) = self.storage.delete(NAMESPACE, &ip.to_string()) {
+            error!("Failed to delete the ban of {ip}: {e}");
+        }
+        self.bans.remove(&ip).is_some()
+    }
+
+    pub fn get(&self, ip: IpAddr) -> Option<&Ban> {
+        self.bans.get(&ip)
+    }
+
+    /// The address `mask` was banned from
+    pub fn find(&self, mask: &str) -> Option<IpAddr> {
+        self.bans
+            .iter()
+            .find(|(_, ban)| ban.mask == mask)
+            .map(|(ip, _)| *ip)
+    }
+}
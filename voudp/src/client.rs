@@ -2,23 +2,50 @@ use anyhow::{Context, Result};
 use chrono::{DateTime, Local};
 use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
 use opus2::{Application, Channels, Decoder, Encoder};
-use std::collections::{BTreeMap, VecDeque};
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::fmt;
 use std::io;
+use std::path::{Path, PathBuf};
 use std::sync::atomic::{AtomicBool, AtomicU16, Ordering};
 use std::sync::mpsc::{self, Receiver, Sender};
 use std::sync::{Arc, Mutex};
 use std::thread;
 use std::time::{Duration, Instant};
 
-use crate::protocol::{self, ClientPacketType, FromPacket};
+use crate::aec::EchoCanceller;
+#[cfg(feature = "async")]
+pub use crate::async_client::AsyncClient;
+use crate::away::{self, Away, AwaySettings, MissedDm};
+use crate::effects::EffectKind;
+use crate::loudness::LevelMeter;
+use crate::prefs::ClientPrefs;
+use crate::probe::{self, QualityProfile, QualitySelection};
+use crate::protocol::{self, ClientPacketType, ControlRequest, FromPacket};
+use crate::recorder::{self, Recorder};
+use crate::resample::Resampler;
 use crate::socket::{self, SecureUdpSocket};
+use crate::soundboard::{Clip, ClipMode, Soundboard};
+use crate::storage::Storage;
 use crate::util::{
-    self, BroadcastPacket, ChannelInfo, ChatPacket, CommandListPacket, CommandResponsePacket,
-    CommandResult, FlowPacket, GlobalListPacket, ServerCommand,
+    self, AtomicF32, BroadcastPacket, ChannelInfo, ChannelRoster, ChatPacket, CommandListPacket,
+    CommandResponsePacket, CommandResult, FlowPacket, GlobalListPacket, ListPagePacket,
+    LocalizedMessage, RetryAfterPacket, RosterUpdate, ServerCommand, TalkersPacket,
 };
-
-const TARGET_FRAME_SIZE: usize = 960; // 20ms at 48kHz
-const BUFFER_CAPACITY: usize = TARGET_FRAME_SIZE * 10; // 10 frames
+use crate::vad::{self, Vad};
+
+// opus runs at 48kHz, devices that can't are resampled to and from it
+const SAMPLE_RATE: u32 = 48000;
+const SAMPLES_PER_MS: usize = 48; // 48kHz
+// largest packet opus produces for a single frame
+const MAX_OPUS_PACKET: usize = 1275;
+// audio gaps up to this many ticks count as lost packets, longer ones are
+// more likely someone pausing
+const MAX_LOSS_GAP: u32 = 5;
+pub const DEFAULT_SETTINGS_DIR: &str = "settings";
+/// How long `ClientState::handshake` waits by default
+pub const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(3);
+// handshakes are unreliable, so they're repeated this often until answered
+const HANDSHAKE_RETRY: Duration = Duration::from_millis(500);
 
 pub enum Mode {
     Repl,
@@ -33,45 +60,350 @@ pub enum State {
 
 #[derive(Clone, Default)]
 pub struct AudioDevices {
+    // devices actually in use, filled in once audio starts
     pub input: String,
     pub output: String,
+    // devices picked by the user, the host defaults are used when unset
+    pub preferred_input: Option<String>,
+    pub preferred_output: Option<String>,
 }
 
 pub struct ClientState {
     pub socket: SecureUdpSocket,
     muted: Arc<AtomicBool>,
+    push_to_talk: Arc<PushToTalk>,
     deafened: Arc<AtomicBool>,
     connected: Arc<AtomicBool>,
     channel_id: Arc<Mutex<u32>>,
+    // the mask we asked for last, asked for again when `run` joins
+    nick: Arc<Mutex<Option<String>>>,
+    // set on channel switches so the network thread drops the old channel's audio
+    flush: Arc<AtomicBool>,
     pub list: SafeChannelList,
     pub talking: Arc<AtomicBool>,
     pub ping: Arc<AtomicU16>,
-    pub rx: Option<Receiver<OwnedMessage>>,
+    events: Sender<OwnedEvent>,
+    // handed out once through `events()`
+    event_rx: Mutex<Option<Receiver<OwnedEvent>>>,
     pub state: Arc<Mutex<State>>,
     pub cmd_list: SafeCommandList,
     pub devices: Arc<Mutex<AudioDevices>>,
+    pub away: SafeAway,
+    pub quality: SafeQuality,
+    // frame RMS the mic has to reach before anything is sent
+    pub vad_threshold: Arc<AtomicF32>,
+    pub levels: Arc<AudioLevels>,
+    stats: SafeStats,
+    recorder: SafeRecorder,
+    // clips waiting to go out with the mic
+    soundboard: SafeSoundboard,
+    // notification sounds, played on top of the voice output
+    alerts: Arc<Mutex<VecDeque<f32>>>,
+    // mask -> gain we hear them at, kept so it survives them rejoining
+    pub volumes: SafeVolumes,
+    // off by default, headphone users have no echo to cancel
+    pub echo: SafeEcho,
+    audio: AudioOptions,
+}
+
+// the handles the audio, network and REPL threads share with the client
+#[derive(Clone)]
+struct Shared {
+    socket: SecureUdpSocket,
+    muted: Arc<AtomicBool>,
+    push_to_talk: Arc<PushToTalk>,
+    deafened: Arc<AtomicBool>,
+    connected: Arc<AtomicBool>,
+    state: Arc<Mutex<State>>,
+    list: SafeChannelList,
+    cmd_list: SafeCommandList,
+    tx: Sender<OwnedEvent>,
+    talking: Arc<AtomicBool>,
+    ping: Arc<AtomicU16>,
+    devices: Arc<Mutex<AudioDevices>>,
+    away: SafeAway,
+    quality: SafeQuality,
+    vad_threshold: Arc<AtomicF32>,
+    levels: Arc<AudioLevels>,
+    volumes: SafeVolumes,
+    echo: SafeEcho,
+    channel_id: Arc<Mutex<u32>>,
+    flush: Arc<AtomicBool>,
+    stats: SafeStats,
+    recorder: SafeRecorder,
+    soundboard: SafeSoundboard,
+    alerts: Arc<Mutex<VecDeque<f32>>>,
+    nick: Arc<Mutex<Option<String>>>,
+    audio: AudioOptions,
+}
+
+/// Push-to-talk: while it's on, the mic only goes out with the talk key held
+#[derive(Default)]
+pub struct PushToTalk {
+    enabled: AtomicBool,
+    held: AtomicBool,
+}
+
+impl PushToTalk {
+    // whether the mic may go out as far as push-to-talk is concerned
+    fn is_open(&self) -> bool {
+        !self.enabled.load(Ordering::Relaxed) || self.held.load(Ordering::Relaxed)
+    }
+}
+
+/// Audio pipeline settings, fixed once the client runs. See `ClientBuilder`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AudioOptions {
+    // opus frame length, has to match the server's tick rate
+    pub frame_ms: u32,
+    // lets opus skip frames that are only background noise
+    pub dtx: bool,
+    // mic channels to ask the device for, its first f32 config when unset
+    pub capture_channels: Option<u16>,
+    // frames the capture and playback buffers hold before dropping audio
+    pub buffer_frames: usize,
+}
+
+impl Default for AudioOptions {
+    fn default() -> Self {
+        Self {
+            frame_ms: 20,
+            dtx: false,
+            capture_channels: None,
+            buffer_frames: 10,
+        }
+    }
 }
 
-type OwnedMessage = (Message, DateTime<Local>);
+impl AudioOptions {
+    /// Samples per channel in one frame
+    pub fn frame_size(&self) -> usize {
+        self.frame_ms as usize * SAMPLES_PER_MS
+    }
+
+    fn buffer_capacity(&self) -> usize {
+        self.frame_size() * self.buffer_frames
+    }
+}
 
-pub enum Message {
-    JoinMessage(String),
-    LeaveMessage(String),
-    ChatMessage(String, String, bool),
-    Command(CommandResult),
-    Renick(String, String),
-    Broadcast(String, String),
-    Kick(String),
+/// Builds a `ClientState` with a tuned audio pipeline, anything left unset
+/// keeps the defaults `ClientState::new` uses
+pub struct ClientBuilder {
+    ip: String,
+    phrase: Vec<u8>,
+    channel_id: u32,
+    audio: AudioOptions,
+    bitrate: Option<i32>,
+    fec: Option<bool>,
+}
+
+impl ClientBuilder {
+    pub fn new(ip: &str, phrase: &[u8]) -> Self {
+        Self {
+            ip: ip.to_string(),
+            phrase: phrase.to_vec(),
+            channel_id: protocol::DEFAULT_CHANNEL_ID,
+            audio: AudioOptions::default(),
+            bitrate: None,
+            fec: None,
+        }
+    }
+
+    pub fn channel(mut self, channel_id: u32) -> Self {
+        self.channel_id = channel_id;
+        self
+    }
+
+    /// Fixed opus bitrate in bits per second, skips the startup probe
+    pub fn bitrate(mut self, bitrate: i32) -> Self {
+        self.bitrate = Some(bitrate);
+        self
+    }
+
+    /// In-band FEC on or off, skips the startup probe
+    pub fn fec(mut self, fec: bool) -> Self {
+        self.fec = Some(fec);
+        self
+    }
+
+    /// 10, 20 or 40
+    pub fn frame_ms(mut self, frame_ms: u32) -> Self {
+        self.audio.frame_ms = frame_ms;
+        self
+    }
+
+    pub fn dtx(mut self, dtx: bool) -> Self {
+        self.audio.dtx = dtx;
+        self
+    }
+
+    /// 1 or 2
+    pub fn capture_channels(mut self, channels: u16) -> Self {
+        self.audio.capture_channels = Some(channels);
+        self
+    }
+
+    pub fn buffer_frames(mut self, frames: usize) -> Self {
+        self.audio.buffer_frames = frames;
+        self
+    }
+
+    pub fn build(self) -> Result<ClientState, io::Error> {
+        let invalid = |msg: &str| io::Error::new(io::ErrorKind::InvalidInput, msg.to_string());
+        if ![10, 20, 40].contains(&self.audio.frame_ms) {
+            return Err(invalid("frame size must be 10, 20 or 40 ms"));
+        }
+        if self
+            .audio
+            .capture_channels
+            .is_some_and(|channels| !(1..=2).contains(&channels))
+        {
+            return Err(invalid("capture must be mono or stereo"));
+        }
+        if self.audio.buffer_frames == 0 {
+            return Err(invalid("buffers need room for at least one frame"));
+        }
+        if self
+            .bitrate
+            .is_some_and(|bitrate| !(6000..=510000).contains(&bitrate))
+        {
+            return Err(invalid("bitrate must be between 6 and 510 kbps"));
+        }
+
+        let mut client = ClientState::new(&self.ip, self.channel_id, &self.phrase)?;
+        client.audio = self.audio;
+
+        if self.bitrate.is_some() || self.fec.is_some() {
+            let base = QualityProfile::default();
+            let fec = self.fec.unwrap_or(base.fec);
+            client.set_quality(Some(QualityProfile {
+                name: "custom",
+                bitrate: self.bitrate.unwrap_or(base.bitrate),
+                fec,
+                loss_perc: if fec { base.loss_perc } else { 0 },
+            }));
+        }
+
+        Ok(client)
+    }
+}
+
+/// A running mic self test, see `ClientState::start_loopback`
+pub struct Loopback {
+    _input: cpal::Stream,
+    // none when only the levels are wanted
+    _output: Option<cpal::Stream>,
+}
+
+/// Live mic and speaker levels, for VU meters and checking the mic works
+#[derive(Default)]
+pub struct AudioLevels {
+    // raw capture, before the noise gate
+    pub input: LevelMeter,
+    // the mix as it's played
+    pub output: LevelMeter,
+}
+
+/// How the connection is doing, refreshed by the network thread every second
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ConnectionStats {
+    // round trip of the last list request, none until one comes back
+    pub rtt_ms: Option<u16>,
+    // share of the channel's audio that never arrived over the last second
+    pub loss: f32,
+    // our voice going out
+    pub send_kbps: f32,
+    // everything coming in, mostly the channel's mix
+    pub recv_kbps: f32,
+    // frames opus couldn't decode since connecting
+    pub decode_errors: u64,
+}
+
+impl fmt::Display for ConnectionStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.rtt_ms {
+            Some(rtt) => write!(f, "rtt {rtt} ms")?,
+            None => write!(f, "rtt unknown")?,
+        }
+        write!(
+            f,
+            ", loss {:.1}%, up {:.1} kbps, down {:.1} kbps, {} decode error(s)",
+            self.loss * 100.0,
+            self.send_kbps,
+            self.recv_kbps,
+            self.decode_errors
+        )
+    }
+}
+
+pub type OwnedEvent = (ClientEvent, DateTime<Local>);
+
+/// Everything the network thread has to tell a frontend, in arrival order
+#[derive(Debug, Clone)]
+pub enum ClientEvent {
+    Chat {
+        from: String,
+        message: String,
+        is_self: bool,
+    },
+    UserJoined(String),
+    UserLeft(String),
+    Renamed {
+        old: String,
+        new: String,
+    },
+    Broadcast {
+        from: String,
+        message: String,
+    },
+    Whisper {
+        from: String,
+        message: String,
+    },
+    Command {
+        command: String,
+        result: CommandResult,
+    },
+    Notice(LocalizedMessage),
+    // only sent when the roster actually changed
+    ListUpdated {
+        channels: Vec<ChannelInfo>,
+        current: u32,
+    },
+    CommandsUpdated(Vec<ServerCommand>),
+    // masked users audible in our channel right now
+    TalkersChanged(Vec<String>),
+    // we chatted or sent a DM before picking a mask
+    NickRequired,
+    Disconnected(DisconnectReason),
+    // a packet we couldn't make sense of, the connection carries on
+    Error(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum DisconnectReason {
+    Kicked(String),
+    IncorrectPhrase,
+    ConnectionLost(String),
 }
 
 pub struct GlobalListState {
     pub channels: Vec<ChannelInfo>,
     pub last_updated: Instant,
     pub current_channel: u32,
+    // LIST_FILTER_* bits sent with every list request
+    pub filter: u8,
 }
 
 type SafeChannelList = Arc<Mutex<GlobalListState>>;
 type SafeCommandList = Arc<Mutex<Vec<ServerCommand>>>;
+type SafeAway = Arc<Mutex<Away>>;
+type SafeQuality = Arc<Mutex<QualitySelection>>;
+type SafeVolumes = Arc<Mutex<HashMap<String, f32>>>;
+type SafeEcho = Arc<Mutex<EchoCanceller>>;
+type SafeStats = Arc<Mutex<ConnectionStats>>;
+type SafeRecorder = Arc<Mutex<Option<Recorder>>>;
+type SafeSoundboard = Arc<Mutex<Soundboard>>;
 
 impl ClientState {
     pub fn new(ip: &str, channel_id: u32, phrase: &[u8]) -> Result<Self, io::Error> {
@@ -79,27 +411,129 @@ impl ClientState {
         let socket = SecureUdpSocket::create("0.0.0.0:0".into(), key)?; // let OS decide port
 
         socket.connect(ip)?;
+        let (events, event_rx) = mpsc::channel();
 
         Ok(Self {
             socket,
             muted: Arc::new(AtomicBool::new(false)),
+            push_to_talk: Arc::new(PushToTalk::default()),
             deafened: Arc::new(AtomicBool::new(false)),
             connected: Arc::new(AtomicBool::new(true)),
             channel_id: Arc::new(Mutex::new(channel_id)),
+            nick: Arc::new(Mutex::new(None)),
+            flush: Arc::new(AtomicBool::new(false)),
             list: Arc::new(Mutex::new(GlobalListState {
                 channels: vec![],
                 last_updated: Instant::now(),
                 current_channel: 0,
+                filter: 0,
             })),
             ping: Arc::new(AtomicU16::new(u16::MAX)),
             talking: Arc::new(AtomicBool::new(false)),
-            rx: None,
+            events,
+            event_rx: Mutex::new(Some(event_rx)),
             state: Arc::new(Mutex::new(State::Fine)),
             cmd_list: Arc::new(Mutex::new(vec![])),
             devices: Arc::new(Mutex::new(AudioDevices::default())),
+            away: Arc::new(Mutex::new(Away::new(None))),
+            quality: Arc::new(Mutex::new(QualitySelection::default())),
+            vad_threshold: Arc::new(AtomicF32::new(vad::DEFAULT_VAD_THRESHOLD)),
+            levels: Arc::new(AudioLevels::default()),
+            stats: Arc::new(Mutex::new(ConnectionStats::default())),
+            recorder: Arc::new(Mutex::new(None)),
+            soundboard: Arc::new(Mutex::new(Soundboard::default())),
+            alerts: Arc::new(Mutex::new(VecDeque::new())),
+            volumes: Arc::new(Mutex::new(HashMap::new())),
+            echo: Arc::new(Mutex::new(EchoCanceller::default())),
+            audio: AudioOptions::default(),
         })
     }
 
+    pub fn audio_options(&self) -> AudioOptions {
+        self.audio
+    }
+
+    /// Sets how loud the mic has to be before we transmit, 0 sends everything
+    pub fn set_vad_threshold(&self, threshold: f32) {
+        self.vad_threshold.store(threshold.max(0.0));
+    }
+
+    /// Only sends the mic while `set_talk_key` says the talk key is held
+    pub fn set_push_to_talk(&self, enabled: bool) {
+        self.push_to_talk.enabled.store(enabled, Ordering::Relaxed);
+    }
+
+    pub fn set_talk_key(&self, held: bool) {
+        self.push_to_talk.held.store(held, Ordering::Relaxed);
+    }
+
+    /// Whether the mic goes out right now, it doesn't when muted or while
+    /// push-to-talk waits for its key
+    pub fn is_transmitting(&self) -> bool {
+        !self.muted.load(Ordering::Relaxed) && self.push_to_talk.is_open()
+    }
+
+    /// Pins the encoder to `profile` and skips the startup probe, `None`
+    /// goes back to whatever the probe picked
+    pub fn set_quality(&self, profile: Option<QualityProfile>) {
+        self.quality.lock().unwrap().set_manual(profile);
+    }
+
+    /// Cancels our own speaker output picked up by the mic, for people
+    /// without headphones
+    pub fn set_echo_cancellation(&self, enabled: bool) {
+        self.echo.lock().unwrap().set_enabled(enabled);
+    }
+
+    // runs before joining so nothing else is in flight yet
+    fn probe_quality(socket: &SecureUdpSocket, quality: &SafeQuality) {
+        if quality.lock().unwrap().manual {
+            return;
+        }
+
+        match probe::run_probe(socket) {
+            Ok(steps) => quality.lock().unwrap().set_probed(&steps),
+            Err(e) => eprintln!("bandwidth probe failed: {e}"),
+        }
+    }
+
+    /// Loads the away settings from `storage` and saves any later changes there
+    pub fn use_settings(&self, storage: Arc<dyn Storage>) {
+        *self.away.lock().unwrap() = Away::new(Some(storage));
+    }
+
+    /// Names of the audio input devices on the default host
+    pub fn input_devices() -> Result<Vec<String>> {
+        let devices = cpal::default_host().input_devices()?;
+        Ok(devices.filter_map(|device| device.name().ok()).collect())
+    }
+
+    /// Names of the audio output devices on the default host
+    pub fn output_devices() -> Result<Vec<String>> {
+        let devices = cpal::default_host().output_devices()?;
+        Ok(devices.filter_map(|device| device.name().ok()).collect())
+    }
+
+    /// Records using the input device called `name`. Takes effect when audio
+    /// starts, so call it before `run`.
+    pub fn select_input_device(&self, name: &str) -> Result<()> {
+        if !Self::input_devices()?.iter().any(|device| device == name) {
+            anyhow::bail!("no input device named '{name}'");
+        }
+        self.devices.lock().unwrap().preferred_input = Some(name.to_string());
+        Ok(())
+    }
+
+    /// Plays through the output device called `name`. Takes effect when audio
+    /// starts, so call it before `run`.
+    pub fn select_output_device(&self, name: &str) -> Result<()> {
+        if !Self::output_devices()?.iter().any(|device| device == name) {
+            anyhow::bail!("no output device named '{name}'");
+        }
+        self.devices.lock().unwrap().preferred_output = Some(name.to_string());
+        Ok(())
+    }
+
     pub fn join(&self, id: u32) -> Result<usize, std::io::Error> {
         let join_packet = {
             let mut p = vec![0x01];
@@ -110,28 +544,236 @@ impl ClientState {
         self.socket.send(&join_packet)
     }
 
-    pub fn run(&mut self, mode: Mode) -> Result<()> {
-        let socket = self.socket.clone();
-        let muted = self.muted.clone();
-        let deafened = self.deafened.clone();
-        let connected = self.connected.clone();
-        let list = self.list.clone();
-        let cmd_list = self.cmd_list.clone();
-        let state = self.state.clone();
-        let talking = self.talking.clone();
-        let (tx, rx) = mpsc::channel::<OwnedMessage>();
-        let ping = self.ping.clone();
-        let devices = self.devices.clone();
-
-        self.rx = Some(rx);
-        let id = { self.channel_id.lock().unwrap() };
+    /// Moves to channel `id` without reconnecting. Audio still buffered from
+    /// the old channel is dropped rather than played over the new one
+    pub fn join_channel(&self, id: u32) -> Result<usize, std::io::Error> {
+        join_channel(&self.socket, &self.channel_id, &self.flush, id)
+    }
+
+    /// RTT, loss, bitrates and decode errors as of the last second
+    pub fn stats(&self) -> ConnectionStats {
+        *self.stats.lock().unwrap()
+    }
+
+    /// Starts writing what we hear to a WAV file, `None` picks a dated name in
+    /// `DEFAULT_RECORDINGS_DIR`. `include_mic` mixes in what we send too
+    pub fn start_recording(&self, path: Option<&Path>, include_mic: bool) -> io::Result<PathBuf> {
+        start_recording(&self.recorder, path, include_mic)
+    }
+
+    /// Finishes the recording, `None` if there wasn't one
+    pub fn stop_recording(&self) -> io::Result<Option<PathBuf>> {
+        stop_recording(&self.recorder)
+    }
+
+    pub fn is_recording(&self) -> bool {
+        self.recorder.lock().unwrap().is_some()
+    }
+
+    /// Sends `clip` to the channel along with, or instead of, our mic. Cuts
+    /// off whatever clip was playing
+    pub fn play_clip(&self, clip: Clip, mode: ClipMode) {
+        self.soundboard.lock().unwrap().play(clip, mode);
+    }
+
+    /// `false` if nothing was playing
+    pub fn stop_clip(&self) -> bool {
+        self.soundboard.lock().unwrap().stop()
+    }
+
+    pub fn playing_clip(&self) -> Option<Clip> {
+        self.soundboard.lock().unwrap().playing().cloned()
+    }
+
+    /// Plays `clip` to us alone, over what we hear. Skipped while another
+    /// alert is still playing, so a burst of them doesn't queue up
+    pub fn play_alert(&self, clip: &Clip) {
+        let mut alerts = self.alerts.lock().unwrap();
+        if alerts.is_empty() {
+            alerts.extend(clip.samples());
+        }
+    }
+
+    /// Hears `channel_id` on top of our own channel without joining it, we
+    /// still only talk in our own. Joining it ends the subscription
+    pub fn listen_to(&self, channel_id: u32) -> Result<usize, std::io::Error> {
+        self.socket
+            .send(&protocol::create_subscription_request(channel_id, true))
+    }
+
+    pub fn stop_listening_to(&self, channel_id: u32) -> Result<usize, std::io::Error> {
+        self.socket
+            .send(&protocol::create_subscription_request(channel_id, false))
+    }
+
+    /// Asks the server for `nick` as our mask, it's asked for again on the
+    /// next `run`
+    pub fn set_nick(&self, nick: &str) {
+        let mut packet = vec![ClientPacketType::Mask as u8];
+        packet.extend_from_slice(nick.as_bytes());
+        self.send(&packet);
+        *self.nick.lock().unwrap() = Some(nick.to_string());
+    }
+
+    pub fn nick(&self) -> Option<String> {
+        self.nick.lock().unwrap().clone()
+    }
+
+    /// Takes on saved preferences, call it before `run`. Devices that are
+    /// gone are skipped for the host defaults, the server and channel are
+    /// the caller's to hand to the builder
+    pub fn apply_prefs(&self, prefs: &ClientPrefs) {
+        *self.nick.lock().unwrap() = prefs.nick.clone();
+        if let Some(name) = &prefs.input_device {
+            let _ = self.select_input_device(name);
+        }
+        if let Some(name) = &prefs.output_device {
+            let _ = self.select_output_device(name);
+        }
+        self.muted.store(prefs.muted, Ordering::Relaxed);
+        self.deafened.store(prefs.deafened, Ordering::Relaxed);
+        self.volumes.lock().unwrap().extend(prefs.volumes.clone());
+    }
+
+    /// Writes what we're using now into `prefs`, everything but the server
+    pub fn update_prefs(&self, prefs: &mut ClientPrefs) {
+        prefs.channel_id = Some(self.channel_id());
+        prefs.nick = self.nick();
+        {
+            let devices = self.devices.lock().unwrap();
+            prefs.input_device = devices.preferred_input.clone();
+            prefs.output_device = devices.preferred_output.clone();
+        }
+        prefs.muted = self.muted.load(Ordering::Relaxed);
+        prefs.deafened = self.deafened.load(Ordering::Relaxed);
+        prefs.volumes = self
+            .volumes
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(mask, &gain)| (mask.clone(), gain))
+            .collect();
+    }
+
+    // our mask and mute/deafen state, for the server to hear right after we join
+    fn status_packets(&self) -> Vec<Vec<u8>> {
+        let mut packets = vec![];
+        if let Some(nick) = self.nick() {
+            let mut packet = vec![ClientPacketType::Mask as u8];
+            packet.extend_from_slice(nick.as_bytes());
+            packets.push(packet);
+        }
+        if self.muted.load(Ordering::Relaxed) {
+            packets.push(vec![
+                ClientPacketType::Ctrl as u8,
+                ControlRequest::SetMute as u8,
+            ]);
+        }
+        if self.deafened.load(Ordering::Relaxed) {
+            packets.push(vec![
+                ClientPacketType::Ctrl as u8,
+                ControlRequest::SetDeafen as u8,
+            ]);
+        }
+        packets
+    }
+
+    /// The channel we joined last, the server may have moved us since
+    pub fn channel_id(&self) -> u32 {
+        *self.channel_id.lock().unwrap()
+    }
+
+    /// Waits for the server to answer, UDP "connects" to anything so `new`
+    /// can't tell. Fails if nothing answers within `timeout`, which is also
+    /// what a wrong phrase looks like, or if the server runs a different
+    /// frame size than `AudioOptions::frame_ms`
+    pub fn handshake(&self, timeout: Duration) -> io::Result<()> {
+        match handshake(&self.socket, timeout)? {
+            Some(frame_size) if frame_size != self.audio.frame_size() => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "the server uses {} ms frames, this client was built for {} ms",
+                    frame_size / SAMPLES_PER_MS,
+                    self.audio.frame_ms
+                ),
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    /// Takes the event stream, only the first caller gets it
+    pub fn events(&self) -> Option<Receiver<OwnedEvent>> {
+        self.event_rx.lock().unwrap().take()
+    }
+
+    // fresh handles for the threads `run` starts
+    fn shared(&self) -> Shared {
+        Shared {
+            socket: self.socket.clone(),
+            muted: self.muted.clone(),
+            push_to_talk: self.push_to_talk.clone(),
+            deafened: self.deafened.clone(),
+            connected: self.connected.clone(),
+            state: self.state.clone(),
+            list: self.list.clone(),
+            cmd_list: self.cmd_list.clone(),
+            tx: self.events.clone(),
+            talking: self.talking.clone(),
+            ping: self.ping.clone(),
+            devices: self.devices.clone(),
+            away: self.away.clone(),
+            quality: self.quality.clone(),
+            vad_threshold: self.vad_threshold.clone(),
+            levels: self.levels.clone(),
+            volumes: self.volumes.clone(),
+            echo: self.echo.clone(),
+            channel_id: self.channel_id.clone(),
+            flush: self.flush.clone(),
+            stats: self.stats.clone(),
+            recorder: self.recorder.clone(),
+            soundboard: self.soundboard.clone(),
+            alerts: self.alerts.clone(),
+            nick: self.nick.clone(),
+            audio: self.audio,
+        }
+    }
+
+    pub fn run(&self, mode: Mode) -> Result<()> {
+        let shared = self.shared();
+        // picked before connecting, the server only learns about it once we're in
+        let status = self.status_packets();
+
+        let id = *self.channel_id.lock().unwrap();
         match mode {
             Mode::Repl => {
-                self.join(*id)?;
-                Self::start_audio(
-                    socket, muted, deafened, connected, state, list, cmd_list, tx, mode, talking,
-                    ping, devices,
-                )?;
+                // the rest of the events have no place in the REPL yet
+                if let Some(events) = self.events() {
+                    thread::spawn(move || {
+                        for (event, _) in events {
+                            match event {
+                                ClientEvent::NickRequired => {
+                                    println!("\n:: set a nickname first, 'n <nick>'");
+                                }
+                                ClientEvent::Command {
+                                    command,
+                                    result: CommandResult::Success(message),
+                                } => println!("\n:: {command}: {message}"),
+                                ClientEvent::Command {
+                                    command,
+                                    result: CommandResult::Error(message),
+                                } => println!("\n:: {command} failed: {message}"),
+                                _ => {}
+                            }
+                        }
+                    });
+                }
+                Self::probe_quality(&shared.socket, &shared.quality);
+                println!("quality: {}", shared.quality.lock().unwrap());
+                self.join(id)?;
+                for packet in &status {
+                    self.socket.send(packet)?;
+                }
+                Self::start_audio(shared, mode)?;
             }
             Mode::Gui => {
                 let join_packet = {
@@ -140,14 +782,16 @@ impl ClientState {
                     p
                 };
                 thread::spawn(move || {
+                    let socket = &shared.socket;
+                    Self::probe_quality(socket, &shared.quality);
                     if let Err(e) = socket.send(&join_packet) {
                         eprintln!("send error: {e:?}");
                         return;
                     }
-                    if let Err(e) = Self::start_audio(
-                        socket, muted, deafened, connected, state, list, cmd_list, tx, mode,
-                        talking, ping, devices,
-                    ) {
+                    for packet in &status {
+                        let _ = socket.send(packet);
+                    }
+                    if let Err(e) = Self::start_audio(shared, mode) {
                         eprintln!("audio thread error: {e:?}");
                     }
                 });
@@ -158,81 +802,38 @@ impl ClientState {
         Ok(())
     }
 
-    fn start_audio(
-        socket: SecureUdpSocket,
-        muted: Arc<AtomicBool>,
-        deafened: Arc<AtomicBool>,
-        connected: Arc<AtomicBool>,
-        state: Arc<Mutex<State>>,
-        list: SafeChannelList,
-        cmd_list: SafeCommandList,
-        tx: Sender<OwnedMessage>,
-        mode: Mode,
-        talking: Arc<AtomicBool>,
-        ping: Arc<AtomicU16>,
-        devices: Arc<Mutex<AudioDevices>>,
-    ) -> Result<()> {
-        let muted_clone = muted.clone();
-        let deafened_clone = deafened.clone();
-
-        let input_buffer = Arc::new(Mutex::new(VecDeque::<f32>::with_capacity(
-            BUFFER_CAPACITY * 2,
-        )));
-        let output_buffer = Arc::new(Mutex::new(VecDeque::<f32>::with_capacity(
-            BUFFER_CAPACITY * 2,
-        )));
+    fn start_audio(shared: Shared, mode: Mode) -> Result<()> {
+        let Shared {
+            muted,
+            deafened,
+            connected,
+            talking,
+            devices,
+            vad_threshold,
+            levels,
+            echo,
+            recorder,
+            alerts,
+            audio,
+            ..
+        } = shared.clone();
+        let capacity = audio.buffer_capacity();
+
+        let input_buffer = Arc::new(Mutex::new(VecDeque::<f32>::with_capacity(capacity * 2)));
+        let output_buffer = Arc::new(Mutex::new(VecDeque::<f32>::with_capacity(capacity * 2)));
 
         // spawn network thread
         {
-            let socket = socket.clone();
+            let shared = shared.clone();
             let input_clone = Arc::clone(&input_buffer);
             let output_clone = Arc::clone(&output_buffer);
-            let connected_clone = Arc::clone(&connected);
-            let muted_clone = Arc::clone(&muted);
-            let state_clone = Arc::clone(&state);
-            let list = list.clone();
-            let cmd_list = cmd_list.clone();
-            let ping = ping.clone();
-            thread::spawn(move || {
-                Self::network_thread(
-                    socket,
-                    input_clone,
-                    output_clone,
-                    list,
-                    tx,
-                    connected_clone,
-                    state_clone,
-                    cmd_list,
-                    muted_clone,
-                    ping,
-                )
-            });
+            thread::spawn(move || Self::network_thread(shared, input_clone, output_clone));
         }
 
-        let host = cpal::default_host();
-
-        let input_device = host.default_input_device().context("no input device")?;
-        let output_device = host.default_output_device().context("no output device")?;
-
-        {
-            let mut dev = devices.lock().unwrap();
-            dev.input = input_device.name().unwrap_or("Unknown".into());
-            dev.output = output_device.name().unwrap_or("Unknown".into());
-        }
-
-        let supported = input_device.supported_input_configs()?;
-
-        let config_range = supported
-            .filter(|c| c.min_sample_rate().0 <= 48000 && c.max_sample_rate().0 >= 48000)
-            .find(|c| c.sample_format() == cpal::SampleFormat::F32)
-            .ok_or_else(|| anyhow::anyhow!("No supported config with 48kHz and f32 format"))?;
-
-        let channels = config_range.channels();
-        let config = cpal::StreamConfig {
-            channels,
-            sample_rate: cpal::SampleRate(48000),
-            buffer_size: cpal::BufferSize::Default,
-        };
+        let (input_device, output_device) = Self::open_devices(&devices)?;
+        let config = Self::input_config(&input_device, audio.capture_channels)?;
+        let channels = config.channels;
+        let mut resampler = Self::capture_resampler(&config);
 
         let gate_envelope = Arc::new(Mutex::new(0.0f32));
         let gate_gain = Arc::new(Mutex::new(0.0f32));
@@ -241,15 +842,43 @@ impl ClientState {
         let gain_clone = Arc::clone(&gate_gain);
 
         let input_clone = Arc::clone(&input_buffer);
+        let threshold_clone = Arc::clone(&vad_threshold);
+        let levels_clone = Arc::clone(&levels);
+        let echo_clone = Arc::clone(&echo);
         let input_stream = input_device
             .build_input_stream(
                 &config,
                 move |data: &[f32], _| {
+                    levels_clone.input.process(data);
+
+                    let mut resampled = vec![];
+                    let data = match &mut resampler {
+                        Some(resampler) => {
+                            resampler.process(data, &mut resampled);
+                            &resampled[..]
+                        }
+                        None => data,
+                    };
+
+                    // take out what the speakers put back into the mic first,
+                    // otherwise the gate opens on it
+                    let mut cleaned = vec![];
+                    let data = {
+                        let mut echo = echo_clone.lock().unwrap();
+                        if echo.enabled() {
+                            cleaned.extend_from_slice(data);
+                            echo.capture(&mut cleaned, channels as usize);
+                            &cleaned[..]
+                        } else {
+                            data
+                        }
+                    };
+
                     let mut buffer = input_clone.lock().unwrap();
                     let mut env = env_clone.lock().unwrap();
                     let mut gain = gain_clone.lock().unwrap();
 
-                    const THRESHOLD: f32 = 0.03; // sensitivity
+                    let threshold = threshold_clone.load(); // sensitivity
                     const ATTACK: f32 = 0.2; // how fast it opens
                     const RELEASE: f32 = 0.02; // how fast it closes
                     const GAIN_ATTACK: f32 = 0.1;
@@ -266,13 +895,13 @@ impl ClientState {
                         *env = RELEASE * rms + (1.0 - RELEASE) * *env;
                     }
 
-                    let target_gain = if *env > THRESHOLD { 1.0 } else { 0.0 };
+                    let target_gain = if *env > threshold { 1.0 } else { 0.0 };
 
                     *gain = *gain + (target_gain - *gain) * GAIN_ATTACK;
 
                     if channels == 1 {
                         for sample in data {
-                            if buffer.len() >= BUFFER_CAPACITY * 2 {
+                            if buffer.len() >= capacity * 2 {
                                 buffer.pop_front();
                                 buffer.pop_front();
                             }
@@ -290,7 +919,7 @@ impl ClientState {
                         }
                     } else if channels == 2 {
                         for sample in data {
-                            if buffer.len() >= BUFFER_CAPACITY {
+                            if buffer.len() >= capacity {
                                 buffer.pop_front();
                             }
 
@@ -306,7 +935,7 @@ impl ClientState {
                         }
                     }
 
-                    if *env > THRESHOLD {
+                    if *env > threshold {
                         talking.store(true, Ordering::Relaxed);
                     } else {
                         talking.store(false, Ordering::Relaxed);
@@ -317,24 +946,46 @@ impl ClientState {
             )
             .context("building input stream failed")?;
 
-        let output_config = cpal::StreamConfig {
-            channels: 2,
-            sample_rate: cpal::SampleRate(48000),
-            buffer_size: cpal::BufferSize::Default,
-        };
+        let output_config = Self::output_config(&output_device);
+        let mut resampler = Self::playback_resampler(&output_config);
 
         let output_clone = Arc::clone(&output_buffer);
+        let levels_clone = Arc::clone(&levels);
+        let echo_clone = Arc::clone(&echo);
+        let recorder_clone = Arc::clone(&recorder);
         let output_stream = output_device
             .build_output_stream(
                 &output_config,
                 move |data: &mut [f32], _| {
                     let mut buffer = output_clone.lock().unwrap();
-                    for sample in data {
-                        *sample = if !deafened.load(Ordering::Relaxed) {
-                            buffer.pop_front().unwrap_or(0.0)
+                    let mut alerts = alerts.lock().unwrap();
+                    let deafened = deafened.load(Ordering::Relaxed);
+
+                    // what we play at 48kHz, the echo canceller has to see
+                    // the same rate as the mic
+                    let mut played = Vec::with_capacity(data.len());
+                    let mut next = || {
+                        let sample = if !deafened {
+                            let voice = buffer.pop_front().unwrap_or(0.0);
+                            (voice + alerts.pop_front().unwrap_or(0.0)).clamp(-1.0, 1.0)
                         } else {
                             0.0
                         };
+                        played.push(sample);
+                        sample
+                    };
+
+                    match &mut resampler {
+                        Some(resampler) => resampler.fill(data, next),
+                        None => data.iter_mut().for_each(|sample| *sample = next()),
+                    }
+                    levels_clone.output.process(data);
+                    echo_clone.lock().unwrap().playback(&played, 2);
+
+                    let mut recorder = recorder_clone.lock().unwrap();
+                    if let Some(Err(e)) = recorder.as_mut().map(|r| r.write_mix(&played)) {
+                        eprintln!("recording stopped: {e}");
+                        *recorder = None;
                     }
                 },
                 |err| eprintln!("output stream error: {err:?}"),
@@ -352,35 +1003,251 @@ impl ClientState {
                 }
                 Ok(())
             }
-            Mode::Repl => {
-                let list = list.clone();
-                Self::repl(socket, muted_clone, deafened_clone, list)
-            }
+            Mode::Repl => Self::repl(shared),
+        }
+    }
+
+    // the picked devices, or the host defaults, recording which ones are in use
+    fn open_devices(devices: &Mutex<AudioDevices>) -> Result<(cpal::Device, cpal::Device)> {
+        let host = cpal::default_host();
+
+        let (preferred_input, preferred_output) = {
+            let dev = devices.lock().unwrap();
+            (dev.preferred_input.clone(), dev.preferred_output.clone())
+        };
+
+        let input_device = match preferred_input {
+            Some(name) => host
+                .input_devices()?
+                .find(|device| device.name().is_ok_and(|n| n == name))
+                .with_context(|| format!("input device '{name}' is gone"))?,
+            None => host.default_input_device().context("no input device")?,
+        };
+        let output_device = match preferred_output {
+            Some(name) => host
+                .output_devices()?
+                .find(|device| device.name().is_ok_and(|n| n == name))
+                .with_context(|| format!("output device '{name}' is gone"))?,
+            None => host.default_output_device().context("no output device")?,
+        };
+
+        {
+            let mut dev = devices.lock().unwrap();
+            dev.input = input_device.name().unwrap_or("Unknown".into());
+            dev.output = output_device.name().unwrap_or("Unknown".into());
+        }
+
+        Ok((input_device, output_device))
+    }
+
+    // 48kHz when the config has it, otherwise the closest rate it does
+    fn device_rate(config: &cpal::SupportedStreamConfigRange) -> u32 {
+        SAMPLE_RATE.clamp(config.min_sample_rate().0, config.max_sample_rate().0)
+    }
+
+    fn input_config(device: &cpal::Device, channels: Option<u16>) -> Result<cpal::StreamConfig> {
+        let supported = device.supported_input_configs()?;
+
+        let config_range = supported
+            .filter(|c| channels.is_none_or(|channels| c.channels() == channels))
+            .filter(|c| c.sample_format() == cpal::SampleFormat::F32)
+            .min_by_key(|c| Self::device_rate(c).abs_diff(SAMPLE_RATE))
+            .ok_or_else(|| anyhow::anyhow!("No supported config with f32 format"))?;
+
+        Ok(cpal::StreamConfig {
+            channels: config_range.channels(),
+            sample_rate: cpal::SampleRate(Self::device_rate(&config_range)),
+            buffer_size: cpal::BufferSize::Default,
+        })
+    }
+
+    fn output_config(device: &cpal::Device) -> cpal::StreamConfig {
+        // if the device won't say, ask for 48kHz and let the stream fail
+        let rate = device
+            .supported_output_configs()
+            .ok()
+            .and_then(|supported| {
+                supported
+                    .filter(|c| c.channels() == 2 && c.sample_format() == cpal::SampleFormat::F32)
+                    .map(|c| Self::device_rate(&c))
+                    .min_by_key(|rate| rate.abs_diff(SAMPLE_RATE))
+            })
+            .unwrap_or(SAMPLE_RATE);
+
+        cpal::StreamConfig {
+            channels: 2,
+            sample_rate: cpal::SampleRate(rate),
+            buffer_size: cpal::BufferSize::Default,
+        }
+    }
+
+    // mic audio to 48kHz, none needed when the device already runs at it
+    fn capture_resampler(config: &cpal::StreamConfig) -> Option<Resampler> {
+        (config.sample_rate.0 != SAMPLE_RATE)
+            .then(|| Resampler::new(config.sample_rate.0, SAMPLE_RATE, config.channels as usize))
+    }
+
+    // 48kHz audio to the speakers' rate
+    fn playback_resampler(config: &cpal::StreamConfig) -> Option<Resampler> {
+        (config.sample_rate.0 != SAMPLE_RATE)
+            .then(|| Resampler::new(SAMPLE_RATE, config.sample_rate.0, config.channels as usize))
+    }
+
+    /// Plays the mic straight back through the speakers without touching
+    /// the network, to check the device setup before joining. The echo runs
+    /// until the returned handle is dropped.
+    pub fn start_loopback(&self) -> Result<Loopback> {
+        Self::check_mic(&self.devices, self.audio, &self.levels, true)
+    }
+
+    /// Opens the mic without a client so `levels` can show whether it picks
+    /// anything up, also playing it back through the speakers with
+    /// `play_back`. Runs until the returned handle is dropped
+    pub fn check_mic(
+        devices: &Mutex<AudioDevices>,
+        audio: AudioOptions,
+        levels: &Arc<AudioLevels>,
+        play_back: bool,
+    ) -> Result<Loopback> {
+        let (input_device, output_device) = Self::open_devices(devices)?;
+        let config = Self::input_config(&input_device, audio.capture_channels)?;
+        let channels = config.channels;
+        let capacity = audio.buffer_capacity();
+        let mut resampler = Self::capture_resampler(&config);
+
+        let echo = Arc::new(Mutex::new(VecDeque::<f32>::with_capacity(capacity * 2)));
+
+        let echo_clone = Arc::clone(&echo);
+        let levels_clone = Arc::clone(levels);
+        let input = input_device
+            .build_input_stream(
+                &config,
+                move |data: &[f32], _| {
+                    levels_clone.input.process(data);
+                    if !play_back {
+                        return;
+                    }
+
+                    let mut resampled = vec![];
+                    let data = match &mut resampler {
+                        Some(resampler) => {
+                            resampler.process(data, &mut resampled);
+                            &resampled[..]
+                        }
+                        None => data,
+                    };
+
+                    let mut echo = echo_clone.lock().unwrap();
+                    for frame in data.chunks(channels as usize) {
+                        // mono mics go to both ears, anything past stereo is dropped
+                        let left = frame[0];
+                        let right = frame.get(1).copied().unwrap_or(left);
+                        echo.push_back(left);
+                        echo.push_back(right);
+                    }
+
+                    // never let the echo lag further and further behind
+                    while echo.len() > capacity * 2 {
+                        echo.pop_front();
+                    }
+                },
+                |err| eprintln!("input stream error: {err:?}"),
+                None,
+            )
+            .context("building input stream failed")?;
+
+        input.play()?;
+        if !play_back {
+            return Ok(Loopback {
+                _input: input,
+                _output: None,
+            });
+        }
+
+        let output_config = Self::output_config(&output_device);
+        let mut resampler = Self::playback_resampler(&output_config);
+
+        let levels = Arc::clone(levels);
+        let output = output_device
+            .build_output_stream(
+                &output_config,
+                move |data: &mut [f32], _| {
+                    let mut echo = echo.lock().unwrap();
+                    let mut next = || echo.pop_front().unwrap_or(0.0);
+                    match &mut resampler {
+                        Some(resampler) => resampler.fill(data, next),
+                        None => data.iter_mut().for_each(|sample| *sample = next()),
+                    }
+                    levels.output.process(data);
+                },
+                |err| eprintln!("output stream error: {err:?}"),
+                None,
+            )
+            .context("building output stream failed")?;
+        output.play()?;
+
+        Ok(Loopback {
+            _input: input,
+            _output: Some(output),
+        })
+    }
+
+    /// Runs the loopback for `duration` while printing the levels
+    pub fn mic_test(&self, duration: Duration) -> Result<()> {
+        let _loopback = self.start_loopback()?;
+        {
+            let devices = self.devices.lock().unwrap();
+            println!(
+                "echoing {} through {}, say something",
+                devices.input, devices.output
+            );
         }
+        print_levels(&self.levels, duration);
+        Ok(())
     }
 
     fn network_thread(
-        socket: SecureUdpSocket,
+        shared: Shared,
         input: Arc<Mutex<VecDeque<f32>>>,
         output: Arc<Mutex<VecDeque<f32>>>,
-        list: SafeChannelList,
-        tx: Sender<OwnedMessage>,
-        connected: Arc<AtomicBool>,
-        state: Arc<Mutex<State>>,
-        cmd_list: SafeCommandList,
-        muted: Arc<AtomicBool>,
-        ping: Arc<AtomicU16>,
     ) {
+        let Shared {
+            socket,
+            list,
+            tx,
+            connected,
+            state,
+            cmd_list,
+            muted,
+            push_to_talk,
+            ping,
+            alerts,
+            away,
+            quality,
+            vad_threshold,
+            volumes,
+            flush,
+            stats,
+            recorder,
+            soundboard,
+            audio,
+            ..
+        } = shared;
         let mut encoder = Encoder::new(48000, Channels::Stereo, Application::Audio).unwrap();
         let mut decoder = Decoder::new(48000, Channels::Stereo).unwrap();
 
-        encoder.set_inband_fec(true).unwrap();
-        encoder.set_bitrate(opus2::Bitrate::Bits(96000)).unwrap();
+        let mut applied = quality.lock().unwrap().profile;
+        applied.apply(&mut encoder).unwrap();
         encoder.set_vbr(true).unwrap();
-        encoder.set_packet_loss_perc(10).unwrap();
+        encoder.set_dtx(audio.dtx).unwrap();
+
+        let frame_size = audio.frame_size();
+        let capacity = audio.buffer_capacity();
+
+        let mut vad = Vad::new(vad_threshold.load());
 
         let mut recv_buf = [0u8; 2048];
-        let mut frame_buf = vec![0.0f32; TARGET_FRAME_SIZE * 2];
+        let mut frame_buf = vec![0.0f32; frame_size * 2];
 
         let mut test = Instant::now();
         let mut ping_reply = Instant::now();
@@ -389,14 +1256,75 @@ impl ClientState {
         let mut expected_tick: Option<u32> = None;
         const MAX_JITTER_FRAMES: usize = 50;
 
+        // counted over the current second, then published to `stats`
+        let mut sent_bytes = 0usize;
+        let mut recv_bytes = 0usize;
+        let mut frames_expected = 0u32;
+        let mut frames_received = 0u32;
+        let mut last_tick: Option<u32> = None;
+        let mut decode_errors = 0u64;
+
+        // big channels come in pages, assembled here until the last one arrives
+        let mut roster = ChannelRoster::new();
+
+        // set when the server is pacing joins and wants us to come back later
+        let mut retry_join: Option<(Instant, u32)> = None;
+
         loop {
             if !connected.load(Ordering::Relaxed) {
                 break;
             }
 
+            socket.tick_reliable();
+
+            // switched channels, nothing from the old one should still play
+            if flush.swap(false, Ordering::Relaxed) {
+                jitter_buffer.clear();
+                expected_tick = None;
+                last_tick = None;
+                let _ = decoder.reset_state();
+                output.lock().unwrap().clear();
+            }
+
+            // picked up whenever the profile is overridden mid call
+            let profile = quality.lock().unwrap().profile;
+            if profile != applied && profile.apply(&mut encoder).is_ok() {
+                applied = profile;
+            }
+
+            if let Some((at, channel_id)) = retry_join
+                && Instant::now() >= at
+            {
+                let mut join_packet = vec![0x01];
+                join_packet.extend_from_slice(&channel_id.to_be_bytes());
+                let _ = socket.send(&join_packet);
+                retry_join = None;
+            }
+
             // send periodic requests
             if test.elapsed() > Duration::from_secs(1) {
-                socket.send(&protocol::create_list_request()).unwrap();
+                let secs = test.elapsed().as_secs_f32();
+                let kbps = |bytes: usize| bytes as f32 * 8.0 / 1000.0 / secs;
+                *stats.lock().unwrap() = ConnectionStats {
+                    rtt_ms: Some(ping.load(Ordering::Relaxed)).filter(|&rtt| rtt != u16::MAX),
+                    loss: if frames_expected > 0 {
+                        1.0 - frames_received as f32 / frames_expected as f32
+                    } else {
+                        0.0
+                    },
+                    send_kbps: kbps(sent_bytes),
+                    recv_kbps: kbps(recv_bytes),
+                    decode_errors,
+                };
+                sent_bytes = 0;
+                recv_bytes = 0;
+                frames_expected = 0;
+                frames_received = 0;
+
+                let filter = list.lock().unwrap().filter;
+                socket
+                    .send(&protocol::create_list_page_request(0, 0, filter))
+                    .unwrap();
                 socket
                     .send(&protocol::create_sync_commands_request())
                     .unwrap();
@@ -407,9 +1335,10 @@ impl ClientState {
             // send audio
             {
                 let mut buffer = input.lock().unwrap();
-                let muted = muted.load(Ordering::Relaxed);
-                while buffer.len() >= TARGET_FRAME_SIZE * 2 {
-                    for i in 0..TARGET_FRAME_SIZE {
+                // push-to-talk without its key held is as good as muted
+                let muted = muted.load(Ordering::Relaxed) || !push_to_talk.is_open();
+                while buffer.len() >= frame_size * 2 {
+                    for i in 0..frame_size {
                         frame_buf[i * 2] = buffer.pop_front().unwrap_or(0.0);
                         frame_buf[i * 2 + 1] = buffer.pop_front().unwrap_or(0.0);
                     }
@@ -420,17 +1349,46 @@ impl ClientState {
                         }
                     }
 
-                    let mut opus_data = vec![0u8; 400];
-                    if !muted && let Ok(len) = encoder.encode_float(&frame_buf, &mut opus_data) {
+                    // silence isn't worth encoding, let alone sending. a clip
+                    // still goes out over a quiet or muted mic
+                    vad.set_threshold(vad_threshold.load());
+                    let speaking = !muted && vad.process(&frame_buf);
+                    let mut soundboard = soundboard.lock().unwrap();
+                    if !speaking {
+                        if soundboard.playing().is_none() {
+                            continue;
+                        }
+                        frame_buf.fill(0.0);
+                    }
+                    soundboard.mix_into(&mut frame_buf);
+                    drop(soundboard);
+
+                    if let Some(recorder) = recorder.lock().unwrap().as_mut() {
+                        recorder.write_mic(&frame_buf);
+                    }
+
+                    let mut opus_data = vec![0u8; MAX_OPUS_PACKET];
+                    if let Ok(len) = encoder.encode_float(&frame_buf, &mut opus_data) {
+                        // dtx says there's nothing worth sending
+                        if audio.dtx && len <= 2 {
+                            continue;
+                        }
+
                         let packet = protocol::create_audio_packet(&opus_data[..len]);
-                        let _ = socket.send(&packet);
+                        if let Ok(sent) = socket.send(&packet) {
+                            sent_bytes += sent;
+                        }
                     }
                 }
             }
 
             // receive
             type Cpt = ClientPacketType;
-            match socket.recv_from(&mut recv_buf) {
+            let received = socket.recv_from(&mut recv_buf);
+            if let Ok((size, _)) = &received {
+                recv_bytes += size;
+            }
+            match received {
                 Ok((size, _)) if size > 1 => match Cpt::try_from(recv_buf[0]) {
                     Ok(Cpt::Audio) => {
                         if size < 5 {
@@ -444,6 +1402,18 @@ impl ClientState {
                             recv_buf[4],
                         ]);
 
+                        match last_tick {
+                            Some(last) if tick > last => {
+                                let gap = tick - last;
+                                frames_expected += if gap <= MAX_LOSS_GAP { gap } else { 1 };
+                                frames_received += 1;
+                                last_tick = Some(tick);
+                            }
+                            // late, it was already counted as lost
+                            Some(_) => {}
+                            None => last_tick = Some(tick),
+                        }
+
                         let opus = recv_buf[5..size].to_vec();
 
                         jitter_buffer.insert(tick, opus);
@@ -460,70 +1430,93 @@ impl ClientState {
                     Ok(Cpt::List) => {
                         let packet = &recv_buf[..size];
                         let Ok(parsed) = GlobalListPacket::deserialize(&packet[1..]) else {
-                            eprintln!("error: Received bad list");
+                            let _ = tx.send((ClientEvent::Error("bad list".into()), Local::now()));
                             continue;
                         };
 
-                        {
-                            let mut list = list.lock().unwrap();
-                            list.channels = parsed.channels;
-                            list.current_channel = parsed.current;
-                            list.last_updated = Instant::now();
+                        ping.store(
+                            Instant::now().duration_since(ping_reply).as_millis() as u16,
+                            Ordering::Relaxed,
+                        );
+                        update_list(&socket, &list, &volumes, &tx, parsed);
+                    }
+                    Ok(Cpt::ListPage) => {
+                        let Ok(page) = ListPagePacket::deserialize(&recv_buf[..size]) else {
+                            let _ =
+                                tx.send((ClientEvent::Error("bad list page".into()), Local::now()));
+                            continue;
+                        };
 
-                            ping.store(
-                                Instant::now().duration_since(ping_reply).as_millis() as u16,
-                                Ordering::Relaxed,
-                            );
+                        match roster.apply(page) {
+                            RosterUpdate::Next(offset) => {
+                                let filter = list.lock().unwrap().filter;
+                                let _ = socket
+                                    .send(&protocol::create_list_page_request(offset, 0, filter));
+                            }
+                            RosterUpdate::Complete(parsed) => {
+                                ping.store(
+                                    Instant::now().duration_since(ping_reply).as_millis() as u16,
+                                    Ordering::Relaxed,
+                                );
+                                update_list(&socket, &list, &volumes, &tx, parsed);
+                            }
+                            RosterUpdate::Ignored => {}
                         }
                     }
-                    Ok(Cpt::Chat) => match ChatPacket::deserialize(&recv_buf[..size]) {
-                        Ok(chat) => {
-                            let _ = tx.send((
-                                Message::ChatMessage(chat.username, chat.message, chat.is_self),
-                                Local::now(),
-                            ));
+                    Ok(
+                        Cpt::Chat
+                        | Cpt::Broadcast
+                        | Cpt::FlowJoin
+                        | Cpt::FlowLeave
+                        | Cpt::FlowRenick
+                        | Cpt::Dm
+                        | Cpt::Notice
+                        | Cpt::Talkers
+                        | Cpt::CommandResponse,
+                    ) => {
+                        if let Some(event) = decode_event(&recv_buf[..size]) {
+                            let _ = tx.send((event, Local::now()));
                         }
-                        Err(e) => {
-                            eprintln!("error: {e}");
-                        }
-                    },
-                    Ok(Cpt::Broadcast) => match BroadcastPacket::deserialize(&recv_buf[..size]) {
-                        Ok(broadcast) => {
-                            let _ = tx.send((
-                                Message::Broadcast(broadcast.title, broadcast.content),
-                                Local::now(),
-                            ));
+                    }
+                    Ok(Cpt::Whisper) => {
+                        let Ok(FlowPacket::Whisper { from, message }) =
+                            FlowPacket::deserialize(&recv_buf[..size])
+                        else {
+                            let _ =
+                                tx.send((ClientEvent::Error("bad whisper".into()), Local::now()));
+                            continue;
+                        };
+
+                        let now = Local::now();
+                        let (reply, chime) = {
+                            let mut away = away.lock().unwrap();
+                            (away.on_dm(&from, &message, now), away.settings().chime)
+                        };
+
+                        if chime {
+                            alerts.lock().unwrap().extend(away::chime(48000));
                         }
-                        Err(e) => {
-                            eprintln!("error: {e}");
+                        if let Some(reply) = reply {
+                            let _ = socket.send(&protocol::create_dm_packet(&from, &reply));
                         }
-                    },
-                    Ok(Cpt::FlowJoin) | Ok(Cpt::FlowLeave) | Ok(Cpt::FlowRenick) | Ok(Cpt::Dm) => {
-                        if let Ok(flow) = FlowPacket::deserialize(&recv_buf[..size]) {
-                            let msg = match flow {
-                                FlowPacket::Join(user) => Message::JoinMessage(user),
-                                FlowPacket::Leave(user) => Message::LeaveMessage(user),
-                                FlowPacket::Renick { old_mask, new_mask } => {
-                                    Message::Renick(old_mask, new_mask)
-                                }
-                                FlowPacket::Broadcast { from, message } => {
-                                    Message::Broadcast(from, message)
-                                }
-                            };
 
-                            let _ = tx.send((msg, Local::now())); // this is quite fucked
+                        let _ = tx.send((ClientEvent::Whisper { from, message }, now));
+                    }
+                    Ok(Cpt::RetryAfter) => {
+                        if let Ok(retry) = RetryAfterPacket::deserialize(&recv_buf[..size]) {
+                            retry_join = Some((Instant::now() + retry.backoff(), retry.channel_id));
                         }
                     }
-                    Ok(Cpt::CommandResponse) => {}
                     Ok(Cpt::SyncCommands) => {
                         if let Ok(packet) = CommandListPacket::deserialize(&recv_buf[1..size]) {
                             let mut list = cmd_list.lock().unwrap();
-                            *list = packet.commands;
-                        }
-                    }
-                    Ok(Cpt::Cmd) => {
-                        if let Ok(packet) = CommandResponsePacket::deserialize(&recv_buf[1..size]) {
-                            let _ = tx.send((Message::Command(packet.result), Local::now()));
+                            if *list != packet.commands {
+                                *list = packet.commands;
+                                let _ = tx.send((
+                                    ClientEvent::CommandsUpdated(list.clone()),
+                                    Local::now(),
+                                ));
+                            }
                         }
                     }
                     Ok(Cpt::Eof) => {}
@@ -533,11 +1526,25 @@ impl ClientState {
                             .unwrap_or("Unknown reason".into());
                         *state = State::Kicked(reason.clone());
 
-                        let _ = tx.send((Message::Kick(reason.clone()), Local::now()));
+                        let _ = tx.send((
+                            ClientEvent::Disconnected(DisconnectReason::Kicked(reason)),
+                            Local::now(),
+                        ));
                     }
-                    Ok(Cpt::Join) | Ok(Cpt::Mask) | Ok(Cpt::Ctrl) | Ok(Cpt::RegisterConsole) => {}
+                    // late echoes from the startup probe
+                    Ok(Cpt::Probe | Cpt::Handshake) => {}
+                    Ok(Cpt::Join)
+                    | Ok(Cpt::Mask)
+                    | Ok(Cpt::Ctrl)
+                    | Ok(Cpt::Cmd)
+                    | Ok(Cpt::RegisterConsole)
+                    | Ok(Cpt::NickRequired) => {}
                     Err(_) => {}
                 },
+                // the nick error is the only packet that's just its type
+                Ok((size, _)) if is_nick_required(&recv_buf[..size]) => {
+                    let _ = tx.send((ClientEvent::NickRequired, Local::now()));
+                }
                 Ok((_, _)) => {}
                 Err(e) if e.0.kind() == io::ErrorKind::WouldBlock => {
                     thread::sleep(Duration::from_millis(1));
@@ -548,25 +1555,46 @@ impl ClientState {
                         let mut state = state.lock().unwrap();
                         *state = State::IncorrectPhraseError;
                     }
+                    let _ = tx.send((
+                        ClientEvent::Disconnected(DisconnectReason::IncorrectPhrase),
+                        Local::now(),
+                    ));
+                    break;
+                }
+                Err(e) => {
+                    let _ = tx.send((
+                        ClientEvent::Disconnected(DisconnectReason::ConnectionLost(
+                            e.0.to_string(),
+                        )),
+                        Local::now(),
+                    ));
                     break;
                 }
-                Err(_) => break,
             }
 
             while let Some((&tick, _)) = jitter_buffer.iter().next() {
                 let opus = jitter_buffer.remove(&tick).unwrap_or_default();
-                let mut pcm = vec![0.0f32; TARGET_FRAME_SIZE * 2];
+                let mut pcm = vec![0.0f32; frame_size * 2];
 
-                let _ = if opus.is_empty() {
+                let decoded = if opus.is_empty() {
                     decoder.decode_float(&[], &mut pcm, false)
                 } else {
                     decoder.decode_float(&opus, &mut pcm, false)
                 };
+                // the server's frames may be shorter than ours, only what was
+                // decoded gets played
+                let samples = match decoded {
+                    Ok(decoded) => decoded * 2,
+                    Err(_) => {
+                        decode_errors += 1;
+                        0
+                    }
+                };
 
                 // push samples to output buffer
                 let mut buffer = output.lock().unwrap();
-                for s in &pcm[..(TARGET_FRAME_SIZE * 2)] {
-                    if buffer.len() >= BUFFER_CAPACITY * 2 {
+                for s in &pcm[..samples] {
+                    if buffer.len() >= capacity * 2 {
                         buffer.pop_front();
                     }
                     buffer.push_back(*s);
@@ -577,18 +1605,36 @@ impl ClientState {
         }
     }
 
-    fn repl(
-        socket: SecureUdpSocket,
-        muted: Arc<AtomicBool>,
-        deafened: Arc<AtomicBool>,
-        list: SafeChannelList,
-    ) -> Result<()> {
+    fn repl(shared: Shared) -> Result<()> {
+        let Shared {
+            socket,
+            muted,
+            deafened,
+            list,
+            away,
+            quality,
+            vad_threshold,
+            levels,
+            volumes,
+            echo,
+            channel_id,
+            flush,
+            stats,
+            recorder,
+            soundboard,
+            nick,
+            cmd_list,
+            ..
+        } = shared;
         loop {
             let prompt = util::ask("> ");
             let (cmd, arg) = prompt.split_once(' ').unwrap_or((prompt.as_str(), ""));
             print!(":: ");
             match cmd.to_lowercase().as_str() {
                 "q" | "quit" => {
+                    if let Ok(Some(path)) = stop_recording(&recorder) {
+                        println!("saved {}", path.display());
+                    }
                     println!("goodbye!");
                     break;
                 }
@@ -614,6 +1660,224 @@ impl ClientState {
 
                     println!("speaker {}deafened", if new { "" } else { "un" });
                 }
+                "p" | "pan" => {
+                    let panning = match arg {
+                        "on" => true,
+                        "off" => false,
+                        _ => {
+                            println!("usage: pan <on|off>");
+                            continue;
+                        }
+                    };
+
+                    let mut pan_packet = vec![0x08];
+                    let mode = if panning { 0x05 } else { 0x06 };
+                    pan_packet.extend_from_slice(&[mode]);
+                    let _ = socket.send(&pan_packet);
+
+                    println!("stereo panning {}", if panning { "on" } else { "off" });
+                }
+                "g" | "gain" => {
+                    let Some((mask, gain)) = arg
+                        .rsplit_once(' ')
+                        .and_then(|(mask, gain)| Some((mask, gain.parse::<f32>().ok()?)))
+                    else {
+                        println!("usage: gain <mask> <gain>");
+                        continue;
+                    };
+
+                    let gain = set_user_volume(&socket, &volumes, mask, gain);
+                    println!("{mask} will now play at {gain}x");
+                }
+                "f" | "filter" => {
+                    let filter = match arg {
+                        "all" => 0,
+                        "speaking" => protocol::LIST_FILTER_SPEAKING,
+                        "admins" => protocol::LIST_FILTER_ADMINS,
+                        _ => {
+                            println!("usage: filter <all|speaking|admins>");
+                            continue;
+                        }
+                    };
+
+                    list.lock().unwrap().filter = filter;
+                    println!("list will show {arg} users");
+                }
+                "x" | "fx" => {
+                    let effects = if arg == "off" {
+                        Ok(vec![])
+                    } else {
+                        arg.split_whitespace()
+                            .map(str::parse::<EffectKind>)
+                            .collect::<Result<Vec<_>, _>>()
+                    };
+
+                    match effects {
+                        Ok(effects) if !arg.is_empty() => {
+                            let _ = socket.send(&protocol::create_effects_request(&effects));
+                            println!("effects set to '{arg}'");
+                        }
+                        Ok(_) => println!("usage: fx <pitch|reverb|robot ...|off>"),
+                        Err(e) => println!("{e}"),
+                    }
+                }
+                "w" | "whisper" => {
+                    let Some((to, message)) = arg.split_once(' ') else {
+                        println!("usage: whisper <user> <message>");
+                        continue;
+                    };
+
+                    let _ = socket.send(&protocol::create_dm_packet(to, message));
+                    println!("-> {to}: {message}");
+                }
+                "a" | "away" => {
+                    let mut away = away.lock().unwrap();
+                    if !arg.is_empty() {
+                        let settings = AwaySettings {
+                            message: arg.to_string(),
+                            ..away.settings().clone()
+                        };
+                        away.update_settings(settings);
+                    }
+                    away.set_away();
+                    println!("you are away, replying '{}'", away.settings().message);
+                }
+                "b" | "back" => {
+                    let missed = away.lock().unwrap().back();
+                    println!("welcome back!");
+                    print_missed(&missed);
+                }
+                "chime" => {
+                    let chime = match arg {
+                        "on" => true,
+                        "off" => false,
+                        _ => {
+                            println!("usage: chime <on|off>");
+                            continue;
+                        }
+                    };
+
+                    let mut away = away.lock().unwrap();
+                    let settings = AwaySettings {
+                        chime,
+                        ..away.settings().clone()
+                    };
+                    away.update_settings(settings);
+                    println!("whisper chime {arg}");
+                }
+                "v" | "vad" => {
+                    if arg.is_empty() {
+                        println!("voice activation threshold is {}", vad_threshold.load());
+                        continue;
+                    }
+                    let Ok(threshold) = arg.parse::<f32>() else {
+                        println!("usage: vad [threshold]");
+                        continue;
+                    };
+
+                    vad_threshold.store(threshold.max(0.0));
+                    println!("voice activation threshold set to {}", vad_threshold.load());
+                }
+                "j" | "join" => {
+                    let Ok(id) = arg.parse::<u32>() else {
+                        println!("usage: join <channel id>");
+                        continue;
+                    };
+
+                    match join_channel(&socket, &channel_id, &flush, id) {
+                        Ok(_) => println!("joining channel {id}"),
+                        Err(e) => println!("couldn't join channel {id}: {e}"),
+                    }
+                }
+                command @ ("listen" | "unlisten") => {
+                    let Ok(id) = arg.parse::<u32>() else {
+                        println!("usage: {command} <channel id>");
+                        continue;
+                    };
+
+                    let listen = command == "listen";
+                    match socket.send(&protocol::create_subscription_request(id, listen)) {
+                        Ok(_) if listen => println!("listening to channel {id}"),
+                        Ok(_) => println!("stopped listening to channel {id}"),
+                        Err(e) => println!("couldn't reach the server: {e}"),
+                    }
+                }
+                "stats" => println!("{}", stats.lock().unwrap()),
+                "r" | "record" => match arg {
+                    "start" | "start mic" => {
+                        match start_recording(&recorder, None, arg == "start mic") {
+                            Ok(path) => println!("recording to {}", path.display()),
+                            Err(e) => println!("couldn't start recording: {e}"),
+                        }
+                    }
+                    "stop" => match stop_recording(&recorder) {
+                        Ok(Some(path)) => println!("saved {}", path.display()),
+                        Ok(None) => println!("not recording"),
+                        Err(e) => println!("couldn't save the recording: {e}"),
+                    },
+                    _ => println!("usage: record <start [mic]|stop>"),
+                },
+                "play" => {
+                    let (mode, file) = match arg.strip_prefix("-r ") {
+                        Some(file) => (ClipMode::Replace, file),
+                        None => (ClipMode::Mix, arg),
+                    };
+                    match file {
+                        "" => println!("usage: play [-r] <file|stop>"),
+                        "stop" => match soundboard.lock().unwrap().stop() {
+                            true => println!("stopped"),
+                            false => println!("nothing is playing"),
+                        },
+                        file => match Clip::load(Path::new(file)) {
+                            Ok(clip) => {
+                                println!(
+                                    "playing {} ({:.1}s)",
+                                    clip.name(),
+                                    clip.duration().as_secs_f32()
+                                );
+                                soundboard.lock().unwrap().play(clip, mode);
+                            }
+                            Err(e) => println!("couldn't play {file}: {e}"),
+                        },
+                    }
+                }
+                "vu" => {
+                    println!("mic / speaker levels for the next few seconds");
+                    print_levels(&levels, Duration::from_secs(3));
+                }
+                "aec" => {
+                    let enabled = match arg {
+                        "on" => true,
+                        "off" => false,
+                        _ => {
+                            println!("usage: aec <on|off>");
+                            continue;
+                        }
+                    };
+
+                    echo.lock().unwrap().set_enabled(enabled);
+                    println!("echo cancellation is now {arg}");
+                }
+                "quality" => {
+                    let profile = match arg {
+                        "" => {
+                            println!("{}", quality.lock().unwrap());
+                            continue;
+                        }
+                        "auto" => None,
+                        name => match QualityProfile::by_name(name) {
+                            Some(profile) => Some(profile),
+                            None => {
+                                println!("usage: quality [auto|low|medium|high|max]");
+                                continue;
+                            }
+                        },
+                    };
+
+                    let mut quality = quality.lock().unwrap();
+                    quality.set_manual(profile);
+                    println!("quality: {quality}");
+                }
                 "s" | "send" => {
                     if arg.is_empty() {
                         println!("empty will not be sent!");
@@ -633,6 +1897,7 @@ impl ClientState {
                     let mut nick_packet = vec![0x04];
                     nick_packet.extend_from_slice(arg.as_bytes());
                     let _ = socket.send(&nick_packet);
+                    *nick.lock().unwrap() = Some(arg.to_string());
                     println!("you are now masked as '{}'", arg);
                 }
                 "l" | "list" => {
@@ -665,6 +1930,44 @@ impl ClientState {
                         println!("\t{}", line);
                     }
                 }
+                "c" | "commands" => {
+                    let commands = cmd_list.lock().unwrap();
+                    if commands.is_empty() {
+                        println!("the server hasn't sent its commands yet");
+                        continue;
+                    }
+
+                    println!();
+                    for command in util::complete_command(&commands, arg) {
+                        println!("  {} - {}", command.usage, command.description);
+                    }
+                }
+                command if command.starts_with('/') => {
+                    let commands = cmd_list.lock().unwrap();
+                    if let Some(usage) = util::usage_hint(&commands, &prompt) {
+                        println!("usage: {usage}");
+                        continue;
+                    }
+
+                    // nothing synced yet, the server will have to judge
+                    if !commands.is_empty() && util::find_command(&commands, command).is_none() {
+                        let names = util::complete_command(&commands, command)
+                            .iter()
+                            .map(|command| command.name.as_str())
+                            .collect::<Vec<_>>();
+                        if names.is_empty() {
+                            println!("no such command, 'c' lists them");
+                        } else {
+                            println!("did you mean {}?", names.join(", "));
+                        }
+                        continue;
+                    }
+
+                    let mut packet = vec![ClientPacketType::Cmd as u8];
+                    packet.extend_from_slice(prompt.as_bytes());
+                    let _ = socket.send(&packet);
+                    println!();
+                }
                 _ => println!("unknown command. type 'h' for help"),
             }
         }
@@ -692,7 +1995,63 @@ impl ClientState {
         self.deafened.store(deafened, Ordering::Relaxed);
     }
 
+    pub fn set_panning(&self, panning: bool) {
+        let mut pan_packet = vec![0x08];
+        let mode = if panning { 0x05 } else { 0x06 };
+        pan_packet.extend_from_slice(&[mode]);
+        self.send(&pan_packet);
+    }
+
+    /// Sets how loud `mask` sounds to us, 1.0 being unchanged
+    pub fn set_talker_gain(&self, mask: &str, gain: f32) {
+        self.send(&create_gain_packet(mask, gain));
+    }
+
+    /// Like `set_talker_gain` but remembered, so it's applied again when they
+    /// rejoin or we move channels. 0.0 mutes them for us only
+    pub fn set_user_volume(&self, mask: &str, gain: f32) -> f32 {
+        set_user_volume(&self.socket, &self.volumes, mask, gain)
+    }
+
+    pub fn user_volume(&self, mask: &str) -> f32 {
+        self.volumes
+            .lock()
+            .unwrap()
+            .get(mask)
+            .copied()
+            .unwrap_or(1.0)
+    }
+
+    /// Replaces our effect chain, an empty slice turns effects off
+    pub fn set_effects(&self, effects: &[EffectKind]) {
+        self.send(&protocol::create_effects_request(effects));
+    }
+
+    /// Starts auto replying to whispers and keeping them for later
+    pub fn set_away(&self) {
+        self.away.lock().unwrap().set_away();
+    }
+
+    /// Stops being away, returning the whispers that came in meanwhile
+    pub fn set_back(&self) -> Vec<MissedDm> {
+        self.away.lock().unwrap().back()
+    }
+
+    /// Privately messages whoever is using `mask`, they get it as a whisper.
+    /// If nobody is, the server answers with a `UserNotOnline` notice
+    pub fn send_dm(&self, mask: &str, message: &str) {
+        self.send(&protocol::create_dm_packet(mask, message));
+    }
+
+    pub fn whisper(&self, to: &str, message: &str) {
+        self.send_dm(to, message);
+    }
+
     pub fn disconnect(&self) {
+        if let Err(e) = self.stop_recording() {
+            eprintln!("couldn't save the recording: {e}");
+        }
+
         let leave = vec![0x03];
         self.socket.send(&leave).unwrap();
 
@@ -709,3 +2068,242 @@ impl ClientState {
         let _ = self.socket.send(&packet);
     }
 }
+
+/// Turns the packets that don't depend on any client state into events,
+/// `None` for every other packet type
+pub(crate) fn decode_event(packet: &[u8]) -> Option<ClientEvent> {
+    type Cpt = ClientPacketType;
+    let event = match Cpt::try_from(*packet.first()?).ok()? {
+        Cpt::Chat => ChatPacket::deserialize(packet).map(|chat| ClientEvent::Chat {
+            from: chat.username,
+            message: chat.message,
+            is_self: chat.is_self,
+        }),
+        Cpt::Broadcast => {
+            BroadcastPacket::deserialize(packet).map(|broadcast| ClientEvent::Broadcast {
+                from: broadcast.title,
+                message: broadcast.content,
+            })
+        }
+        Cpt::FlowJoin | Cpt::FlowLeave | Cpt::FlowRenick | Cpt::Dm | Cpt::Whisper => {
+            FlowPacket::deserialize(packet).map(|flow| match flow {
+                FlowPacket::Join(user) => ClientEvent::UserJoined(user),
+                FlowPacket::Leave(user) => ClientEvent::UserLeft(user),
+                FlowPacket::Renick { old_mask, new_mask } => ClientEvent::Renamed {
+                    old: old_mask,
+                    new: new_mask,
+                },
+                FlowPacket::Broadcast { from, message } => ClientEvent::Broadcast { from, message },
+                FlowPacket::Whisper { from, message } => ClientEvent::Whisper { from, message },
+            })
+        }
+        Cpt::Notice => LocalizedMessage::deserialize(packet).map(ClientEvent::Notice),
+        Cpt::Talkers => TalkersPacket::deserialize(packet)
+            .map(|talkers| ClientEvent::TalkersChanged(talkers.masks)),
+        Cpt::CommandResponse => {
+            CommandResponsePacket::deserialize(packet).map(|response| ClientEvent::Command {
+                command: response.command,
+                result: response.result,
+            })
+        }
+        Cpt::NickRequired => Ok(ClientEvent::NickRequired),
+        _ => return None,
+    };
+
+    Some(event.unwrap_or_else(|e| ClientEvent::Error(e.to_string())))
+}
+
+pub(crate) fn is_nick_required(packet: &[u8]) -> bool {
+    packet == [ClientPacketType::NickRequired as u8]
+}
+
+fn create_gain_packet(mask: &str, gain: f32) -> Vec<u8> {
+    let mut packet = vec![0x08, 0x07];
+    packet.extend_from_slice(&gain.to_be_bytes());
+    packet.extend_from_slice(mask.as_bytes());
+    packet
+}
+
+// the server's frame size in samples per channel, older servers don't say
+pub(crate) fn handshake(socket: &SecureUdpSocket, timeout: Duration) -> io::Result<Option<usize>> {
+    // linux reports a closed port on the next send or recv
+    let refused = |e: io::Error| {
+        if e.kind() == io::ErrorKind::ConnectionRefused {
+            io::Error::new(e.kind(), "nothing is listening at the server's address")
+        } else {
+            e
+        }
+    };
+
+    let deadline = Instant::now() + timeout;
+    let mut sent_at: Option<Instant> = None;
+    let mut buf = [0u8; 2048];
+
+    while Instant::now() < deadline {
+        if sent_at.is_none_or(|at| at.elapsed() >= HANDSHAKE_RETRY) {
+            socket
+                .send(&[ClientPacketType::Handshake as u8])
+                .map_err(refused)?;
+            sent_at = Some(Instant::now());
+        }
+
+        match socket.recv_from(&mut buf) {
+            Ok((size, _)) if size > 0 && buf[0] == ClientPacketType::Handshake as u8 => {
+                let frame_size = buf[1..size]
+                    .try_into()
+                    .ok()
+                    .map(|bytes| u32::from_be_bytes(bytes) as usize);
+                return Ok(frame_size);
+            }
+            Ok(_) => {}
+            Err((e, _)) if e.kind() == io::ErrorKind::ConnectionRefused => {
+                return Err(refused(e));
+            }
+            Err(_) => thread::sleep(Duration::from_millis(5)),
+        }
+    }
+
+    Err(io::Error::new(
+        io::ErrorKind::TimedOut,
+        "the server didn't answer, check the address and phrase",
+    ))
+}
+
+fn start_recording(
+    recorder: &SafeRecorder,
+    path: Option<&Path>,
+    include_mic: bool,
+) -> io::Result<PathBuf> {
+    let mut recorder = recorder.lock().unwrap();
+    if let Some(current) = recorder.as_ref() {
+        return Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            format!("already recording to {}", current.path().display()),
+        ));
+    }
+
+    let path = path.map_or_else(
+        || Recorder::default_path(Path::new(recorder::DEFAULT_RECORDINGS_DIR)),
+        Path::to_path_buf,
+    );
+    *recorder = Some(Recorder::create(&path, include_mic)?);
+    Ok(path)
+}
+
+fn stop_recording(recorder: &SafeRecorder) -> io::Result<Option<PathBuf>> {
+    let recorder = recorder.lock().unwrap().take();
+    recorder.map(Recorder::finish).transpose()
+}
+
+fn join_channel(
+    socket: &SecureUdpSocket,
+    channel_id: &Mutex<u32>,
+    flush: &AtomicBool,
+    id: u32,
+) -> Result<usize, std::io::Error> {
+    let mut join_packet = vec![ClientPacketType::Join as u8];
+    join_packet.extend_from_slice(&id.to_be_bytes());
+    let sent = socket.send(&join_packet)?;
+
+    *channel_id.lock().unwrap() = id;
+    flush.store(true, Ordering::Relaxed);
+    Ok(sent)
+}
+
+// returns the gain actually applied
+fn set_user_volume(socket: &SecureUdpSocket, volumes: &SafeVolumes, mask: &str, gain: f32) -> f32 {
+    let gain = gain.clamp(0.0, protocol::MAX_TALKER_GAIN);
+    {
+        let mut volumes = volumes.lock().unwrap();
+        if gain == 1.0 {
+            volumes.remove(mask);
+        } else {
+            volumes.insert(mask.to_string(), gain);
+        }
+    }
+
+    let _ = socket.send(&create_gain_packet(mask, gain));
+    gain
+}
+
+// keeps the shared list for the repl and only tells listeners about changes
+fn update_list(
+    socket: &SecureUdpSocket,
+    list: &SafeChannelList,
+    volumes: &SafeVolumes,
+    tx: &Sender<OwnedEvent>,
+    parsed: GlobalListPacket,
+) {
+    let mut list = list.lock().unwrap();
+    list.last_updated = Instant::now();
+    if list.channels == parsed.channels && list.current_channel == parsed.current {
+        return;
+    }
+
+    // the server forgets our gains for anyone who leaves, or when we move
+    let volumes = volumes.lock().unwrap();
+    let current = parsed
+        .channels
+        .iter()
+        .find(|channel| channel.channel_id == parsed.current);
+    for (mask, ..) in current
+        .into_iter()
+        .flat_map(|channel| &channel.masked_users)
+    {
+        if let Some(&gain) = volumes.get(mask) {
+            let _ = socket.send(&create_gain_packet(mask, gain));
+        }
+    }
+
+    list.channels = parsed.channels;
+    list.current_channel = parsed.current;
+    let _ = tx.send((
+        ClientEvent::ListUpdated {
+            channels: list.channels.clone(),
+            current: list.current_channel,
+        },
+        Local::now(),
+    ));
+}
+
+fn print_levels(levels: &AudioLevels, duration: Duration) {
+    let start = Instant::now();
+    while start.elapsed() < duration {
+        print!(
+            "\r\tmic {} speaker {}",
+            vu_bar(&levels.input),
+            vu_bar(&levels.output)
+        );
+        let _ = io::Write::flush(&mut io::stdout());
+        thread::sleep(Duration::from_millis(100));
+    }
+    println!();
+}
+
+// 20 cells covering -60 to 0 dBFS, with the held peak marked
+fn vu_bar(meter: &LevelMeter) -> String {
+    let cell = |dbfs: f32| (((dbfs + 60.0) / 3.0).clamp(0.0, 20.0)) as usize;
+    let filled = cell(meter.rms_dbfs());
+    let peak = cell(meter.peak_dbfs()).min(19);
+
+    let bar = (0..20)
+        .map(|i| match i {
+            i if i < filled => '#',
+            i if i == peak && meter.peak() > 0.0 => '|',
+            _ => '.',
+        })
+        .collect::<String>();
+    format!("[{bar}] {:>6.1} dB", meter.rms_dbfs().max(-99.9))
+}
+
+fn print_missed(missed: &[MissedDm]) {
+    if missed.is_empty() {
+        println!("nobody whispered you while you were away");
+        return;
+    }
+
+    println!("while you were away:");
+    for dm in missed {
+        println!("\t[{}] {}: {}", dm.at.format("%H:%M"), dm.from, dm.message);
+    }
+}
@@ -4,7 +4,7 @@ use std::collections::HashMap;
 use crate::{
     server::{Channel, ServerState},
     socket::SecureUdpSocket,
-    util::{CommandCategory, CommandContext, CommandResult, ServerCommand},
+    util::{ArgKind, CommandArg, CommandCategory, CommandContext, CommandResult, ServerCommand},
 };
 
 pub type CommandFn = Box<
@@ -31,27 +31,6 @@ impl CommandSystem {
     }
 
     fn register_default_commands(&mut self, socket: &SecureUdpSocket) {
-        self.register_command(
-            ServerCommand {
-                name: "/test".to_string(),
-                description: "Test command".to_string(),
-                usage: "/test <args>".to_string(),
-                category: CommandCategory::Fun,
-                aliases: vec!["/".to_string()],
-                requires_auth: true,
-                admin_only: false,
-            },
-            |ctx, _| {
-                let mask = ctx.sender_mask.clone().unwrap();
-
-                if mask.eq("spixa") {
-                    CommandResult::Success("Good name".into())
-                } else {
-                    CommandResult::Error("Bad name".into())
-                }
-            },
-        );
-
         let socket = socket.clone();
 
         let titles: Vec<String> = vec![
@@ -73,14 +52,11 @@ impl CommandSystem {
                 aliases: vec!["/".to_string()],
                 requires_auth: true,
                 admin_only: false,
+                args: vec![CommandArg::required("activity", ArgKind::Text)],
             },
             move |ctx, chans| {
-                if ctx.arguments.is_empty() {
-                    return CommandResult::Silent;
-                }
-
                 let mask = ctx.sender_mask.clone().unwrap();
-                let activity = ctx.arguments.join(" ");
+                let activity = ctx.text(0);
 
                 let mut rng = rand::rng();
                 let title = titles.choose(&mut rng).cloned();
@@ -106,6 +82,7 @@ impl CommandSystem {
                 aliases: vec![],
                 requires_auth: false,
                 admin_only: false,
+                args: vec![],
             },
             |_, _| {
                 // if let Some(_) = chans.get(&ctx.channel_id) {
@@ -125,6 +102,7 @@ impl CommandSystem {
                 aliases: vec![],
                 requires_auth: true,
                 admin_only: false,
+                args: vec![],
             },
             |ctx, chans| {
                 if let Some(channel) = chans.get(&ctx.channel_id) {
@@ -142,115 +120,159 @@ impl CommandSystem {
             },
         );
 
-        //     self.register_command(ServerCommand {
-        //         name: "/join".to_string(),
-        //         description: "Switch to another channel".to_string(),
-        //         usage: "/join <channel_id>".to_string(),
-        //         category: CommandCategory::Channel,
-        //         aliases: vec!["/j".to_string(), "/switch".to_string()],
-        //         requires_auth: false,
-        //         admin_only: false,
-        //     });
-
-        //     self.register_command(ServerCommand {
-        //         name: "/list".to_string(),
-        //         description: "List all channels and users".to_string(),
-        //         usage: "/list".to_string(),
-        //         category: CommandCategory::Channel,
-        //         aliases: vec!["/channels".to_string(), "/ls".to_string()],
-        //         requires_auth: false,
-        //         admin_only: false,
-        //     });
-
-        //     self.register_command(ServerCommand {
-        //         name: "/mute".to_string(),
-        //         description: "Toggle your microphone mute".to_string(),
-        //         usage: "/mute".to_string(),
-        //         category: CommandCategory::Audio,
-        //         aliases: vec![],
-        //         requires_auth: false,
-        //         admin_only: false,
-        //     });
-
-        //     self.register_command(ServerCommand {
-        //         name: "/deafen".to_string(),
-        //         description: "Toggle your speaker deafen".to_string(),
-        //         usage: "/deafen".to_string(),
-        //         category: CommandCategory::Audio,
-        //         aliases: vec![],
-        //         requires_auth: false,
-        //         admin_only: false,
-        //     });
-
-        //     self.register_command(ServerCommand {
-        //         name: "/me".to_string(),
-        //         description: "Perform an action".to_string(),
-        //         usage: "/me <action>".to_string(),
-        //         category: CommandCategory::Chat,
-        //         aliases: vec![],
-        //         requires_auth: true,
-        //         admin_only: false,
-        //     });
-
-        //     self.register_command(ServerCommand {
-        //         name: "/whisper".to_string(),
-        //         description: "Send a private message".to_string(),
-        //         usage: "/whisper <user> <message>".to_string(),
-        //         category: CommandCategory::Chat,
-        //         aliases: vec!["/w".to_string(), "/msg".to_string(), "/tell".to_string()],
-        //         requires_auth: true,
-        //         admin_only: false,
-        //     });
-
-        //     self.register_command(ServerCommand {
-        //         name: "/kick".to_string(),
-        //         description: "Kick a user from the server".to_string(),
-        //         usage: "/kick <user> [reason]".to_string(),
-        //         category: CommandCategory::Admin,
-        //         aliases: vec![],
-        //         requires_auth: true,
-        //         admin_only: true,
-        //     });
-
-        //     self.register_command(ServerCommand {
-        //         name: "/ban".to_string(),
-        //         description: "Ban a user from the server".to_string(),
-        //         usage: "/ban <user> [reason]".to_string(),
-        //         category: CommandCategory::Admin,
-        //         aliases: vec![],
-        //         requires_auth: true,
-        //         admin_only: true,
-        //     });
+        self.register_command(
+            ServerCommand {
+                name: "/list".to_string(),
+                description: "List all channels and users".to_string(),
+                usage: "/list".to_string(),
+                category: CommandCategory::Channel,
+                aliases: vec!["/channels".to_string(), "/ls".to_string()],
+                requires_auth: false,
+                admin_only: false,
+                args: vec![],
+            },
+            |_, chans| {
+                let mut ids = chans.keys().copied().collect::<Vec<_>>();
+                ids.sort();
+
+                let lines = ids
+                    .iter()
+                    .map(|id| {
+                        let channel = &chans[id];
+                        let name = match &channel.name {
+                            Some(name) => format!("#{name} ({id})"),
+                            None => format!("channel {id}"),
+                        };
+                        let masks = channel
+                            .remotes
+                            .iter()
+                            .map(|remote| {
+                                let remote = remote.lock().unwrap();
+                                remote.mask.clone().unwrap_or_else(|| "(no nick)".into())
+                            })
+                            .collect::<Vec<_>>();
+                        if masks.is_empty() {
+                            format!("{name}: empty")
+                        } else {
+                            format!("{name}: {}", masks.join(", "))
+                        }
+                    })
+                    .collect::<Vec<_>>();
 
-        //     self.register_command(ServerCommand {
-        //         name: "/help".to_string(),
-        //         description: "Show help for commands".to_string(),
-        //         usage: "/help [command]".to_string(),
-        //         category: CommandCategory::Utility,
-        //         aliases: vec!["/?".to_string(), "/commands".to_string()],
-        //         requires_auth: false,
-        //         admin_only: false,
-        //     });
+                CommandResult::Success(lines.join("\n"))
+            },
+        );
 
-        //     self.register_command(ServerCommand {
-        //         name: "/ping".to_string(),
-        //         description: "Check server latency".to_string(),
-        //         usage: "/ping".to_string(),
-        //         category: CommandCategory::Utility,
-        //         aliases: vec![],
-        //         requires_auth: false,
-        //         admin_only: false,
-        //     });
+        self.register_command(
+            ServerCommand {
+                name: "/ping".to_string(),
+                description: "Check that the server answers".to_string(),
+                usage: "/ping".to_string(),
+                category: CommandCategory::Utility,
+                aliases: vec![],
+                requires_auth: false,
+                admin_only: false,
+                args: vec![],
+            },
+            |_, _| CommandResult::Success("pong".into()),
+        );
 
-        //     self.register_command(ServerCommand {
-        //         name: "/serverinfo".to_string(),
-        //         description: "Show server information".to_string(),
-        //         usage: "/serverinfo".to_string(),
-        //         category: CommandCategory::Utility,
-        //         aliases: vec!["/info".to_string(), "/status".to_string()],
-        //         requires_auth: false,
-        //         admin_only: false,
-        //     });
+        // these need more of the server than the channels, `ServerState` runs
+        // them itself
+        let server_commands = [
+            ServerCommand {
+                name: "/nick".to_string(),
+                description: "Set your nickname".to_string(),
+                usage: "/nick <name>".to_string(),
+                category: CommandCategory::User,
+                aliases: vec!["/name".to_string()],
+                requires_auth: false,
+                admin_only: false,
+                args: vec![CommandArg::required("name", ArgKind::Text)],
+            },
+            ServerCommand {
+                name: "/join".to_string(),
+                description: "Switch to another channel".to_string(),
+                usage: "/join <channel_id>".to_string(),
+                category: CommandCategory::Channel,
+                aliases: vec!["/j".to_string(), "/switch".to_string()],
+                requires_auth: false,
+                admin_only: false,
+                args: vec![CommandArg::required("channel_id", ArgKind::Channel)],
+            },
+            ServerCommand {
+                name: "/kick".to_string(),
+                description: "Kick a user from the server".to_string(),
+                usage: "/kick <user> [reason]".to_string(),
+                category: CommandCategory::Admin,
+                aliases: vec![],
+                requires_auth: true,
+                admin_only: true,
+                args: vec![
+                    CommandArg::required("user", ArgKind::User),
+                    CommandArg::optional("reason", ArgKind::Text),
+                ],
+            },
+            ServerCommand {
+                name: "/ban".to_string(),
+                description: "Ban a user's address from the server".to_string(),
+                usage: "/ban <user> [reason]".to_string(),
+                category: CommandCategory::Admin,
+                aliases: vec![],
+                requires_auth: true,
+                admin_only: true,
+                args: vec![
+                    CommandArg::required("user", ArgKind::User),
+                    CommandArg::optional("reason", ArgKind::Text),
+                ],
+            },
+            ServerCommand {
+                name: "/move".to_string(),
+                description: "Move a user to another channel".to_string(),
+                usage: "/move <user> <channel_id>".to_string(),
+                category: CommandCategory::Admin,
+                aliases: vec!["/mv".to_string()],
+                requires_auth: true,
+                admin_only: true,
+                args: vec![
+                    CommandArg::required("user", ArgKind::User),
+                    CommandArg::required("channel_id", ArgKind::Channel),
+                ],
+            },
+            ServerCommand {
+                name: "/unban".to_string(),
+                description: "Lift a ban".to_string(),
+                usage: "/unban <user|address>".to_string(),
+                category: CommandCategory::Admin,
+                aliases: vec![],
+                requires_auth: true,
+                admin_only: true,
+                args: vec![CommandArg::required("user|address", ArgKind::String)],
+            },
+            ServerCommand {
+                name: "/stats".to_string(),
+                description: "Show how the server is keeping up".to_string(),
+                usage: "/stats".to_string(),
+                category: CommandCategory::Utility,
+                aliases: vec![],
+                requires_auth: false,
+                admin_only: false,
+                args: vec![],
+            },
+            ServerCommand {
+                name: "/help".to_string(),
+                description: "Show help for commands".to_string(),
+                usage: "/help [command]".to_string(),
+                category: CommandCategory::Utility,
+                aliases: vec!["/?".to_string(), "/commands".to_string()],
+                requires_auth: false,
+                admin_only: false,
+                args: vec![CommandArg::optional("command", ArgKind::String)],
+            },
+        ];
+        for command in server_commands {
+            self.register_command(command, |_, _| CommandResult::Silent);
+        }
     }
 
     pub fn register_command<F>(&mut self, command: ServerCommand, f: F)
@@ -272,6 +294,11 @@ impl CommandSystem {
         }
     }
 
+    pub fn unregister_command(&mut self, name: &str) {
+        self.commands.remove(name);
+        self.command_aliases.retain(|_, target| target != name);
+    }
+
     pub fn get_command(&self, name: &str) -> Option<(&ServerCommand, &CommandFn)> {
         let actual_name = self
             .command_aliases
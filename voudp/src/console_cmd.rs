@@ -1,27 +1,105 @@
 // console_commands.rs
-use crate::server::{Channel, ServerConfig};
-use crate::socket::SecureUdpSocket;
+use std::collections::HashMap;
+use std::net::SocketAddr;
+
+use crate::audit::{self, AuditLog};
+use crate::plugin::PluginManager;
+use crate::protocol::{DEFAULT_CHANNEL_ID, MAX_TALKER_GAIN};
+use crate::server::{Channel, Role, ServerConfig};
+
+/// What a command needs done to clients, which only the server can send
+pub enum ConsoleAction {
+    Kick {
+        addr: SocketAddr,
+        reason: String,
+    },
+    Ban {
+        addr: SocketAddr,
+        mask: String,
+        reason: String,
+    },
+    Mute {
+        addr: SocketAddr,
+        muted: bool,
+    },
+    Move {
+        addr: SocketAddr,
+        channel: u32,
+    },
+    /// A notice to everyone in the channel
+    Say {
+        channel: u32,
+        text: String,
+    },
+}
 
 pub enum ConsoleCommandResult {
     Reply(String),
+    /// The command didn't run, scripts stop here
+    Error(String),
+    /// The reply, sent once the server has carried out the actions
+    Apply(String, Vec<ConsoleAction>),
+}
+
+/// Everything a console can run, what `commands` hands out for completion
+pub const COMMANDS: &[&str] = &[
+    "help",
+    "ping",
+    "list",
+    "rename",
+    "inspect",
+    "note",
+    "op",
+    "deop",
+    "dsp",
+    "gain",
+    "loudness",
+    "plugins",
+    "chans",
+    "create",
+    "del",
+    "kick",
+    "ban",
+    "mute",
+    "unmute",
+    "move",
+    "say",
+    "sessions",
+    "status",
+    "subscribe",
+    "unsubscribe",
+    "commands",
+];
+
+/// Whether the command only looks, which is all observer consoles may run
+pub fn is_read_only(cmd: &str, parts: &[&str]) -> bool {
+    match cmd {
+        "help" | "ping" | "list" | "inspect" | "loudness" | "chans" | "sessions" | "status"
+        | "subscribe" | "unsubscribe" | "commands" => true,
+        "plugins" => matches!(parts.get(1..), Some([] | ["list"])),
+        // without settings it only shows them
+        "dsp" => parts.len() <= 2,
+        _ => false,
+    }
 }
 
 pub fn handle_command(
     cmd: &str,
     parts: &[&str],
-    channels: &mut std::collections::HashMap<u32, Channel>,
+    channels: &mut HashMap<u32, Channel>,
     config: &ServerConfig,
-    _socket_sender: Option<&mut SecureUdpSocket>,
+    audit: &mut AuditLog,
+    plugins: &mut PluginManager,
 ) -> ConsoleCommandResult {
     match cmd {
         "help" => ConsoleCommandResult::Reply("you are connected to a voudp 0.1 server".into()),
         "ping" => ConsoleCommandResult::Reply("pong".into()),
         "list" => {
-            ConsoleCommandResult::Reply("global list cannot be displayed with crossterm".into())
+            ConsoleCommandResult::Error("global list cannot be displayed with crossterm".into())
         }
         "rename" => {
             if parts.len() < 3 {
-                ConsoleCommandResult::Reply("usage: rename <channel> <new-name>".to_string())
+                ConsoleCommandResult::Error("usage: rename <channel> <new-name>".to_string())
             } else {
                 let ident = parts[1];
                 let new_name = parts[2..].join(" ");
@@ -43,10 +121,241 @@ pub fn handle_command(
                             old_name, new_name
                         ))
                     }
-                    None => ConsoleCommandResult::Reply(format!("channel '{}' not found", ident)),
+                    None => ConsoleCommandResult::Error(format!("channel '{}' not found", ident)),
+                }
+            }
+        }
+        "inspect" => {
+            if parts.len() < 2 {
+                ConsoleCommandResult::Error("usage: inspect <mask>".into())
+            } else {
+                let mask = parts[1];
+                let location = channels.iter().find_map(|(id, channel)| {
+                    channel.remotes.iter().find_map(|remote| {
+                        let remote = remote.lock().ok()?;
+                        (remote.mask.as_deref() == Some(mask)).then(|| {
+                            format!(
+                                "{mask} is in {} ({id}) from {} (muted: {}, deafened: {})",
+                                channel.name.clone().unwrap_or_else(|| "unnamed".into()),
+                                remote.addr,
+                                remote.status.mute,
+                                remote.status.deaf
+                            )
+                        })
+                    })
+                });
+
+                ConsoleCommandResult::Reply(format!(
+                    "{}\n{}",
+                    location.unwrap_or_else(|| format!("{mask} is not connected")),
+                    audit::format_notes(mask, audit.notes(mask))
+                ))
+            }
+        }
+        "note" => {
+            if parts.len() < 3 {
+                ConsoleCommandResult::Error("usage: note <mask> <text>".into())
+            } else {
+                audit.add_note(parts[1], "console", &parts[2..].join(" "));
+                ConsoleCommandResult::Reply(format!("noted about {}", parts[1]))
+            }
+        }
+        "op" | "deop" => {
+            let Some(mask) = parts.get(1) else {
+                return ConsoleCommandResult::Error(format!("usage: {cmd} <mask>"));
+            };
+            let role = if cmd == "op" { Role::Admin } else { Role::User };
+
+            let remote = channels.values().find_map(|channel| {
+                channel
+                    .remotes
+                    .iter()
+                    .find(|r| r.lock().unwrap().mask.as_deref() == Some(*mask))
+            });
+
+            match remote {
+                Some(remote) => {
+                    remote.lock().unwrap().role = role;
+                    log::info!("{mask} is now {role:?}");
+                    audit.add_note(mask, "console", &format!("role set to {role:?}"));
+                    ConsoleCommandResult::Reply(format!("{mask} is now {role:?}"))
+                }
+                None => ConsoleCommandResult::Error(format!("{mask} is not connected")),
+            }
+        }
+        "dsp" => {
+            let Some(channel) = parts
+                .get(1)
+                .and_then(|id| id.parse::<u32>().ok())
+                .and_then(|id| channels.get_mut(&id))
+            else {
+                return ConsoleCommandResult::Error(
+                    "usage: dsp <channel_id> [alpha <0..1>] [hpf <hz|off>] [gate <rms>]".into(),
+                );
+            };
+
+            for pair in parts[2..].chunks(2) {
+                let [key, value] = pair else {
+                    return ConsoleCommandResult::Error(format!("missing value for '{}'", pair[0]));
+                };
+
+                let config = &mut channel.server_config;
+                match (*key, value.parse::<f32>()) {
+                    ("alpha", Ok(alpha)) if (0.0..1.0).contains(&alpha) => config.dc_alpha = alpha,
+                    ("hpf", _) if *value == "off" => config.highpass_hz = None,
+                    ("hpf", Ok(hz)) if hz > 0.0 && hz < config.sample_rate as f32 / 2.0 => {
+                        config.highpass_hz = Some(hz)
+                    }
+                    ("gate", Ok(rms)) if rms >= 0.0 => config.silence_threshold = rms,
+                    _ => {
+                        return ConsoleCommandResult::Error(format!(
+                            "invalid value '{value}' for '{key}'"
+                        ));
+                    }
+                }
+            }
+
+            // filters have to be rebuilt with the new cutoff
+            channel.highpass_states.clear();
+
+            let config = &channel.server_config;
+            ConsoleCommandResult::Reply(format!(
+                "{}: dc alpha {}, high-pass {}, silence gate {}",
+                channel.name.clone().unwrap_or_else(|| "unnamed".into()),
+                config.dc_alpha,
+                config
+                    .highpass_hz
+                    .map_or_else(|| "off".to_string(), |hz| format!("{hz} Hz")),
+                config.silence_threshold
+            ))
+        }
+        "gain" => {
+            // gain <talker> <gain>, or gain <listener> <talker> <gain> for a single listener
+            let (listener, talker, gain) = match parts {
+                [_, talker, gain] => (None, *talker, gain.parse::<f32>()),
+                [_, listener, talker, gain] => (Some(*listener), *talker, gain.parse::<f32>()),
+                _ => {
+                    return ConsoleCommandResult::Error(
+                        "usage: gain [listener] <talker> <gain>".into(),
+                    );
+                }
+            };
+
+            let Ok(gain) = gain else {
+                return ConsoleCommandResult::Error("gain must be a number".into());
+            };
+            let gain = gain.clamp(0.0, MAX_TALKER_GAIN);
+
+            let Some(channel) = channels
+                .values_mut()
+                .find(|channel| channel.find_remote(talker).is_some())
+            else {
+                return ConsoleCommandResult::Error(format!("{talker} is not connected"));
+            };
+            let talker_addr = channel.find_remote(talker).unwrap();
+
+            match listener {
+                None => {
+                    channel.set_talker_gain(talker_addr, gain);
+                    ConsoleCommandResult::Reply(format!("everyone now hears {talker} at {gain}x"))
+                }
+                Some(listener) => match channel.find_remote(listener) {
+                    Some(listener_addr) => {
+                        channel.set_listener_gain(listener_addr, talker_addr, gain);
+                        ConsoleCommandResult::Reply(format!(
+                            "{listener} now hears {talker} at {gain}x"
+                        ))
+                    }
+                    None => ConsoleCommandResult::Error(format!(
+                        "{listener} is not in the same channel as {talker}"
+                    )),
+                },
+            }
+        }
+        "loudness" => {
+            let mut ids = match parts.get(1).map(|id| id.parse::<u32>()) {
+                Some(Ok(id)) if channels.contains_key(&id) => vec![id],
+                Some(_) => {
+                    return ConsoleCommandResult::Error("usage: loudness [channel_id]".into());
+                }
+                None => channels.keys().copied().collect(),
+            };
+            ids.sort();
+
+            let mut out = Vec::new();
+            for id in ids {
+                let channel = &channels[&id];
+                out.push(format!(
+                    "{} ({id}): {} LUFS short-term, {} LUFS momentary",
+                    channel.name.clone().unwrap_or_else(|| "unnamed".into()),
+                    format_lufs(channel.meter.short_term()),
+                    format_lufs(channel.meter.momentary())
+                ));
+
+                // loudest first, that's who you are looking for
+                let mut talkers = channel
+                    .remotes
+                    .iter()
+                    .filter_map(|remote| {
+                        let remote = remote.lock().ok()?;
+                        let meter = channel.meters.get(&remote.addr)?;
+                        let name = remote.mask.clone().unwrap_or(remote.addr.to_string());
+                        Some((name, meter.short_term(), meter.peak_dbfs()))
+                    })
+                    .collect::<Vec<_>>();
+                talkers.sort_by(|a, b| b.1.total_cmp(&a.1));
+
+                for (name, lufs, peak) in talkers {
+                    out.push(format!(
+                        "  {name}: {} LUFS, peak {} dBFS{}",
+                        format_lufs(lufs),
+                        format_lufs(peak),
+                        if peak >= 0.0 { " (clipping)" } else { "" }
+                    ));
                 }
             }
+
+            ConsoleCommandResult::Reply(out.join("\n"))
         }
+        "plugins" => match parts.get(1..) {
+            Some([]) | Some(["list"]) => {
+                let names = plugins
+                    .names()
+                    .into_iter()
+                    .map(|name| match plugins.is_enabled(name) {
+                        true => name.to_string(),
+                        false => format!("{name} (disabled)"),
+                    })
+                    .chain(
+                        plugins
+                            .native_names()
+                            .into_iter()
+                            .map(|name| format!("{name} (native)")),
+                    )
+                    .collect::<Vec<_>>();
+                ConsoleCommandResult::Reply(format!("plugins: {}", names.join(", ")))
+            }
+            Some([action @ ("enable" | "disable"), name]) => {
+                match plugins.set_enabled(name, *action == "enable") {
+                    Ok(()) => ConsoleCommandResult::Reply(format!("{action}d {name}")),
+                    Err(e) => ConsoleCommandResult::Error(e),
+                }
+            }
+            Some(["reload"]) => {
+                plugins.reload_all();
+                ConsoleCommandResult::Reply(format!(
+                    "reloaded plugins: {}",
+                    plugins.names().join(", ")
+                ))
+            }
+            Some(["reload", name]) => match plugins.reload_plugin(name) {
+                Ok(()) => ConsoleCommandResult::Reply(format!("reloaded {name}")),
+                Err(e) => ConsoleCommandResult::Error(e),
+            },
+            _ => ConsoleCommandResult::Error(
+                "usage: plugins [list | reload [name] | enable <name> | disable <name>]".into(),
+            ),
+        },
         "chans" => {
             let s = channels
                 .iter()
@@ -63,11 +372,12 @@ pub fn handle_command(
         }
         "create" => {
             if parts.len() < 2 {
-                ConsoleCommandResult::Reply("usage: create <channel_name>".into())
+                ConsoleCommandResult::Error("usage: create <channel_name>".into())
             } else {
                 let name = parts[1..].join(" ");
                 let new_id = channels.keys().max().map_or(1, |id| id + 1);
                 channels.insert(new_id, Channel::new(*config, name.clone(), new_id));
+                plugins.dispatch_channel_create(new_id, Some(&name));
                 ConsoleCommandResult::Reply(format!(
                     "created channel '{}' with id {} ({}kHz)",
                     name,
@@ -78,52 +388,167 @@ pub fn handle_command(
         }
         "del" => {
             if parts.len() < 2 {
-                ConsoleCommandResult::Reply("usage: del <channel_id|channel_name>".into())
+                ConsoleCommandResult::Error("usage: del <channel_id|channel_name>".into())
             } else {
-                let target = parts[1];
-                let maybe_channel_id = target.parse::<u32>().ok();
-
-                let channel_id_to_delete = if let Some(id) = maybe_channel_id {
-                    if id == 1 { None } else { Some(id) }
-                } else {
-                    channels
-                        .iter()
-                        .find(|(_, c)| c.name.as_deref() == Some(target))
-                        .map(|(id, _)| *id)
-                };
-
-                if let Some(channel_id) = channel_id_to_delete {
-                    if channel_id == 1 {
-                        ConsoleCommandResult::Reply(
+                if let Some(channel_id) = find_channel(channels, parts[1]) {
+                    if channel_id == DEFAULT_CHANNEL_ID {
+                        ConsoleCommandResult::Error(
                             "cannot delete the default channel defined by the voudp protocol"
                                 .into(),
                         )
                     } else if let Some(channel) = channels.remove(&channel_id) {
-                        // Notify users
-                        for remote in channel.remotes.iter() {
-                            if let Ok(remote) = remote.lock() {
-                                // If you need to send messages, you'll need to handle this differently
-                                // Option 1: Return the notifications in ConsoleCommandResult
-                                // Option 2: Pass a callback/sender
-                                log::info!("Would notify {} about channel deletion", remote.addr);
-                            }
+                        plugins.dispatch_channel_delete(channel_id, channel.name.as_deref());
+                        // nobody can listen in on it anymore either
+                        for remote in channels.values().flat_map(|channel| &channel.remotes) {
+                            let mut remote = remote.lock().unwrap();
+                            remote.subscriptions.retain(|&id| id != channel_id);
                         }
+                        // move everyone over to the default channel, telling them so
+                        let moves = channel
+                            .remotes
+                            .iter()
+                            .map(|remote| ConsoleAction::Move {
+                                addr: remote.lock().unwrap().addr,
+                                channel: DEFAULT_CHANNEL_ID,
+                            })
+                            .collect();
 
-                        ConsoleCommandResult::Reply(format!(
-                            "deleted channel '{}' (id {}) and moved users to default",
-                            channel.name.unwrap_or_else(|| "unknown".into()),
-                            channel_id
-                        ))
+                        ConsoleCommandResult::Apply(
+                            format!(
+                                "deleted channel '{}' (id {}) and moved users to default",
+                                channel.name.unwrap_or_else(|| "unknown".into()),
+                                channel_id
+                            ),
+                            moves,
+                        )
                     } else {
-                        ConsoleCommandResult::Reply("channel not found".into())
+                        ConsoleCommandResult::Error("channel not found".into())
                     }
                 } else {
-                    ConsoleCommandResult::Reply("channel not found".into())
+                    ConsoleCommandResult::Error("channel not found".into())
                 }
             }
         }
-        _ => ConsoleCommandResult::Reply(
+        "kick" | "ban" => {
+            let Some(mask) = parts.get(1) else {
+                return ConsoleCommandResult::Error(format!("usage: {cmd} <mask> [reason]"));
+            };
+            let Some((_, addr)) = find_user(channels, mask) else {
+                return ConsoleCommandResult::Error(format!("{mask} is not connected"));
+            };
+            let reason = match parts[2..].join(" ") {
+                reason if reason.is_empty() => "no reason given".to_string(),
+                reason => reason,
+            };
+
+            let (description, action) = if cmd == "ban" {
+                (
+                    format!("banned {mask} ({})", addr.ip()),
+                    ConsoleAction::Ban {
+                        addr,
+                        mask: mask.to_string(),
+                        reason: reason.clone(),
+                    },
+                )
+            } else {
+                (
+                    format!("kicked {mask}"),
+                    ConsoleAction::Kick {
+                        addr,
+                        reason: reason.clone(),
+                    },
+                )
+            };
+            log::info!("console {description}: {reason}");
+            audit.add_note(mask, "console", &format!("{description}: {reason}"));
+            ConsoleCommandResult::Apply(description, vec![action])
+        }
+        "mute" | "unmute" => {
+            let Some(mask) = parts.get(1) else {
+                return ConsoleCommandResult::Error(format!("usage: {cmd} <mask>"));
+            };
+            let Some((_, addr)) = find_user(channels, mask) else {
+                return ConsoleCommandResult::Error(format!("{mask} is not connected"));
+            };
+
+            log::info!("console {cmd}d {mask}");
+            audit.add_note(mask, "console", &format!("{cmd}d"));
+            ConsoleCommandResult::Apply(
+                format!("{cmd}d {mask}"),
+                vec![ConsoleAction::Mute {
+                    addr,
+                    muted: cmd == "mute",
+                }],
+            )
+        }
+        "move" => {
+            let [_, mask, target] = parts else {
+                return ConsoleCommandResult::Error("usage: move <mask> <channel>".into());
+            };
+            let Some((from, addr)) = find_user(channels, mask) else {
+                return ConsoleCommandResult::Error(format!("{mask} is not connected"));
+            };
+            let Some(channel) = find_channel(channels, target) else {
+                return ConsoleCommandResult::Error(format!("channel '{target}' not found"));
+            };
+            if from == channel {
+                return ConsoleCommandResult::Error(format!(
+                    "{mask} is already in channel {channel}"
+                ));
+            }
+
+            log::info!("console moved {mask} to channel {channel}");
+            ConsoleCommandResult::Apply(
+                format!("moved {mask} to channel {channel}"),
+                vec![ConsoleAction::Move { addr, channel }],
+            )
+        }
+        "say" => {
+            if parts.len() < 3 {
+                return ConsoleCommandResult::Error("usage: say <channel> <message>".into());
+            }
+            let Some(channel) = find_channel(channels, parts[1]) else {
+                return ConsoleCommandResult::Error(format!("channel '{}' not found", parts[1]));
+            };
+
+            let text = parts[2..].join(" ");
+            log::info!("[#chan-{channel}] <console> {text}");
+            ConsoleCommandResult::Apply(
+                format!(
+                    "said to {} users in channel {channel}",
+                    channels[&channel].remotes.len()
+                ),
+                vec![ConsoleAction::Say { channel, text }],
+            )
+        }
+        _ => ConsoleCommandResult::Error(
             "unknown command. read the manual on executing remote commands".into(),
         ),
     }
 }
+
+// by id or by name
+fn find_channel(channels: &HashMap<u32, Channel>, target: &str) -> Option<u32> {
+    match target.parse::<u32>() {
+        Ok(id) => channels.contains_key(&id).then_some(id),
+        Err(_) => channels
+            .iter()
+            .find(|(_, channel)| channel.name.as_deref() == Some(target))
+            .map(|(id, _)| *id),
+    }
+}
+
+// the channel `mask` is in and their address
+fn find_user(channels: &HashMap<u32, Channel>, mask: &str) -> Option<(u32, SocketAddr)> {
+    channels
+        .iter()
+        .find_map(|(id, channel)| Some((*id, channel.find_remote(mask)?)))
+}
+
+fn format_lufs(value: f32) -> String {
+    if value.is_finite() {
+        format!("{value:.1}")
+    } else {
+        "-inf".into()
+    }
+}
@@ -0,0 +1,282 @@
+// What the server says to consoles. Every packet is one JSON object on a line
+// of its own, `{"type":..,"time":..,"payload":{..},"rows":[{..},..]}`, so
+// consoles can color, filter and tabulate it instead of guessing from
+// free-form text. Only the shape below is written and understood, with string
+// values throughout, no serde needed for it.
+use std::{
+    fmt,
+    iter::Peekable,
+    str::Chars,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::protocol::ConsoleEvent;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConsoleMessageKind {
+    /// The answer to a command, `payload.text`
+    Reply,
+    /// A command that couldn't run, `payload.text`
+    Error,
+    /// Something that happened, pushed to subscribed consoles
+    Event(ConsoleEvent),
+    /// The answer to a console's keepalive, with an empty payload
+    Keepalive,
+}
+
+impl ConsoleMessageKind {
+    pub fn name(self) -> &'static str {
+        match self {
+            ConsoleMessageKind::Reply => "reply",
+            ConsoleMessageKind::Error => "error",
+            ConsoleMessageKind::Event(event) => event.name(),
+            ConsoleMessageKind::Keepalive => "keepalive",
+        }
+    }
+}
+
+impl fmt::Display for ConsoleMessageKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConsoleMessage {
+    pub kind: ConsoleMessageKind,
+    /// Milliseconds since the unix epoch, when the server sent it
+    pub time: u64,
+    /// In the order they were written
+    pub payload: Vec<(String, String)>,
+    /// Tables, like the one `status` answers with. Left out when empty
+    pub rows: Vec<Vec<(String, String)>>,
+}
+
+impl ConsoleMessage {
+    pub fn new(kind: ConsoleMessageKind, payload: Vec<(String, String)>) -> Self {
+        let time = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |since| since.as_millis() as u64);
+        Self {
+            kind,
+            time,
+            payload,
+            rows: vec![],
+        }
+    }
+
+    pub fn reply(text: impl Into<String>) -> Self {
+        Self::new(
+            ConsoleMessageKind::Reply,
+            vec![("text".into(), text.into())],
+        )
+    }
+
+    pub fn error(text: impl Into<String>) -> Self {
+        Self::new(
+            ConsoleMessageKind::Error,
+            vec![("text".into(), text.into())],
+        )
+    }
+
+    pub fn event(event: ConsoleEvent, payload: &[(&str, String)]) -> Self {
+        let payload = payload
+            .iter()
+            .map(|(key, value)| (key.to_string(), value.clone()))
+            .collect();
+        Self::new(ConsoleMessageKind::Event(event), payload)
+    }
+
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.payload
+            .iter()
+            .find(|(got, _)| got == key)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// `payload.text`, what replies and errors carry
+    pub fn text(&self) -> &str {
+        self.get("text").unwrap_or_default()
+    }
+
+    pub fn to_json(&self) -> String {
+        let mut json = format!(
+            "{{\"type\":{},\"time\":{},\"payload\":{}",
+            quote(self.kind.name()),
+            self.time,
+            object(&self.payload)
+        );
+        if !self.rows.is_empty() {
+            let rows = self.rows.iter().map(|row| object(row)).collect::<Vec<_>>();
+            json.push_str(&format!(",\"rows\":[{}]", rows.join(",")));
+        }
+        json.push('}');
+        json
+    }
+
+    pub fn parse(line: &str) -> Result<Self, String> {
+        let mut chars = line.trim().chars().peekable();
+        let mut kind = None;
+        let mut time = None;
+        let mut payload = None;
+        let mut rows = vec![];
+
+        expect(&mut chars, '{')?;
+        loop {
+            let key = parse_string(&mut chars)?;
+            expect(&mut chars, ':')?;
+            match key.as_str() {
+                "type" => {
+                    let name = parse_string(&mut chars)?;
+                    kind = Some(match name.as_str() {
+                        "reply" => ConsoleMessageKind::Reply,
+                        "error" => ConsoleMessageKind::Error,
+                        "keepalive" => ConsoleMessageKind::Keepalive,
+                        event => ConsoleMessageKind::Event(event.parse()?),
+                    });
+                }
+                "time" => time = Some(parse_number(&mut chars)?),
+                "payload" => payload = Some(parse_payload(&mut chars)?),
+                "rows" => rows = parse_rows(&mut chars)?,
+                other => return Err(format!("unexpected key '{other}'")),
+            }
+            if !next_item(&mut chars, '}')? {
+                break;
+            }
+        }
+        skip_whitespace(&mut chars);
+        if chars.next().is_some() {
+            return Err("trailing characters after the message".into());
+        }
+
+        Ok(Self {
+            kind: kind.ok_or("missing type")?,
+            time: time.ok_or("missing time")?,
+            payload: payload.ok_or("missing payload")?,
+            rows,
+        })
+    }
+}
+
+type Cursor<'a> = Peekable<Chars<'a>>;
+
+fn skip_whitespace(chars: &mut Cursor) {
+    while chars.next_if(|c| c.is_whitespace()).is_some() {}
+}
+
+fn expect(chars: &mut Cursor, want: char) -> Result<(), String> {
+    skip_whitespace(chars);
+    match chars.next() {
+        Some(c) if c == want => Ok(()),
+        Some(c) => Err(format!("expected '{want}', found '{c}'")),
+        None => Err(format!("expected '{want}', found the end")),
+    }
+}
+
+// after an item, `true` if another one follows and `false` at `close`
+fn next_item(chars: &mut Cursor, close: char) -> Result<bool, String> {
+    skip_whitespace(chars);
+    match chars.next() {
+        Some(',') => Ok(true),
+        Some(c) if c == close => Ok(false),
+        Some(c) => Err(format!("expected ',' or '{close}', found '{c}'")),
+        None => Err(format!("expected ',' or '{close}', found the end")),
+    }
+}
+
+fn parse_payload(chars: &mut Cursor) -> Result<Vec<(String, String)>, String> {
+    let mut payload = vec![];
+    expect(chars, '{')?;
+    skip_whitespace(chars);
+    if chars.next_if_eq(&'}').is_some() {
+        return Ok(payload);
+    }
+    loop {
+        let key = parse_string(chars)?;
+        expect(chars, ':')?;
+        payload.push((key, parse_string(chars)?));
+        if !next_item(chars, '}')? {
+            return Ok(payload);
+        }
+    }
+}
+
+fn parse_rows(chars: &mut Cursor) -> Result<Vec<Vec<(String, String)>>, String> {
+    let mut rows = vec![];
+    expect(chars, '[')?;
+    skip_whitespace(chars);
+    if chars.next_if_eq(&']').is_some() {
+        return Ok(rows);
+    }
+    loop {
+        rows.push(parse_payload(chars)?);
+        if !next_item(chars, ']')? {
+            return Ok(rows);
+        }
+    }
+}
+
+fn parse_number(chars: &mut Cursor) -> Result<u64, String> {
+    skip_whitespace(chars);
+    let mut digits = String::new();
+    while let Some(c) = chars.next_if(char::is_ascii_digit) {
+        digits.push(c);
+    }
+    digits
+        .parse()
+        .map_err(|_| format!("'{digits}' isn't a valid time"))
+}
+
+fn parse_string(chars: &mut Cursor) -> Result<String, String> {
+    expect(chars, '"')?;
+    let mut out = String::new();
+
+    loop {
+        match chars.next().ok_or("unterminated string")? {
+            '"' => return Ok(out),
+            '\\' => match chars.next().ok_or("unterminated string")? {
+                'n' => out.push('\n'),
+                't' => out.push('\t'),
+                'r' => out.push('\r'),
+                '"' => out.push('"'),
+                '\\' => out.push('\\'),
+                '/' => out.push('/'),
+                'u' => {
+                    let hex = chars.by_ref().take(4).collect::<String>();
+                    let c = u32::from_str_radix(&hex, 16)
+                        .ok()
+                        .and_then(char::from_u32)
+                        .ok_or_else(|| format!("bad escape \\u{hex}"))?;
+                    out.push(c);
+                }
+                c => return Err(format!("bad escape \\{c}")),
+            },
+            c => out.push(c),
+        }
+    }
+}
+
+fn object(fields: &[(String, String)]) -> String {
+    let fields = fields
+        .iter()
+        .map(|(key, value)| format!("{}:{}", quote(key), quote(value)))
+        .collect::<Vec<_>>();
+    format!("{{{}}}", fields.join(","))
+}
+
+fn quote(value: &str) -> String {
+    let mut out = String::from('"');
+    for c in value.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\t' => out.push_str("\\t"),
+            '\r' => out.push_str("\\r"),
+            c if c.is_control() => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
@@ -0,0 +1,359 @@
+// Admin consoles over TCP with TLS, for networks that mangle long-lived UDP.
+// A connection carries the same console packets as the UDP path, each prefixed
+// with its length as a big-endian u32: the same challenge-response login, the
+// same commands and the same JSON answers, with TLS in place of the phrase.
+use std::{
+    hash::{Hash, Hasher},
+    io::{self, Read, Write},
+    net::{SocketAddr, TcpListener, TcpStream},
+    path::{Path, PathBuf},
+    sync::{
+        Arc,
+        mpsc::{self, Receiver, Sender},
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use log::{info, warn};
+use rustls::{
+    ClientConfig, ClientConnection, RootCertStore, ServerConfig, ServerConnection, StreamOwned,
+    pki_types::{CertificateDer, PrivateKeyDer, ServerName, pem::PemObject},
+};
+
+use crate::{
+    console_msg::{ConsoleMessage, ConsoleMessageKind},
+    protocol::{self, ClientPacketType, ConsoleAuth, ConsoleRole},
+};
+
+// far bigger than any console packet, past it the peer is broken
+const MAX_FRAME: usize = 64 * 1024;
+// how long connection threads wait on their peer before writing replies
+const POLL: Duration = Duration::from_millis(20);
+// consoles send keepalives, a connection this quiet is dead
+const IDLE_TIMEOUT: Duration = Duration::from_secs(60);
+// for the client's handshake and the answers it waits on
+const REPLY_TIMEOUT: Duration = Duration::from_secs(3);
+
+#[derive(Debug, Clone)]
+pub struct TlsConsoleConfig {
+    /// Where to listen, port 0 picks a free one
+    pub bind: SocketAddr,
+    /// PEM certificate chain, the server's own first
+    pub cert_chain: PathBuf,
+    /// PEM private key of the first certificate
+    pub key: PathBuf,
+}
+
+/// One connection, as the server's console code sees it
+#[derive(Debug, Clone)]
+pub struct TlsLink {
+    id: u64,
+    pub peer: SocketAddr,
+    outgoing: Sender<Vec<u8>>,
+}
+
+impl TlsLink {
+    /// Queues `packet` for the connection's thread to write
+    pub fn send(&self, packet: Vec<u8>) -> io::Result<()> {
+        self.outgoing
+            .send(packet)
+            .map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, "the connection is closed"))
+    }
+}
+
+impl PartialEq for TlsLink {
+    fn eq(&self, other: &Self) -> bool {
+        self.id == other.id
+    }
+}
+
+impl Eq for TlsLink {}
+
+impl Hash for TlsLink {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.id.hash(state);
+    }
+}
+
+/// What connection threads hand to the server loop
+pub enum TlsConsoleEvent {
+    Packet(TlsLink, Vec<u8>),
+    Closed(TlsLink),
+}
+
+/// Binds the listener and accepts connections in the background. Returns where
+/// it listens and the events of every connection
+pub fn listen(config: &TlsConsoleConfig) -> io::Result<(SocketAddr, Receiver<TlsConsoleEvent>)> {
+    let certs = CertificateDer::pem_file_iter(&config.cert_chain)
+        .and_then(|certs| certs.collect::<Result<Vec<_>, _>>())
+        .map_err(|e| pem_error(&config.cert_chain, e))?;
+    let key = PrivateKeyDer::from_pem_file(&config.key).map_err(|e| pem_error(&config.key, e))?;
+    let tls = ServerConfig::builder()
+        .with_no_client_auth()
+        .with_single_cert(certs, key)
+        .map_err(io::Error::other)?;
+    let tls = Arc::new(tls);
+
+    let listener = TcpListener::bind(config.bind)?;
+    let local = listener.local_addr()?;
+    info!("Listening for TLS consoles on {local}");
+
+    let (events, rx) = mpsc::channel();
+    thread::spawn(move || {
+        for (id, stream) in (1..).zip(listener.incoming()) {
+            match stream {
+                Ok(stream) => {
+                    let (tls, events) = (tls.clone(), events.clone());
+                    thread::spawn(move || serve(stream, id, tls, events));
+                }
+                Err(e) => warn!("Could not accept a TLS console: {e}"),
+            }
+        }
+    });
+
+    Ok((local, rx))
+}
+
+fn serve(stream: TcpStream, id: u64, tls: Arc<ServerConfig>, events: Sender<TlsConsoleEvent>) {
+    let Ok(peer) = stream.peer_addr() else {
+        return;
+    };
+    let conn = match ServerConnection::new(tls) {
+        Ok(conn) => conn,
+        Err(e) => {
+            warn!("Could not start TLS with {peer}: {e}");
+            return;
+        }
+    };
+    if let Err(e) = stream.set_read_timeout(Some(POLL)) {
+        warn!("Could not set up the connection with {peer}: {e}");
+        return;
+    }
+
+    let (outgoing, replies) = mpsc::channel();
+    let link = TlsLink { id, peer, outgoing };
+    let mut stream = StreamOwned::new(conn, stream);
+    match pump(&mut stream, &link, &replies, &events) {
+        Ok(()) => info!("TLS console connection from {peer} closed"),
+        Err(e) => info!("TLS console connection from {peer} dropped: {e}"),
+    }
+    let _ = events.send(TlsConsoleEvent::Closed(link));
+}
+
+// moves packets both ways until either end is done
+fn pump(
+    stream: &mut StreamOwned<ServerConnection, TcpStream>,
+    link: &TlsLink,
+    replies: &Receiver<Vec<u8>>,
+    events: &Sender<TlsConsoleEvent>,
+) -> io::Result<()> {
+    let mut frames = Frames::default();
+    let mut last_heard = Instant::now();
+
+    loop {
+        if frames.fill(stream)? {
+            last_heard = Instant::now();
+        }
+        while let Some(packet) = frames.next()? {
+            // the server is gone
+            if events
+                .send(TlsConsoleEvent::Packet(link.clone(), packet))
+                .is_err()
+            {
+                return Ok(());
+            }
+        }
+
+        while let Ok(packet) = replies.try_recv() {
+            write_frame(stream, &packet)?;
+        }
+
+        if last_heard.elapsed() > IDLE_TIMEOUT {
+            return Err(io::Error::new(
+                io::ErrorKind::TimedOut,
+                "nothing heard for too long",
+            ));
+        }
+    }
+}
+
+fn pem_error(path: &Path, e: impl std::fmt::Display) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidInput,
+        format!("{}: {e}", path.display()),
+    )
+}
+
+// length-prefixed packets out of a byte stream
+#[derive(Default)]
+struct Frames {
+    pending: Vec<u8>,
+}
+
+impl Frames {
+    // `false` when nothing came before the read timeout
+    fn fill(&mut self, stream: &mut impl Read) -> io::Result<bool> {
+        let mut buf = [0u8; 4096];
+        match stream.read(&mut buf) {
+            Ok(0) => Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "the other end hung up",
+            )),
+            Ok(size) => {
+                self.pending.extend_from_slice(&buf[..size]);
+                Ok(true)
+            }
+            Err(e)
+                if matches!(
+                    e.kind(),
+                    io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+                ) =>
+            {
+                Ok(false)
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    fn next(&mut self) -> io::Result<Option<Vec<u8>>> {
+        let Some(&[a, b, c, d]) = self.pending.get(..4) else {
+            return Ok(None);
+        };
+        let len = u32::from_be_bytes([a, b, c, d]) as usize;
+        if len > MAX_FRAME {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("a {len} byte packet is too big"),
+            ));
+        }
+        if self.pending.len() < 4 + len {
+            return Ok(None);
+        }
+
+        let packet = self.pending[4..4 + len].to_vec();
+        self.pending.drain(..4 + len);
+        Ok(Some(packet))
+    }
+}
+
+fn write_frame(stream: &mut impl Write, packet: &[u8]) -> io::Result<()> {
+    let mut frame = (packet.len() as u32).to_be_bytes().to_vec();
+    frame.extend_from_slice(packet);
+    stream.write_all(&frame)?;
+    stream.flush()
+}
+
+/// The console end of a connection, for tools that can't count on UDP
+pub struct TlsConsoleClient {
+    stream: StreamOwned<ClientConnection, TcpStream>,
+    frames: Frames,
+}
+
+impl TlsConsoleClient {
+    /// Connects to `addr`, trusting the certificates in `ca_file` to vouch for
+    /// `server_name`
+    pub fn connect(addr: SocketAddr, server_name: &str, ca_file: &Path) -> io::Result<Self> {
+        let mut roots = RootCertStore::empty();
+        for cert in CertificateDer::pem_file_iter(ca_file).map_err(|e| pem_error(ca_file, e))? {
+            let cert = cert.map_err(|e| pem_error(ca_file, e))?;
+            roots.add(cert).map_err(io::Error::other)?;
+        }
+        let tls = ClientConfig::builder()
+            .with_root_certificates(roots)
+            .with_no_client_auth();
+        let name = ServerName::try_from(server_name.to_string())
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+        let mut conn = ClientConnection::new(Arc::new(tls), name).map_err(io::Error::other)?;
+
+        let mut stream = TcpStream::connect_timeout(&addr, REPLY_TIMEOUT)?;
+        stream.set_read_timeout(Some(REPLY_TIMEOUT))?;
+        while conn.is_handshaking() {
+            conn.complete_io(&mut stream)?;
+        }
+        stream.set_read_timeout(Some(POLL))?;
+
+        Ok(Self {
+            stream: StreamOwned::new(conn, stream),
+            frames: Frames::default(),
+        })
+    }
+
+    pub fn send(&mut self, packet: &[u8]) -> io::Result<()> {
+        write_frame(&mut self.stream, packet)
+    }
+
+    /// The next packet from the server, `None` if nothing came before `deadline`
+    pub fn recv(&mut self, deadline: Instant) -> io::Result<Option<Vec<u8>>> {
+        loop {
+            if let Some(packet) = self.frames.next()? {
+                return Ok(Some(packet));
+            }
+            if Instant::now() >= deadline {
+                return Ok(None);
+            }
+            self.frames.fill(&mut self.stream)?;
+        }
+    }
+
+    /// The same login as `util::console_login`, returning the session token
+    pub fn login(&mut self, password: &str, role: ConsoleRole) -> io::Result<Vec<u8>> {
+        self.send(&protocol::create_console_auth(
+            ConsoleAuth::Hello,
+            &[role as u8],
+        ))?;
+        let nonce = self.answer(ConsoleAuth::Challenge)?;
+        let proof = protocol::console_proof(password, &nonce);
+        self.send(&protocol::create_console_auth(ConsoleAuth::Proof, &proof))?;
+        self.answer(ConsoleAuth::Session)
+    }
+
+    /// Runs `command` and waits for its reply or error, skipping events
+    pub fn command(&mut self, token: &[u8], command: &str) -> io::Result<ConsoleMessage> {
+        self.send(&protocol::create_console_command(token, command))?;
+
+        let deadline = Instant::now() + REPLY_TIMEOUT;
+        while let Some(packet) = self.recv(deadline)? {
+            let Ok(msg) = ConsoleMessage::parse(&String::from_utf8_lossy(&packet)) else {
+                continue;
+            };
+            if matches!(
+                msg.kind,
+                ConsoleMessageKind::Reply | ConsoleMessageKind::Error
+            ) {
+                return Ok(msg);
+            }
+        }
+        Err(io::Error::new(
+            io::ErrorKind::TimedOut,
+            format!("the server didn't answer '{command}'"),
+        ))
+    }
+
+    // the payload of the login step we wait on
+    fn answer(&mut self, expected: ConsoleAuth) -> io::Result<Vec<u8>> {
+        let deadline = Instant::now() + REPLY_TIMEOUT;
+        while let Some(packet) = self.recv(deadline)? {
+            let [kind, step, payload @ ..] = &packet[..] else {
+                continue;
+            };
+            if *kind != ClientPacketType::RegisterConsole as u8 {
+                continue;
+            }
+            match ConsoleAuth::try_from(*step) {
+                Ok(step) if step == expected => return Ok(payload.to_vec()),
+                Ok(ConsoleAuth::Denied) => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::PermissionDenied,
+                        "the server refused the console password",
+                    ));
+                }
+                _ => {}
+            }
+        }
+        Err(io::Error::new(
+            io::ErrorKind::TimedOut,
+            "the server didn't answer the console login",
+        ))
+    }
+}
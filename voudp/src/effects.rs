@@ -0,0 +1,286 @@
+// Per-remote voice effects. Every remote can pick a short chain of effects
+// which the channel runs over their decoded audio before it goes into the mix.
+use std::{f32::consts::PI, fmt, str::FromStr};
+
+/// Longest chain a remote can ask for, effects aren't free
+pub const MAX_EFFECTS: usize = 4;
+
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EffectKind {
+    PitchShift = 0x01,
+    Reverb = 0x02,
+    Robot = 0x03,
+}
+
+impl EffectKind {
+    pub fn name(self) -> &'static str {
+        match self {
+            EffectKind::PitchShift => "pitch",
+            EffectKind::Reverb => "reverb",
+            EffectKind::Robot => "robot",
+        }
+    }
+
+    pub fn build(self, sample_rate: u32) -> Box<dyn Effect> {
+        match self {
+            // about seven semitones up
+            EffectKind::PitchShift => Box::new(PitchShift::new(1.5, sample_rate)),
+            EffectKind::Reverb => Box::new(Reverb::new(0.3, sample_rate)),
+            EffectKind::Robot => Box::new(Robot::new(60.0, sample_rate)),
+        }
+    }
+}
+
+impl TryFrom<u8> for EffectKind {
+    type Error = u8;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0x01 => Ok(EffectKind::PitchShift),
+            0x02 => Ok(EffectKind::Reverb),
+            0x03 => Ok(EffectKind::Robot),
+            _ => Err(value),
+        }
+    }
+}
+
+impl FromStr for EffectKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "pitch" => Ok(EffectKind::PitchShift),
+            "reverb" => Ok(EffectKind::Reverb),
+            "robot" => Ok(EffectKind::Robot),
+            _ => Err(format!("unknown effect '{s}' (pitch, reverb, robot)")),
+        }
+    }
+}
+
+impl fmt::Display for EffectKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+pub trait Effect: Send {
+    /// Processes one interleaved stereo buffer in place
+    fn process(&mut self, buf: &mut [f32]);
+}
+
+/// Effects run in order, each one keeping its own state between frames
+pub struct EffectChain {
+    kinds: Vec<EffectKind>,
+    effects: Vec<Box<dyn Effect>>,
+}
+
+impl EffectChain {
+    pub fn new(kinds: &[EffectKind], sample_rate: u32) -> Self {
+        let kinds = kinds.iter().copied().take(MAX_EFFECTS).collect::<Vec<_>>();
+        Self {
+            effects: kinds.iter().map(|kind| kind.build(sample_rate)).collect(),
+            kinds,
+        }
+    }
+
+    pub fn kinds(&self) -> &[EffectKind] {
+        &self.kinds
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.effects.is_empty()
+    }
+
+    pub fn process(&mut self, buf: &mut [f32]) {
+        for effect in &mut self.effects {
+            effect.process(buf);
+        }
+    }
+}
+
+/// Delay line pitch shifter: two read heads sweep through a short window at
+/// `ratio` speed and are crossfaded so neither one is heard as it wraps around
+pub struct PitchShift {
+    ratio: f32,
+    window: f32,
+    lines: [Vec<f32>; 2],
+    write: usize,
+    // distance of the first head behind the write position, in samples
+    delay: f32,
+}
+
+impl PitchShift {
+    pub fn new(ratio: f32, sample_rate: u32) -> Self {
+        // 40ms grains, long enough for speech without sounding too echoey
+        let window = (sample_rate as f32 * 0.04).max(2.0);
+        let len = window as usize + 2;
+        Self {
+            ratio,
+            window,
+            lines: [vec![0.0; len], vec![0.0; len]],
+            write: 0,
+            delay: 0.0,
+        }
+    }
+
+    fn read(line: &[f32], write: usize, delay: f32) -> f32 {
+        let len = line.len() as f32;
+        let pos = (write as f32 - delay).rem_euclid(len);
+        let i = pos as usize % line.len();
+        let next = (i + 1) % line.len();
+        let frac = pos.fract();
+        line[i] * (1.0 - frac) + line[next] * frac
+    }
+}
+
+impl Effect for PitchShift {
+    fn process(&mut self, buf: &mut [f32]) {
+        let half = self.window / 2.0;
+
+        for frame in buf.chunks_exact_mut(2) {
+            let first = self.delay;
+            let second = (self.delay + half) % self.window;
+            // triangular windows, the two always add up to one
+            let first_gain = 1.0 - (2.0 * first / self.window - 1.0).abs();
+            let second_gain = 1.0 - first_gain;
+
+            for (channel, sample) in frame.iter_mut().enumerate() {
+                let line = &mut self.lines[channel];
+                line[self.write] = *sample;
+                *sample = Self::read(line, self.write, first) * first_gain
+                    + Self::read(line, self.write, second) * second_gain;
+            }
+
+            self.write = (self.write + 1) % self.lines[0].len();
+            self.delay = (self.delay + 1.0 - self.ratio).rem_euclid(self.window);
+        }
+    }
+}
+
+struct Comb {
+    buf: Vec<f32>,
+    pos: usize,
+    feedback: f32,
+    damp: f32,
+    last: f32,
+}
+
+impl Comb {
+    fn run(&mut self, x: f32) -> f32 {
+        let y = self.buf[self.pos];
+        self.last = y * (1.0 - self.damp) + self.last * self.damp;
+        self.buf[self.pos] = x + self.last * self.feedback;
+        self.pos = (self.pos + 1) % self.buf.len();
+        y
+    }
+}
+
+struct AllPass {
+    buf: Vec<f32>,
+    pos: usize,
+}
+
+impl AllPass {
+    fn run(&mut self, x: f32) -> f32 {
+        let delayed = self.buf[self.pos];
+        self.buf[self.pos] = x + delayed * 0.5;
+        self.pos = (self.pos + 1) % self.buf.len();
+        delayed - x
+    }
+}
+
+/// Schroeder/Freeverb style room: parallel damped combs into series all-passes,
+/// with the right channel's delays offset a little for width
+pub struct Reverb {
+    wet: f32,
+    combs: [Vec<Comb>; 2],
+    allpasses: [Vec<AllPass>; 2],
+}
+
+impl Reverb {
+    // Freeverb's tunings, in samples at 44.1 kHz
+    const COMBS: [usize; 4] = [1116, 1188, 1277, 1356];
+    const ALLPASSES: [usize; 2] = [556, 441];
+    const SPREAD: usize = 23;
+
+    pub fn new(wet: f32, sample_rate: u32) -> Self {
+        let scale = |len: usize| (len * sample_rate as usize / 44100).max(1);
+
+        let combs = [0, Self::SPREAD].map(|spread| {
+            Self::COMBS
+                .iter()
+                .map(|&len| Comb {
+                    buf: vec![0.0; scale(len + spread)],
+                    pos: 0,
+                    feedback: 0.84,
+                    damp: 0.2,
+                    last: 0.0,
+                })
+                .collect()
+        });
+        let allpasses = [0, Self::SPREAD].map(|spread| {
+            Self::ALLPASSES
+                .iter()
+                .map(|&len| AllPass {
+                    buf: vec![0.0; scale(len + spread)],
+                    pos: 0,
+                })
+                .collect()
+        });
+
+        Self {
+            wet,
+            combs,
+            allpasses,
+        }
+    }
+}
+
+impl Effect for Reverb {
+    fn process(&mut self, buf: &mut [f32]) {
+        let comb_gain = 1.0 / Self::COMBS.len() as f32;
+
+        for frame in buf.chunks_exact_mut(2) {
+            for (channel, sample) in frame.iter_mut().enumerate() {
+                let x = *sample;
+                let mut y = self.combs[channel]
+                    .iter_mut()
+                    .map(|comb| comb.run(x))
+                    .sum::<f32>()
+                    * comb_gain;
+                for allpass in &mut self.allpasses[channel] {
+                    y = allpass.run(y);
+                }
+
+                *sample = x * (1.0 - self.wet) + y * self.wet;
+            }
+        }
+    }
+}
+
+/// Ring modulation with a low sine, the classic sci-fi robot
+pub struct Robot {
+    step: f32,
+    phase: f32,
+}
+
+impl Robot {
+    pub fn new(carrier_hz: f32, sample_rate: u32) -> Self {
+        Self {
+            step: 2.0 * PI * carrier_hz / sample_rate as f32,
+            phase: 0.0,
+        }
+    }
+}
+
+impl Effect for Robot {
+    fn process(&mut self, buf: &mut [f32]) {
+        for frame in buf.chunks_exact_mut(2) {
+            let carrier = self.phase.sin();
+            frame[0] *= carrier;
+            frame[1] *= carrier;
+            self.phase = (self.phase + self.step) % (2.0 * PI);
+        }
+    }
+}
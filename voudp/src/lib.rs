@@ -1,10 +1,31 @@
+pub mod aec;
+#[cfg(feature = "async")]
+mod async_client;
+pub mod audit;
+pub mod away;
+pub mod bans;
 pub mod client;
 pub mod commands;
 pub mod console_cmd;
+pub mod console_msg;
+#[cfg(feature = "tls-console")]
+pub mod console_tls;
+pub mod effects;
+pub mod loudness;
 pub mod mixer;
 pub mod music;
+pub mod native_plugin;
 pub mod plugin;
+pub mod prefs;
+pub mod probe;
 pub mod protocol;
+pub mod recorder;
+pub mod resample;
 pub mod server;
 pub mod socket;
+pub mod soundboard;
+pub mod storage;
+#[cfg(feature = "telemetry")]
+pub mod telemetry;
 pub mod util;
+pub mod vad;
@@ -0,0 +1,288 @@
+// ITU-R BS.1770 style loudness metering (K-weighting + mean square over a
+// sliding window). Good enough to compare talkers and spot who is hot in the
+// mix, not a certified meter: there is no gating.
+use std::collections::VecDeque;
+
+use crate::util::AtomicF32;
+
+const MOMENTARY_MILLIS: u32 = 400;
+const SHORT_TERM_MILLIS: u32 = 3000;
+// how much of the held peak is left after each block, a VU style fall off
+const PEAK_FALLOFF: f32 = 0.9;
+
+#[derive(Debug, Clone, Copy)]
+struct Biquad {
+    b0: f64,
+    b1: f64,
+    b2: f64,
+    a1: f64,
+    a2: f64,
+}
+
+impl Biquad {
+    fn run(&self, x: f64, state: &mut (f64, f64, f64, f64)) -> f64 {
+        let (x1, x2, y1, y2) = *state;
+        let y = self.b0 * x + self.b1 * x1 + self.b2 * x2 - self.a1 * y1 - self.a2 * y2;
+        *state = (x, x1, y, y1);
+        y
+    }
+}
+
+// the two K-weighting stages from BS.1770, derived for any sample rate
+fn k_weighting(sample_rate: u32) -> (Biquad, Biquad) {
+    let fs = sample_rate as f64;
+
+    // stage 1: high shelf modelling the head
+    let (f0, gain_db, q) = (1681.974450955533, 3.999843853973347, 0.7071752369554196);
+    let k = (std::f64::consts::PI * f0 / fs).tan();
+    let vh = 10f64.powf(gain_db / 20.0);
+    let vb = vh.powf(0.4996667741545416);
+    let a0 = 1.0 + k / q + k * k;
+    let shelf = Biquad {
+        b0: (vh + vb * k / q + k * k) / a0,
+        b1: 2.0 * (k * k - vh) / a0,
+        b2: (vh - vb * k / q + k * k) / a0,
+        a1: 2.0 * (k * k - 1.0) / a0,
+        a2: (1.0 - k / q + k * k) / a0,
+    };
+
+    // stage 2: RLB high-pass
+    let (f0, q) = (38.13547087602444, 0.5003270373238773);
+    let k = (std::f64::consts::PI * f0 / fs).tan();
+    let a0 = 1.0 + k / q + k * k;
+    let highpass = Biquad {
+        b0: 1.0,
+        b1: -2.0,
+        b2: 1.0,
+        a1: 2.0 * (k * k - 1.0) / a0,
+        a2: (1.0 - k / q + k * k) / a0,
+    };
+
+    (shelf, highpass)
+}
+
+#[derive(Debug, Clone)]
+pub struct LoudnessMeter {
+    shelf: Biquad,
+    highpass: Biquad,
+    // filter state per (stage, channel)
+    state: [[(f64, f64, f64, f64); 2]; 2],
+    sample_rate: u32,
+    // (mean square, sample count, peak) per processed buffer, newest last
+    blocks: VecDeque<(f64, usize, f32)>,
+    window_samples: usize,
+}
+
+impl LoudnessMeter {
+    pub fn new(sample_rate: u32) -> Self {
+        let (shelf, highpass) = k_weighting(sample_rate);
+        Self {
+            shelf,
+            highpass,
+            state: Default::default(),
+            sample_rate,
+            blocks: VecDeque::new(),
+            window_samples: 0,
+        }
+    }
+
+    /// Feeds one interleaved stereo buffer into the meter
+    pub fn process(&mut self, buf: &[f32]) {
+        let mut sum = 0.0;
+        let mut peak = 0.0f32;
+
+        for frame in buf.chunks_exact(2) {
+            for (channel, &sample) in frame.iter().enumerate() {
+                peak = peak.max(sample.abs());
+                let shelved = self.shelf.run(sample as f64, &mut self.state[0][channel]);
+                let weighted = self.highpass.run(shelved, &mut self.state[1][channel]);
+                sum += weighted * weighted;
+            }
+        }
+
+        // channel weights are 1.0 for left and right, so the per-channel mean
+        // squares just add up
+        let frames = buf.len() / 2;
+        let mean_square = if frames == 0 {
+            0.0
+        } else {
+            sum / frames as f64
+        };
+        self.blocks.push_back((mean_square, frames, peak));
+        self.window_samples += frames;
+
+        let max_samples = (self.sample_rate * SHORT_TERM_MILLIS / 1000) as usize;
+        while self.window_samples > max_samples
+            && let Some((_, frames, _)) = self.blocks.pop_front()
+        {
+            self.window_samples -= frames;
+        }
+    }
+
+    /// Loudness over the last 400ms in LUFS
+    pub fn momentary(&self) -> f32 {
+        self.loudness(MOMENTARY_MILLIS)
+    }
+
+    /// Loudness over the last 3s in LUFS
+    pub fn short_term(&self) -> f32 {
+        self.loudness(SHORT_TERM_MILLIS)
+    }
+
+    /// Highest sample over the last 3s, in dBFS
+    pub fn peak_dbfs(&self) -> f32 {
+        let peak = self.blocks.iter().fold(0.0f32, |max, b| max.max(b.2));
+        20.0 * peak.log10()
+    }
+
+    fn loudness(&self, millis: u32) -> f32 {
+        let wanted = (self.sample_rate * millis / 1000) as usize;
+        let mut energy = 0.0;
+        let mut samples = 0;
+
+        for &(mean_square, frames, _) in self.blocks.iter().rev() {
+            if samples >= wanted {
+                break;
+            }
+            energy += mean_square * frames as f64;
+            samples += frames;
+        }
+
+        if samples == 0 || energy <= 0.0 {
+            return f32::NEG_INFINITY;
+        }
+
+        (-0.691 + 10.0 * (energy / samples as f64).log10()) as f32
+    }
+}
+
+// how far the normalizer may push a track either way, in dB
+const MAX_NORMALIZE_DB: f32 = 12.0;
+// below this a track is between songs, not worth chasing
+const NORMALIZE_FLOOR_LUFS: f32 = -50.0;
+// blocks this far under the track so far are fades and quiet intros, like
+// the relative gate in R128
+const NORMALIZE_RELATIVE_GATE: f32 = 10.0;
+// share of the way to the wanted gain covered each buffer
+const NORMALIZE_SMOOTHING: f32 = 0.05;
+
+/// Streaming stand-in for a replay gain scan: estimates a track's gated
+/// loudness as it plays and steers a gain to bring it to a target.
+pub struct Normalizer {
+    meter: LoudnessMeter,
+    sample_rate: u32,
+    target: f32,
+    gain: f32,
+    // momentary energy summed over the blocks that passed the gates
+    energy: f64,
+    blocks: u32,
+}
+
+impl Normalizer {
+    pub fn new(sample_rate: u32, target_lufs: f32) -> Self {
+        Self {
+            meter: LoudnessMeter::new(sample_rate),
+            sample_rate,
+            target: target_lufs,
+            gain: 1.0,
+            energy: 0.0,
+            blocks: 0,
+        }
+    }
+
+    /// Forgets how loud the last track was, the gain carries over until the
+    /// new one has been heard for a bit
+    pub fn reset(&mut self) {
+        self.meter = LoudnessMeter::new(self.sample_rate);
+        self.energy = 0.0;
+        self.blocks = 0;
+    }
+
+    /// Measures one interleaved stereo buffer and applies the gain to it
+    pub fn process(&mut self, buf: &mut [f32]) {
+        self.meter.process(buf);
+        let momentary = self.meter.momentary();
+        if momentary > NORMALIZE_FLOOR_LUFS
+            && (self.blocks == 0 || momentary > self.loudness() - NORMALIZE_RELATIVE_GATE)
+        {
+            self.energy += 10f64.powf((momentary as f64 + 0.691) / 10.0);
+            self.blocks += 1;
+        }
+
+        if self.blocks > 0 {
+            let wanted_db =
+                (self.target - self.loudness()).clamp(-MAX_NORMALIZE_DB, MAX_NORMALIZE_DB);
+            let wanted = 10f32.powf(wanted_db / 20.0);
+            self.gain += (wanted - self.gain) * NORMALIZE_SMOOTHING;
+        }
+
+        for sample in buf {
+            *sample = (*sample * self.gain).clamp(-1.0, 1.0);
+        }
+    }
+
+    /// The linear gain applied to the last buffer
+    pub fn gain(&self) -> f32 {
+        self.gain
+    }
+
+    /// Gated loudness of the track so far in LUFS
+    pub fn loudness(&self) -> f32 {
+        if self.blocks == 0 {
+            return f32::NEG_INFINITY;
+        }
+        (-0.691 + 10.0 * (self.energy / self.blocks as f64).log10()) as f32
+    }
+}
+
+/// Lock free RMS and held peak of the latest audio block. Meant to be fed
+/// from an audio callback and read from a UI thread.
+pub struct LevelMeter {
+    rms: AtomicF32,
+    peak: AtomicF32,
+}
+
+impl Default for LevelMeter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl LevelMeter {
+    pub fn new() -> Self {
+        Self {
+            rms: AtomicF32::new(0.0),
+            peak: AtomicF32::new(0.0),
+        }
+    }
+
+    pub fn process(&self, buf: &[f32]) {
+        if buf.is_empty() {
+            return;
+        }
+
+        let sum = buf.iter().map(|s| s * s).sum::<f32>();
+        let peak = buf.iter().fold(0.0f32, |max, s| max.max(s.abs()));
+
+        self.rms.store((sum / buf.len() as f32).sqrt());
+        self.peak.store(peak.max(self.peak.load() * PEAK_FALLOFF));
+    }
+
+    /// Linear RMS of the latest block, 0 to 1
+    pub fn rms(&self) -> f32 {
+        self.rms.load()
+    }
+
+    /// Linear peak, held and slowly falling off, 0 to 1
+    pub fn peak(&self) -> f32 {
+        self.peak.load()
+    }
+
+    pub fn rms_dbfs(&self) -> f32 {
+        20.0 * self.rms().log10()
+    }
+
+    pub fn peak_dbfs(&self) -> f32 {
+        20.0 * self.peak().log10()
+    }
+}
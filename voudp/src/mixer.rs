@@ -1,5 +1,3 @@
-const SILENCE_THRESHOLD: f32 = 0.001; // silence threshold
-
 pub fn normalize(buf: &mut [f32]) {
     let max = buf.iter().fold(0.0, |max, &s| f32::max(max, s.abs()));
 
@@ -11,25 +9,106 @@ pub fn normalize(buf: &mut [f32]) {
     }
 }
 
+// Work on fixed-size lanes so the compiler can keep the hot loops in vector
+// registers. 8 f32s covers AVX, and is two iterations of SSE/NEON.
+const LANES: usize = 8;
+
+/// `mix += buf * gain`
+pub fn accumulate(mix: &mut [f32], buf: &[f32], gain: f32) {
+    let mut out = mix.chunks_exact_mut(LANES);
+    let mut input = buf.chunks_exact(LANES);
+
+    for (out, input) in (&mut out).zip(&mut input) {
+        let out: &mut [f32; LANES] = out.try_into().unwrap();
+        let input: &[f32; LANES] = input.try_into().unwrap();
+        for lane in 0..LANES {
+            out[lane] += input[lane] * gain;
+        }
+    }
+
+    for (out, input) in out.into_remainder().iter_mut().zip(input.remainder()) {
+        *out += input * gain;
+    }
+}
+
+/// `out = (total - own) * gain`, the mix of everyone in `total` except `own`
+pub fn mix_excluding(out: &mut [f32], total: &[f32], own: Option<&[f32]>, gain: f32) {
+    out.copy_from_slice(total);
+    if let Some(own) = own {
+        accumulate(out, own, -1.0);
+    }
+
+    scale(out, gain);
+}
+
+pub fn scale(buf: &mut [f32], gain: f32) {
+    let mut chunks = buf.chunks_exact_mut(LANES);
+    for chunk in &mut chunks {
+        let chunk: &mut [f32; LANES] = chunk.try_into().unwrap();
+        for sample in chunk.iter_mut() {
+            *sample *= gain;
+        }
+    }
+
+    for sample in chunks.into_remainder() {
+        *sample *= gain;
+    }
+}
+
+/// Linearly ramps the gain of an interleaved stereo buffer from `from` to `to`
+/// over the first `frames` frames, holding `to` for the rest of the buffer.
+pub fn apply_ramp(buf: &mut [f32], from: f32, to: f32, frames: usize) {
+    let frames = frames.max(1);
+    for (i, frame) in buf.chunks_exact_mut(2).enumerate() {
+        let gain = if i < frames {
+            from + (to - from) * (i + 1) as f32 / frames as f32
+        } else {
+            to
+        };
+        frame[0] *= gain;
+        frame[1] *= gain;
+    }
+}
+
+pub fn hard_clip(buf: &mut [f32]) {
+    let mut chunks = buf.chunks_exact_mut(LANES);
+    for chunk in &mut chunks {
+        let chunk: &mut [f32; LANES] = chunk.try_into().unwrap();
+        for sample in chunk.iter_mut() {
+            *sample = sample.clamp(-1.0, 1.0);
+        }
+    }
+
+    for sample in chunks.into_remainder() {
+        *sample = sample.clamp(-1.0, 1.0);
+    }
+}
+
 pub fn soft_clip(buf: &mut [f32]) {
     for sample in buf {
         *sample = sample.tanh(); // thanks deepseek. the range of tanh is -1 to +1. this will do the soft clipping for us
     }
 }
 
-pub fn remove_dc_bias(buf: &mut [f32], prev: &mut (f32, f32)) {
-    const ALPHA: f32 = 0.995; // DC removal coefficient
+/// Per-remote state of the DC blocker: last input and output sample for the
+/// left and right channels.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct DcState {
+    last_in: (f32, f32),
+    last_out: (f32, f32),
+}
 
+pub fn remove_dc_bias(buf: &mut [f32], prev: &mut DcState, alpha: f32) {
     for i in (0..buf.len()).step_by(2) {
         let left = buf[i];
         let right = buf[i + 1];
 
-        // Apply DC removal filter (high-pass)
-        let new_left = left - prev.0 + ALPHA * prev.0;
-        let new_right = right - prev.1 + ALPHA * prev.1;
+        // Apply DC removal filter (high-pass): y[n] = x[n] - x[n-1] + alpha * y[n-1]
+        let new_left = left - prev.last_in.0 + alpha * prev.last_out.0;
+        let new_right = right - prev.last_in.1 + alpha * prev.last_out.1;
 
-        prev.0 = new_left;
-        prev.1 = new_right;
+        prev.last_in = (left, right);
+        prev.last_out = (new_left, new_right);
 
         buf[i] = new_left;
         buf[i + 1] = new_right;
@@ -52,6 +131,52 @@ pub fn remove_dc_bias(buf: &mut [f32], prev: &mut (f32, f32)) {
 //     }
 // }
 
+/// Second order Butterworth high-pass (RBJ biquad) over an interleaved stereo
+/// buffer. Meant for cutting rumble and handling noise below ~80-120 Hz.
+#[derive(Debug, Clone)]
+pub struct HighPass {
+    b0: f32,
+    b1: f32,
+    b2: f32,
+    a1: f32,
+    a2: f32,
+    // (x1, x2, y1, y2) for left and right
+    state: [(f32, f32, f32, f32); 2],
+}
+
+impl HighPass {
+    pub fn new(cutoff_hz: f32, sample_rate: u32) -> Self {
+        let w0 = 2.0 * std::f32::consts::PI * cutoff_hz / sample_rate as f32;
+        let (sin, cos) = w0.sin_cos();
+        let alpha = sin / (2.0 * std::f32::consts::FRAC_1_SQRT_2); // butterworth q
+
+        let a0 = 1.0 + alpha;
+        Self {
+            b0: (1.0 + cos) / 2.0 / a0,
+            b1: -(1.0 + cos) / a0,
+            b2: (1.0 + cos) / 2.0 / a0,
+            a1: -2.0 * cos / a0,
+            a2: (1.0 - alpha) / a0,
+            state: [(0.0, 0.0, 0.0, 0.0); 2],
+        }
+    }
+
+    pub fn process(&mut self, buf: &mut [f32]) {
+        for frame in buf.chunks_exact_mut(2) {
+            for (sample, (x1, x2, y1, y2)) in frame.iter_mut().zip(self.state.iter_mut()) {
+                let x = *sample;
+                let y = self.b0 * x + self.b1 * *x1 + self.b2 * *x2 - self.a1 * *y1 - self.a2 * *y2;
+
+                *x2 = *x1;
+                *x1 = x;
+                *y2 = *y1;
+                *y1 = y;
+                *sample = y;
+            }
+        }
+    }
+}
+
 pub fn compress(buf: &mut [f32], threshold: f32, ratio: f32) {
     for sample in buf {
         let abs = sample.abs();
@@ -64,11 +189,39 @@ pub fn compress(buf: &mut [f32], threshold: f32, ratio: f32) {
     }
 }
 
+// Spread talkers over [-PAN_SPREAD, PAN_SPREAD] so nobody ends up fully in one ear
+const PAN_SPREAD: f32 = 0.8;
+
+/// Deterministic pan position in [-PAN_SPREAD, PAN_SPREAD] for a talker. Uses
+/// FNV-1a so a user keeps the same position across ticks and server restarts.
+pub fn pan_position(key: &str) -> f32 {
+    let hash = key.bytes().fold(0x811c9dc5u32, |hash, b| {
+        (hash ^ b as u32).wrapping_mul(0x01000193)
+    });
+
+    let unit = (hash % 1024) as f32 / 1023.0; // 0..=1
+    (unit * 2.0 - 1.0) * PAN_SPREAD
+}
+
+/// Downmixes an interleaved stereo buffer to mono and adds it to `mix` at the
+/// given pan position using a constant-power pan law.
+pub fn mix_panned(mix: &mut [f32], buf: &[f32], pan: f32, gain: f32) {
+    let angle = (pan.clamp(-1.0, 1.0) + 1.0) * std::f32::consts::FRAC_PI_4;
+    let left_gain = angle.cos() * gain;
+    let right_gain = angle.sin() * gain;
+
+    for (out, frame) in mix.chunks_exact_mut(2).zip(buf.chunks_exact(2)) {
+        let mono = (frame[0] + frame[1]) * 0.5;
+        out[0] += mono * left_gain;
+        out[1] += mono * right_gain;
+    }
+}
+
 // util:
-pub fn is_silent(buf: &[f32]) -> bool {
+pub fn is_silent(buf: &[f32], threshold: f32) -> bool {
     // new impl: calculate RMS for better silence detection
     let sum_sq: f32 = buf.iter().map(|s| s * s).sum();
     let rms = (sum_sq / buf.len() as f32).sqrt();
 
-    rms < SILENCE_THRESHOLD
+    rms < threshold
 }
@@ -1,48 +1,334 @@
 use std::{
-    fs::File,
-    io::{ErrorKind, Read},
-    path::Path,
+    collections::VecDeque,
+    fs::{self, File},
+    io::{self, BufRead, BufReader, ErrorKind, Read, Write},
+    net::TcpStream,
+    path::{Component, Path, PathBuf},
     sync::{
         Arc, Mutex,
         atomic::{AtomicBool, AtomicU8, Ordering},
+        mpsc::{self, Receiver, RecvTimeoutError, Sender},
     },
     thread,
     time::{Duration, Instant},
 };
 
-use anyhow::{Context, Result, anyhow};
+use anyhow::{Context, Result, anyhow, bail};
 use opus2::{Bitrate, Encoder};
+use rand::seq::SliceRandom;
 use symphonia::{
     core::{
         audio::{AudioBufferRef, Signal},
         codecs::{CODEC_TYPE_NULL, DecoderOptions},
-        formats::FormatOptions,
-        io::MediaSourceStream,
-        meta::MetadataOptions,
-        probe::Hint,
+        formats::{FormatOptions, SeekMode, SeekTo},
+        io::{MediaSource, MediaSourceStream, ReadOnlySource},
+        meta::{MetadataOptions, StandardTagKey, Tag},
+        probe::{Hint, ProbeResult},
         sample::i24,
+        units::Time,
     },
     default::{get_codecs, get_probe},
 };
 
 use crate::{
+    client,
+    loudness::Normalizer,
     protocol::{self, ClientPacketType, FromPacket, ToBytes},
+    resample::SincResampler,
     socket::{self, SecureUdpSocket},
-    util::{ChatPacket, FlowPacket},
+    util::{ChatPacket, FlowPacket, RetryAfterPacket},
 };
 
 const TARGET_SAMPLE_RATE: u32 = 48_000;
 const FRAME_SIZE: usize = 960; // 20ms at 48kHz
 const FRAME_DURATION: Duration = Duration::from_millis(20);
 const CHANNELS: usize = 2; // Stereo
+// what streaming services level to, the volume then goes on top
+const NORMALIZE_TARGET_LUFS: f32 = -14.0;
+const MAX_LAG: Duration = Duration::from_millis(200);
+const MAX_REDIRECTS: usize = 5;
+// a radio that sends nothing for this long is gone
+const STREAM_TIMEOUT: Duration = Duration::from_secs(10);
+// the server forgets whoever it doesn't hear from, which would be us whenever
+// we're paused or waiting on a stream
+const KEEPALIVE_INTERVAL: Duration = Duration::from_secs(1);
+// the keepalive is answered, so this long without a word means we were
+// dropped or the server is gone
+const LINK_TIMEOUT: Duration = Duration::from_secs(3);
+// how much of the queue #queue lists before it says how many more there are
+const QUEUE_SHOWN: usize = 5;
+const PROGRESS_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Commands for a playing [`MusicClientState`], see [`MusicClientState::transport`]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Transport {
+    Pause,
+    Resume,
+    // on to the next track
+    Skip,
+    // the whole list
+    Stop,
+    // from the start of the current track
+    Seek(Duration),
+    // `None` goes on to the next mode
+    Loop(Option<Repeat>),
+}
+
+/// What happens once a track is over, see [`MusicClientState::set_repeat`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Repeat {
+    #[default]
+    Off,
+    // the same track again until it's skipped
+    Track,
+    // finished tracks go to the back of the queue
+    Playlist,
+}
+
+impl Repeat {
+    /// `off`, `track` or `playlist`
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "off" | "none" => Some(Self::Off),
+            "track" | "one" => Some(Self::Track),
+            "playlist" | "all" => Some(Self::Playlist),
+            _ => None,
+        }
+    }
+
+    fn next(self) -> Self {
+        match self {
+            Self::Off => Self::Track,
+            Self::Track => Self::Playlist,
+            Self::Playlist => Self::Off,
+        }
+    }
+}
+
+impl Transport {
+    /// `pause`, `resume`, `skip`, `stop`, `seek <secs|m:ss>` or
+    /// `loop <off|track|playlist>`. A bare `loop` goes to the next mode
+    pub fn parse(line: &str) -> Option<Self> {
+        let (cmd, arg) = line.trim().split_once(' ').unwrap_or((line.trim(), ""));
+        match cmd {
+            "pause" | "p" => Some(Self::Pause),
+            "resume" | "r" => Some(Self::Resume),
+            "skip" | "next" => Some(Self::Skip),
+            "stop" => Some(Self::Stop),
+            "seek" => {
+                let secs = match arg.trim().split_once(':') {
+                    Some((mins, secs)) => {
+                        mins.parse::<u64>().ok()? * 60 + secs.parse::<u64>().ok()?
+                    }
+                    None => arg.trim().parse().ok()?,
+                };
+                Some(Self::Seek(Duration::from_secs(secs)))
+            }
+            "loop" | "repeat" if arg.trim().is_empty() => Some(Self::Loop(None)),
+            "loop" | "repeat" => Repeat::parse(arg.trim()).map(|mode| Self::Loop(Some(mode))),
+            _ => None,
+        }
+    }
+}
+
+/// What a playing [`MusicClientState`] reports, see [`MusicClientState::events`]
+#[derive(Debug, Clone, PartialEq)]
+pub enum MusicEvent {
+    // every second or so while something plays
+    Progress(Progress),
+    // frames went out this far behind their 20ms slots, e.g. a stream stalled
+    Underrun(Duration),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Progress {
+    pub track: String,
+    pub elapsed: Duration,
+    // unknown for streams and piped audio
+    pub total: Option<Duration>,
+    // of the encoded audio since the last report, in bits per second
+    pub bitrate: u32,
+    pub packets_sent: u64,
+}
+
+/// What a track's tags say it is, any of it may be missing
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TrackTags {
+    pub title: Option<String>,
+    pub artist: Option<String>,
+    pub album: Option<String>,
+}
+
+impl TrackTags {
+    /// Reads the tags of a file without playing it
+    pub fn read(path: &Path) -> Result<Self> {
+        let mut hint = Hint::new();
+        if let Some(ext) = path.extension().and_then(|ext| ext.to_str()) {
+            hint.with_extension(ext);
+        }
+        let mss = MediaSourceStream::new(Box::new(File::open(path)?), Default::default());
+        let mut probed =
+            get_probe().format(&hint, mss, &Default::default(), &Default::default())?;
+        Ok(Self::from_probed(&mut probed))
+    }
+
+    pub fn from_tags(tags: &[Tag]) -> Self {
+        let mut found = Self::default();
+        for tag in tags {
+            // RIFF INFO strings come with their NUL terminator
+            let value = Some(tag.value.to_string().trim_end_matches('\0').to_string());
+            match tag.std_key {
+                Some(StandardTagKey::TrackTitle) => found.title = value,
+                Some(StandardTagKey::Artist) => found.artist = value,
+                Some(StandardTagKey::Album) => found.album = value,
+                _ => {}
+            }
+        }
+        found
+    }
+
+    // the container's own tags win over ones in front of it (ID3 on an mp3)
+    fn from_probed(probed: &mut ProbeResult) -> Self {
+        if let Some(revision) = probed.format.metadata().current() {
+            return Self::from_tags(revision.tags());
+        }
+        probed
+            .metadata
+            .get()
+            .and_then(|metadata| {
+                metadata
+                    .current()
+                    .map(|revision| Self::from_tags(revision.tags()))
+            })
+            .unwrap_or_default()
+    }
+
+    /// "Artist - Title", or just the title. `None` without a title
+    pub fn describe(&self) -> Option<String> {
+        let title = self.title.as_deref()?.trim();
+        if title.is_empty() {
+            return None;
+        }
+        Some(match self.artist.as_deref().map(str::trim) {
+            Some(artist) if !artist.is_empty() => format!("{artist} - {title}"),
+            _ => title.to_string(),
+        })
+    }
+}
+
+/// Sample formats for [`MusicClientState::run_pcm`], named like ffmpeg's
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PcmFormat {
+    S16Le,
+    F32Le,
+}
+
+impl PcmFormat {
+    pub fn by_name(name: &str) -> Option<Self> {
+        match name {
+            "s16le" => Some(Self::S16Le),
+            "f32le" => Some(Self::F32Le),
+            _ => None,
+        }
+    }
+
+    pub fn sample_size(self) -> usize {
+        match self {
+            Self::S16Le => 2,
+            Self::F32Le => 4,
+        }
+    }
+
+    /// Turns raw bytes into samples, replacing what's in `out`
+    pub fn decode(self, bytes: &[u8], out: &mut Vec<f32>) {
+        out.clear();
+        match self {
+            Self::S16Le => out.extend(
+                bytes
+                    .chunks_exact(2)
+                    .map(|b| i16::from_le_bytes([b[0], b[1]]) as f32 / 32768.0),
+            ),
+            Self::F32Le => out.extend(
+                bytes
+                    .chunks_exact(4)
+                    .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]).clamp(-1.0, 1.0)),
+            ),
+        }
+    }
+}
 
 pub struct MusicClientState {
     first: bool,
+    shuffle: bool,
+    normalize: bool,
+    announce: bool,
+    transport_tx: Sender<Transport>,
+    transport: Receiver<Transport>,
     socket: SecureUdpSocket,
     volume: Arc<AtomicU8>,
     current: Arc<Mutex<String>>,
     connected: Arc<AtomicBool>,
     channel_id: u32,
+    // what's left to play, chat requests go on the end
+    queue: Arc<Mutex<VecDeque<PathBuf>>>,
+    // where #play may pick from, requests are off without one
+    library: Option<PathBuf>,
+    repeat: Repeat,
+    mask: String,
+    // when the server last answered our keepalive
+    last_heard: Arc<Mutex<Instant>>,
+    last_rejoin: Instant,
+    events: Sender<MusicEvent>,
+    // handed out once through `events()`
+    event_rx: Mutex<Option<Receiver<MusicEvent>>>,
+}
+
+// what the listener needs to take requests over chat
+struct Requests {
+    library: Option<PathBuf>,
+    queue: Arc<Mutex<VecDeque<PathBuf>>>,
+    transport: Sender<Transport>,
+}
+
+// how a track came to an end
+enum Ended {
+    Finished,
+    Skipped,
+    Stopped,
+}
+
+// what carries over from one track to the next so there's no gap between them
+struct Playback {
+    encoder: Encoder,
+    // decoded samples not sent yet, the tail of a track leads into the next
+    samples: Vec<f32>,
+    start: Instant,
+    frames: u32,
+    normalizer: Option<Normalizer>,
+    // for progress reports
+    track: String,
+    elapsed: Duration,
+    total: Option<Duration>,
+    packets_sent: u64,
+    // encoded since the last report
+    bytes: usize,
+    reported: Instant,
+    // behind schedule, reported once until we catch up
+    late: bool,
+    // how far into the track the server last heard us, where a rejoin
+    // picks it up again if it can be seeked
+    heard_at: Duration,
+    seekable: bool,
+}
+
+impl Playback {
+    fn begin(&mut self, track: String, total: Option<Duration>, seekable: bool) {
+        self.track = track;
+        self.elapsed = Duration::ZERO;
+        self.total = total;
+        self.heard_at = Duration::ZERO;
+        self.seekable = seekable;
+    }
 }
 
 impl MusicClientState {
@@ -50,210 +336,385 @@ impl MusicClientState {
         let key = socket::derive_key_from_phrase(phrase, protocol::VOUDP_SALT);
         let socket = SecureUdpSocket::create("0.0.0.0:0".into(), key)?;
         socket.connect(addr)?;
+        let (transport_tx, transport) = mpsc::channel();
+        let (events, event_rx) = mpsc::channel();
 
         Ok(Self {
             first: true,
+            shuffle: false,
+            normalize: true,
+            announce: false,
+            transport_tx,
+            transport,
             socket,
             volume: Arc::new(AtomicU8::new(50)),
             current: Arc::new(Mutex::new(String::from("Nothing"))),
             connected: Arc::new(AtomicBool::new(true)),
             channel_id,
+            queue: Arc::new(Mutex::new(VecDeque::new())),
+            library: None,
+            repeat: Repeat::Off,
+            mask: String::from("Music"),
+            last_heard: Arc::new(Mutex::new(Instant::now())),
+            last_rejoin: Instant::now(),
+            events,
+            event_rx: Mutex::new(Some(event_rx)),
         })
     }
 
-    pub fn run(&mut self, path: String) -> Result<()> {
-        if self.first {
-            let mut join_packet = ClientPacketType::Join.to_bytes();
-            join_packet.extend_from_slice(&self.channel_id.to_be_bytes());
-            self.socket.send(&join_packet)?;
-        }
+    /// Plays the tracks in a random order instead of as given
+    pub fn set_shuffle(&mut self, shuffle: bool) {
+        self.shuffle = shuffle;
+    }
 
-        self.first = false;
-        let path = Path::new(&path);
-
-        let count = Path::new(&path)
-            .read_dir()
-            .ok()
-            .map(|dir| dir.enumerate().count())
-            .unwrap_or(0);
-
-        let single = if path.is_dir() {
-            match path.read_dir() {
-                Ok(dir) => {
-                    let volume = self.volume.clone();
-                    let sock = self.socket.clone();
-                    let conn = self.connected.clone();
-                    let current_music = self.current.clone();
-                    thread::spawn(move || {
-                        loop {
-                            if !conn.load(Ordering::Relaxed) {
-                                break;
-                            }
+    /// Checks the server is there and shares our phrase, see
+    /// [`ClientState::handshake`](crate::client::ClientState::handshake)
+    pub fn handshake(&self, timeout: Duration) -> io::Result<()> {
+        client::handshake(&self.socket, timeout).map(|_| ())
+    }
 
-                            let mut recv_buf = [0u8; 2048];
-                            match sock.recv_from(&mut recv_buf) {
-                                Ok((size, _)) => {
-                                    if size > 1 && recv_buf[0] == 0x06 {
-                                        match ChatPacket::deserialize(&recv_buf[..size]) {
-                                            Ok(chat) => {
-                                                let caster = chat.username;
-                                                let cmd = chat.message;
-
-                                                if cmd.starts_with("#current") {
-                                                    let mut msg_packet = vec![0x06];
-                                                    msg_packet.extend_from_slice(
-                                                        format!(
-                                                            "{caster}, I'm currently playing {}",
-                                                            { current_music.lock().unwrap() }
-                                                        )
-                                                        .as_bytes(),
-                                                    );
-                                                    let _ = sock.send(&msg_packet);
-                                                }
-                                                if cmd.starts_with("#volume") {
-                                                    let args = cmd
-                                                        .split_whitespace()
-                                                        .collect::<Vec<&str>>();
-
-                                                    match args.get(1) {
-                                                        Some(vol_str) => {
-                                                            match vol_str.parse::<u8>() {
-                                                                Ok(vol) => {
-                                                                    let mut msg_packet = vec![0x06];
-                                                                    msg_packet.extend_from_slice(
-                                                        format!("Volume set to {vol}, {caster}")
-                                                            .as_bytes(),
-                                                    );
-                                                                    let _ = sock.send(&msg_packet);
-
-                                                                    volume.store(
-                                                                        vol,
-                                                                        Ordering::Relaxed,
-                                                                    );
-                                                                }
-                                                                Err(e) => {
-                                                                    let mut msg_packet = vec![0x06];
-                                                                    msg_packet.extend_from_slice(
-                                                        format!("Garbage volume, {caster}: {e}")
-                                                            .as_bytes(),
-                                                    );
-                                                                    let _ = sock.send(&msg_packet);
-                                                                }
-                                                            }
-                                                        }
-                                                        None => {
-                                                            let mut msg_packet = vec![0x06];
-                                                            msg_packet.extend_from_slice(format!("{caster}, use it like this: #volume <0-100>").as_bytes());
-                                                            let _ = sock.send(&msg_packet);
-                                                        }
-                                                    }
-                                                }
-                                            }
-                                            Err(e) => {
-                                                eprintln!("error: {e}");
-                                            }
-                                        }
-                                    }
+    /// Says in chat what's playing whenever a track starts
+    pub fn set_announce(&mut self, announce: bool) {
+        self.announce = announce;
+    }
 
-                                    if size > 1
-                                        && (recv_buf[0] == 0x0a || recv_buf[0] == 0x0b)
-                                        && let Ok(msg) = FlowPacket::deserialize(&recv_buf[..size])
-                                        && let FlowPacket::Join(name) = msg
-                                    {
-                                        let mut msg_packet = vec![0x06];
-                                        msg_packet.extend_from_slice(
-                                            format!("Why hello there, {name}. I'm playing {}", {
-                                                current_music.lock().unwrap()
-                                            })
-                                            .as_bytes(),
-                                        );
-                                        let _ = sock.send(&msg_packet);
-                                    }
-                                }
+    /// Evens out loudness between tracks, on by default
+    pub fn set_normalize(&mut self, normalize: bool) {
+        self.normalize = normalize;
+    }
 
-                                Err(e) if e.0.kind() == ErrorKind::WouldBlock => {
-                                    thread::sleep(Duration::from_micros(100));
-                                }
-                                Err(_) => {}
-                            }
-                            thread::sleep(Duration::from_micros(1000));
-                        }
-                    });
+    /// Percent of the (normalized) level to play at, also set by `#volume`
+    pub fn set_volume(&self, percent: u8) {
+        self.volume.store(percent, Ordering::Relaxed);
+    }
 
-                    for (num, entry) in dir.enumerate() {
-                        match entry {
-                            Ok(entry) => {
-                                if entry.file_type().unwrap().is_file() {
-                                    let p = entry.file_name().to_str().unwrap().to_string();
-                                    let mut nick_packet = vec![0x04];
-                                    nick_packet.extend_from_slice(
-                                        format!("Music ({}/{count})", num + 1).as_bytes(),
-                                    );
-
-                                    *self.current.lock().unwrap() = p.clone();
-                                    let _ = self.socket.send(&nick_packet);
-
-                                    let mut msg_packet = vec![0x06];
-                                    msg_packet.extend_from_slice(
-                                        format!("Now playing the hit song {}", p).as_bytes(),
-                                    );
-                                    let _ = self.socket.send(&msg_packet)?;
-
-                                    match self.run(entry.path().to_str().unwrap().to_string()) {
-                                        Ok(_) => {}
-                                        Err(e) => {
-                                            println!("Ran into an error: {e}, skipping this track");
-                                        }
-                                    }
-                                }
-                            }
-                            Err(e) => {
-                                println!("ran into an error with an entry, skipping due to {e}");
-                            }
-                        }
+    pub fn volume(&self) -> u8 {
+        self.volume.load(Ordering::Relaxed)
+    }
+
+    /// Plays a track or the whole queue over and over, also switched with
+    /// [`Transport::Loop`] while playing
+    pub fn set_repeat(&mut self, repeat: Repeat) {
+        self.repeat = repeat;
+    }
+
+    pub fn repeat(&self) -> Repeat {
+        self.repeat
+    }
+
+    /// Lets listeners queue up what's in `dir` (and http streams) with
+    /// `#play`, `run` then waits for more once the queue runs dry
+    pub fn set_library(&mut self, dir: PathBuf) {
+        self.library = Some(dir);
+    }
+
+    /// What's left to play after the current track, requests included
+    pub fn queue(&self) -> Vec<PathBuf> {
+        self.queue.lock().unwrap().iter().cloned().collect()
+    }
+
+    /// Takes the progress and underrun reports, only the first caller gets them
+    pub fn events(&self) -> Option<Receiver<MusicEvent>> {
+        self.event_rx.lock().unwrap().take()
+    }
+
+    /// Controls playback from another thread while `run` is going, commands
+    /// sent between tracks are picked up when the next one starts
+    pub fn transport(&self) -> Sender<Transport> {
+        self.transport_tx.clone()
+    }
+
+    /// Plays `tracks` back to back, see [`tracks`] for turning a file,
+    /// directory or playlist into them. A track that fails is skipped.
+    /// With a library set this keeps going on requests until stopped
+    pub fn run(&mut self, mut tracks: Vec<PathBuf>) -> Result<()> {
+        if self.shuffle {
+            tracks.shuffle(&mut rand::rng());
+        }
+        self.queue.lock().unwrap().extend(tracks);
+        self.start()?;
+
+        // one clock for the whole list, a track starting doesn't reset it
+        let mut playback = self.playback()?;
+
+        let mut num = 0;
+        while let Some(path) = self.next_track(&mut playback) {
+            num += 1;
+            let count = num + self.queue.lock().unwrap().len();
+            // masked before anything is said in chat
+            self.mask = format!("Music ({num}/{count})");
+            let mut nick_packet = vec![0x04];
+            nick_packet.extend_from_slice(self.mask.as_bytes());
+            let _ = self.socket.send(&nick_packet);
+
+            match self.play(&path, &mut playback) {
+                Ok(Ended::Finished) if self.repeat == Repeat::Track => {
+                    self.queue.lock().unwrap().push_front(path);
+                }
+                Ok(Ended::Finished | Ended::Skipped) => {
+                    if self.repeat == Repeat::Playlist {
+                        self.queue.lock().unwrap().push_back(path);
                     }
                 }
-                Err(e) => {
-                    eprintln!("error when opening directory: {e}");
+                Ok(Ended::Stopped) => {
+                    println!("Stopped");
+                    break;
                 }
+                // not looped, or a broken file would be tried forever
+                Err(e) => println!("Ran into an error: {e}, skipping this track"),
             }
-            println!("Goodbye!");
-            self.connected.store(false, Ordering::Relaxed);
-            return Ok(());
-        } else {
-            true
-        };
+        }
+
+        self.finish(playback)
+    }
+
+    /// Streams raw interleaved PCM, e.g. piped from ffmpeg, until `reader`
+    /// ends or we're told to stop. What's read while paused is dropped, a
+    /// live source has nothing to come back to
+    pub fn run_pcm(
+        &mut self,
+        mut reader: impl Read,
+        format: PcmFormat,
+        sample_rate: u32,
+        channels: usize,
+    ) -> Result<()> {
+        if !(1..=2).contains(&channels) {
+            bail!("unsupported number of channels: {channels}");
+        }
+        self.start()?;
+        let mut playback = self.playback()?;
+        *self.current.lock().unwrap() = String::from("a live feed");
+        playback.begin(String::from("a live feed"), None, false);
+        if self.announce {
+            let mut msg_packet = vec![0x06];
+            msg_packet.extend_from_slice(b"Now playing a live feed");
+            self.socket.send(&msg_packet)?;
+        }
+
+        let frame_bytes = format.sample_size() * channels;
+        let mut raw = vec![0u8; FRAME_SIZE * frame_bytes];
+        let mut interleaved = Vec::with_capacity(FRAME_SIZE * channels);
+        let mut resampler = None;
+        let mut paused = false;
+
+        'feed: loop {
+            while let Ok(command) = self.transport.try_recv() {
+                match command {
+                    Transport::Pause => paused = true,
+                    Transport::Resume if paused => {
+                        paused = false;
+                        playback.start = Instant::now();
+                        playback.frames = 0;
+                    }
+                    Transport::Resume => {}
+                    Transport::Skip | Transport::Stop => {
+                        println!("Stopped");
+                        playback.samples.clear();
+                        break 'feed;
+                    }
+                    Transport::Seek(_) => println!("Can't seek a live feed"),
+                    Transport::Loop(_) => println!("Can't loop a live feed"),
+                }
+            }
+
+            let len = read_full(&mut reader, &mut raw)?;
+            if len == 0 {
+                break;
+            }
+            if paused {
+                continue;
+            }
+
+            // a torn sample at the very end is dropped
+            format.decode(&raw[..len - len % frame_bytes], &mut interleaved);
+            process_interleaved(
+                &interleaved,
+                channels,
+                sample_rate,
+                &mut resampler,
+                &mut playback.samples,
+            )?;
+            self.send_frames(&mut playback)?;
+        }
+
+        flush_resampler(resampler, &mut playback.samples);
+        self.finish(playback)
+    }
+
+    // the front of the queue. taking requests, an empty one is waited on
+    // until someone asks for something or we're told to stop
+    fn next_track(&mut self, playback: &mut Playback) -> Option<PathBuf> {
+        let mut waited = false;
+        loop {
+            if let Some(path) = self.queue.lock().unwrap().pop_front() {
+                if waited {
+                    playback.start = Instant::now();
+                    playback.frames = 0;
+                }
+                return Some(path);
+            }
+            self.library.as_ref()?;
 
-        if single {
-            println!("(re)joined channel {}", self.channel_id);
+            if !waited {
+                *self.current.lock().unwrap() = String::from("Nothing");
+                waited = true;
+            }
+            // there's nothing to pause or skip
+            match self.transport.recv_timeout(Duration::from_millis(100)) {
+                Ok(Transport::Stop) | Err(RecvTimeoutError::Disconnected) => return None,
+                Ok(Transport::Loop(mode)) => self.switch_repeat(mode),
+                _ => {}
+            }
+        }
+    }
 
-            let mut deaf_packet = vec![0x08];
-            let mode = 0x01;
-            deaf_packet.extend_from_slice(&[mode]);
-            self.socket.send(&deaf_packet)?;
+    // joins, and has someone answer chat while we play
+    fn start(&mut self) -> Result<()> {
+        if self.first {
+            self.join()?;
+            *self.last_heard.lock().unwrap() = Instant::now();
+
+            let volume = self.volume.clone();
+            let sock = self.socket.clone();
+            let conn = self.connected.clone();
+            let current_music = self.current.clone();
+            let last_heard = self.last_heard.clone();
+            let requests = Requests {
+                library: self.library.clone(),
+                queue: self.queue.clone(),
+                transport: self.transport_tx.clone(),
+            };
+            thread::spawn(move || listen(sock, volume, conn, current_music, last_heard, requests));
         }
+        self.first = false;
+        Ok(())
+    }
 
-        let mut opus_encoder = Encoder::new(
+    fn join(&self) -> io::Result<()> {
+        let mut join_packet = ClientPacketType::Join.to_bytes();
+        join_packet.extend_from_slice(&self.channel_id.to_be_bytes());
+        self.socket.send(&join_packet)?;
+        println!("(re)joined channel {}", self.channel_id);
+
+        let mut deaf_packet = vec![0x08];
+        let mode = 0x01;
+        deaf_packet.extend_from_slice(&[mode]);
+        self.socket.send(&deaf_packet)?;
+
+        // chat needs a mask, and there may not be a track to name us yet
+        let mut nick_packet = vec![0x04];
+        nick_packet.extend_from_slice(self.mask.as_bytes());
+        self.socket.send(&nick_packet)?;
+        Ok(())
+    }
+
+    // rejoins once the server has gone quiet on us, e.g. it timed us out,
+    // and goes back to about where it last heard us
+    fn check_link(&mut self, playback: &mut Playback) {
+        let silent_for = self.last_heard.lock().unwrap().elapsed();
+        if silent_for < LINK_TIMEOUT {
+            playback.heard_at = playback.elapsed.saturating_sub(silent_for);
+            return;
+        }
+        if self.last_rejoin.elapsed() < LINK_TIMEOUT {
+            return;
+        }
+
+        self.last_rejoin = Instant::now();
+        println!("Lost the server");
+        if let Err(e) = self.join() {
+            println!("Couldn't rejoin: {e}");
+        }
+        if playback.seekable {
+            let _ = self.transport_tx.send(Transport::Seek(playback.heard_at));
+        }
+    }
+
+    fn playback(&self) -> Result<Playback> {
+        let mut encoder = Encoder::new(
             TARGET_SAMPLE_RATE,
             opus2::Channels::Stereo,
             opus2::Application::Audio,
         )?;
+        encoder.set_bitrate(Bitrate::Bits(96000))?;
+
+        Ok(Playback {
+            encoder,
+            samples: Vec::with_capacity(FRAME_SIZE * CHANNELS * 10), // 10 frames
+            start: Instant::now(),
+            frames: 0,
+            normalizer: self
+                .normalize
+                .then(|| Normalizer::new(TARGET_SAMPLE_RATE, NORMALIZE_TARGET_LUFS)),
+            track: String::new(),
+            elapsed: Duration::ZERO,
+            total: None,
+            packets_sent: 0,
+            bytes: 0,
+            reported: Instant::now(),
+            late: false,
+            heard_at: Duration::ZERO,
+            seekable: false,
+        })
+    }
 
-        opus_encoder.set_bitrate(Bitrate::Bits(96000))?;
+    fn announce(&mut self, name: &str) -> Result<()> {
+        *self.current.lock().unwrap() = name.to_string();
+        if self.announce {
+            let mut msg_packet = vec![0x06];
+            msg_packet.extend_from_slice(format!("Now playing the hit song {}", name).as_bytes());
+            self.socket.send(&msg_packet)?;
+        }
+        Ok(())
+    }
 
-        // open and decode file
-        let mut file = File::open(path)?;
-        let mut data = Vec::new();
-        file.read_to_end(&mut data)?;
+    fn finish(&mut self, mut playback: Playback) -> Result<()> {
+        // after this, there is usually samples left that dont fit a whole FRAME_SIZE*CHANNELS. we will pad them:
+        if !playback.samples.is_empty() {
+            let mut padded = vec![0.0; FRAME_SIZE * CHANNELS];
+            let copy_len = playback.samples.len().min(padded.len());
+            padded[..copy_len].copy_from_slice(&playback.samples[..copy_len]); // the rest that are untouched are left as 0.0 samples
+            self.level(&mut padded, playback.normalizer.as_mut());
+
+            let mut opus_frame = vec![0u8; 4000]; // deja vu
+            let len = playback.encoder.encode_float(&padded, &mut opus_frame)?;
+
+            let mut packet = vec![0x02u8];
+            packet.extend_from_slice(&opus_frame[..len]);
+            self.upload_packet(&packet);
+        }
+
+        println!("Goodbye!");
+        self.connected.store(false, Ordering::Relaxed);
+        Ok(())
+    }
+
+    fn play(&mut self, path: &Path, playback: &mut Playback) -> Result<Ended> {
+        // open the file or stream, both are decoded as they're read
+        let mut hint = Hint::new(); // information
+        let source: Box<dyn MediaSource> = if is_stream(path) {
+            let (reader, ext) = open_stream(&path.to_string_lossy())?;
+            if let Some(ext) = ext {
+                hint.with_extension(ext);
+            }
+            Box::new(ReadOnlySource::new(reader))
+        } else {
+            if let Some(ext) = path.extension().and_then(|ext| ext.to_str()) {
+                hint.with_extension(ext);
+            }
+            Box::new(File::open(path)?)
+        };
 
         // stuff for decoding the file
-        let mss = MediaSourceStream::new(Box::new(std::io::Cursor::new(data)), Default::default()); // cursor implements a Seek
-        let hint = Hint::new(); // information
+        let mss = MediaSourceStream::new(source, Default::default());
         let format_opts = FormatOptions::default();
         let metadata_opts = MetadataOptions::default();
         let decode_opts = DecoderOptions::default();
 
-        let probed = get_probe().format(&hint, mss, &format_opts, &metadata_opts)?;
+        let mut probed = get_probe().format(&hint, mss, &format_opts, &metadata_opts)?;
+        let tags = TrackTags::from_probed(&mut probed);
+        let name = tags.describe().unwrap_or_else(|| track_name(path));
+        self.announce(&name)?;
 
         let mut format = probed.format;
         let track = format
@@ -264,90 +725,546 @@ impl MusicClientState {
 
         let mut decoder = get_codecs().make(&track.codec_params, &decode_opts)?;
         let track_id = track.id;
-
-        // init sample buffer
-        let mut sample_buf = Vec::with_capacity(FRAME_SIZE * CHANNELS * 10); // 10 frames
         let sample_rate = track.codec_params.sample_rate.unwrap_or(TARGET_SAMPLE_RATE);
-
-        // timing stuff:
-        let start = Instant::now();
-        let mut f_idx = 0; // frame index
+        let total = track
+            .codec_params
+            .n_frames
+            .map(|frames| Duration::from_secs_f64(frames as f64 / sample_rate as f64));
+        playback.begin(name, total, !is_stream(path));
+        let mut resampler: Option<SincResampler> = None;
+        let mut paused = false;
+        if let Some(normalizer) = &mut playback.normalizer {
+            normalizer.reset();
+        }
 
         while let Ok(packet) = format.next_packet() {
             if packet.track_id() != track_id {
                 continue;
             }
 
+            // a pause blocks here until we're told what's next
+            let mut seeked = false;
+            while let Some(command) = if paused {
+                self.transport.recv().ok()
+            } else {
+                self.transport.try_recv().ok()
+            } {
+                match command {
+                    Transport::Pause => paused = true,
+                    Transport::Resume if paused => {
+                        paused = false;
+                        // the time spent paused isn't owed to anyone
+                        playback.start = Instant::now();
+                        playback.frames = 0;
+                    }
+                    Transport::Resume => {}
+                    Transport::Skip => {
+                        playback.samples.clear();
+                        return Ok(Ended::Skipped);
+                    }
+                    Transport::Stop => {
+                        playback.samples.clear();
+                        return Ok(Ended::Stopped);
+                    }
+                    Transport::Loop(mode) => self.switch_repeat(mode),
+                    Transport::Seek(to_duration) => {
+                        let to = SeekTo::Time {
+                            time: Time::from(to_duration.as_secs_f64()),
+                            track_id: Some(track_id),
+                        };
+                        // live streams can't
+                        if let Err(e) = format.seek(SeekMode::Coarse, to) {
+                            println!("Couldn't seek: {e}");
+                            continue;
+                        }
+                        decoder.reset();
+                        if let Some(resampler) = &mut resampler {
+                            resampler.reset();
+                        }
+                        playback.samples.clear();
+                        playback.elapsed = to_duration;
+                        seeked = true;
+                    }
+                }
+            }
+            // the packet we have is from before the seek
+            if seeked {
+                continue;
+            }
+            let sample_buf = &mut playback.samples;
+
             // holy hell it was a pain to figure all of them out except the first one maybe
-            let vol = 0.01 * self.volume.load(Ordering::Relaxed) as f32;
             match decoder.decode(&packet)? {
                 AudioBufferRef::F32(buf) => {
-                    process_buffer_f32(vol, &buf, &mut sample_buf, sample_rate)?
+                    process_buffer_f32(&buf, sample_buf, sample_rate, &mut resampler)?
                 }
                 AudioBufferRef::S16(buf) => {
-                    process_buffer_i16(vol, &buf, &mut sample_buf, sample_rate)?
+                    process_buffer_i16(&buf, sample_buf, sample_rate, &mut resampler)?
                 }
                 AudioBufferRef::S24(buf) => {
-                    process_buffer_i24(vol, &buf, &mut sample_buf, sample_rate)?
+                    process_buffer_i24(&buf, sample_buf, sample_rate, &mut resampler)?
                 }
                 AudioBufferRef::S32(buf) => {
-                    process_buffer_i32(vol, &buf, &mut sample_buf, sample_rate)?
+                    process_buffer_i32(&buf, sample_buf, sample_rate, &mut resampler)?
                 }
                 AudioBufferRef::U8(buf) => {
-                    process_buffer_u8(vol, &buf, &mut sample_buf, sample_rate)?
+                    process_buffer_u8(&buf, sample_buf, sample_rate, &mut resampler)?
                 }
                 _ => return Err(anyhow!("unsupported audio buffer type")),
             }
 
-            // this ensures that we are dealing with complete frames every time
-            while sample_buf.len() >= FRAME_SIZE * CHANNELS {
-                // calculate target time: (frame index * frame duration) + begin offset
-                let target_time = start + FRAME_DURATION * f_idx;
-                f_idx += 1;
-
-                let frame = &sample_buf[..FRAME_SIZE * CHANNELS];
-                let mut opus_frame = vec![0u8; 4000]; // idk deepseek said its a good size
+            self.send_frames(playback)?;
+        }
 
-                let len = opus_encoder.encode_float(frame, &mut opus_frame)?;
+        // the next track picks up right where this one's tail ends
+        flush_resampler(resampler, &mut playback.samples);
+        self.send_frames(playback)?;
+        Ok(Ended::Finished)
+    }
 
-                // create packet with 0x02 header
-                let mut audio_packet = vec![0x02];
-                audio_packet.extend_from_slice(&opus_frame[..len]);
+    fn switch_repeat(&mut self, mode: Option<Repeat>) {
+        self.repeat = mode.unwrap_or(self.repeat.next());
+        println!("Looping: {:?}", self.repeat);
+    }
 
-                // request upload
-                self.upload_packet(&audio_packet)?;
+    fn send_frames(&mut self, playback: &mut Playback) -> Result<()> {
+        // this ensures that we are dealing with complete frames every time
+        while playback.samples.len() >= FRAME_SIZE * CHANNELS {
+            // calculate target time: (frame index * frame duration) + begin offset
+            let target_time = playback.start + FRAME_DURATION * playback.frames;
+            playback.frames += 1;
+
+            let frame = &mut playback.samples[..FRAME_SIZE * CHANNELS];
+            self.level(frame, playback.normalizer.as_mut());
+            let mut opus_frame = vec![0u8; 4000]; // idk deepseek said its a good size
+
+            let len = playback.encoder.encode_float(frame, &mut opus_frame)?;
+
+            // create packet with 0x02 header
+            let mut audio_packet = vec![0x02];
+            audio_packet.extend_from_slice(&opus_frame[..len]);
+
+            // request upload
+            self.upload_packet(&audio_packet);
+            self.check_link(playback);
+            playback.elapsed += FRAME_DURATION;
+            playback.packets_sent += 1;
+            playback.bytes += len;
+            if playback.reported.elapsed() >= PROGRESS_INTERVAL {
+                self.report(playback);
+            }
 
-                // remove the samples we read:
-                sample_buf.drain(0..FRAME_SIZE * CHANNELS);
-                // timing logic:
-                let now = Instant::now();
-                if now < target_time {
-                    std::thread::sleep(target_time - now); // wait until we are back to schedule
+            // remove the samples we read:
+            playback.samples.drain(0..FRAME_SIZE * CHANNELS);
+            // timing logic:
+            let now = Instant::now();
+            if now < target_time {
+                playback.late = false;
+                std::thread::sleep(target_time - now); // wait until we are back to schedule
+            } else {
+                let behind = now - target_time;
+                if behind > FRAME_DURATION && !playback.late {
+                    playback.late = true;
+                    let _ = self.events.send(MusicEvent::Underrun(behind));
+                }
+                if behind > MAX_LAG {
+                    // a stream that stalled shouldn't catch up in one burst
+                    playback.start = now;
+                    playback.frames = 1;
                 }
             }
         }
 
-        // after this, there is usually samples left that dont fit a whole FRAME_SIZE*CHANNELS. we will pad them:
-        if !sample_buf.is_empty() {
-            let mut padded = vec![0.0; FRAME_SIZE * CHANNELS];
-            let copy_len = sample_buf.len().min(padded.len());
-            padded[..copy_len].copy_from_slice(&sample_buf[..copy_len]); // the rest that are untouched are left as 0.0 samples
+        Ok(())
+    }
 
-            let mut opus_frame = vec![0u8; 4000]; // deja vu
-            let len = opus_encoder.encode_float(&padded, &mut opus_frame)?;
+    fn report(&self, playback: &mut Playback) {
+        let secs = playback.reported.elapsed().as_secs_f64();
+        let _ = self.events.send(MusicEvent::Progress(Progress {
+            track: playback.track.clone(),
+            elapsed: playback.elapsed,
+            total: playback.total,
+            bitrate: (playback.bytes as f64 * 8.0 / secs) as u32,
+            packets_sent: playback.packets_sent,
+        }));
+        playback.bytes = 0;
+        playback.reported = Instant::now();
+    }
 
-            let mut packet = vec![0x02u8];
-            packet.extend_from_slice(&opus_frame[..len]);
-            self.upload_packet(&packet)?;
+    // normalization first so the volume is relative to the target level
+    fn level(&self, frame: &mut [f32], normalizer: Option<&mut Normalizer>) {
+        if let Some(normalizer) = normalizer {
+            normalizer.process(frame);
+        }
+        let vol = 0.01 * self.volume.load(Ordering::Relaxed) as f32;
+        for sample in frame {
+            *sample = (*sample * vol).clamp(-1.0, 1.0);
         }
+    }
 
-        Ok(())
+    // a send that fails is caught by `check_link` when nothing comes back
+    fn upload_packet(&self, packet: &[u8]) {
+        let _ = self.socket.send(packet);
+    }
+}
+
+// the file name, or the whole url for a stream
+fn track_name(path: &Path) -> String {
+    if is_stream(path) {
+        path.to_string_lossy().into_owned()
+    } else {
+        path.file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default()
     }
+}
 
-    fn upload_packet(&mut self, packet: &[u8]) -> Result<()> {
-        self.socket.send(packet)?;
-        Ok(())
+/// What a `#play` asks for: an http stream, or a file, directory or playlist
+/// inside `library`. Paths that would lead out of it are refused
+pub fn request(library: &Path, request: &str) -> Result<Vec<PathBuf>> {
+    let path = Path::new(request);
+    if is_stream(path) {
+        return Ok(vec![path.to_path_buf()]);
     }
+    if !path
+        .components()
+        .all(|component| matches!(component, Component::Normal(_)))
+    {
+        bail!("{request} isn't in the library");
+    }
+
+    let path = library.join(path);
+    if !path.exists() {
+        bail!("there's no {request} in the library");
+    }
+    let found = tracks(&path)?;
+    if found.is_empty() {
+        bail!("there's nothing to play in {request}");
+    }
+    Ok(found)
+}
+
+// fills `buf` unless the reader ends first, a pipe hands out whatever it has
+fn read_full(reader: &mut impl Read, buf: &mut [u8]) -> io::Result<usize> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match reader.read(&mut buf[filled..]) {
+            Ok(0) => break,
+            Ok(len) => filled += len,
+            Err(e) if e.kind() == ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(filled)
+}
+
+/// What there is to play at `path`: the files of a directory by name, the
+/// entries of an `.m3u` playlist, or just the file itself
+pub fn tracks(path: &Path) -> Result<Vec<PathBuf>> {
+    if is_stream(path) {
+        return Ok(vec![path.to_path_buf()]);
+    }
+    if path.is_dir() {
+        let mut files = path
+            .read_dir()?
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| path.is_file())
+            .collect::<Vec<_>>();
+        files.sort();
+        return Ok(files);
+    }
+
+    let is_playlist = path
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("m3u") || ext.eq_ignore_ascii_case("m3u8"));
+    if is_playlist {
+        return read_playlist(path);
+    }
+
+    Ok(vec![path.to_path_buf()])
+}
+
+/// Entries of an `.m3u` playlist, relative ones are from the playlist's
+/// directory and URLs are kept as they are. `#EXTINF` and other comments
+/// are skipped
+pub fn read_playlist(path: &Path) -> Result<Vec<PathBuf>> {
+    let text = fs::read_to_string(path)
+        .with_context(|| format!("couldn't read playlist {}", path.display()))?;
+    let dir = path.parent().unwrap_or(Path::new(""));
+
+    Ok(text
+        .lines()
+        .map(|line| line.trim().trim_start_matches('\u{feff}'))
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| {
+            if is_stream(Path::new(line)) {
+                PathBuf::from(line)
+            } else {
+                dir.join(line)
+            }
+        })
+        .collect())
+}
+
+/// Whether `path` is an `http(s)://` URL rather than a file
+pub fn is_stream(path: &Path) -> bool {
+    path.to_str()
+        .is_some_and(|path| path.starts_with("http://") || path.starts_with("https://"))
+}
+
+// GETs a plain http url, following redirects. The body is read as it comes,
+// the content type helps the probe along
+fn open_stream(url: &str) -> Result<(BufReader<TcpStream>, Option<&'static str>)> {
+    let mut url = url.to_string();
+    for _ in 0..=MAX_REDIRECTS {
+        if url.starts_with("https://") {
+            bail!("https streams aren't supported, use the station's http url");
+        }
+        let rest = url
+            .strip_prefix("http://")
+            .context("expected http://host[:port]/path")?;
+        let (host, path) = rest.split_once('/').unwrap_or((rest, ""));
+        if host.is_empty() {
+            bail!("expected http://host[:port]/path");
+        }
+        let addr = if host.contains(':') {
+            host.to_string()
+        } else {
+            format!("{host}:80")
+        };
+
+        let mut stream = TcpStream::connect(addr)?;
+        stream.set_read_timeout(Some(STREAM_TIMEOUT))?;
+        // 1.0 so the body doesn't come chunked
+        let request = format!(
+            "GET /{path} HTTP/1.0\r\nHost: {host}\r\nUser-Agent: voudp\r\nAccept: */*\r\n\r\n"
+        );
+        stream.write_all(request.as_bytes())?;
+
+        let mut reader = BufReader::new(stream);
+        let mut status = String::new();
+        reader.read_line(&mut status)?;
+        let mut location = None;
+        let mut content_type = None;
+        loop {
+            let mut line = String::new();
+            if reader.read_line(&mut line)? == 0 || line.trim().is_empty() {
+                break;
+            }
+            if let Some((name, value)) = line.split_once(':') {
+                match name.trim().to_ascii_lowercase().as_str() {
+                    "location" => location = Some(value.trim().to_string()),
+                    "content-type" => content_type = Some(value.trim().to_ascii_lowercase()),
+                    _ => {}
+                }
+            }
+        }
+
+        // shoutcast answers "ICY 200 OK"
+        match status.split_whitespace().nth(1).unwrap_or_default() {
+            "200" => return Ok((reader, content_type.as_deref().and_then(extension_for))),
+            "301" | "302" | "303" | "307" | "308" => {
+                let location = location.context("redirect without a location")?;
+                url = if location.starts_with('/') {
+                    format!("http://{host}{location}")
+                } else {
+                    location
+                };
+            }
+            _ => bail!("stream answered {}", status.trim()),
+        }
+    }
+
+    bail!("more than {MAX_REDIRECTS} redirects")
+}
+
+fn extension_for(content_type: &str) -> Option<&'static str> {
+    let mime = content_type.split(';').next()?.trim();
+    Some(match mime {
+        "audio/mpeg" | "audio/mp3" => "mp3",
+        "audio/ogg" | "application/ogg" | "audio/vorbis" => "ogg",
+        "audio/flac" | "audio/x-flac" => "flac",
+        "audio/wav" | "audio/x-wav" | "audio/wave" => "wav",
+        _ => return None,
+    })
+}
+
+// answers #current, #volume and requests, greets whoever joins and keeps us
+// connected
+fn listen(
+    sock: SecureUdpSocket,
+    volume: Arc<AtomicU8>,
+    conn: Arc<AtomicBool>,
+    current_music: Arc<Mutex<String>>,
+    last_heard: Arc<Mutex<Instant>>,
+    requests: Requests,
+) {
+    let mut last_keepalive = Instant::now();
+    loop {
+        if !conn.load(Ordering::Relaxed) {
+            break;
+        }
+        sock.tick_reliable();
+
+        // a list request, same as the voice client's
+        if last_keepalive.elapsed() >= KEEPALIVE_INTERVAL {
+            let _ = sock.send(&protocol::create_list_page_request(0, 0, 0));
+            last_keepalive = Instant::now();
+        }
+
+        let mut recv_buf = [0u8; 2048];
+        match sock.recv_from(&mut recv_buf) {
+            Ok((size, _)) => {
+                // only those still in the server's books get their list
+                if size > 0 && recv_buf[0] == ClientPacketType::ListPage as u8 {
+                    *last_heard.lock().unwrap() = Instant::now();
+                }
+                if let Ok(retry) = RetryAfterPacket::deserialize(&recv_buf[..size]) {
+                    let mut join_packet = ClientPacketType::Join.to_bytes();
+                    join_packet.extend_from_slice(&retry.channel_id.to_be_bytes());
+
+                    let sock = sock.clone();
+                    thread::spawn(move || {
+                        thread::sleep(retry.backoff());
+                        let _ = sock.send(&join_packet);
+                    });
+                }
+
+                if size > 1 && recv_buf[0] == 0x06 {
+                    match ChatPacket::deserialize(&recv_buf[..size]) {
+                        Ok(chat) => {
+                            let caster = chat.username;
+                            let cmd = chat.message;
+
+                            if cmd.starts_with("#current") {
+                                let mut msg_packet = vec![0x06];
+                                msg_packet.extend_from_slice(
+                                    format!("{caster}, I'm currently playing {}", {
+                                        current_music.lock().unwrap()
+                                    })
+                                    .as_bytes(),
+                                );
+                                let _ = sock.send(&msg_packet);
+                            }
+                            if cmd.starts_with("#volume") {
+                                let args = cmd.split_whitespace().collect::<Vec<&str>>();
+
+                                match args.get(1) {
+                                    Some(vol_str) => match vol_str.parse::<u8>() {
+                                        Ok(vol) => {
+                                            let mut msg_packet = vec![0x06];
+                                            msg_packet.extend_from_slice(
+                                                format!("Volume set to {vol}, {caster}").as_bytes(),
+                                            );
+                                            let _ = sock.send(&msg_packet);
+
+                                            volume.store(vol, Ordering::Relaxed);
+                                        }
+                                        Err(e) => {
+                                            let mut msg_packet = vec![0x06];
+                                            msg_packet.extend_from_slice(
+                                                format!("Garbage volume, {caster}: {e}").as_bytes(),
+                                            );
+                                            let _ = sock.send(&msg_packet);
+                                        }
+                                    },
+                                    None => {
+                                        let mut msg_packet = vec![0x06];
+                                        msg_packet.extend_from_slice(
+                                            format!("{caster}, use it like this: #volume <0-100>")
+                                                .as_bytes(),
+                                        );
+                                        let _ = sock.send(&msg_packet);
+                                    }
+                                }
+                            }
+                            let current = current_music.lock().unwrap().clone();
+                            if let Some(reply) = answer_request(&cmd, &caster, &requests, &current)
+                            {
+                                let mut msg_packet = vec![0x06];
+                                msg_packet.extend_from_slice(reply.as_bytes());
+                                let _ = sock.send(&msg_packet);
+                            }
+                        }
+                        Err(e) => {
+                            eprintln!("error: {e}");
+                        }
+                    }
+                }
+
+                if size > 1
+                    && (recv_buf[0] == 0x0a || recv_buf[0] == 0x0b)
+                    && let Ok(msg) = FlowPacket::deserialize(&recv_buf[..size])
+                    && let FlowPacket::Join(name) = msg
+                {
+                    let mut msg_packet = vec![0x06];
+                    msg_packet.extend_from_slice(
+                        format!("Why hello there, {name}. I'm playing {}", {
+                            current_music.lock().unwrap()
+                        })
+                        .as_bytes(),
+                    );
+                    let _ = sock.send(&msg_packet);
+                }
+            }
+
+            Err(e) if e.0.kind() == ErrorKind::WouldBlock => {
+                thread::sleep(Duration::from_micros(100));
+            }
+            Err(_) => {}
+        }
+        thread::sleep(Duration::from_micros(1000));
+    }
+}
+
+// #play, #skip and #queue, `None` for anything else
+fn answer_request(cmd: &str, caster: &str, requests: &Requests, current: &str) -> Option<String> {
+    let (word, arg) = cmd.trim().split_once(' ').unwrap_or((cmd.trim(), ""));
+    let reply = match word {
+        "#play" => {
+            let Some(library) = &requests.library else {
+                return Some(format!("{caster}, I'm not taking requests"));
+            };
+            if arg.trim().is_empty() {
+                return Some(format!("{caster}, use it like this: #play <file|url>"));
+            }
+            match request(library, arg.trim()) {
+                Ok(found) => {
+                    let mut queue = requests.queue.lock().unwrap();
+                    let name = match &found[..] {
+                        [single] => track_name(single),
+                        _ => format!("{} tracks from {}", found.len(), arg.trim()),
+                    };
+                    queue.extend(found);
+                    format!("{caster}, queued {name} ({} in the queue)", queue.len())
+                }
+                Err(e) => format!("{caster}, {e}"),
+            }
+        }
+        "#skip" => {
+            let _ = requests.transport.send(Transport::Skip);
+            format!("Skipping {current} for {caster}")
+        }
+        "#queue" => {
+            let queue = requests.queue.lock().unwrap();
+            if queue.is_empty() {
+                return Some(format!("{caster}, nothing's queued"));
+            }
+            let mut names = queue
+                .iter()
+                .take(QUEUE_SHOWN)
+                .map(|path| track_name(path))
+                .collect::<Vec<_>>()
+                .join(", ");
+            if queue.len() > QUEUE_SHOWN {
+                names.push_str(&format!(" and {} more", queue.len() - QUEUE_SHOWN));
+            }
+            format!("Up next: {names}")
+        }
+        _ => return None,
+    };
+    Some(reply)
 }
 
 // OK so these process functions i had no fucking clue how to make them
@@ -355,10 +1272,10 @@ impl MusicClientState {
 
 // no conversion needed as we deal with f32 ourselves
 fn process_buffer_f32(
-    vol: f32,
     buffer: &symphonia::core::audio::AudioBuffer<f32>,
     sample_buffer: &mut Vec<f32>,
     original_sample_rate: u32,
+    resampler: &mut Option<SincResampler>,
 ) -> Result<()> {
     let channels = buffer.spec().channels.count();
     let frames = buffer.frames();
@@ -372,19 +1289,19 @@ fn process_buffer_f32(
     }
 
     process_interleaved(
-        vol,
         &interleaved,
         channels,
         original_sample_rate,
+        resampler,
         sample_buffer,
     )
 }
 
 fn process_buffer_i16(
-    vol: f32,
     buffer: &symphonia::core::audio::AudioBuffer<i16>,
     sample_buffer: &mut Vec<f32>,
     original_sample_rate: u32,
+    resampler: &mut Option<SincResampler>,
 ) -> Result<()> {
     let channels = buffer.spec().channels.count();
     let frames = buffer.frames();
@@ -399,20 +1316,20 @@ fn process_buffer_i16(
     }
 
     process_interleaved(
-        vol,
         &interleaved,
         channels,
         original_sample_rate,
+        resampler,
         sample_buffer,
     )
 }
 
 // Process i24 buffer
 fn process_buffer_i24(
-    vol: f32,
     buffer: &symphonia::core::audio::AudioBuffer<i24>,
     sample_buffer: &mut Vec<f32>,
     original_sample_rate: u32,
+    resampler: &mut Option<SincResampler>,
 ) -> Result<()> {
     let channels = buffer.spec().channels.count();
     let frames = buffer.frames();
@@ -427,20 +1344,20 @@ fn process_buffer_i24(
     }
 
     process_interleaved(
-        vol,
         &interleaved,
         channels,
         original_sample_rate,
+        resampler,
         sample_buffer,
     )
 }
 
 // Process i32 buffer
 fn process_buffer_i32(
-    vol: f32,
     buffer: &symphonia::core::audio::AudioBuffer<i32>,
     sample_buffer: &mut Vec<f32>,
     original_sample_rate: u32,
+    resampler: &mut Option<SincResampler>,
 ) -> Result<()> {
     let channels = buffer.spec().channels.count();
     let frames = buffer.frames();
@@ -455,20 +1372,20 @@ fn process_buffer_i32(
     }
 
     process_interleaved(
-        vol,
         &interleaved,
         channels,
         original_sample_rate,
+        resampler,
         sample_buffer,
     )
 }
 
 // Process u8 buffer
 fn process_buffer_u8(
-    vol: f32,
     buffer: &symphonia::core::audio::AudioBuffer<u8>,
     sample_buffer: &mut Vec<f32>,
     original_sample_rate: u32,
+    resampler: &mut Option<SincResampler>,
 ) -> Result<()> {
     let channels = buffer.spec().channels.count();
     let frames = buffer.frames();
@@ -483,113 +1400,55 @@ fn process_buffer_u8(
     }
 
     process_interleaved(
-        vol,
         &interleaved,
         channels,
         original_sample_rate,
+        resampler,
         sample_buffer,
     )
 }
 
 fn process_interleaved(
-    vol: f32,
     interleaved: &[f32],
     channels: usize,
     original_sample_rate: u32,
+    resampler: &mut Option<SincResampler>,
     sample_buffer: &mut Vec<f32>,
 ) -> Result<()> {
-    // resample if necessary
-    let resampled = if original_sample_rate != TARGET_SAMPLE_RATE {
-        // println!(
-        //     "sample rate mismatch. resampling... [{} -> {}]",
-        //     original_sample_rate, TARGET_SAMPLE_RATE
-        // );
-        resample(
-            interleaved,
-            original_sample_rate,
-            TARGET_SAMPLE_RATE,
-            channels,
-        )
-        .unwrap()
-    } else {
-        interleaved.to_vec()
-    };
-
-    let mut final_samples = if channels == 1 {
-        let mut stereo = Vec::with_capacity(resampled.len() * 2);
-        for sample in &resampled {
-            // mono audio pair for stereo channels
-            stereo.push(*sample);
-            stereo.push(*sample);
-        }
-        stereo
-    } else if channels == 2 {
-        resampled
-    } else {
+    if channels != 1 && channels != 2 {
         return Err(anyhow!("unsupported number of channels: {}", channels));
-    };
-
-    for sample in &mut final_samples {
-        *sample *= vol;
-        *sample = sample.clamp(-1.0, 1.0);
     }
 
-    sample_buffer.extend(final_samples);
+    // resample if necessary, the resampler lives as long as the track does
+    if original_sample_rate != TARGET_SAMPLE_RATE {
+        let resampler = resampler.get_or_insert_with(|| {
+            SincResampler::new(original_sample_rate, TARGET_SAMPLE_RATE, channels)
+        });
+        let mut resampled = Vec::with_capacity(interleaved.len() * 2);
+        resampler.process(interleaved, &mut resampled);
+        to_stereo(&resampled, channels, sample_buffer);
+    } else {
+        to_stereo(interleaved, channels, sample_buffer);
+    }
     Ok(())
 }
 
-// i learnt a lot
-fn resample(
-    interleaved: &[f32],
-    from_rate: u32,
-    to_rate: u32,
-    channels: usize,
-) -> Result<Vec<f32>, Box<dyn std::error::Error>> {
-    if channels == 0 || interleaved.is_empty() {
-        return Ok(interleaved.to_vec());
-    }
-
-    // deinterlieve
-    let mut deinterleaved: Vec<Vec<f32>> =
-        vec![Vec::with_capacity(interleaved.len() / channels); channels];
-    for (i, sample) in interleaved.iter().enumerate() {
-        deinterleaved[i % channels].push(*sample);
-    }
-
-    // resample each channel
-    let ratio = to_rate as f64 / from_rate as f64;
-    let new_len = (deinterleaved[0].len() as f64 * ratio).ceil() as usize;
-    let mut resampled_channels = Vec::with_capacity(channels);
-
-    for channel in deinterleaved {
-        let mut resampled = Vec::with_capacity(new_len);
-        for i in 0..new_len {
-            let pos = i as f64 / ratio;
-            let idx = pos as usize;
-            let frac = pos - idx as f64;
-
-            if idx < channel.len().saturating_sub(1) {
-                let s1 = channel[idx];
-                let s2 = channel[idx + 1];
-                resampled.push(s1 + (s2 - s1) * (frac as f32));
-            } else if !channel.is_empty() {
-                resampled.push(*channel.last().unwrap());
-            } else {
-                resampled.push(0.0);
-            }
-        }
-        resampled_channels.push(resampled);
+// the tail a resampler holds back once a track has no more to give it
+fn flush_resampler(resampler: Option<SincResampler>, sample_buffer: &mut Vec<f32>) {
+    if let Some(mut resampler) = resampler {
+        let mut tail = vec![];
+        resampler.flush(&mut tail);
+        to_stereo(&tail, resampler.channels(), sample_buffer);
     }
+}
 
-    // Interleave resampled channels
-    let mut resampled_interleaved = Vec::with_capacity(new_len * channels);
-    for i in 0..new_len {
-        for ch in resampled_channels.iter().take(channels) {
-            resampled_interleaved.push(ch[i]);
-        }
+fn to_stereo(samples: &[f32], channels: usize, sample_buffer: &mut Vec<f32>) {
+    if channels == 1 {
+        // mono audio pair for stereo channels
+        sample_buffer.extend(samples.iter().flat_map(|&sample| [sample, sample]));
+    } else {
+        sample_buffer.extend_from_slice(samples);
     }
-
-    Ok(resampled_interleaved)
 }
 
 impl Drop for MusicClientState {
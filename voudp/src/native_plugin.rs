@@ -0,0 +1,64 @@
+// Plugins compiled into the server, for extensions too hot to script. They are
+// registered on the `ServerState` before it runs and see the same events as lua
+// plugins, ahead of them and in registration order. Every hook is optional.
+use std::{net::SocketAddr, sync::mpsc::Sender};
+
+use crate::plugin::{DirectoryChannel, PluginAction};
+
+/// Someone joining a channel, or switching to another one when `rejoining`
+#[derive(Debug)]
+pub struct JoinEvent<'a> {
+    pub addr: SocketAddr,
+    /// Where they end up, change it to move them elsewhere
+    pub channel_id: u32,
+    pub mask: Option<&'a str>,
+    pub rejoining: bool,
+    pub users: usize,
+}
+
+pub trait VoudpPlugin: Send {
+    fn name(&self) -> &str;
+
+    /// Called once on registration with where to send replies, kicks and moves
+    fn on_load(&mut self, _actions: Sender<PluginAction>) {}
+
+    /// `false` cancels the join
+    fn on_join(&mut self, _join: &mut JoinEvent) -> bool {
+        true
+    }
+
+    /// `false` keeps the message from being sent
+    fn on_message(&mut self, _username: &str, _channel_id: u32, _message: &str) -> bool {
+        true
+    }
+
+    fn on_leave(&mut self, _username: &str) {}
+
+    /// `false` rejects the new mask
+    fn on_mask(
+        &mut self,
+        _addr: SocketAddr,
+        _old_mask: Option<&str>,
+        _mask: &str,
+        _channel_id: u32,
+    ) -> bool {
+        true
+    }
+
+    fn on_channel_create(&mut self, _channel_id: u32, _name: Option<&str>) {}
+
+    fn on_channel_delete(&mut self, _channel_id: u32, _name: Option<&str>) {}
+
+    /// `on_audio` is only called when this is `true`
+    fn wants_audio(&self) -> bool {
+        false
+    }
+
+    /// A masked remote's frame before it's mixed, `false` drops it
+    fn on_audio(&mut self, _mask: &str, _frame: &mut [f32]) -> bool {
+        true
+    }
+
+    /// Every server tick, with who is where
+    fn on_tick(&mut self, _channels: &[DirectoryChannel]) {}
+}
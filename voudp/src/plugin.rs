@@ -1,18 +1,29 @@
 use std::{
-    net::SocketAddr,
-    path::Path,
+    collections::HashSet,
+    fs,
+    io::{self, Read, Write},
+    mem,
+    net::{SocketAddr, TcpStream, ToSocketAddrs},
+    path::{Path, PathBuf},
+    str::FromStr,
     sync::{
-        Arc,
-        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+        atomic::{AtomicBool, AtomicU8, AtomicU32, AtomicU64, AtomicUsize, Ordering},
         mpsc::Sender,
     },
+    thread,
+    time::{Duration, Instant},
 };
 
 use chrono::Local;
 use log::{error, info, warn};
 use mlua::{Lua, RegistryKey, UserData, UserDataMethods};
 
-use crate::protocol;
+use crate::{
+    native_plugin::{JoinEvent, VoudpPlugin},
+    protocol,
+    util::{CommandCategory, CommandContext, CommandResult, ServerCommand},
+};
 
 pub enum PluginAction {
     Reply {
@@ -23,41 +34,294 @@ pub enum PluginAction {
         to: SocketAddr,
         msg: String,
     },
+    /// Chat from the plugin, to one channel or everyone when `channel_id`
+    /// is `None`
     Broadcast {
+        from: String,
+        channel_id: Option<u32>,
         msg: String,
     },
     Kick {
         user: String,
         reason: Option<String>,
     },
+    Move {
+        user: String,
+        channel: u32,
+    },
+    SetMute {
+        user: String,
+        muted: bool,
+    },
+    SetDeafen {
+        user: String,
+        deafened: bool,
+    },
+}
+
+impl PluginAction {
+    /// What a lua plugin has to declare to take this action
+    pub fn capability(&self) -> Capability {
+        match self {
+            Self::Reply { .. } | Self::ReplyByAddr { .. } | Self::Broadcast { .. } => {
+                Capability::Chat
+            }
+            Self::Kick { .. } => Capability::Kick,
+            Self::Move { .. } | Self::SetMute { .. } | Self::SetDeafen { .. } => {
+                Capability::Moderate
+            }
+        }
+    }
+}
+
+/// What a lua plugin may do, declared in the `capabilities` list of its
+/// `plugin` table. Everything else is denied
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Capability {
+    /// Replies and broadcasts
+    Chat,
+    Kick,
+    /// Moving, muting and deafening users
+    Moderate,
+    /// Hearing and changing audio through `on_audio`
+    Audio,
+    /// Files, through lua's `io` library and the file functions of `os`
+    Storage,
+    /// `Core.http_get`
+    Network,
+}
+
+impl Capability {
+    pub const ALL: [Capability; 6] = [
+        Self::Chat,
+        Self::Kick,
+        Self::Moderate,
+        Self::Audio,
+        Self::Storage,
+        Self::Network,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Chat => "chat",
+            Self::Kick => "kick",
+            Self::Moderate => "moderate",
+            Self::Audio => "audio",
+            Self::Storage => "storage",
+            Self::Network => "network",
+        }
+    }
+
+    fn bit(self) -> u8 {
+        1 << self as u8
+    }
+}
+
+impl FromStr for Capability {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|capability| capability.name() == s)
+            .ok_or_else(|| format!("unknown capability {s:?}"))
+    }
+}
+
+// the capabilities a plugin was loaded with. While its top level runs they're
+// read from the `plugin` table instead, so a script can already use what it
+// declares when loading
+#[derive(Default)]
+struct Sandbox {
+    granted: AtomicU8,
+    loaded: AtomicBool,
+}
+
+impl Sandbox {
+    fn grant(&self, capabilities: &[Capability]) {
+        let bits = capabilities.iter().fold(0, |bits, c| bits | c.bit());
+        self.granted.store(bits, Ordering::SeqCst);
+        self.loaded.store(true, Ordering::SeqCst);
+    }
+
+    fn check(&self, lua: &Lua, capability: Capability) -> mlua::Result<()> {
+        let granted = if self.loaded.load(Ordering::SeqCst) {
+            self.granted.load(Ordering::SeqCst) & capability.bit() != 0
+        } else {
+            declared_capabilities(lua)?.contains(&capability)
+        };
+
+        if granted {
+            Ok(())
+        } else {
+            Err(mlua::Error::runtime(format!(
+                "the plugin didn't declare the {} capability",
+                capability.name()
+            )))
+        }
+    }
+}
+
+fn declared_capabilities(lua: &Lua) -> mlua::Result<Vec<Capability>> {
+    let Some(plugin) = lua.globals().get::<_, Option<mlua::Table>>("plugin")? else {
+        return Ok(vec![]);
+    };
+    plugin
+        .get::<_, Option<Vec<String>>>("capabilities")?
+        .unwrap_or_default()
+        .iter()
+        .map(|name| name.parse().map_err(mlua::Error::runtime))
+        .collect()
+}
+
+// where a lua plugin's actions go, each one checked against its capabilities
+#[derive(Clone)]
+struct Actions {
+    tx: Sender<PluginAction>,
+    sandbox: Arc<Sandbox>,
+}
+
+impl Actions {
+    fn send(&self, lua: &Lua, action: PluginAction) -> mlua::Result<()> {
+        self.sandbox.check(lua, action.capability())?;
+        self.tx.send(action).ok();
+        Ok(())
+    }
+}
+
+// lua functions that reach outside the server, wrapped so they need a
+// capability where the lua build has them at all. The ones that run programs
+// or end the process are removed
+const GUARDED: &[(&str, &str, Capability)] = &[
+    ("io", "close", Capability::Storage),
+    ("io", "input", Capability::Storage),
+    ("io", "lines", Capability::Storage),
+    ("io", "open", Capability::Storage),
+    ("io", "output", Capability::Storage),
+    ("io", "read", Capability::Storage),
+    ("io", "tmpfile", Capability::Storage),
+    ("io", "write", Capability::Storage),
+    ("os", "remove", Capability::Storage),
+    ("os", "rename", Capability::Storage),
+    ("os", "tmpname", Capability::Storage),
+    ("_G", "dofile", Capability::Storage),
+    ("_G", "loadfile", Capability::Storage),
+];
+// loading native code gets around everything else, so no capability covers it
+const REMOVED: &[(&str, &str)] = &[
+    ("io", "popen"),
+    ("os", "execute"),
+    ("os", "exit"),
+    ("package", "loadlib"),
+];
+
+fn sandbox_globals(lua: &Lua, sandbox: &Arc<Sandbox>) -> mlua::Result<()> {
+    let globals = lua.globals();
+    let guard = |table: &mlua::Table, key: mlua::Value, capability| -> mlua::Result<()> {
+        let Some(original) = table.get::<_, Option<mlua::Function>>(key.clone())? else {
+            return Ok(());
+        };
+        let original = lua.create_registry_value(original)?;
+        let sandbox = sandbox.clone();
+        let guarded = lua.create_function(move |lua, args: mlua::MultiValue| {
+            sandbox.check(lua, capability)?;
+            lua.registry_value::<mlua::Function>(&original)?
+                .call::<_, mlua::MultiValue>(args)
+        })?;
+        table.set(key, guarded)
+    };
+
+    for &(table, name, capability) in GUARDED {
+        let table: mlua::Table = if table == "_G" {
+            globals.clone()
+        } else {
+            globals.get(table)?
+        };
+        guard(
+            &table,
+            mlua::Value::String(lua.create_string(name)?),
+            capability,
+        )?;
+    }
+    for &(table, name) in REMOVED {
+        globals.get::<_, mlua::Table>(table)?.set(name, mlua::Nil)?;
+    }
+
+    // the searchers `require` uses for C libraries, the last two
+    let searchers: mlua::Table = globals.get::<_, mlua::Table>("package")?.get("searchers")?;
+    for i in [4, 3] {
+        searchers.set(i, mlua::Nil)?;
+    }
+
+    Ok(())
+}
+
+/// A channel as plugins see it through `Core.list_channels` and
+/// `Core.list_users`, refreshed by the server every tick
+#[derive(Debug, Clone)]
+pub struct DirectoryChannel {
+    pub id: u32,
+    pub name: Option<String>,
+    pub users: Vec<DirectoryUser>,
+}
+
+#[derive(Debug, Clone)]
+pub struct DirectoryUser {
+    pub mask: Option<String>,
+    pub addr: SocketAddr,
+    pub muted: bool,
+    pub deafened: bool,
+    pub admin: bool,
 }
 
+type Directory = Arc<Mutex<Vec<DirectoryChannel>>>;
+// hosts `Core.http_get` may reach, set by the operator
+type HttpHosts = Arc<Mutex<Vec<String>>>;
+
 #[derive(Debug)]
 pub struct PluginMetadata {
     pub name: String,
     pub version: Option<String>,
     pub author: Option<String>,
     pub description: Option<String>,
+    pub capabilities: Vec<Capability>,
 }
 
 pub struct JoinContext {
     pub addr: SocketAddr,
-    pub channel_id: u32,
+    pub mask: Option<String>,
+    // already connected and switching channels
+    pub rejoining: bool,
+    pub users: usize,
+    // where they end up, plugins may move them elsewhere
+    channel_id: Arc<AtomicU32>,
     cancelled: Arc<AtomicBool>,
-    tx: Sender<PluginAction>,
+    tx: Actions,
 }
 
 impl UserData for JoinContext {
     fn add_methods<'lua, M: UserDataMethods<'lua, Self>>(methods: &mut M) {
-        methods.add_method("reply", |_, ctx, msg: String| {
+        methods.add_method("reply", |lua, ctx, msg: String| {
             ctx.tx
-                .send(PluginAction::ReplyByAddr { to: ctx.addr, msg })
-                .ok();
-            Ok(())
+                .send(lua, PluginAction::ReplyByAddr { to: ctx.addr, msg })
         });
         methods.add_method("get_addr", |_, ctx, ()| Ok(ctx.addr.to_string().clone()));
         methods.add_method("get_channel_id", |_, ctx, ()| {
-            Ok(ctx.channel_id.to_string())
+            Ok(ctx.channel_id.load(Ordering::SeqCst).to_string())
+        });
+        methods.add_method("get_mask", |_, ctx, ()| Ok(ctx.mask.clone()));
+        methods.add_method("is_rejoining", |_, ctx, ()| Ok(ctx.rejoining));
+        methods.add_method("get_user_count", |_, ctx, ()| Ok(ctx.users));
+
+        methods.add_method("move_to", |lua, ctx, channel_id: u32| {
+            ctx.tx.sandbox.check(lua, Capability::Moderate)?;
+            if channel_id == 0 || channel_id >= u16::MAX as u32 {
+                return Err(mlua::Error::runtime(format!(
+                    "{channel_id} isn't a valid channel id"
+                )));
+            }
+            ctx.channel_id.store(channel_id, Ordering::SeqCst);
+            Ok(())
         });
 
         methods.add_method("cancel", |_, ctx, ()| {
@@ -69,35 +333,38 @@ impl UserData for JoinContext {
 
 pub struct MessageContext {
     pub username: String,
+    pub channel_id: u32,
     pub message: String,
+    plugin: String,
     cancelled: Arc<AtomicBool>,
-    tx: Sender<PluginAction>,
+    tx: Actions,
 }
 
 impl UserData for MessageContext {
     fn add_methods<'lua, M: UserDataMethods<'lua, Self>>(methods: &mut M) {
         methods.add_method("get_message", |_, ctx, ()| Ok(ctx.message.clone()));
         methods.add_method("get_username", |_, ctx, ()| Ok(ctx.username.clone()));
+        methods.add_method("get_channel_id", |_, ctx, ()| Ok(ctx.channel_id));
 
-        methods.add_method("reply", |_, ctx, msg: String| {
+        methods.add_method("reply", |lua, ctx, msg: String| {
             // info!("relying");
-            ctx.tx
-                .send(PluginAction::Reply {
+            ctx.tx.send(
+                lua,
+                PluginAction::Reply {
                     to: ctx.username.clone(),
                     msg,
-                })
-                .ok();
-            Ok(())
+                },
+            )
         });
 
-        methods.add_method("kick", |_, ctx, reason: String| {
-            ctx.tx
-                .send(PluginAction::Kick {
+        methods.add_method("kick", |lua, ctx, reason: String| {
+            ctx.tx.send(
+                lua,
+                PluginAction::Kick {
                     user: ctx.username.clone(),
                     reason: Some(reason),
-                })
-                .ok();
-            Ok(())
+                },
+            )
         });
 
         methods.add_method("cancel", |_, ctx, ()| {
@@ -105,46 +372,557 @@ impl UserData for MessageContext {
             Ok(())
         });
 
-        methods.add_method("broadcast", |_, _, _: String| {
-            // unimplemeted!();
+        // to the channel the message was sent in
+        methods.add_method("broadcast", |lua, ctx, msg: String| {
+            ctx.tx.send(
+                lua,
+                PluginAction::Broadcast {
+                    from: ctx.plugin.clone(),
+                    channel_id: Some(ctx.channel_id),
+                    msg,
+                },
+            )
+        });
+    }
+}
+
+pub struct MaskContext {
+    pub addr: SocketAddr,
+    pub old_mask: Option<String>,
+    pub mask: String,
+    pub channel_id: u32,
+    cancelled: Arc<AtomicBool>,
+    tx: Actions,
+}
+
+impl UserData for MaskContext {
+    fn add_methods<'lua, M: UserDataMethods<'lua, Self>>(methods: &mut M) {
+        methods.add_method("get_addr", |_, ctx, ()| Ok(ctx.addr.to_string()));
+        methods.add_method("get_old_mask", |_, ctx, ()| Ok(ctx.old_mask.clone()));
+        methods.add_method("get_mask", |_, ctx, ()| Ok(ctx.mask.clone()));
+        methods.add_method("get_channel_id", |_, ctx, ()| Ok(ctx.channel_id));
+
+        methods.add_method("reply", |lua, ctx, msg: String| {
+            ctx.tx
+                .send(lua, PluginAction::ReplyByAddr { to: ctx.addr, msg })
+        });
+
+        // they keep their old mask, or none
+        methods.add_method("cancel", |_, ctx, ()| {
+            ctx.cancelled.store(true, Ordering::SeqCst);
             Ok(())
         });
     }
 }
 
+pub struct ChannelContext {
+    pub channel_id: u32,
+    pub name: Option<String>,
+    plugin: String,
+    tx: Actions,
+}
+
+impl UserData for ChannelContext {
+    fn add_methods<'lua, M: UserDataMethods<'lua, Self>>(methods: &mut M) {
+        methods.add_method("get_channel_id", |_, ctx, ()| Ok(ctx.channel_id));
+        methods.add_method("get_name", |_, ctx, ()| Ok(ctx.name.clone()));
+
+        methods.add_method("broadcast", |lua, ctx, msg: String| {
+            ctx.tx.send(
+                lua,
+                PluginAction::Broadcast {
+                    from: ctx.plugin.clone(),
+                    channel_id: None,
+                    msg,
+                },
+            )
+        });
+    }
+}
+
 pub struct LeaveContext {
     pub username: String,
+    plugin: String,
+    tx: Actions,
 }
 
 impl UserData for LeaveContext {
     fn add_methods<'lua, M: UserDataMethods<'lua, Self>>(methods: &mut M) {
-        methods.add_method("broadcast", |_, _, msg: String| {
-            println!("[broadcast] {}", msg);
+        methods.add_method("get_username", |_, ctx, ()| Ok(ctx.username.clone()));
+
+        methods.add_method("broadcast", |lua, ctx, msg: String| {
+            ctx.tx.send(
+                lua,
+                PluginAction::Broadcast {
+                    from: ctx.plugin.clone(),
+                    channel_id: None,
+                    msg,
+                },
+            )
+        });
+    }
+}
+
+// one decoded frame of a remote's voice, interleaved stereo. indices are
+// 1-based like everything else in lua
+pub struct AudioContext {
+    pub mask: String,
+    samples: Arc<Mutex<Vec<f32>>>,
+    cancelled: Arc<AtomicBool>,
+    tx: Actions,
+}
+
+impl UserData for AudioContext {
+    fn add_methods<'lua, M: UserDataMethods<'lua, Self>>(methods: &mut M) {
+        methods.add_method("get_mask", |_, ctx, ()| Ok(ctx.mask.clone()));
+        methods.add_method("len", |_, ctx, ()| Ok(ctx.samples.lock().unwrap().len()));
+
+        methods.add_method("get", |_, ctx, i: usize| {
+            let samples = ctx.samples.lock().unwrap();
+            i.checked_sub(1)
+                .and_then(|i| samples.get(i).copied())
+                .ok_or_else(|| mlua::Error::runtime(format!("sample {i} is out of range")))
+        });
+
+        methods.add_method("set", |_, ctx, (i, value): (usize, f32)| {
+            let mut samples = ctx.samples.lock().unwrap();
+            let sample = i
+                .checked_sub(1)
+                .and_then(|i| samples.get_mut(i))
+                .ok_or_else(|| mlua::Error::runtime(format!("sample {i} is out of range")))?;
+            *sample = value.clamp(-1.0, 1.0);
             Ok(())
         });
+
+        methods.add_method("get_samples", |_, ctx, ()| {
+            Ok(ctx.samples.lock().unwrap().clone())
+        });
+
+        methods.add_method("set_samples", |_, ctx, new: Vec<f32>| {
+            let mut samples = ctx.samples.lock().unwrap();
+            if new.len() != samples.len() {
+                return Err(mlua::Error::runtime(format!(
+                    "expected {} samples, got {}",
+                    samples.len(),
+                    new.len()
+                )));
+            }
+            for (sample, value) in samples.iter_mut().zip(new) {
+                *sample = value.clamp(-1.0, 1.0);
+            }
+            Ok(())
+        });
+
+        methods.add_method("scale", |_, ctx, gain: f32| {
+            for sample in ctx.samples.lock().unwrap().iter_mut() {
+                *sample = (*sample * gain).clamp(-1.0, 1.0);
+            }
+            Ok(())
+        });
+
+        methods.add_method("get_rms", |_, ctx, ()| {
+            let samples = ctx.samples.lock().unwrap();
+            let sum = samples.iter().map(|s| s * s).sum::<f32>();
+            Ok((sum / samples.len().max(1) as f32).sqrt())
+        });
+
+        methods.add_method("reply", |lua, ctx, msg: String| {
+            ctx.tx.send(
+                lua,
+                PluginAction::Reply {
+                    to: ctx.mask.clone(),
+                    msg,
+                },
+            )
+        });
+
+        methods.add_method("kick", |lua, ctx, reason: String| {
+            ctx.tx.send(
+                lua,
+                PluginAction::Kick {
+                    user: ctx.mask.clone(),
+                    reason: Some(reason),
+                },
+            )
+        });
+
+        // the frame isn't mixed, as if they were silent
+        methods.add_method("cancel", |_, ctx, ()| {
+            ctx.cancelled.store(true, Ordering::SeqCst);
+            Ok(())
+        });
+    }
+}
+
+pub struct CommandCallContext {
+    pub sender_addr: SocketAddr,
+    pub sender_mask: Option<String>,
+    pub channel_id: u32,
+    pub arguments: Vec<String>,
+    pub is_admin: bool,
+    plugin: String,
+    tx: Actions,
+}
+
+impl UserData for CommandCallContext {
+    fn add_methods<'lua, M: UserDataMethods<'lua, Self>>(methods: &mut M) {
+        methods.add_method("get_args", |_, ctx, ()| Ok(ctx.arguments.clone()));
+        methods.add_method("get_mask", |_, ctx, ()| Ok(ctx.sender_mask.clone()));
+        methods.add_method("get_addr", |_, ctx, ()| Ok(ctx.sender_addr.to_string()));
+        methods.add_method("get_channel_id", |_, ctx, ()| Ok(ctx.channel_id));
+        methods.add_method("is_admin", |_, ctx, ()| Ok(ctx.is_admin));
+
+        methods.add_method("reply", |lua, ctx, msg: String| {
+            ctx.tx.send(
+                lua,
+                PluginAction::ReplyByAddr {
+                    to: ctx.sender_addr,
+                    msg,
+                },
+            )
+        });
+
+        methods.add_method("broadcast", |lua, ctx, msg: String| {
+            ctx.tx.send(
+                lua,
+                PluginAction::Broadcast {
+                    from: ctx.plugin.clone(),
+                    channel_id: Some(ctx.channel_id),
+                    msg,
+                },
+            )
+        });
+    }
+}
+
+/// A slash command a plugin registered with `Core.register_command`
+pub struct PluginCommand {
+    pub command: ServerCommand,
+    handler: RegistryKey,
+}
+
+// how often the wheel turns, timers fire at most this late
+const TIMER_RESOLUTION: Duration = Duration::from_millis(50);
+const TIMER_SLOTS: u64 = 256;
+
+struct Timer {
+    id: u64,
+    func: RegistryKey,
+    every: Option<u64>,
+    // the wheel tick it's due at
+    due: u64,
+}
+
+/// A hashed timer wheel for `Core.schedule` and `Core.every`. Timers that are
+/// more than a turn away are skipped until the turn they're due in
+struct TimerWheel {
+    slots: Vec<Vec<Timer>>,
+    started: Instant,
+    ticks: u64,
+    next_id: u64,
+    // taken out of the wheel to be called, cancelling one forgets its id
+    firing: HashSet<u64>,
+}
+
+impl TimerWheel {
+    fn new() -> Self {
+        Self {
+            slots: (0..TIMER_SLOTS).map(|_| Vec::new()).collect(),
+            started: Instant::now(),
+            ticks: 0,
+            next_id: 1,
+            firing: HashSet::new(),
+        }
+    }
+
+    fn ticks_at(&self, at: Instant) -> u64 {
+        (at.duration_since(self.started).as_millis() / TIMER_RESOLUTION.as_millis()) as u64
+    }
+
+    fn add(&mut self, delay: Duration, func: RegistryKey, repeat: bool) -> u64 {
+        let delay = delay
+            .as_millis()
+            .div_ceil(TIMER_RESOLUTION.as_millis())
+            .max(1) as u64;
+        let id = self.next_id;
+        self.next_id += 1;
+
+        // the current tick is partly gone, so one more keeps it from firing early
+        let due = self.ticks_at(Instant::now()).max(self.ticks) + delay + 1;
+        self.insert(Timer {
+            id,
+            func,
+            every: repeat.then_some(delay),
+            due,
+        });
+        id
+    }
+
+    fn insert(&mut self, mut timer: Timer) {
+        timer.due = timer.due.max(self.ticks + 1);
+        self.slots[(timer.due % TIMER_SLOTS) as usize].push(timer);
+    }
+
+    /// `false` if it isn't pending anymore
+    fn cancel(&mut self, id: u64) -> bool {
+        if self.firing.remove(&id) {
+            return true;
+        }
+        for slot in &mut self.slots {
+            if let Some(i) = slot.iter().position(|timer| timer.id == id) {
+                slot.swap_remove(i);
+                return true;
+            }
+        }
+        false
+    }
+
+    /// Turns the wheel up to `now`, handing back whatever is due
+    fn advance(&mut self, now: Instant) -> Vec<Timer> {
+        let mut due = vec![];
+        let target = self.ticks_at(now);
+
+        while self.ticks < target {
+            self.ticks += 1;
+            let slot = &mut self.slots[(self.ticks % TIMER_SLOTS) as usize];
+            let mut i = 0;
+            while i < slot.len() {
+                if slot[i].due <= self.ticks {
+                    due.push(slot.swap_remove(i));
+                } else {
+                    i += 1;
+                }
+            }
+        }
+
+        self.firing.extend(due.iter().map(|timer| timer.id));
+        due
+    }
+
+    /// Puts a fired timer back if it repeats and nobody cancelled it, handing
+    /// back the ones that are done
+    fn rearm(&mut self, mut timer: Timer) -> Option<Timer> {
+        match timer.every {
+            Some(every) if self.firing.remove(&timer.id) => {
+                timer.due += every;
+                self.insert(timer);
+                None
+            }
+            _ => {
+                self.firing.remove(&timer.id);
+                Some(timer)
+            }
+        }
     }
 }
 
+/// Optional list in the plugin directory of which plugins load first and
+/// which don't load at all
+pub const MANIFEST_FILE: &str = "plugins.txt";
+
+// what the manifest says: one file per line, loaded in that order, and a
+// leading `!` for one that must not load
+#[derive(Default)]
+struct Manifest {
+    order: Vec<String>,
+    disabled: HashSet<String>,
+}
+
+impl Manifest {
+    fn parse(text: &str) -> Self {
+        let mut manifest = Self::default();
+        for line in text.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (disabled, file) = match line.strip_prefix('!') {
+                Some(file) => (true, file.trim()),
+                None => (false, line),
+            };
+            let file = if file.ends_with(".lua") {
+                file.to_string()
+            } else {
+                format!("{file}.lua")
+            };
+
+            if disabled {
+                manifest.disabled.insert(file);
+            } else if !manifest.order.contains(&file) {
+                manifest.order.push(file);
+            }
+        }
+        manifest
+    }
+}
+
+// a plugin failing this many times in a row is disabled
+const MAX_CONSECUTIVE_ERRORS: u32 = 10;
+
+const HTTP_TIMEOUT: Duration = Duration::from_secs(5);
+// anything longer is cut off
+const MAX_HTTP_RESPONSE: u64 = 1024 * 1024;
+// each one holds a thread until it's answered or times out
+const MAX_HTTP_IN_FLIGHT: usize = 4;
+
+// a finished `Core.http_get`, handed to its callback on the next tick
+struct HttpResponse {
+    callback: RegistryKey,
+    result: Result<(u16, String), String>,
+}
+
+// "host[:port]" and path of a plain http url
+fn split_http_url(url: &str) -> io::Result<(&str, &str)> {
+    let invalid = || {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "expected http://host[:port]/path",
+        )
+    };
+    if url.starts_with("https://") {
+        return Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "https isn't supported, use a plain http url",
+        ));
+    }
+
+    let rest = url.strip_prefix("http://").ok_or_else(invalid)?;
+    // it goes into the request line as is, so nothing that could end it
+    if rest.chars().any(|c| c.is_whitespace() || c.is_control()) {
+        return Err(invalid());
+    }
+    let (host, path) = rest.split_once('/').unwrap_or((rest, ""));
+    if host.is_empty() {
+        return Err(invalid());
+    }
+    Ok((host, path))
+}
+
+// allowing a host allows its subdomains too
+fn host_allowed(allowed: &[String], host: &str) -> bool {
+    let name = host.split(':').next().unwrap_or(host).to_ascii_lowercase();
+    allowed.iter().any(|allowed| {
+        let allowed = allowed.to_ascii_lowercase();
+        name == allowed || name.ends_with(&format!(".{allowed}"))
+    })
+}
+
+// the status and body of a GET, blocking for up to `HTTP_TIMEOUT` per step
+fn http_get(url: &str) -> io::Result<(u16, String)> {
+    let (host, path) = split_http_url(url)?;
+    let addr = if host.contains(':') {
+        host.to_string()
+    } else {
+        format!("{host}:80")
+    };
+    let addr = addr.to_socket_addrs()?.next().ok_or_else(|| {
+        io::Error::new(io::ErrorKind::NotFound, format!("{host} doesn't resolve"))
+    })?;
+
+    let mut stream = TcpStream::connect_timeout(&addr, HTTP_TIMEOUT)?;
+    stream.set_write_timeout(Some(HTTP_TIMEOUT))?;
+    stream.set_read_timeout(Some(HTTP_TIMEOUT))?;
+    // 1.0 so the body doesn't come chunked
+    write!(
+        stream,
+        "GET /{path} HTTP/1.0\r\nHost: {host}\r\nUser-Agent: voudp\r\nAccept: */*\r\n\r\n"
+    )?;
+
+    let mut response = Vec::new();
+    stream.take(MAX_HTTP_RESPONSE).read_to_end(&mut response)?;
+    let response = String::from_utf8_lossy(&response);
+    let bad = || io::Error::new(io::ErrorKind::InvalidData, "not an http response");
+    let (head, body) = response.split_once("\r\n\r\n").ok_or_else(bad)?;
+    let status = head
+        .split_whitespace()
+        .nth(1)
+        .and_then(|status| status.parse().ok())
+        .ok_or_else(bad)?;
+    Ok((status, body.to_string()))
+}
+
 pub struct Plugin {
     pub metadata: PluginMetadata,
+    pub path: PathBuf,
     pub lua: Lua,
     pub on_join: Option<RegistryKey>,
     pub on_message: Option<RegistryKey>,
     pub on_leave: Option<RegistryKey>,
+    pub on_audio: Option<RegistryKey>,
+    pub on_mask: Option<RegistryKey>,
+    pub on_channel_create: Option<RegistryKey>,
+    pub on_channel_delete: Option<RegistryKey>,
+    pub on_tick: Option<RegistryKey>,
+    pub commands: Vec<PluginCommand>,
+    timers: Arc<Mutex<TimerWheel>>,
+    responses: Arc<Mutex<Vec<HttpResponse>>>,
+    actions: Actions,
+    errors: AtomicU32,
+    disabled: AtomicBool,
 }
 
 impl Plugin {
-    pub fn load(path: &Path) -> mlua::Result<Self> {
+    pub fn load(
+        path: &Path,
+        tx: Sender<PluginAction>,
+        directory: Directory,
+        http_hosts: HttpHosts,
+    ) -> mlua::Result<Self> {
         let lua = Lua::new();
-
-        let code = std::fs::read_to_string(path)?;
-        lua.load(&code).exec()?;
+        let code = fs::read_to_string(path)?;
+        let sandbox = Arc::new(Sandbox::default());
+        let actions = Actions {
+            tx,
+            sandbox: sandbox.clone(),
+        };
+        // only filled while the plugin's top level runs
+        let registered = Arc::new(Mutex::new(Some(Vec::new())));
+        let timers = Arc::new(Mutex::new(TimerWheel::new()));
+        let responses = Arc::new(Mutex::new(Vec::new()));
 
         // Everything that borrows `lua` lives in this block
-        let (metadata, on_join, on_message, on_leave) = {
+        let metadata = {
             let globals = lua.globals();
+            sandbox_globals(&lua, &sandbox)?;
 
             let core = lua.create_table()?;
+            let commands = registered.clone();
+            core.set(
+                "register_command",
+                lua.create_function(
+                    move |lua,
+                          (name, usage, admin_only, handler): (
+                        String,
+                        String,
+                        bool,
+                        mlua::Function,
+                    )| {
+                        let mut commands = commands.lock().unwrap();
+                        let commands = commands.as_mut().ok_or_else(|| {
+                            mlua::Error::runtime("commands can only be registered on load")
+                        })?;
+
+                        let name = format!("/{}", name.trim_start_matches('/'));
+                        if name.len() == 1 || name.contains(char::is_whitespace) {
+                            return Err(mlua::Error::runtime(format!("bad command name {name:?}")));
+                        }
+
+                        commands.push(PluginCommand {
+                            command: ServerCommand {
+                                name,
+                                description: String::new(),
+                                usage,
+                                category: CommandCategory::Utility,
+                                aliases: vec![],
+                                requires_auth: false,
+                                admin_only,
+                                args: vec![],
+                            },
+                            handler: lua.create_registry_value(handler)?,
+                        });
+                        Ok(())
+                    },
+                )?,
+            )?;
             core.set(
                 "starts_with",
                 lua.create_function(|_, (s, prefix): (String, String)| Ok(s.starts_with(&prefix)))?,
@@ -157,8 +935,152 @@ impl Plugin {
                 })?,
             )?;
 
+            let wheel = timers.clone();
+            core.set(
+                "schedule",
+                lua.create_function(move |lua, (seconds, func): (f64, mlua::Function)| {
+                    let delay = Duration::try_from_secs_f64(seconds).unwrap_or_default();
+                    let func = lua.create_registry_value(func)?;
+                    Ok(wheel.lock().unwrap().add(delay, func, false))
+                })?,
+            )?;
+
+            let wheel = timers.clone();
+            core.set(
+                "every",
+                lua.create_function(move |lua, (seconds, func): (f64, mlua::Function)| {
+                    let period = Duration::try_from_secs_f64(seconds)
+                        .ok()
+                        .filter(|period| !period.is_zero())
+                        .ok_or_else(|| {
+                            mlua::Error::runtime(format!("can't repeat every {seconds} seconds"))
+                        })?;
+                    let func = lua.create_registry_value(func)?;
+                    Ok(wheel.lock().unwrap().add(period, func, true))
+                })?,
+            )?;
+
+            let wheel = timers.clone();
+            core.set(
+                "cancel",
+                lua.create_function(move |_, id: u64| Ok(wheel.lock().unwrap().cancel(id)))?,
+            )?;
+
+            let tx = actions.clone();
+            core.set(
+                "move",
+                lua.create_function(move |lua, (user, channel): (String, u32)| {
+                    if channel == 0 || channel >= u16::MAX as u32 {
+                        return Err(mlua::Error::runtime(format!(
+                            "{channel} isn't a valid channel id"
+                        )));
+                    }
+                    tx.send(lua, PluginAction::Move { user, channel })
+                })?,
+            )?;
+
+            let tx = actions.clone();
+            core.set(
+                "set_mute",
+                lua.create_function(move |lua, (user, muted): (String, bool)| {
+                    tx.send(lua, PluginAction::SetMute { user, muted })
+                })?,
+            )?;
+
+            let tx = actions.clone();
+            core.set(
+                "set_deafen",
+                lua.create_function(move |lua, (user, deafened): (String, bool)| {
+                    tx.send(lua, PluginAction::SetDeafen { user, deafened })
+                })?,
+            )?;
+
+            let channels = directory.clone();
+            core.set(
+                "list_channels",
+                lua.create_function(move |lua, ()| {
+                    let list = lua.create_table()?;
+                    for channel in channels.lock().unwrap().iter() {
+                        let entry = lua.create_table()?;
+                        entry.set("id", channel.id)?;
+                        entry.set("name", channel.name.clone())?;
+                        entry.set("users", channel.users.len())?;
+                        list.push(entry)?;
+                    }
+                    Ok(list)
+                })?,
+            )?;
+
+            core.set(
+                "list_users",
+                lua.create_function(move |lua, channel_id: Option<u32>| {
+                    let list = lua.create_table()?;
+                    let channels = directory.lock().unwrap();
+                    let channels = channels
+                        .iter()
+                        .filter(|channel| channel_id.is_none_or(|id| id == channel.id));
+                    for channel in channels {
+                        for user in &channel.users {
+                            let entry = lua.create_table()?;
+                            entry.set("mask", user.mask.clone())?;
+                            entry.set("addr", user.addr.to_string())?;
+                            entry.set("channel_id", channel.id)?;
+                            entry.set("muted", user.muted)?;
+                            entry.set("deafened", user.deafened)?;
+                            entry.set("admin", user.admin)?;
+                            list.push(entry)?;
+                        }
+                    }
+                    Ok(list)
+                })?,
+            )?;
+
+            let network = sandbox.clone();
+            let finished = responses.clone();
+            let in_flight = Arc::new(AtomicUsize::new(0));
+            core.set(
+                "http_get",
+                lua.create_function(move |lua, (url, callback): (String, mlua::Function)| {
+                    network.check(lua, Capability::Network)?;
+                    let (host, _) =
+                        split_http_url(&url).map_err(|e| mlua::Error::runtime(e.to_string()))?;
+                    if !host_allowed(&http_hosts.lock().unwrap(), host) {
+                        return Err(mlua::Error::runtime(format!(
+                            "{host} isn't on the server's http allowlist"
+                        )));
+                    }
+
+                    if in_flight
+                        .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |count| {
+                            (count < MAX_HTTP_IN_FLIGHT).then_some(count + 1)
+                        })
+                        .is_err()
+                    {
+                        return Err(mlua::Error::runtime(format!(
+                            "already {MAX_HTTP_IN_FLIGHT} http requests in flight, wait for one to finish"
+                        )));
+                    }
+
+                    let callback = lua.create_registry_value(callback)?;
+                    let finished = finished.clone();
+                    let in_flight = in_flight.clone();
+                    thread::spawn(move || {
+                        let result = http_get(&url).map_err(|e| e.to_string());
+                        finished
+                            .lock()
+                            .unwrap()
+                            .push(HttpResponse { callback, result });
+                        in_flight.fetch_sub(1, Ordering::SeqCst);
+                    });
+                    Ok(())
+                })?,
+            )?;
+
             core.set("LOOPBACK", "127.0.0.1")?;
             core.set("PROTOCOL_VERSION", protocol::VERSION)?;
+            globals.set("Core", core.clone())?;
+
+            lua.load(&code).exec()?;
 
             // --- metadata ---
             let plugin_table: mlua::Table = globals.get("plugin")?;
@@ -168,7 +1090,25 @@ impl Plugin {
                 version: plugin_table.get("version").ok(),
                 author: plugin_table.get("author").ok(),
                 description: plugin_table.get("description").ok(),
+                capabilities: declared_capabilities(&lua)?,
             };
+            sandbox.grant(&metadata.capabilities);
+
+            let name = metadata.name.clone();
+            let tx = actions.clone();
+            core.set(
+                "broadcast",
+                lua.create_function(move |lua, (msg, channel_id): (String, Option<u32>)| {
+                    tx.send(
+                        lua,
+                        PluginAction::Broadcast {
+                            from: name.clone(),
+                            channel_id,
+                            msg,
+                        },
+                    )
+                })?,
+            )?;
 
             let name = metadata.name.clone();
             core.set(
@@ -199,51 +1139,267 @@ impl Plugin {
 
             globals.set("Core", core)?;
 
-            // --- callbacks ---
-            let on_join = globals
-                .get::<_, mlua::Function>("on_join")
-                .ok()
-                .map(|f| lua.create_registry_value(f))
-                .transpose()?;
+            metadata
+        };
 
-            let on_message = globals
-                .get::<_, mlua::Function>("on_message")
-                .ok()
-                .map(|f| lua.create_registry_value(f))
-                .transpose()?;
+        let mut commands = registered.lock().unwrap().take().unwrap_or_default();
+        for command in &mut commands {
+            command.command.description = format!("From the {} plugin", metadata.name);
+        }
 
-            let on_leave = globals
-                .get::<_, mlua::Function>("on_leave")
+        // --- callbacks ---
+        let callback = |name: &str| {
+            lua.globals()
+                .get::<_, mlua::Function>(name)
                 .ok()
                 .map(|f| lua.create_registry_value(f))
-                .transpose()?;
-
-            (metadata, on_join, on_message, on_leave)
+                .transpose()
         };
 
+        let on_audio = callback("on_audio")?;
+        if on_audio.is_some() && !metadata.capabilities.contains(&Capability::Audio) {
+            return Err(mlua::Error::runtime("on_audio needs the audio capability"));
+        }
+
         Ok(Self {
             metadata,
+            path: path.to_path_buf(),
+            on_join: callback("on_join")?,
+            on_message: callback("on_message")?,
+            on_leave: callback("on_leave")?,
+            on_audio,
+            on_mask: callback("on_mask")?,
+            on_channel_create: callback("on_channel_create")?,
+            on_channel_delete: callback("on_channel_delete")?,
+            on_tick: callback("on_tick")?,
             lua,
-            on_join,
-            on_message,
-            on_leave,
+            commands,
+            timers,
+            responses,
+            actions,
+            errors: AtomicU32::new(0),
+            disabled: AtomicBool::new(false),
         })
     }
+
+    pub fn is_enabled(&self) -> bool {
+        !self.disabled.load(Ordering::SeqCst)
+    }
+
+    fn set_enabled(&self, enabled: bool) {
+        self.errors.store(0, Ordering::SeqCst);
+        self.disabled.store(!enabled, Ordering::SeqCst);
+    }
+
+    /// Logs a failed hook, disabling the plugin once it keeps failing
+    fn check<T>(&self, hook: &str, result: mlua::Result<T>) -> Option<T> {
+        let e = match result {
+            Ok(value) => {
+                self.errors.store(0, Ordering::SeqCst);
+                return Some(value);
+            }
+            Err(e) => e,
+        };
+
+        let name = &self.metadata.name;
+        error!("{name} {hook} error: {e}");
+        let errors = self.errors.fetch_add(1, Ordering::SeqCst) + 1;
+        if errors >= MAX_CONSECUTIVE_ERRORS && !self.disabled.swap(true, Ordering::SeqCst) {
+            error!(
+                "{name} failed {errors} times in a row and was disabled, `plugins enable {name}` turns it back on"
+            );
+        }
+        None
+    }
 }
 
 pub struct PluginManager {
     plugins: Vec<Plugin>,
+    // compiled in, never reloaded or disabled
+    native: Vec<Mutex<Box<dyn VoudpPlugin>>>,
     sender: Sender<PluginAction>,
+    dir: Option<PathBuf>,
+    // files the last scan didn't load, and why
+    skipped: Vec<(String, String)>,
+    directory: Directory,
+    http_hosts: HttpHosts,
+    started: Instant,
+    // whole seconds since `started` that on_tick was last called for
+    ticked: AtomicU64,
 }
 
 impl PluginManager {
     pub fn new(sender: Sender<PluginAction>) -> Self {
         Self {
             plugins: Vec::new(),
+            native: Vec::new(),
             sender,
+            dir: None,
+            skipped: Vec::new(),
+            directory: Arc::default(),
+            http_hosts: Arc::default(),
+            started: Instant::now(),
+            ticked: AtomicU64::new(0),
         }
     }
 
+    /// Hosts `Core.http_get` may reach, each covering its subdomains. Nothing
+    /// is allowed until this is set
+    pub fn set_http_hosts(&mut self, hosts: Vec<String>) {
+        *self.http_hosts.lock().unwrap() = hosts;
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.plugins.is_empty() && self.native.is_empty()
+    }
+
+    pub fn register_native(&mut self, mut plugin: Box<dyn VoudpPlugin>) {
+        plugin.on_load(self.sender.clone());
+        info!("Registered native plugin: {}", plugin.name());
+        self.native.push(Mutex::new(plugin));
+    }
+
+    pub fn native_names(&self) -> Vec<String> {
+        self.native
+            .iter()
+            .map(|plugin| plugin.lock().unwrap().name().to_string())
+            .collect()
+    }
+
+    /// Replaces the channels and users plugins can list
+    pub fn set_directory(&self, channels: Vec<DirectoryChannel>) {
+        *self.directory.lock().unwrap() = channels;
+    }
+
+    pub fn names(&self) -> Vec<&str> {
+        self.plugins
+            .iter()
+            .map(|plugin| plugin.metadata.name.as_str())
+            .collect()
+    }
+
+    pub fn is_enabled(&self, name: &str) -> bool {
+        self.enabled()
+            .any(|plugin| plugin.metadata.name.eq_ignore_ascii_case(name))
+    }
+
+    fn enabled(&self) -> impl Iterator<Item = &Plugin> {
+        self.plugins.iter().filter(|plugin| plugin.is_enabled())
+    }
+
+    /// Turns a plugin off or back on, by hand or after `check` disabled it
+    pub fn set_enabled(&self, name: &str, enabled: bool) -> Result<(), String> {
+        let plugin = self
+            .plugins
+            .iter()
+            .find(|plugin| plugin.metadata.name.eq_ignore_ascii_case(name))
+            .ok_or_else(|| format!("no plugin named {name}"))?;
+        plugin.set_enabled(enabled);
+        Ok(())
+    }
+
+    /// Loads every plugin in `dir`, those listed in its `MANIFEST_FILE` first
+    /// and in that order, the rest alphabetically. `dir` is remembered for
+    /// `reload_all`
+    pub fn load_dir(&mut self, dir: &Path) {
+        self.dir = Some(dir.to_path_buf());
+        self.skipped.clear();
+        if !dir.is_dir() {
+            warn!("Directory `{}` does not exist", dir.display());
+            return;
+        }
+
+        let mut files = match fs::read_dir(dir) {
+            Ok(entries) => entries
+                .flatten()
+                .map(|entry| entry.file_name().to_string_lossy().into_owned())
+                .filter(|file| file.ends_with(".lua"))
+                .collect::<Vec<_>>(),
+            Err(e) => {
+                error!("Failed to read plugins directory: {e}");
+                return;
+            }
+        };
+        files.sort();
+
+        let manifest = match fs::read_to_string(dir.join(MANIFEST_FILE)) {
+            Ok(text) => Manifest::parse(&text),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Manifest::default(),
+            Err(e) => {
+                error!("Failed to read {MANIFEST_FILE}: {e}");
+                Manifest::default()
+            }
+        };
+        for file in &manifest.order {
+            if !files.contains(file) {
+                self.skipped.push((
+                    file.clone(),
+                    format!("listed in {MANIFEST_FILE} but missing"),
+                ));
+            }
+        }
+        files.sort_by_key(|file| {
+            manifest
+                .order
+                .iter()
+                .position(|listed| listed == file)
+                .unwrap_or(usize::MAX)
+        });
+
+        for file in files {
+            if manifest.disabled.contains(&file) {
+                self.skipped
+                    .push((file, format!("disabled in {MANIFEST_FILE}")));
+            } else if file.ends_with(".d.lua") {
+                // lsp stubs like core.d.lua
+                self.skipped.push((file, "type definitions".into()));
+            } else {
+                self.load_plugin(&dir.join(file));
+            }
+        }
+    }
+
+    /// Files the last `load_dir` left out, with the reason
+    pub fn skipped(&self) -> &[(String, String)] {
+        &self.skipped
+    }
+
+    /// Drops every plugin and loads the plugin directory again, picking up
+    /// new and removed files
+    pub fn reload_all(&mut self) {
+        self.plugins.clear();
+        if let Some(dir) = self.dir.clone() {
+            self.load_dir(&dir);
+        }
+        self.log_loaded();
+    }
+
+    /// Re-reads one plugin's file. If it doesn't load anymore the old one is
+    /// kept running
+    pub fn reload_plugin(&mut self, name: &str) -> Result<(), String> {
+        let plugin = self
+            .plugins
+            .iter_mut()
+            .find(|plugin| plugin.metadata.name.eq_ignore_ascii_case(name))
+            .ok_or_else(|| format!("no plugin named {name}"))?;
+
+        let reloaded = Plugin::load(
+            &plugin.path,
+            self.sender.clone(),
+            self.directory.clone(),
+            self.http_hosts.clone(),
+        )
+        .map_err(|e| {
+            format!(
+                "{} failed to reload, kept the old one: {e}",
+                plugin.path.display()
+            )
+        })?;
+        info!("Reloaded plugin: {}", reloaded.metadata.name);
+        *plugin = reloaded;
+        Ok(())
+    }
+
     pub fn log_loaded(&mut self) {
         let count = self.plugins.len();
 
@@ -254,10 +1410,18 @@ impl PluginManager {
             .collect::<Vec<String>>();
 
         info!("Plugins ({count}): {}", plugins_info.join(", "));
+        for (file, reason) in &self.skipped {
+            info!("Skipped {file}: {reason}");
+        }
     }
 
     pub fn load_plugin(&mut self, path: &Path) {
-        match Plugin::load(path) {
+        match Plugin::load(
+            path,
+            self.sender.clone(),
+            self.directory.clone(),
+            self.http_hosts.clone(),
+        ) {
             Ok(plugin) => {
                 info!(
                     "Loaded plugin: {} {} {} {}",
@@ -278,18 +1442,61 @@ impl PluginManager {
                         "".into()
                     }
                 );
+                let capabilities = plugin
+                    .metadata
+                    .capabilities
+                    .iter()
+                    .map(|capability| capability.name())
+                    .collect::<Vec<_>>();
+                if capabilities.is_empty() {
+                    info!("\tCapabilities: none");
+                } else {
+                    info!("\tCapabilities: {}", capabilities.join(", "));
+                }
                 self.plugins.push(plugin);
             }
             Err(e) => {
                 error!("Failed to load plugin {:?}: {}", path, e);
+                let file = path.file_name().unwrap_or(path.as_os_str());
+                self.skipped.push((
+                    file.to_string_lossy().into_owned(),
+                    format!(
+                        "failed to load: {}",
+                        e.to_string().lines().next().unwrap_or_default()
+                    ),
+                ));
             }
         }
     }
 
-    pub fn dispatch_join(&self, addr: SocketAddr, channel_id: u32) -> bool {
+    /// Runs on every join, also when a connected remote switches channels.
+    /// Gives back the channel they should end up in, `None` if a plugin
+    /// cancelled the join
+    pub fn dispatch_join(
+        &self,
+        addr: SocketAddr,
+        channel_id: u32,
+        mask: Option<&str>,
+        rejoining: bool,
+        users: usize,
+    ) -> Option<u32> {
+        let mut join = JoinEvent {
+            addr,
+            channel_id,
+            mask,
+            rejoining,
+            users,
+        };
+        for plugin in &self.native {
+            if !plugin.lock().unwrap().on_join(&mut join) {
+                return None;
+            }
+        }
+
         let cancelled = Arc::new(AtomicBool::new(false)); // joining isnt cancelled by default
+        let channel_id = Arc::new(AtomicU32::new(join.channel_id));
 
-        for plugin in &self.plugins {
+        for plugin in self.enabled() {
             if let Some(key) = &plugin.on_join {
                 let func: mlua::Function = match plugin.lua.registry_value(key) {
                     Ok(f) => f,
@@ -301,28 +1508,39 @@ impl PluginManager {
 
                 let ctx = JoinContext {
                     addr,
-                    channel_id,
+                    mask: mask.map(str::to_string),
+                    rejoining,
+                    users,
+                    channel_id: channel_id.clone(),
                     cancelled: cancelled.clone(),
-                    tx: self.sender.clone(),
+                    tx: plugin.actions.clone(),
                 };
 
-                if let Err(e) = func.call::<_, ()>(ctx) {
-                    error!("{} on_join error: {}", plugin.metadata.name, e);
-                }
+                plugin.check("on_join", func.call::<_, ()>(ctx));
 
                 if cancelled.load(Ordering::SeqCst) {
-                    return false;
+                    return None;
                 }
             }
         }
-        true
+        Some(channel_id.load(Ordering::SeqCst))
     }
 
-    pub fn dispatch_message(&self, username: &str, message: &str) -> bool {
+    pub fn dispatch_message(&self, username: &str, channel_id: u32, message: &str) -> bool {
         // return type means if it is cancelled
+        for plugin in &self.native {
+            if !plugin
+                .lock()
+                .unwrap()
+                .on_message(username, channel_id, message)
+            {
+                return false;
+            }
+        }
+
         let cancelled = Arc::new(AtomicBool::new(false)); // message isnt cancelled by default
 
-        for plugin in &self.plugins {
+        for plugin in self.enabled() {
             if let Some(key) = &plugin.on_message {
                 let func: mlua::Function = match plugin.lua.registry_value(key) {
                     Ok(f) => f,
@@ -334,14 +1552,14 @@ impl PluginManager {
 
                 let ctx = MessageContext {
                     username: username.to_string(),
+                    channel_id,
                     message: message.to_string(),
+                    plugin: plugin.metadata.name.clone(),
                     cancelled: cancelled.clone(),
-                    tx: self.sender.clone(),
+                    tx: plugin.actions.clone(),
                 };
 
-                if let Err(e) = func.call::<_, ()>(ctx) {
-                    error!("{} on_message error: {}", plugin.metadata.name, e);
-                }
+                plugin.check("on_message", func.call::<_, ()>(ctx));
 
                 if cancelled.load(Ordering::SeqCst) {
                     return false;
@@ -353,7 +1571,11 @@ impl PluginManager {
     }
 
     pub fn dispatch_leave(&self, username: &str) {
-        for plugin in &self.plugins {
+        for plugin in &self.native {
+            plugin.lock().unwrap().on_leave(username);
+        }
+
+        for plugin in self.enabled() {
             if let Some(key) = &plugin.on_leave {
                 let func: mlua::Function = match plugin.lua.registry_value(key) {
                     Ok(f) => f,
@@ -365,12 +1587,292 @@ impl PluginManager {
 
                 let ctx = LeaveContext {
                     username: username.to_string(),
+                    plugin: plugin.metadata.name.clone(),
+                    tx: plugin.actions.clone(),
+                };
+
+                plugin.check("on_leave", func.call::<_, ()>(ctx));
+            }
+        }
+    }
+
+    /// `false` if a plugin rejected the new mask
+    pub fn dispatch_mask(
+        &self,
+        addr: SocketAddr,
+        old_mask: Option<&str>,
+        mask: &str,
+        channel_id: u32,
+    ) -> bool {
+        for plugin in &self.native {
+            if !plugin
+                .lock()
+                .unwrap()
+                .on_mask(addr, old_mask, mask, channel_id)
+            {
+                return false;
+            }
+        }
+
+        let cancelled = Arc::new(AtomicBool::new(false));
+
+        for plugin in self.enabled() {
+            if let Some(key) = &plugin.on_mask {
+                let func: mlua::Function = match plugin.lua.registry_value(key) {
+                    Ok(f) => f,
+                    Err(e) => {
+                        error!("{}: {}", plugin.metadata.name, e);
+                        continue;
+                    }
                 };
 
-                if let Err(e) = func.call::<_, ()>(ctx) {
-                    error!("{} on_leave error: {}", plugin.metadata.name, e);
+                let ctx = MaskContext {
+                    addr,
+                    old_mask: old_mask.map(str::to_string),
+                    mask: mask.to_string(),
+                    channel_id,
+                    cancelled: cancelled.clone(),
+                    tx: plugin.actions.clone(),
+                };
+
+                plugin.check("on_mask", func.call::<_, ()>(ctx));
+
+                if cancelled.load(Ordering::SeqCst) {
+                    return false;
                 }
             }
         }
+
+        true
+    }
+
+    pub fn dispatch_channel_create(&self, channel_id: u32, name: Option<&str>) {
+        for plugin in &self.native {
+            plugin.lock().unwrap().on_channel_create(channel_id, name);
+        }
+        self.dispatch_channel(channel_id, name, |plugin| &plugin.on_channel_create);
+    }
+
+    pub fn dispatch_channel_delete(&self, channel_id: u32, name: Option<&str>) {
+        for plugin in &self.native {
+            plugin.lock().unwrap().on_channel_delete(channel_id, name);
+        }
+        self.dispatch_channel(channel_id, name, |plugin| &plugin.on_channel_delete);
+    }
+
+    fn dispatch_channel(
+        &self,
+        channel_id: u32,
+        name: Option<&str>,
+        hook: impl Fn(&Plugin) -> &Option<RegistryKey>,
+    ) {
+        for plugin in self.enabled() {
+            if let Some(key) = hook(plugin) {
+                let func: mlua::Function = match plugin.lua.registry_value(key) {
+                    Ok(f) => f,
+                    Err(e) => {
+                        error!("{}: {}", plugin.metadata.name, e);
+                        continue;
+                    }
+                };
+
+                let ctx = ChannelContext {
+                    channel_id,
+                    name: name.map(str::to_string),
+                    plugin: plugin.metadata.name.clone(),
+                    tx: plugin.actions.clone(),
+                };
+
+                plugin.check("channel hook", func.call::<_, ()>(ctx));
+            }
+        }
+    }
+
+    /// Runs every frame a masked remote talks, before it's mixed. Plugins may
+    /// change `frame` in place, `false` if one cancelled it
+    pub fn dispatch_audio(&self, mask: &str, frame: &mut [f32]) -> bool {
+        for plugin in &self.native {
+            let mut plugin = plugin.lock().unwrap();
+            if plugin.wants_audio() && !plugin.on_audio(mask, frame) {
+                return false;
+            }
+        }
+
+        if self.enabled().all(|plugin| plugin.on_audio.is_none()) {
+            return true;
+        }
+
+        let cancelled = Arc::new(AtomicBool::new(false));
+        let samples = Arc::new(Mutex::new(frame.to_vec()));
+
+        for plugin in self.enabled() {
+            if let Some(key) = &plugin.on_audio {
+                let func: mlua::Function = match plugin.lua.registry_value(key) {
+                    Ok(f) => f,
+                    Err(e) => {
+                        error!("{}: {}", plugin.metadata.name, e);
+                        continue;
+                    }
+                };
+
+                let ctx = AudioContext {
+                    mask: mask.to_string(),
+                    samples: samples.clone(),
+                    cancelled: cancelled.clone(),
+                    tx: plugin.actions.clone(),
+                };
+
+                plugin.check("on_audio", func.call::<_, ()>(ctx));
+
+                if cancelled.load(Ordering::SeqCst) {
+                    return false;
+                }
+            }
+        }
+
+        frame.copy_from_slice(&samples.lock().unwrap());
+        true
+    }
+
+    /// Fires the plugins' due timers, hands finished http requests to their
+    /// callbacks and calls on_tick once a second, called every server tick
+    pub fn run_timers(&self) {
+        if !self.native.is_empty() {
+            let channels = self.directory.lock().unwrap();
+            for plugin in &self.native {
+                plugin.lock().unwrap().on_tick(&channels);
+            }
+        }
+
+        let now = Instant::now();
+        // a stalled server skips seconds rather than catching up
+        let seconds = now.duration_since(self.started).as_secs();
+        let new_second = self.ticked.swap(seconds, Ordering::SeqCst) != seconds;
+
+        for plugin in self.enabled() {
+            if let Some(key) = &plugin.on_tick
+                && new_second
+            {
+                match plugin.lua.registry_value::<mlua::Function>(key) {
+                    Ok(func) => {
+                        plugin.check("on_tick", func.call::<_, ()>(seconds));
+                    }
+                    Err(e) => error!("{}: {}", plugin.metadata.name, e),
+                }
+            }
+
+            let due = plugin.timers.lock().unwrap().advance(now);
+            for timer in due {
+                // called unlocked, a timer may well add or cancel others
+                match plugin.lua.registry_value::<mlua::Function>(&timer.func) {
+                    Ok(func) => {
+                        plugin.check("timer", func.call::<_, ()>(()));
+                    }
+                    Err(e) => error!("{}: {}", plugin.metadata.name, e),
+                }
+
+                let done = plugin.timers.lock().unwrap().rearm(timer);
+                if let Some(timer) = done {
+                    plugin.lua.remove_registry_value(timer.func).ok();
+                }
+            }
+
+            let finished = mem::take(&mut *plugin.responses.lock().unwrap());
+            for response in finished {
+                let lua = &plugin.lua;
+                let result = lua
+                    .registry_value::<mlua::Function>(&response.callback)
+                    .and_then(|func| match response.result {
+                        Ok((status, body)) => {
+                            let table = lua.create_table()?;
+                            table.set("status", status)?;
+                            table.set("body", body)?;
+                            func.call::<_, ()>(table)
+                        }
+                        Err(e) => func.call::<_, ()>((mlua::Nil, e)),
+                    });
+                plugin.check("http_get", result);
+                lua.remove_registry_value(response.callback).ok();
+            }
+        }
+    }
+
+    pub fn commands(&self) -> impl Iterator<Item = &ServerCommand> {
+        self.plugins
+            .iter()
+            .flat_map(|plugin| &plugin.commands)
+            .map(|command| &command.command)
+    }
+
+    /// Drops a plugin's command, for when its name is already taken
+    pub fn unregister_command(&mut self, name: &str) {
+        for plugin in &mut self.plugins {
+            plugin
+                .commands
+                .retain(|command| command.command.name != name);
+        }
+    }
+
+    /// Runs `name` if a plugin owns it. The handler's return value is the
+    /// reply, raising an error fails the command
+    pub fn dispatch_command(&self, name: &str, ctx: &CommandContext) -> Option<CommandResult> {
+        let (plugin, command) = self.plugins.iter().find_map(|plugin| {
+            plugin
+                .commands
+                .iter()
+                .find(|command| command.command.name == name)
+                .map(|command| (plugin, command))
+        })?;
+        if !plugin.is_enabled() {
+            return Some(CommandResult::Error(format!(
+                "The {} plugin is disabled.",
+                plugin.metadata.name
+            )));
+        }
+        if let Err(denied) = command.command.authorize(ctx) {
+            return Some(denied);
+        }
+
+        let result = plugin
+            .lua
+            .registry_value::<mlua::Function>(&command.handler)
+            .and_then(|func| {
+                func.call::<_, Option<String>>(CommandCallContext {
+                    sender_addr: ctx.sender_addr,
+                    sender_mask: ctx.sender_mask.clone(),
+                    channel_id: ctx.channel_id,
+                    arguments: ctx.arguments.clone(),
+                    is_admin: ctx.is_admin(),
+                    plugin: plugin.metadata.name.clone(),
+                    tx: plugin.actions.clone(),
+                })
+            });
+
+        Some(match result {
+            Ok(Some(reply)) => CommandResult::Success(reply),
+            Ok(None) => CommandResult::Silent,
+            // raising is how commands fail, so it doesn't count towards disabling
+            Err(e) => {
+                error!("{} {name} error: {}", plugin.metadata.name, e);
+                CommandResult::Error(command_error(&e))
+            }
+        })
+    }
+}
+
+// the message a handler raised, without lua's location and traceback
+fn command_error(e: &mlua::Error) -> String {
+    let message = match e {
+        mlua::Error::CallbackError { cause, .. } => return command_error(cause),
+        mlua::Error::RuntimeError(message) => message.lines().next().unwrap_or_default(),
+        _ => return "The command failed.".to_string(),
+    };
+    // "[string \"...\"]:12: message"
+    match message.split_once("]:") {
+        Some((_, rest)) => rest
+            .split_once(": ")
+            .map_or(rest, |(_, message)| message)
+            .to_string(),
+        None => message.to_string(),
     }
 }
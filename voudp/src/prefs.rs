@@ -0,0 +1,249 @@
+// Client preferences kept between sessions, so the CLI and GUI start where the
+// user left off. Stored as a small flat TOML file, only the handful of value
+// types below are understood and unknown keys are skipped.
+use std::{
+    collections::BTreeMap,
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+pub const PREFS_FILE: &str = "client.toml";
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ClientPrefs {
+    pub server: Option<String>,
+    pub nick: Option<String>,
+    pub channel_id: Option<u32>,
+    pub input_device: Option<String>,
+    pub output_device: Option<String>,
+    pub muted: bool,
+    pub deafened: bool,
+    // the key held to talk, push-to-talk is off without one
+    pub push_to_talk: Option<String>,
+    pub notify: NotifyPrefs,
+    // mask -> gain we hear them at
+    pub volumes: BTreeMap<String, f32>,
+}
+
+/// What gets a sound and, while the window is in the background, a desktop
+/// notification
+#[derive(Debug, Clone, PartialEq)]
+pub struct NotifyPrefs {
+    /// Chat that calls us by name
+    pub mentions: bool,
+    pub whispers: bool,
+    /// People joining and leaving our channel
+    pub joins: bool,
+    /// A sound file played instead of the chime
+    pub sound: Option<String>,
+}
+
+impl Default for NotifyPrefs {
+    fn default() -> Self {
+        Self {
+            mentions: true,
+            whispers: true,
+            joins: false,
+            sound: None,
+        }
+    }
+}
+
+impl ClientPrefs {
+    /// `~/.config/voudp/client.toml`, or wherever `XDG_CONFIG_HOME` (`APPDATA`
+    /// on windows) points. `None` if there's no home to speak of
+    pub fn default_path() -> Option<PathBuf> {
+        let config_dir = std::env::var_os("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            .or_else(|| std::env::var_os("HOME").map(|home| Path::new(&home).join(".config")))
+            .or_else(|| std::env::var_os("APPDATA").map(PathBuf::from))?;
+        Some(config_dir.join("voudp").join(PREFS_FILE))
+    }
+
+    /// A missing file is a first run and loads the defaults
+    pub fn load(path: &Path) -> io::Result<Self> {
+        match fs::read_to_string(path) {
+            Ok(text) => Self::parse(&text),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e),
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(path, self.to_toml())
+    }
+
+    pub fn parse(text: &str) -> io::Result<Self> {
+        let mut prefs = Self::default();
+        let mut in_volumes = false;
+
+        for (n, line) in text.lines().enumerate() {
+            let bad = |what: &str| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("line {}: {what}", n + 1),
+                )
+            };
+
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            if let Some(table) = line.strip_prefix('[') {
+                in_volumes = table
+                    .strip_suffix(']')
+                    .ok_or_else(|| bad("unclosed table"))?
+                    == "volumes";
+                continue;
+            }
+
+            let (key, rest) = parse_key(line).ok_or_else(|| bad("expected key = value"))?;
+            let value = rest
+                .trim_start()
+                .strip_prefix('=')
+                .ok_or_else(|| bad("expected key = value"))?
+                .trim();
+
+            if in_volumes {
+                let gain = value.parse().map_err(|_| bad("volume isn't a number"))?;
+                prefs.volumes.insert(key, gain);
+                continue;
+            }
+
+            let string = || parse_string(value).ok_or_else(|| bad("expected a string"));
+            let boolean = || {
+                value
+                    .parse::<bool>()
+                    .map_err(|_| bad("expected true or false"))
+            };
+            match key.as_str() {
+                "server" => prefs.server = Some(string()?),
+                "nick" => prefs.nick = Some(string()?),
+                "channel" => {
+                    prefs.channel_id = Some(value.parse().map_err(|_| bad("bad channel id"))?)
+                }
+                "input_device" => prefs.input_device = Some(string()?),
+                "output_device" => prefs.output_device = Some(string()?),
+                "muted" => prefs.muted = boolean()?,
+                "deafened" => prefs.deafened = boolean()?,
+                "push_to_talk" => prefs.push_to_talk = Some(string()?),
+                "notify_mentions" => prefs.notify.mentions = boolean()?,
+                "notify_whispers" => prefs.notify.whispers = boolean()?,
+                "notify_joins" => prefs.notify.joins = boolean()?,
+                "notify_sound" => prefs.notify.sound = Some(string()?),
+                _ => {}
+            }
+        }
+
+        Ok(prefs)
+    }
+
+    pub fn to_toml(&self) -> String {
+        let mut out = String::new();
+        let strings = [
+            ("server", &self.server),
+            ("nick", &self.nick),
+            ("input_device", &self.input_device),
+            ("output_device", &self.output_device),
+            ("push_to_talk", &self.push_to_talk),
+            ("notify_sound", &self.notify.sound),
+        ];
+        for (key, value) in strings {
+            if let Some(value) = value {
+                out.push_str(&format!("{key} = {}\n", quote(value)));
+            }
+        }
+        if let Some(id) = self.channel_id {
+            out.push_str(&format!("channel = {id}\n"));
+        }
+        out.push_str(&format!("muted = {}\n", self.muted));
+        out.push_str(&format!("deafened = {}\n", self.deafened));
+        out.push_str(&format!("notify_mentions = {}\n", self.notify.mentions));
+        out.push_str(&format!("notify_whispers = {}\n", self.notify.whispers));
+        out.push_str(&format!("notify_joins = {}\n", self.notify.joins));
+
+        if !self.volumes.is_empty() {
+            out.push_str("\n[volumes]\n");
+            for (mask, gain) in &self.volumes {
+                // {:?} keeps a decimal point, so it reads back as a float
+                out.push_str(&format!("{} = {gain:?}\n", quote(mask)));
+            }
+        }
+
+        out
+    }
+}
+
+// a bare or quoted key, and what's left of the line after it
+fn parse_key(line: &str) -> Option<(String, &str)> {
+    if line.starts_with('"') {
+        let end = string_end(line)?;
+        return Some((parse_string(&line[..end])?, &line[end..]));
+    }
+
+    let end = line
+        .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_' || c == '-'))
+        .unwrap_or(line.len());
+    (end > 0).then(|| (line[..end].to_string(), &line[end..]))
+}
+
+// byte index just past the closing quote of the string `s` starts with
+fn string_end(s: &str) -> Option<usize> {
+    let mut escaped = false;
+    for (i, c) in s.char_indices().skip(1) {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' => escaped = true,
+            '"' => return Some(i + 1),
+            _ => {}
+        }
+    }
+    None
+}
+
+fn parse_string(value: &str) -> Option<String> {
+    let inner = value.strip_prefix('"')?.strip_suffix('"')?;
+    let mut out = String::with_capacity(inner.len());
+    let mut chars = inner.chars();
+
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.next()? {
+            'n' => out.push('\n'),
+            't' => out.push('\t'),
+            'r' => out.push('\r'),
+            '"' => out.push('"'),
+            '\\' => out.push('\\'),
+            'u' => {
+                let hex = chars.by_ref().take(4).collect::<String>();
+                out.push(char::from_u32(u32::from_str_radix(&hex, 16).ok()?)?);
+            }
+            _ => return None,
+        }
+    }
+
+    Some(out)
+}
+
+fn quote(value: &str) -> String {
+    let mut out = String::from('"');
+    for c in value.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\t' => out.push_str("\\t"),
+            '\r' => out.push_str("\\r"),
+            c if c.is_control() => out.push_str(&format!("\\u{:04X}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
@@ -0,0 +1,270 @@
+// Startup bandwidth probe. Before joining, the client sends short bursts of
+// padded packets at rising rates and the server echoes a small header back
+// for each one. The highest rate that got through without loss or queueing
+// picks the opus bitrate and FEC settings the client starts with.
+use std::{
+    collections::HashMap,
+    fmt, io, thread,
+    time::{Duration, Instant},
+};
+
+use opus2::{Bitrate, Encoder};
+
+use crate::{protocol::ClientPacketType, socket::SecureUdpSocket};
+
+/// Rates tried in order, stopping at the first one the link can't carry
+pub const PROBE_RATES_KBPS: [u32; 4] = [32, 64, 128, 192];
+pub const PROBE_STEP: Duration = Duration::from_millis(200);
+pub const PROBE_PACKET_BYTES: usize = 500;
+// late echoes after a step are still counted
+const PROBE_GRACE: Duration = Duration::from_millis(100);
+// a step that loses more than this is over the link's capacity
+const MAX_LOSS: f32 = 0.05;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct QualityProfile {
+    pub name: &'static str,
+    pub bitrate: i32,
+    pub fec: bool,
+    pub loss_perc: i32,
+}
+
+pub const PROFILES: [QualityProfile; 4] = [
+    QualityProfile {
+        name: "low",
+        bitrate: 24000,
+        fec: true,
+        loss_perc: 20,
+    },
+    QualityProfile {
+        name: "medium",
+        bitrate: 48000,
+        fec: true,
+        loss_perc: 15,
+    },
+    QualityProfile {
+        name: "high",
+        bitrate: 96000,
+        fec: true,
+        loss_perc: 10,
+    },
+    QualityProfile {
+        name: "max",
+        bitrate: 128000,
+        fec: false,
+        loss_perc: 0,
+    },
+];
+
+impl Default for QualityProfile {
+    // what every client used before probing existed
+    fn default() -> Self {
+        PROFILES[2]
+    }
+}
+
+impl QualityProfile {
+    pub fn by_name(name: &str) -> Option<Self> {
+        PROFILES
+            .iter()
+            .copied()
+            .find(|profile| profile.name == name)
+    }
+
+    pub fn apply(&self, encoder: &mut Encoder) -> Result<(), opus2::Error> {
+        encoder.set_bitrate(Bitrate::Bits(self.bitrate))?;
+        encoder.set_inband_fec(self.fec)?;
+        encoder.set_packet_loss_perc(self.loss_perc)
+    }
+}
+
+impl fmt::Display for QualityProfile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} ({} kbps, fec {})",
+            self.name,
+            self.bitrate / 1000,
+            if self.fec { "on" } else { "off" }
+        )
+    }
+}
+
+/// The profile in use and how it was chosen, shown in the client stats
+#[derive(Debug, Clone, Copy, Default)]
+pub struct QualitySelection {
+    pub profile: QualityProfile,
+    // set by the user, the probe leaves it alone
+    pub manual: bool,
+    // what the probe picked and the highest rate it saw get through
+    pub probed: Option<(QualityProfile, Option<u32>)>,
+}
+
+impl QualitySelection {
+    /// A manual profile always wins, `None` goes back to the probed one
+    pub fn set_manual(&mut self, profile: Option<QualityProfile>) {
+        self.manual = profile.is_some();
+        self.profile = profile
+            .or(self.probed.map(|(profile, _)| profile))
+            .unwrap_or_default();
+    }
+
+    pub fn set_probed(&mut self, steps: &[ProbeStep]) {
+        let profile = pick_profile(steps);
+        self.probed = Some((profile, sustained_kbps(steps)));
+        if !self.manual {
+            self.profile = profile;
+        }
+    }
+}
+
+impl fmt::Display for QualitySelection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.profile)?;
+        match self.probed {
+            _ if self.manual => write!(f, ", set manually"),
+            Some((_, Some(kbps))) => write!(f, ", link carried {kbps} kbps"),
+            Some((_, None)) => write!(f, ", link failed the probe"),
+            None => write!(f, ", not probed"),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ProbeStep {
+    pub rate_kbps: u32,
+    pub sent: u32,
+    pub echoed: u32,
+    pub rtts: Vec<Duration>,
+}
+
+impl ProbeStep {
+    pub fn loss(&self) -> f32 {
+        if self.sent == 0 {
+            return 0.0;
+        }
+        1.0 - self.echoed.min(self.sent) as f32 / self.sent as f32
+    }
+
+    pub fn median_rtt(&self) -> Option<Duration> {
+        let mut rtts = self.rtts.clone();
+        rtts.sort();
+        rtts.get(rtts.len() / 2).copied()
+    }
+
+    // packets pile up in a queue somewhere once the rate is too high, so a
+    // step is only good if it neither loses packets nor slows down much
+    fn passed(&self, baseline: Option<Duration>) -> bool {
+        let queued = match (self.median_rtt(), baseline) {
+            (Some(rtt), Some(base)) => rtt > base * 2 + Duration::from_millis(20),
+            (None, _) => true,
+            _ => false,
+        };
+        self.echoed > 0 && self.loss() <= MAX_LOSS && !queued
+    }
+}
+
+/// Highest rate that went through cleanly, `None` if even the first failed
+pub fn sustained_kbps(steps: &[ProbeStep]) -> Option<u32> {
+    let baseline = steps.first().and_then(ProbeStep::median_rtt);
+    steps
+        .iter()
+        .take_while(|step| step.passed(baseline))
+        .last()
+        .map(|step| step.rate_kbps)
+}
+
+/// Picks the best profile that leaves a quarter of the measured rate spare
+/// for packet overhead and everything else on the link
+pub fn pick_profile(steps: &[ProbeStep]) -> QualityProfile {
+    let Some(kbps) = sustained_kbps(steps) else {
+        return PROFILES[0];
+    };
+
+    let budget = kbps as i32 * 1000 * 3 / 4;
+    let mut profile = PROFILES
+        .iter()
+        .copied()
+        .rev()
+        .find(|profile| profile.bitrate <= budget)
+        .unwrap_or(PROFILES[0]);
+
+    // some loss even at a rate we can afford, so cover for it
+    let loss = steps
+        .iter()
+        .find(|step| step.rate_kbps == kbps)
+        .map_or(0.0, ProbeStep::loss);
+    if loss > 0.0 {
+        profile.fec = true;
+        profile.loss_perc = profile.loss_perc.max((loss * 100.0).ceil() as i32);
+    }
+
+    profile
+}
+
+pub fn create_probe_packet(step: u8, seq: u16) -> Vec<u8> {
+    let mut packet = vec![ClientPacketType::Probe as u8, step];
+    packet.extend_from_slice(&seq.to_be_bytes());
+    packet.resize(PROBE_PACKET_BYTES, 0);
+    packet
+}
+
+/// Runs every step against the server `socket` is connected to. Must run
+/// before joining, anything else that arrives meanwhile is dropped.
+pub fn run_probe(socket: &SecureUdpSocket) -> io::Result<Vec<ProbeStep>> {
+    let mut steps: Vec<ProbeStep> = vec![];
+    let mut buf = [0u8; 2048];
+
+    for (index, &rate_kbps) in PROBE_RATES_KBPS.iter().enumerate() {
+        let bits = rate_kbps as f32 * 1000.0 * PROBE_STEP.as_secs_f32();
+        let count = ((bits / (PROBE_PACKET_BYTES * 8) as f32).ceil() as u32).max(4);
+        let interval = PROBE_STEP / count;
+
+        let mut step = ProbeStep {
+            rate_kbps,
+            sent: 0,
+            echoed: 0,
+            rtts: vec![],
+        };
+        let mut sent_at = HashMap::new();
+        let start = Instant::now();
+
+        while start.elapsed() < PROBE_STEP + PROBE_GRACE {
+            if step.sent < count && start.elapsed() >= interval * step.sent {
+                let seq = step.sent as u16;
+                socket.send(&create_probe_packet(index as u8, seq))?;
+                sent_at.insert(seq, Instant::now());
+                step.sent += 1;
+            }
+
+            match socket.recv_from(&mut buf) {
+                Ok((size, _))
+                    if size >= 4
+                        && buf[0] == ClientPacketType::Probe as u8
+                        && buf[1] == index as u8 =>
+                {
+                    let seq = u16::from_be_bytes([buf[2], buf[3]]);
+                    if let Some(at) = sent_at.remove(&seq) {
+                        step.echoed += 1;
+                        step.rtts.push(at.elapsed());
+                    }
+                }
+                Ok(_) => {}
+                Err(e) if e.0.kind() == io::ErrorKind::WouldBlock => {
+                    thread::sleep(Duration::from_micros(200));
+                }
+                Err(e) if e.0.kind() == io::ErrorKind::InvalidData => {}
+                Err(e) => return Err(e.0),
+            }
+        }
+
+        let baseline = steps.first().and_then(ProbeStep::median_rtt);
+        let passed = step.passed(baseline.or(step.median_rtt()));
+        steps.push(step);
+        if !passed {
+            break;
+        }
+    }
+
+    Ok(steps)
+}
@@ -1,12 +1,32 @@
 /*
     Protocol definiton for VoUDP v0.1
 */
-use std::{array::TryFromSliceError, convert::TryFrom, string::FromUtf8Error};
+use std::{array::TryFromSliceError, convert::TryFrom, str::FromStr, string::FromUtf8Error};
+
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+use crate::effects::EffectKind;
 
 pub const VOUDP_SALT: &[u8; 5] = b"voudp";
 pub const PASSWORD: &str = "password";
+pub const CONSOLE_NONCE_LEN: usize = 16;
+pub const CONSOLE_TOKEN_LEN: usize = 16;
+/// Channel every server creates at startup. It can't be deleted, joins asking
+/// for channel 0 land in it, and it's where users go when their channel is removed.
+pub const DEFAULT_CHANNEL_ID: u32 = 1;
+/// Upper bound for per-talker gains set by listeners or the console
+pub const MAX_TALKER_GAIN: f32 = 4.0;
+/// Channels a remote can listen to on top of the one it's in
+pub const MAX_SUBSCRIPTIONS: usize = 8;
 pub const VERSION: &str = "0.2";
 
+// filter bits for paginated list requests
+pub const LIST_FILTER_SPEAKING: u8 = 0b00000001;
+pub const LIST_FILTER_ADMINS: u8 = 0b00000010;
+/// Users per page when a list request doesn't ask for a limit
+pub const DEFAULT_LIST_PAGE_LIMIT: u16 = 64;
+
 // internal flags for packet processing:
 pub const RELIABLE_FLAG: u8 = 0x80;
 pub const ACK_FLAG: u8 = 0x81;
@@ -14,27 +34,46 @@ pub const ACK_FLAG: u8 = 0x81;
 #[repr(u8)]
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum ClientPacketType {
-    // Handshake = 0x00,
+    // [0x00] both ways, answered by the server so clients know it's there
+    // (and shares their phrase) before they join. The answer carries the
+    // server's frame size: [0x00][samples per channel: u32 BE]
+    Handshake = 0x00,
     Join = 0x01,
     Audio = 0x02,
     Eof = 0x03,
     Mask = 0x04,
     List = 0x05,
     Chat = 0x06,
-    // 0x07 is reserved
+    // [0x07], from the server when chat or a DM comes from a user without a mask
+    NickRequired = 0x07,
     Ctrl = 0x08,
     // 0x09 is reserved
     FlowJoin = 0x0a,
     FlowLeave = 0x0b,
     SyncCommands = 0x0c,
     Cmd = 0x0d,
+    // from the server for every command run, [status][cmd_len][command][message].
+    // `command` is the name it ran under, aliases resolved
     CommandResponse = 0x0e,
     // 0x0f is reserved
     FlowRenick = 0x10,
+    // to the server [to_len][to][message], handed to `to` as a Whisper.
+    // from the server it's a plain server message
     Dm = 0x11,
     Kick = 0x12,
     Broadcast = 0x13,
-    // 0x14-0xfe are reserved
+    Notice = 0x14,
+    RetryAfter = 0x15,
+    ListPage = 0x16,
+    // user to user message, [from_len][from][message]
+    Whisper = 0x17,
+    // bandwidth probe, [step][seq: u16][padding]. the server echoes the
+    // first four bytes back
+    Probe = 0x18,
+    // masked users audible in the channel's mix, sent when that changes.
+    // [count] then [len][mask] per talker
+    Talkers = 0x19,
+    // 0x1a-0xfe are reserved
     RegisterConsole = 0xff,
 }
 
@@ -52,13 +91,90 @@ impl ClientPacketType {
                 | ClientPacketType::RegisterConsole
                 | ClientPacketType::Kick
                 | ClientPacketType::Broadcast
+                | ClientPacketType::Notice
+                | ClientPacketType::RetryAfter
+                | ClientPacketType::Whisper
+                | ClientPacketType::Talkers
+                | ClientPacketType::NickRequired
         )
     }
 }
 
+/// Steps of a console login, the byte after RegisterConsole. The password
+/// never goes over the wire: the server hands out a nonce, the console proves
+/// it knows the password with an HMAC of it and gets a session token back
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConsoleAuth {
+    /// To the server, asking for a challenge, [ConsoleRole] or nothing for admin
+    Hello = 0x01,
+    /// From the server, [nonce]
+    Challenge = 0x02,
+    /// To the server, [HMAC-SHA256 of the nonce keyed by the password]
+    Proof = 0x03,
+    /// From the server, [token] to prefix every command with
+    Session = 0x04,
+    /// From the server, the proof didn't match or came too late
+    Denied = 0x05,
+}
+
+/// What a console session may do. Observers only get the commands that
+/// don't change anything, for dashboards attached next to an operator
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConsoleRole {
+    Observer = 0x01,
+    Admin = 0x02,
+}
+
+/// What a console can subscribe to. Events are pushed to it as reliable
+/// `event <name> key=value ...` lines, any free text goes last
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum ConsoleEvent {
+    Joins,
+    Leaves,
+    Chat,
+    Kicks,
+    AudioErrors,
+}
+
+impl ConsoleEvent {
+    pub const ALL: [ConsoleEvent; 5] = [
+        ConsoleEvent::Joins,
+        ConsoleEvent::Leaves,
+        ConsoleEvent::Chat,
+        ConsoleEvent::Kicks,
+        ConsoleEvent::AudioErrors,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            ConsoleEvent::Joins => "joins",
+            ConsoleEvent::Leaves => "leaves",
+            ConsoleEvent::Chat => "chat",
+            ConsoleEvent::Kicks => "kicks",
+            ConsoleEvent::AudioErrors => "audio-errors",
+        }
+    }
+}
+
+impl FromStr for ConsoleEvent {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        ConsoleEvent::ALL
+            .into_iter()
+            .find(|event| event.name() == s)
+            .ok_or_else(|| {
+                format!("unknown event '{s}' (joins, leaves, chat, kicks, audio-errors, all)")
+            })
+    }
+}
+
 #[repr(u8)]
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum ConsolePacketType {
+    // [token][command]
     Cmd = 0x0d,
     Eof = 0x03,
     Keepalive = 0x04,
@@ -71,7 +187,16 @@ pub enum ControlRequest {
     SetUndeafen = 0x02,
     SetMute = 0x03,
     SetUnmute = 0x04,
-    // SetVolume takes a parameter, so it's handled separately
+    SetPanning = 0x05,
+    SetNoPanning = 0x06,
+    // followed by the gain (f32) and the talker's mask
+    SetTalkerGain = 0x07,
+    // followed by one byte per EffectKind, in order. none clears the chain
+    SetEffects = 0x08,
+    // followed by a channel id (u32) to listen to without joining, or to stop
+    // listening to
+    Subscribe = 0x09,
+    Unsubscribe = 0x0a,
 }
 
 #[repr(u8)]
@@ -82,17 +207,94 @@ pub enum CommandResultPacketType {
     Silent = 0x03,
 }
 
+/// Codes for server-sent strings so clients can localize them. Notices always
+/// carry an English fallback, so a client that doesn't know a code (or has no
+/// translation for it) can still show something sensible.
+#[repr(u16)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MessageCode {
+    Custom = 0x0000,
+    ChannelMoved = 0x0001,
+    EmptyMessage = 0x0002,
+    JoinBlocked = 0x0003,
+    KickedByPlugin = 0x0004,
+    KickRequested = 0x0005,
+    JoinFailed = 0x0006,
+    Monitored = 0x0007,
+    UserNotOnline = 0x0008,
+    MaskRejected = 0x0009,
+    ServerMuted = 0x000a,
+    ServerUnmuted = 0x000b,
+    ServerDeafened = 0x000c,
+    ServerUndeafened = 0x000d,
+    Kicked = 0x000e,
+    Banned = 0x000f,
+}
+
+impl MessageCode {
+    /// English template for this code. `{0}`, `{1}`, ... are replaced with the
+    /// notice parameters in order.
+    pub fn template(self) -> &'static str {
+        match self {
+            MessageCode::Custom => "{0}",
+            MessageCode::ChannelMoved => "You have been moved to #{0}",
+            MessageCode::EmptyMessage => "Don't send an empty message",
+            MessageCode::JoinBlocked => "Server plugins blocked you from joining",
+            MessageCode::KickedByPlugin => "{0}",
+            MessageCode::KickRequested => "We have successfully met your desires",
+            MessageCode::JoinFailed => "The server could not set up audio for you, try again later",
+            MessageCode::Monitored => "{0} is monitoring voice in this channel",
+            MessageCode::UserNotOnline => "{0} is not online",
+            MessageCode::MaskRejected => "Server plugins rejected the nickname {0}",
+            MessageCode::ServerMuted => "The server has muted you",
+            MessageCode::ServerUnmuted => "The server has unmuted you",
+            MessageCode::ServerDeafened => "The server has deafened you",
+            MessageCode::ServerUndeafened => "The server has undeafened you",
+            MessageCode::Kicked => "{0} kicked you: {1}",
+            MessageCode::Banned => "You are banned from this server: {0}",
+        }
+    }
+}
+
+impl TryFrom<u16> for MessageCode {
+    type Error = u16;
+
+    fn try_from(value: u16) -> Result<Self, Self::Error> {
+        match value {
+            0x0000 => Ok(Self::Custom),
+            0x0001 => Ok(Self::ChannelMoved),
+            0x0002 => Ok(Self::EmptyMessage),
+            0x0003 => Ok(Self::JoinBlocked),
+            0x0004 => Ok(Self::KickedByPlugin),
+            0x0005 => Ok(Self::KickRequested),
+            0x0006 => Ok(Self::JoinFailed),
+            0x0007 => Ok(Self::Monitored),
+            0x0008 => Ok(Self::UserNotOnline),
+            0x0009 => Ok(Self::MaskRejected),
+            0x000a => Ok(Self::ServerMuted),
+            0x000b => Ok(Self::ServerUnmuted),
+            0x000c => Ok(Self::ServerDeafened),
+            0x000d => Ok(Self::ServerUndeafened),
+            0x000e => Ok(Self::Kicked),
+            0x000f => Ok(Self::Banned),
+            _ => Err(value),
+        }
+    }
+}
+
 impl TryFrom<u8> for ClientPacketType {
     type Error = u8;
 
     fn try_from(value: u8) -> Result<Self, Self::Error> {
         match value {
+            0x00 => Ok(Self::Handshake),
             0x01 => Ok(Self::Join),
             0x02 => Ok(Self::Audio),
             0x03 => Ok(Self::Eof),
             0x04 => Ok(Self::Mask),
             0x05 => Ok(Self::List),
             0x06 => Ok(Self::Chat),
+            0x07 => Ok(Self::NickRequired),
             0x08 => Ok(Self::Ctrl),
             0x0a => Ok(Self::FlowJoin),
             0x0b => Ok(Self::FlowLeave),
@@ -102,6 +304,12 @@ impl TryFrom<u8> for ClientPacketType {
             0x11 => Ok(Self::Dm),
             0x12 => Ok(Self::Kick),
             0x13 => Ok(Self::Broadcast),
+            0x14 => Ok(Self::Notice),
+            0x15 => Ok(Self::RetryAfter),
+            0x16 => Ok(Self::ListPage),
+            0x17 => Ok(Self::Whisper),
+            0x18 => Ok(Self::Probe),
+            0x19 => Ok(Self::Talkers),
             0xff => Ok(Self::RegisterConsole),
             _ => Err(value),
         }
@@ -121,6 +329,33 @@ impl TryFrom<u8> for ConsolePacketType {
     }
 }
 
+impl TryFrom<u8> for ConsoleAuth {
+    type Error = u8;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0x01 => Ok(Self::Hello),
+            0x02 => Ok(Self::Challenge),
+            0x03 => Ok(Self::Proof),
+            0x04 => Ok(Self::Session),
+            0x05 => Ok(Self::Denied),
+            _ => Err(value),
+        }
+    }
+}
+
+impl TryFrom<u8> for ConsoleRole {
+    type Error = u8;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0x01 => Ok(Self::Observer),
+            0x02 => Ok(Self::Admin),
+            _ => Err(value),
+        }
+    }
+}
+
 impl TryFrom<u8> for CommandResultPacketType {
     type Error = u8;
 
@@ -143,6 +378,12 @@ impl TryFrom<u8> for ControlRequest {
             0x02 => Ok(Self::SetUndeafen),
             0x03 => Ok(Self::SetMute),
             0x04 => Ok(Self::SetUnmute),
+            0x05 => Ok(Self::SetPanning),
+            0x06 => Ok(Self::SetNoPanning),
+            0x07 => Ok(Self::SetTalkerGain),
+            0x08 => Ok(Self::SetEffects),
+            0x09 => Ok(Self::Subscribe),
+            0x0a => Ok(Self::Unsubscribe),
             _ => Err(value),
         }
     }
@@ -203,6 +444,90 @@ pub fn create_list_request() -> Vec<u8> {
     ClientPacketType::List.to_bytes()
 }
 
+/// A list request for one page of users. A limit of 0 lets the server pick.
+pub fn create_list_page_request(offset: u32, limit: u16, filter: u8) -> Vec<u8> {
+    let mut packet = ClientPacketType::List.to_bytes();
+    packet.extend_from_slice(&offset.to_be_bytes());
+    packet.extend_from_slice(&limit.to_be_bytes());
+    packet.push(filter);
+    packet
+}
+
+pub fn create_effects_request(effects: &[EffectKind]) -> Vec<u8> {
+    let mut packet = vec![
+        ClientPacketType::Ctrl as u8,
+        ControlRequest::SetEffects as u8,
+    ];
+    packet.extend(effects.iter().map(|&kind| kind as u8));
+    packet
+}
+
+/// Starts or stops hearing `channel_id` alongside our own channel
+pub fn create_subscription_request(channel_id: u32, listen: bool) -> Vec<u8> {
+    let request = if listen {
+        ControlRequest::Subscribe
+    } else {
+        ControlRequest::Unsubscribe
+    };
+    let mut packet = vec![ClientPacketType::Ctrl as u8, request as u8];
+    packet.extend_from_slice(&channel_id.to_be_bytes());
+    packet
+}
+
+pub fn create_whisper_packet(from: &str, message: &str) -> Vec<u8> {
+    // masks are short, but never let the length byte wrap
+    let from = &from.as_bytes()[..from.len().min(u8::MAX as usize)];
+    let mut packet = vec![ClientPacketType::Whisper as u8, from.len() as u8];
+    packet.extend_from_slice(from);
+    packet.extend_from_slice(message.as_bytes());
+    packet
+}
+
+pub fn create_dm_packet(to: &str, message: &str) -> Vec<u8> {
+    let to = &to.as_bytes()[..to.len().min(u8::MAX as usize)];
+    let mut packet = vec![ClientPacketType::Dm as u8, to.len() as u8];
+    packet.extend_from_slice(to);
+    packet.extend_from_slice(message.as_bytes());
+    packet
+}
+
+pub fn create_command_response(
+    status: CommandResultPacketType,
+    command: &str,
+    message: &str,
+) -> Vec<u8> {
+    let command = &command.as_bytes()[..command.len().min(u8::MAX as usize)];
+    let mut packet = vec![
+        ClientPacketType::CommandResponse as u8,
+        status as u8,
+        command.len() as u8,
+    ];
+    packet.extend_from_slice(command);
+    packet.extend_from_slice(message.as_bytes());
+    packet
+}
+
+/// What a console answers the server's `nonce` with
+pub fn console_proof(password: &str, nonce: &[u8]) -> [u8; 32] {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(password.as_bytes()).expect("hmac takes keys of any length");
+    mac.update(nonce);
+    mac.finalize().into_bytes().into()
+}
+
+pub fn create_console_auth(step: ConsoleAuth, payload: &[u8]) -> Vec<u8> {
+    let mut packet = vec![ClientPacketType::RegisterConsole as u8, step as u8];
+    packet.extend_from_slice(payload);
+    packet
+}
+
+pub fn create_console_command(token: &[u8], command: &str) -> Vec<u8> {
+    let mut packet = vec![ConsolePacketType::Cmd as u8];
+    packet.extend_from_slice(token);
+    packet.extend_from_slice(command.as_bytes());
+    packet
+}
+
 pub fn create_sync_commands_request() -> Vec<u8> {
     ClientPacketType::SyncCommands.to_bytes()
 }
@@ -214,6 +539,7 @@ pub fn is_flow_packet(packet_type: ClientPacketType) -> bool {
             | ClientPacketType::FlowLeave
             | ClientPacketType::FlowRenick
             | ClientPacketType::Dm
+            | ClientPacketType::Whisper
     )
 }
 
@@ -0,0 +1,144 @@
+// Records a call to a WAV file the way it was heard, optionally with our own
+// voice mixed in. 16 bit stereo at 48kHz, the start time goes in the file's
+// INFO chunk and its name. Sizes are patched in when the recording stops.
+use chrono::{DateTime, Local};
+use std::collections::VecDeque;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+pub const DEFAULT_RECORDINGS_DIR: &str = "recordings";
+
+const SAMPLE_RATE: u32 = 48000;
+const CHANNELS: u16 = 2;
+const BYTES_PER_SAMPLE: u16 = 2;
+// one second of our voice waiting for the mix, past that the oldest goes
+const MAX_MIC_BACKLOG: usize = SAMPLE_RATE as usize * CHANNELS as usize;
+
+pub struct Recorder {
+    file: BufWriter<File>,
+    path: PathBuf,
+    started: DateTime<Local>,
+    // where the data chunk's size goes
+    data_size_at: u64,
+    data_bytes: u32,
+    // our voice, mixed into the next mix written. `None` leaves it out
+    mic: Option<VecDeque<f32>>,
+}
+
+impl Recorder {
+    pub fn create(path: &Path, include_mic: bool) -> io::Result<Self> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+
+        let started = Local::now();
+        let mut file = BufWriter::new(File::create(path)?);
+
+        let block_align = CHANNELS * BYTES_PER_SAMPLE;
+        file.write_all(b"RIFF")?;
+        file.write_all(&0u32.to_le_bytes())?; // patched in `finish`
+        file.write_all(b"WAVE")?;
+
+        file.write_all(b"fmt ")?;
+        file.write_all(&16u32.to_le_bytes())?;
+        file.write_all(&1u16.to_le_bytes())?; // pcm
+        file.write_all(&CHANNELS.to_le_bytes())?;
+        file.write_all(&SAMPLE_RATE.to_le_bytes())?;
+        file.write_all(&(SAMPLE_RATE * block_align as u32).to_le_bytes())?;
+        file.write_all(&block_align.to_le_bytes())?;
+        file.write_all(&(BYTES_PER_SAMPLE * 8).to_le_bytes())?;
+
+        // chunks are padded to an even length
+        let mut date = started.format("%Y-%m-%d %H:%M:%S").to_string().into_bytes();
+        date.push(0);
+        if date.len() % 2 == 1 {
+            date.push(0);
+        }
+        file.write_all(b"LIST")?;
+        file.write_all(&(4 + 8 + date.len() as u32).to_le_bytes())?;
+        file.write_all(b"INFO")?;
+        file.write_all(b"ICRD")?;
+        file.write_all(&(date.len() as u32).to_le_bytes())?;
+        file.write_all(&date)?;
+
+        file.write_all(b"data")?;
+        let data_size_at = file.stream_position()?;
+        file.write_all(&0u32.to_le_bytes())?;
+
+        Ok(Self {
+            file,
+            path: path.to_path_buf(),
+            started,
+            data_size_at,
+            data_bytes: 0,
+            mic: include_mic.then(VecDeque::new),
+        })
+    }
+
+    /// `dir/recording-<start time>.wav`
+    pub fn default_path(dir: &Path) -> PathBuf {
+        dir.join(format!(
+            "recording-{}.wav",
+            Local::now().format("%Y%m%d-%H%M%S")
+        ))
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn started(&self) -> DateTime<Local> {
+        self.started
+    }
+
+    pub fn duration(&self) -> Duration {
+        let frames = self.data_bytes / (CHANNELS * BYTES_PER_SAMPLE) as u32;
+        Duration::from_secs_f64(frames as f64 / SAMPLE_RATE as f64)
+    }
+
+    /// Queues interleaved stereo mic audio to go out with the next mix,
+    /// ignored unless the recording includes the mic
+    pub fn write_mic(&mut self, samples: &[f32]) {
+        let Some(mic) = &mut self.mic else {
+            return;
+        };
+
+        mic.extend(samples);
+        while mic.len() > MAX_MIC_BACKLOG {
+            mic.pop_front();
+        }
+    }
+
+    /// Writes interleaved stereo audio as it was played
+    pub fn write_mix(&mut self, samples: &[f32]) -> io::Result<()> {
+        let mut bytes = Vec::with_capacity(samples.len() * BYTES_PER_SAMPLE as usize);
+        for &sample in samples {
+            let mic = self
+                .mic
+                .as_mut()
+                .and_then(|mic| mic.pop_front())
+                .unwrap_or(0.0);
+            let sample = (sample + mic).clamp(-1.0, 1.0);
+            bytes.extend_from_slice(&((sample * i16::MAX as f32) as i16).to_le_bytes());
+        }
+
+        self.file.write_all(&bytes)?;
+        self.data_bytes = self.data_bytes.saturating_add(bytes.len() as u32);
+        Ok(())
+    }
+
+    /// Fills in the sizes and closes the file, returning where it went
+    pub fn finish(mut self) -> io::Result<PathBuf> {
+        let end = self.file.stream_position()?;
+
+        self.file.seek(SeekFrom::Start(4))?;
+        self.file.write_all(&(end as u32 - 8).to_le_bytes())?;
+        self.file.seek(SeekFrom::Start(self.data_size_at))?;
+        self.file.write_all(&self.data_bytes.to_le_bytes())?;
+        self.file.flush()?;
+
+        Ok(self.path)
+    }
+}
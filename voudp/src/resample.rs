@@ -0,0 +1,203 @@
+// Resampling between a device's rate and the 48kHz opus runs at. Linear for
+// hardware that can't do 48kHz itself (44.1kHz is common on macOS and with
+// bluetooth headsets), windowed-sinc for music where quality matters more.
+// Both work on interleaved audio a chunk at a time and carry their position
+// over, so chunk boundaries don't click.
+
+pub struct Resampler {
+    channels: usize,
+    // input frames per output frame
+    step: f64,
+    // where the next output frame falls between `prev` and `next`
+    pos: f64,
+    prev: Vec<f32>,
+    next: Vec<f32>,
+}
+
+impl Resampler {
+    pub fn new(from: u32, to: u32, channels: usize) -> Self {
+        Self {
+            channels,
+            step: from as f64 / to as f64,
+            pos: 0.0,
+            prev: vec![0.0; channels],
+            next: vec![0.0; channels],
+        }
+    }
+
+    /// Converts everything in `input` and appends it to `out`, for audio that
+    /// arrives in chunks like a mic
+    pub fn process(&mut self, input: &[f32], out: &mut Vec<f32>) {
+        for frame in input.chunks_exact(self.channels) {
+            std::mem::swap(&mut self.prev, &mut self.next);
+            self.next.copy_from_slice(frame);
+
+            while self.pos < 1.0 {
+                self.emit(out);
+                self.pos += self.step;
+            }
+            self.pos -= 1.0;
+        }
+    }
+
+    /// Fills all of `out`, pulling input samples from `source` as they're
+    /// needed, for audio that's asked for like a speaker
+    pub fn fill(&mut self, out: &mut [f32], mut source: impl FnMut() -> f32) {
+        for frame in out.chunks_exact_mut(self.channels) {
+            while self.pos >= 1.0 {
+                std::mem::swap(&mut self.prev, &mut self.next);
+                self.next.iter_mut().for_each(|s| *s = source());
+                self.pos -= 1.0;
+            }
+
+            let t = self.pos as f32;
+            for ((sample, prev), next) in frame.iter_mut().zip(&self.prev).zip(&self.next) {
+                *sample = prev + (next - prev) * t;
+            }
+            self.pos += self.step;
+        }
+    }
+
+    fn emit(&self, out: &mut Vec<f32>) {
+        let t = self.pos as f32;
+        out.extend(
+            self.prev
+                .iter()
+                .zip(&self.next)
+                .map(|(prev, next)| prev + (next - prev) * t),
+        );
+    }
+}
+
+// how many zero crossings of the sinc are kept either side, and how close to
+// nyquist it starts rolling off. more of either is sharper and costs more
+const ZERO_CROSSINGS: usize = 32;
+const ROLLOFF: f64 = 0.92;
+const KAISER_BETA: f64 = 7.0;
+// kernel points per input frame, lookups interpolate between them
+const TABLE_DENSITY: usize = 512;
+
+/// Windowed-sinc resampling for music, where the images linear interpolation
+/// leaves behind can be heard. Streams like [`Resampler::process`] and lags
+/// its input by a few frames, [`SincResampler::flush`] gets those out
+pub struct SincResampler {
+    channels: usize,
+    // input frames per output frame
+    step: f64,
+    // how far the kernel reaches either side, in input frames
+    width: usize,
+    // one side of the kernel, `TABLE_DENSITY` points per input frame
+    table: Vec<f32>,
+    // interleaved input still needed, starting `width` frames before `pos`
+    buf: Vec<f32>,
+    // where the next output frame falls in `buf`, in frames
+    pos: f64,
+}
+
+impl SincResampler {
+    pub fn new(from: u32, to: u32, channels: usize) -> Self {
+        // going down the cutoff follows the lower nyquist, so nothing folds back
+        let cutoff = ROLLOFF * (to as f64 / from as f64).min(1.0);
+        let width = (ZERO_CROSSINGS as f64 / cutoff).ceil() as usize;
+
+        let table = (0..=(width + 1) * TABLE_DENSITY)
+            .map(|i| {
+                let x = i as f64 / TABLE_DENSITY as f64;
+                if x >= width as f64 {
+                    return 0.0;
+                }
+                let t = std::f64::consts::PI * cutoff * x;
+                let sinc = if t == 0.0 { 1.0 } else { t.sin() / t };
+                let window = bessel_i0(KAISER_BETA * (1.0 - (x / width as f64).powi(2)).sqrt())
+                    / bessel_i0(KAISER_BETA);
+                (cutoff * sinc * window) as f32
+            })
+            .collect();
+
+        let mut resampler = Self {
+            channels,
+            step: from as f64 / to as f64,
+            width,
+            table,
+            buf: vec![],
+            pos: 0.0,
+        };
+        resampler.reset();
+        resampler
+    }
+
+    pub fn channels(&self) -> usize {
+        self.channels
+    }
+
+    /// Forgets the input so far, after a seek or once a track is done
+    pub fn reset(&mut self) {
+        self.buf.clear();
+        self.buf.resize(self.width * self.channels, 0.0);
+        self.pos = self.width as f64;
+    }
+
+    /// Converts what it can of `input` and appends it to `out`, the last few
+    /// frames wait for the input that comes after them
+    pub fn process(&mut self, input: &[f32], out: &mut Vec<f32>) {
+        self.buf.extend_from_slice(input);
+        let frames = self.buf.len() / self.channels;
+
+        while (self.pos as usize) + self.width < frames {
+            self.emit(out);
+            self.pos += self.step;
+        }
+
+        // what's behind the kernel now won't be needed again
+        let done = (self.pos as usize).saturating_sub(self.width);
+        self.buf.drain(..done * self.channels);
+        self.pos -= done as f64;
+    }
+
+    /// Converts what's left as if silence followed it, then starts over
+    pub fn flush(&mut self, out: &mut Vec<f32>) {
+        let end = self.buf.len() / self.channels;
+        self.buf.resize((end + self.width) * self.channels, 0.0);
+        while self.pos < end as f64 {
+            self.emit(out);
+            self.pos += self.step;
+        }
+        self.reset();
+    }
+
+    fn emit(&self, out: &mut Vec<f32>) {
+        let start = out.len();
+        out.resize(start + self.channels, 0.0);
+        let frame = &mut out[start..];
+
+        let center = self.pos as usize;
+        for j in center + 1 - self.width..=center + self.width {
+            let weight = self.kernel((self.pos - j as f64).abs());
+            let input = &self.buf[j * self.channels..(j + 1) * self.channels];
+            for (sample, input) in frame.iter_mut().zip(input) {
+                *sample += input * weight;
+            }
+        }
+    }
+
+    fn kernel(&self, x: f64) -> f32 {
+        let at = x * TABLE_DENSITY as f64;
+        let i = at as usize;
+        let t = (at - i as f64) as f32;
+        self.table[i] + (self.table[i + 1] - self.table[i]) * t
+    }
+}
+
+// the modified bessel function the kaiser window is built on
+fn bessel_i0(x: f64) -> f64 {
+    let mut sum = 1.0;
+    let mut term = 1.0;
+    for k in 1..50 {
+        term *= (x / (2.0 * k as f64)).powi(2);
+        sum += term;
+        if term < sum * 1e-12 {
+            break;
+        }
+    }
+    sum
+}
@@ -5,11 +5,11 @@ use ringbuf::{
     traits::{Consumer, Observer, Producer},
 };
 use std::{
-    collections::{HashMap, VecDeque},
-    fs, io,
+    collections::{HashMap, HashSet, VecDeque},
+    fmt, io,
     net::SocketAddr,
     ops::Not,
-    path::Path,
+    path::{Path, PathBuf},
     sync::{
         Arc, Mutex,
         mpsc::{self, Receiver},
@@ -17,21 +17,39 @@ use std::{
     time::{Duration, Instant},
 };
 
+#[cfg(feature = "tls-console")]
+use crate::console_tls::{self, TlsConsoleConfig, TlsConsoleEvent, TlsLink};
+#[cfg(feature = "telemetry")]
+use crate::telemetry::{Telemetry, TelemetryConfig};
 use crate::{
+    audit::{self, AuditLog},
+    bans::BanList,
     commands::CommandSystem,
-    console_cmd::{ConsoleCommandResult, handle_command},
+    console_cmd::{self, ConsoleAction, ConsoleCommandResult, handle_command},
+    console_msg::{ConsoleMessage, ConsoleMessageKind},
+    effects::{EffectChain, EffectKind, MAX_EFFECTS},
+    loudness::LoudnessMeter,
     mixer,
-    plugin::{PluginAction, PluginManager},
+    native_plugin::VoudpPlugin,
+    plugin::{DirectoryChannel, DirectoryUser, PluginAction, PluginManager},
     protocol::{
-        self, ClientPacketType, ConsolePacketType, ControlRequest, FromPacket, IntoPacket, PASSWORD,
+        self, CONSOLE_NONCE_LEN, CONSOLE_TOKEN_LEN, ClientPacketType, ConsoleAuth, ConsoleEvent,
+        ConsolePacketType, ConsoleRole, ControlRequest, FromPacket, IntoPacket, MessageCode,
+        PASSWORD,
     },
     socket::{self, SecureUdpSocket},
+    storage::{FileStorage, Storage},
     util::{
-        self, BroadcastPacket, CommandCategory, CommandContext, CommandResult, ControlPacket,
-        ServerCommand,
+        self, ArgKind, BroadcastPacket, ChannelInfo, ChatPacket, CommandArg, CommandCategory,
+        CommandContext, CommandResponsePacket, CommandResult, ControlPacket, GlobalListPacket,
+        ListPagePacket, LocalizedMessage, RetryAfterPacket, ServerCommand, TalkersPacket,
     },
 };
 const JITTER_BUFFER_LEN: usize = 50;
+pub const DEFAULT_DATA_DIR: &str = "data";
+pub const DEFAULT_PLUGIN_DIR: &str = "plugins";
+const FADE_MILLIS: u32 = 5; // talker fade in/out length
+const AUDIO_STATS_WINDOW: Duration = Duration::from_secs(1);
 
 #[derive(Clone, Copy, PartialEq, Debug)]
 pub enum Clipping {
@@ -53,6 +71,14 @@ pub struct ServerConfig {
     pub sample_rate: u32,
     pub tickrate: u32,
     pub current_tick: u32,
+    pub stereo_panning: bool,
+    pub dc_alpha: f32,
+    pub highpass_hz: Option<f32>,
+    pub silence_threshold: f32,
+    pub max_joins_per_sec: u32,
+    pub announce_monitoring: bool,
+    // how loud channels a remote only listens to are next to its own
+    pub subscription_gain: f32,
 }
 
 impl Default for ServerConfig {
@@ -70,6 +96,13 @@ impl Default for ServerConfig {
             sample_rate: 48000,
             tickrate: 50,
             current_tick: 0,
+            stereo_panning: false,
+            dc_alpha: 0.995,
+            highpass_hz: None,
+            silence_threshold: 0.001,
+            max_joins_per_sec: 20,
+            announce_monitoring: true,
+            subscription_gain: 0.5,
         }
     }
 }
@@ -80,21 +113,117 @@ impl ServerConfig {
     }
 }
 
-#[derive(Default, Clone, Copy)]
+#[derive(Clone, Copy)]
 pub struct RemoteStatus {
     pub deaf: bool,
     pub mute: bool,
+    pub panning: bool,
+    // set by plugins, the client can't lift these
+    pub forced_deaf: bool,
+    pub forced_mute: bool,
+}
+
+impl RemoteStatus {
+    pub fn is_deaf(&self) -> bool {
+        self.deaf || self.forced_deaf
+    }
+
+    pub fn is_muted(&self) -> bool {
+        self.mute || self.forced_mute
+    }
+}
+
+impl Default for RemoteStatus {
+    fn default() -> Self {
+        Self {
+            deaf: false,
+            mute: false,
+            panning: true,
+            forced_deaf: false,
+            forced_mute: false,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Role {
+    #[default]
+    User,
+    Admin,
+}
+
+/// What an operator is listening to instead of their own channel
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MonitorTarget {
+    Channel(u32),
+    Remote(SocketAddr),
+}
+
+// what a remote sent over the last window, for the console's `status`. There
+// are no sequence numbers on audio, so loss only counts the frames the server
+// had to drop itself, not the ones lost on the way
+#[derive(Default)]
+struct AudioStats {
+    bytes: u64,
+    frames: u64,
+    dropped: u64,
+    since: Option<Instant>,
+    // of the last full window
+    kbps: f32,
+    loss_percent: f32,
+}
+
+impl AudioStats {
+    fn record(&mut self, bytes: usize, dropped: bool) {
+        self.roll();
+        self.bytes += bytes as u64;
+        if dropped {
+            self.dropped += 1;
+        } else {
+            self.frames += 1;
+        }
+    }
+
+    // starts a new window once the current one is over
+    fn roll(&mut self) {
+        let since = *self.since.get_or_insert_with(Instant::now);
+        let elapsed = since.elapsed();
+        if elapsed < AUDIO_STATS_WINDOW {
+            return;
+        }
+
+        self.kbps = self.bytes as f32 * 8.0 / 1000.0 / elapsed.as_secs_f32();
+        let sent = self.frames + self.dropped;
+        self.loss_percent = if sent == 0 {
+            0.0
+        } else {
+            self.dropped as f32 * 100.0 / sent as f32
+        };
+        *self = Self {
+            since: Some(Instant::now()),
+            kbps: self.kbps,
+            loss_percent: self.loss_percent,
+            ..Default::default()
+        };
+    }
 }
 
 pub struct Remote {
     encoder: Encoder,
     decoder: Decoder,
     last_active: Instant,
-    channel_id: u32,
+    pub(crate) channel_id: u32,
     pub(crate) addr: SocketAddr,
-    mask: Option<String>,
+    pub(crate) mask: Option<String>,
     jitter_buffer: VecDeque<Vec<f32>>,
     pub(crate) status: RemoteStatus,
+    pub(crate) role: Role,
+    // kept here so the effects follow the remote across channels
+    pub(crate) effects: Vec<EffectKind>,
+    pub(crate) monitoring: Option<MonitorTarget>,
+    // channels heard listen-only on top of `channel_id`
+    pub(crate) subscriptions: Vec<u32>,
+    audio_stats: AudioStats,
 }
 
 impl Remote {
@@ -115,29 +244,118 @@ impl Remote {
             encoder,
             decoder,
             last_active: Instant::now(),
-            channel_id: 0,
+            channel_id: protocol::DEFAULT_CHANNEL_ID,
             addr,
             mask: None,
             jitter_buffer: VecDeque::with_capacity(JITTER_BUFFER_LEN),
             status: Default::default(),
+            role: Role::User,
+            effects: vec![],
+            monitoring: None,
+            subscriptions: vec![],
+            audio_stats: AudioStats::default(),
         })
     }
+
+    /// Runs the output stage over a finished mix, then encodes and sends it
+    fn send_mix(&mut self, socket: &SecureUdpSocket, config: &ServerConfig, mix: &mut [f32]) {
+        if config.should_compress {
+            mixer::compress(mix, config.compress_threshold, config.compress_ratio);
+        }
+
+        if config.should_normalize {
+            mixer::normalize(mix);
+        }
+
+        match config.clipping {
+            Clipping::Soft => mixer::soft_clip(mix),
+            Clipping::Hard => mixer::hard_clip(mix),
+        }
+
+        let mut encoded = vec![0u8; 400];
+        let len = self.encoder.encode_float(mix, &mut encoded).unwrap_or(0);
+
+        if len > 0 {
+            let mut packet = vec![0x02];
+            packet.extend_from_slice(&config.current_tick.to_be_bytes());
+            packet.extend_from_slice(&encoded[..len]);
+            if let Err(e) = socket.send_to(&packet, self.addr) {
+                error!("Failed to send audio to {}: {e}", self.addr);
+            }
+        }
+    }
 }
 
 struct Console {
-    _addr: SocketAddr,
+    id: u32,
+    link: ConsoleLink,
+    role: ConsoleRole,
     last_active: Instant,
+    // every command has to carry it, the address alone proves nothing
+    token: [u8; CONSOLE_TOKEN_LEN],
+    events: HashSet<ConsoleEvent>,
 }
 
 impl Console {
-    fn new(_addr: SocketAddr) -> Self {
+    fn new(id: u32, link: ConsoleLink, role: ConsoleRole) -> Self {
         Self {
-            _addr,
+            id,
+            link,
+            role,
             last_active: Instant::now(),
+            token: rand::random(),
+            events: HashSet::new(),
         }
     }
 }
 
+// how a console reaches us
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum ConsoleLink {
+    Udp(SocketAddr),
+    #[cfg(feature = "tls-console")]
+    Tls(TlsLink),
+}
+
+impl ConsoleLink {
+    fn send(&self, socket: &SecureUdpSocket, packet: Vec<u8>) -> io::Result<()> {
+        match self {
+            ConsoleLink::Udp(addr) => socket.send_reliable(packet, *addr),
+            #[cfg(feature = "tls-console")]
+            ConsoleLink::Tls(link) => link.send(packet),
+        }
+    }
+
+    // keepalives are sent again soon enough
+    fn send_unreliable(&self, socket: &SecureUdpSocket, packet: &[u8]) -> io::Result<()> {
+        match self {
+            ConsoleLink::Udp(addr) => socket.send_to(packet, *addr).map(drop),
+            #[cfg(feature = "tls-console")]
+            ConsoleLink::Tls(link) => link.send(packet.to_vec()),
+        }
+    }
+}
+
+impl fmt::Display for ConsoleLink {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConsoleLink::Udp(addr) => write!(f, "{addr}"),
+            #[cfg(feature = "tls-console")]
+            ConsoleLink::Tls(link) => write!(f, "{} over tls", link.peer),
+        }
+    }
+}
+
+// a nonce handed to a would-be console, answered at most once
+struct ConsoleChallenge {
+    nonce: [u8; CONSOLE_NONCE_LEN],
+    role: ConsoleRole,
+    sent: Instant,
+}
+
+// how long a console has to answer its challenge
+const CONSOLE_CHALLENGE_TIMEOUT: Duration = Duration::from_secs(10);
+
 type SafeRemote = Arc<Mutex<Remote>>;
 type SafeConsole = Arc<Mutex<Console>>;
 pub struct Channel {
@@ -145,7 +363,23 @@ pub struct Channel {
     pub _id: u32,
     pub remotes: Vec<SafeRemote>,
     pub buffers: HashMap<SocketAddr, Vec<f32>>,
-    pub filter_states: HashMap<SocketAddr, (f32, f32)>,
+    pub filter_states: HashMap<SocketAddr, mixer::DcState>,
+    pub highpass_states: HashMap<SocketAddr, mixer::HighPass>,
+    pub fade_levels: HashMap<SocketAddr, f32>,
+    // talker -> gain everyone in the channel hears them at
+    pub talker_gains: HashMap<SocketAddr, f32>,
+    // listener -> talker -> gain, on top of the channel-wide talker gain
+    pub listener_gains: HashMap<SocketAddr, HashMap<SocketAddr, f32>>,
+    pub meters: HashMap<SocketAddr, LoudnessMeter>,
+    pub meter: LoudnessMeter,
+    pub effect_chains: HashMap<SocketAddr, EffectChain>,
+    // what was mixed last tick, for operators monitoring this channel
+    pub last_talkers: HashMap<SocketAddr, Vec<f32>>,
+    pub last_total: Vec<f32>,
+    // talkers the channel was last told about
+    pub talkers: TalkersPacket,
+    // mixes of listeners with subscriptions, finished in send_subscriber_mixes
+    pub held_mixes: HashMap<SocketAddr, Vec<f32>>,
     pub server_config: ServerConfig,
 }
 
@@ -161,55 +395,243 @@ impl Channel {
             remotes: vec![],
             buffers: HashMap::new(),
             filter_states: HashMap::new(),
+            highpass_states: HashMap::new(),
+            fade_levels: HashMap::new(),
+            talker_gains: HashMap::new(),
+            listener_gains: HashMap::new(),
+            meters: HashMap::new(),
+            meter: LoudnessMeter::new(server_config.sample_rate),
+            effect_chains: HashMap::new(),
+            last_talkers: HashMap::new(),
+            last_total: vec![],
+            talkers: TalkersPacket::default(),
+            held_mixes: HashMap::new(),
             server_config,
         }
     }
 
-    fn add_remote(&mut self, remote: SafeRemote) {
-        let addr = { remote.lock().unwrap().addr };
+    pub(crate) fn add_remote(&mut self, remote: SafeRemote) {
+        let (addr, effects) = {
+            let remote = remote.lock().unwrap();
+            (remote.addr, remote.effects.clone())
+        };
         self.remotes.push(remote);
+        self.rebuild_effects(addr, &effects);
 
         self.buffers
             .insert(addr, vec![0.0; self.server_config.get_framesize() * 2]);
-        self.filter_states.insert(addr, (0.0, 0.0));
+        self.filter_states.insert(addr, Default::default());
     }
 
     fn remove_remote(&mut self, addr: &SocketAddr) {
         self.remotes.retain(|c| c.lock().unwrap().addr != *addr);
         self.buffers.remove(addr);
         self.filter_states.remove(addr);
+        self.highpass_states.remove(addr);
+        self.fade_levels.remove(addr);
+        self.talker_gains.remove(addr);
+        self.listener_gains.remove(addr);
+        self.meters.remove(addr);
+        self.effect_chains.remove(addr);
+        self.held_mixes.remove(addr);
+        for gains in self.listener_gains.values_mut() {
+            gains.remove(addr);
+        }
+    }
+
+    /// Replaces the remote's effect chain. Returns false if they aren't in this channel
+    pub(crate) fn set_effects(&mut self, addr: SocketAddr, effects: &[EffectKind]) -> bool {
+        let Some(remote) = self
+            .remotes
+            .iter()
+            .find(|remote| remote.lock().unwrap().addr == addr)
+        else {
+            return false;
+        };
+
+        remote.lock().unwrap().effects = effects.iter().copied().take(MAX_EFFECTS).collect();
+        self.rebuild_effects(addr, effects);
+        true
+    }
+
+    fn rebuild_effects(&mut self, addr: SocketAddr, effects: &[EffectKind]) {
+        if effects.is_empty() {
+            self.effect_chains.remove(&addr);
+        } else {
+            self.effect_chains.insert(
+                addr,
+                EffectChain::new(effects, self.server_config.sample_rate),
+            );
+        }
+    }
+
+    /// Whether the remote was audible in the last mixed tick
+    pub(crate) fn is_speaking(&self, addr: &SocketAddr) -> bool {
+        self.fade_levels.get(addr).is_some_and(|&level| level > 0.0)
+    }
+
+    pub(crate) fn find_remote(&self, mask: &str) -> Option<SocketAddr> {
+        self.remotes.iter().find_map(|remote| {
+            let remote = remote.lock().unwrap();
+            (remote.mask.as_deref() == Some(mask)).then_some(remote.addr)
+        })
+    }
+
+    pub(crate) fn set_listener_gain(
+        &mut self,
+        listener: SocketAddr,
+        talker: SocketAddr,
+        gain: f32,
+    ) {
+        let gains = self.listener_gains.entry(listener).or_default();
+        if gain == 1.0 {
+            gains.remove(&talker);
+        } else {
+            gains.insert(talker, gain);
+        }
+    }
+
+    pub(crate) fn set_talker_gain(&mut self, talker: SocketAddr, gain: f32) {
+        if gain == 1.0 {
+            self.talker_gains.remove(&talker);
+        } else {
+            self.talker_gains.insert(talker, gain);
+        }
+    }
+
+    // clients show who they hear and set per user volumes off of this
+    fn announce_talkers(&mut self, socket: &SecureUdpSocket) {
+        let mut masks = self
+            .remotes
+            .iter()
+            .filter_map(|remote| {
+                let remote = remote.lock().unwrap();
+                self.is_speaking(&remote.addr)
+                    .then(|| remote.mask.clone())
+                    .flatten()
+            })
+            .collect::<Vec<_>>();
+        masks.sort();
+
+        if masks == self.talkers.masks {
+            return;
+        }
+
+        self.talkers.masks = masks;
+        let packet = self.talkers.serialize();
+        for remote in &self.remotes {
+            let addr = remote.lock().unwrap().addr;
+            if let Err(e) = socket.send_reliable(packet.clone(), addr) {
+                error!("Failed to send talkers to {addr}: {e}");
+            }
+        }
     }
 
-    fn mix(&mut self, socket: &SecureUdpSocket) {
+    /// Mixes and sends this tick's audio, returning how many remotes were talking
+    fn mix(&mut self, socket: &SecureUdpSocket) -> usize {
         // pre-proc audio for every remote:
+        let fade_frames = (self.server_config.sample_rate * FADE_MILLIS / 1000) as usize;
+        let mut talking = 0;
         let mut processed_buffers = HashMap::new();
         for (addr, buf) in &self.buffers {
-            if buf.len() != self.server_config.get_framesize() * 2 || mixer::is_silent(buf) {
+            if buf.len() != self.server_config.get_framesize() * 2 {
+                continue;
+            }
+
+            self.meters
+                .entry(*addr)
+                .or_insert_with(|| LoudnessMeter::new(self.server_config.sample_rate))
+                .process(buf);
+
+            // talkers are ramped in and out of the mix instead of being cut, so a
+            // remote that just went quiet still gets one fading frame
+            let level = self.fade_levels.get(addr).copied().unwrap_or(0.0);
+            let silent = mixer::is_silent(buf, self.server_config.silence_threshold);
+            if silent && level == 0.0 {
                 continue;
             }
 
-            let state = self.filter_states.entry(*addr).or_insert((0.0, 0.0));
+            let state = self.filter_states.entry(*addr).or_default();
             let mut processed = buf.clone();
-            mixer::remove_dc_bias(&mut processed, state);
+            mixer::remove_dc_bias(&mut processed, state, self.server_config.dc_alpha);
+
+            if let Some(cutoff) = self.server_config.highpass_hz {
+                self.highpass_states
+                    .entry(*addr)
+                    .or_insert_with(|| mixer::HighPass::new(cutoff, self.server_config.sample_rate))
+                    .process(&mut processed);
+            }
+
+            if let Some(chain) = self.effect_chains.get_mut(addr) {
+                chain.process(&mut processed);
+            }
+
+            let target = if silent { 0.0 } else { 1.0 };
+            if level != target {
+                mixer::apply_ramp(&mut processed, level, target, fade_frames);
+            }
+            self.fade_levels.insert(*addr, target);
+
+            if let Some(&talker_gain) = self.talker_gains.get(addr) {
+                mixer::scale(&mut processed, talker_gain);
+            }
+
+            talking += !silent as usize;
             processed_buffers.insert(*addr, processed);
         }
 
+        self.announce_talkers(socket);
+
+        let framesize = self.server_config.get_framesize() * 2;
+
+        // pan positions only depend on who is talking, so every talker gets
+        // panned once here rather than once per listener
+        let panned_buffers: HashMap<SocketAddr, Vec<f32>> = if self.server_config.stereo_panning {
+            self.remotes
+                .iter()
+                .filter_map(|remote| {
+                    let guard = remote.lock().unwrap();
+                    let buf = processed_buffers.get(&guard.addr)?;
+                    let key = guard.mask.clone().unwrap_or(guard.addr.to_string());
+
+                    let mut panned = vec![0.0f32; framesize];
+                    mixer::mix_panned(&mut panned, buf, mixer::pan_position(&key), 1.0);
+                    Some((guard.addr, panned))
+                })
+                .collect()
+        } else {
+            HashMap::new()
+        };
+
+        // everyone hears the same talkers minus themselves, so sum all talkers
+        // once and take each listener's own voice back out of the total
+        let mut total = vec![0.0f32; framesize];
+        for buf in processed_buffers.values() {
+            mixer::accumulate(&mut total, buf, 1.0);
+        }
+        self.meter.process(&total);
+
+        let mut panned_total = vec![0.0f32; framesize];
+        for buf in panned_buffers.values() {
+            mixer::accumulate(&mut panned_total, buf, 1.0);
+        }
+
         // personalized mix which is done separately
         for remote in &self.remotes {
             let mut guard = remote.lock().unwrap();
             let remote_addr = guard.addr;
 
-            if !self.buffers.contains_key(&remote_addr) || guard.status.deaf {
+            // operators monitoring something hear that instead, see send_monitor_mixes
+            if !self.buffers.contains_key(&remote_addr)
+                || guard.status.is_deaf()
+                || guard.monitoring.is_some()
+            {
                 continue;
             }
 
-            // collect all active talkers excluding self
-            let talkers: Vec<_> = processed_buffers
-                .iter()
-                .filter(|(addr, _)| **addr != remote_addr)
-                .collect();
-
-            let active_count = talkers.len();
+            // count all active talkers excluding self
+            let active_count =
+                processed_buffers.len() - processed_buffers.contains_key(&remote_addr) as usize;
             if active_count == 0 {
                 continue;
             }
@@ -217,66 +639,97 @@ impl Channel {
             // compute gain once
             let gain = 1.0 / (active_count as f32).sqrt();
 
-            let mut mix = vec![0.0f32; self.server_config.get_framesize() * 2];
-            for (_, buf) in talkers {
-                for (i, sample) in buf.iter().enumerate() {
-                    mix[i] += sample * gain;
-                }
-            }
-
-            if self.server_config.should_compress {
-                mixer::compress(
-                    &mut mix,
-                    self.server_config.compress_threshold,
-                    self.server_config.compress_ratio,
-                );
-            }
+            let (sum, own, sources) = if self.server_config.stereo_panning && guard.status.panning {
+                (
+                    &panned_total,
+                    panned_buffers.get(&remote_addr),
+                    &panned_buffers,
+                )
+            } else {
+                (
+                    &total,
+                    processed_buffers.get(&remote_addr),
+                    &processed_buffers,
+                )
+            };
 
-            if self.server_config.should_normalize {
-                mixer::normalize(&mut mix);
+            let mut mix = vec![0.0f32; framesize];
+            mix.copy_from_slice(sum);
+            if let Some(own) = own {
+                mixer::accumulate(&mut mix, own, -1.0);
             }
 
-            match self.server_config.clipping {
-                Clipping::Soft => mixer::soft_clip(&mut mix),
-                Clipping::Hard => {
-                    mix.iter_mut().for_each(|s| *s = s.clamp(-1.0, 1.0));
+            // talkers this listener turned up or down get corrected on top of the shared sum
+            for (talker, talker_gain) in self.listener_gains.get(&remote_addr).into_iter().flatten()
+            {
+                if *talker != remote_addr
+                    && let Some(buf) = sources.get(talker)
+                {
+                    mixer::accumulate(&mut mix, buf, talker_gain - 1.0);
                 }
             }
 
-            let mut encoded = vec![0u8; 400];
-            let len = guard.encoder.encode_float(&mix, &mut encoded).unwrap_or(0);
-
-            if len > 0 {
-                let mut packet = vec![0x02];
-                packet.extend_from_slice(&self.server_config.current_tick.to_be_bytes());
-                packet.extend_from_slice(&encoded[..len]);
-                if let Err(e) = socket.send_to(&packet, remote_addr) {
-                    error!("Failed to send audio to {remote_addr}: {e}");
-                }
+            mixer::scale(&mut mix, gain);
+            if guard.subscriptions.is_empty() {
+                guard.send_mix(socket, &self.server_config, &mut mix);
+            } else {
+                self.held_mixes.insert(remote_addr, mix);
             }
         }
 
+        self.last_total = total;
+        self.last_talkers = processed_buffers;
+
         // Clear buffers for next tick
         for buf in self.buffers.values_mut() {
             buf.fill(0.0);
         }
+
+        talking
     }
 }
 
 pub struct ServerState {
     socket: Arc<SecureUdpSocket>,
     remotes: HashMap<SocketAddr, SafeRemote>,
-    consoles: HashMap<SocketAddr, SafeConsole>,
+    // by session id, several can be attached at once
+    consoles: HashMap<u32, SafeConsole>,
+    next_console_id: u32,
+    console_challenges: HashMap<ConsoleLink, ConsoleChallenge>,
     channels: HashMap<u32, Channel>,
     audio_rb: HeapRb<(SocketAddr, Vec<u8>)>,
     config: ServerConfig,
     command_system: CommandSystem,
     plugin_manager: PluginManager,
+    plugin_dir: PathBuf,
+    // names of the plugins' commands in `command_system`
+    plugin_commands: Vec<String>,
     plugin_rx: Receiver<PluginAction>,
+    audit: Arc<Mutex<AuditLog>>,
+    bans: BanList,
+    recent_joins: VecDeque<Instant>,
+    // how long the last second of ticks took, for /stats
+    tick_times: VecDeque<Duration>,
+    #[cfg(feature = "telemetry")]
+    telemetry: Option<Telemetry>,
+    #[cfg(feature = "tls-console")]
+    tls_consoles: Option<Receiver<TlsConsoleEvent>>,
 }
 
 impl ServerState {
     pub fn new(config: ServerConfig, phrase: &[u8]) -> Result<Self, io::Error> {
+        Self::with_storage(
+            config,
+            phrase,
+            Arc::new(FileStorage::open(Path::new(DEFAULT_DATA_DIR))),
+        )
+    }
+
+    pub fn with_storage(
+        config: ServerConfig,
+        phrase: &[u8],
+        storage: Arc<dyn Storage>,
+    ) -> Result<Self, io::Error> {
         info!("v{} VoUDP protocol server", protocol::VERSION);
         info!("Deriving key from phrase...");
         let key = socket::derive_key_from_phrase(phrase, protocol::VOUDP_SALT);
@@ -289,7 +742,14 @@ impl ServerState {
         );
 
         let mut default_channels = HashMap::new();
-        default_channels.insert(1, Channel::new(config, String::from("general"), 1));
+        default_channels.insert(
+            protocol::DEFAULT_CHANNEL_ID,
+            Channel::new(
+                config,
+                String::from("general"),
+                protocol::DEFAULT_CHANNEL_ID,
+            ),
+        );
         default_channels.insert(2, Channel::new(config, String::from("music"), 2));
         default_channels.insert(3, Channel::new(config, String::from("test"), 3));
 
@@ -307,10 +767,42 @@ impl ServerState {
                 aliases: vec![],
                 requires_auth: false,
                 admin_only: false,
+                args: vec![],
             },
             move |_, _| CommandResult::Success(format!("{:#?}", config)),
         );
 
+        let started = Instant::now();
+        command_system.register_command(
+            ServerCommand {
+                name: "/serverinfo".into(),
+                description: "Show server information".into(),
+                usage: "/serverinfo".into(),
+                category: CommandCategory::Utility,
+                aliases: vec!["/status".into()],
+                requires_auth: false,
+                admin_only: false,
+                args: vec![],
+            },
+            move |_, chans| {
+                let uptime = started.elapsed().as_secs();
+                let users = chans.values().map(|chan| chan.remotes.len()).sum::<usize>();
+                CommandResult::Success(format!(
+                    "voudp v{}\n\
+                     uptime: {}h {:02}m\n\
+                     users: {users}/{} in {} channels\n\
+                     audio: {} tps at {}Hz",
+                    protocol::VERSION,
+                    uptime / 3600,
+                    uptime / 60 % 60,
+                    config.max_users,
+                    chans.len(),
+                    config.tickrate,
+                    config.sample_rate,
+                ))
+            },
+        );
+
         command_system.register_command(
             ServerCommand {
                 name: "/broadcast".into(),
@@ -319,18 +811,15 @@ impl ServerState {
                 category: CommandCategory::Admin,
                 aliases: vec!["/b".into(), "/broad".into()],
                 requires_auth: true,
-                admin_only: false,
+                admin_only: true,
+                args: vec![CommandArg::required("message", ArgKind::Text)],
             },
             move |ctx, chans| {
-                if ctx.arguments.is_empty() {
-                    return CommandResult::Error("usage: /broadcast <message>".into());
-                }
-
                 Self::broadcast_channel(
                     socket_clone.clone(),
                     chans,
                     ctx.channel_id,
-                    ctx.arguments.join(" "),
+                    ctx.text(0),
                     String::new(),
                 );
 
@@ -346,13 +835,10 @@ impl ServerState {
                 category: CommandCategory::Admin,
                 aliases: vec![],
                 requires_auth: true,
-                admin_only: false,
+                admin_only: true,
+                args: vec![CommandArg::required("mask", ArgKind::User)],
             },
             move |ctx, chans| {
-                if ctx.arguments.is_empty() {
-                    return CommandResult::Error("usage: /dox <mask>".into());
-                }
-
                 if let Some(chan) = chans.get(&ctx.channel_id) {
                     let remote = chan.remotes.iter().find(|remote| {
                         remote
@@ -381,79 +867,628 @@ impl ServerState {
             },
         );
 
-        let socket = Arc::new(socket); // wrap in Arc
+        command_system.register_command(
+            ServerCommand {
+                name: "/fx".into(),
+                description: "Apply voice effects to yourself".into(),
+                usage: "/fx <pitch|reverb|robot ...|off>".into(),
+                category: CommandCategory::Audio,
+                aliases: vec!["/effects".into()],
+                requires_auth: false,
+                admin_only: false,
+                args: vec![CommandArg::required("effects", ArgKind::Text)],
+            },
+            move |ctx, chans| {
+                let effects = match ctx.arguments.as_slice() {
+                    [off] if off == "off" => vec![],
+                    args => match args.iter().map(|arg| arg.parse()).collect() {
+                        Ok(effects) => effects,
+                        Err(e) => return CommandResult::Error(e),
+                    },
+                };
 
-        let mut plugin_manager = PluginManager::new(plugin_tx.clone());
+                let applied = chans
+                    .get_mut(&ctx.channel_id)
+                    .is_some_and(|chan| chan.set_effects(ctx.sender_addr, &effects));
+                if !applied {
+                    return CommandResult::Error("you are not in a channel".into());
+                }
 
-        let plugins_dir = Path::new("plugins");
-        if plugins_dir.exists() && plugins_dir.is_dir() {
-            for entry in fs::read_dir(plugins_dir)
-                .expect("Failed to read plugins directory")
-                .flatten()
-            {
-                let path = entry.path();
-                if path.extension().and_then(|s| s.to_str()) == Some("lua") {
-                    plugin_manager.load_plugin(&path);
+                if effects.is_empty() {
+                    CommandResult::Success("effects turned off".into())
+                } else {
+                    let names = effects
+                        .iter()
+                        .copied()
+                        .take(MAX_EFFECTS)
+                        .map(EffectKind::name)
+                        .collect::<Vec<_>>();
+                    CommandResult::Success(format!("effects: {}", names.join(" -> ")))
                 }
-            }
-        } else {
-            warn!("Directory `./plugins` does not exist");
-        }
+            },
+        );
+
+        let socket_clone = socket.clone();
+        command_system.register_command(
+            ServerCommand {
+                name: "/whisper".into(),
+                description: "Send a private message".into(),
+                usage: "/whisper <user> <message>".into(),
+                category: CommandCategory::Chat,
+                aliases: vec!["/w".into(), "/msg".into(), "/tell".into()],
+                requires_auth: true,
+                admin_only: false,
+                args: vec![
+                    CommandArg::required("user", ArgKind::User),
+                    CommandArg::required("message", ArgKind::Text),
+                ],
+            },
+            move |ctx, chans| {
+                let to = &ctx.arguments[0];
+                let Some(addr) = Self::find_by_mask(chans, to) else {
+                    return CommandResult::Error(format!("{to} is not online"));
+                };
+
+                let from = ctx.sender_mask.clone().unwrap();
+                let message = ctx.text(1);
+                Self::send_whisper(&socket_clone, addr, &from, &message);
+
+                CommandResult::Success(format!("-> {to}: {message}"))
+            },
+        );
+
+        let bans = BanList::new(storage.clone());
+        let audit = Arc::new(Mutex::new(AuditLog::new(storage)));
+
+        let audit_clone = audit.clone();
+        command_system.register_command(
+            ServerCommand {
+                name: "/note".into(),
+                description: "Attach or read moderation notes".into(),
+                usage: "/note <add|list> <mask> [text]".into(),
+                category: CommandCategory::Admin,
+                aliases: vec![],
+                requires_auth: true,
+                admin_only: true,
+                args: vec![
+                    CommandArg::required("add|list", ArgKind::String),
+                    CommandArg::required("mask", ArgKind::String),
+                    CommandArg::optional("text", ArgKind::Text),
+                ],
+            },
+            move |ctx, _| {
+                let subject = &ctx.arguments[1];
+                let mut audit = audit_clone.lock().unwrap();
+                match ctx.arguments[0].as_str() {
+                    "add" if ctx.arguments.len() > 2 => {
+                        let author = ctx.sender_mask.clone().unwrap_or_default();
+                        audit.add_note(subject, &author, &ctx.text(2));
+                        info!("{author} added a note about {subject}");
+                        CommandResult::Success(format!("noted about {subject}"))
+                    }
+                    "list" => {
+                        CommandResult::Success(audit::format_notes(subject, audit.notes(subject)))
+                    }
+                    _ => CommandResult::Error("usage: /note <add|list> <mask> [text]".into()),
+                }
+            },
+        );
+
+        let audit_clone = audit.clone();
+        let monitor_socket = socket.clone();
+        command_system.register_command(
+            ServerCommand {
+                name: "/monitor".into(),
+                description: "Listen in on a channel or a user instead of your channel".into(),
+                usage: "/monitor <channel_id|mask|off>".into(),
+                category: CommandCategory::Admin,
+                aliases: vec!["/mon".into()],
+                requires_auth: true,
+                admin_only: true,
+                args: vec![CommandArg::required("channel_id|mask|off", ArgKind::String)],
+            },
+            move |ctx, chans| {
+                let arg = &ctx.arguments[0];
+
+                let target = if arg == "off" {
+                    None
+                } else if let Ok(id) = arg.parse::<u32>() {
+                    if !chans.contains_key(&id) {
+                        return CommandResult::Error(format!("channel {id} does not exist"));
+                    }
+                    Some(MonitorTarget::Channel(id))
+                } else {
+                    match chans.values().find_map(|chan| chan.find_remote(arg)) {
+                        Some(addr) if addr == ctx.sender_addr => {
+                            return CommandResult::Error("you can't monitor yourself".into());
+                        }
+                        Some(addr) => Some(MonitorTarget::Remote(addr)),
+                        None => return CommandResult::Error(format!("{arg} is not connected")),
+                    }
+                };
 
-        plugin_manager.log_loaded();
+                let Some(operator) = chans.get(&ctx.channel_id).and_then(|chan| {
+                    chan.remotes
+                        .iter()
+                        .find(|remote| remote.lock().unwrap().addr == ctx.sender_addr)
+                }) else {
+                    return CommandResult::Error("you are not in a channel".into());
+                };
+                operator.lock().unwrap().monitoring = target;
+
+                let operator_mask = ctx.sender_mask.clone().unwrap_or_default();
+                let description = match target {
+                    None => "stopped monitoring".to_string(),
+                    Some(MonitorTarget::Channel(id)) => format!("started monitoring channel {id}"),
+                    Some(MonitorTarget::Remote(_)) => format!("started monitoring {arg}"),
+                };
+
+                // every use is on record, under the operator's name
+                info!("{operator_mask} ({}) {description}", ctx.sender_addr);
+                audit_clone
+                    .lock()
+                    .unwrap()
+                    .add_note(&operator_mask, "monitor", &description);
+
+                let watched = match target {
+                    Some(MonitorTarget::Channel(id)) => chans.get(&id),
+                    Some(MonitorTarget::Remote(addr)) => chans.values().find(|chan| {
+                        chan.remotes
+                            .iter()
+                            .any(|remote| remote.lock().unwrap().addr == addr)
+                    }),
+                    None => None,
+                };
+
+                if config.announce_monitoring
+                    && let Some(watched) = watched
+                {
+                    for remote in &watched.remotes {
+                        let addr = remote.lock().unwrap().addr;
+                        if addr != ctx.sender_addr {
+                            Self::notice(
+                                &monitor_socket,
+                                addr,
+                                LocalizedMessage::new(
+                                    MessageCode::Monitored,
+                                    vec![operator_mask.clone()],
+                                ),
+                            );
+                        }
+                    }
+                }
+
+                CommandResult::Success(description)
+            },
+        );
+
+        let socket = Arc::new(socket); // wrap in Arc
+
+        let plugin_manager = PluginManager::new(plugin_tx.clone());
 
         Ok(Self {
             socket: Arc::clone(&socket),
             remotes: HashMap::new(),
             consoles: HashMap::new(),
+            next_console_id: 1,
+            console_challenges: HashMap::new(),
             channels: default_channels,
             audio_rb: HeapRb::new(config.max_users),
             config,
             command_system,
             plugin_manager,
+            plugin_dir: PathBuf::from(DEFAULT_PLUGIN_DIR),
+            plugin_commands: Vec::new(),
             plugin_rx,
+            audit,
+            bans,
+            recent_joins: VecDeque::new(),
+            tick_times: VecDeque::new(),
+            #[cfg(feature = "telemetry")]
+            telemetry: None,
+            #[cfg(feature = "tls-console")]
+            tls_consoles: None,
         })
     }
 
-    fn handle_console(&mut self, addr: SocketAddr, data: &[u8]) {
+    /// Channels and who's in them, for plugins
+    fn directory(&self) -> Vec<DirectoryChannel> {
+        let mut channels = self
+            .channels
+            .iter()
+            .map(|(&id, channel)| DirectoryChannel {
+                id,
+                name: channel.name.clone(),
+                users: channel
+                    .remotes
+                    .iter()
+                    .map(|remote| {
+                        let remote = remote.lock().unwrap();
+                        DirectoryUser {
+                            mask: remote.mask.clone(),
+                            addr: remote.addr,
+                            muted: remote.status.is_muted(),
+                            deafened: remote.status.is_deaf(),
+                            admin: remote.role == Role::Admin,
+                        }
+                    })
+                    .collect(),
+            })
+            .collect::<Vec<_>>();
+        channels.sort_by_key(|channel| channel.id);
+        channels
+    }
+
+    /// Lists the plugins' slash commands with the built-in ones, replacing
+    /// whatever the plugins registered before a reload
+    fn sync_plugin_commands(&mut self) {
+        for name in self.plugin_commands.drain(..) {
+            self.command_system.unregister_command(&name);
+        }
+
+        let commands = self.plugin_manager.commands().cloned().collect::<Vec<_>>();
+        for command in commands {
+            if self.command_system.get_command(&command.name).is_some() {
+                warn!(
+                    "{} is already a command, skipping the plugin's",
+                    command.name
+                );
+                self.plugin_manager.unregister_command(&command.name);
+                continue;
+            }
+            // execute_command hands it to the plugin
+            self.plugin_commands.push(command.name.clone());
+            self.command_system
+                .register_command(command, |_, _| CommandResult::Silent);
+        }
+    }
+
+    /// Where lua plugins are loaded from when the server starts, `plugins` by
+    /// default
+    pub fn set_plugin_dir(&mut self, dir: impl Into<PathBuf>) {
+        self.plugin_dir = dir.into();
+    }
+
+    /// Lets plugins `Core.http_get` these hosts and their subdomains
+    pub fn allow_plugin_hosts(&mut self, hosts: Vec<String>) {
+        self.plugin_manager.set_http_hosts(hosts);
+    }
+
+    /// Compiles `plugin` in, see `VoudpPlugin`
+    pub fn register_plugin(&mut self, plugin: impl VoudpPlugin + 'static) {
+        self.plugin_manager.register_native(Box::new(plugin));
+    }
+
+    pub fn local_addr(&self) -> SocketAddr {
+        self.socket.local_addr()
+    }
+
+    /// Opts this server into periodic anonymous usage statistics
+    #[cfg(feature = "telemetry")]
+    pub fn enable_telemetry(&mut self, config: TelemetryConfig) {
+        self.telemetry = Some(Telemetry::new(config));
+    }
+
+    /// Also takes consoles over TCP with TLS, returning where it listens
+    #[cfg(feature = "tls-console")]
+    pub fn listen_tls_console(&mut self, config: &TlsConsoleConfig) -> io::Result<SocketAddr> {
+        let (addr, events) = console_tls::listen(config)?;
+        self.tls_consoles = Some(events);
+        Ok(addr)
+    }
+
+    // packets from TLS consoles, which only ever speak the console protocol
+    #[cfg(feature = "tls-console")]
+    fn tls_consoles_update(&mut self) {
+        let Some(events) = &self.tls_consoles else {
+            return;
+        };
+
+        for event in events.try_iter().collect::<Vec<_>>() {
+            match event {
+                TlsConsoleEvent::Packet(link, data) => {
+                    let link = ConsoleLink::Tls(link);
+                    match data.split_first() {
+                        Some((&kind, data)) if kind == ClientPacketType::RegisterConsole as u8 => {
+                            self.register_console(&link, data)
+                        }
+                        Some(_) => self.handle_console(&link, &data),
+                        None => {}
+                    }
+                }
+                TlsConsoleEvent::Closed(link) => {
+                    let link = ConsoleLink::Tls(link);
+                    self.console_challenges.remove(&link);
+                    self.handle_console_eof(&link);
+                }
+            }
+        }
+    }
+
+    // sessions attached over `link`
+    fn consoles_at(&self, link: &ConsoleLink) -> impl Iterator<Item = &SafeConsole> {
+        self.consoles
+            .values()
+            .filter(move |console| console.lock().unwrap().link == *link)
+    }
+
+    fn handle_console(&mut self, link: &ConsoleLink, data: &[u8]) {
         type Cpt = ConsolePacketType;
         match ConsolePacketType::try_from(data[0]) {
-            Ok(Cpt::Cmd) => self.handle_console_command(addr, &data[1..]),
-            Ok(Cpt::Eof) => self.handle_console_eof(addr),
-            Ok(Cpt::Keepalive) => {}
+            Ok(Cpt::Cmd) => self.handle_console_command(link, &data[1..]),
+            Ok(Cpt::Eof) => self.handle_console_eof(link),
+            Ok(Cpt::Keepalive) => {
+                for console in self.consoles_at(link) {
+                    console.lock().unwrap().last_active = Instant::now();
+                }
+                // answered so the console notices when we're gone
+                let keepalive = ConsoleMessage::new(ConsoleMessageKind::Keepalive, vec![]);
+                let _ = link.send_unreliable(&self.socket, keepalive.to_json().as_bytes());
+            }
             _ => error!(
-                "Console {addr} sent an invalid packet (starts with {:#?}",
+                "Console {link} sent an invalid packet (starts with {:#?}",
                 data[0]
             ),
         }
     }
 
-    fn handle_console_command(&mut self, addr: SocketAddr, data: &[u8]) {
+    fn handle_console_command(&mut self, link: &ConsoleLink, data: &[u8]) {
+        let Some((token, data)) = data.split_at_checked(CONSOLE_TOKEN_LEN) else {
+            warn!("Console {link} sent a command without its session token");
+            return;
+        };
+        let Some((id, role)) = self.consoles_at(link).find_map(|console| {
+            let mut console = console.lock().unwrap();
+            same_bytes(token, &console.token).then(|| {
+                console.last_active = Instant::now();
+                (console.id, console.role)
+            })
+        }) else {
+            warn!("Console {link} sent a command with the wrong session token");
+            return;
+        };
+
         if let Ok(req) = String::from_utf8(data.to_vec()) {
             let parts: Vec<&str> = req.split_whitespace().collect();
 
-            let reply: String = if !parts.is_empty() {
+            let reply = if parts.is_empty() {
+                ConsoleMessage::reply("server received your empty message")
+            } else if role == ConsoleRole::Observer && !console_cmd::is_read_only(parts[0], &parts)
+            {
+                ConsoleMessage::error(format!(
+                    "Permission denied: {} needs an admin console, this one observes",
+                    parts[0]
+                ))
+            } else if parts[0] == "sessions" {
+                ConsoleMessage::reply(self.list_consoles(id))
+            } else if parts[0] == "status" {
+                self.status_report()
+            } else if parts[0] == "commands" {
+                // whatever this console may run, in some form
+                let commands = console_cmd::COMMANDS
+                    .iter()
+                    .filter(|cmd| {
+                        role == ConsoleRole::Admin || console_cmd::is_read_only(cmd, &[cmd])
+                    })
+                    .copied()
+                    .collect::<Vec<_>>()
+                    .join(" ");
+                let mut reply = ConsoleMessage::reply(format!("commands: {commands}"));
+                reply.payload.push(("commands".into(), commands));
+                reply
+            } else if parts[0] == "subscribe" || parts[0] == "unsubscribe" {
+                match self.subscribe_console(id, parts[0] == "subscribe", &parts[1..]) {
+                    Ok(subscribed) => ConsoleMessage::reply(subscribed),
+                    Err(e) => ConsoleMessage::error(e),
+                }
+            } else {
                 let cmd = parts[0];
 
-                match handle_command(cmd, &parts, &mut self.channels, &self.config, None) {
-                    ConsoleCommandResult::Reply(msg) => msg,
+                let mut audit = self.audit.lock().unwrap();
+                let result = handle_command(
+                    cmd,
+                    &parts,
+                    &mut self.channels,
+                    &self.config,
+                    &mut audit,
+                    &mut self.plugin_manager,
+                );
+                drop(audit);
+
+                if cmd == "plugins" {
+                    // a reload may have added or dropped commands
+                    self.sync_plugin_commands();
+                }
+                match result {
+                    ConsoleCommandResult::Reply(msg) => ConsoleMessage::reply(msg),
+                    ConsoleCommandResult::Error(msg) => ConsoleMessage::error(msg),
+                    ConsoleCommandResult::Apply(msg, actions) => {
+                        for action in actions {
+                            self.apply_console_action(action);
+                        }
+                        ConsoleMessage::reply(msg)
+                    }
                 }
-            } else {
-                "server received your empty message".into()
             };
 
-            if let Err(e) = self.socket.send_reliable(reply.as_bytes().to_vec(), addr) {
-                warn!("Could not reply back to console {addr} due to {e}");
+            if let Err(e) = link.send(&self.socket, reply.to_json().into_bytes()) {
+                warn!("Could not reply back to console {link} due to {e}");
             }
         } else {
-            warn!("Received bad command from {addr}");
+            warn!("Received bad command from {link}");
         }
     }
 
-    fn handle_console_eof(&mut self, addr: SocketAddr) {
-        self.consoles.retain(|addr_got, _| {
-            if *addr_got == addr {
-                info!("Console {addr} left the server");
+    fn apply_console_action(&mut self, action: ConsoleAction) {
+        match action {
+            ConsoleAction::Kick { addr, reason } => {
+                let kicked =
+                    LocalizedMessage::new(MessageCode::Kicked, vec!["console".into(), reason]);
+                self.kick_socket(addr, Some(kicked));
+            }
+            ConsoleAction::Ban { addr, mask, reason } => {
+                self.bans.ban(addr.ip(), &mask, &reason);
+                let banned = LocalizedMessage::new(MessageCode::Banned, vec![reason]);
+                self.kick_socket(addr, Some(banned));
+            }
+            ConsoleAction::Mute { addr, muted } => {
+                if let Some(remote) = self.remotes.get(&addr) {
+                    remote.lock().unwrap().status.forced_mute = muted;
+                    let code = if muted {
+                        MessageCode::ServerMuted
+                    } else {
+                        MessageCode::ServerUnmuted
+                    };
+                    Self::notice(&self.socket, addr, LocalizedMessage::new(code, vec![]));
+                }
+            }
+            ConsoleAction::Move { addr, channel } => self.move_remote(addr, channel),
+            ConsoleAction::Say { channel, text } => {
+                let Some(channel) = self.channels.get(&channel) else {
+                    return;
+                };
+                for remote in &channel.remotes {
+                    let addr = remote.lock().unwrap().addr;
+                    let said = LocalizedMessage::new(MessageCode::Custom, vec![text.clone()]);
+                    Self::notice(&self.socket, addr, said);
+                }
+            }
+        }
+    }
+
+    // channels and who's in them with what they send, for the `status` console
+    // command. Rows are `row=channel` or `row=user`, each channel followed by
+    // its users
+    fn status_report(&self) -> ConsoleMessage {
+        let mut ids = self.channels.keys().copied().collect::<Vec<_>>();
+        ids.sort();
+
+        let mut rows = vec![];
+        let mut text = vec![];
+        for id in ids {
+            let channel = &self.channels[&id];
+            let name = channel.name.clone().unwrap_or_else(|| "unnamed".into());
+            rows.push(vec![
+                ("row".into(), "channel".into()),
+                ("channel".into(), id.to_string()),
+                ("name".into(), name.clone()),
+                ("users".into(), channel.remotes.len().to_string()),
+            ]);
+            text.push(format!("#{name} ({id}): {} users", channel.remotes.len()));
+
+            for remote in &channel.remotes {
+                let mut remote = remote.lock().unwrap();
+                remote.audio_stats.roll();
+                let mask = remote.mask.clone().unwrap_or_default();
+                let kbps = format!("{:.1}", remote.audio_stats.kbps);
+                let loss = format!("{:.1}", remote.audio_stats.loss_percent);
+                text.push(format!(
+                    "  {} {kbps} kbps, {loss}% dropped",
+                    if mask.is_empty() { "-" } else { &mask }
+                ));
+                rows.push(vec![
+                    ("row".into(), "user".into()),
+                    ("channel".into(), id.to_string()),
+                    ("mask".into(), mask),
+                    ("addr".into(), remote.addr.to_string()),
+                    ("kbps".into(), kbps),
+                    ("loss".into(), loss),
+                ]);
+            }
+        }
+
+        let mut reply = ConsoleMessage::reply(text.join("\n"));
+        reply.rows = rows;
+        reply
+    }
+
+    // the attached consoles, for the `sessions` console command
+    fn list_consoles(&self, asking: u32) -> String {
+        let mut consoles = self
+            .consoles
+            .values()
+            .map(|console| {
+                let console = console.lock().unwrap();
+                (
+                    console.id,
+                    format!(
+                        "#{} {:?} from {}, idle {}s{}",
+                        console.id,
+                        console.role,
+                        console.link,
+                        console.last_active.elapsed().as_secs(),
+                        if console.id == asking { " (you)" } else { "" }
+                    ),
+                )
+            })
+            .collect::<Vec<_>>();
+        consoles.sort();
+        consoles
+            .into_iter()
+            .map(|(_, line)| line)
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    // `subscribe` and `unsubscribe`, without events it shows what's subscribed
+    fn subscribe_console(
+        &self,
+        id: u32,
+        subscribe: bool,
+        events: &[&str],
+    ) -> Result<String, String> {
+        let mut picked = vec![];
+        for event in events {
+            match *event {
+                "all" => picked.extend(ConsoleEvent::ALL),
+                event => match event.parse::<ConsoleEvent>() {
+                    Ok(event) => picked.push(event),
+                    Err(e) => return Err(e),
+                },
+            }
+        }
+
+        let mut console = self.consoles[&id].lock().unwrap();
+        for event in picked {
+            if subscribe {
+                console.events.insert(event);
+            } else {
+                console.events.remove(&event);
+            }
+        }
+
+        let mut events = console.events.iter().copied().collect::<Vec<_>>();
+        events.sort();
+        if events.is_empty() {
+            Ok("subscribed to nothing".into())
+        } else {
+            let names = events.iter().map(|event| event.name()).collect::<Vec<_>>();
+            Ok(format!("subscribed to {}", names.join(", ")))
+        }
+    }
+
+    // pushes `payload` to every console subscribed to `event`
+    fn push_event(
+        consoles: &HashMap<u32, SafeConsole>,
+        socket: &SecureUdpSocket,
+        event: ConsoleEvent,
+        payload: &[(&str, String)],
+    ) {
+        let packet = ConsoleMessage::event(event, payload).to_json().into_bytes();
+        for console in consoles.values() {
+            let console = console.lock().unwrap();
+            if console.events.contains(&event)
+                && let Err(e) = console.link.send(socket, packet.clone())
+            {
+                warn!("Could not push an event to console #{}: {e}", console.id);
+            }
+        }
+    }
+
+    fn handle_console_eof(&mut self, link: &ConsoleLink) {
+        self.consoles.retain(|id, console| {
+            if console.lock().unwrap().link == *link {
+                info!("Console #{id} ({link}) left the server");
                 return false;
             }
             true
@@ -465,8 +1500,11 @@ impl ServerState {
             return;
         }
 
-        if self.consoles.contains_key(&addr) {
-            self.handle_console(addr, data);
+        // consoles can log in more sessions from where they are
+        if data[0] != ClientPacketType::RegisterConsole as u8
+            && self.consoles_at(&ConsoleLink::Udp(addr)).next().is_some()
+        {
+            self.handle_console(&ConsoleLink::Udp(addr), data);
             return;
         }
 
@@ -476,12 +1514,15 @@ impl ServerState {
             Ok(Cpt::Audio) => self.handle_audio(addr, &data[1..]),
             Ok(Cpt::Eof) => self.handle_eof(addr),
             Ok(Cpt::Mask) => self.handle_mask(addr, &data[1..]),
-            Ok(Cpt::List) => self.handle_list(addr),
+            Ok(Cpt::List) => self.handle_list(addr, &data[1..]),
             Ok(Cpt::Chat) => self.handle_chat(addr, &data[1..]),
+            Ok(Cpt::Dm) => self.handle_dm(addr, &data[1..]),
             Ok(Cpt::Ctrl) => self.handle_ctrl(addr, &data[1..]),
             Ok(Cpt::SyncCommands) => self.handle_sync_commands(addr),
             Ok(Cpt::Cmd) => self.handle_cmd(addr, &data[1..]),
-            Ok(Cpt::RegisterConsole) => self.register_console(addr, &data[1..]),
+            Ok(Cpt::RegisterConsole) => self.register_console(&ConsoleLink::Udp(addr), &data[1..]),
+            Ok(Cpt::Probe) => self.handle_probe(addr, data),
+            Ok(Cpt::Handshake) => self.handle_handshake(addr),
             _ => error!(
                 "{} sent an invalid packet (starts with {:#?})",
                 addr, data[0]
@@ -489,18 +1530,63 @@ impl ServerState {
         }
     }
 
-    fn register_console(&mut self, addr: SocketAddr, data: &[u8]) {
-        if let Ok(password) = String::from_utf8(data.to_vec()) {
-            if password.eq(PASSWORD) {
-                info!("Registered {addr} as a new console. Capabilties: cmd");
-                self.consoles
-                    .insert(addr, Arc::new(Mutex::new(Console::new(addr))));
-            } else {
-                info!("{addr} tried to log-in with the incorrect password");
-                self.handle_bad(addr);
+    fn register_console(&mut self, link: &ConsoleLink, data: &[u8]) {
+        let Some((&step, payload)) = data.split_first() else {
+            warn!("{link} sent a bad packet when wanting to register itself as a console");
+            return;
+        };
+
+        match ConsoleAuth::try_from(step) {
+            Ok(ConsoleAuth::Hello) => {
+                let role = match payload.first().map(|&role| ConsoleRole::try_from(role)) {
+                    None => ConsoleRole::Admin,
+                    Some(Ok(role)) => role,
+                    Some(Err(role)) => {
+                        warn!("{link} asked for an unknown console role {role:#04x}");
+                        return;
+                    }
+                };
+                let challenge = ConsoleChallenge {
+                    nonce: rand::random(),
+                    role,
+                    sent: Instant::now(),
+                };
+                let packet =
+                    protocol::create_console_auth(ConsoleAuth::Challenge, &challenge.nonce);
+                self.console_challenges.insert(link.clone(), challenge);
+                let _ = link.send(&self.socket, packet);
             }
-        } else {
-            warn!("{addr} sent a bad packet when wanting to register itself as a console")
+            Ok(ConsoleAuth::Proof) => {
+                // a nonce is good for one answer, replaying a proof gets nowhere
+                let answered = self
+                    .console_challenges
+                    .remove(link)
+                    .filter(|challenge| challenge.sent.elapsed() < CONSOLE_CHALLENGE_TIMEOUT)
+                    .filter(|challenge| {
+                        same_bytes(
+                            payload,
+                            &protocol::console_proof(PASSWORD, &challenge.nonce),
+                        )
+                    });
+                let Some(challenge) = answered else {
+                    info!("{link} tried to log-in with the incorrect password");
+                    let denied = protocol::create_console_auth(ConsoleAuth::Denied, &[]);
+                    let _ = link.send(&self.socket, denied);
+                    return;
+                };
+
+                let id = self.next_console_id;
+                self.next_console_id += 1;
+                info!(
+                    "Registered {link} as console #{id}. Role: {:?}",
+                    challenge.role
+                );
+                let console = Console::new(id, link.clone(), challenge.role);
+                let session = protocol::create_console_auth(ConsoleAuth::Session, &console.token);
+                self.consoles.insert(id, Arc::new(Mutex::new(console)));
+                let _ = link.send(&self.socket, session);
+            }
+            _ => warn!("{link} sent a bad packet when wanting to register itself as a console"),
         }
     }
 
@@ -509,69 +1595,174 @@ impl ServerState {
             return;
         }
 
-        let chan_id = u32::from_be_bytes([data[0], data[1], data[2], data[3]]);
+        let chan_id = match u32::from_be_bytes([data[0], data[1], data[2], data[3]]) {
+            0 => protocol::DEFAULT_CHANNEL_ID,
+            id => id,
+        };
 
-        if chan_id == 0 && chan_id >= u16::MAX as u32 {
+        if chan_id >= u16::MAX as u32 {
             warn!("{addr} tried to join channel with id {chan_id}, but that id is invalid");
             return;
         }
 
+        let rejoining = self.remotes.contains_key(&addr);
+        if let Some(ban) = self.bans.get(addr.ip()).filter(|_| !rejoining) {
+            info!("{addr} is banned, refusing their join");
+            let banned = LocalizedMessage::new(MessageCode::Banned, vec![ban.reason.clone()]);
+            self.kick_socket(addr, Some(banned));
+            return;
+        }
+
+        // only joins that could go through use up pacing slots
+        if !rejoining && self.should_pace_join(addr, chan_id) {
+            return;
+        }
+
+        let mask = self
+            .remotes
+            .get(&addr)
+            .and_then(|remote| remote.lock().unwrap().mask.clone());
+        let Some(chan_id) = self.plugin_manager.dispatch_join(
+            addr,
+            chan_id,
+            mask.as_deref(),
+            rejoining,
+            self.remotes.len(),
+        ) else {
+            let blocked = LocalizedMessage::new(MessageCode::JoinBlocked, vec![]);
+            if rejoining {
+                // they stay where they are
+                info!("Plugins prevented {addr} from joining channel {chan_id}");
+                Self::notice(&self.socket, addr, blocked);
+            } else {
+                info!("Plugins prevented {addr} from joining");
+                self.kick_socket(addr, Some(blocked));
+            }
+            return;
+        };
+
         info!("{} has joined the channel with id {}", addr, chan_id);
 
-        if !self.remotes.contains_key(&addr) && !self.plugin_manager.dispatch_join(addr, chan_id) {
-            info!("Plugins prevented {addr} from joining");
-            self.kick_socket(
-                addr,
-                Some("Server plugins blocked you from joining".to_owned()),
-            );
-            return;
+        if !self.remotes.contains_key(&addr) {
+            info!("{} is a new remote", addr);
+
+            match Remote::new(addr, self.config.sample_rate) {
+                Ok(remote) => {
+                    self.remotes.insert(addr, Arc::new(Mutex::new(remote)));
+                }
+                Err(e) => {
+                    error!("Failed to set up codec for {addr}, rejecting join: {e}");
+                    self.kick_socket(
+                        addr,
+                        Some(LocalizedMessage::new(MessageCode::JoinFailed, vec![])),
+                    );
+                    return;
+                }
+            }
         }
 
-        let remote = self.remotes.entry(addr).or_insert_with(|| {
-            info!("{} is a new remote", addr);
+        self.move_remote(addr, chan_id);
+
+        let mask = self.remotes[&addr].lock().unwrap().mask.clone();
+        Self::push_event(
+            &self.consoles,
+            &self.socket,
+            ConsoleEvent::Joins,
+            &[
+                ("addr", addr.to_string()),
+                ("channel", chan_id.to_string()),
+                ("mask", mask.unwrap_or_default()),
+            ],
+        );
+    }
 
-            Arc::new(Mutex::new(
-                Remote::new(addr, self.config.sample_rate).expect("remote creation failed"),
-            ))
-        });
+    /// Puts a connected remote in `chan_id`, creating the channel if needed
+    fn move_remote(&mut self, addr: SocketAddr, chan_id: u32) {
+        let Some(remote) = self.remotes.get(&addr) else {
+            return;
+        };
 
         let (old_channel_id, mask) = {
             let mut remote_guard = remote.lock().unwrap();
             let old_id = remote_guard.channel_id;
             let mask = remote_guard.mask.clone();
             remote_guard.channel_id = chan_id;
+            // no point listening in on where we are now
+            remote_guard.subscriptions.retain(|&id| id != chan_id);
             (old_id, mask)
         };
 
-        if old_channel_id != chan_id
-            && old_channel_id != 0
-            && let Some(old_channel) = self.channels.get_mut(&old_channel_id)
-        {
+        // also covers rejoining the same channel, so nobody is added twice
+        if let Some(old_channel) = self.channels.get_mut(&old_channel_id) {
             old_channel.remove_remote(&addr);
         }
+        if let Some(mask) = mask.as_deref().filter(|_| old_channel_id != chan_id) {
+            self.broadcast_leave(old_channel_id, mask);
+        }
 
         if let Some(mask) = mask {
             self.broadcast_join(chan_id, mask);
         }
 
         // add to new channel
-        let channel = self
-            .channels
-            .entry(chan_id)
-            .or_insert_with(|| Channel::new(self.config, format!("general-{chan_id}"), chan_id));
+        if !self.channels.contains_key(&chan_id) {
+            let channel = Channel::new(self.config, format!("general-{chan_id}"), chan_id);
+            self.plugin_manager
+                .dispatch_channel_create(chan_id, channel.name.as_deref());
+            self.channels.insert(chan_id, channel);
+        }
+        let channel = self.channels.get_mut(&chan_id).unwrap();
 
         if let Some(channel_name) = &channel.name {
-            Self::dm(
+            Self::notice(
                 &self.socket,
                 addr,
-                format!("You have been moved to #{channel_name}"),
+                LocalizedMessage::new(MessageCode::ChannelMoved, vec![channel_name.clone()]),
             );
         }
 
         if let Some(remote) = self.remotes.get(&addr) {
             channel.add_remote(remote.clone());
-            self.handle_list(addr);
+            // first page only, the client asks for the rest itself
+            self.handle_list(addr, &protocol::create_list_page_request(0, 0, 0)[1..]);
+        }
+    }
+
+    /// Keeps new joins under `max_joins_per_sec`, so a mass reconnect after a
+    /// restart doesn't allocate every codec at once. Joins over the limit are
+    /// told to come back once the window has room.
+    fn should_pace_join(&mut self, addr: SocketAddr, chan_id: u32) -> bool {
+        let limit = self.config.max_joins_per_sec as usize;
+        if limit == 0 {
+            return false;
+        }
+
+        let window = Duration::from_secs(1);
+        while self
+            .recent_joins
+            .front()
+            .is_some_and(|joined| joined.elapsed() >= window)
+        {
+            self.recent_joins.pop_front();
+        }
+
+        if self.recent_joins.len() < limit {
+            self.recent_joins.push_back(Instant::now());
+            return false;
         }
+
+        let delay = window.saturating_sub(self.recent_joins[0].elapsed());
+        let retry = RetryAfterPacket {
+            delay_ms: delay.as_millis().max(50) as u16,
+            channel_id: chan_id,
+        };
+
+        info!(
+            "Pacing joins, asked {addr} to retry in {}ms",
+            retry.delay_ms
+        );
+        let _ = self.socket.send_reliable(retry.serialize(), addr);
+        true
     }
 
     fn handle_audio(&mut self, addr: SocketAddr, data: &[u8]) {
@@ -581,10 +1772,14 @@ impl ServerState {
         let mut remote = remote.lock().unwrap();
 
         remote.last_active = Instant::now();
+        if remote.status.forced_mute {
+            return;
+        }
 
         // push to ring buffer for audio processing:
         if self.audio_rb.is_full() {
             error!("audio buffer overflow");
+            remote.audio_stats.record(data.len(), true);
             return;
         }
 
@@ -596,6 +1791,17 @@ impl ServerState {
             if *addr_got == addr {
                 let channel_id = { remote.lock().unwrap().channel_id };
                 let nick = { remote.lock().unwrap().mask.clone() };
+                Self::push_event(
+                    &self.consoles,
+                    &self.socket,
+                    ConsoleEvent::Leaves,
+                    &[
+                        ("addr", addr.to_string()),
+                        ("channel", channel_id.to_string()),
+                        ("mask", nick.clone().unwrap_or_default()),
+                        ("reason", "left".into()),
+                    ],
+                );
                 if let Some(channel) = self.channels.get_mut(&channel_id) {
                     info!("{addr} has left");
 
@@ -615,6 +1821,7 @@ impl ServerState {
 
                     channel.remove_remote(&addr);
                 } // if this is false, the remote is channel-less which i don't know how that would even happen
+                self.socket.forget_peer(addr);
                 return false;
             }
             true
@@ -647,6 +1854,19 @@ impl ServerState {
                 return;
             }
 
+            if !self
+                .plugin_manager
+                .dispatch_mask(addr, old_mask.as_deref(), &new_mask, channel_id)
+            {
+                info!("Plugins prevented {addr} from masking as '{new_mask}'");
+                Self::notice(
+                    &self.socket,
+                    addr,
+                    LocalizedMessage::new(MessageCode::MaskRejected, vec![new_mask]),
+                );
+                return;
+            }
+
             remote.lock().unwrap().mask = Some(new_mask.clone());
 
             (old_mask, new_mask, channel_id)
@@ -660,7 +1880,7 @@ impl ServerState {
         self.broadcast_join_masked(channel_id, new_mask, old_mask);
     }
 
-    fn handle_list(&self, addr: SocketAddr) {
+    fn handle_list(&self, addr: SocketAddr, data: &[u8]) {
         let Some(remote) = self.remotes.get(&addr) else {
             warn!(
                 "List request from unknown remote: {}, skipping request...",
@@ -675,62 +1895,58 @@ impl ServerState {
             remote.channel_id
         };
 
-        let mut channels_info = Vec::new();
-
-        for (&chan_id, chan) in &self.channels {
-            // if chan.remotes.is_empty() {
-            //     continue;
-            // }
+        // a bare request is from an older client and gets the whole list at once
+        let page_request = (data.len() >= 7).then(|| {
+            (
+                u32::from_be_bytes([data[0], data[1], data[2], data[3]]),
+                u16::from_be_bytes([data[4], data[5]]),
+                data[6],
+            )
+        });
+        let filter = page_request.map_or(0, |(_, _, filter)| filter);
 
-            let (masked_users, unmasked_count): (Vec<(String, bool, bool)>, u32) = chan
-                .remotes
-                .iter()
-                .map(|r| {
-                    let r = r.lock().unwrap();
-                    (r.mask.clone(), r.status.mute, r.status.deaf)
-                })
-                .fold(
-                    (vec![], 0),
-                    |(mut masks, count), (mask_opt, muted, deafened)| {
-                        if let Some(mask) = mask_opt {
-                            masks.push((mask, muted, deafened));
-                            (masks, count)
-                        } else {
-                            (masks, count + 1)
-                        }
-                    },
-                );
+        let mut channels = Vec::new();
 
-            let mut channel_info = Vec::new();
+        for (&chan_id, chan) in &self.channels {
+            let mut masked_users = Vec::new();
+            let mut unmasked_count = 0;
 
-            if let Some(name) = &chan.name {
-                channel_info.push(name.len() as u8);
-                channel_info.extend_from_slice(name.as_bytes());
-            } else {
-                channel_info.extend_from_slice(&[0x0]);
-            }
+            for r in &chan.remotes {
+                let r = r.lock().unwrap();
 
-            channel_info.extend_from_slice(&chan_id.to_be_bytes());
-            channel_info.extend_from_slice(&unmasked_count.to_be_bytes());
-            channel_info.extend_from_slice(&(masked_users.len() as u32).to_be_bytes());
+                if filter & protocol::LIST_FILTER_SPEAKING != 0 && !chan.is_speaking(&r.addr) {
+                    continue;
+                }
+                if filter & protocol::LIST_FILTER_ADMINS != 0 && r.role != Role::Admin {
+                    continue;
+                }
 
-            for (mask, muted, deafened) in &masked_users {
-                channel_info.extend_from_slice(mask.as_bytes());
-                channel_info.push(0x01);
-                let flags = (*muted as u8) | ((*deafened as u8) << 1);
-                channel_info.push(flags);
+                match &r.mask {
+                    Some(mask) => {
+                        masked_users.push((mask.clone(), r.status.is_muted(), r.status.is_deaf()))
+                    }
+                    None => unmasked_count += 1,
+                }
             }
 
-            channels_info.push(channel_info);
+            channels.push(ChannelInfo {
+                name: chan.name.clone().unwrap_or_default(),
+                channel_id: chan_id,
+                unmasked_count,
+                masked_users,
+            });
         }
 
-        let mut list_packet = vec![0x05];
-        list_packet.extend_from_slice(&remote_chan_id.to_be_bytes());
-        list_packet.extend_from_slice(&(channels_info.len() as u32).to_be_bytes());
-
-        for chan_info in channels_info {
-            list_packet.extend_from_slice(&chan_info);
-        }
+        let list_packet = match page_request {
+            Some((offset, limit, _)) => {
+                ListPagePacket::paginate(remote_chan_id, &channels, offset, limit).serialize()
+            }
+            None => GlobalListPacket {
+                channels,
+                current: remote_chan_id,
+            }
+            .serialize(),
+        };
 
         if let Err(e) = self.socket.send_to(&list_packet, addr) {
             // i can get away with sending list unreliably
@@ -739,6 +1955,12 @@ impl ServerState {
     }
 
     fn handle_chat(&mut self, addr: SocketAddr, data: &[u8]) {
+        // typed into the chat box, answered like any other command
+        if data.first() == Some(&b'/') {
+            self.handle_cmd(addr, data);
+            return;
+        }
+
         let (mask, chan_id) = {
             let Some(remote) = self.remotes.get(&addr) else {
                 warn!(
@@ -768,47 +1990,133 @@ impl ServerState {
                 };
 
                 if util::is_whitespace_only(&msg) {
-                    Self::dm(&self.socket, addr, "Don't send an empty message".into());
+                    Self::notice(
+                        &self.socket,
+                        addr,
+                        LocalizedMessage::new(MessageCode::EmptyMessage, vec![]),
+                    );
                     return;
                 }
 
                 let sender_addr = addr;
                 if self
                     .plugin_manager
-                    .dispatch_message(mask.as_str(), msg.as_str())
+                    .dispatch_message(mask.as_str(), chan_id, msg.as_str())
                     .not()
                 {
                     info!("Plugins have prevented {mask} from sending '{msg}'");
                     return;
                 }
 
+                let mut chat = ChatPacket {
+                    username: mask.clone(),
+                    message: msg.clone(),
+                    is_self: false,
+                };
                 for remote in channel.remotes.iter() {
                     let addr = { remote.lock().unwrap().addr };
-                    let is_self = addr.eq(&sender_addr);
-
-                    let mut msg_packet = vec![ClientPacketType::Chat as u8];
-                    msg_packet.extend_from_slice(mask.as_bytes());
-                    msg_packet.push(0x01);
-                    msg_packet.push(is_self as u8);
-                    msg_packet.extend_from_slice(data);
-
-                    let _ = self.socket.send_reliable(msg_packet, addr);
+                    chat.is_self = addr.eq(&sender_addr);
+                    let _ = self.socket.send_reliable(chat.serialize(), addr);
                 }
 
                 info!("[#chan-{}] <{}> {}", chan_id, mask, msg);
+                Self::push_event(
+                    &self.consoles,
+                    &self.socket,
+                    ConsoleEvent::Chat,
+                    &[
+                        ("channel", chan_id.to_string()),
+                        ("mask", mask.clone()),
+                        ("text", msg.clone()),
+                    ],
+                );
 
                 if msg.eq("i want to be kicked") {
-                    self.kick_socket(addr, Some("We have successfully met your desires".into()));
+                    self.kick_socket(
+                        addr,
+                        Some(LocalizedMessage::new(MessageCode::KickRequested, vec![])),
+                    );
                 }
             }
             None => {
-                let unauth_packet = vec![0x07];
+                let unauth_packet = vec![ClientPacketType::NickRequired as u8];
                 let _ = self.socket.send_reliable(unauth_packet, addr);
                 warn!("{addr} tried sending chat message without having a mask!");
             }
         }
     }
 
+    fn handle_dm(&mut self, addr: SocketAddr, data: &[u8]) {
+        let Some(remote) = self.remotes.get(&addr) else {
+            warn!("DM from unknown remote: {addr}, skipping request...");
+            return;
+        };
+        let Some(from) = remote.lock().unwrap().mask.clone() else {
+            let unauth_packet = vec![ClientPacketType::NickRequired as u8];
+            let _ = self.socket.send_reliable(unauth_packet, addr);
+            warn!("{addr} tried sending a DM without having a mask!");
+            return;
+        };
+
+        let Some((&to_len, rest)) = data.split_first() else {
+            self.handle_bad(addr);
+            return;
+        };
+        let to_len = to_len as usize;
+        if rest.len() < to_len {
+            self.handle_bad(addr);
+            return;
+        }
+        let (Ok(to), Ok(message)) = (
+            std::str::from_utf8(&rest[..to_len]),
+            std::str::from_utf8(&rest[to_len..]),
+        ) else {
+            warn!("{addr} sent a non UTF-8 encoded DM");
+            return;
+        };
+
+        if util::is_whitespace_only(message) {
+            Self::notice(
+                &self.socket,
+                addr,
+                LocalizedMessage::new(MessageCode::EmptyMessage, vec![]),
+            );
+            return;
+        }
+
+        let Some(target) = Self::find_by_mask(&self.channels, to) else {
+            Self::notice(
+                &self.socket,
+                addr,
+                LocalizedMessage::new(MessageCode::UserNotOnline, vec![to.to_string()]),
+            );
+            return;
+        };
+
+        Self::send_whisper(&self.socket, target, &from, message);
+    }
+
+    // whispers stay between the two, only the fact one was sent is logged
+    fn send_whisper(socket: &SecureUdpSocket, target: SocketAddr, from: &str, message: &str) {
+        let _ = socket.send_reliable(protocol::create_whisper_packet(from, message), target);
+        info!("whisper sent");
+    }
+
+    // address of whoever is using `mask`, in any channel
+    fn find_by_mask(channels: &HashMap<u32, Channel>, mask: &str) -> Option<SocketAddr> {
+        channels
+            .values()
+            .flat_map(|chan| chan.remotes.iter())
+            .find_map(|remote| {
+                let remote = remote.lock().unwrap();
+                remote
+                    .mask
+                    .as_ref()
+                    .is_some_and(|m| m == mask)
+                    .then_some(remote.addr)
+            })
+    }
+
     pub fn handle_ctrl(&mut self, addr: SocketAddr, data: &[u8]) {
         let Some(remote) = self.remotes.get(&addr) else {
             warn!(
@@ -826,7 +2134,50 @@ impl ServerState {
                 Cq::SetUndeafen => remote.status.deaf = false,
                 Cq::SetMute => remote.status.mute = true,
                 Cq::SetUnmute => remote.status.mute = false,
-                // Cq::SetVolume(_) => warn!("{addr} accessed an unimplemented feature"),
+                Cq::SetPanning => remote.status.panning = true,
+                Cq::SetNoPanning => remote.status.panning = false,
+                Cq::SetTalkerGain => {
+                    let channel_id = remote.channel_id;
+                    drop(remote); // finding the talker locks every remote in the channel
+
+                    let (mask, gain) = req.talker_gain.unwrap_or_default();
+                    let Some(channel) = self.channels.get_mut(&channel_id) else {
+                        return;
+                    };
+
+                    match channel.find_remote(&mask) {
+                        Some(talker) => channel.set_listener_gain(
+                            addr,
+                            talker,
+                            gain.clamp(0.0, protocol::MAX_TALKER_GAIN),
+                        ),
+                        None => warn!("{addr} set the gain of {mask}, who is not in their channel"),
+                    }
+                }
+                Cq::SetEffects => {
+                    let channel_id = remote.channel_id;
+                    drop(remote);
+
+                    if let Some(channel) = self.channels.get_mut(&channel_id) {
+                        channel.set_effects(addr, &req.effects);
+                    }
+                }
+                Cq::Subscribe => {
+                    let id = req.channel_id.unwrap_or_default();
+                    if id == remote.channel_id || remote.subscriptions.contains(&id) {
+                        return;
+                    }
+                    if remote.subscriptions.len() >= protocol::MAX_SUBSCRIPTIONS {
+                        warn!("{addr} is already listening to as many channels as allowed");
+                        return;
+                    }
+                    info!("{addr} is now listening to channel {id}");
+                    remote.subscriptions.push(id);
+                }
+                Cq::Unsubscribe => {
+                    let id = req.channel_id.unwrap_or_default();
+                    remote.subscriptions.retain(|&sub| sub != id);
+                }
             },
             Err(e) => {
                 warn!("{addr} sent a bad control packet: {e}");
@@ -843,25 +2194,33 @@ impl ServerState {
             }
         };
 
-        let (mask, channel_id, is_admin) = {
+        let (mask, channel_id, role) = {
             let Some(remote) = self.remotes.get(&addr) else {
                 warn!("Command from unknown remote: {}", addr);
                 return;
             };
 
             let remote = remote.lock().unwrap();
-            (remote.mask.clone(), remote.channel_id, false)
+            (remote.mask.clone(), remote.channel_id, remote.role)
         };
 
         // execute command
-        let result = self.execute_command(&input, addr, mask.as_deref(), channel_id, is_admin);
+        let result = self.execute_command(&input, addr, mask.as_deref(), channel_id, role);
 
-        let packet = result.serialize();
-        let _ = self.socket.send_to(&packet, addr);
+        let typed = input.split_whitespace().next().unwrap_or_default();
+        let command = match self.command_system.get_command(typed) {
+            Some((command, _)) => command.name.clone(),
+            None => typed.to_string(),
+        };
+        let packet = CommandResponsePacket { command, result }.serialize();
+        let _ = self.socket.send_reliable(packet, addr);
     }
 
     pub fn handle_sync_commands(&mut self, addr: SocketAddr) {
-        let is_admin = false;
+        let is_admin = self
+            .remotes
+            .get(&addr)
+            .is_some_and(|remote| remote.lock().unwrap().role == Role::Admin);
         let available_commands = self.command_system.get_commands_for_user(is_admin);
 
         let mut packet = vec![0x0c];
@@ -911,6 +2270,22 @@ impl ServerState {
         }
     }
 
+    fn handle_probe(&mut self, addr: SocketAddr, data: &[u8]) {
+        // never send back more than we got, so probes can't amplify anything
+        if data.len() < 4 {
+            return;
+        }
+        let _ = self.socket.send_to(&data[..4], addr);
+    }
+
+    // no state is kept until they join, this only proves we're here and
+    // tells them the frame size we decode
+    fn handle_handshake(&mut self, addr: SocketAddr) {
+        let mut packet = vec![ClientPacketType::Handshake as u8];
+        packet.extend_from_slice(&(self.config.get_framesize() as u32).to_be_bytes());
+        let _ = self.socket.send_to(&packet, addr);
+    }
+
     pub fn handle_bad(&mut self, addr: SocketAddr) {
         warn!("{addr} sent a bad packet");
         // let _ = self.socket.send_bad_packet_notice(addr);
@@ -922,13 +2297,17 @@ impl ServerState {
         let _ = socket.send_reliable(packet, addr);
     }
 
+    fn notice(socket: &SecureUdpSocket, addr: SocketAddr, msg: LocalizedMessage) {
+        let _ = socket.send_reliable(msg.serialize(), addr);
+    }
+
     fn execute_command(
         &mut self,
         input: &str,
         sender_addr: SocketAddr,
         sender_mask: Option<&str>,
         channel_id: u32,
-        is_admin: bool,
+        role: Role,
     ) -> CommandResult {
         let (command, _, args) = match self.command_system.parse_command(input) {
             Some((cmd, fun, args)) => (cmd, fun, args),
@@ -939,32 +2318,317 @@ impl ServerState {
             }
         };
 
-        if command.requires_auth && sender_mask.is_none() {
-            return CommandResult::Error("You need to set a nickname first with /nick".to_string());
-        }
-
-        if command.admin_only && !is_admin {
-            return CommandResult::Error(
-                "You don't have permission to use this command.".to_string(),
-            );
-        }
-
         let context = CommandContext {
             sender_addr,
             sender_mask: sender_mask.map(|s| s.to_string()),
             channel_id,
             arguments: args,
-            is_admin,
+            role,
         };
+        if let Err(denied) = command.authorize(&context) {
+            return denied;
+        }
+        let checked = command.check_args(&context.arguments, |mask| {
+            Self::find_by_mask(&self.channels, mask).is_some()
+        });
+        if let Err(e) = checked {
+            return CommandResult::Error(e);
+        }
 
-        let cmd_name = &command.name;
-        if let Some((_, func)) = self.command_system.get_command(cmd_name) {
+        let cmd_name = command.name.clone();
+        if let Some(result) = self.plugin_manager.dispatch_command(&cmd_name, &context) {
+            result
+        } else if let Some(result) = self.run_server_command(&cmd_name, &context) {
+            result
+        } else if let Some((_, func)) = self.command_system.get_command(&cmd_name) {
             func(&context, &mut self.channels)
         } else {
             CommandResult::Silent
         }
     }
 
+    // the commands `CommandSystem` leaves to us, `None` for any other
+    fn run_server_command(&mut self, name: &str, ctx: &CommandContext) -> Option<CommandResult> {
+        Some(match name {
+            "/nick" => self.nick_command(ctx),
+            "/join" => self.join_command(ctx),
+            "/kick" => self.kick_command(ctx, false),
+            "/ban" => self.kick_command(ctx, true),
+            "/move" => self.move_command(ctx),
+            "/unban" => self.unban_command(ctx),
+            "/help" => self.help_command(ctx),
+            "/stats" => self.stats_command(),
+            _ => return None,
+        })
+    }
+
+    fn nick_command(&mut self, ctx: &CommandContext) -> CommandResult {
+        let nick = ctx.text(0);
+        self.handle_mask(ctx.sender_addr, nick.as_bytes());
+        let taken = self
+            .remotes
+            .get(&ctx.sender_addr)
+            .is_some_and(|remote| remote.lock().unwrap().mask.as_ref() == Some(&nick));
+        if taken {
+            CommandResult::Success(format!("you are now {nick}"))
+        } else {
+            // plugins turned it down, they got a notice saying so
+            CommandResult::Silent
+        }
+    }
+
+    fn join_command(&mut self, ctx: &CommandContext) -> CommandResult {
+        let Ok(chan_id) = ctx.arguments[0].parse::<u32>() else {
+            return CommandResult::Error(format!("{} isn't a valid channel id", ctx.arguments[0]));
+        };
+        if chan_id == ctx.channel_id {
+            return CommandResult::Error(format!("you are already in channel {chan_id}"));
+        }
+
+        // same as the client asking, the move notice and list follow
+        self.handle_join(ctx.sender_addr, &chan_id.to_be_bytes());
+        CommandResult::Silent
+    }
+
+    fn kick_command(&mut self, ctx: &CommandContext, ban: bool) -> CommandResult {
+        let user = &ctx.arguments[0];
+        let Some(addr) = Self::find_by_mask(&self.channels, user) else {
+            return CommandResult::Error(format!("{user} is not online"));
+        };
+
+        let by = ctx.sender_mask.clone().unwrap_or_default();
+        let reason = match ctx.text(1) {
+            reason if reason.is_empty() => "no reason given".to_string(),
+            reason => reason,
+        };
+        let (notice, description) = if ban {
+            self.bans.ban(addr.ip(), user, &reason);
+            (
+                LocalizedMessage::new(MessageCode::Banned, vec![reason.clone()]),
+                format!("banned {user} ({})", addr.ip()),
+            )
+        } else {
+            (
+                LocalizedMessage::new(MessageCode::Kicked, vec![by.clone(), reason.clone()]),
+                format!("kicked {user}"),
+            )
+        };
+
+        info!("{by} {description}: {reason}");
+        self.audit
+            .lock()
+            .unwrap()
+            .add_note(user, &by, &format!("{description}: {reason}"));
+        self.kick_socket(addr, Some(notice));
+        CommandResult::Success(description)
+    }
+
+    fn move_command(&mut self, ctx: &CommandContext) -> CommandResult {
+        let user = &ctx.arguments[0];
+        let Ok(chan_id) = ctx.arguments[1].parse::<u32>() else {
+            return CommandResult::Error(format!("{} isn't a valid channel id", ctx.arguments[1]));
+        };
+        let Some(addr) = Self::find_by_mask(&self.channels, user) else {
+            return CommandResult::Error(format!("{user} is not online"));
+        };
+        let channel_of = |server: &Self| {
+            server
+                .remotes
+                .get(&addr)
+                .map(|remote| remote.lock().unwrap().channel_id)
+        };
+        if channel_of(self) == Some(chan_id) {
+            return CommandResult::Error(format!("{user} is already in channel {chan_id}"));
+        }
+
+        // as if they had asked, so plugins get their say
+        self.handle_join(addr, &chan_id.to_be_bytes());
+        if channel_of(self) != Some(chan_id) {
+            return CommandResult::Error(format!("plugins kept {user} from channel {chan_id}"));
+        }
+
+        let by = ctx.sender_mask.clone().unwrap_or_default();
+        info!("{by} moved {user} to channel {chan_id}");
+        CommandResult::Success(format!("moved {user} to channel {chan_id}"))
+    }
+
+    fn unban_command(&mut self, ctx: &CommandContext) -> CommandResult {
+        let target = &ctx.arguments[0];
+        let ip = target.parse().ok().or_else(|| self.bans.find(target));
+
+        match ip {
+            Some(ip) if self.bans.unban(ip) => {
+                let by = ctx.sender_mask.clone().unwrap_or_default();
+                info!("{by} lifted the ban of {target}");
+                CommandResult::Success(format!("{target} is no longer banned"))
+            }
+            _ => CommandResult::Error(format!("{target} isn't banned")),
+        }
+    }
+
+    fn stats_command(&self) -> CommandResult {
+        let period = Duration::from_secs(1) / self.config.tickrate;
+        let ticks = self.tick_times.len().max(1) as u32;
+        let average = self.tick_times.iter().sum::<Duration>() / ticks;
+        let worst = self.tick_times.iter().max().copied().unwrap_or_default();
+        let headroom = 100.0 - average.as_secs_f64() / period.as_secs_f64() * 100.0;
+
+        let stats = self.socket.stats();
+        let kib = |bytes: u64| bytes as f64 / 1024.0;
+        CommandResult::Success(format!(
+            "tick: {:.2}ms avg, {:.2}ms worst of {}ms ({headroom:.0}% headroom)\n\
+             sent: {} packets, {:.1} KiB\n\
+             received: {} packets, {:.1} KiB\n\
+             reliable: {} resent, {} dropped, {} awaiting ack\n\
+             rejected: {} packets",
+            average.as_secs_f64() * 1000.0,
+            worst.as_secs_f64() * 1000.0,
+            period.as_millis(),
+            stats.packets_sent,
+            kib(stats.bytes_sent),
+            stats.packets_received,
+            kib(stats.bytes_received),
+            stats.retransmits,
+            stats.dropped,
+            stats.pending,
+            stats.rejected,
+        ))
+    }
+
+    fn help_command(&self, ctx: &CommandContext) -> CommandResult {
+        let mut commands = self.command_system.get_commands_for_user(ctx.is_admin());
+        commands.sort_by(|a, b| a.name.cmp(&b.name));
+
+        let Some(name) = ctx.arguments.first() else {
+            let lines = commands
+                .iter()
+                .map(|command| format!("{} - {}", command.usage, command.description))
+                .collect::<Vec<_>>();
+            return CommandResult::Success(lines.join("\n"));
+        };
+
+        let name = format!("/{}", name.trim_start_matches('/'));
+        let command = self
+            .command_system
+            .get_command(&name)
+            .map(|(command, _)| command)
+            .filter(|command| !command.admin_only || ctx.is_admin());
+        match command {
+            Some(command) if command.aliases.is_empty() => {
+                CommandResult::Success(format!("{} - {}", command.usage, command.description))
+            }
+            Some(command) => CommandResult::Success(format!(
+                "{} - {} (also {})",
+                command.usage,
+                command.description,
+                command.aliases.join(", ")
+            )),
+            None => CommandResult::Error(format!("no command named {name}")),
+        }
+    }
+
+    /// Sends operators the channel or remote they are monitoring, as heard last tick
+    fn send_monitor_mixes(&self) {
+        let monitors = self
+            .remotes
+            .values()
+            .filter_map(|remote| Some((remote, remote.lock().unwrap().monitoring?)))
+            .collect::<Vec<_>>();
+
+        for (operator, target) in monitors {
+            let (channel, mut frame) = match target {
+                MonitorTarget::Channel(id) => {
+                    // nobody talked, same as a regular listener getting nothing
+                    let Some(channel) = self
+                        .channels
+                        .get(&id)
+                        .filter(|channel| !channel.last_talkers.is_empty())
+                    else {
+                        continue;
+                    };
+                    let mut frame = channel.last_total.clone();
+                    mixer::scale(&mut frame, 1.0 / (channel.last_talkers.len() as f32).sqrt());
+                    (channel, frame)
+                }
+                MonitorTarget::Remote(addr) => {
+                    let Some(channel_id) = self
+                        .remotes
+                        .get(&addr)
+                        .map(|remote| remote.lock().unwrap().channel_id)
+                    else {
+                        continue; // they left, stay quiet until the operator moves on
+                    };
+                    let Some(channel) = self.channels.get(&channel_id) else {
+                        continue;
+                    };
+                    let Some(frame) = channel.last_talkers.get(&addr) else {
+                        continue;
+                    };
+                    (channel, frame.clone())
+                }
+            };
+
+            operator
+                .lock()
+                .unwrap()
+                .send_mix(&self.socket, &channel.server_config, &mut frame);
+        }
+    }
+
+    /// Sends listeners with subscriptions their channel's mix with every
+    /// subscribed channel's mix on top, turned down by `subscription_gain`
+    fn send_subscriber_mixes(&mut self) {
+        let subscribers = self
+            .remotes
+            .iter()
+            .filter_map(|(addr, remote)| {
+                let guard = remote.lock().unwrap();
+                (!guard.subscriptions.is_empty()
+                    && !guard.status.is_deaf()
+                    && guard.monitoring.is_none())
+                .then(|| {
+                    (
+                        *addr,
+                        remote.clone(),
+                        guard.channel_id,
+                        guard.subscriptions.clone(),
+                    )
+                })
+            })
+            .collect::<Vec<_>>();
+
+        for (addr, remote, channel_id, subscriptions) in subscribers {
+            let Some(channel) = self.channels.get_mut(&channel_id) else {
+                continue;
+            };
+            let config = channel.server_config;
+            let mut mix = channel.held_mixes.remove(&addr);
+
+            for id in subscriptions {
+                // nobody talked there, nothing to add
+                let Some(channel) = self
+                    .channels
+                    .get(&id)
+                    .filter(|channel| !channel.last_talkers.is_empty())
+                else {
+                    continue;
+                };
+
+                let gain =
+                    self.config.subscription_gain / (channel.last_talkers.len() as f32).sqrt();
+                let mix = mix.get_or_insert_with(|| vec![0.0; channel.last_total.len()]);
+                mixer::accumulate(mix, &channel.last_total, gain);
+            }
+
+            if let Some(mut mix) = mix {
+                remote
+                    .lock()
+                    .unwrap()
+                    .send_mix(&self.socket, &config, &mut mix);
+            }
+        }
+    }
+
     fn process_audio_tick(&mut self) {
         let framesize = self.config.get_framesize();
         // decode incoming packets and fill jitter buffers
@@ -975,44 +2639,107 @@ impl ServerState {
             let mut remote = remote.lock().unwrap();
 
             let mut pcm = vec![0.0f32; framesize * 2];
-            match remote.decoder.decode_float(&data, &mut pcm, false) {
+            let buffered = match remote.decoder.decode_float(&data, &mut pcm, false) {
                 Ok(len) if len == framesize => {
                     if remote.jitter_buffer.len() < JITTER_BUFFER_LEN {
                         remote.jitter_buffer.push_back(pcm);
+                        true
                     } else {
                         warn!("Jitter buffer full for {addr}");
+                        false
                     }
                 }
-                Ok(len) => error!("Bad frame size from {addr}: got {len}, expected {framesize}"),
-                Err(e) => error!("Decode error from {addr}: {e:?}"),
-            }
+                Ok(len) => {
+                    error!("Bad frame size from {addr}: got {len}, expected {framesize}");
+                    Self::push_event(
+                        &self.consoles,
+                        &self.socket,
+                        ConsoleEvent::AudioErrors,
+                        &[
+                            ("addr", addr.to_string()),
+                            (
+                                "text",
+                                format!("bad frame size {len}, expected {framesize}"),
+                            ),
+                        ],
+                    );
+                    false
+                }
+                Err(e) => {
+                    error!("Decode error from {addr}: {e:?}");
+                    Self::push_event(
+                        &self.consoles,
+                        &self.socket,
+                        ConsoleEvent::AudioErrors,
+                        &[
+                            ("addr", addr.to_string()),
+                            ("text", format!("decode error {e:?}")),
+                        ],
+                    );
+                    false
+                }
+            };
+            remote.audio_stats.record(data.len(), !buffered);
         }
 
         // Pull one frame per remote into channel buffer
         for (addr, remote) in &self.remotes {
             let mut remote = remote.lock().unwrap();
             let chan_id = remote.channel_id;
-            let frame =
-                remote
-                    .jitter_buffer
-                    .pop_front()
-                    .unwrap_or(vec![0.0; self.config.get_framesize() * 2]);
+            let frame = match remote.jitter_buffer.pop_front() {
+                // plugins only hear the frames somebody actually sent
+                Some(mut frame) => {
+                    if let Some(mask) = &remote.mask
+                        && !self.plugin_manager.dispatch_audio(mask, &mut frame)
+                    {
+                        frame.fill(0.0);
+                    }
+                    frame
+                }
+                None => vec![0.0; self.config.get_framesize() * 2],
+            };
 
             if let Some(channel) = self.channels.get_mut(&chan_id) {
                 channel.buffers.insert(*addr, frame);
             }
         }
 
+        let mut talkers = 0;
         for channel in self.channels.values_mut() {
-            channel.mix(&self.socket);
+            talkers += channel.mix(&self.socket);
+        }
+        self.send_monitor_mixes();
+        self.send_subscriber_mixes();
+
+        // who was talking only matters to telemetry
+        #[cfg(feature = "telemetry")]
+        if let Some(telemetry) = &mut self.telemetry {
+            telemetry.record_tick(self.remotes.len(), talkers);
         }
+        #[cfg(not(feature = "telemetry"))]
+        let _ = talkers;
     }
 
     fn broadcast_join(&mut self, channel_id: u32, mask: String) {
         self.broadcast_join_masked(channel_id, mask, None);
     }
 
-    fn kick_socket(&mut self, addr: SocketAddr, reason: Option<String>) {
+    fn broadcast_leave(&self, channel_id: u32, mask: &str) {
+        let Some(channel) = self.channels.get(&channel_id) else {
+            return;
+        };
+
+        let mut packet = vec![ClientPacketType::FlowLeave as u8];
+        packet.extend_from_slice(mask.as_bytes());
+        for peer in &channel.remotes {
+            let peer_addr = { peer.lock().unwrap().addr };
+            if let Err(e) = self.socket.send_reliable(packet.clone(), peer_addr) {
+                warn!("Failed to send leave packet to {}: {:?}", peer_addr, e);
+            }
+        }
+    }
+
+    fn kick_socket(&mut self, addr: SocketAddr, reason: Option<LocalizedMessage>) {
         if !self.remotes.contains_key(&addr) {
             info!(
                 "{} is not a registered client to kick, sending request anyway...",
@@ -1022,9 +2749,32 @@ impl ServerState {
             info!("Kicked {addr}");
         }
 
+        let mask = self
+            .remotes
+            .get(&addr)
+            .and_then(|remote| remote.lock().unwrap().mask.clone());
+        Self::push_event(
+            &self.consoles,
+            &self.socket,
+            ConsoleEvent::Kicks,
+            &[
+                ("addr", addr.to_string()),
+                ("mask", mask.unwrap_or_default()),
+                (
+                    "text",
+                    reason
+                        .as_ref()
+                        .map_or_else(String::new, |reason| reason.fallback.clone()),
+                ),
+            ],
+        );
+
+        // the kick packet keeps carrying plain text for older clients, the coded
+        // reason goes out as a notice right before it
         let mut packet = vec![ClientPacketType::Kick as u8];
         if let Some(reason) = reason {
-            packet.extend_from_slice(reason.as_bytes());
+            packet.extend_from_slice(reason.fallback.as_bytes());
+            Self::notice(&self.socket, addr, reason);
         }
         let _ = self.socket.send_reliable(packet, addr);
 
@@ -1102,13 +2852,16 @@ impl ServerState {
     fn cleanup(&mut self) {
         let now = Instant::now();
 
-        self.consoles.retain(|addr, guard| {
+        self.console_challenges
+            .retain(|_, challenge| challenge.sent.elapsed() < CONSOLE_CHALLENGE_TIMEOUT);
+
+        self.consoles.retain(|id, guard| {
             let console = guard.lock().unwrap();
 
-            if console.last_active.duration_since(now)
+            if now.duration_since(console.last_active)
                 > Duration::from_secs(self.config.timeout_secs)
             {
-                info!("Dropped console {addr} due to timeout");
+                info!("Dropped console #{id} ({}) due to timeout", console.link);
                 false
             } else {
                 true
@@ -1121,6 +2874,17 @@ impl ServerState {
             let channel_id = { remote.lock().unwrap().channel_id };
 
             if now.duration_since(last_active) > Duration::from_secs(self.config.timeout_secs) {
+                Self::push_event(
+                    &self.consoles,
+                    &self.socket,
+                    ConsoleEvent::Leaves,
+                    &[
+                        ("addr", addr.to_string()),
+                        ("channel", channel_id.to_string()),
+                        ("mask", nick.clone().unwrap_or_default()),
+                        ("reason", "timeout".into()),
+                    ],
+                );
                 if let Some(channel) = self.channels.get_mut(&channel_id) {
                     info!(
                         "{addr} is dropped due to timeout of {} seconds",
@@ -1142,6 +2906,7 @@ impl ServerState {
                     }
                     channel.remove_remote(addr);
                 } // if this is false, the remote is channel-less which i don't know how that would even happen
+                self.socket.forget_peer(*addr);
                 false // remote hasn't updated in the past N seconds, needs to be kicked
             } else {
                 true // remote can stay alive
@@ -1149,6 +2914,20 @@ impl ServerState {
         });
     }
 
+    /// Mutes or deafens `user` from the server side and tells them
+    fn force_status(&self, user: &str, set: impl FnOnce(&mut RemoteStatus), code: MessageCode) {
+        let Some(addr) = Self::find_by_mask(&self.channels, user) else {
+            warn!("Plugins tried to change {user}'s status, who is not connected");
+            return;
+        };
+
+        if let Some(remote) = self.remotes.get(&addr) {
+            set(&mut remote.lock().unwrap().status);
+            info!("Plugins changed {user}'s status: {code:?}");
+            Self::notice(&self.socket, addr, LocalizedMessage::new(code, vec![]));
+        }
+    }
+
     fn plugins_update(&mut self) {
         while let Ok(action) = self.plugin_rx.try_recv() {
             match action {
@@ -1164,8 +2943,63 @@ impl ServerState {
                 PluginAction::ReplyByAddr { to, msg } => {
                     Self::dm(&self.socket, to, msg);
                 }
-                PluginAction::Broadcast { msg: _ } => {
-                    todo!()
+                PluginAction::Broadcast {
+                    from,
+                    channel_id,
+                    msg,
+                } => {
+                    let addrs = match channel_id {
+                        Some(id) => self.channels.get(&id).map_or_else(Vec::new, |channel| {
+                            channel
+                                .remotes
+                                .iter()
+                                .map(|remote| remote.lock().unwrap().addr)
+                                .collect()
+                        }),
+                        None => self.remotes.keys().copied().collect(),
+                    };
+
+                    let packet = ChatPacket {
+                        username: from.clone(),
+                        message: msg.clone(),
+                        is_self: false,
+                    }
+                    .serialize();
+                    for addr in addrs {
+                        let _ = self.socket.send_reliable(packet.clone(), addr);
+                    }
+
+                    match channel_id {
+                        Some(id) => info!("[#chan-{id}] <{from}> {msg}"),
+                        None => info!("[everyone] <{from}> {msg}"),
+                    }
+                }
+                PluginAction::Move { user, channel } => {
+                    match Self::find_by_mask(&self.channels, &user) {
+                        Some(addr) => {
+                            info!("Plugins moved {user} to channel {channel}");
+                            self.move_remote(addr, channel);
+                        }
+                        None => warn!("Plugins tried to move {user}, who is not connected"),
+                    }
+                }
+                PluginAction::SetMute { user, muted } => {
+                    self.force_status(&user, |status| status.forced_mute = muted, {
+                        if muted {
+                            MessageCode::ServerMuted
+                        } else {
+                            MessageCode::ServerUnmuted
+                        }
+                    });
+                }
+                PluginAction::SetDeafen { user, deafened } => {
+                    self.force_status(&user, |status| status.forced_deaf = deafened, {
+                        if deafened {
+                            MessageCode::ServerDeafened
+                        } else {
+                            MessageCode::ServerUndeafened
+                        }
+                    });
                 }
                 PluginAction::Kick { user, reason } => {
                     if let Some((addr, _)) = self
@@ -1173,7 +3007,12 @@ impl ServerState {
                         .iter()
                         .find(|r| r.1.lock().unwrap().mask.clone().is_some_and(|m| m == user))
                     {
-                        self.kick_socket(*addr, reason);
+                        self.kick_socket(
+                            *addr,
+                            reason.map(|r| {
+                                LocalizedMessage::new(MessageCode::KickedByPlugin, vec![r])
+                            }),
+                        );
                     }
                 }
             }
@@ -1184,6 +3023,10 @@ impl ServerState {
         let mut buf = [0u8; 2048];
         let mut next_tick = Instant::now();
 
+        self.plugin_manager.load_dir(&self.plugin_dir);
+        self.plugin_manager.log_loaded();
+        self.sync_plugin_commands();
+
         let throttle = self.config.throttle_millis;
         let tick_period = 1000 / self.config.tickrate as u64; // in ms
         info!(
@@ -1242,11 +3085,23 @@ impl ServerState {
             }
 
             self.plugins_update();
+            #[cfg(feature = "tls-console")]
+            self.tls_consoles_update();
+            self.socket.tick_reliable();
 
             if Instant::now() >= next_tick {
                 self.config.current_tick += 1;
+                let mixing = Instant::now();
                 self.process_audio_tick();
+                if self.tick_times.len() >= self.config.tickrate as usize {
+                    self.tick_times.pop_front();
+                }
+                self.tick_times.push_back(mixing.elapsed());
                 self.cleanup();
+                if !self.plugin_manager.is_empty() {
+                    self.plugin_manager.set_directory(self.directory());
+                    self.plugin_manager.run_timers();
+                }
                 next_tick += Duration::from_millis(tick_period);
             }
 
@@ -1254,3 +3109,8 @@ impl ServerState {
         }
     }
 }
+
+// compares secrets without bailing out at the first difference
+fn same_bytes(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (x, y)| diff | (x ^ y)) == 0
+}
@@ -6,7 +6,7 @@ use chacha20poly1305::{
 use pbkdf2::pbkdf2_hmac;
 use sha2::Sha256;
 use std::{
-    collections::HashMap,
+    collections::{HashMap, VecDeque},
     sync::{
         Arc, Mutex,
         atomic::{AtomicU32, AtomicU64},
@@ -21,6 +21,9 @@ use std::{
 
 use crate::protocol::{ACK_FLAG, ClientPacketType, RELIABLE_FLAG};
 
+// how many reliable sequence numbers per peer are remembered to drop retransmits
+const SEEN_WINDOW: usize = 64;
+
 pub fn derive_key_from_phrase(phrase: &[u8], salt: &[u8]) -> Key {
     let iters = 600_000u32;
     let mut key_b = [0u8; 32];
@@ -29,6 +32,34 @@ pub fn derive_key_from_phrase(phrase: &[u8], salt: &[u8]) -> Key {
     Key::from_slice(&key_b).to_owned()
 }
 
+/// What went through the socket since it was created
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct TransportStats {
+    pub packets_sent: u64,
+    pub bytes_sent: u64,
+    pub packets_received: u64,
+    pub bytes_received: u64,
+    /// Reliable packets sent again for want of an ack
+    pub retransmits: u64,
+    /// Reliable packets given up on after the last retry
+    pub dropped: u64,
+    /// Packets that were too small or didn't decrypt
+    pub rejected: u64,
+    /// Reliable packets still waiting on an ack
+    pub pending: usize,
+}
+
+#[derive(Default)]
+struct Counters {
+    packets_sent: AtomicU64,
+    bytes_sent: AtomicU64,
+    packets_received: AtomicU64,
+    bytes_received: AtomicU64,
+    retransmits: AtomicU64,
+    dropped: AtomicU64,
+    rejected: AtomicU64,
+}
+
 struct PendingPacket {
     data: Vec<u8>,
     addr: SocketAddr,
@@ -41,9 +72,13 @@ struct InnerSocket {
     cipher: ChaCha20Poly1305,
     seq_counter: AtomicU32,
     pending: Mutex<HashMap<u32, PendingPacket>>,
+    // reliable seqs recently delivered per peer, a retransmit whose ack got lost
+    // is acked again but not handed up twice
+    seen: Mutex<HashMap<SocketAddr, VecDeque<u32>>>,
     nonce_counter: AtomicU64,
     nonce_prefix: [u8; 4],
     connected_addr: Mutex<Option<SocketAddr>>,
+    counters: Counters,
 }
 
 #[derive(Clone)]
@@ -64,11 +99,14 @@ impl SecureUdpSocket {
             inner: Arc::new(InnerSocket {
                 socket,
                 cipher,
-                seq_counter: AtomicU32::new(1),
+                // random start so a peer reusing an address doesn't look like a retransmit
+                seq_counter: AtomicU32::new(OsRng.next_u32()),
                 pending: Mutex::new(HashMap::new()),
+                seen: Mutex::new(HashMap::new()),
                 nonce_counter: AtomicU64::new(0),
                 nonce_prefix,
                 connected_addr: Mutex::new(None),
+                counters: Counters::default(),
             }),
         })
     }
@@ -127,7 +165,13 @@ impl SecureUdpSocket {
         packet.extend_from_slice(&nonce_bytes);
         packet.extend_from_slice(&ciphertext);
 
-        self.inner.socket.send_to(&packet, addr)
+        let sent = self.inner.socket.send_to(&packet, addr)?;
+        let counters = &self.inner.counters;
+        counters.packets_sent.fetch_add(1, Ordering::Relaxed);
+        counters
+            .bytes_sent
+            .fetch_add(sent as u64, Ordering::Relaxed);
+        Ok(sent)
     }
 
     pub fn send_reliable(&self, payload: Vec<u8>, addr: SocketAddr) -> io::Result<()> {
@@ -168,8 +212,14 @@ impl SecureUdpSocket {
             Ok(ok) => ok,
             Err(e) => return Err((e, SocketAddr::from(([0, 0, 0, 0], 0)))),
         };
+        let counters = &self.inner.counters;
+        counters.packets_received.fetch_add(1, Ordering::Relaxed);
+        counters
+            .bytes_received
+            .fetch_add(size as u64, Ordering::Relaxed);
 
         if size < 12 {
+            counters.rejected.fetch_add(1, Ordering::Relaxed);
             return Err((
                 io::Error::new(io::ErrorKind::InvalidData, "packet too small"),
                 addr,
@@ -182,6 +232,7 @@ impl SecureUdpSocket {
         let plaintext = match self.inner.cipher.decrypt(nonce, ciphertext) {
             Ok(pt) => pt,
             Err(_) => {
+                counters.rejected.fetch_add(1, Ordering::Relaxed);
                 return Err((
                     io::Error::new(io::ErrorKind::InvalidData, "decryption failure"),
                     addr,
@@ -201,6 +252,18 @@ impl SecureUdpSocket {
             let seq = u32::from_be_bytes(plaintext[1..5].try_into().unwrap());
             let _ = self.send_ack(seq, addr);
 
+            {
+                let mut seen = self.inner.seen.lock().unwrap();
+                let seen = seen.entry(addr).or_default();
+                if seen.contains(&seq) {
+                    return Ok((0, addr));
+                }
+                if seen.len() == SEEN_WINDOW {
+                    seen.pop_front();
+                }
+                seen.push_back(seq);
+            }
+
             let inner = &plaintext[5..];
             if inner.len() > buf.len() {
                 return Err((
@@ -223,6 +286,8 @@ impl SecureUdpSocket {
         Ok((plaintext.len(), addr))
     }
 
+    /// Resends reliable packets that haven't been acked yet. Has to be called
+    /// regularly by whoever owns the socket.
     pub fn tick_reliable(&self) {
         let mut pending = self.inner.pending.lock().unwrap();
         let now = Instant::now();
@@ -231,16 +296,41 @@ impl SecureUdpSocket {
 
         pending.retain(|_, pkt| {
             if pkt.retries >= max_retries {
+                self.inner.counters.dropped.fetch_add(1, Ordering::Relaxed);
                 return false; // give up
             }
 
             if now.duration_since(pkt.last_sent) >= timeout {
-                let _ = self.inner.socket.send_to(&pkt.data, pkt.addr);
+                // goes through send_to so the retransmit is encrypted under a fresh nonce
+                let _ = self.send_to(&pkt.data, pkt.addr);
                 pkt.last_sent = now;
                 pkt.retries += 1;
+                self.inner
+                    .counters
+                    .retransmits
+                    .fetch_add(1, Ordering::Relaxed);
             }
 
             true
         });
     }
+
+    pub fn stats(&self) -> TransportStats {
+        let counters = &self.inner.counters;
+        TransportStats {
+            packets_sent: counters.packets_sent.load(Ordering::Relaxed),
+            bytes_sent: counters.bytes_sent.load(Ordering::Relaxed),
+            packets_received: counters.packets_received.load(Ordering::Relaxed),
+            bytes_received: counters.bytes_received.load(Ordering::Relaxed),
+            retransmits: counters.retransmits.load(Ordering::Relaxed),
+            dropped: counters.dropped.load(Ordering::Relaxed),
+            rejected: counters.rejected.load(Ordering::Relaxed),
+            pending: self.inner.pending.lock().unwrap().len(),
+        }
+    }
+
+    /// Drops what the socket remembers about a peer that went away
+    pub fn forget_peer(&self, addr: SocketAddr) {
+        self.inner.seen.lock().unwrap().remove(&addr);
+    }
 }
@@ -0,0 +1,180 @@
+// Soundboard clips, decoded up front and fed into what we send a frame at a
+// time. A clip is mixed with the mic or takes its place while it plays.
+use std::{
+    fs::{self, File},
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
+
+use anyhow::{Context, Result, bail};
+use symphonia::{
+    core::{audio::SampleBuffer, codecs::CODEC_TYPE_NULL, io::MediaSourceStream, probe::Hint},
+    default::{get_codecs, get_probe},
+};
+
+use crate::resample::Resampler;
+
+pub const DEFAULT_CLIPS_DIR: &str = "sounds";
+
+const SAMPLE_RATE: u32 = 48000;
+// longer than this isn't a clip anymore, the music bot is for that
+pub const MAX_CLIP_LENGTH: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ClipMode {
+    // on top of our voice
+    #[default]
+    Mix,
+    // the mic goes quiet until the clip is over
+    Replace,
+}
+
+/// Interleaved stereo audio at 48kHz, cheap to clone
+#[derive(Debug, Clone)]
+pub struct Clip {
+    name: String,
+    samples: Arc<[f32]>,
+}
+
+impl Clip {
+    /// Decodes anything symphonia knows, at any rate, mono or stereo
+    pub fn load(path: &Path) -> Result<Self> {
+        let file = File::open(path).with_context(|| format!("couldn't open {}", path.display()))?;
+        let mss = MediaSourceStream::new(Box::new(file), Default::default());
+
+        let mut hint = Hint::new();
+        if let Some(ext) = path.extension().and_then(|ext| ext.to_str()) {
+            hint.with_extension(ext);
+        }
+
+        let probed = get_probe().format(&hint, mss, &Default::default(), &Default::default())?;
+        let mut format = probed.format;
+        let track = format
+            .tracks()
+            .iter()
+            .find(|t| t.codec_params.codec != CODEC_TYPE_NULL)
+            .context("no supported tracks found")?;
+        let track_id = track.id;
+        let rate = track.codec_params.sample_rate.unwrap_or(SAMPLE_RATE);
+        let mut decoder = get_codecs().make(&track.codec_params, &Default::default())?;
+
+        let mut resampler = Resampler::new(rate, SAMPLE_RATE, 2);
+        let mut stereo = vec![];
+        let mut samples = vec![];
+        let max_samples = (MAX_CLIP_LENGTH.as_secs() as usize) * SAMPLE_RATE as usize * 2;
+
+        while let Ok(packet) = format.next_packet() {
+            if packet.track_id() != track_id {
+                continue;
+            }
+
+            let decoded = decoder.decode(&packet)?;
+            let channels = decoded.spec().channels.count();
+            let mut buf = SampleBuffer::<f32>::new(decoded.capacity() as u64, *decoded.spec());
+            buf.copy_interleaved_ref(decoded);
+
+            // mono goes to both ears, anything past stereo is dropped
+            stereo.clear();
+            for frame in buf.samples().chunks_exact(channels) {
+                let right = if channels == 1 { frame[0] } else { frame[1] };
+                stereo.extend([frame[0], right]);
+            }
+            resampler.process(&stereo, &mut samples);
+
+            if samples.len() > max_samples {
+                bail!("clip is longer than {} seconds", MAX_CLIP_LENGTH.as_secs());
+            }
+        }
+
+        if samples.is_empty() {
+            bail!("{} has no audio", path.display());
+        }
+
+        let name = path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        Ok(Self::from_samples(name, samples))
+    }
+
+    pub fn from_samples(name: impl Into<String>, samples: Vec<f32>) -> Self {
+        Self {
+            name: name.into(),
+            samples: samples.into(),
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// 48kHz stereo, interleaved
+    pub fn samples(&self) -> &[f32] {
+        &self.samples
+    }
+
+    pub fn duration(&self) -> Duration {
+        Duration::from_secs_f64(self.samples.len() as f64 / 2.0 / SAMPLE_RATE as f64)
+    }
+}
+
+/// Files in `dir` sorted by name, whether they decode is only known once
+/// they're played
+pub fn find_clips(dir: &Path) -> Vec<PathBuf> {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return vec![];
+    };
+
+    let mut clips = entries
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.is_file())
+        .collect::<Vec<_>>();
+    clips.sort();
+    clips
+}
+
+#[derive(Default)]
+pub struct Soundboard {
+    // the clip, how far into it we are and how it's played
+    playing: Option<(Clip, usize, ClipMode)>,
+}
+
+impl Soundboard {
+    /// Starts `clip` from the top, cutting off whatever was playing
+    pub fn play(&mut self, clip: Clip, mode: ClipMode) {
+        self.playing = Some((clip, 0, mode));
+    }
+
+    /// `false` if nothing was playing
+    pub fn stop(&mut self) -> bool {
+        self.playing.take().is_some()
+    }
+
+    pub fn playing(&self) -> Option<&Clip> {
+        self.playing.as_ref().map(|(clip, _, _)| clip)
+    }
+
+    /// Puts the next part of the clip into an interleaved stereo mic frame,
+    /// `false` once there's nothing left to play
+    pub fn mix_into(&mut self, frame: &mut [f32]) -> bool {
+        let Some((clip, pos, mode)) = &mut self.playing else {
+            return false;
+        };
+
+        let rest = &clip.samples[*pos..];
+        for (i, out) in frame.iter_mut().enumerate() {
+            let sample = rest.get(i).copied().unwrap_or(0.0);
+            *out = match mode {
+                ClipMode::Mix => (*out + sample).clamp(-1.0, 1.0),
+                ClipMode::Replace => sample,
+            };
+        }
+
+        *pos += frame.len().min(rest.len());
+        if *pos >= clip.samples.len() {
+            self.playing = None;
+        }
+        true
+    }
+}
@@ -0,0 +1,201 @@
+// Shared persistence for server features. Everything is a blob under a
+// (namespace, key) pair, so features pick a namespace and never care which
+// backend the operator chose.
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum StorageError {
+    #[error("io error: {0}")]
+    Io(#[from] io::Error),
+
+    #[cfg(feature = "sled")]
+    #[error("sled error: {0}")]
+    Sled(#[from] sled::Error),
+
+    #[error("invalid namespace '{0}'")]
+    InvalidNamespace(String),
+}
+
+pub type StorageResult<T> = Result<T, StorageError>;
+
+pub trait Storage: Send + Sync {
+    fn get(&self, namespace: &str, key: &str) -> StorageResult<Option<Vec<u8>>>;
+    fn put(&self, namespace: &str, key: &str, value: &[u8]) -> StorageResult<()>;
+    /// Keys in the namespace, sorted
+    fn list(&self, namespace: &str) -> StorageResult<Vec<String>>;
+    /// Returns whether the key existed
+    fn delete(&self, namespace: &str, key: &str) -> StorageResult<bool>;
+}
+
+fn check_namespace(namespace: &str) -> StorageResult<()> {
+    let valid = !namespace.is_empty()
+        && namespace
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-');
+
+    if valid {
+        Ok(())
+    } else {
+        Err(StorageError::InvalidNamespace(namespace.to_string()))
+    }
+}
+
+/// One directory per namespace, one file per key. Keys are escaped so any
+/// string (masks, addresses, ...) makes a valid file name.
+pub struct FileStorage {
+    root: PathBuf,
+}
+
+impl FileStorage {
+    /// Nothing is created on disk until the first write
+    pub fn open(root: &Path) -> Self {
+        Self {
+            root: root.to_path_buf(),
+        }
+    }
+
+    fn path(&self, namespace: &str, key: &str) -> StorageResult<PathBuf> {
+        check_namespace(namespace)?;
+        Ok(self.root.join(namespace).join(encode_key(key)))
+    }
+}
+
+impl Storage for FileStorage {
+    fn get(&self, namespace: &str, key: &str) -> StorageResult<Option<Vec<u8>>> {
+        match fs::read(self.path(namespace, key)?) {
+            Ok(data) => Ok(Some(data)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn put(&self, namespace: &str, key: &str, value: &[u8]) -> StorageResult<()> {
+        let path = self.path(namespace, key)?;
+        fs::create_dir_all(self.root.join(namespace))?;
+
+        // write then rename so a crash never leaves half a value behind
+        let tmp = path.with_extension("tmp");
+        fs::write(&tmp, value)?;
+        fs::rename(tmp, path)?;
+        Ok(())
+    }
+
+    fn list(&self, namespace: &str) -> StorageResult<Vec<String>> {
+        check_namespace(namespace)?;
+
+        let entries = match fs::read_dir(self.root.join(namespace)) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(vec![]),
+            Err(e) => return Err(e.into()),
+        };
+
+        let mut keys = Vec::new();
+        for entry in entries {
+            let name = entry?.file_name();
+            if let Some(key) = name.to_str().and_then(decode_key) {
+                keys.push(key);
+            }
+        }
+        keys.sort();
+        Ok(keys)
+    }
+
+    fn delete(&self, namespace: &str, key: &str) -> StorageResult<bool> {
+        match fs::remove_file(self.path(namespace, key)?) {
+            Ok(()) => Ok(true),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(false),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+// keeps [A-Za-z0-9_-] and %-escapes every other byte. the '.' is escaped too so
+// keys can't collide with our .tmp files or walk out of the namespace
+fn encode_key(key: &str) -> String {
+    let mut out = String::with_capacity(key.len() + 4);
+    out.push('k');
+    for b in key.bytes() {
+        if b.is_ascii_alphanumeric() || b == b'_' || b == b'-' {
+            out.push(b as char);
+        } else {
+            out.push_str(&format!("%{b:02x}"));
+        }
+    }
+    out
+}
+
+fn decode_key(name: &str) -> Option<String> {
+    let encoded = name.strip_prefix('k')?;
+    let mut bytes = Vec::with_capacity(encoded.len());
+    let mut chars = encoded.bytes();
+
+    while let Some(b) = chars.next() {
+        if b == b'%' {
+            let hex = [chars.next()?, chars.next()?];
+            bytes.push(u8::from_str_radix(std::str::from_utf8(&hex).ok()?, 16).ok()?);
+        } else if b.is_ascii_alphanumeric() || b == b'_' || b == b'-' {
+            bytes.push(b);
+        } else {
+            return None;
+        }
+    }
+
+    String::from_utf8(bytes).ok()
+}
+
+/// Every namespace is a sled tree
+#[cfg(feature = "sled")]
+pub struct SledStorage {
+    db: sled::Db,
+}
+
+#[cfg(feature = "sled")]
+impl SledStorage {
+    pub fn open(path: &Path) -> StorageResult<Self> {
+        Ok(Self {
+            db: sled::open(path)?,
+        })
+    }
+
+    fn tree(&self, namespace: &str) -> StorageResult<sled::Tree> {
+        check_namespace(namespace)?;
+        Ok(self.db.open_tree(namespace)?)
+    }
+}
+
+#[cfg(feature = "sled")]
+impl Storage for SledStorage {
+    fn get(&self, namespace: &str, key: &str) -> StorageResult<Option<Vec<u8>>> {
+        Ok(self.tree(namespace)?.get(key)?.map(|v| v.to_vec()))
+    }
+
+    fn put(&self, namespace: &str, key: &str, value: &[u8]) -> StorageResult<()> {
+        let tree = self.tree(namespace)?;
+        tree.insert(key, value)?;
+        tree.flush()?;
+        Ok(())
+    }
+
+    fn list(&self, namespace: &str) -> StorageResult<Vec<String>> {
+        let mut keys = Vec::new();
+        for entry in self.tree(namespace)?.iter() {
+            let (key, _) = entry?;
+            if let Ok(key) = String::from_utf8(key.to_vec()) {
+                keys.push(key);
+            }
+        }
+        Ok(keys)
+    }
+
+    fn delete(&self, namespace: &str, key: &str) -> StorageResult<bool> {
+        let tree = self.tree(namespace)?;
+        let existed = tree.remove(key)?.is_some();
+        tree.flush()?;
+        Ok(existed)
+    }
+}
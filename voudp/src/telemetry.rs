@@ -0,0 +1,155 @@
+// Opt-in aggregate usage statistics. Nothing here knows about individual users:
+// only counts are kept, and nothing is written unless the operator enables it.
+use std::{
+    fs::OpenOptions,
+    io::{self, Read, Write},
+    net::TcpStream,
+    path::PathBuf,
+    thread,
+    time::{Duration, Instant},
+};
+
+use chrono::Local;
+use log::{info, warn};
+
+use crate::protocol;
+
+#[derive(Debug, Clone)]
+pub enum TelemetrySink {
+    /// Append one JSON object per report to a local file
+    File(PathBuf),
+    /// POST each report as JSON to a plain `http://host[:port]/path` endpoint
+    Http(String),
+}
+
+#[derive(Debug, Clone)]
+pub struct TelemetryConfig {
+    pub sink: TelemetrySink,
+    pub interval: Duration,
+}
+
+pub struct Telemetry {
+    config: TelemetryConfig,
+    started: Instant,
+    last_report: Instant,
+    peak_users: usize,
+    talker_sum: u64,
+    ticks: u64,
+}
+
+impl Telemetry {
+    pub fn new(config: TelemetryConfig) -> Self {
+        info!(
+            "Anonymous usage statistics are enabled, reporting to {:?} every {}s",
+            config.sink,
+            config.interval.as_secs()
+        );
+
+        Self {
+            config,
+            started: Instant::now(),
+            last_report: Instant::now(),
+            peak_users: 0,
+            talker_sum: 0,
+            ticks: 0,
+        }
+    }
+
+    /// Records one audio tick. Called with the connected user count and the
+    /// number of remotes that were actually talking during the tick.
+    pub fn record_tick(&mut self, users: usize, talkers: usize) {
+        self.peak_users = self.peak_users.max(users);
+        self.talker_sum += talkers as u64;
+        self.ticks += 1;
+
+        if self.last_report.elapsed() >= self.config.interval {
+            self.report();
+        }
+    }
+
+    fn report(&mut self) {
+        let average_talkers = if self.ticks == 0 {
+            0.0
+        } else {
+            self.talker_sum as f64 / self.ticks as f64
+        };
+
+        let json = format!(
+            r#"{{"version":"{}","time":"{}","uptime_secs":{},"peak_users":{},"average_talkers":{:.3}}}"#,
+            protocol::VERSION,
+            Local::now().to_rfc3339(),
+            self.started.elapsed().as_secs(),
+            self.peak_users,
+            average_talkers
+        );
+
+        match &self.config.sink {
+            TelemetrySink::File(path) => {
+                let result = OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(path)
+                    .and_then(|mut file| writeln!(file, "{json}"));
+
+                if let Err(e) = result {
+                    warn!("Failed to write usage statistics: {e}");
+                }
+            }
+            TelemetrySink::Http(url) => {
+                // don't stall the audio loop on a slow endpoint
+                let url = url.clone();
+                thread::spawn(move || {
+                    if let Err(e) = post(&url, &json) {
+                        warn!("Failed to post usage statistics: {e}");
+                    }
+                });
+            }
+        }
+
+        // counters are per interval, uptime is not
+        self.last_report = Instant::now();
+        self.peak_users = 0;
+        self.talker_sum = 0;
+        self.ticks = 0;
+    }
+}
+
+fn post(url: &str, body: &str) -> io::Result<()> {
+    let invalid = || {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "expected http://host[:port]/path",
+        )
+    };
+
+    let rest = url.strip_prefix("http://").ok_or_else(invalid)?;
+    let (host, path) = rest.split_once('/').unwrap_or((rest, ""));
+    if host.is_empty() {
+        return Err(invalid());
+    }
+    let addr = if host.contains(':') {
+        host.to_string()
+    } else {
+        format!("{host}:80")
+    };
+
+    let mut stream = TcpStream::connect(addr)?;
+    stream.set_write_timeout(Some(Duration::from_secs(2)))?;
+    stream.set_read_timeout(Some(Duration::from_secs(2)))?;
+
+    write!(
+        stream,
+        "POST /{path} HTTP/1.1\r\nHost: {host}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+        body.len()
+    )?;
+
+    let mut status = [0u8; 12];
+    stream.read_exact(&mut status)?;
+    match &status[9..10] {
+        b"2" => Ok(()),
+        _ => Err(io::Error::other(format!(
+            "endpoint answered {}",
+            String::from_utf8_lossy(&status[9..12])
+        ))),
+    }
+}
@@ -1,12 +1,18 @@
 use std::io;
 use std::io::Write;
 use std::net::SocketAddr;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::time::{Duration, Instant};
 
+use crate::effects::EffectKind;
 use crate::protocol::{
-    ClientPacketType, CommandResultPacketType, ControlRequest, FromPacket, IntoPacket, PacketError,
+    self, ClientPacketType, CommandResultPacketType, ConsoleAuth, ConsoleRole, ControlRequest,
+    FromPacket, IntoPacket, MessageCode, PacketError,
 };
+use crate::server::Role;
+use crate::socket::SecureUdpSocket;
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct ChannelInfo {
     pub name: String,
     pub channel_id: u32,
@@ -14,7 +20,7 @@ pub struct ChannelInfo {
     pub masked_users: Vec<(String, bool, bool)>,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct ServerCommand {
     pub name: String,
     pub description: String,
@@ -23,6 +29,154 @@ pub struct ServerCommand {
     pub aliases: Vec<String>,
     pub requires_auth: bool,
     pub admin_only: bool,
+    /// Checked before the handler runs, empty leaves the arguments to it.
+    /// Stays on the server, synced commands come without
+    pub args: Vec<CommandArg>,
+}
+
+/// What a command argument has to be
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ArgKind {
+    Int,
+    /// A single word
+    String,
+    /// The mask of someone online
+    User,
+    /// A valid channel id, the channel doesn't have to exist yet
+    Channel,
+    /// All the words left, only goes last
+    Text,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CommandArg {
+    pub name: String,
+    pub kind: ArgKind,
+    pub required: bool,
+}
+
+impl CommandArg {
+    pub fn required(name: &str, kind: ArgKind) -> Self {
+        Self {
+            name: name.to_string(),
+            kind,
+            required: true,
+        }
+    }
+
+    pub fn optional(name: &str, kind: ArgKind) -> Self {
+        Self {
+            name: name.to_string(),
+            kind,
+            required: false,
+        }
+    }
+}
+
+impl ServerCommand {
+    /// Checks `args` against the command's schema, the error says what's wrong
+    /// along with the usage. `is_online` tells whether a mask is connected
+    pub fn check_args(
+        &self,
+        args: &[String],
+        is_online: impl Fn(&str) -> bool,
+    ) -> Result<(), String> {
+        if self.args.is_empty() {
+            return Ok(());
+        }
+
+        let usage = &self.usage;
+        for (spec, value) in self.args.iter().zip(args) {
+            match spec.kind {
+                ArgKind::Int if value.parse::<i64>().is_err() => {
+                    return Err(format!("{} must be a number, usage: {usage}", spec.name));
+                }
+                ArgKind::Channel
+                    if !value
+                        .parse::<u32>()
+                        .is_ok_and(|id| id != 0 && id < u16::MAX as u32) =>
+                {
+                    return Err(format!("{value} isn't a valid channel id"));
+                }
+                ArgKind::User if !is_online(value) => {
+                    return Err(format!("{value} is not online"));
+                }
+                _ => {}
+            }
+        }
+
+        if let Some(missing) = self.args.get(args.len()).filter(|spec| spec.required) {
+            return Err(format!("missing {}, usage: {usage}", missing.name));
+        }
+        let takes_rest = self
+            .args
+            .last()
+            .is_some_and(|spec| spec.kind == ArgKind::Text);
+        if !takes_rest && args.len() > self.args.len() {
+            return Err(format!("too many arguments, usage: {usage}"));
+        }
+        Ok(())
+    }
+
+    /// Whether the sender in `ctx` may run this command, the reply to send
+    /// back when they may not
+    pub fn authorize(&self, ctx: &CommandContext) -> Result<(), CommandResult> {
+        if self.requires_auth && ctx.sender_mask.is_none() {
+            return Err(CommandResult::permission_denied(&format!(
+                "set a nickname with /nick to use {}",
+                self.name
+            )));
+        }
+        if self.admin_only && !ctx.is_admin() {
+            return Err(CommandResult::permission_denied(&format!(
+                "{} is for admins only",
+                self.name
+            )));
+        }
+        Ok(())
+    }
+
+    /// Whether `name` is this command or one of its aliases, slash optional
+    pub fn is_called(&self, name: &str) -> bool {
+        let name = name.trim_start_matches('/');
+        std::iter::once(&self.name)
+            .chain(&self.aliases)
+            .any(|n| n.trim_start_matches('/').eq_ignore_ascii_case(name))
+    }
+
+    /// `<required>` arguments in the usage, `[optional]` ones don't count
+    pub fn required_args(&self) -> usize {
+        self.usage.matches('<').count()
+    }
+}
+
+/// Commands whose name or an alias starts with `prefix`, slash optional
+pub fn complete_command<'a>(commands: &'a [ServerCommand], prefix: &str) -> Vec<&'a ServerCommand> {
+    let prefix = prefix.trim_start_matches('/').to_lowercase();
+    commands
+        .iter()
+        .filter(|cmd| {
+            std::iter::once(&cmd.name).chain(&cmd.aliases).any(|n| {
+                n.trim_start_matches('/')
+                    .to_lowercase()
+                    .starts_with(&prefix)
+            })
+        })
+        .collect()
+}
+
+/// The command a typed line would run, e.g. "/w bob hi" finds /whisper
+pub fn find_command<'a>(commands: &'a [ServerCommand], line: &str) -> Option<&'a ServerCommand> {
+    let name = line.split_whitespace().next()?;
+    commands.iter().find(|cmd| cmd.is_called(name))
+}
+
+/// The usage to show instead of sending `line`, when it's obviously missing
+/// arguments. Unknown commands are left for the server to judge
+pub fn usage_hint<'a>(commands: &'a [ServerCommand], line: &str) -> Option<&'a str> {
+    let command = find_command(commands, line)?;
+    let given = line.split_whitespace().count() - 1;
+    (given < command.required_args()).then_some(command.usage.as_str())
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -36,19 +190,88 @@ pub enum CommandCategory {
     Fun,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum CommandResult {
     Success(String),
     Error(String),
     Silent,
 }
 
+impl CommandResult {
+    /// The one reply for a command the sender isn't allowed to run
+    pub fn permission_denied(why: &str) -> Self {
+        Self::Error(format!("Permission denied: {why}"))
+    }
+}
+
 pub struct CommandContext {
     pub sender_addr: SocketAddr,
     pub sender_mask: Option<String>,
     pub channel_id: u32,
     pub arguments: Vec<String>,
-    pub is_admin: bool,
+    pub role: Role,
+}
+
+impl CommandContext {
+    pub fn is_admin(&self) -> bool {
+        self.role == Role::Admin
+    }
+
+    /// The words from argument `i` on, for an `ArgKind::Text`
+    pub fn text(&self, i: usize) -> String {
+        self.arguments.get(i..).unwrap_or_default().join(" ")
+    }
+}
+
+/// Logs `socket` in as a `role` console of `server`, returning the session
+/// token commands have to carry
+pub fn console_login(
+    socket: &SecureUdpSocket,
+    server: SocketAddr,
+    password: &str,
+    role: ConsoleRole,
+) -> io::Result<Vec<u8>> {
+    let answer = |expected: ConsoleAuth| -> io::Result<Vec<u8>> {
+        let deadline = Instant::now() + Duration::from_secs(3);
+        let mut buf = [0u8; 64];
+        while Instant::now() < deadline {
+            match socket.recv_from(&mut buf) {
+                Ok((size, addr)) if addr == server && size >= 2 => {
+                    if buf[0] != ClientPacketType::RegisterConsole as u8 {
+                        continue;
+                    }
+                    match ConsoleAuth::try_from(buf[1]) {
+                        Ok(step) if step == expected => return Ok(buf[2..size].to_vec()),
+                        Ok(ConsoleAuth::Denied) => {
+                            return Err(io::Error::new(
+                                io::ErrorKind::PermissionDenied,
+                                "the server refused the console password",
+                            ));
+                        }
+                        _ => {}
+                    }
+                }
+                Ok(_) => {}
+                Err(_) => std::thread::sleep(Duration::from_millis(5)),
+            }
+        }
+        Err(io::Error::new(
+            io::ErrorKind::TimedOut,
+            "the server didn't answer the console login",
+        ))
+    };
+
+    socket.send_reliable(
+        protocol::create_console_auth(ConsoleAuth::Hello, &[role as u8]),
+        server,
+    )?;
+    let nonce = answer(ConsoleAuth::Challenge)?;
+    let proof = protocol::console_proof(password, &nonce);
+    socket.send_reliable(
+        protocol::create_console_auth(ConsoleAuth::Proof, &proof),
+        server,
+    )?;
+    answer(ConsoleAuth::Session)
 }
 
 pub fn ask(prompt: &str) -> String {
@@ -75,25 +298,14 @@ pub fn is_whitespace_only(s: &str) -> bool {
     })
 }
 
-impl IntoPacket for CommandResult {
+impl IntoPacket for CommandResponsePacket {
     fn serialize(&self) -> Vec<u8> {
-        let mut packet = vec![ClientPacketType::Cmd as u8];
-        match self {
-            CommandResult::Success(content) => {
-                packet.push(CommandResultPacketType::Success as u8);
-                packet.extend_from_slice(content.as_bytes());
-                packet
-            }
-            CommandResult::Error(content) => {
-                packet.push(CommandResultPacketType::Error as u8);
-                packet.extend_from_slice(content.as_bytes());
-                packet
-            }
-            CommandResult::Silent => {
-                packet.push(CommandResultPacketType::Silent as u8);
-                packet
-            }
-        }
+        let (status, message) = match &self.result {
+            CommandResult::Success(message) => (CommandResultPacketType::Success, message.as_str()),
+            CommandResult::Error(message) => (CommandResultPacketType::Error, message.as_str()),
+            CommandResult::Silent => (CommandResultPacketType::Silent, ""),
+        };
+        protocol::create_command_response(status, &self.command, message)
     }
 }
 
@@ -108,6 +320,56 @@ impl IntoPacket for BroadcastPacket {
     }
 }
 
+impl IntoPacket for ChatPacket {
+    fn serialize(&self) -> Vec<u8> {
+        let mut packet = vec![ClientPacketType::Chat as u8];
+        packet.extend_from_slice(self.username.as_bytes());
+        packet.push(0x01_u8);
+        packet.push(self.is_self as u8);
+        packet.extend_from_slice(self.message.as_bytes());
+
+        packet
+    }
+}
+
+impl IntoPacket for LocalizedMessage {
+    fn serialize(&self) -> Vec<u8> {
+        let mut packet = vec![ClientPacketType::Notice as u8];
+        packet.extend_from_slice(&self.code.to_be_bytes());
+        packet.push(self.params.len() as u8);
+        for param in &self.params {
+            let bytes = &param.as_bytes()[..param.len().min(u8::MAX as usize)];
+            packet.push(bytes.len() as u8);
+            packet.extend_from_slice(bytes);
+        }
+        packet.extend_from_slice(self.fallback.as_bytes());
+
+        packet
+    }
+}
+
+impl IntoPacket for RetryAfterPacket {
+    fn serialize(&self) -> Vec<u8> {
+        let mut packet = vec![ClientPacketType::RetryAfter as u8];
+        packet.extend_from_slice(&self.delay_ms.to_be_bytes());
+        packet.extend_from_slice(&self.channel_id.to_be_bytes());
+        packet
+    }
+}
+
+impl IntoPacket for TalkersPacket {
+    fn serialize(&self) -> Vec<u8> {
+        let count = self.masks.len().min(u8::MAX as usize);
+        let mut packet = vec![ClientPacketType::Talkers as u8, count as u8];
+        for mask in &self.masks[..count] {
+            let bytes = &mask.as_bytes()[..mask.len().min(u8::MAX as usize)];
+            packet.push(bytes.len() as u8);
+            packet.extend_from_slice(bytes);
+        }
+        packet
+    }
+}
+
 // Define your packet types
 #[derive(Debug, Clone)]
 pub struct GlobalListPacket {
@@ -120,8 +382,10 @@ pub struct CommandListPacket {
     pub commands: Vec<ServerCommand>,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct CommandResponsePacket {
+    /// The command that ran, or what was typed when there was no such command
+    pub command: String,
     pub result: CommandResult,
 }
 
@@ -138,17 +402,98 @@ pub struct BroadcastPacket {
     pub content: String,
 }
 
+/// A server-sent string identified by a `MessageCode`. `code` is kept raw so
+/// codes from newer servers survive the round trip; `fallback` is what the
+/// server would have sent as plain English.
+#[derive(Debug, Clone)]
+pub struct LocalizedMessage {
+    pub code: u16,
+    pub params: Vec<String>,
+    pub fallback: String,
+}
+
+impl LocalizedMessage {
+    pub fn new(code: MessageCode, params: Vec<String>) -> Self {
+        let fallback = fill_template(code.template(), &params);
+        Self {
+            code: code as u16,
+            params,
+            fallback,
+        }
+    }
+
+    pub fn custom(text: impl Into<String>) -> Self {
+        Self::new(MessageCode::Custom, vec![text.into()])
+    }
+
+    pub fn message_code(&self) -> Option<MessageCode> {
+        MessageCode::try_from(self.code).ok()
+    }
+
+    /// Renders the message with the given template lookup, falling back to the
+    /// server-provided string for codes the lookup doesn't know.
+    pub fn render_with(&self, lookup: impl Fn(MessageCode) -> Option<String>) -> String {
+        self.message_code()
+            .and_then(lookup)
+            .map(|template| fill_template(&template, &self.params))
+            .unwrap_or_else(|| self.fallback.clone())
+    }
+
+    pub fn render(&self) -> String {
+        self.render_with(|code| Some(code.template().to_string()))
+    }
+}
+
+fn fill_template(template: &str, params: &[String]) -> String {
+    params
+        .iter()
+        .enumerate()
+        .fold(template.to_string(), |out, (i, param)| {
+            out.replace(&format!("{{{i}}}"), param)
+        })
+}
+
+/// Sent instead of accepting a join while the server is pacing joins. The
+/// client should try joining `channel_id` again after `backoff()`.
+#[derive(Debug, Clone)]
+pub struct RetryAfterPacket {
+    pub delay_ms: u16,
+    pub channel_id: u32,
+}
+
+impl RetryAfterPacket {
+    /// The requested delay plus up to 50% jitter, so a herd of clients that got
+    /// turned away together doesn't come back together
+    pub fn backoff(&self) -> Duration {
+        let delay = self.delay_ms as u64;
+        Duration::from_millis(delay + rand::random_range(0..=delay / 2))
+    }
+}
+
+/// Who the listeners of a channel currently hear, unmasked talkers aren't named
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TalkersPacket {
+    pub masks: Vec<String>,
+}
+
 #[derive(Debug, Clone)]
 pub enum FlowPacket {
     Join(String),
     Leave(String),
     Renick { old_mask: String, new_mask: String },
     Broadcast { from: String, message: String },
+    Whisper { from: String, message: String },
 }
 
 #[derive(Debug, Clone)]
 pub struct ControlPacket {
     pub request: ControlRequest,
+    // (talker mask, gain), only set for SetTalkerGain
+    pub talker_gain: Option<(String, f32)>,
+    // only set for SetEffects
+    pub effects: Vec<EffectKind>,
+    // only set for Subscribe and Unsubscribe
+    pub channel_id: Option<u32>,
 }
 
 impl FromPacket for GlobalListPacket {
@@ -159,76 +504,291 @@ impl FromPacket for GlobalListPacket {
 
         let current = u32::from_be_bytes(bytes[0..4].try_into()?);
         let chan_count = u32::from_be_bytes(bytes[4..8].try_into()?);
-        let mut channels = Vec::new();
-        let mut i = 8;
+        let mut channels = read_channels(bytes, 8, chan_count)?;
 
-        for _ in 0..chan_count {
-            // Ensure we have at least the channel name length byte
-            if i >= bytes.len() {
-                return Err(PacketError::BufferUnderflow(i));
-            }
+        channels.sort_by_key(|c| c.channel_id);
 
-            let chan_name_len = bytes[i] as usize;
-            i += 1;
+        Ok(GlobalListPacket { channels, current })
+    }
+}
 
-            // Check if we have enough bytes for the channel name
-            if i + chan_name_len > bytes.len() {
+impl IntoPacket for GlobalListPacket {
+    fn serialize(&self) -> Vec<u8> {
+        let mut packet = vec![ClientPacketType::List as u8];
+        packet.extend_from_slice(&self.current.to_be_bytes());
+        packet.extend_from_slice(&(self.channels.len() as u32).to_be_bytes());
+        for channel in &self.channels {
+            write_channel(&mut packet, channel);
+        }
+        packet
+    }
+}
+
+fn write_channel(packet: &mut Vec<u8>, channel: &ChannelInfo) {
+    packet.push(channel.name.len() as u8);
+    packet.extend_from_slice(channel.name.as_bytes());
+    packet.extend_from_slice(&channel.channel_id.to_be_bytes());
+    packet.extend_from_slice(&channel.unmasked_count.to_be_bytes());
+    packet.extend_from_slice(&(channel.masked_users.len() as u32).to_be_bytes());
+
+    for (mask, muted, deafened) in &channel.masked_users {
+        packet.extend_from_slice(mask.as_bytes());
+        packet.push(0x01);
+        let flags = (*muted as u8) | ((*deafened as u8) << 1);
+        packet.push(flags);
+    }
+}
+
+fn read_channels(
+    bytes: &[u8],
+    mut i: usize,
+    chan_count: u32,
+) -> Result<Vec<ChannelInfo>, PacketError> {
+    let mut channels = Vec::new();
+
+    for _ in 0..chan_count {
+        // Ensure we have at least the channel name length byte
+        if i >= bytes.len() {
+            return Err(PacketError::BufferUnderflow(i));
+        }
+
+        let chan_name_len = bytes[i] as usize;
+        i += 1;
+
+        // Check if we have enough bytes for the channel name
+        if i + chan_name_len > bytes.len() {
+            return Err(PacketError::BufferUnderflow(i));
+        }
+
+        let name = String::from_utf8(bytes[i..i + chan_name_len].to_vec())?;
+        i += chan_name_len;
+
+        // Check if we have enough bytes for channel metadata
+        if i + 12 > bytes.len() {
+            return Err(PacketError::BufferUnderflow(i));
+        }
+
+        let channel_id = u32::from_be_bytes(bytes[i..i + 4].try_into()?);
+        let unmasked_count = u32::from_be_bytes(bytes[i + 4..i + 8].try_into()?);
+        let masked_count = u32::from_be_bytes(bytes[i + 8..i + 12].try_into()?);
+        i += 12;
+
+        let mut masked_users = Vec::new();
+
+        for _ in 0..masked_count {
+            // Find the delimiter (0x01)
+            let sep_pos = bytes[i..]
+                .iter()
+                .position(|&b| b == 0x01)
+                .ok_or(PacketError::MissingDelimiter)?;
+
+            if i + sep_pos > bytes.len() {
                 return Err(PacketError::BufferUnderflow(i));
             }
 
-            let name = String::from_utf8(bytes[i..i + chan_name_len].to_vec())?;
-            i += chan_name_len;
+            let mask_str = String::from_utf8(bytes[i..i + sep_pos].to_vec())?;
+            i += sep_pos + 1; // +1 for the delimiter
 
-            // Check if we have enough bytes for channel metadata
-            if i + 12 > bytes.len() {
+            if i >= bytes.len() {
                 return Err(PacketError::BufferUnderflow(i));
             }
 
-            let channel_id = u32::from_be_bytes(bytes[i..i + 4].try_into()?);
-            let unmasked_count = u32::from_be_bytes(bytes[i + 4..i + 8].try_into()?);
-            let masked_count = u32::from_be_bytes(bytes[i + 8..i + 12].try_into()?);
-            i += 12;
+            let flags = bytes[i];
+            i += 1;
 
-            let mut masked_users = Vec::new();
+            let muted = flags & 0b00000001 != 0;
+            let deafened = flags & 0b00000010 != 0;
 
-            for _ in 0..masked_count {
-                // Find the delimiter (0x01)
-                let sep_pos = bytes[i..]
-                    .iter()
-                    .position(|&b| b == 0x01)
-                    .ok_or(PacketError::MissingDelimiter)?;
+            masked_users.push((mask_str, muted, deafened));
+        }
 
-                if i + sep_pos > bytes.len() {
-                    return Err(PacketError::BufferUnderflow(i));
-                }
+        channels.push(ChannelInfo {
+            name,
+            channel_id,
+            unmasked_count,
+            masked_users,
+        });
+    }
 
-                let mask_str = String::from_utf8(bytes[i..i + sep_pos].to_vec())?;
-                i += sep_pos + 1; // +1 for the delimiter
+    Ok(channels)
+}
 
-                if i >= bytes.len() {
-                    return Err(PacketError::BufferUnderflow(i));
-                }
+/// Keeps a list page well under a typical path MTU once encrypted
+pub const MAX_LIST_PAGE_BYTES: usize = 1200;
 
-                let flags = bytes[i];
-                i += 1;
+/// One page of the global list. Masked users are paged across all channels in
+/// channel id order; `next` is the offset to ask for next, `None` on the last page.
+/// Only the first page carries every channel, later pages only carry the
+/// channels their users are in.
+#[derive(Debug, Clone)]
+pub struct ListPagePacket {
+    pub current: u32,
+    pub offset: u32,
+    pub next: Option<u32>,
+    pub total_users: u32,
+    pub channels: Vec<ChannelInfo>,
+}
 
-                let muted = flags & 0b00000001 != 0;
-                let deafened = flags & 0b00000010 != 0;
+impl ListPagePacket {
+    /// Cuts the page starting at `offset` out of the full list, stopping at
+    /// `limit` users or when the page would outgrow `MAX_LIST_PAGE_BYTES`
+    pub fn paginate(current: u32, channels: &[ChannelInfo], offset: u32, limit: u16) -> Self {
+        let mut sorted = channels.iter().collect::<Vec<_>>();
+        sorted.sort_by_key(|c| c.channel_id);
+
+        let total_users = sorted.iter().map(|c| c.masked_users.len() as u32).sum();
+        let limit = match limit {
+            0 => protocol::DEFAULT_LIST_PAGE_LIMIT,
+            limit => limit,
+        } as usize;
+
+        // header: type, current, offset, next, total, channel count
+        let mut size = 21;
+        let mut taken = 0;
+        let mut skipped = 0;
+        let mut full = false;
+        let mut page = Vec::new();
+
+        for channel in sorted {
+            let users = channel.masked_users.len();
+            let start = (offset as usize).saturating_sub(skipped).min(users);
+            skipped += start;
+
+            let mut picked = Vec::new();
+            for user in &channel.masked_users[start..] {
+                let user_size = user.0.len() + 2;
+                // always take at least one user so the client makes progress
+                full =
+                    full || taken == limit || (taken > 0 && size + user_size > MAX_LIST_PAGE_BYTES);
+                if full {
+                    break;
+                }
+                size += user_size;
+                taken += 1;
+                picked.push(user.clone());
+            }
 
-                masked_users.push((mask_str, muted, deafened));
+            if offset == 0 || !picked.is_empty() {
+                size += channel.name.len() + 13;
+                page.push(ChannelInfo {
+                    name: channel.name.clone(),
+                    channel_id: channel.channel_id,
+                    unmasked_count: channel.unmasked_count,
+                    masked_users: picked,
+                });
             }
+        }
 
-            channels.push(ChannelInfo {
-                name,
-                channel_id,
-                unmasked_count,
-                masked_users,
-            });
+        let end = offset + taken as u32;
+        ListPagePacket {
+            current,
+            offset,
+            next: (end < total_users).then_some(end),
+            total_users,
+            channels: page,
         }
+    }
+}
 
-        channels.sort_by(|a, b| a.channel_id.cmp(&b.channel_id));
+impl IntoPacket for ListPagePacket {
+    fn serialize(&self) -> Vec<u8> {
+        let mut packet = vec![ClientPacketType::ListPage as u8];
+        packet.extend_from_slice(&self.current.to_be_bytes());
+        packet.extend_from_slice(&self.offset.to_be_bytes());
+        packet.extend_from_slice(&self.next.unwrap_or(u32::MAX).to_be_bytes());
+        packet.extend_from_slice(&self.total_users.to_be_bytes());
+        packet.extend_from_slice(&(self.channels.len() as u32).to_be_bytes());
+        for channel in &self.channels {
+            write_channel(&mut packet, channel);
+        }
+        packet
+    }
+}
 
-        Ok(GlobalListPacket { channels, current })
+impl FromPacket for ListPagePacket {
+    fn deserialize(bytes: &[u8]) -> Result<Self, PacketError> {
+        if bytes.len() < 21 {
+            return Err(PacketError::TooShort(21, bytes.len()));
+        }
+
+        if bytes[0] != ClientPacketType::ListPage as u8 {
+            return Err(PacketError::InvalidType(bytes[0]));
+        }
+
+        let next = u32::from_be_bytes(bytes[9..13].try_into()?);
+        let chan_count = u32::from_be_bytes(bytes[17..21].try_into()?);
+
+        Ok(ListPagePacket {
+            current: u32::from_be_bytes(bytes[1..5].try_into()?),
+            offset: u32::from_be_bytes(bytes[5..9].try_into()?),
+            next: (next != u32::MAX).then_some(next),
+            total_users: u32::from_be_bytes(bytes[13..17].try_into()?),
+            channels: read_channels(bytes, 21, chan_count)?,
+        })
+    }
+}
+
+pub enum RosterUpdate {
+    /// The last page arrived, here is the whole list
+    Complete(GlobalListPacket),
+    /// Ask for the page at this offset next
+    Next(u32),
+    /// Page didn't belong to the list being assembled
+    Ignored,
+}
+
+/// Client side assembly of paginated list responses. A page at offset 0 always
+/// starts over, so a lost page only costs one refresh.
+#[derive(Debug, Default)]
+pub struct ChannelRoster {
+    channels: Vec<ChannelInfo>,
+    expected: Option<u32>,
+}
+
+impl ChannelRoster {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn apply(&mut self, page: ListPagePacket) -> RosterUpdate {
+        if page.offset == 0 {
+            self.channels.clear();
+            self.expected = Some(0);
+        }
+
+        if self.expected != Some(page.offset) || page.next.is_some_and(|next| next <= page.offset) {
+            return RosterUpdate::Ignored;
+        }
+
+        for mut channel in page.channels {
+            match self
+                .channels
+                .iter_mut()
+                .find(|c| c.channel_id == channel.channel_id)
+            {
+                Some(existing) => {
+                    existing.unmasked_count = channel.unmasked_count;
+                    existing.masked_users.append(&mut channel.masked_users);
+                }
+                None => self.channels.push(channel),
+            }
+        }
+
+        match page.next {
+            Some(next) => {
+                self.expected = Some(next);
+                RosterUpdate::Next(next)
+            }
+            None => {
+                self.expected = None;
+                let mut channels = std::mem::take(&mut self.channels);
+                channels.sort_by_key(|c| c.channel_id);
+                RosterUpdate::Complete(GlobalListPacket {
+                    channels,
+                    current: page.current,
+                })
+            }
+        }
     }
 }
 
@@ -338,6 +898,7 @@ impl FromPacket for CommandListPacket {
                 aliases,
                 requires_auth,
                 admin_only,
+                args: Vec::new(),
             });
         }
 
@@ -347,32 +908,30 @@ impl FromPacket for CommandListPacket {
 
 impl FromPacket for CommandResponsePacket {
     fn deserialize(bytes: &[u8]) -> Result<Self, PacketError> {
-        if bytes.is_empty() {
-            return Err(PacketError::TooShort(1, 0));
+        if bytes.len() < 3 {
+            return Err(PacketError::TooShort(3, bytes.len()));
         }
 
-        let mode = CommandResultPacketType::try_from(bytes[0])
-            .map_err(|_| PacketError::InvalidType(bytes[0]))?;
-
-        if mode == CommandResultPacketType::Silent {
-            return Ok(CommandResponsePacket {
-                result: CommandResult::Silent,
-            });
+        if bytes[0] != ClientPacketType::CommandResponse as u8 {
+            return Err(PacketError::InvalidType(bytes[0]));
         }
 
-        if bytes.len() < 2 {
-            return Err(PacketError::TooShort(2, bytes.len()));
+        let status = CommandResultPacketType::try_from(bytes[1])
+            .map_err(|_| PacketError::InvalidType(bytes[1]))?;
+        let end = 3 + bytes[2] as usize;
+        if end > bytes.len() {
+            return Err(PacketError::BufferUnderflow(3));
         }
+        let command = String::from_utf8(bytes[3..end].to_vec())?;
+        let message = String::from_utf8(bytes[end..].to_vec())?;
 
-        let content = String::from_utf8(bytes[1..].to_vec())?;
-
-        let result = match mode {
-            CommandResultPacketType::Success => CommandResult::Success(content),
-            CommandResultPacketType::Error => CommandResult::Error(content),
-            CommandResultPacketType::Silent => unreachable!(),
+        let result = match status {
+            CommandResultPacketType::Success => CommandResult::Success(message),
+            CommandResultPacketType::Error => CommandResult::Error(message),
+            CommandResultPacketType::Silent => CommandResult::Silent,
         };
 
-        Ok(CommandResponsePacket { result })
+        Ok(CommandResponsePacket { command, result })
     }
 }
 
@@ -500,6 +1059,20 @@ impl FromPacket for FlowPacket {
                     message: msg,
                 })
             }
+            ClientPacketType::Whisper => {
+                if bytes.len() < 2 {
+                    return Err(PacketError::TooShort(2, bytes.len()));
+                }
+
+                let from_len = bytes[1] as usize;
+                if 2 + from_len > bytes.len() {
+                    return Err(PacketError::BufferUnderflow(2));
+                }
+                let from = String::from_utf8(bytes[2..2 + from_len].to_vec())?;
+                let message = String::from_utf8(bytes[2 + from_len..].to_vec())?;
+
+                Ok(FlowPacket::Whisper { from, message })
+            }
             _ => Err(PacketError::InvalidType(bytes[0])),
         }
     }
@@ -516,9 +1089,166 @@ impl FromPacket for ControlPacket {
             0x02 => ControlRequest::SetUndeafen,
             0x03 => ControlRequest::SetMute,
             0x04 => ControlRequest::SetUnmute,
+            0x05 => ControlRequest::SetPanning,
+            0x06 => ControlRequest::SetNoPanning,
+            0x07 => ControlRequest::SetTalkerGain,
+            0x08 => ControlRequest::SetEffects,
+            0x09 => ControlRequest::Subscribe,
+            0x0a => ControlRequest::Unsubscribe,
             _ => return Err(PacketError::InvalidType(bytes[0])),
         };
 
-        Ok(ControlPacket { request })
+        let talker_gain = if request == ControlRequest::SetTalkerGain {
+            if bytes.len() < 6 {
+                return Err(PacketError::TooShort(6, bytes.len()));
+            }
+
+            let gain = f32::from_be_bytes(bytes[1..5].try_into()?);
+            let mask = String::from_utf8(bytes[5..].to_vec())?;
+            Some((mask, gain))
+        } else {
+            None
+        };
+
+        let effects = if request == ControlRequest::SetEffects {
+            bytes[1..]
+                .iter()
+                .map(|&kind| EffectKind::try_from(kind).map_err(PacketError::InvalidType))
+                .collect::<Result<Vec<_>, _>>()?
+        } else {
+            vec![]
+        };
+
+        let channel_id = if matches!(
+            request,
+            ControlRequest::Subscribe | ControlRequest::Unsubscribe
+        ) {
+            if bytes.len() < 5 {
+                return Err(PacketError::TooShort(5, bytes.len()));
+            }
+            Some(u32::from_be_bytes(bytes[1..5].try_into()?))
+        } else {
+            None
+        };
+
+        Ok(ControlPacket {
+            request,
+            talker_gain,
+            effects,
+            channel_id,
+        })
+    }
+}
+
+impl FromPacket for LocalizedMessage {
+    fn deserialize(bytes: &[u8]) -> Result<Self, PacketError> {
+        if bytes.len() < 4 {
+            return Err(PacketError::TooShort(4, bytes.len()));
+        }
+
+        if bytes[0] != ClientPacketType::Notice as u8 {
+            return Err(PacketError::InvalidType(bytes[0]));
+        }
+
+        let code = u16::from_be_bytes([bytes[1], bytes[2]]);
+        let param_count = bytes[3] as usize;
+        let mut i = 4;
+        let mut params = Vec::with_capacity(param_count);
+
+        for _ in 0..param_count {
+            if i >= bytes.len() {
+                return Err(PacketError::BufferUnderflow(i));
+            }
+            let len = bytes[i] as usize;
+            i += 1;
+
+            if i + len > bytes.len() {
+                return Err(PacketError::BufferUnderflow(i));
+            }
+            params.push(String::from_utf8(bytes[i..i + len].to_vec())?);
+            i += len;
+        }
+
+        let fallback = String::from_utf8(bytes[i..].to_vec())?;
+
+        Ok(LocalizedMessage {
+            code,
+            params,
+            fallback,
+        })
+    }
+}
+
+impl FromPacket for RetryAfterPacket {
+    fn deserialize(bytes: &[u8]) -> Result<Self, PacketError> {
+        if bytes.len() < 7 {
+            return Err(PacketError::TooShort(7, bytes.len()));
+        }
+
+        if bytes[0] != ClientPacketType::RetryAfter as u8 {
+            return Err(PacketError::InvalidType(bytes[0]));
+        }
+
+        Ok(RetryAfterPacket {
+            delay_ms: u16::from_be_bytes(bytes[1..3].try_into()?),
+            channel_id: u32::from_be_bytes(bytes[3..7].try_into()?),
+        })
     }
 }
+
+impl FromPacket for TalkersPacket {
+    fn deserialize(bytes: &[u8]) -> Result<Self, PacketError> {
+        if bytes.len() < 2 {
+            return Err(PacketError::TooShort(2, bytes.len()));
+        }
+
+        if bytes[0] != ClientPacketType::Talkers as u8 {
+            return Err(PacketError::InvalidType(bytes[0]));
+        }
+
+        let mut masks = Vec::with_capacity(bytes[1] as usize);
+        let mut i = 2;
+        for _ in 0..bytes[1] {
+            let Some(&len) = bytes.get(i) else {
+                return Err(PacketError::BufferUnderflow(i));
+            };
+            i += 1;
+
+            if i + len as usize > bytes.len() {
+                return Err(PacketError::BufferUnderflow(i));
+            }
+            masks.push(String::from_utf8(bytes[i..i + len as usize].to_vec())?);
+            i += len as usize;
+        }
+
+        Ok(TalkersPacket { masks })
+    }
+}
+
+/// An f32 shared between the audio callbacks and the network thread
+pub struct AtomicF32(AtomicU32);
+
+impl AtomicF32 {
+    pub fn new(value: f32) -> Self {
+        Self(AtomicU32::new(value.to_bits()))
+    }
+
+    pub fn load(&self) -> f32 {
+        f32::from_bits(self.0.load(Ordering::Relaxed))
+    }
+
+    pub fn store(&self, value: f32) {
+        self.0.store(value.to_bits(), Ordering::Relaxed);
+    }
+}
+
+/// Whether `message` calls `nick` by name, as a whole word and with or without
+/// an `@`, ignoring case
+pub fn mentions(message: &str, nick: &str) -> bool {
+    let nick = nick.trim().to_lowercase();
+    !nick.is_empty()
+        && message
+            .to_lowercase()
+            .split(|c: char| !(c.is_alphanumeric() || c == '_' || c == '-'))
+            .any(|word| word == nick)
+}
@@ -0,0 +1,53 @@
+// Client side voice activity detection. Frames that don't carry speech are
+// never encoded or sent; the server already copes with a talker going quiet
+// since muted clients stop sending too.
+
+/// Frame RMS above which the mic counts as speaking
+pub const DEFAULT_VAD_THRESHOLD: f32 = 0.03;
+
+/// Frames still sent after speech stops, so word endings aren't clipped
+pub const VAD_HANGOVER_FRAMES: u32 = 15; // 300ms at 20ms frames
+
+/// Energy detector with a hangover
+pub struct Vad {
+    threshold: f32,
+    remaining: u32,
+}
+
+impl Vad {
+    pub fn new(threshold: f32) -> Self {
+        Self {
+            threshold,
+            remaining: 0,
+        }
+    }
+
+    pub fn threshold(&self) -> f32 {
+        self.threshold
+    }
+
+    pub fn set_threshold(&mut self, threshold: f32) {
+        self.threshold = threshold;
+    }
+
+    /// Whether `frame` should be sent
+    pub fn process(&mut self, frame: &[f32]) -> bool {
+        if rms(frame) >= self.threshold {
+            self.remaining = VAD_HANGOVER_FRAMES;
+            return true;
+        }
+
+        if self.remaining > 0 {
+            self.remaining -= 1;
+            return true;
+        }
+        false
+    }
+}
+
+pub fn rms(frame: &[f32]) -> f32 {
+    if frame.is_empty() {
+        return 0.0;
+    }
+    (frame.iter().map(|s| s * s).sum::<f32>() / frame.len() as f32).sqrt()
+}
@@ -0,0 +1,68 @@
+use voudp::aec::EchoCanceller;
+
+// deterministic white-ish noise, speech-like enough for the filter
+fn noise(len: usize, seed: u32) -> Vec<f32> {
+    let mut state = seed;
+    (0..len)
+        .map(|_| {
+            state = state.wrapping_mul(1664525).wrapping_add(1013904223);
+            (state >> 8) as f32 / (1 << 24) as f32 * 0.6 - 0.3
+        })
+        .collect()
+}
+
+fn power(buf: &[f32]) -> f32 {
+    buf.iter().map(|s| s * s).sum::<f32>() / buf.len() as f32
+}
+
+// the room: a short delay and some attenuation
+fn echo_of(far: &[f32]) -> Vec<f32> {
+    const DELAY: usize = 40;
+    (0..far.len())
+        .map(|n| n.checked_sub(DELAY).map_or(0.0, |m| 0.4 * far[m]))
+        .collect()
+}
+
+#[test]
+fn cancels_a_stable_echo() {
+    let mut aec = EchoCanceller::new(128);
+    aec.set_enabled(true);
+
+    let far = noise(48000 * 2, 7);
+    let echo = echo_of(&far);
+
+    let mut residual = vec![];
+    for (far, echo) in far.chunks(480).zip(echo.chunks(480)) {
+        // mono far end played out as stereo, mono mic
+        let played = far.iter().flat_map(|&s| [s, s]).collect::<Vec<_>>();
+        aec.playback(&played, 2);
+
+        let mut mic = echo.to_vec();
+        aec.capture(&mut mic, 1);
+        residual = mic;
+    }
+
+    // the last 10ms should be at least 20 dB quieter than the raw echo
+    let before = power(&echo[echo.len() - 480..]);
+    let after = power(&residual);
+    assert!(after < before / 100.0, "{after} vs {before}");
+}
+
+#[test]
+fn near_end_speech_passes_through() {
+    let mut aec = EchoCanceller::new(128);
+    aec.set_enabled(true);
+
+    // nothing is playing, so there is no echo to take out
+    let voice = noise(4800, 3);
+    let mut mic = voice.clone();
+    aec.capture(&mut mic, 1);
+    assert_eq!(mic, voice);
+
+    // off means untouched, even with audio playing
+    aec.set_enabled(false);
+    aec.playback(&noise(4800, 9), 1);
+    let mut mic = voice.clone();
+    aec.capture(&mut mic, 1);
+    assert_eq!(mic, voice);
+}
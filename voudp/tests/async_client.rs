@@ -0,0 +1,81 @@
+#![cfg(feature = "async")]
+
+use std::{net::SocketAddr, thread, time::Duration};
+
+use voudp::{
+    client::{AsyncClient, ClientEvent},
+    protocol,
+    server::{ServerConfig, ServerState},
+};
+
+const PHRASE: &[u8] = b"async-test";
+
+async fn wait_for(
+    client: &mut AsyncClient,
+    matches: impl Fn(&ClientEvent) -> bool,
+) -> Option<ClientEvent> {
+    let wait = async {
+        while let Some((event, _)) = client.next_event().await {
+            if matches(&event) {
+                return Some(event);
+            }
+        }
+        None
+    };
+
+    tokio::time::timeout(Duration::from_secs(5), wait)
+        .await
+        .ok()
+        .flatten()
+}
+
+#[test]
+fn async_client_chats_with_a_server() {
+    let server = ServerState::new(ServerConfig::default(), PHRASE).expect("server should bind");
+    let addr = SocketAddr::from(([127, 0, 0, 1], server.local_addr().port()));
+    thread::spawn(move || {
+        let mut server = server;
+        server.run();
+    });
+
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .unwrap();
+
+    runtime.block_on(async {
+        let mut client =
+            AsyncClient::connect(&addr.to_string(), protocol::DEFAULT_CHANNEL_ID, PHRASE)
+                .await
+                .unwrap();
+
+        match wait_for(&mut client, |e| {
+            matches!(e, ClientEvent::ListUpdated { .. })
+        })
+        .await
+        {
+            Some(ClientEvent::ListUpdated { current, .. }) => {
+                assert_eq!(current, protocol::DEFAULT_CHANNEL_ID);
+            }
+            other => panic!("expected a list update, got {other:?}"),
+        }
+
+        client.set_nick("bot").await.unwrap();
+        client.send_chat("beep boop").await.unwrap();
+
+        match wait_for(&mut client, |e| matches!(e, ClientEvent::Chat { .. })).await {
+            Some(ClientEvent::Chat {
+                from,
+                message,
+                is_self,
+            }) => {
+                assert_eq!(from, "bot");
+                assert_eq!(message, "beep boop");
+                assert!(is_self);
+            }
+            other => panic!("expected our chat back, got {other:?}"),
+        }
+
+        client.disconnect().await;
+    });
+}
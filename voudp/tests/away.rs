@@ -0,0 +1,161 @@
+use std::{net::SocketAddr, sync::Arc, thread, time::Duration};
+
+use chrono::Local;
+use voudp::{
+    away::{self, Away, AwaySettings, MAX_MISSED},
+    protocol::{self, ClientPacketType, FromPacket},
+    server::{ServerConfig, ServerState},
+    socket::{self, SecureUdpSocket},
+    storage::{FileStorage, Storage},
+    util::{CommandResponsePacket, CommandResult, FlowPacket},
+};
+
+mod common;
+
+use common::{recv_packet, temp_dir};
+
+const PHRASE: &[u8] = b"away-test";
+
+#[test]
+fn away_replies_once_per_sender_and_queues() {
+    let mut away = Away::new(None);
+
+    // nothing happens while present
+    assert_eq!(away.on_dm("bob", "hi", Local::now()), None);
+
+    away.set_away();
+    assert_eq!(
+        away.on_dm("bob", "you there?", Local::now()).as_deref(),
+        Some(away::DEFAULT_AWAY_MESSAGE)
+    );
+    assert_eq!(away.on_dm("bob", "hello??", Local::now()), None);
+    assert!(away.on_dm("carol", "lunch?", Local::now()).is_some());
+
+    let missed = away.back();
+    let summary = missed
+        .iter()
+        .map(|dm| (dm.from.as_str(), dm.message.as_str()))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        summary,
+        [
+            ("bob", "you there?"),
+            ("bob", "hello??"),
+            ("carol", "lunch?")
+        ]
+    );
+    assert!(!away.is_away());
+    assert!(away.back().is_empty());
+
+    // a new away period replies again
+    away.set_away();
+    assert!(away.on_dm("bob", "again", Local::now()).is_some());
+}
+
+#[test]
+fn missed_queue_is_bounded() {
+    let mut away = Away::new(None);
+    away.set_away();
+    for n in 0..MAX_MISSED + 10 {
+        away.on_dm(&format!("user-{n}"), "spam", Local::now());
+    }
+    assert_eq!(away.back().len(), MAX_MISSED);
+}
+
+#[test]
+fn settings_survive_a_restart() {
+    let storage: Arc<dyn Storage> = Arc::new(FileStorage::open(&temp_dir("away")));
+
+    let mut away = Away::new(Some(storage.clone()));
+    assert_eq!(away.settings(), &AwaySettings::default());
+    away.update_settings(AwaySettings {
+        message: "brb, walking the dog".into(),
+        chime: false,
+    });
+
+    let away = Away::new(Some(storage));
+    assert_eq!(away.settings().message, "brb, walking the dog");
+    assert!(!away.settings().chime);
+}
+
+#[test]
+fn whisper_packet_round_trip() {
+    let packet = protocol::create_whisper_packet("alice", "psst");
+    match FlowPacket::deserialize(&packet).unwrap() {
+        FlowPacket::Whisper { from, message } => {
+            assert_eq!(from, "alice");
+            assert_eq!(message, "psst");
+        }
+        other => panic!("expected a whisper, got {other:?}"),
+    }
+
+    assert!(FlowPacket::deserialize(&[ClientPacketType::Whisper as u8, 9, b'a']).is_err());
+    assert!(!away::chime(48000).is_empty());
+}
+
+#[test]
+fn server_delivers_whispers_across_channels() {
+    let server = ServerState::new(ServerConfig::default(), PHRASE).expect("server should bind");
+    let addr = SocketAddr::from(([127, 0, 0, 1], server.local_addr().port()));
+    thread::spawn(move || {
+        let mut server = server;
+        server.run();
+    });
+
+    let key = socket::derive_key_from_phrase(PHRASE, protocol::VOUDP_SALT);
+    let join = |channel: u32, mask: &str| {
+        let socket = SecureUdpSocket::create("127.0.0.1:0".into(), key).unwrap();
+        socket.connect(addr).unwrap();
+        let mut join = vec![ClientPacketType::Join as u8];
+        join.extend_from_slice(&channel.to_be_bytes());
+        socket.send(&join).unwrap();
+        let mut nick = vec![ClientPacketType::Mask as u8];
+        nick.extend_from_slice(mask.as_bytes());
+        socket.send(&nick).unwrap();
+        socket
+    };
+
+    let alice = join(protocol::DEFAULT_CHANNEL_ID, "alice");
+    let bob = join(3, "bob");
+    thread::sleep(Duration::from_millis(100));
+
+    let mut cmd = vec![ClientPacketType::Cmd as u8];
+    cmd.extend_from_slice(b"/w bob are you around?");
+    alice.send(&cmd).unwrap();
+
+    let whisper = recv_packet(&bob, ClientPacketType::Whisper as u8).expect("no whisper");
+    match FlowPacket::deserialize(&whisper).unwrap() {
+        FlowPacket::Whisper { from, message } => {
+            assert_eq!(from, "alice");
+            assert_eq!(message, "are you around?");
+        }
+        other => panic!("expected a whisper, got {other:?}"),
+    }
+
+    // the DM packet goes the same way, and nobody else hears either
+    let carol = join(protocol::DEFAULT_CHANNEL_ID, "carol");
+    thread::sleep(Duration::from_millis(100));
+    let mut dm = vec![ClientPacketType::Dm as u8, 3];
+    dm.extend_from_slice(b"bobstill there?");
+    alice.send(&dm).unwrap();
+    let whisper = recv_packet(&bob, ClientPacketType::Whisper as u8).expect("no DM");
+    assert!(matches!(
+        FlowPacket::deserialize(&whisper).unwrap(),
+        FlowPacket::Whisper { message, .. } if message == "still there?"
+    ));
+    assert!(recv_packet(&carol, ClientPacketType::Whisper as u8).is_none());
+
+    let result = || {
+        let reply = recv_packet(&alice, ClientPacketType::CommandResponse as u8)
+            .expect("no command response");
+        CommandResponsePacket::deserialize(&reply).unwrap().result
+    };
+    assert_eq!(
+        result(),
+        CommandResult::Success("-> bob: are you around?".into())
+    );
+    cmd.truncate(1);
+    cmd.extend_from_slice(b"/w dave hello");
+    alice.send(&cmd).unwrap();
+    assert_eq!(result(), CommandResult::Error("dave is not online".into()));
+}
@@ -0,0 +1,54 @@
+use std::io;
+
+use voudp::client::{AudioOptions, ClientBuilder};
+
+const ADDR: &str = "127.0.0.1:37549";
+const PHRASE: &[u8] = b"builder-test";
+
+#[test]
+fn builder_rejects_options_the_pipeline_cant_run() {
+    let rejected = [
+        ClientBuilder::new(ADDR, PHRASE).frame_ms(30),
+        ClientBuilder::new(ADDR, PHRASE).capture_channels(6),
+        ClientBuilder::new(ADDR, PHRASE).buffer_frames(0),
+        ClientBuilder::new(ADDR, PHRASE).bitrate(1_000_000),
+    ];
+
+    for builder in rejected {
+        let err = builder.build().err().expect("should be rejected");
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    }
+}
+
+#[test]
+fn builder_options_reach_the_client() {
+    let client = ClientBuilder::new(ADDR, PHRASE)
+        .channel(4)
+        .bitrate(32000)
+        .fec(false)
+        .frame_ms(40)
+        .dtx(true)
+        .capture_channels(1)
+        .buffer_frames(4)
+        .build()
+        .unwrap();
+
+    let audio = client.audio_options();
+    assert_eq!(
+        audio,
+        AudioOptions {
+            frame_ms: 40,
+            dtx: true,
+            capture_channels: Some(1),
+            buffer_frames: 4,
+        }
+    );
+    assert_eq!(audio.frame_size(), 1920);
+
+    // a fixed bitrate means there's nothing left for the probe to pick
+    let quality = *client.quality.lock().unwrap();
+    assert!(quality.manual);
+    assert_eq!(quality.profile.bitrate, 32000);
+    assert!(!quality.profile.fec);
+    assert_eq!(quality.profile.loss_perc, 0);
+}
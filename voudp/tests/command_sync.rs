@@ -0,0 +1,183 @@
+use std::{
+    net::SocketAddr,
+    thread,
+    time::{Duration, Instant},
+};
+
+use voudp::{
+    protocol::{self, ClientPacketType, FromPacket, IntoPacket},
+    server::{ServerConfig, ServerState},
+    socket::{self, SecureUdpSocket},
+    util::{
+        self, ArgKind, CommandArg, CommandCategory, CommandListPacket, CommandResponsePacket,
+        CommandResult, ServerCommand,
+    },
+};
+
+const PHRASE: &[u8] = b"command-sync-test";
+
+fn command(name: &str, usage: &str, aliases: &[&str]) -> ServerCommand {
+    ServerCommand {
+        name: name.into(),
+        description: String::new(),
+        usage: usage.into(),
+        category: CommandCategory::Utility,
+        aliases: aliases.iter().map(|a| a.to_string()).collect(),
+        requires_auth: false,
+        admin_only: false,
+        args: vec![],
+    }
+}
+
+#[test]
+fn commands_complete_by_name_and_alias() {
+    let commands = [
+        command("/whisper", "/whisper <user> <message>", &["/w", "/msg"]),
+        command("/who", "/who [channel]", &[]),
+        command("/note", "/note <add|list> <mask> [text]", &[]),
+    ];
+
+    let names = |prefix| {
+        util::complete_command(&commands, prefix)
+            .iter()
+            .map(|c| c.name.as_str())
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(names("/wh"), ["/whisper", "/who"]);
+    assert_eq!(names("ms"), ["/whisper"]);
+    assert_eq!(names("/"), ["/whisper", "/who", "/note"]);
+    assert!(names("/zzz").is_empty());
+
+    let found = util::find_command(&commands, "/MSG bob hi").unwrap();
+    assert_eq!(found.name, "/whisper");
+
+    // required arguments are caught before sending, optional ones aren't
+    assert_eq!(
+        util::usage_hint(&commands, "/w bob"),
+        Some("/whisper <user> <message>")
+    );
+    assert_eq!(util::usage_hint(&commands, "/w bob hi there"), None);
+    assert_eq!(util::usage_hint(&commands, "/who"), None);
+    assert_eq!(
+        util::usage_hint(&commands, "/note add"),
+        Some("/note <add|list> <mask> [text]")
+    );
+    // the server gets to judge commands we don't know
+    assert_eq!(util::usage_hint(&commands, "/unknown"), None);
+}
+
+#[test]
+fn command_arguments_are_checked_against_their_schema() {
+    let mut kick = command("/kick", "/kick <user> [reason]", &[]);
+    kick.args = vec![
+        CommandArg::required("user", ArgKind::User),
+        CommandArg::optional("reason", ArgKind::Text),
+    ];
+    let mut volume = command("/volume", "/volume <channel_id> <percent>", &[]);
+    volume.args = vec![
+        CommandArg::required("channel_id", ArgKind::Channel),
+        CommandArg::required("percent", ArgKind::Int),
+    ];
+
+    let words = |input: &str| {
+        input
+            .split_whitespace()
+            .map(String::from)
+            .collect::<Vec<_>>()
+    };
+    let online = |mask: &str| mask == "bob";
+    let check = |command: &ServerCommand, input| command.check_args(&words(input), online);
+
+    assert_eq!(check(&kick, "bob"), Ok(()));
+    assert_eq!(check(&kick, "bob way too loud"), Ok(()));
+    assert_eq!(
+        check(&kick, ""),
+        Err("missing user, usage: /kick <user> [reason]".into())
+    );
+    assert_eq!(check(&kick, "carol"), Err("carol is not online".into()));
+
+    assert_eq!(check(&volume, "3 50"), Ok(()));
+    assert_eq!(
+        check(&volume, "0 50"),
+        Err("0 isn't a valid channel id".into())
+    );
+    assert_eq!(
+        check(&volume, "3 loud"),
+        Err("percent must be a number, usage: /volume <channel_id> <percent>".into())
+    );
+    assert_eq!(
+        check(&volume, "3 50 60"),
+        Err("too many arguments, usage: /volume <channel_id> <percent>".into())
+    );
+
+    // no schema, the handler sorts it out
+    let who = command("/who", "/who [channel]", &[]);
+    assert_eq!(check(&who, "anything at all"), Ok(()));
+}
+
+#[test]
+fn command_responses_round_trip() {
+    for result in [
+        CommandResult::Success("moved bob to channel 5".into()),
+        CommandResult::Error("bob is not online".into()),
+        CommandResult::Silent,
+    ] {
+        let response = CommandResponsePacket {
+            command: "/move".into(),
+            result,
+        };
+        let bytes = response.serialize();
+        assert_eq!(bytes[0], ClientPacketType::CommandResponse as u8);
+        assert_eq!(
+            CommandResponsePacket::deserialize(&bytes).unwrap(),
+            response
+        );
+    }
+
+    // the command's length runs past the end
+    let bytes = [ClientPacketType::CommandResponse as u8, 0x01, 9, b'/'];
+    assert!(CommandResponsePacket::deserialize(&bytes).is_err());
+    let bytes = [ClientPacketType::CommandResponse as u8, 0x07, 0];
+    assert!(CommandResponsePacket::deserialize(&bytes).is_err());
+}
+
+#[test]
+fn server_syncs_its_commands() {
+    let server = ServerState::new(ServerConfig::default(), PHRASE).expect("server should bind");
+    let addr = SocketAddr::from(([127, 0, 0, 1], server.local_addr().port()));
+    thread::spawn(move || {
+        let mut server = server;
+        server.run();
+    });
+
+    let key = socket::derive_key_from_phrase(PHRASE, protocol::VOUDP_SALT);
+    let socket = SecureUdpSocket::create("127.0.0.1:0".into(), key).unwrap();
+    socket.connect(addr).unwrap();
+    let mut join = vec![ClientPacketType::Join as u8];
+    join.extend_from_slice(&protocol::DEFAULT_CHANNEL_ID.to_be_bytes());
+    socket.send(&join).unwrap();
+    socket
+        .send(&protocol::create_sync_commands_request())
+        .unwrap();
+
+    let deadline = Instant::now() + Duration::from_secs(3);
+    let mut buf = [0u8; 8192];
+    let commands = loop {
+        assert!(Instant::now() < deadline, "no command list");
+        match socket.recv_from(&mut buf) {
+            Ok((size, _)) if size > 0 && buf[0] == ClientPacketType::SyncCommands as u8 => {
+                break CommandListPacket::deserialize(&buf[1..size])
+                    .unwrap()
+                    .commands;
+            }
+            Ok(_) => {}
+            Err(_) => thread::sleep(Duration::from_millis(2)),
+        }
+    };
+
+    let whisper = util::find_command(&commands, "/w").expect("whisper is synced");
+    assert_eq!(whisper.usage, "/whisper <user> <message>");
+    assert!(whisper.aliases.contains(&"/tell".to_string()));
+    // we're no admin
+    assert!(util::find_command(&commands, "/monitor").is_none());
+}
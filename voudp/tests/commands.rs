@@ -0,0 +1,361 @@
+use std::{net::SocketAddr, sync::Arc, thread, time::Duration};
+
+use voudp::{
+    bans::BanList,
+    console_msg::{ConsoleMessage, ConsoleMessageKind},
+    protocol::{self, ClientPacketType, ConsoleRole, FromPacket, MessageCode},
+    server::{ServerConfig, ServerState},
+    socket::{self, SecureUdpSocket},
+    storage::{FileStorage, Storage},
+    util::{self, CommandResponsePacket, CommandResult, FlowPacket, LocalizedMessage},
+};
+
+mod common;
+
+use common::{join, recv_packet, temp_dir};
+
+const PHRASE: &[u8] = b"commands-test";
+
+// the first notice with `code`, skipping the others
+fn recv_notice(socket: &SecureUdpSocket, code: MessageCode) -> Option<LocalizedMessage> {
+    loop {
+        let notice = recv_packet(socket, ClientPacketType::Notice as u8)?;
+        let notice = LocalizedMessage::deserialize(&notice).unwrap();
+        if notice.message_code() == Some(code) {
+            return Some(notice);
+        }
+    }
+}
+
+// flow packets of type `ty` until they stop coming
+fn flows(socket: &SecureUdpSocket, ty: ClientPacketType) -> impl Iterator<Item = FlowPacket> + '_ {
+    std::iter::from_fn(move || recv_packet(socket, ty as u8))
+        .map(|packet| FlowPacket::deserialize(&packet).unwrap())
+}
+
+// typed into the chat box, the way users run commands
+fn respond(socket: &SecureUdpSocket, input: &str) -> CommandResponsePacket {
+    let mut packet = vec![ClientPacketType::Chat as u8];
+    packet.extend_from_slice(input.as_bytes());
+    socket.send(&packet).unwrap();
+
+    let reply =
+        recv_packet(socket, ClientPacketType::CommandResponse as u8).expect("no command response");
+    CommandResponsePacket::deserialize(&reply).unwrap()
+}
+
+fn chat(socket: &SecureUdpSocket, input: &str) -> CommandResult {
+    respond(socket, input).result
+}
+
+// makes `mask` an admin through the console
+fn op(console: &SecureUdpSocket, server: SocketAddr, mask: &str) {
+    let token =
+        util::console_login(console, server, protocol::PASSWORD, ConsoleRole::Admin).unwrap();
+    let op = protocol::create_console_command(&token, &format!("op {mask}"));
+    console.send_to(&op, server).unwrap();
+    thread::sleep(Duration::from_millis(50));
+}
+
+// a console command and the console's answer
+fn console_run(
+    console: &SecureUdpSocket,
+    server: SocketAddr,
+    token: &[u8],
+    cmd: &str,
+) -> ConsoleMessage {
+    let packet = protocol::create_console_command(token, cmd);
+    console.send_to(&packet, server).unwrap();
+    let reply = recv_packet(console, b'{').expect("no console reply");
+    ConsoleMessage::parse(&String::from_utf8(reply).unwrap()).unwrap()
+}
+
+fn success(result: CommandResult) -> String {
+    match result {
+        CommandResult::Success(reply) => reply,
+        other => panic!("expected a success, got {other:?}"),
+    }
+}
+
+#[test]
+fn chat_commands_run_and_admins_can_kick_and_ban() {
+    let storage: Arc<dyn Storage> = Arc::new(FileStorage::open(&temp_dir("commands")));
+    let config = ServerConfig {
+        max_joins_per_sec: 0,
+        ..Default::default()
+    };
+    let server = ServerState::with_storage(config, PHRASE, storage.clone()).unwrap();
+    let addr = SocketAddr::from(([127, 0, 0, 1], server.local_addr().port()));
+    thread::spawn(move || {
+        let mut server = server;
+        server.run();
+    });
+
+    let key = socket::derive_key_from_phrase(PHRASE, protocol::VOUDP_SALT);
+    let new_socket = || SecureUdpSocket::create("127.0.0.1:0".into(), key).unwrap();
+    let alice = new_socket();
+    join(&alice, addr, protocol::DEFAULT_CHANNEL_ID, Some("alice"));
+    let bob = new_socket();
+    join(&bob, addr, 2, Some("bob"));
+
+    assert_eq!(success(chat(&alice, "/ping")), "pong");
+    // aliases come back under the command's name
+    let list = respond(&alice, "/ls");
+    assert_eq!(list.command, "/list");
+    let list = success(list.result);
+    assert!(list.contains("alice") && list.contains("bob"), "{list}");
+    let info = success(chat(&alice, "/serverinfo"));
+    assert!(info.starts_with("voudp v"), "{info}");
+    assert!(info.contains("users: 2/1024 in "), "{info}");
+    let stats = success(chat(&alice, "/stats"));
+    assert!(
+        stats.starts_with("tick: ") && stats.contains("headroom"),
+        "{stats}"
+    );
+    assert!(
+        stats.lines().any(|line| line.starts_with("received: ")),
+        "{stats}"
+    );
+
+    assert_eq!(
+        respond(&alice, "/nope"),
+        CommandResponsePacket {
+            command: "/nope".into(),
+            result: CommandResult::Error(
+                "Unknown command. Type /help for available commands.".into()
+            ),
+        }
+    );
+
+    // admin commands stay out of everyone else's help
+    let help = success(chat(&alice, "/help"));
+    assert!(help.contains("/ping") && !help.contains("/ban"), "{help}");
+    assert!(success(chat(&alice, "/help w")).starts_with("/whisper <user> <message>"));
+    assert_eq!(
+        chat(&alice, "/kick bob"),
+        CommandResult::Error("Permission denied: /kick is for admins only".into())
+    );
+    assert_eq!(
+        chat(&alice, "/whisper"),
+        CommandResult::Error("missing user, usage: /whisper <user> <message>".into())
+    );
+    assert_eq!(
+        chat(&alice, "/whisper nobody hi"),
+        CommandResult::Error("nobody is not online".into())
+    );
+    assert_eq!(
+        chat(&alice, "/join 0"),
+        CommandResult::Error("0 isn't a valid channel id".into())
+    );
+
+    assert_eq!(
+        chat(&alice, "/dox bob"),
+        CommandResult::Error("Permission denied: /dox is for admins only".into())
+    );
+    assert_eq!(
+        chat(&alice, "/broadcast hello"),
+        CommandResult::Error("Permission denied: /broadcast is for admins only".into())
+    );
+    assert_eq!(
+        chat(&alice, "/note list bob"),
+        CommandResult::Error("Permission denied: /note is for admins only".into())
+    );
+
+    op(&new_socket(), addr, "alice");
+
+    assert_eq!(
+        success(chat(&alice, "/note add bob warned")),
+        "noted about bob"
+    );
+    assert!(success(chat(&alice, "/note list bob")).contains("alice: warned"));
+    assert_eq!(success(chat(&alice, "/kick bob too loud")), "kicked bob");
+    let notice = recv_notice(&bob, MessageCode::Kicked).expect("bob was not told");
+    assert_eq!(notice.params, ["alice", "too loud"]);
+    assert!(recv_packet(&bob, ClientPacketType::Kick as u8).is_some());
+
+    // a kick isn't a ban, bob gets back in
+    join(&bob, addr, 2, Some("bob"));
+    assert!(success(chat(&alice, "/ban bob spam")).starts_with("banned bob"));
+    assert!(recv_packet(&bob, ClientPacketType::Kick as u8).is_some());
+    let carol = new_socket();
+    join(&carol, addr, 2, Some("carol"));
+    let notice = recv_notice(&carol, MessageCode::Banned).expect("the ban didn't hold");
+    assert_eq!(notice.params, ["spam"]);
+
+    // it outlives the server
+    let bans = BanList::new(storage);
+    let ip = bans.find("bob").expect("the ban wasn't stored");
+    assert_eq!(bans.get(ip).unwrap().reason, "spam");
+
+    assert!(success(chat(&alice, "/unban bob")).contains("no longer banned"));
+    let dave = new_socket();
+    join(&dave, addr, 2, Some("dave"));
+    assert!(recv_notice(&dave, MessageCode::ChannelMoved).is_some());
+
+    assert_eq!(success(chat(&alice, "/nick alicia")), "you are now alicia");
+    assert!(matches!(chat(&alice, "/join 3"), CommandResult::Silent));
+    assert_eq!(success(chat(&alice, "/whoami")), "You are @alicia in #test");
+}
+
+#[test]
+fn admins_move_users_between_channels() {
+    let storage: Arc<dyn Storage> = Arc::new(FileStorage::open(&temp_dir("commands")));
+    let config = ServerConfig {
+        max_joins_per_sec: 0,
+        ..Default::default()
+    };
+    let server = ServerState::with_storage(config, PHRASE, storage).unwrap();
+    let addr = SocketAddr::from(([127, 0, 0, 1], server.local_addr().port()));
+    thread::spawn(move || {
+        let mut server = server;
+        server.run();
+    });
+
+    let key = socket::derive_key_from_phrase(PHRASE, protocol::VOUDP_SALT);
+    let new_socket = || SecureUdpSocket::create("127.0.0.1:0".into(), key).unwrap();
+    let alice = new_socket();
+    join(&alice, addr, protocol::DEFAULT_CHANNEL_ID, Some("alice"));
+    let bob = new_socket();
+    join(&bob, addr, 2, Some("bob"));
+    let carol = new_socket();
+    join(&carol, addr, 2, Some("carol"));
+    let dave = new_socket();
+    join(&dave, addr, 5, Some("dave"));
+
+    assert_eq!(
+        chat(&bob, "/move carol 5"),
+        CommandResult::Error("Permission denied: /move is for admins only".into())
+    );
+    op(&new_socket(), addr, "alice");
+    assert_eq!(
+        chat(&alice, "/move bob 2"),
+        CommandResult::Error("bob is already in channel 2".into())
+    );
+    assert_eq!(success(chat(&alice, "/mv bob 5")), "moved bob to channel 5");
+
+    assert!(recv_notice(&bob, MessageCode::ChannelMoved).is_some());
+    // both channels hear about it
+    assert!(
+        flows(&carol, ClientPacketType::FlowLeave)
+            .any(|flow| matches!(flow, FlowPacket::Leave(mask) if mask == "bob"))
+    );
+    assert!(
+        flows(&dave, ClientPacketType::FlowJoin)
+            .any(|flow| matches!(flow, FlowPacket::Join(mask) if mask == "bob"))
+    );
+}
+
+#[test]
+fn consoles_kick_ban_mute_move_and_announce() {
+    let storage: Arc<dyn Storage> = Arc::new(FileStorage::open(&temp_dir("commands")));
+    let config = ServerConfig {
+        max_joins_per_sec: 0,
+        ..Default::default()
+    };
+    let server = ServerState::with_storage(config, PHRASE, storage.clone()).unwrap();
+    let addr = SocketAddr::from(([127, 0, 0, 1], server.local_addr().port()));
+    thread::spawn(move || {
+        let mut server = server;
+        server.run();
+    });
+
+    let key = socket::derive_key_from_phrase(PHRASE, protocol::VOUDP_SALT);
+    let new_socket = || SecureUdpSocket::create("127.0.0.1:0".into(), key).unwrap();
+    let alice = new_socket();
+    join(&alice, addr, protocol::DEFAULT_CHANNEL_ID, Some("alice"));
+    let bob = new_socket();
+    join(&bob, addr, 2, Some("bob"));
+    let carol = new_socket();
+    join(&carol, addr, 2, Some("carol"));
+
+    let console = new_socket();
+    let token =
+        util::console_login(&console, addr, protocol::PASSWORD, ConsoleRole::Admin).unwrap();
+    let run = |cmd: &str| {
+        let reply = console_run(&console, addr, &token, cmd);
+        (reply.kind, reply.text().to_string())
+    };
+
+    assert_eq!(
+        run("kick nobody"),
+        (ConsoleMessageKind::Error, "nobody is not connected".into())
+    );
+
+    run("say 2 restarting at noon");
+    let said = recv_notice(&carol, MessageCode::Custom).expect("carol heard nothing");
+    assert_eq!(said.params, ["restarting at noon"]);
+    assert!(recv_notice(&alice, MessageCode::Custom).is_none());
+
+    assert_eq!(
+        run("mute bob"),
+        (ConsoleMessageKind::Reply, "muted bob".into())
+    );
+    assert!(recv_notice(&bob, MessageCode::ServerMuted).is_some());
+
+    assert_eq!(
+        run("move carol 2"),
+        (
+            ConsoleMessageKind::Error,
+            "carol is already in channel 2".into()
+        )
+    );
+    run("move carol general");
+    assert!(recv_notice(&carol, MessageCode::ChannelMoved).is_some());
+    assert!(
+        flows(&alice, ClientPacketType::FlowJoin)
+            .any(|flow| matches!(flow, FlowPacket::Join(mask) if mask == "carol"))
+    );
+
+    // deleting a channel tells whoever was in it where they ended up
+    run("del 2");
+    assert!(recv_notice(&bob, MessageCode::ChannelMoved).is_some());
+
+    run("kick carol too loud");
+    let kicked = recv_notice(&carol, MessageCode::Kicked).expect("carol wasn't kicked");
+    assert_eq!(kicked.params, ["console", "too loud"]);
+    assert!(recv_packet(&carol, ClientPacketType::Kick as u8).is_some());
+
+    run("ban bob spam");
+    assert!(recv_notice(&bob, MessageCode::Banned).is_some());
+    let bans = BanList::new(storage);
+    let ip = bans.find("bob").expect("the ban wasn't stored");
+    assert_eq!(bans.get(ip).unwrap().reason, "spam");
+}
+
+#[test]
+fn deleting_a_channel_moves_its_users_to_the_default_one() {
+    let server = ServerState::new(ServerConfig::default(), PHRASE).unwrap();
+    let addr = SocketAddr::from(([127, 0, 0, 1], server.local_addr().port()));
+    thread::spawn(move || {
+        let mut server = server;
+        server.run();
+    });
+
+    let key = socket::derive_key_from_phrase(PHRASE, protocol::VOUDP_SALT);
+    let new_socket = || SecureUdpSocket::create("127.0.0.1:0".into(), key).unwrap();
+    let alice = new_socket();
+    join(&alice, addr, protocol::DEFAULT_CHANNEL_ID, Some("alice"));
+    let bob = new_socket();
+    join(&bob, addr, 3, Some("bob"));
+
+    let console = new_socket();
+    let token =
+        util::console_login(&console, addr, protocol::PASSWORD, ConsoleRole::Admin).unwrap();
+    let deleted = console_run(&console, addr, &token, "del test");
+    assert_eq!(
+        deleted.text(),
+        "deleted channel 'test' (id 3) and moved users to default"
+    );
+
+    // past the notice from joining #test
+    assert!(
+        std::iter::from_fn(|| recv_notice(&bob, MessageCode::ChannelMoved))
+            .any(|moved| moved.params == ["general"])
+    );
+    assert!(
+        flows(&alice, ClientPacketType::FlowJoin)
+            .any(|flow| matches!(flow, FlowPacket::Join(mask) if mask == "bob"))
+    );
+    let inspected = console_run(&console, addr, &token, "inspect bob");
+    assert!(inspected.text().starts_with("bob is in general (1)"));
+}
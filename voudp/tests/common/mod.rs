@@ -0,0 +1,82 @@
+// Helpers the integration tests share, each test only uses some of them
+#![allow(dead_code)]
+
+use std::{
+    net::SocketAddr,
+    path::PathBuf,
+    thread,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use voudp::{console_msg::ConsoleMessage, protocol::ClientPacketType, socket::SecureUdpSocket};
+
+// a fresh directory name for each call, nothing is created
+pub fn temp_dir(name: &str) -> PathBuf {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_nanos();
+    std::env::temp_dir().join(format!("voudp-{name}-{}-{nanos}", std::process::id()))
+}
+
+// the first packet of type `ty` within a few seconds, skipping the others
+pub fn recv_packet(socket: &SecureUdpSocket, ty: u8) -> Option<Vec<u8>> {
+    let deadline = Instant::now() + Duration::from_secs(3);
+    let mut buf = [0u8; 8192];
+
+    while Instant::now() < deadline {
+        match socket.recv_from(&mut buf) {
+            Ok((size, _)) if size > 0 && buf[0] == ty => return Some(buf[..size].to_vec()),
+            Ok(_) => {}
+            Err(_) => thread::sleep(Duration::from_millis(5)),
+        }
+    }
+
+    None
+}
+
+// connects `socket` and joins `channel`, nicked `mask` when there is one
+pub fn join(socket: &SecureUdpSocket, server: SocketAddr, channel: u32, mask: Option<&str>) {
+    socket.connect(server).unwrap();
+
+    let mut join = vec![ClientPacketType::Join as u8];
+    join.extend_from_slice(&channel.to_be_bytes());
+    socket.send(&join).unwrap();
+    if let Some(mask) = mask {
+        let mut nick = vec![ClientPacketType::Mask as u8];
+        nick.extend_from_slice(mask.as_bytes());
+        socket.send(&nick).unwrap();
+    }
+    thread::sleep(Duration::from_millis(50));
+}
+
+// a new client in `channel`, see `join`
+pub fn joined(
+    key: chacha20poly1305::Key,
+    server: SocketAddr,
+    channel: u32,
+    mask: Option<&str>,
+) -> SecureUdpSocket {
+    let socket = SecureUdpSocket::create("127.0.0.1:0".into(), key).unwrap();
+    join(&socket, server, channel, mask);
+    socket
+}
+
+// the next console message within a second, resending unacked ones meanwhile
+pub fn message(socket: &SecureUdpSocket) -> Option<ConsoleMessage> {
+    let deadline = Instant::now() + Duration::from_secs(1);
+    let mut buf = [0u8; 8192];
+
+    while Instant::now() < deadline {
+        socket.tick_reliable();
+        match socket.recv_from(&mut buf) {
+            Ok((size, _)) if size > 0 => {
+                return ConsoleMessage::parse(&String::from_utf8_lossy(&buf[..size])).ok();
+            }
+            Ok(_) => {}
+            Err(_) => thread::sleep(Duration::from_millis(5)),
+        }
+    }
+
+    None
+}
@@ -0,0 +1,270 @@
+use std::{
+    io,
+    net::SocketAddr,
+    thread,
+    time::{Duration, Instant},
+};
+
+use voudp::{
+    console_msg::ConsoleMessageKind,
+    protocol::{self, ClientPacketType, ConsoleAuth, ConsoleRole},
+    server::{ServerConfig, ServerState},
+    socket::{self, SecureUdpSocket},
+    util,
+};
+
+mod common;
+
+use common::message;
+
+const PHRASE: &[u8] = b"console-auth-test";
+
+fn recv(socket: &SecureUdpSocket) -> Option<Vec<u8>> {
+    let deadline = Instant::now() + Duration::from_secs(1);
+    let mut buf = [0u8; 2048];
+
+    while Instant::now() < deadline {
+        match socket.recv_from(&mut buf) {
+            Ok((size, _)) if size > 0 => return Some(buf[..size].to_vec()),
+            Ok(_) => {}
+            Err(_) => thread::sleep(Duration::from_millis(5)),
+        }
+    }
+
+    None
+}
+
+#[test]
+fn consoles_log_in_with_a_challenge_and_a_session_token() {
+    let server = ServerState::new(ServerConfig::default(), PHRASE).expect("server should bind");
+    let addr = SocketAddr::from(([127, 0, 0, 1], server.local_addr().port()));
+    thread::spawn(move || {
+        let mut server = server;
+        server.run();
+    });
+
+    let key = socket::derive_key_from_phrase(PHRASE, protocol::VOUDP_SALT);
+    let new_socket = || SecureUdpSocket::create("127.0.0.1:0".into(), key).unwrap();
+
+    let intruder = new_socket();
+    let err = util::console_login(&intruder, addr, "hunter2", ConsoleRole::Admin).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::PermissionDenied);
+    // the old plaintext login is gone
+    let mut plaintext = vec![ClientPacketType::RegisterConsole as u8];
+    plaintext.extend_from_slice(protocol::PASSWORD.as_bytes());
+    intruder.send_to(&plaintext, addr).unwrap();
+    let ping = protocol::create_console_command(&[0; protocol::CONSOLE_TOKEN_LEN], "ping");
+    intruder.send_to(&ping, addr).unwrap();
+    assert_eq!(recv(&intruder), None);
+
+    let console = new_socket();
+    let token =
+        util::console_login(&console, addr, protocol::PASSWORD, ConsoleRole::Admin).unwrap();
+    assert_eq!(token.len(), protocol::CONSOLE_TOKEN_LEN);
+    console
+        .send_to(&protocol::create_console_command(&token, "ping"), addr)
+        .unwrap();
+    let pong = message(&console).unwrap();
+    assert_eq!(
+        (pong.kind, pong.text()),
+        (ConsoleMessageKind::Reply, "pong")
+    );
+    // keepalives are answered, that's how consoles notice a dead server
+    console
+        .send_to(&[protocol::ConsolePacketType::Keepalive as u8], addr)
+        .unwrap();
+    assert_eq!(
+        message(&console).unwrap().kind,
+        ConsoleMessageKind::Keepalive
+    );
+
+    // commands without the token are dropped
+    let mut forged = token.clone();
+    forged[0] ^= 0xff;
+    console
+        .send_to(&protocol::create_console_command(&forged, "ping"), addr)
+        .unwrap();
+    assert_eq!(recv(&console), None);
+
+    // a proof is only good for the nonce it answered
+    let replayer = new_socket();
+    replayer
+        .send_reliable(protocol::create_console_auth(ConsoleAuth::Hello, &[]), addr)
+        .unwrap();
+    let challenge = recv(&replayer).unwrap();
+    let proof = protocol::console_proof(protocol::PASSWORD, &challenge[2..]);
+    let answer = protocol::create_console_auth(ConsoleAuth::Proof, &proof);
+    replayer.send_reliable(answer.clone(), addr).unwrap();
+    let session = recv(&replayer).unwrap();
+    assert_eq!(session[1], ConsoleAuth::Session as u8);
+
+    let replayed = new_socket();
+    replayed.send_to(&answer, addr).unwrap();
+    assert_eq!(
+        recv(&replayed),
+        Some(protocol::create_console_auth(ConsoleAuth::Denied, &[]))
+    );
+}
+
+#[test]
+fn observers_and_admins_stay_attached_side_by_side() {
+    let server = ServerState::new(ServerConfig::default(), PHRASE).expect("server should bind");
+    let addr = SocketAddr::from(([127, 0, 0, 1], server.local_addr().port()));
+    thread::spawn(move || {
+        let mut server = server;
+        server.run();
+    });
+
+    let key = socket::derive_key_from_phrase(PHRASE, protocol::VOUDP_SALT);
+    let new_socket = || SecureUdpSocket::create("127.0.0.1:0".into(), key).unwrap();
+    let run = |console: &SecureUdpSocket, token: &[u8], cmd: &str| {
+        console
+            .send_to(&protocol::create_console_command(token, cmd), addr)
+            .unwrap();
+        message(console).expect("no reply")
+    };
+
+    let dashboard = new_socket();
+    let watching =
+        util::console_login(&dashboard, addr, protocol::PASSWORD, ConsoleRole::Observer).unwrap();
+    let operator = new_socket();
+    let operating =
+        util::console_login(&operator, addr, protocol::PASSWORD, ConsoleRole::Admin).unwrap();
+
+    assert_eq!(run(&dashboard, &watching, "ping").text(), "pong");
+    let denied = run(&dashboard, &watching, "create 42");
+    assert_eq!(denied.kind, ConsoleMessageKind::Error);
+    assert!(
+        denied
+            .text()
+            .starts_with("Permission denied: create needs an admin console"),
+    );
+    assert_eq!(
+        run(&dashboard, &watching, "op bob").kind,
+        ConsoleMessageKind::Error
+    );
+    assert_eq!(
+        run(&operator, &operating, "create 42").kind,
+        ConsoleMessageKind::Reply
+    );
+    assert!(run(&dashboard, &watching, "chans").text().contains("42"));
+    // failures are errors too, so scripts can stop on them
+    let missing = run(&operator, &operating, "del 9999");
+    assert_eq!(
+        (missing.kind, missing.text()),
+        (ConsoleMessageKind::Error, "channel not found")
+    );
+    // what the console completes from
+    let listed = run(&dashboard, &watching, "commands");
+    let observing = listed
+        .get("commands")
+        .unwrap()
+        .split(' ')
+        .collect::<Vec<_>>();
+    assert!(observing.contains(&"chans") && !observing.contains(&"create"));
+    let listed = run(&operator, &operating, "commands");
+    assert!(
+        listed
+            .get("commands")
+            .unwrap()
+            .split(' ')
+            .any(|cmd| cmd == "create")
+    );
+
+    let sessions = run(&dashboard, &watching, "sessions").text().to_string();
+    assert_eq!(sessions.lines().count(), 2, "{sessions}");
+    assert!(
+        sessions
+            .lines()
+            .any(|line| line.contains("Observer") && line.ends_with("(you)")),
+        "{sessions}"
+    );
+    assert!(sessions.contains("Admin"), "{sessions}");
+
+    // one leaving doesn't take the other with it
+    dashboard
+        .send_to(&[protocol::ConsolePacketType::Eof as u8], addr)
+        .unwrap();
+    thread::sleep(Duration::from_millis(50));
+    let sessions = run(&operator, &operating, "sessions").text().to_string();
+    assert_eq!(sessions.lines().count(), 1, "{sessions}");
+}
+
+#[test]
+fn consoles_get_the_events_they_subscribe_to() {
+    let server = ServerState::new(ServerConfig::default(), PHRASE).expect("server should bind");
+    let addr = SocketAddr::from(([127, 0, 0, 1], server.local_addr().port()));
+    thread::spawn(move || {
+        let mut server = server;
+        server.run();
+    });
+
+    let key = socket::derive_key_from_phrase(PHRASE, protocol::VOUDP_SALT);
+    let new_socket = || SecureUdpSocket::create("127.0.0.1:0".into(), key).unwrap();
+    let dashboard = new_socket();
+    let token =
+        util::console_login(&dashboard, addr, protocol::PASSWORD, ConsoleRole::Observer).unwrap();
+    let run = |cmd: &str| {
+        dashboard
+            .send_to(&protocol::create_console_command(&token, cmd), addr)
+            .unwrap();
+        message(&dashboard).expect("no reply")
+    };
+    let event = || message(&dashboard);
+
+    assert_eq!(run("subscribe").text(), "subscribed to nothing");
+    let bogus = run("subscribe joins bogus");
+    assert_eq!(bogus.kind, ConsoleMessageKind::Error);
+    assert!(bogus.text().starts_with("unknown event 'bogus'"));
+    assert_eq!(
+        run("subscribe all").text(),
+        "subscribed to joins, leaves, chat, kicks, audio-errors"
+    );
+    assert_eq!(
+        run("unsubscribe leaves audio-errors").text(),
+        "subscribed to joins, chat, kicks"
+    );
+
+    let alice = new_socket();
+    alice.connect(addr).unwrap();
+    let mut join = vec![ClientPacketType::Join as u8];
+    join.extend_from_slice(&2u32.to_be_bytes());
+    alice.send(&join).unwrap();
+    let joined = event().expect("no join event");
+    assert_eq!(
+        joined.kind,
+        ConsoleMessageKind::Event(protocol::ConsoleEvent::Joins)
+    );
+    assert_eq!(
+        joined.get("addr"),
+        Some(alice.local_addr().to_string().as_str())
+    );
+    assert_eq!(
+        (joined.get("channel"), joined.get("mask")),
+        (Some("2"), Some(""))
+    );
+
+    let mut mask = vec![ClientPacketType::Mask as u8];
+    mask.extend_from_slice(b"alice");
+    alice.send(&mask).unwrap();
+    let mut chat = vec![ClientPacketType::Chat as u8];
+    chat.extend_from_slice(b"hello there");
+    alice.send(&chat).unwrap();
+    let said = event().expect("no chat event");
+    assert_eq!(
+        said.kind,
+        ConsoleMessageKind::Event(protocol::ConsoleEvent::Chat)
+    );
+    assert_eq!(
+        said.payload,
+        [
+            ("channel".to_string(), "2".to_string()),
+            ("mask".into(), "alice".into()),
+            ("text".into(), "hello there".into()),
+        ]
+    );
+
+    // leaves were unsubscribed
+    alice.send(&[ClientPacketType::Eof as u8]).unwrap();
+    assert_eq!(event(), None);
+}
@@ -0,0 +1,36 @@
+use voudp::{
+    console_msg::{ConsoleMessage, ConsoleMessageKind},
+    protocol::ConsoleEvent,
+};
+
+#[test]
+fn console_messages_survive_a_round_trip() {
+    let reply = ConsoleMessage::reply("tick: 1ms\nsent: \"42\" \\ \u{1} ünïcode");
+    let json = reply.to_json();
+    assert!(!json.contains('\n'), "{json}");
+    assert_eq!(ConsoleMessage::parse(&json).unwrap(), reply);
+
+    let event = ConsoleMessage::event(
+        ConsoleEvent::Kicks,
+        &[("mask", "bob".into()), ("text", "too loud".into())],
+    );
+    let parsed = ConsoleMessage::parse(&event.to_json()).unwrap();
+    assert_eq!(parsed.kind, ConsoleMessageKind::Event(ConsoleEvent::Kicks));
+    assert_eq!(parsed.get("text"), Some("too loud"));
+
+    let written =
+        ConsoleMessage::parse(r#" { "payload": {}, "type": "error" , "time": 1700000000000 } "#)
+            .unwrap();
+    assert_eq!(written.kind, ConsoleMessageKind::Error);
+    assert_eq!(written.time, 1_700_000_000_000);
+    assert_eq!(written.text(), "");
+}
+
+#[test]
+fn console_messages_reject_what_they_dont_understand() {
+    assert!(ConsoleMessage::parse("pong").is_err());
+    assert!(ConsoleMessage::parse(r#"{"type":"reply","time":1}"#).is_err());
+    assert!(ConsoleMessage::parse(r#"{"type":"gossip","time":1,"payload":{}}"#).is_err());
+    assert!(ConsoleMessage::parse(r#"{"type":"reply","time":1,"payload":{"text":3}}"#).is_err());
+    assert!(ConsoleMessage::parse(r#"{"type":"reply","time":1,"payload":{}} extra"#).is_err());
+}
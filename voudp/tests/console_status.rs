@@ -0,0 +1,82 @@
+use std::{net::SocketAddr, thread, time::Duration};
+
+use opus2::{Application, Channels, Encoder};
+use voudp::{
+    console_msg::ConsoleMessage,
+    protocol::{self, ClientPacketType, ConsoleRole},
+    server::{ServerConfig, ServerState},
+    socket::{self, SecureUdpSocket},
+    util,
+};
+
+mod common;
+
+use common::message;
+
+const PHRASE: &[u8] = b"console-status-test";
+
+fn field<'a>(row: &'a [(String, String)], key: &str) -> &'a str {
+    row.iter()
+        .find(|(got, _)| got == key)
+        .map_or("", |(_, value)| value)
+}
+
+#[test]
+fn status_tabulates_channels_and_what_users_send() {
+    let server = ServerState::new(ServerConfig::default(), PHRASE).expect("server should bind");
+    let addr = SocketAddr::from(([127, 0, 0, 1], server.local_addr().port()));
+    thread::spawn(move || {
+        let mut server = server;
+        server.run();
+    });
+
+    let key = socket::derive_key_from_phrase(PHRASE, protocol::VOUDP_SALT);
+    let new_socket = || SecureUdpSocket::create("127.0.0.1:0".into(), key).unwrap();
+    let alice = new_socket();
+    alice.connect(addr).unwrap();
+    let mut join = vec![ClientPacketType::Join as u8];
+    join.extend_from_slice(&2u32.to_be_bytes());
+    alice.send(&join).unwrap();
+    let mut mask = vec![ClientPacketType::Mask as u8];
+    mask.extend_from_slice(b"alice");
+    alice.send(&mask).unwrap();
+
+    // half of it in frames of the wrong size, which the server drops
+    let mut encoder = Encoder::new(48000, Channels::Stereo, Application::Audio).unwrap();
+    let mut opus = [0u8; 1500];
+    for frame in [960, 480].repeat(10) {
+        let len = encoder
+            .encode_float(&vec![0.0; frame * 2], &mut opus)
+            .unwrap();
+        alice
+            .send(&protocol::create_audio_packet(&opus[..len]))
+            .unwrap();
+        thread::sleep(Duration::from_millis(10));
+    }
+    // rates cover the last full second
+    thread::sleep(Duration::from_millis(1100));
+
+    let dashboard = new_socket();
+    let token =
+        util::console_login(&dashboard, addr, protocol::PASSWORD, ConsoleRole::Observer).unwrap();
+    dashboard
+        .send_to(&protocol::create_console_command(&token, "status"), addr)
+        .unwrap();
+    let status = message(&dashboard).expect("no status");
+    assert!(status.text().contains("alice"), "{}", status.text());
+
+    let channel = status
+        .rows
+        .iter()
+        .position(|row| field(row, "row") == "channel" && field(row, "channel") == "2")
+        .expect("channel 2 isn't listed");
+    assert_eq!(field(&status.rows[channel], "users"), "1");
+    // users come right after their channel
+    let user = &status.rows[channel + 1];
+    assert_eq!((field(user, "row"), field(user, "mask")), ("user", "alice"));
+    assert!(field(user, "kbps").parse::<f32>().unwrap() > 0.0);
+    assert_eq!(field(user, "loss"), "50.0");
+
+    // and it survives the trip
+    assert_eq!(ConsoleMessage::parse(&status.to_json()).unwrap(), status);
+}
@@ -0,0 +1,100 @@
+#![cfg(feature = "tls-console")]
+
+use std::{
+    io,
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    thread,
+    time::Duration,
+};
+
+use voudp::{
+    console_msg::ConsoleMessageKind,
+    console_tls::{TlsConsoleClient, TlsConsoleConfig},
+    protocol::{self, ConsoleRole},
+    server::{ServerConfig, ServerState},
+    socket::{self, SecureUdpSocket},
+    util,
+};
+
+mod common;
+
+use common::message;
+
+const PHRASE: &[u8] = b"console-tls-test";
+
+fn fixture(name: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/data/tls")
+        .join(name)
+}
+
+// a running server and where its UDP and TLS consoles go
+fn start_server() -> (SocketAddr, SocketAddr) {
+    let mut server = ServerState::new(ServerConfig::default(), PHRASE).unwrap();
+    let udp = SocketAddr::from(([127, 0, 0, 1], server.local_addr().port()));
+    let tls = server
+        .listen_tls_console(&TlsConsoleConfig {
+            bind: "127.0.0.1:0".parse().unwrap(),
+            cert_chain: fixture("cert.pem"),
+            key: fixture("key.pem"),
+        })
+        .unwrap();
+    thread::spawn(move || server.run());
+    (udp, tls)
+}
+
+#[test]
+fn tls_consoles_log_in_and_run_commands() {
+    let (udp, tls) = start_server();
+    let connect = || TlsConsoleClient::connect(tls, "localhost", &fixture("ca.pem")).unwrap();
+    // slow in debug builds, the sessions below would time out waiting on it
+    let key = socket::derive_key_from_phrase(PHRASE, protocol::VOUDP_SALT);
+
+    let denied = connect()
+        .login("not the password", ConsoleRole::Admin)
+        .unwrap_err();
+    assert_eq!(denied.kind(), io::ErrorKind::PermissionDenied);
+
+    let mut admin = connect();
+    let token = admin.login(protocol::PASSWORD, ConsoleRole::Admin).unwrap();
+    let pong = admin.command(&token, "ping").unwrap();
+    assert_eq!(
+        (pong.kind, pong.text()),
+        (ConsoleMessageKind::Reply, "pong")
+    );
+    let created = admin.command(&token, "create lobby").unwrap();
+    assert!(created.text().starts_with("created channel 'lobby'"));
+
+    let mut observer = connect();
+    let token = observer
+        .login(protocol::PASSWORD, ConsoleRole::Observer)
+        .unwrap();
+    let refused = observer.command(&token, "del lobby").unwrap();
+    assert_eq!(refused.kind, ConsoleMessageKind::Error);
+
+    // side by side with consoles over UDP
+    let console = SecureUdpSocket::create("127.0.0.1:0".into(), key).unwrap();
+    let udp_token =
+        util::console_login(&console, udp, protocol::PASSWORD, ConsoleRole::Observer).unwrap();
+    let sessions = observer.command(&token, "sessions").unwrap();
+    assert_eq!(sessions.text().matches("over tls").count(), 2);
+    assert_eq!(sessions.text().lines().count(), 3);
+
+    // hanging up ends the sessions
+    drop(admin);
+    drop(observer);
+    thread::sleep(Duration::from_millis(200));
+    let packet = protocol::create_console_command(&udp_token, "sessions");
+    console.send_reliable(packet, udp).unwrap();
+    let sessions = message(&console).expect("no sessions from the server");
+    assert_eq!(sessions.text().lines().count(), 1);
+}
+
+#[test]
+fn tls_consoles_only_trust_the_right_server_name() {
+    let (_, tls) = start_server();
+
+    assert!(TlsConsoleClient::connect(tls, "voudp.example", &fixture("ca.pem")).is_err());
+    assert!(TlsConsoleClient::connect(tls, "localhost", &fixture("ca.pem")).is_ok());
+}
@@ -0,0 +1,73 @@
+use std::{
+    net::SocketAddr,
+    thread,
+    time::{Duration, Instant},
+};
+
+use voudp::{
+    protocol::{self, ClientPacketType, FromPacket, MessageCode},
+    server::{ServerConfig, ServerState},
+    socket::{self, SecureUdpSocket},
+    util::{FlowPacket, LocalizedMessage},
+};
+
+mod common;
+
+use common::joined;
+
+const PHRASE: &[u8] = b"dm-test";
+
+// the first packet of type `ty`, everything else is skipped
+fn recv(socket: &SecureUdpSocket, ty: ClientPacketType) -> Option<Vec<u8>> {
+    let deadline = Instant::now() + Duration::from_secs(3);
+    let mut buf = [0u8; 2048];
+
+    while Instant::now() < deadline {
+        match socket.recv_from(&mut buf) {
+            Ok((size, _)) if size > 0 && buf[0] == ty as u8 => return Some(buf[..size].to_vec()),
+            Ok(_) => {}
+            Err(_) => thread::sleep(Duration::from_millis(2)),
+        }
+    }
+
+    None
+}
+
+#[test]
+fn dms_reach_only_their_target() {
+    let server = ServerState::new(ServerConfig::default(), PHRASE).expect("server should bind");
+    let addr = SocketAddr::from(([127, 0, 0, 1], server.local_addr().port()));
+    thread::spawn(move || {
+        let mut server = server;
+        server.run();
+    });
+
+    let key = socket::derive_key_from_phrase(PHRASE, protocol::VOUDP_SALT);
+    let alice = joined(key, addr, protocol::DEFAULT_CHANNEL_ID, Some("alice"));
+    let bob = joined(key, addr, protocol::DEFAULT_CHANNEL_ID, Some("bob"));
+    thread::sleep(Duration::from_millis(100));
+
+    alice
+        .send(&protocol::create_dm_packet("bob", "psst"))
+        .unwrap();
+    let packet = recv(&bob, ClientPacketType::Whisper).expect("bob never got the dm");
+    match FlowPacket::deserialize(&packet).unwrap() {
+        FlowPacket::Whisper { from, message } => {
+            assert_eq!(from, "alice");
+            assert_eq!(message, "psst");
+        }
+        other => panic!("expected a whisper, got {other:?}"),
+    }
+
+    // nobody by that name, alice is told so
+    alice
+        .send(&protocol::create_dm_packet("carol", "hello?"))
+        .unwrap();
+    let notice = std::iter::from_fn(|| recv(&alice, ClientPacketType::Notice))
+        .map(|packet| LocalizedMessage::deserialize(&packet).unwrap())
+        // joining the channel sends its own notice first
+        .find(|notice| notice.message_code() != Some(MessageCode::ChannelMoved))
+        .expect("alice was never told");
+    assert_eq!(notice.message_code(), Some(MessageCode::UserNotOnline));
+    assert_eq!(notice.fallback, "carol is not online");
+}
@@ -0,0 +1,114 @@
+use voudp::{
+    effects::{Effect, EffectChain, EffectKind, MAX_EFFECTS, PitchShift, Reverb, Robot},
+    protocol::{self, ControlRequest, FromPacket},
+    util::ControlPacket,
+};
+
+const SAMPLE_RATE: u32 = 48000;
+const FRAME: usize = 1920; // 20ms of interleaved stereo
+
+fn sine(freq: f32, amplitude: f32, frames: usize) -> Vec<f32> {
+    (0..frames)
+        .flat_map(|n| {
+            let s = amplitude
+                * (2.0 * std::f32::consts::PI * freq * n as f32 / SAMPLE_RATE as f32).sin();
+            [s, s]
+        })
+        .collect()
+}
+
+fn run(effect: &mut dyn Effect, mut signal: Vec<f32>) -> Vec<f32> {
+    for frame in signal.chunks_mut(FRAME) {
+        effect.process(frame);
+    }
+    signal
+}
+
+fn energy(buf: &[f32]) -> f32 {
+    buf.iter().map(|s| s * s).sum::<f32>() / buf.len() as f32
+}
+
+// rising zero crossings of the left channel
+fn crossings(buf: &[f32]) -> usize {
+    buf.iter()
+        .step_by(2)
+        .collect::<Vec<_>>()
+        .windows(2)
+        .filter(|w| *w[0] < 0.0 && *w[1] >= 0.0)
+        .count()
+}
+
+#[test]
+fn pitch_shift_raises_frequency() {
+    let mut shifter = PitchShift::new(1.5, SAMPLE_RATE);
+    let out = run(&mut shifter, sine(200.0, 0.5, 48000));
+
+    // skip the first window while the delay line fills up
+    let settled = &out[9600..];
+    let seconds = settled.len() as f32 / 2.0 / SAMPLE_RATE as f32;
+    let freq = crossings(settled) as f32 / seconds;
+    assert!((freq - 300.0).abs() < 15.0, "got {freq} Hz");
+    assert!(settled.iter().all(|s| s.abs() <= 0.5 + 1e-4));
+}
+
+#[test]
+fn reverb_leaves_a_tail() {
+    let mut reverb = Reverb::new(0.3, SAMPLE_RATE);
+    let mut signal = sine(440.0, 0.5, 4800);
+    signal.extend(vec![0.0; 4800 * 2]);
+    let out = run(&mut reverb, signal);
+
+    let tail = &out[9600..9600 + FRAME];
+    assert!(energy(tail) > 1e-6, "no tail: {}", energy(tail));
+    assert!(out.iter().all(|s| s.is_finite() && s.abs() < 1.0));
+}
+
+#[test]
+fn robot_modulates_but_keeps_silence() {
+    let mut robot = Robot::new(60.0, SAMPLE_RATE);
+    let input = sine(440.0, 0.5, 48000);
+    let out = run(&mut robot, input.clone());
+
+    // ring modulation by a full scale sine halves the power
+    let ratio = energy(&out) / energy(&input);
+    assert!((ratio - 0.5).abs() < 0.02, "power ratio {ratio}");
+
+    let silence = run(&mut robot, vec![0.0; FRAME]);
+    assert!(silence.iter().all(|&s| s == 0.0));
+}
+
+#[test]
+fn chain_is_bounded_and_stays_sane() {
+    let kinds = [
+        EffectKind::PitchShift,
+        EffectKind::Reverb,
+        EffectKind::Robot,
+        EffectKind::Reverb,
+        EffectKind::Robot,
+    ];
+    let mut chain = EffectChain::new(&kinds, SAMPLE_RATE);
+    assert_eq!(chain.kinds(), &kinds[..MAX_EFFECTS]);
+
+    let mut signal = sine(300.0, 0.9, 48000);
+    for frame in signal.chunks_mut(FRAME) {
+        chain.process(frame);
+    }
+    assert!(signal.iter().all(|s| s.is_finite() && s.abs() <= 1.0));
+
+    assert!(EffectChain::new(&[], SAMPLE_RATE).is_empty());
+}
+
+#[test]
+fn effects_control_packet_round_trip() {
+    let packet = protocol::create_effects_request(&[EffectKind::Robot, EffectKind::Reverb]);
+    let parsed = ControlPacket::deserialize(&packet[1..]).unwrap();
+    assert_eq!(parsed.request, ControlRequest::SetEffects);
+    assert_eq!(parsed.effects, [EffectKind::Robot, EffectKind::Reverb]);
+
+    let off = ControlPacket::deserialize(&protocol::create_effects_request(&[])[1..]).unwrap();
+    assert!(off.effects.is_empty());
+
+    assert!(ControlPacket::deserialize(&[ControlRequest::SetEffects as u8, 0x42]).is_err());
+    assert_eq!("robot".parse::<EffectKind>(), Ok(EffectKind::Robot));
+    assert!("chorus".parse::<EffectKind>().is_err());
+}
@@ -0,0 +1,142 @@
+use std::{
+    net::SocketAddr,
+    sync::mpsc::Receiver,
+    thread,
+    time::{Duration, Instant},
+};
+
+use voudp::{
+    client::{ClientEvent, ClientState, Mode, OwnedEvent},
+    protocol,
+    server::{ServerConfig, ServerState},
+};
+
+const PHRASE: &[u8] = b"events-test";
+
+fn wait_for(
+    events: &Receiver<OwnedEvent>,
+    matches: impl Fn(&ClientEvent) -> bool,
+) -> Option<ClientEvent> {
+    let deadline = Instant::now() + Duration::from_secs(5);
+
+    while Instant::now() < deadline {
+        match events.recv_timeout(Duration::from_millis(50)) {
+            Ok((event, _)) if matches(&event) => return Some(event),
+            _ => {}
+        }
+    }
+
+    None
+}
+
+#[test]
+fn client_reports_roster_and_chat_as_events() {
+    let server = ServerState::new(ServerConfig::default(), PHRASE).expect("server should bind");
+    let addr = SocketAddr::from(([127, 0, 0, 1], server.local_addr().port()));
+    thread::spawn(move || {
+        let mut server = server;
+        server.run();
+    });
+
+    let client = ClientState::new(&addr.to_string(), protocol::DEFAULT_CHANNEL_ID, PHRASE).unwrap();
+    let events = client.events().expect("first caller gets the events");
+    assert!(client.events().is_none());
+
+    // the network thread keeps running even where there's no audio device
+    client.run(Mode::Gui).unwrap();
+
+    match wait_for(&events, |e| matches!(e, ClientEvent::ListUpdated { .. })) {
+        Some(ClientEvent::ListUpdated { channels, current }) => {
+            assert_eq!(current, protocol::DEFAULT_CHANNEL_ID);
+            assert!(channels.iter().any(|c| c.unmasked_count == 1));
+        }
+        other => panic!("expected a list update, got {other:?}"),
+    }
+
+    // only masked users can chat
+    let mut nick = vec![0x04];
+    nick.extend_from_slice(b"alice");
+    client.send(&nick);
+
+    let mut chat = vec![0x06];
+    chat.extend_from_slice(b"hello there");
+    client.send(&chat);
+
+    match wait_for(&events, |e| matches!(e, ClientEvent::Chat { .. })) {
+        Some(ClientEvent::Chat {
+            from,
+            message,
+            is_self,
+        }) => {
+            assert_eq!(from, "alice");
+            assert_eq!(message, "hello there");
+            assert!(is_self);
+        }
+        other => panic!("expected our chat back, got {other:?}"),
+    }
+
+    client.disconnect();
+}
+
+#[test]
+fn client_switches_channels_without_reconnecting() {
+    let server = ServerState::new(ServerConfig::default(), PHRASE).expect("server should bind");
+    let addr = SocketAddr::from(([127, 0, 0, 1], server.local_addr().port()));
+    thread::spawn(move || {
+        let mut server = server;
+        server.run();
+    });
+
+    let client = ClientState::new(&addr.to_string(), protocol::DEFAULT_CHANNEL_ID, PHRASE).unwrap();
+    let events = client.events().unwrap();
+    client.run(Mode::Gui).unwrap();
+
+    assert!(
+        wait_for(&events, |e| matches!(
+            e,
+            ClientEvent::ListUpdated { current, .. } if *current == protocol::DEFAULT_CHANNEL_ID
+        ))
+        .is_some()
+    );
+
+    client.join_channel(3).unwrap();
+    assert_eq!(client.channel_id(), 3);
+
+    match wait_for(
+        &events,
+        |e| matches!(e, ClientEvent::ListUpdated { current, .. } if *current == 3),
+    ) {
+        Some(ClientEvent::ListUpdated { channels, .. }) => {
+            let test = channels.iter().find(|c| c.channel_id == 3).unwrap();
+            assert_eq!(test.unmasked_count, 1);
+        }
+        other => panic!("never moved to channel 3, got {other:?}"),
+    }
+
+    client.disconnect();
+}
+
+#[test]
+fn chatting_without_a_mask_asks_for_one() {
+    let server = ServerState::new(ServerConfig::default(), PHRASE).expect("server should bind");
+    let addr = SocketAddr::from(([127, 0, 0, 1], server.local_addr().port()));
+    thread::spawn(move || {
+        let mut server = server;
+        server.run();
+    });
+
+    let client = ClientState::new(&addr.to_string(), protocol::DEFAULT_CHANNEL_ID, PHRASE).unwrap();
+    let events = client.events().unwrap();
+    client.run(Mode::Gui).unwrap();
+    wait_for(&events, |e| matches!(e, ClientEvent::ListUpdated { .. })).expect("never joined");
+
+    let mut chat = vec![0x06];
+    chat.extend_from_slice(b"anyone?");
+    client.send(&chat);
+    assert!(wait_for(&events, |e| matches!(e, ClientEvent::NickRequired)).is_some());
+
+    client.send(&protocol::create_dm_packet("bob", "psst"));
+    assert!(wait_for(&events, |e| matches!(e, ClientEvent::NickRequired)).is_some());
+
+    client.disconnect();
+}
@@ -0,0 +1,86 @@
+use std::{
+    io,
+    net::{SocketAddr, UdpSocket},
+    thread,
+    time::Duration,
+};
+
+use voudp::{
+    client::{self, ClientBuilder, ClientState},
+    protocol,
+    server::{ServerConfig, ServerState},
+};
+
+const PHRASE: &[u8] = b"handshake-test";
+
+fn start_server() -> SocketAddr {
+    let server = ServerState::new(ServerConfig::default(), PHRASE).expect("server should bind");
+    let addr = SocketAddr::from(([127, 0, 0, 1], server.local_addr().port()));
+    thread::spawn(move || {
+        let mut server = server;
+        server.run();
+    });
+    addr
+}
+
+#[test]
+fn handshake_finds_a_live_server() {
+    let addr = start_server();
+    let client = ClientState::new(&addr.to_string(), protocol::DEFAULT_CHANNEL_ID, PHRASE).unwrap();
+    client.handshake(client::HANDSHAKE_TIMEOUT).unwrap();
+}
+
+#[test]
+fn handshake_rejects_frames_the_server_cant_decode() {
+    let addr = start_server();
+    let client = ClientBuilder::new(&addr.to_string(), PHRASE)
+        .frame_ms(40)
+        .build()
+        .unwrap();
+
+    let err = client.handshake(client::HANDSHAKE_TIMEOUT).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    assert_eq!(
+        err.to_string(),
+        "the server uses 20 ms frames, this client was built for 40 ms"
+    );
+}
+
+#[test]
+fn handshake_fails_when_nothing_listens() {
+    // a port that was free a moment ago
+    let port = UdpSocket::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port();
+
+    let client = ClientState::new(
+        &format!("127.0.0.1:{port}"),
+        protocol::DEFAULT_CHANNEL_ID,
+        PHRASE,
+    )
+    .expect("udp can't tell yet");
+    let err = client.handshake(Duration::from_secs(1)).unwrap_err();
+    assert!(
+        matches!(
+            err.kind(),
+            io::ErrorKind::ConnectionRefused | io::ErrorKind::TimedOut
+        ),
+        "{err}"
+    );
+}
+
+#[test]
+fn handshake_fails_with_the_wrong_phrase() {
+    let addr = start_server();
+    let client = ClientState::new(
+        &addr.to_string(),
+        protocol::DEFAULT_CHANNEL_ID,
+        b"not the phrase",
+    )
+    .unwrap();
+
+    let err = client.handshake(Duration::from_secs(1)).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+}
@@ -0,0 +1,200 @@
+use std::{net::SocketAddr, thread, time::Duration};
+
+use voudp::{
+    protocol::{self, ClientPacketType, FromPacket, IntoPacket},
+    server::{ServerConfig, ServerState},
+    socket::{self, SecureUdpSocket},
+    util::{
+        ChannelInfo, ChannelRoster, GlobalListPacket, ListPagePacket, MAX_LIST_PAGE_BYTES,
+        RosterUpdate,
+    },
+};
+
+mod common;
+
+use common::recv_packet;
+
+const PHRASE: &[u8] = b"list-pagination-test";
+
+// channel 2 is left empty on purpose
+fn big_list(users: &[usize]) -> Vec<ChannelInfo> {
+    users
+        .iter()
+        .enumerate()
+        .map(|(c, &count)| ChannelInfo {
+            name: format!("channel-{c}"),
+            channel_id: c as u32 + 1,
+            unmasked_count: 3,
+            masked_users: (0..count)
+                .map(|u| (format!("user-{c}-{u}"), u % 3 == 0, u % 5 == 0))
+                .collect(),
+        })
+        .collect()
+}
+
+// walks every page like a client would and returns the assembled list
+fn fetch_all(channels: &[ChannelInfo], limit: u16) -> (GlobalListPacket, usize) {
+    let mut roster = ChannelRoster::new();
+    let mut offset = 0;
+    let mut pages = 0;
+
+    loop {
+        let page = ListPagePacket::paginate(1, channels, offset, limit);
+        let bytes = page.serialize();
+        assert!(
+            bytes.len() <= MAX_LIST_PAGE_BYTES,
+            "page of {}",
+            bytes.len()
+        );
+        pages += 1;
+
+        match roster.apply(ListPagePacket::deserialize(&bytes).unwrap()) {
+            RosterUpdate::Next(next) => offset = next,
+            RosterUpdate::Complete(list) => return (list, pages),
+            RosterUpdate::Ignored => panic!("page at {offset} was ignored"),
+        }
+    }
+}
+
+fn assert_same(actual: &[ChannelInfo], expected: &[ChannelInfo]) {
+    assert_eq!(actual.len(), expected.len());
+    for (a, e) in actual.iter().zip(expected) {
+        assert_eq!(a.channel_id, e.channel_id);
+        assert_eq!(a.name, e.name);
+        assert_eq!(a.unmasked_count, e.unmasked_count);
+        assert_eq!(a.masked_users, e.masked_users);
+    }
+}
+
+#[test]
+fn thousands_of_users_reassemble() {
+    let channels = big_list(&[1200, 0, 450, 1]);
+    let (list, pages) = fetch_all(&channels, 0);
+
+    assert_eq!(list.current, 1);
+    assert_same(&list.channels, &channels);
+    // the default limit and the byte budget both keep pages small
+    assert!(pages >= 1651 / protocol::DEFAULT_LIST_PAGE_LIMIT as usize);
+}
+
+#[test]
+fn limit_is_respected() {
+    let channels = big_list(&[1000, 0, 500]);
+
+    let page = ListPagePacket::paginate(1, &channels, 0, 10);
+    let users: usize = page.channels.iter().map(|c| c.masked_users.len()).sum();
+    assert_eq!(users, 10);
+    assert_eq!(page.next, Some(10));
+    assert_eq!(page.total_users, 1500);
+
+    let (list, pages) = fetch_all(&channels, 10);
+    assert_same(&list.channels, &channels);
+    assert_eq!(pages, 150);
+}
+
+#[test]
+fn first_page_carries_every_channel() {
+    let channels = big_list(&[1000, 0, 500]);
+
+    let first = ListPagePacket::paginate(1, &channels, 0, 5);
+    assert_eq!(first.channels.len(), 3);
+
+    // deep in the first channel, nothing else is on the page
+    let later = ListPagePacket::paginate(1, &channels, 100, 5);
+    assert_eq!(later.channels.len(), 1);
+    assert_eq!(later.channels[0].masked_users[0].0, "user-0-100");
+
+    // a page straddling two channels skips the empty one in between
+    let straddle = ListPagePacket::paginate(1, &channels, 998, 5);
+    let ids = straddle
+        .channels
+        .iter()
+        .map(|c| c.channel_id)
+        .collect::<Vec<_>>();
+    assert_eq!(ids, [1, 3]);
+}
+
+#[test]
+fn last_page_has_no_next() {
+    let channels = big_list(&[3]);
+    let page = ListPagePacket::paginate(1, &channels, 0, 0);
+    assert_eq!(page.next, None);
+
+    let empty = ListPagePacket::paginate(1, &big_list(&[0, 0]), 0, 0);
+    assert_eq!(empty.next, None);
+    assert_eq!(empty.channels.len(), 2);
+}
+
+#[test]
+fn roster_ignores_stray_pages_and_restarts() {
+    let channels = big_list(&[100]);
+    let mut roster = ChannelRoster::new();
+
+    // nothing requested yet
+    let stray = ListPagePacket::paginate(1, &channels, 30, 30);
+    assert!(matches!(roster.apply(stray), RosterUpdate::Ignored));
+
+    let first = ListPagePacket::paginate(1, &channels, 0, 30);
+    assert!(matches!(roster.apply(first), RosterUpdate::Next(30)));
+
+    // skipping a page is not accepted
+    let skipped = ListPagePacket::paginate(1, &channels, 60, 30);
+    assert!(matches!(roster.apply(skipped), RosterUpdate::Ignored));
+
+    // a fresh first page drops whatever was assembled so far
+    let first = ListPagePacket::paginate(1, &channels, 0, 50);
+    assert!(matches!(roster.apply(first), RosterUpdate::Next(50)));
+    let last = ListPagePacket::paginate(1, &channels, 50, 50);
+    match roster.apply(last) {
+        RosterUpdate::Complete(list) => assert_same(&list.channels, &channels),
+        _ => panic!("expected a complete list"),
+    }
+}
+
+#[test]
+fn server_filters_and_keeps_legacy_lists() {
+    let server = ServerState::new(ServerConfig::default(), PHRASE).expect("server should bind");
+    let addr = SocketAddr::from(([127, 0, 0, 1], server.local_addr().port()));
+    thread::spawn(move || {
+        let mut server = server;
+        server.run();
+    });
+
+    let key = socket::derive_key_from_phrase(PHRASE, protocol::VOUDP_SALT);
+    let client = SecureUdpSocket::create("127.0.0.1:0".into(), key).unwrap();
+    client.connect(addr).unwrap();
+
+    let mut join = vec![ClientPacketType::Join as u8];
+    join.extend_from_slice(&protocol::DEFAULT_CHANNEL_ID.to_be_bytes());
+    client.send(&join).unwrap();
+    let mut mask = vec![ClientPacketType::Mask as u8];
+    mask.extend_from_slice(b"alice");
+    client.send(&mask).unwrap();
+    thread::sleep(Duration::from_millis(100));
+
+    let masked_users = |filter| {
+        client
+            .send(&protocol::create_list_page_request(0, 0, filter))
+            .unwrap();
+        loop {
+            let page =
+                recv_packet(&client, ClientPacketType::ListPage as u8).expect("no list page");
+            let page = ListPagePacket::deserialize(&page).unwrap();
+            // the join itself also triggers an unfiltered page
+            if page.total_users > 0 || filter != 0 {
+                return page.total_users;
+            }
+        }
+    };
+
+    assert_eq!(masked_users(0), 1);
+    // alice never sent audio and isn't an admin
+    assert_eq!(masked_users(protocol::LIST_FILTER_SPEAKING), 0);
+    assert_eq!(masked_users(protocol::LIST_FILTER_ADMINS), 0);
+
+    // older clients send a bare request and still get the whole list
+    client.send(&protocol::create_list_request()).unwrap();
+    let legacy = recv_packet(&client, ClientPacketType::List as u8).expect("no legacy list");
+    let legacy = GlobalListPacket::deserialize(&legacy[1..]).unwrap();
+    assert_eq!(legacy.channels[0].masked_users[0].0, "alice");
+}
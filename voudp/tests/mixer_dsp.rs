@@ -0,0 +1,377 @@
+use voudp::{
+    loudness::{LevelMeter, LoudnessMeter, Normalizer},
+    mixer::{self, DcState, HighPass},
+};
+
+const SAMPLE_RATE: u32 = 48000;
+const EPSILON: f32 = 1e-5;
+
+fn assert_close(actual: &[f32], expected: &[f32]) {
+    assert_eq!(actual.len(), expected.len(), "length mismatch");
+    for (i, (a, e)) in actual.iter().zip(expected).enumerate() {
+        assert!(
+            (a - e).abs() < EPSILON,
+            "sample {i}: got {a}, expected {e}\n  actual: {actual:?}\n  expected: {expected:?}"
+        );
+    }
+}
+
+fn assert_sane(buf: &[f32], bound: f32) {
+    for (i, s) in buf.iter().enumerate() {
+        assert!(s.is_finite(), "sample {i} is not finite: {s}");
+        assert!(s.abs() <= bound, "sample {i} out of bounds: {s}");
+    }
+}
+
+// interleaved stereo sine
+fn sine(freq: f32, amplitude: f32, frames: usize) -> Vec<f32> {
+    (0..frames)
+        .flat_map(|n| {
+            let s = amplitude
+                * (2.0 * std::f32::consts::PI * freq * n as f32 / SAMPLE_RATE as f32).sin();
+            [s, s]
+        })
+        .collect()
+}
+
+fn mean(buf: &[f32]) -> f32 {
+    buf.iter().sum::<f32>() / buf.len() as f32
+}
+
+fn peak(buf: &[f32]) -> f32 {
+    buf.iter().fold(0.0, |max, s| f32::max(max, s.abs()))
+}
+
+#[test]
+fn dc_bias_golden() {
+    let mut buf = vec![1.0, 0.5, 1.0, 0.5, 1.0, 0.5, 1.0, 0.5];
+    let mut state = DcState::default();
+    mixer::remove_dc_bias(&mut buf, &mut state, 0.5);
+
+    assert_close(&buf, &[1.0, 0.5, 0.5, 0.25, 0.25, 0.125, 0.125, 0.0625]);
+}
+
+#[test]
+fn dc_bias_state_carries_across_frames() {
+    let input = sine(440.0, 0.5, 960)
+        .iter()
+        .map(|s| s + 0.3)
+        .collect::<Vec<_>>();
+
+    let mut whole = input.clone();
+    mixer::remove_dc_bias(&mut whole, &mut DcState::default(), 0.995);
+
+    let mut split = input;
+    let mut state = DcState::default();
+    let (first, second) = split.split_at_mut(480);
+    mixer::remove_dc_bias(first, &mut state, 0.995);
+    mixer::remove_dc_bias(second, &mut state, 0.995);
+
+    assert_close(&split, &whole);
+}
+
+#[test]
+fn dc_bias_removes_offset() {
+    let mut state = DcState::default();
+    let mut last = Vec::new();
+
+    // a second of a 440 Hz tone sitting on a 0.3 offset, in 20ms frames
+    for _ in 0..50 {
+        let mut frame = sine(440.0, 0.5, 960)
+            .iter()
+            .map(|s| s + 0.3)
+            .collect::<Vec<_>>();
+        mixer::remove_dc_bias(&mut frame, &mut state, 0.995);
+        assert_sane(&frame, 2.0);
+        last = frame;
+    }
+
+    assert!(mean(&last).abs() < 0.01, "DC left: {}", mean(&last));
+    assert!(peak(&last) > 0.4, "tone was attenuated: {}", peak(&last));
+}
+
+#[test]
+fn compress_golden() {
+    let mut buf = vec![0.0, 0.25, 0.5, 0.75, 1.0, -1.0, -0.4, 2.0];
+    mixer::compress(&mut buf, 0.5, 0.8);
+
+    assert_close(&buf, &[0.0, 0.25, 0.5, 0.7, 0.9, -0.9, -0.4, 1.7]);
+}
+
+#[test]
+fn compress_keeps_sign_and_order() {
+    let mut buf = (-100..=100).map(|i| i as f32 / 50.0).collect::<Vec<_>>();
+    mixer::compress(&mut buf, 0.5, 0.8);
+
+    assert_sane(&buf, 2.0);
+    assert!(buf.windows(2).all(|w| w[0] <= w[1]));
+    assert_eq!(buf[100], 0.0);
+}
+
+#[test]
+fn normalize_golden() {
+    let mut loud = vec![0.5, -2.0, 1.0, 0.0];
+    mixer::normalize(&mut loud);
+    assert_close(&loud, &[0.25, -1.0, 0.5, 0.0]);
+
+    // already within range, left untouched
+    let mut quiet = vec![0.5, -0.75, 1.0, 0.0];
+    mixer::normalize(&mut quiet);
+    assert_close(&quiet, &[0.5, -0.75, 1.0, 0.0]);
+}
+
+#[test]
+fn normalize_silence() {
+    let mut buf = vec![0.0; 1920];
+    mixer::normalize(&mut buf);
+    assert_sane(&buf, 0.0);
+}
+
+#[test]
+fn soft_clip_golden() {
+    let mut buf = vec![0.0, 0.5, 1.0, -3.0];
+    mixer::soft_clip(&mut buf);
+
+    assert_close(&buf, &[0.0, 0.46211716, 0.7615942, -0.9950548]);
+}
+
+#[test]
+fn soft_clip_bounds_extremes() {
+    let mut buf = vec![1e6, -1e6, 50.0, -50.0, f32::MAX, f32::MIN];
+    mixer::soft_clip(&mut buf);
+    assert_sane(&buf, 1.0);
+}
+
+#[test]
+fn is_silent_threshold() {
+    assert!(mixer::is_silent(&[0.0; 1920], 0.001));
+    assert!(mixer::is_silent(&[0.0005; 1920], 0.001));
+    assert!(!mixer::is_silent(&[0.002; 1920], 0.001));
+    assert!(!mixer::is_silent(&sine(440.0, 0.1, 960), 0.001));
+}
+
+#[test]
+fn highpass_cuts_rumble_keeps_voice() {
+    let mut low = HighPass::new(100.0, SAMPLE_RATE);
+    let mut voice = HighPass::new(100.0, SAMPLE_RATE);
+
+    // one second of each, fed through in 20ms frames
+    let mut rumble = sine(20.0, 0.5, 48000);
+    let mut voice_tone = sine(1000.0, 0.5, 48000);
+    for (r, v) in rumble.chunks_mut(1920).zip(voice_tone.chunks_mut(1920)) {
+        low.process(r);
+        voice.process(v);
+        assert_sane(r, 1.0);
+        assert_sane(v, 1.0);
+    }
+    let rumble_out = &rumble[rumble.len() - 1920..];
+    let voice_out = &voice_tone[voice_tone.len() - 1920..];
+
+    // a 2nd order filter is ~-28 dB two octaves+ below cutoff
+    assert!(peak(rumble_out) < 0.05, "rumble: {}", peak(rumble_out));
+    assert!(peak(voice_out) > 0.48, "voice: {}", peak(voice_out));
+}
+
+#[test]
+fn highpass_removes_dc() {
+    let mut filter = HighPass::new(80.0, SAMPLE_RATE);
+    let mut frame = Vec::new();
+    for _ in 0..10 {
+        frame = vec![0.5; 1920];
+        filter.process(&mut frame);
+    }
+
+    assert!(peak(&frame) < 1e-3, "DC left: {}", peak(&frame));
+}
+
+#[test]
+fn pan_position_is_deterministic_and_bounded() {
+    for name in ["alice", "bob", "carol", "127.0.0.1:4000", ""] {
+        let pan = mixer::pan_position(name);
+        assert_eq!(pan, mixer::pan_position(name));
+        assert!((-1.0..=1.0).contains(&pan));
+    }
+}
+
+#[test]
+fn mix_panned_is_constant_power() {
+    for pan in [-1.0, -0.5, 0.0, 0.3, 1.0] {
+        let mut mix = vec![0.0; 2];
+        mixer::mix_panned(&mut mix, &[1.0, 1.0], pan, 1.0);
+
+        let power = mix[0] * mix[0] + mix[1] * mix[1];
+        assert!((power - 1.0).abs() < EPSILON, "pan {pan}: power {power}");
+    }
+
+    let mut center = vec![0.0; 2];
+    mixer::mix_panned(&mut center, &[1.0, 1.0], 0.0, 1.0);
+    assert_close(&center, &[std::f32::consts::FRAC_1_SQRT_2; 2]);
+}
+
+// There is no limiter/AGC stage yet; once one lands in mixer.rs it gets golden
+// fixtures here like the stages above.
+
+#[test]
+fn accumulate_matches_scalar() {
+    // odd length to exercise the remainder path
+    let buf = sine(440.0, 0.5, 21);
+    let mut expected = vec![0.25; buf.len()];
+    for (out, s) in expected.iter_mut().zip(&buf) {
+        *out += s * 0.7;
+    }
+
+    let mut mix = vec![0.25; buf.len()];
+    mixer::accumulate(&mut mix, &buf, 0.7);
+    assert_close(&mix, &expected);
+}
+
+#[test]
+fn mix_excluding_removes_own_voice() {
+    let own = sine(440.0, 0.5, 480);
+    let other = sine(1000.0, 0.3, 480);
+
+    let mut total = vec![0.0; own.len()];
+    mixer::accumulate(&mut total, &own, 1.0);
+    mixer::accumulate(&mut total, &other, 1.0);
+
+    let mut mix = vec![0.0; own.len()];
+    mixer::mix_excluding(&mut mix, &total, Some(&own), 0.5);
+
+    let expected = other.iter().map(|s| s * 0.5).collect::<Vec<_>>();
+    assert_close(&mix, &expected);
+}
+
+#[test]
+fn hard_clip_golden() {
+    let mut buf = vec![0.0, 0.5, 1.5, -2.0, -0.25, 1.0, -1.0, 3.0, 0.99];
+    mixer::hard_clip(&mut buf);
+
+    assert_close(&buf, &[0.0, 0.5, 1.0, -1.0, -0.25, 1.0, -1.0, 1.0, 0.99]);
+}
+
+#[test]
+fn ramp_golden() {
+    let mut fade_in = vec![1.0; 12];
+    mixer::apply_ramp(&mut fade_in, 0.0, 1.0, 4);
+    assert_close(
+        &fade_in,
+        &[
+            0.25, 0.25, 0.5, 0.5, 0.75, 0.75, 1.0, 1.0, 1.0, 1.0, 1.0, 1.0,
+        ],
+    );
+
+    let mut fade_out = vec![1.0; 12];
+    mixer::apply_ramp(&mut fade_out, 1.0, 0.0, 4);
+    assert_close(
+        &fade_out,
+        &[
+            0.75, 0.75, 0.5, 0.5, 0.25, 0.25, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0,
+        ],
+    );
+}
+
+#[test]
+fn ramp_has_no_jumps() {
+    let mut buf = vec![0.8; 1920];
+    mixer::apply_ramp(&mut buf, 0.0, 1.0, 240);
+
+    let max_step = buf
+        .chunks_exact(2)
+        .map(|f| f[0])
+        .collect::<Vec<_>>()
+        .windows(2)
+        .fold(0.0, |max, w| f32::max(max, (w[1] - w[0]).abs()));
+    assert!(max_step < 0.01, "step of {max_step}");
+}
+
+#[test]
+fn loudness_of_full_scale_sine() {
+    // BS.1770 calibration: a 0 dBFS 1 kHz sine reads -3.01 LUFS in one
+    // channel, so ~0 LUFS in both
+    let mut meter = LoudnessMeter::new(SAMPLE_RATE);
+    let tone = sine(1000.0, 1.0, 48000 * 3);
+    for frame in tone.chunks(1920) {
+        meter.process(frame);
+    }
+
+    let lufs = meter.short_term();
+    assert!(lufs.abs() < 0.1, "got {lufs} LUFS");
+    assert!((meter.momentary() - lufs).abs() < 0.1);
+    assert!(meter.peak_dbfs().abs() < 0.01);
+}
+
+#[test]
+fn loudness_tracks_level_and_silence() {
+    let mut meter = LoudnessMeter::new(SAMPLE_RATE);
+    assert_eq!(meter.short_term(), f32::NEG_INFINITY);
+
+    let loud = sine(1000.0, 0.5, 48000);
+    for frame in loud.chunks(1920) {
+        meter.process(frame);
+    }
+    let before = meter.momentary();
+
+    let quiet = sine(1000.0, 0.05, 48000);
+    for frame in quiet.chunks(1920) {
+        meter.process(frame);
+    }
+
+    // 20 dB quieter input reads 20 LU lower
+    assert!((before - meter.momentary() - 20.0).abs() < 0.5);
+    // only the filter tail is left after going quiet
+    for frame in vec![0.0; 48000 * 6].chunks(1920) {
+        meter.process(frame);
+    }
+    assert!(meter.short_term() < -70.0);
+}
+
+#[test]
+fn normalizer_evens_out_tracks() {
+    let mut normalizer = Normalizer::new(SAMPLE_RATE, -14.0);
+
+    // a hot master and a quiet one, back to back
+    for amplitude in [0.5, 0.08] {
+        normalizer.reset();
+        let mut out = LoudnessMeter::new(SAMPLE_RATE);
+        for frame in sine(1000.0, amplitude, 48000 * 10).chunks_mut(1920) {
+            normalizer.process(frame);
+            out.process(frame);
+        }
+        let lufs = out.short_term();
+        assert!(
+            (lufs + 14.0).abs() < 0.5,
+            "{amplitude} came out at {lufs} LUFS"
+        );
+    }
+
+    // the gap between songs isn't pumped up
+    let gain = normalizer.gain();
+    let mut silence = vec![0.0; 48000 * 6];
+    for frame in silence.chunks_mut(1920) {
+        normalizer.process(frame);
+    }
+    assert!(silence.iter().all(|&s| s == 0.0));
+    assert!((normalizer.gain() - gain).abs() < 0.05);
+}
+
+#[test]
+fn level_meter_reads_rms_and_holds_peak() {
+    let meter = LevelMeter::new();
+    assert_eq!(meter.rms(), 0.0);
+    assert_eq!(meter.rms_dbfs(), f32::NEG_INFINITY);
+
+    // 1 kHz fits a 20ms frame exactly, so the rms is amplitude / sqrt(2)
+    meter.process(&sine(1000.0, 0.5, 960));
+    assert!((meter.rms() - 0.5 * std::f32::consts::FRAC_1_SQRT_2).abs() < 1e-3);
+    assert!((meter.peak() - 0.5).abs() < 1e-3);
+    assert!((meter.peak_dbfs() + 6.02).abs() < 0.05);
+
+    // the rms drops right away, the peak falls off slowly
+    meter.process(&[0.0; 1920]);
+    assert_eq!(meter.rms(), 0.0);
+    assert!(meter.peak() > 0.4 && meter.peak() < 0.5);
+    for _ in 0..100 {
+        meter.process(&[0.0; 1920]);
+    }
+    assert!(meter.peak() < 1e-3);
+}
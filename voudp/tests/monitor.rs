@@ -0,0 +1,131 @@
+use std::{net::SocketAddr, sync::Arc, thread, time::Duration};
+
+use opus2::{Application, Channels, Encoder};
+use voudp::{
+    audit::AuditLog,
+    protocol::{self, ClientPacketType, ConsoleRole, FromPacket, MessageCode},
+    server::{ServerConfig, ServerState},
+    socket::{self, SecureUdpSocket},
+    storage::{FileStorage, Storage},
+    util::{self, CommandResponsePacket, CommandResult, LocalizedMessage},
+};
+
+mod common;
+
+use common::{recv_packet, temp_dir};
+
+const PHRASE: &[u8] = b"monitor-test";
+
+struct Peer {
+    socket: SecureUdpSocket,
+}
+
+impl Peer {
+    fn join(socket: SecureUdpSocket, server: SocketAddr, channel: u32, mask: &str) -> Self {
+        socket.connect(server).unwrap();
+
+        let mut join = vec![ClientPacketType::Join as u8];
+        join.extend_from_slice(&channel.to_be_bytes());
+        socket.send(&join).unwrap();
+        let mut nick = vec![ClientPacketType::Mask as u8];
+        nick.extend_from_slice(mask.as_bytes());
+        socket.send(&nick).unwrap();
+        thread::sleep(Duration::from_millis(50));
+
+        Self { socket }
+    }
+
+    fn command(&self, input: &str) -> CommandResult {
+        let mut packet = vec![ClientPacketType::Cmd as u8];
+        packet.extend_from_slice(input.as_bytes());
+        self.socket.send(&packet).unwrap();
+
+        let reply = recv_packet(&self.socket, ClientPacketType::CommandResponse as u8)
+            .expect("no command response");
+        CommandResponsePacket::deserialize(&reply).unwrap().result
+    }
+}
+
+#[test]
+fn admins_can_monitor_and_everyone_is_told() {
+    let storage: Arc<dyn Storage> = Arc::new(FileStorage::open(&temp_dir("monitor")));
+    let config = ServerConfig {
+        max_joins_per_sec: 0,
+        ..Default::default()
+    };
+    let server = ServerState::with_storage(config, PHRASE, storage.clone()).unwrap();
+    let addr = SocketAddr::from(([127, 0, 0, 1], server.local_addr().port()));
+    thread::spawn(move || {
+        let mut server = server;
+        server.run();
+    });
+
+    let key = socket::derive_key_from_phrase(PHRASE, protocol::VOUDP_SALT);
+    let new_socket = || SecureUdpSocket::create("127.0.0.1:0".into(), key).unwrap();
+
+    let operator = Peer::join(new_socket(), addr, protocol::DEFAULT_CHANNEL_ID, "mod");
+    let talker = Peer::join(new_socket(), addr, 2, "bob");
+
+    // plain users can't listen in
+    assert!(matches!(
+        operator.command("/monitor 2"),
+        CommandResult::Error(_)
+    ));
+
+    let console = new_socket();
+    let token =
+        util::console_login(&console, addr, protocol::PASSWORD, ConsoleRole::Admin).unwrap();
+    let op = protocol::create_console_command(&token, "op mod");
+    console.send_to(&op, addr).unwrap();
+    thread::sleep(Duration::from_millis(50));
+
+    assert!(matches!(
+        operator.command("/monitor bob"),
+        CommandResult::Success(_)
+    ));
+
+    // bob's channel hears about it, after the notice from joining
+    let notice = loop {
+        let notice = recv_packet(&talker.socket, ClientPacketType::Notice as u8)
+            .expect("channel was not told");
+        let notice = LocalizedMessage::deserialize(&notice).unwrap();
+        if notice.message_code() == Some(MessageCode::Monitored) {
+            break notice;
+        }
+    };
+    assert_eq!(notice.params, ["mod"]);
+
+    // bob talks alone in his channel, yet the operator gets his audio
+    let mut encoder = Encoder::new(48000, Channels::Stereo, Application::Audio).unwrap();
+    let tone = (0..960)
+        .flat_map(|n| {
+            let s = 0.3 * (2.0 * std::f32::consts::PI * 440.0 * n as f32 / 48000.0).sin();
+            [s, s]
+        })
+        .collect::<Vec<_>>();
+    let mut opus = vec![0u8; 400];
+    for _ in 0..10 {
+        let len = encoder.encode_float(&tone, &mut opus).unwrap();
+        talker
+            .socket
+            .send(&protocol::create_audio_packet(&opus[..len]))
+            .unwrap();
+        thread::sleep(Duration::from_millis(20));
+    }
+    assert!(recv_packet(&operator.socket, ClientPacketType::Audio as u8).is_some());
+
+    assert!(matches!(
+        operator.command("/monitor off"),
+        CommandResult::Success(_)
+    ));
+
+    // both uses are on record under the operator
+    let audit = AuditLog::new(storage);
+    let notes = audit
+        .notes("mod")
+        .iter()
+        .map(|note| note.text.as_str())
+        .collect::<Vec<_>>();
+    assert!(notes.contains(&"started monitoring bob"), "{notes:?}");
+    assert!(notes.contains(&"stopped monitoring"), "{notes:?}");
+}
@@ -0,0 +1,693 @@
+use std::{
+    fs,
+    io::{Read, Write},
+    net::{SocketAddr, TcpListener, UdpSocket},
+    path::{Path, PathBuf},
+    process,
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use voudp::{
+    music::{self, MusicClientState, MusicEvent, Repeat, TrackTags, Transport},
+    protocol::{self, ClientPacketType, FromPacket},
+    server::{ServerConfig, ServerState},
+    socket::{self, SecureUdpSocket},
+    util::ChatPacket,
+};
+
+const PHRASE: &[u8] = b"music-test";
+
+fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir()
+        .join(format!("voudp-music-{}", process::id()))
+        .join(name);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+// a 16 bit mono 440Hz tone, the server drops silence
+fn wav(secs: u32) -> Vec<u8> {
+    let data = (0..48000 * secs)
+        .flat_map(|n| {
+            let s = 0.3 * (2.0 * std::f32::consts::PI * 440.0 * n as f32 / 48000.0).sin();
+            ((s * i16::MAX as f32) as i16).to_le_bytes()
+        })
+        .collect::<Vec<_>>();
+    let mut wav = vec![];
+    wav.extend_from_slice(b"RIFF");
+    wav.extend_from_slice(&(36 + data.len() as u32).to_le_bytes());
+    wav.extend_from_slice(b"WAVEfmt ");
+    wav.extend_from_slice(&16u32.to_le_bytes());
+    wav.extend_from_slice(&1u16.to_le_bytes());
+    wav.extend_from_slice(&1u16.to_le_bytes());
+    wav.extend_from_slice(&48000u32.to_le_bytes());
+    wav.extend_from_slice(&(48000u32 * 2).to_le_bytes());
+    wav.extend_from_slice(&2u16.to_le_bytes());
+    wav.extend_from_slice(&16u16.to_le_bytes());
+    wav.extend_from_slice(b"data");
+    wav.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    wav.extend_from_slice(&data);
+    wav
+}
+
+// a wav with a RIFF INFO list in front of the audio
+fn tagged_wav(title: &str, artist: &str) -> Vec<u8> {
+    let mut info = b"INFO".to_vec();
+    for (id, value) in [(b"INAM", title), (b"IART", artist)] {
+        let mut value = value.as_bytes().to_vec();
+        value.push(0);
+        if value.len() % 2 == 1 {
+            value.push(0);
+        }
+        info.extend_from_slice(id);
+        info.extend_from_slice(&(value.len() as u32).to_le_bytes());
+        info.extend_from_slice(&value);
+    }
+
+    let plain = wav(1);
+    let mut tagged = plain[..36].to_vec();
+    tagged.extend_from_slice(b"LIST");
+    tagged.extend_from_slice(&(info.len() as u32).to_le_bytes());
+    tagged.extend_from_slice(&info);
+    tagged.extend_from_slice(&plain[36..]);
+    let riff_len = (tagged.len() - 8) as u32;
+    tagged[4..8].copy_from_slice(&riff_len.to_le_bytes());
+    tagged
+}
+
+#[test]
+fn tags_name_the_track() {
+    let dir = temp_dir("tags");
+    let path = dir.join("track01.wav");
+    fs::write(&path, tagged_wav("Blue Monday", "New Order")).unwrap();
+
+    let tags = TrackTags::read(&path).unwrap();
+    assert_eq!(tags.title.as_deref(), Some("Blue Monday"));
+    assert_eq!(tags.describe().as_deref(), Some("New Order - Blue Monday"));
+
+    let untagged = dir.join("track02.wav");
+    fs::write(&untagged, wav(1)).unwrap();
+    assert_eq!(TrackTags::read(&untagged).unwrap().describe(), None);
+
+    let title_only = TrackTags {
+        title: Some("Intro".into()),
+        artist: Some(" ".into()),
+        album: None,
+    };
+    assert_eq!(title_only.describe().as_deref(), Some("Intro"));
+    fs::remove_dir_all(dir).ok();
+}
+
+#[test]
+fn transport_commands_parse() {
+    assert_eq!(Transport::parse("pause"), Some(Transport::Pause));
+    assert_eq!(Transport::parse(" skip "), Some(Transport::Skip));
+    assert_eq!(
+        Transport::parse("seek 90"),
+        Some(Transport::Seek(Duration::from_secs(90)))
+    );
+    assert_eq!(
+        Transport::parse("seek 1:30"),
+        Some(Transport::Seek(Duration::from_secs(90)))
+    );
+    assert_eq!(Transport::parse("seek"), None);
+    assert_eq!(Transport::parse("rewind"), None);
+    assert_eq!(Transport::parse("loop"), Some(Transport::Loop(None)));
+    assert_eq!(
+        Transport::parse("loop track"),
+        Some(Transport::Loop(Some(Repeat::Track)))
+    );
+    assert_eq!(
+        Transport::parse("loop off"),
+        Some(Transport::Loop(Some(Repeat::Off)))
+    );
+    assert_eq!(Transport::parse("loop forever"), None);
+}
+
+#[test]
+fn a_looped_track_plays_until_the_loop_is_turned_off() {
+    let server = ServerState::new(ServerConfig::default(), PHRASE).expect("server should bind");
+    let addr = SocketAddr::from(([127, 0, 0, 1], server.local_addr().port()));
+    thread::spawn(move || {
+        let mut server = server;
+        server.run();
+    });
+
+    let dir = temp_dir("loop");
+    let track = dir.join("ambience.wav");
+    fs::write(&track, wav(1)).unwrap();
+    let mut client = MusicClientState::new(&addr.to_string(), 1, PHRASE).unwrap();
+    client.set_repeat(Repeat::Track);
+    let transport = client.transport();
+    let player = thread::spawn(move || client.run(vec![track]));
+
+    thread::sleep(Duration::from_millis(2500));
+    assert!(!player.is_finished(), "a one second track wasn't looped");
+
+    transport.send(Transport::Loop(Some(Repeat::Off))).unwrap();
+    let turned_off = Instant::now();
+    player.join().unwrap().unwrap();
+    assert!(turned_off.elapsed() < Duration::from_millis(1500));
+    fs::remove_dir_all(dir).ok();
+}
+
+#[test]
+fn tracks_can_be_skipped_and_stopped() {
+    let server = ServerState::new(ServerConfig::default(), PHRASE).expect("server should bind");
+    let addr = SocketAddr::from(([127, 0, 0, 1], server.local_addr().port()));
+    thread::spawn(move || {
+        let mut server = server;
+        server.run();
+    });
+
+    let dir = temp_dir("transport");
+    let tracks = (0..3)
+        .map(|n| {
+            let path = dir.join(format!("{n}.wav"));
+            fs::write(&path, wav(5)).unwrap();
+            path
+        })
+        .collect::<Vec<_>>();
+
+    let mut client = MusicClientState::new(&addr.to_string(), 1, PHRASE).unwrap();
+    let transport = client.transport();
+    let started = Instant::now();
+    let player = thread::spawn(move || client.run(tracks));
+
+    thread::sleep(Duration::from_millis(200));
+    transport.send(Transport::Pause).unwrap();
+    transport
+        .send(Transport::Seek(Duration::from_secs(4)))
+        .unwrap();
+    transport.send(Transport::Resume).unwrap();
+    transport.send(Transport::Skip).unwrap();
+    thread::sleep(Duration::from_millis(200));
+    transport.send(Transport::Stop).unwrap();
+
+    player.join().unwrap().unwrap();
+    // fifteen seconds of music, cut short
+    assert!(started.elapsed() < Duration::from_secs(2));
+    fs::remove_dir_all(dir).ok();
+}
+
+#[test]
+fn playlists_resolve_next_to_themselves() {
+    let dir = temp_dir("playlist");
+    let playlist = dir.join("mix.m3u");
+    fs::write(
+        &playlist,
+        "#EXTM3U\n\
+         #EXTINF:123,Someone - First\n\
+         first.flac\n\
+         \n\
+         albums/second.mp3\n\
+         /srv/music/third.ogg\n\
+         http://radio.example.org:8000/live\n",
+    )
+    .unwrap();
+
+    let expected = [
+        dir.join("first.flac"),
+        dir.join("albums/second.mp3"),
+        PathBuf::from("/srv/music/third.ogg"),
+        PathBuf::from("http://radio.example.org:8000/live"),
+    ];
+    assert_eq!(music::read_playlist(&playlist).unwrap(), expected);
+    assert_eq!(music::tracks(&playlist).unwrap(), expected);
+    fs::remove_dir_all(dir).ok();
+}
+
+#[test]
+fn directories_play_in_name_order() {
+    let dir = temp_dir("album");
+    for name in ["02 b.wav", "01 a.wav", "03 c.wav"] {
+        fs::write(dir.join(name), b"").unwrap();
+    }
+    fs::create_dir_all(dir.join("scans")).unwrap();
+
+    assert_eq!(
+        music::tracks(&dir).unwrap(),
+        [
+            dir.join("01 a.wav"),
+            dir.join("02 b.wav"),
+            dir.join("03 c.wav")
+        ]
+    );
+    let single = dir.join("01 a.wav");
+    assert_eq!(music::tracks(&single).unwrap(), vec![single]);
+    assert!(music::read_playlist(&dir.join("missing.m3u")).is_err());
+    fs::remove_dir_all(dir).ok();
+}
+
+#[test]
+fn web_radio_is_streamed_over_http() {
+    let server = ServerState::new(ServerConfig::default(), PHRASE).expect("server should bind");
+    let addr = SocketAddr::from(([127, 0, 0, 1], server.local_addr().port()));
+    thread::spawn(move || {
+        let mut server = server;
+        server.run();
+    });
+
+    // a station that moved, answering like shoutcast does
+    let radio = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}/stream", radio.local_addr().unwrap());
+    thread::spawn(move || {
+        let responses = [
+            b"HTTP/1.1 302 Found\r\nLocation: /live\r\n\r\n".to_vec(),
+            [
+                b"ICY 200 OK\r\nContent-Type: audio/wav\r\n\r\n".to_vec(),
+                wav(1),
+            ]
+            .concat(),
+        ];
+        for response in responses {
+            let (mut stream, _) = radio.accept().unwrap();
+            let mut request = vec![];
+            let mut buf = [0u8; 256];
+            while !request.ends_with(b"\r\n\r\n") {
+                let len = stream.read(&mut buf).unwrap();
+                request.extend_from_slice(&buf[..len]);
+            }
+            stream.write_all(&response).unwrap();
+        }
+    });
+
+    assert_eq!(
+        music::tracks(Path::new(&url)).unwrap(),
+        [PathBuf::from(&url)]
+    );
+    let mut client = MusicClientState::new(&addr.to_string(), 1, PHRASE).unwrap();
+    let started = Instant::now();
+    client.run(vec![PathBuf::from(url)]).unwrap();
+    // played in real time, not skipped as an error
+    let elapsed = started.elapsed();
+    assert!(elapsed > Duration::from_millis(800), "{elapsed:?}");
+}
+
+#[test]
+fn piped_pcm_is_decoded_and_paced() {
+    let mut samples = vec![];
+    music::PcmFormat::S16Le.decode(&[0x00, 0x40, 0x00, 0xc0, 0xff], &mut samples);
+    // the torn last sample is left out
+    assert_eq!(samples, [0.5, -0.5]);
+    music::PcmFormat::F32Le.decode(&0.25f32.to_le_bytes(), &mut samples);
+    assert_eq!(samples, [0.25]);
+    assert_eq!(
+        music::PcmFormat::by_name("f32le"),
+        Some(music::PcmFormat::F32Le)
+    );
+    assert_eq!(music::PcmFormat::by_name("mp3"), None);
+
+    let server = ServerState::new(ServerConfig::default(), PHRASE).expect("server should bind");
+    let addr = SocketAddr::from(([127, 0, 0, 1], server.local_addr().port()));
+    thread::spawn(move || {
+        let mut server = server;
+        server.run();
+    });
+
+    // half a second of 24kHz mono, as ffmpeg would pipe it
+    let pcm = vec![0u8; 24000 / 2 * 2];
+    let mut client = MusicClientState::new(&addr.to_string(), 1, PHRASE).unwrap();
+    let started = Instant::now();
+    client
+        .run_pcm(pcm.as_slice(), music::PcmFormat::S16Le, 24000, 1)
+        .unwrap();
+    let elapsed = started.elapsed();
+    assert!(
+        (Duration::from_millis(400)..Duration::from_millis(900)).contains(&elapsed),
+        "{elapsed:?}"
+    );
+    assert!(
+        client
+            .run_pcm(pcm.as_slice(), music::PcmFormat::S16Le, 48000, 6)
+            .is_err()
+    );
+}
+
+#[test]
+fn a_paused_player_stays_connected() {
+    let config = ServerConfig {
+        // a little over the player's keepalive
+        timeout_secs: 2,
+        max_joins_per_sec: 0,
+        ..Default::default()
+    };
+    let server = ServerState::new(config, PHRASE).expect("server should bind");
+    let addr = SocketAddr::from(([127, 0, 0, 1], server.local_addr().port()));
+    thread::spawn(move || {
+        let mut server = server;
+        server.run();
+    });
+
+    let wrong = MusicClientState::new(&addr.to_string(), 1, b"not-the-phrase").unwrap();
+    assert!(wrong.handshake(Duration::from_millis(500)).is_err());
+
+    let dir = temp_dir("keepalive");
+    let track = dir.join("long.wav");
+    fs::write(&track, wav(10)).unwrap();
+    // deriving the key takes long enough for the listener to time out
+    let mut client = MusicClientState::new(&addr.to_string(), 1, PHRASE).unwrap();
+    client.handshake(Duration::from_secs(3)).unwrap();
+
+    let key = socket::derive_key_from_phrase(PHRASE, protocol::VOUDP_SALT);
+    let listener = SecureUdpSocket::create("127.0.0.1:0".into(), key).unwrap();
+    listener.connect(addr).unwrap();
+    let mut join = vec![ClientPacketType::Join as u8];
+    join.extend_from_slice(&1u32.to_be_bytes());
+    listener.send(&join).unwrap();
+
+    // whether the player is heard for `secs`, the listener keeping itself alive
+    let listen = |secs| {
+        let deadline = Instant::now() + Duration::from_secs(secs);
+        let mut buf = [0u8; 2048];
+        let mut last_ping = Instant::now();
+        let mut heard = false;
+        while Instant::now() < deadline {
+            if last_ping.elapsed() > Duration::from_millis(300) {
+                listener
+                    .send(&protocol::create_list_page_request(0, 0, 0))
+                    .unwrap();
+                last_ping = Instant::now();
+            }
+            match listener.recv_from(&mut buf) {
+                Ok((size, _)) if size > 1 && buf[0] == ClientPacketType::Audio as u8 => {
+                    heard = true;
+                }
+                Ok(_) => {}
+                Err(_) => thread::sleep(Duration::from_millis(2)),
+            }
+        }
+        heard
+    };
+
+    let transport = client.transport();
+    let player = thread::spawn(move || client.run(vec![track]));
+    thread::sleep(Duration::from_millis(200));
+    transport.send(Transport::Pause).unwrap();
+
+    listen(3);
+    transport.send(Transport::Resume).unwrap();
+    assert!(listen(1), "the player timed out while paused");
+
+    transport.send(Transport::Stop).unwrap();
+    player.join().unwrap().unwrap();
+    fs::remove_dir_all(dir).ok();
+}
+
+#[test]
+fn requests_stay_inside_the_library() {
+    let library = temp_dir("library");
+    fs::create_dir_all(library.join("album")).unwrap();
+    for name in ["one.wav", "album/a.wav", "album/b.wav"] {
+        fs::write(library.join(name), wav(1)).unwrap();
+    }
+
+    assert_eq!(
+        music::request(&library, "one.wav").unwrap(),
+        [library.join("one.wav")]
+    );
+    assert_eq!(
+        music::request(&library, "album").unwrap(),
+        [library.join("album/a.wav"), library.join("album/b.wav")]
+    );
+    assert_eq!(
+        music::request(&library, "http://radio.example.org/live").unwrap(),
+        [PathBuf::from("http://radio.example.org/live")]
+    );
+
+    for outside in ["../music-test", "/etc/passwd", "album/../../one.wav"] {
+        let err = music::request(&library, outside).unwrap_err();
+        assert!(err.to_string().contains("isn't in the library"), "{err}");
+    }
+    assert!(music::request(&library, "two.wav").is_err());
+    fs::remove_dir_all(library).ok();
+}
+
+#[test]
+fn listeners_queue_tracks_over_chat() {
+    let config = ServerConfig {
+        max_joins_per_sec: 0,
+        ..Default::default()
+    };
+    let server = ServerState::new(config, PHRASE).expect("server should bind");
+    let addr = SocketAddr::from(([127, 0, 0, 1], server.local_addr().port()));
+    thread::spawn(move || {
+        let mut server = server;
+        server.run();
+    });
+
+    let library = temp_dir("requests");
+    fs::write(library.join("tone.wav"), wav(1)).unwrap();
+    let mut client = MusicClientState::new(&addr.to_string(), 1, PHRASE).unwrap();
+    client.set_library(library.clone());
+    let transport = client.transport();
+    // nothing to play until someone asks
+    let player = thread::spawn(move || client.run(vec![]));
+
+    let key = socket::derive_key_from_phrase(PHRASE, protocol::VOUDP_SALT);
+    let alice = SecureUdpSocket::create("127.0.0.1:0".into(), key).unwrap();
+    alice.connect(addr).unwrap();
+    let mut join = vec![ClientPacketType::Join as u8];
+    join.extend_from_slice(&1u32.to_be_bytes());
+    alice.send(&join).unwrap();
+    let mut mask = vec![ClientPacketType::Mask as u8];
+    mask.extend_from_slice(b"alice");
+    alice.send(&mask).unwrap();
+    // past the greeting
+    let mut buf = [0u8; 2048];
+    let deadline = Instant::now() + Duration::from_millis(500);
+    while Instant::now() < deadline {
+        alice.tick_reliable();
+        if alice.recv_from(&mut buf).is_err() {
+            thread::sleep(Duration::from_millis(2));
+        }
+    }
+
+    // what the player says back to `message`, and whether it's heard meanwhile
+    let ask = |message: &str| {
+        let mut chat = vec![ClientPacketType::Chat as u8];
+        chat.extend_from_slice(message.as_bytes());
+        alice.send(&chat).unwrap();
+
+        let deadline = Instant::now() + Duration::from_secs(2);
+        let mut buf = [0u8; 2048];
+        let mut reply = None;
+        let mut heard = false;
+        while Instant::now() < deadline {
+            alice.tick_reliable();
+            match alice.recv_from(&mut buf) {
+                Ok((size, _)) if size > 1 && buf[0] == ClientPacketType::Audio as u8 => {
+                    heard = true;
+                }
+                Ok((size, _)) => {
+                    if let Ok(chat) = ChatPacket::deserialize(&buf[..size])
+                        && !chat.is_self
+                    {
+                        reply.get_or_insert(chat.message);
+                    }
+                }
+                Err(_) => thread::sleep(Duration::from_millis(2)),
+            }
+        }
+        (reply.expect("the player never answered"), heard)
+    };
+
+    let (reply, _) = ask("#play ../tone.wav");
+    assert!(reply.contains("isn't in the library"), "{reply}");
+    let (reply, heard) = ask("#play tone.wav");
+    assert!(reply.contains("queued tone.wav"), "{reply}");
+    assert!(heard, "the request was never played");
+    let (reply, _) = ask("#queue");
+    assert!(reply.contains("nothing's queued"), "{reply}");
+
+    transport.send(Transport::Stop).unwrap();
+    player.join().unwrap().unwrap();
+    fs::remove_dir_all(library).ok();
+}
+
+// hands out its audio, but goes quiet for a while halfway through
+struct Stalling {
+    pcm: Vec<u8>,
+    stalled: bool,
+}
+
+impl Read for Stalling {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        if !self.stalled && self.pcm.len() <= 48000 {
+            self.stalled = true;
+            thread::sleep(Duration::from_millis(300));
+        }
+        let len = buf.len().min(self.pcm.len());
+        buf[..len].copy_from_slice(&self.pcm[..len]);
+        self.pcm.drain(..len);
+        Ok(len)
+    }
+}
+
+#[test]
+fn progress_and_underruns_are_reported() {
+    let server = ServerState::new(ServerConfig::default(), PHRASE).expect("server should bind");
+    let addr = SocketAddr::from(([127, 0, 0, 1], server.local_addr().port()));
+    thread::spawn(move || {
+        let mut server = server;
+        server.run();
+    });
+
+    let dir = temp_dir("progress");
+    let track = dir.join("tone.wav");
+    fs::write(&track, wav(2)).unwrap();
+    let mut client = MusicClientState::new(&addr.to_string(), 1, PHRASE).unwrap();
+    let events = client.events().unwrap();
+    assert!(client.events().is_none());
+    client.run(vec![track]).unwrap();
+
+    let progress = events
+        .try_iter()
+        .filter_map(|event| match event {
+            MusicEvent::Progress(progress) => Some(progress),
+            MusicEvent::Underrun(_) => None,
+        })
+        .collect::<Vec<_>>();
+    let first = progress.first().expect("no progress was reported");
+    assert_eq!(first.track, "tone.wav");
+    assert_eq!(first.total, Some(Duration::from_secs(2)));
+    let ms = first.elapsed.as_millis();
+    assert!(ms.abs_diff(1000) <= 40, "{ms} ms in");
+    assert!(first.bitrate > 0);
+    assert!(first.packets_sent.abs_diff(50) <= 2);
+
+    // a second of 48kHz mono that stalls after half of it
+    client
+        .run_pcm(
+            Stalling {
+                pcm: wav(1)[44..].to_vec(),
+                stalled: false,
+            },
+            music::PcmFormat::S16Le,
+            48000,
+            1,
+        )
+        .unwrap();
+    let underruns = events
+        .try_iter()
+        .filter_map(|event| match event {
+            MusicEvent::Underrun(behind) => Some(behind),
+            MusicEvent::Progress(_) => None,
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(underruns.len(), 1, "{underruns:?}");
+    assert!(underruns[0] > Duration::from_millis(200), "{underruns:?}");
+    fs::remove_dir_all(dir).ok();
+}
+
+// passes datagrams between one client and `server`, or drops them all while
+// `blackout` is set
+fn relay(server: SocketAddr, blackout: Arc<AtomicBool>) -> SocketAddr {
+    let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+    let addr = socket.local_addr().unwrap();
+    thread::spawn(move || {
+        let mut client = None;
+        let mut buf = [0u8; 4096];
+        while let Ok((size, from)) = socket.recv_from(&mut buf) {
+            if blackout.load(Ordering::Relaxed) {
+                continue;
+            }
+            let to = if from == server {
+                client
+            } else {
+                client = Some(from);
+                Some(server)
+            };
+            if let Some(to) = to {
+                let _ = socket.send_to(&buf[..size], to);
+            }
+        }
+    });
+    addr
+}
+
+#[test]
+fn the_player_rejoins_and_resumes_after_being_dropped() {
+    let config = ServerConfig {
+        timeout_secs: 1,
+        max_joins_per_sec: 0,
+        ..Default::default()
+    };
+    let server = ServerState::new(config, PHRASE).expect("server should bind");
+    let addr = SocketAddr::from(([127, 0, 0, 1], server.local_addr().port()));
+    thread::spawn(move || {
+        let mut server = server;
+        server.run();
+    });
+
+    let blackout = Arc::new(AtomicBool::new(false));
+    let relayed = relay(addr, blackout.clone());
+    let dir = temp_dir("rejoin");
+    let track = dir.join("long.wav");
+    fs::write(&track, wav(8)).unwrap();
+    let mut client = MusicClientState::new(&relayed.to_string(), 1, PHRASE).unwrap();
+    let events = client.events().unwrap();
+
+    let key = socket::derive_key_from_phrase(PHRASE, protocol::VOUDP_SALT);
+    let listener = SecureUdpSocket::create("127.0.0.1:0".into(), key).unwrap();
+    listener.connect(addr).unwrap();
+    let mut join = vec![ClientPacketType::Join as u8];
+    join.extend_from_slice(&1u32.to_be_bytes());
+    listener.send(&join).unwrap();
+
+    // whether the player is heard within `secs`, the listener keeping itself alive
+    let listen = |secs| {
+        let deadline = Instant::now() + Duration::from_secs(secs);
+        let mut buf = [0u8; 2048];
+        let mut last_ping = Instant::now();
+        let mut heard = false;
+        while Instant::now() < deadline {
+            if last_ping.elapsed() > Duration::from_millis(300) {
+                listener
+                    .send(&protocol::create_list_page_request(0, 0, 0))
+                    .unwrap();
+                last_ping = Instant::now();
+            }
+            match listener.recv_from(&mut buf) {
+                Ok((size, _)) if size > 1 && buf[0] == ClientPacketType::Audio as u8 => {
+                    heard = true;
+                }
+                Ok(_) => {}
+                Err(_) => thread::sleep(Duration::from_millis(2)),
+            }
+        }
+        heard
+    };
+
+    let started = Instant::now();
+    let player = thread::spawn(move || client.run(vec![track]));
+    assert!(listen(1));
+
+    // long enough for the server to drop the player
+    blackout.store(true, Ordering::Relaxed);
+    listen(2);
+    blackout.store(false, Ordering::Relaxed);
+    assert!(listen(3), "the player never came back");
+
+    player.join().unwrap().unwrap();
+    // what played into the blackout was played again
+    assert!(
+        started.elapsed() > Duration::from_millis(9500),
+        "{:?}",
+        started.elapsed()
+    );
+    let last = events
+        .try_iter()
+        .filter_map(|event| match event {
+            MusicEvent::Progress(progress) => Some(progress.elapsed),
+            MusicEvent::Underrun(_) => None,
+        })
+        .collect::<Vec<_>>();
+    assert!(last.windows(2).any(|w| w[1] < w[0]), "{last:?}");
+    fs::remove_dir_all(dir).ok();
+}
@@ -0,0 +1,974 @@
+use std::{
+    fs,
+    io::{Read, Write},
+    net::{SocketAddr, TcpListener},
+    process,
+    sync::{
+        Arc,
+        atomic::{AtomicUsize, Ordering},
+        mpsc,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use voudp::{
+    native_plugin::{JoinEvent, VoudpPlugin},
+    plugin::{self, DirectoryChannel, DirectoryUser, PluginAction, PluginManager},
+    protocol::{self, ClientPacketType, FromPacket, IntoPacket, MessageCode},
+    server::{Role, ServerConfig, ServerState},
+    socket::{self, SecureUdpSocket},
+    util::{ChatPacket, CommandContext, CommandResult, LocalizedMessage},
+};
+
+#[test]
+fn audio_plugins_change_or_cancel_frames() {
+    let dir = std::env::temp_dir().join(format!("voudp-plugin-{}", process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("levels.lua");
+    fs::write(
+        &path,
+        r#"
+plugin = { name = "Levels", capabilities = { "audio", "chat" } }
+
+function on_audio(ctx)
+    local mask = ctx:get_mask()
+    if mask == "loud" then
+        ctx:scale(0.5)
+        if ctx:get_rms() > 0.2 then
+            ctx:reply("still too loud")
+        end
+    elseif mask == "bleeped" then
+        for i = 1, ctx:len() do
+            ctx:set(i, (i % 2) * 0.1)
+        end
+    elseif mask == "banned" then
+        ctx:cancel()
+    end
+end
+"#,
+    )
+    .unwrap();
+
+    let (tx, rx) = mpsc::channel();
+    let mut plugins = PluginManager::new(tx);
+    let mut frame = vec![0.8; 8];
+    // nothing loaded, nothing touched
+    assert!(plugins.dispatch_audio("loud", &mut frame));
+    assert_eq!(frame, [0.8; 8]);
+
+    plugins.load_plugin(&path);
+    assert!(plugins.dispatch_audio("loud", &mut frame));
+    assert_eq!(frame, [0.4; 8]);
+    match rx.try_recv() {
+        Ok(PluginAction::Reply { to, msg }) => {
+            assert_eq!(to, "loud");
+            assert_eq!(msg, "still too loud");
+        }
+        _ => panic!("the plugin never replied"),
+    }
+
+    assert!(plugins.dispatch_audio("bleeped", &mut frame));
+    assert_eq!(frame, [0.1, 0.0, 0.1, 0.0, 0.1, 0.0, 0.1, 0.0]);
+    assert!(!plugins.dispatch_audio("banned", &mut frame));
+    assert!(plugins.dispatch_audio("someone", &mut frame));
+    fs::remove_dir_all(dir).ok();
+}
+
+#[test]
+fn plugins_register_and_run_commands() {
+    let dir = std::env::temp_dir().join(format!("voudp-commands-{}", process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("dice.lua");
+    fs::write(
+        &path,
+        r#"
+plugin = { name = "Dice", capabilities = { "chat" } }
+
+Core.register_command("roll", "/roll [sides]", false, function(ctx)
+    local sides = tonumber(ctx:get_args()[1] or "6")
+    if not sides or sides < 1 then
+        error("sides must be a positive number")
+    end
+    ctx:reply("rolling for " .. (ctx:get_mask() or "someone"))
+    return "rolled " .. math.min(sides, 4)
+end)
+
+Core.register_command("/wipe", "/wipe", true, function(ctx) end)
+
+function on_join(ctx)
+    Core.register_command("/late", "/late", false, function(ctx) end)
+end
+"#,
+    )
+    .unwrap();
+
+    let (tx, rx) = mpsc::channel();
+    let mut plugins = PluginManager::new(tx);
+    plugins.load_plugin(&path);
+
+    let mut names = plugins
+        .commands()
+        .map(|command| (command.name.as_str(), command.admin_only))
+        .collect::<Vec<_>>();
+    names.sort();
+    assert_eq!(names, [("/roll", false), ("/wipe", true)]);
+
+    let ctx = |args: &[&str]| CommandContext {
+        sender_addr: "127.0.0.1:4000".parse().unwrap(),
+        sender_mask: Some("bob".into()),
+        channel_id: 1,
+        arguments: args.iter().map(|arg| arg.to_string()).collect(),
+        role: Role::User,
+    };
+    assert!(matches!(
+        plugins.dispatch_command("/roll", &ctx(&["20"])),
+        Some(CommandResult::Success(reply)) if reply == "rolled 4"
+    ));
+    match rx.try_recv() {
+        Ok(PluginAction::ReplyByAddr { msg, .. }) => assert_eq!(msg, "rolling for bob"),
+        _ => panic!("the command never replied"),
+    }
+    assert!(matches!(
+        plugins.dispatch_command("/roll", &ctx(&["many"])),
+        Some(CommandResult::Error(e)) if e == "sides must be a positive number"
+    ));
+    assert_eq!(
+        plugins.dispatch_command("/wipe", &ctx(&[])),
+        Some(CommandResult::permission_denied("/wipe is for admins only"))
+    );
+    let admin = CommandContext {
+        role: Role::Admin,
+        ..ctx(&[])
+    };
+    assert_eq!(
+        plugins.dispatch_command("/wipe", &admin),
+        Some(CommandResult::Silent)
+    );
+    assert!(plugins.dispatch_command("/help", &ctx(&[])).is_none());
+
+    // too late to add any
+    assert_eq!(
+        plugins.dispatch_join("127.0.0.1:4000".parse().unwrap(), 1, None, false, 0),
+        Some(1)
+    );
+    assert_eq!(plugins.commands().count(), 2);
+
+    plugins.unregister_command("/wipe");
+    assert!(plugins.dispatch_command("/wipe", &ctx(&[])).is_none());
+    fs::remove_dir_all(dir).ok();
+}
+
+#[test]
+fn plugin_timers_fire_until_cancelled() {
+    let dir = std::env::temp_dir().join(format!("voudp-timers-{}", process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("announcer.lua");
+    fs::write(
+        &path,
+        r#"
+plugin = { name = "Announcer", capabilities = { "chat" } }
+
+local n = 0
+local ticker = Core.every(0.1, function()
+    n = n + 1
+    Core.broadcast("tick " .. n)
+end)
+
+local once = Core.schedule(0.25, function()
+    Core.cancel(ticker)
+    Core.broadcast("stopped")
+end)
+
+Core.schedule(0.5, function()
+    Core.broadcast("done " .. tostring(Core.cancel(once)))
+end)
+"#,
+    )
+    .unwrap();
+
+    let (tx, rx) = mpsc::channel();
+    let mut plugins = PluginManager::new(tx);
+    plugins.load_plugin(&path);
+
+    let deadline = Instant::now() + Duration::from_millis(800);
+    while Instant::now() < deadline {
+        plugins.run_timers();
+        thread::sleep(Duration::from_millis(5));
+    }
+
+    let said = rx
+        .try_iter()
+        .map(|action| match action {
+            PluginAction::Broadcast { msg, .. } => msg,
+            _ => panic!("only broadcasts were expected"),
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(said, ["tick 1", "tick 2", "stopped", "done false"]);
+    fs::remove_dir_all(dir).ok();
+}
+
+#[test]
+fn on_tick_runs_once_a_second() {
+    let dir = std::env::temp_dir().join(format!("voudp-tick-{}", process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("clock.lua");
+    fs::write(
+        &path,
+        r#"
+plugin = { name = "Clock", capabilities = { "chat" } }
+
+function on_tick(seconds)
+    Core.broadcast("up for " .. seconds .. "s")
+end
+"#,
+    )
+    .unwrap();
+
+    let (tx, rx) = mpsc::channel();
+    let mut plugins = PluginManager::new(tx);
+    plugins.load_plugin(&path);
+
+    let deadline = Instant::now() + Duration::from_millis(2300);
+    while Instant::now() < deadline {
+        plugins.run_timers();
+        thread::sleep(Duration::from_millis(5));
+    }
+
+    let said = rx
+        .try_iter()
+        .map(|action| match action {
+            PluginAction::Broadcast { msg, .. } => msg,
+            _ => panic!("only broadcasts were expected"),
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(said, ["up for 1s", "up for 2s"]);
+    fs::remove_dir_all(dir).ok();
+}
+
+#[test]
+fn plugins_reload_from_their_directory() {
+    let dir = std::env::temp_dir().join(format!("voudp-reload-{}", process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let greeter = |greeting: &str| {
+        format!(
+            "plugin = {{ name = \"Greeter\", capabilities = {{ \"chat\" }} }}\n\
+             function on_message(ctx) Core.broadcast(\"{greeting}\") end\n"
+        )
+    };
+    fs::write(dir.join("greeter.lua"), greeter("hi")).unwrap();
+    fs::write(dir.join("notes.txt"), "not a plugin").unwrap();
+
+    let (tx, rx) = mpsc::channel();
+    let mut plugins = PluginManager::new(tx);
+    plugins.load_dir(&dir);
+    assert_eq!(plugins.names(), ["Greeter"]);
+
+    let said = |plugins: &PluginManager| {
+        plugins.dispatch_message("bob", 1, "hello");
+        match rx.try_recv() {
+            Ok(PluginAction::Broadcast { msg, .. }) => msg,
+            _ => panic!("the plugin said nothing"),
+        }
+    };
+    assert_eq!(said(&plugins), "hi");
+
+    fs::write(dir.join("greeter.lua"), greeter("hello there")).unwrap();
+    plugins.reload_plugin("greeter").unwrap();
+    assert_eq!(said(&plugins), "hello there");
+
+    // a broken edit keeps the last good one running
+    fs::write(dir.join("greeter.lua"), "plugin = {").unwrap();
+    assert!(plugins.reload_plugin("Greeter").is_err());
+    assert_eq!(said(&plugins), "hello there");
+    assert!(plugins.reload_plugin("nobody").is_err());
+
+    fs::write(dir.join("greeter.lua"), greeter("hey")).unwrap();
+    fs::write(dir.join("extra.lua"), "plugin = { name = \"Extra\" }\n").unwrap();
+    plugins.reload_all();
+    assert_eq!(plugins.names(), ["Extra", "Greeter"]);
+    assert_eq!(said(&plugins), "hey");
+    fs::remove_dir_all(dir).ok();
+}
+
+#[test]
+fn plugin_directories_follow_their_manifest() {
+    let dir = std::env::temp_dir().join(format!("voudp-manifest-{}", process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    for name in ["alpha", "beta", "gamma"] {
+        fs::write(
+            dir.join(format!("{name}.lua")),
+            format!("plugin = {{ name = \"{name}\" }}\n"),
+        )
+        .unwrap();
+    }
+    fs::write(dir.join("broken.lua"), "plugin = {").unwrap();
+    fs::write(dir.join("core.d.lua"), "plugin = { name = \"Core\" }\n").unwrap();
+    fs::write(
+        dir.join(plugin::MANIFEST_FILE),
+        "# gamma goes first\ngamma\n!beta.lua\nghost.lua\n",
+    )
+    .unwrap();
+
+    let (tx, _rx) = mpsc::channel();
+    let mut plugins = PluginManager::new(tx);
+    plugins.load_dir(&dir);
+    assert_eq!(plugins.names(), ["gamma", "alpha"]);
+
+    let skipped = plugins
+        .skipped()
+        .iter()
+        .map(|(file, reason)| (file.as_str(), reason.split(':').next().unwrap()))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        skipped,
+        [
+            ("ghost.lua", "listed in plugins.txt but missing"),
+            ("beta.lua", "disabled in plugins.txt"),
+            ("broken.lua", "failed to load"),
+            ("core.d.lua", "type definitions"),
+        ]
+    );
+
+    // reloading reads the manifest again
+    fs::remove_file(dir.join(plugin::MANIFEST_FILE)).unwrap();
+    plugins.reload_all();
+    assert_eq!(plugins.names(), ["alpha", "beta", "gamma"]);
+    assert_eq!(plugins.skipped().len(), 2);
+    fs::remove_dir_all(dir).ok();
+}
+
+#[test]
+fn plugins_broadcast_to_a_channel_or_everyone() {
+    let dir = std::env::temp_dir().join(format!("voudp-broadcast-{}", process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("echo.lua");
+    fs::write(
+        &path,
+        r#"
+plugin = { name = "Echo", capabilities = { "chat" } }
+
+function on_message(ctx)
+    ctx:broadcast(ctx:get_username() .. " said " .. ctx:get_message())
+    Core.broadcast("and everyone heard it")
+end
+"#,
+    )
+    .unwrap();
+
+    let (tx, rx) = mpsc::channel();
+    let mut plugins = PluginManager::new(tx);
+    plugins.load_plugin(&path);
+    assert!(plugins.dispatch_message("bob", 3, "hi"));
+
+    let said = rx
+        .try_iter()
+        .map(|action| match action {
+            PluginAction::Broadcast {
+                from,
+                channel_id,
+                msg,
+            } => (from, channel_id, msg),
+            _ => panic!("only broadcasts were expected"),
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(
+        said,
+        [
+            ("Echo".to_string(), Some(3), "bob said hi".to_string()),
+            (
+                "Echo".to_string(),
+                None,
+                "and everyone heard it".to_string()
+            ),
+        ]
+    );
+
+    // they reach clients as chat from the plugin
+    let chat = ChatPacket {
+        username: "Echo".into(),
+        message: "bob said hi".into(),
+        is_self: false,
+    };
+    let parsed = ChatPacket::deserialize(&chat.serialize()).unwrap();
+    assert_eq!(parsed.username, "Echo");
+    assert_eq!(parsed.message, "bob said hi");
+    assert!(!parsed.is_self);
+    fs::remove_dir_all(dir).ok();
+}
+
+#[test]
+fn join_plugins_see_who_is_joining_and_can_move_them() {
+    let dir = std::env::temp_dir().join(format!("voudp-gatekeeper-{}", process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("gatekeeper.lua");
+    fs::write(
+        &path,
+        r#"
+plugin = { name = "Gatekeeper", capabilities = { "chat", "moderate" } }
+
+function on_join(ctx)
+    if ctx:get_user_count() >= 10 then
+        ctx:cancel()
+    elseif not ctx:is_rejoining() then
+        ctx:move_to(2) -- the lobby
+        ctx:reply("welcome, you're in the lobby")
+    elseif ctx:get_mask() == "guest" and ctx:get_channel_id() == "5" then
+        ctx:cancel()
+    end
+end
+"#,
+    )
+    .unwrap();
+
+    let (tx, rx) = mpsc::channel();
+    let mut plugins = PluginManager::new(tx);
+    plugins.load_plugin(&path);
+    let addr = "127.0.0.1:4000".parse().unwrap();
+
+    assert_eq!(plugins.dispatch_join(addr, 1, None, false, 3), Some(2));
+    assert!(matches!(
+        rx.try_recv(),
+        Ok(PluginAction::ReplyByAddr { to, .. }) if to == addr
+    ));
+    assert_eq!(
+        plugins.dispatch_join(addr, 5, Some("bob"), true, 3),
+        Some(5)
+    );
+    assert_eq!(plugins.dispatch_join(addr, 5, Some("guest"), true, 3), None);
+    assert_eq!(plugins.dispatch_join(addr, 1, None, false, 10), None);
+    fs::remove_dir_all(dir).ok();
+}
+
+#[test]
+fn plugins_police_masks_and_follow_channels() {
+    let dir = std::env::temp_dir().join(format!("voudp-policy-{}", process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("policy.lua");
+    fs::write(
+        &path,
+        r##"
+plugin = { name = "Policy", capabilities = { "chat" } }
+
+function on_mask(ctx)
+    if #ctx:get_mask() > 12 or ctx:get_mask():lower():find("admin") then
+        ctx:reply("pick another name")
+        ctx:cancel()
+    elseif ctx:get_old_mask() then
+        Core.info(ctx:get_old_mask() .. " is now " .. ctx:get_mask())
+    end
+end
+
+function on_channel_create(ctx)
+    ctx:broadcast("#" .. ctx:get_name() .. " (" .. ctx:get_channel_id() .. ") is open")
+end
+
+function on_channel_delete(ctx)
+    ctx:broadcast("#" .. (ctx:get_name() or "?") .. " is gone")
+end
+"##,
+    )
+    .unwrap();
+
+    let (tx, rx) = mpsc::channel();
+    let mut plugins = PluginManager::new(tx);
+    plugins.load_plugin(&path);
+    let addr = "127.0.0.1:4000".parse().unwrap();
+
+    assert!(plugins.dispatch_mask(addr, None, "bob", 1));
+    assert!(plugins.dispatch_mask(addr, Some("bob"), "robert", 1));
+    assert!(rx.try_recv().is_err());
+    assert!(!plugins.dispatch_mask(addr, Some("bob"), "a-very-long-nickname", 1));
+    assert!(!plugins.dispatch_mask(addr, None, "TheAdmin", 1));
+    assert_eq!(rx.try_iter().count(), 2);
+
+    plugins.dispatch_channel_create(7, Some("games"));
+    plugins.dispatch_channel_delete(7, Some("games"));
+    let said = rx
+        .try_iter()
+        .map(|action| match action {
+            PluginAction::Broadcast { msg, .. } => msg,
+            _ => panic!("only broadcasts were expected"),
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(said, ["#games (7) is open", "#games is gone"]);
+    fs::remove_dir_all(dir).ok();
+}
+
+#[test]
+fn plugins_list_channels_and_users() {
+    let dir = std::env::temp_dir().join(format!("voudp-who-{}", process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("who.lua");
+    fs::write(
+        &path,
+        r#"
+plugin = { name = "Who" }
+
+Core.register_command("/who", "/who [channel_id]", false, function(ctx)
+    local lines = {}
+    for _, user in ipairs(Core.list_users(tonumber(ctx:get_args()[1]))) do
+        local flags = (user.muted and "m" or "") .. (user.deafened and "d" or "")
+        table.insert(lines, (user.mask or user.addr) .. "@" .. user.channel_id .. " " .. flags)
+    end
+    return table.concat(lines, ", ")
+end)
+
+Core.register_command("/chans", "/chans", false, function(ctx)
+    local lines = {}
+    for _, channel in ipairs(Core.list_channels()) do
+        table.insert(lines, (channel.name or "?") .. "=" .. channel.users)
+    end
+    return table.concat(lines, ", ")
+end)
+"#,
+    )
+    .unwrap();
+
+    let (tx, _rx) = mpsc::channel();
+    let mut plugins = PluginManager::new(tx);
+    plugins.load_plugin(&path);
+
+    let user = |mask: Option<&str>, port: u16, muted: bool| DirectoryUser {
+        mask: mask.map(str::to_string),
+        addr: ([127, 0, 0, 1], port).into(),
+        muted,
+        deafened: muted,
+        admin: false,
+    };
+    plugins.set_directory(vec![
+        DirectoryChannel {
+            id: 1,
+            name: Some("general".into()),
+            users: vec![user(Some("bob"), 4000, false), user(None, 4001, true)],
+        },
+        DirectoryChannel {
+            id: 2,
+            name: None,
+            users: vec![user(Some("alice"), 4002, true)],
+        },
+    ]);
+
+    let run = |line: &[&str]| {
+        let ctx = CommandContext {
+            sender_addr: "127.0.0.1:4000".parse().unwrap(),
+            sender_mask: Some("bob".into()),
+            channel_id: 1,
+            arguments: line[1..].iter().map(|arg| arg.to_string()).collect(),
+            role: Role::User,
+        };
+        match plugins.dispatch_command(line[0], &ctx) {
+            Some(CommandResult::Success(reply)) => reply,
+            _ => panic!("{} failed", line[0]),
+        }
+    };
+    assert_eq!(run(&["/who"]), "bob@1 , 127.0.0.1:4001@1 md, alice@2 md");
+    assert_eq!(run(&["/who", "2"]), "alice@2 md");
+    assert_eq!(run(&["/chans"]), "general=2, ?=1");
+    fs::remove_dir_all(dir).ok();
+}
+
+#[test]
+fn moderation_plugins_move_mute_and_deafen() {
+    let dir = std::env::temp_dir().join(format!("voudp-moderation-{}", process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("moderation.lua");
+    fs::write(
+        &path,
+        r#"
+plugin = { name = "Moderation", capabilities = { "moderate" } }
+
+Core.register_command("/jail", "/jail <mask>", true, function(ctx)
+    local user = ctx:get_args()[1]
+    Core.move(user, 42)
+    Core.set_mute(user, true)
+    Core.set_deafen(user, false)
+end)
+
+Core.register_command("/nowhere", "/nowhere <mask>", true, function(ctx)
+    Core.move(ctx:get_args()[1], 0)
+end)
+"#,
+    )
+    .unwrap();
+
+    let (tx, rx) = mpsc::channel();
+    let mut plugins = PluginManager::new(tx);
+    plugins.load_plugin(&path);
+
+    let ctx = CommandContext {
+        sender_addr: "127.0.0.1:4000".parse().unwrap(),
+        sender_mask: Some("mod".into()),
+        channel_id: 1,
+        arguments: vec!["troll".into()],
+        role: Role::Admin,
+    };
+    assert!(matches!(
+        plugins.dispatch_command("/jail", &ctx),
+        Some(CommandResult::Silent)
+    ));
+    let actions = rx.try_iter().collect::<Vec<_>>();
+    assert!(matches!(
+        &actions[..],
+        [
+            PluginAction::Move { user, channel: 42 },
+            PluginAction::SetMute { muted: true, .. },
+            PluginAction::SetDeafen { deafened: false, .. },
+        ] if user == "troll"
+    ));
+
+    assert!(matches!(
+        plugins.dispatch_command("/nowhere", &ctx),
+        Some(CommandResult::Error(e)) if e.contains("isn't a valid channel id")
+    ));
+    assert!(rx.try_recv().is_err());
+    fs::remove_dir_all(dir).ok();
+}
+
+#[test]
+fn failing_plugins_are_disabled_until_enabled() {
+    let dir = std::env::temp_dir().join(format!("voudp-failing-{}", process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("buggy.lua");
+    fs::write(
+        &path,
+        r#"
+plugin = { name = "Buggy", capabilities = { "chat" } }
+
+function on_message(ctx)
+    Core.broadcast("called")
+    if ctx:get_message() ~= "fine" then
+        local oops = nil
+        oops.field = 1
+    end
+end
+"#,
+    )
+    .unwrap();
+
+    let (tx, rx) = mpsc::channel();
+    let mut plugins = PluginManager::new(tx);
+    plugins.load_plugin(&path);
+    let calls = |rx: &mpsc::Receiver<PluginAction>| rx.try_iter().count();
+
+    // a success in between starts the count over
+    for _ in 0..9 {
+        plugins.dispatch_message("bob", 1, "boom");
+    }
+    plugins.dispatch_message("bob", 1, "fine");
+    for _ in 0..9 {
+        plugins.dispatch_message("bob", 1, "boom");
+    }
+    assert_eq!(calls(&rx), 19);
+    assert!(plugins.is_enabled("Buggy"));
+
+    plugins.dispatch_message("bob", 1, "boom");
+    assert!(!plugins.is_enabled("Buggy"));
+    plugins.dispatch_message("bob", 1, "fine");
+    assert_eq!(calls(&rx), 1);
+
+    plugins.set_enabled("buggy", true).unwrap();
+    plugins.dispatch_message("bob", 1, "fine");
+    assert_eq!(calls(&rx), 1);
+    plugins.set_enabled("Buggy", false).unwrap();
+    plugins.dispatch_message("bob", 1, "fine");
+    assert_eq!(calls(&rx), 0);
+    assert!(plugins.set_enabled("nobody", true).is_err());
+    fs::remove_dir_all(dir).ok();
+}
+
+#[test]
+fn plugins_only_get_the_capabilities_they_declare() {
+    let dir = std::env::temp_dir().join(format!("voudp-sandbox-{}", process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let saved = dir.join("saved.txt");
+    fs::write(
+        dir.join("archivist.lua"),
+        format!(
+            r#"
+plugin = {{ name = "Archivist", capabilities = {{ "storage" }} }}
+local path = {saved:?}
+
+-- declared capabilities already hold while loading
+local file = io.open(path, "w")
+file:write("kept")
+file:close()
+
+Core.register_command("/read", "/read", false, function(ctx)
+    local file = io.open(path)
+    local text = file:read("a")
+    file:close()
+    return text
+end)
+Core.register_command("/say", "/say", false, function(ctx) Core.broadcast("hi") end)
+Core.register_command("/reply", "/reply", false, function(ctx) ctx:reply("hi") end)
+Core.register_command("/native", "/native", false, function(ctx)
+    package.loadlib("socket.so", "luaopen_socket")
+end)
+Core.register_command("/run", "/run", false, function(ctx) os.execute("true") end)
+"#
+        ),
+    )
+    .unwrap();
+    fs::write(
+        dir.join("mixer.lua"),
+        "plugin = { name = \"Mixer\" }\nfunction on_audio(ctx) end\n",
+    )
+    .unwrap();
+    fs::write(
+        dir.join("typo.lua"),
+        "plugin = { name = \"Typo\", capabilities = { \"chta\" } }\n",
+    )
+    .unwrap();
+
+    let (tx, rx) = mpsc::channel();
+    let mut plugins = PluginManager::new(tx);
+    plugins.load_dir(&dir);
+    assert_eq!(plugins.names(), ["Archivist"]);
+    assert_eq!(fs::read_to_string(&saved).unwrap(), "kept");
+
+    let ctx = CommandContext {
+        sender_addr: "127.0.0.1:4000".parse().unwrap(),
+        sender_mask: Some("bob".into()),
+        channel_id: 1,
+        arguments: vec![],
+        role: Role::User,
+    };
+    let run = |name: &str| plugins.dispatch_command(name, &ctx).unwrap();
+    assert!(matches!(run("/read"), CommandResult::Success(text) if text == "kept"));
+    for (name, capability) in [("/say", "chat"), ("/reply", "chat")] {
+        match run(name) {
+            CommandResult::Error(e) => assert!(e.contains(capability), "{name}: {e}"),
+            _ => panic!("{name} should have been denied"),
+        }
+    }
+    // no native modules or programs either way
+    assert!(matches!(run("/native"), CommandResult::Error(_)));
+    assert!(matches!(run("/run"), CommandResult::Error(_)));
+    assert!(rx.try_recv().is_err());
+    fs::remove_dir_all(dir).ok();
+}
+
+#[test]
+fn plugins_fetch_allowed_hosts_in_the_background() {
+    let web = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}/weather", web.local_addr().unwrap());
+    thread::spawn(move || {
+        let (mut stream, _) = web.accept().unwrap();
+        let mut request = [0u8; 1024];
+        let len = stream.read(&mut request).unwrap();
+        assert!(request[..len].starts_with(b"GET /weather HTTP/1.0\r\n"));
+        stream
+            .write_all(b"HTTP/1.0 200 OK\r\nContent-Type: text/plain\r\n\r\nsunny")
+            .unwrap();
+    });
+
+    let dir = std::env::temp_dir().join(format!("voudp-http-{}", process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("weather.lua");
+    fs::write(
+        &path,
+        r#"
+plugin = { name = "Weather", capabilities = { "chat", "network" } }
+
+Core.register_command("/weather", "/weather <url>", false, function(ctx)
+    Core.http_get(ctx:get_args()[1], function(response, err)
+        Core.broadcast(response and (response.status .. " " .. response.body) or err)
+    end)
+end)
+Core.register_command("/native", "/native", false, function(ctx)
+    return tostring(package.loadlib) .. " " .. #package.searchers
+end)
+"#,
+    )
+    .unwrap();
+
+    let (tx, rx) = mpsc::channel();
+    let mut plugins = PluginManager::new(tx);
+    plugins.set_http_hosts(vec!["localhost".into(), "127.0.0.1".into()]);
+    plugins.load_plugin(&path);
+
+    let run = |url: &str| {
+        let ctx = CommandContext {
+            sender_addr: "127.0.0.1:4000".parse().unwrap(),
+            sender_mask: Some("bob".into()),
+            channel_id: 1,
+            arguments: vec![url.to_string()],
+            role: Role::User,
+        };
+        plugins.dispatch_command("/weather", &ctx).unwrap()
+    };
+    for (url, error) in [
+        ("http://example.com/", "allowlist"),
+        ("http://evil-localhost/", "allowlist"),
+        ("https://127.0.0.1/", "https"),
+        (
+            "http://127.0.0.1/a HTTP/1.0\r\nX-Injected: 1",
+            "expected http",
+        ),
+    ] {
+        match run(url) {
+            CommandResult::Error(e) => assert!(e.contains(error), "{url}: {e}"),
+            _ => panic!("{url} should have been refused"),
+        }
+    }
+    assert!(matches!(run(&url), CommandResult::Silent));
+
+    // the answer comes on a later tick
+    let deadline = Instant::now() + Duration::from_secs(5);
+    let msg = loop {
+        plugins.run_timers();
+        match rx.try_recv() {
+            Ok(PluginAction::Broadcast { msg, .. }) => break msg,
+            _ if Instant::now() > deadline => panic!("the callback never ran"),
+            _ => thread::sleep(Duration::from_millis(10)),
+        }
+    };
+    assert_eq!(msg, "200 sunny");
+
+    // network doesn't stretch to native code
+    let native = plugins.dispatch_command(
+        "/native",
+        &CommandContext {
+            sender_addr: "127.0.0.1:4000".parse().unwrap(),
+            sender_mask: Some("bob".into()),
+            channel_id: 1,
+            arguments: vec![],
+            role: Role::User,
+        },
+    );
+    assert!(matches!(native, Some(CommandResult::Success(text)) if text == "nil 2"));
+
+    // a server that never answers only ties up so many threads
+    let silent = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}/", silent.local_addr().unwrap());
+    for _ in 0..4 {
+        assert!(matches!(run(&url), CommandResult::Silent));
+    }
+    match run(&url) {
+        CommandResult::Error(e) => assert!(e.contains("in flight"), "{e}"),
+        _ => panic!("a fifth request should have been refused"),
+    }
+    fs::remove_dir_all(dir).ok();
+}
+
+// keeps everyone out of the music channel and counts what it hears
+#[derive(Default)]
+struct Bouncer {
+    actions: Option<mpsc::Sender<PluginAction>>,
+    messages: Arc<AtomicUsize>,
+}
+
+impl VoudpPlugin for Bouncer {
+    fn name(&self) -> &str {
+        "Bouncer"
+    }
+
+    fn on_load(&mut self, actions: mpsc::Sender<PluginAction>) {
+        self.actions = Some(actions);
+    }
+
+    fn on_join(&mut self, join: &mut JoinEvent) -> bool {
+        if join.channel_id == 2 {
+            join.channel_id = 3;
+        }
+        join.mask != Some("troll")
+    }
+
+    fn on_message(&mut self, username: &str, _channel_id: u32, message: &str) -> bool {
+        self.messages.fetch_add(1, Ordering::SeqCst);
+        if message.contains("spam") {
+            let actions = self.actions.as_ref().unwrap();
+            actions
+                .send(PluginAction::Reply {
+                    to: username.into(),
+                    msg: "no spam".into(),
+                })
+                .ok();
+            return false;
+        }
+        true
+    }
+}
+
+#[test]
+fn native_plugins_run_before_lua_ones() {
+    let dir = std::env::temp_dir().join(format!("voudp-native-{}", process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("counter.lua");
+    fs::write(
+        &path,
+        r#"
+plugin = { name = "Counter", capabilities = { "chat" } }
+
+function on_message(ctx)
+    Core.broadcast("lua saw " .. ctx:get_message())
+end
+"#,
+    )
+    .unwrap();
+
+    let (tx, rx) = mpsc::channel();
+    let mut plugins = PluginManager::new(tx);
+    let messages = Arc::new(AtomicUsize::new(0));
+    plugins.register_native(Box::new(Bouncer {
+        messages: messages.clone(),
+        ..Default::default()
+    }));
+    plugins.load_plugin(&path);
+    assert_eq!(plugins.native_names(), ["Bouncer"]);
+    assert!(!plugins.is_empty());
+
+    let addr = "127.0.0.1:4000".parse().unwrap();
+    assert_eq!(plugins.dispatch_join(addr, 2, None, false, 0), Some(3));
+    assert_eq!(plugins.dispatch_join(addr, 1, Some("troll"), true, 0), None);
+
+    assert!(plugins.dispatch_message("bob", 1, "hi"));
+    assert!(matches!(
+        rx.try_recv(),
+        Ok(PluginAction::Broadcast { msg, .. }) if msg == "lua saw hi"
+    ));
+    // lua never hears it
+    assert!(!plugins.dispatch_message("bob", 1, "buy spam"));
+    assert!(matches!(
+        rx.try_recv(),
+        Ok(PluginAction::Reply { msg, .. }) if msg == "no spam"
+    ));
+    assert!(rx.try_recv().is_err());
+    assert_eq!(messages.load(Ordering::SeqCst), 2);
+    fs::remove_dir_all(dir).ok();
+}
+
+#[test]
+fn servers_run_registered_native_plugins() {
+    let phrase = b"native-plugin-test";
+    let mut server = ServerState::new(ServerConfig::default(), phrase).expect("server should bind");
+    server.register_plugin(Bouncer::default());
+    let addr = SocketAddr::from(([127, 0, 0, 1], server.local_addr().port()));
+    thread::spawn(move || server.run());
+
+    let key = socket::derive_key_from_phrase(phrase, protocol::VOUDP_SALT);
+    let client = SecureUdpSocket::create("127.0.0.1:0".into(), key).unwrap();
+    client.connect(addr).unwrap();
+    let mut join = vec![ClientPacketType::Join as u8];
+    join.extend_from_slice(&2u32.to_be_bytes());
+    client.send(&join).unwrap();
+
+    let deadline = Instant::now() + Duration::from_secs(3);
+    let mut buf = [0u8; 2048];
+    let moved_to = loop {
+        assert!(Instant::now() < deadline, "never told where we joined");
+        match client.recv_from(&mut buf) {
+            Ok((size, _)) if size > 0 && buf[0] == ClientPacketType::Notice as u8 => {
+                let notice = LocalizedMessage::deserialize(&buf[..size]).unwrap();
+                if notice.message_code() == Some(MessageCode::ChannelMoved) {
+                    break notice.params[0].clone();
+                }
+            }
+            Ok(_) => {}
+            Err(_) => thread::sleep(Duration::from_millis(2)),
+        }
+    };
+    // asked for the music channel, put in the test one
+    assert_eq!(moved_to, "test");
+}
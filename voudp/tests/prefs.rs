@@ -0,0 +1,73 @@
+use std::{fs, process};
+
+use voudp::{
+    prefs::{ClientPrefs, NotifyPrefs},
+    util,
+};
+
+#[test]
+fn prefs_survive_a_round_trip() {
+    let mut prefs = ClientPrefs {
+        server: Some("127.0.0.1:37549".into()),
+        nick: Some("say \"hi\"".into()),
+        channel_id: Some(7),
+        input_device: Some("USB Mic \\ Line In".into()),
+        output_device: None,
+        muted: true,
+        deafened: false,
+        push_to_talk: Some("Space".into()),
+        notify: NotifyPrefs {
+            mentions: false,
+            joins: true,
+            sound: Some("/home/bob/ding.ogg".into()),
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    prefs.volumes.insert("bob".into(), 0.5);
+    prefs.volumes.insert("al = ice\n".into(), 2.0);
+
+    let text = prefs.to_toml();
+    assert_eq!(ClientPrefs::parse(&text).unwrap(), prefs);
+
+    let path = std::env::temp_dir()
+        .join(format!("voudp-prefs-{}", process::id()))
+        .join("client.toml");
+    // a first run
+    assert_eq!(ClientPrefs::load(&path).unwrap(), ClientPrefs::default());
+    prefs.save(&path).unwrap();
+    assert_eq!(ClientPrefs::load(&path).unwrap(), prefs);
+    fs::remove_dir_all(path.parent().unwrap()).ok();
+}
+
+#[test]
+fn prefs_skip_unknown_keys_and_reject_bad_values() {
+    let prefs = ClientPrefs::parse(
+        "# written by hand\n\
+         server = \"voice.example.org:37549\"\n\
+         theme = \"dark\"\n\
+         \n\
+         [volumes]\n\
+         bob = 1.5\n",
+    )
+    .unwrap();
+    assert_eq!(prefs.server.as_deref(), Some("voice.example.org:37549"));
+    assert_eq!(prefs.volumes["bob"], 1.5);
+    assert!(!prefs.muted);
+    // older files leave mentions and whispers on
+    assert_eq!(prefs.notify, NotifyPrefs::default());
+    assert!(prefs.notify.mentions && prefs.notify.whispers);
+
+    let err = ClientPrefs::parse("nick = \"bob\"\nmuted = yes\n").unwrap_err();
+    assert!(err.to_string().contains("line 2"), "{err}");
+    assert!(ClientPrefs::parse("channel = \"lobby\"").is_err());
+}
+
+#[test]
+fn mentions_match_whole_names() {
+    assert!(util::mentions("hey Bob, you there?", "bob"));
+    assert!(util::mentions("@bob look", "Bob"));
+    assert!(!util::mentions("bobby is here", "bob"));
+    assert!(!util::mentions("anyone?", " "));
+    assert!(util::mentions("ping al-ice", "al-ice"));
+}
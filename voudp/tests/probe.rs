@@ -0,0 +1,101 @@
+use std::{net::SocketAddr, thread, time::Duration};
+
+use voudp::{
+    probe::{self, PROBE_RATES_KBPS, PROFILES, ProbeStep, QualityProfile, QualitySelection},
+    protocol,
+    server::{ServerConfig, ServerState},
+    socket::{self, SecureUdpSocket},
+};
+
+const PHRASE: &[u8] = b"probe-test";
+
+fn step(rate_kbps: u32, sent: u32, echoed: u32, rtt_millis: u64) -> ProbeStep {
+    ProbeStep {
+        rate_kbps,
+        sent,
+        echoed,
+        rtts: vec![Duration::from_millis(rtt_millis); echoed as usize],
+    }
+}
+
+#[test]
+fn picks_the_best_profile_the_link_carries() {
+    let clean = [
+        step(32, 4, 4, 10),
+        step(64, 4, 4, 10),
+        step(128, 7, 7, 11),
+        step(192, 10, 10, 12),
+    ];
+    assert_eq!(probe::sustained_kbps(&clean), Some(192));
+    assert_eq!(probe::pick_profile(&clean).name, "max");
+
+    // the 192 kbps step loses a third of its packets
+    let lossy = [
+        step(32, 4, 4, 10),
+        step(64, 4, 4, 10),
+        step(128, 7, 7, 11),
+        step(192, 9, 6, 12),
+    ];
+    assert_eq!(probe::pick_profile(&lossy), QualityProfile::default());
+
+    // no loss, but the rtt balloons once a queue builds up
+    let queued = [step(32, 4, 4, 10), step(64, 4, 4, 80)];
+    assert_eq!(probe::sustained_kbps(&queued), Some(32));
+    assert_eq!(probe::pick_profile(&queued).name, "low");
+
+    // nothing came back at all
+    assert_eq!(probe::sustained_kbps(&[step(32, 4, 0, 0)]), None);
+    assert_eq!(probe::pick_profile(&[]), PROFILES[0]);
+}
+
+#[test]
+fn small_loss_turns_fec_on() {
+    let steps = [
+        step(32, 40, 40, 10),
+        step(64, 40, 40, 10),
+        step(128, 40, 40, 10),
+        step(192, 40, 39, 10),
+    ];
+    let profile = probe::pick_profile(&steps);
+    assert_eq!(profile.name, "max");
+    assert!(profile.fec);
+    assert_eq!(profile.loss_perc, 3);
+}
+
+#[test]
+fn manual_override_beats_the_probe() {
+    let mut quality = QualitySelection::default();
+    quality.set_manual(QualityProfile::by_name("low"));
+    quality.set_probed(&[step(32, 4, 4, 10), step(64, 4, 4, 10)]);
+    assert_eq!(quality.profile.name, "low");
+    assert!(quality.manual);
+
+    // going back to auto picks up what the probe found
+    quality.set_manual(None);
+    assert_eq!(quality.profile.name, "medium");
+    assert!(quality.to_string().contains("64 kbps"));
+
+    assert!(QualityProfile::by_name("ultra").is_none());
+}
+
+#[test]
+fn probe_against_a_server() {
+    let server = ServerState::new(ServerConfig::default(), PHRASE).expect("server should bind");
+    let addr = SocketAddr::from(([127, 0, 0, 1], server.local_addr().port()));
+    thread::spawn(move || {
+        let mut server = server;
+        server.run();
+    });
+
+    let key = socket::derive_key_from_phrase(PHRASE, protocol::VOUDP_SALT);
+    let client = SecureUdpSocket::create("127.0.0.1:0".into(), key).unwrap();
+    client.connect(addr).unwrap();
+
+    let steps = probe::run_probe(&client).unwrap();
+    assert_eq!(steps.len(), PROBE_RATES_KBPS.len(), "{steps:?}");
+    for step in &steps {
+        assert!(step.sent >= 4);
+        assert!(step.loss() <= 0.05, "{step:?}");
+    }
+    assert_eq!(probe::pick_profile(&steps).name, "max");
+}
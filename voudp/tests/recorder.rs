@@ -0,0 +1,88 @@
+use std::{fs, path::PathBuf, process, time::Duration};
+
+use voudp::recorder::Recorder;
+
+fn temp_wav(name: &str) -> PathBuf {
+    std::env::temp_dir()
+        .join(format!("voudp-recorder-{}", process::id()))
+        .join(name)
+}
+
+fn u32_at(bytes: &[u8], at: usize) -> u32 {
+    u32::from_le_bytes(bytes[at..at + 4].try_into().unwrap())
+}
+
+fn find(bytes: &[u8], tag: &[u8]) -> usize {
+    bytes
+        .windows(tag.len())
+        .position(|w| w == tag)
+        .unwrap_or_else(|| panic!("no {} chunk", String::from_utf8_lossy(tag)))
+}
+
+#[test]
+fn recording_is_a_valid_wav() {
+    let path = temp_wav("call.wav");
+    let mut recorder = Recorder::create(&path, false).unwrap();
+
+    // 10ms of stereo, twice
+    recorder.write_mix(&[0.5; 960]).unwrap();
+    recorder.write_mix(&[-0.5; 960]).unwrap();
+    assert_eq!(recorder.duration(), Duration::from_millis(20));
+    assert_eq!(recorder.finish().unwrap(), path);
+
+    let bytes = fs::read(&path).unwrap();
+    assert_eq!(&bytes[..4], b"RIFF");
+    assert_eq!(&bytes[8..12], b"WAVE");
+    assert_eq!(u32_at(&bytes, 4) as usize, bytes.len() - 8);
+
+    let fmt = find(&bytes, b"fmt ");
+    assert_eq!(u16::from_le_bytes([bytes[fmt + 10], bytes[fmt + 11]]), 2);
+    assert_eq!(u32_at(&bytes, fmt + 12), 48000);
+
+    // when it was made rides along
+    find(&bytes, b"ICRD");
+
+    let data = find(&bytes, b"data");
+    assert_eq!(u32_at(&bytes, data + 4), 1920 * 2);
+    let first = i16::from_le_bytes([bytes[data + 8], bytes[data + 9]]);
+    assert_eq!(first, i16::MAX / 2);
+    assert_eq!(bytes.len(), data + 8 + 1920 * 2);
+
+    fs::remove_file(path).ok();
+}
+
+#[test]
+fn mic_is_mixed_in_only_when_asked() {
+    let with_mic = temp_wav("with-mic.wav");
+    let mut recorder = Recorder::create(&with_mic, true).unwrap();
+    recorder.write_mic(&[0.25; 4]);
+    recorder.write_mix(&[0.25; 8]).unwrap();
+    recorder.finish().unwrap();
+
+    let without_mic = temp_wav("without-mic.wav");
+    let mut recorder = Recorder::create(&without_mic, false).unwrap();
+    recorder.write_mic(&[0.25; 4]);
+    recorder.write_mix(&[0.25; 8]).unwrap();
+    recorder.finish().unwrap();
+
+    let samples = |path: &PathBuf| {
+        let bytes = fs::read(path).unwrap();
+        let data = find(&bytes, b"data") + 8;
+        bytes[data..]
+            .chunks(2)
+            .map(|s| i16::from_le_bytes([s[0], s[1]]))
+            .collect::<Vec<_>>()
+    };
+
+    // our voice only lasts as long as we spoke
+    let half = i16::MAX / 2;
+    let quarter = i16::MAX / 4;
+    assert_eq!(
+        samples(&with_mic),
+        [half, half, half, half, quarter, quarter, quarter, quarter]
+    );
+    assert_eq!(samples(&without_mic), [quarter; 8]);
+
+    fs::remove_file(with_mic).ok();
+    fs::remove_file(without_mic).ok();
+}
@@ -0,0 +1,94 @@
+use std::{
+    net::SocketAddr,
+    thread,
+    time::{Duration, Instant},
+};
+
+use voudp::{
+    console_msg::ConsoleMessage,
+    protocol::{self, ClientPacketType, ConsoleRole, FromPacket, MessageCode},
+    server::{ServerConfig, ServerState},
+    socket::{self, SecureUdpSocket},
+    util::{self, LocalizedMessage},
+};
+
+mod common;
+
+use common::recv_packet;
+
+const PHRASE: &[u8] = b"remote-creation-test";
+
+fn spawn_server(config: ServerConfig) -> SocketAddr {
+    let server = ServerState::new(config, PHRASE).expect("server should bind");
+    let port = server.local_addr().port();
+
+    thread::spawn(move || {
+        let mut server = server;
+        server.run();
+    });
+
+    SocketAddr::from(([127, 0, 0, 1], port))
+}
+
+fn connect(server: SocketAddr) -> SecureUdpSocket {
+    let key = socket::derive_key_from_phrase(PHRASE, protocol::VOUDP_SALT);
+    let socket = SecureUdpSocket::create("127.0.0.1:0".into(), key).unwrap();
+    socket.connect(server).unwrap();
+    socket
+}
+
+fn join_packet(channel_id: u32) -> Vec<u8> {
+    let mut packet = vec![ClientPacketType::Join as u8];
+    packet.extend_from_slice(&channel_id.to_be_bytes());
+    packet
+}
+
+#[test]
+fn codec_failure_rejects_join_and_keeps_serving() {
+    // opus only accepts 8/12/16/24/48 kHz, so every remote fails to initialize
+    let server = spawn_server(ServerConfig {
+        sample_rate: 44100,
+        tickrate: 50,
+        ..Default::default()
+    });
+
+    let client = connect(server);
+    client.send(&join_packet(1)).unwrap();
+
+    let notice = recv_packet(&client, ClientPacketType::Notice as u8).expect("no rejection notice");
+    let notice = LocalizedMessage::deserialize(&notice).unwrap();
+    assert_eq!(notice.message_code(), Some(MessageCode::JoinFailed));
+    assert!(recv_packet(&client, ClientPacketType::Kick as u8).is_some());
+
+    // the server is still up and answering other peers
+    let console = connect(server);
+    let token =
+        util::console_login(&console, server, protocol::PASSWORD, ConsoleRole::Admin).unwrap();
+    let ping = protocol::create_console_command(&token, "ping");
+    console.send_to(&ping, server).unwrap();
+
+    let deadline = Instant::now() + Duration::from_secs(3);
+    let mut buf = [0u8; 2048];
+    let mut reply = None;
+    while reply.is_none() && Instant::now() < deadline {
+        match console.recv_from(&mut buf) {
+            Ok((size, _)) if size > 0 => reply = Some(buf[..size].to_vec()),
+            _ => thread::sleep(Duration::from_millis(5)),
+        }
+    }
+    let reply = ConsoleMessage::parse(&String::from_utf8(reply.unwrap()).unwrap()).unwrap();
+    assert_eq!(reply.text(), "pong");
+}
+
+#[test]
+fn join_succeeds_with_valid_codec_config() {
+    let server = spawn_server(ServerConfig::default());
+
+    let client = connect(server);
+    client.send(&join_packet(1)).unwrap();
+    thread::sleep(Duration::from_millis(50));
+
+    // only registered remotes get a channel list back
+    client.send(&[ClientPacketType::List as u8]).unwrap();
+    assert!(recv_packet(&client, ClientPacketType::List as u8).is_some());
+}
@@ -0,0 +1,95 @@
+use voudp::resample::{Resampler, SincResampler};
+
+fn sine(hz: f32, rate: u32, frames: usize) -> Vec<f32> {
+    (0..frames)
+        .map(|n| (2.0 * std::f32::consts::PI * hz * n as f32 / rate as f32).sin())
+        .collect()
+}
+
+// rising zero crossings, a rough frequency that doesn't care about the rate
+fn crossings(buf: &[f32]) -> usize {
+    buf.windows(2).filter(|w| w[0] < 0.0 && w[1] >= 0.0).count()
+}
+
+// how much of `hz` there is in `buf`, by goertzel
+fn level(buf: &[f32], hz: f32, rate: u32) -> f32 {
+    let coeff = 2.0 * (2.0 * std::f32::consts::PI * hz / rate as f32).cos();
+    let (mut s1, mut s2) = (0.0, 0.0);
+    for &x in buf {
+        let s = x + coeff * s1 - s2;
+        s2 = s1;
+        s1 = s;
+    }
+    (s1 * s1 + s2 * s2 - coeff * s1 * s2).sqrt() / buf.len() as f32
+}
+
+#[test]
+fn mic_audio_is_brought_up_to_48k() {
+    let mut resampler = Resampler::new(44100, 48000, 1);
+    let tone = sine(440.0, 44100, 44100);
+
+    // in uneven callback sized chunks, like a device hands them over
+    let mut out = vec![];
+    for chunk in tone.chunks(441 + 7) {
+        resampler.process(chunk, &mut out);
+    }
+
+    assert!(out.len().abs_diff(48000) <= 1, "{} frames", out.len());
+    assert!(crossings(&out).abs_diff(440) <= 1);
+    // no clicks where the chunks meet, a 440Hz sine never jumps this far
+    assert!(out.windows(2).all(|w| (w[1] - w[0]).abs() < 0.1));
+}
+
+#[test]
+fn playback_pulls_only_what_it_needs() {
+    let mut resampler = Resampler::new(48000, 44100, 2);
+    let tone = sine(440.0, 48000, 48000)
+        .into_iter()
+        .flat_map(|s| [s, s])
+        .collect::<Vec<_>>();
+    let mut source = tone.iter().copied();
+    let mut pulled = 0usize;
+
+    // one second of speaker callbacks
+    let mut out = vec![];
+    for _ in 0..100 {
+        let mut data = vec![0.0; 441 * 2];
+        resampler.fill(&mut data, || {
+            pulled += 1;
+            source.next().unwrap_or(0.0)
+        });
+        out.extend(data);
+    }
+
+    assert!(pulled.abs_diff(48000 * 2) <= 4, "pulled {pulled}");
+    let left = out.iter().step_by(2).copied().collect::<Vec<_>>();
+    let right = out.iter().skip(1).step_by(2).copied().collect::<Vec<_>>();
+    assert_eq!(left, right);
+    assert!(crossings(&left).abs_diff(440) <= 1);
+}
+
+#[test]
+fn music_is_upsampled_without_images() {
+    let tone = sine(15000.0, 44100, 44100);
+    let mut out = vec![];
+    let mut sinc = SincResampler::new(44100, 48000, 1);
+    for chunk in tone.chunks(1152) {
+        sinc.process(chunk, &mut out);
+    }
+    sinc.flush(&mut out);
+    assert!(out.len().abs_diff(48000) <= 1, "{} frames", out.len());
+
+    // 44.1kHz - 15kHz lands on 29.1kHz, which a 48kHz stream hears at 18.9kHz
+    let mut linear = vec![];
+    Resampler::new(44100, 48000, 1).process(&tone, &mut linear);
+    let image = |out: &[f32]| level(out, 18900.0, 48000) / level(out, 15000.0, 48000);
+    assert!(image(&linear) > 0.05, "linear {}", image(&linear));
+    assert!(image(&out) < 0.001, "sinc {}", image(&out));
+
+    // flushed, a track's tail lines up with the next one's head
+    let mut again = vec![];
+    sinc.process(&tone, &mut again);
+    sinc.flush(&mut again);
+    assert!(again.len().abs_diff(out.len()) <= 1);
+    assert!(again.iter().zip(&out).all(|(a, b)| (a - b).abs() < 1e-4));
+}
@@ -0,0 +1,95 @@
+use std::{
+    net::UdpSocket,
+    thread,
+    time::{Duration, Instant},
+};
+
+use voudp::{
+    protocol::{self, ClientPacketType},
+    socket::{self, SecureUdpSocket},
+};
+
+// waits for the next datagram that decrypts, returning its (possibly empty) payload
+fn recv(socket: &SecureUdpSocket) -> Option<Vec<u8>> {
+    let deadline = Instant::now() + Duration::from_secs(2);
+    let mut buf = [0u8; 2048];
+
+    while Instant::now() < deadline {
+        match socket.recv_from(&mut buf) {
+            Ok((size, _)) => return Some(buf[..size].to_vec()),
+            Err((e, _)) if e.kind() == std::io::ErrorKind::WouldBlock => {
+                thread::sleep(Duration::from_millis(5))
+            }
+            Err((e, _)) => panic!("recv failed: {e}"),
+        }
+    }
+
+    None
+}
+
+#[test]
+fn retransmits_are_encrypted_and_delivered_once() {
+    let key = socket::derive_key_from_phrase(b"secure-socket-test", protocol::VOUDP_SALT);
+    let sender = SecureUdpSocket::create("127.0.0.1:0".into(), key).unwrap();
+    let receiver = SecureUdpSocket::create("127.0.0.1:0".into(), key).unwrap();
+
+    // everything goes through a wire tap first, which never acks
+    let tap = UdpSocket::bind("127.0.0.1:0").unwrap();
+    tap.set_read_timeout(Some(Duration::from_secs(2))).unwrap();
+    sender.connect(tap.local_addr().unwrap()).unwrap();
+
+    let mut chat = vec![ClientPacketType::Chat as u8];
+    chat.extend_from_slice(b"attack at dawn");
+    // chat is unreliable, so send it as a reliable payload explicitly
+    sender
+        .send_reliable(chat.clone(), tap.local_addr().unwrap())
+        .unwrap();
+
+    thread::sleep(Duration::from_millis(250));
+    sender.tick_reliable();
+
+    let mut captured = Vec::new();
+    let mut buf = [0u8; 2048];
+    for _ in 0..2 {
+        let (size, _) = tap
+            .recv_from(&mut buf)
+            .expect("packet was not retransmitted");
+        captured.push(buf[..size].to_vec());
+    }
+
+    assert_ne!(captured[0], captured[1], "retransmit reused the nonce");
+    for packet in &captured {
+        assert!(
+            !packet.windows(6).any(|w| w == b"attack"),
+            "payload went out in cleartext"
+        );
+    }
+
+    // the receiver decrypts both, but only hands the payload up once
+    for packet in &captured {
+        tap.send_to(packet, receiver.local_addr()).unwrap();
+    }
+    assert_eq!(recv(&receiver).as_deref(), Some(&chat[..]));
+    assert_eq!(recv(&receiver).as_deref(), Some(&[][..]));
+}
+
+#[test]
+fn wrong_phrase_cannot_decrypt() {
+    let key = socket::derive_key_from_phrase(b"right", protocol::VOUDP_SALT);
+    let other = socket::derive_key_from_phrase(b"wrong", protocol::VOUDP_SALT);
+    let sender = SecureUdpSocket::create("127.0.0.1:0".into(), key).unwrap();
+    let receiver = SecureUdpSocket::create("127.0.0.1:0".into(), other).unwrap();
+
+    sender.connect(receiver.local_addr()).unwrap();
+    sender.send(&protocol::create_list_request()).unwrap();
+
+    let deadline = Instant::now() + Duration::from_secs(2);
+    let mut buf = [0u8; 2048];
+    loop {
+        match receiver.recv_from(&mut buf) {
+            Err((e, _)) if e.kind() == std::io::ErrorKind::InvalidData => break,
+            Err(_) if Instant::now() < deadline => thread::sleep(Duration::from_millis(5)),
+            other => panic!("expected a decryption failure, got {other:?}"),
+        }
+    }
+}
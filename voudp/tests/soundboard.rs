@@ -0,0 +1,95 @@
+use std::{fs, path::PathBuf, process, time::Duration};
+
+use voudp::soundboard::{self, Clip, ClipMode, Soundboard};
+
+fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir()
+        .join(format!("voudp-soundboard-{}", process::id()))
+        .join(name);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+// 16 bit mono pcm
+fn write_wav(path: &PathBuf, rate: u32, samples: &[i16]) {
+    let data = samples
+        .iter()
+        .flat_map(|s| s.to_le_bytes())
+        .collect::<Vec<_>>();
+
+    let mut wav = vec![];
+    wav.extend_from_slice(b"RIFF");
+    wav.extend_from_slice(&(36 + data.len() as u32).to_le_bytes());
+    wav.extend_from_slice(b"WAVEfmt ");
+    wav.extend_from_slice(&16u32.to_le_bytes());
+    wav.extend_from_slice(&1u16.to_le_bytes());
+    wav.extend_from_slice(&1u16.to_le_bytes());
+    wav.extend_from_slice(&rate.to_le_bytes());
+    wav.extend_from_slice(&(rate * 2).to_le_bytes());
+    wav.extend_from_slice(&2u16.to_le_bytes());
+    wav.extend_from_slice(&16u16.to_le_bytes());
+    wav.extend_from_slice(b"data");
+    wav.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    wav.extend_from_slice(&data);
+    fs::write(path, wav).unwrap();
+}
+
+#[test]
+fn clips_are_decoded_to_48k_stereo() {
+    let dir = temp_dir("decode");
+    let path = dir.join("airhorn.wav");
+    // half a second at 24kHz
+    write_wav(&path, 24000, &[i16::MAX / 2; 12000]);
+
+    let clip = Clip::load(&path).unwrap();
+    assert_eq!(clip.name(), "airhorn");
+    let ms = clip.duration().as_millis();
+    assert!(ms.abs_diff(500) <= 1, "{ms} ms");
+
+    let mut board = Soundboard::default();
+    board.play(clip, ClipMode::Replace);
+    let mut frame = [0.9; 960 * 2];
+    assert!(board.mix_into(&mut frame));
+    // both ears, the mic replaced. the resampler eases in from silence
+    assert!(frame[4..].iter().all(|s| (s - 0.5).abs() < 0.01));
+    assert!(frame[..4].iter().all(|&s| s < 0.5));
+
+    let too_long = dir.join("speech.wav");
+    write_wav(&too_long, 8000, &vec![0; 8000 * 31]);
+    let err = Clip::load(&too_long).unwrap_err();
+    assert!(err.to_string().contains("longer than"), "{err}");
+
+    assert_eq!(
+        soundboard::find_clips(&dir),
+        [dir.join("airhorn.wav"), dir.join("speech.wav")]
+    );
+    fs::remove_dir_all(dir).ok();
+}
+
+#[test]
+fn clips_mix_with_the_mic_until_they_end() {
+    let clip = Clip::from_samples("beep", vec![0.25; 1500]);
+    assert_eq!(clip.duration(), Duration::from_secs_f64(750.0 / 48000.0));
+
+    let mut board = Soundboard::default();
+    board.play(clip, ClipMode::Mix);
+
+    let mut frame = [0.5; 1000];
+    assert!(board.mix_into(&mut frame));
+    assert!(frame.iter().all(|&s| s == 0.75));
+
+    // the tail is padded with the mic alone
+    let mut frame = [0.5; 1000];
+    assert!(board.mix_into(&mut frame));
+    assert_eq!(frame[499], 0.75);
+    assert_eq!(frame[500], 0.5);
+
+    assert!(board.playing().is_none());
+    let mut frame = [0.5; 1000];
+    assert!(!board.mix_into(&mut frame));
+    assert!(frame.iter().all(|&s| s == 0.5));
+
+    board.play(Clip::from_samples("beep", vec![0.25; 1500]), ClipMode::Mix);
+    assert!(board.stop());
+    assert!(!board.stop());
+}
@@ -0,0 +1,63 @@
+use std::{
+    net::SocketAddr,
+    thread,
+    time::{Duration, Instant},
+};
+
+use voudp::{
+    client::{ClientState, ConnectionStats, Mode},
+    protocol,
+    server::{ServerConfig, ServerState},
+};
+
+const PHRASE: &[u8] = b"stats-test";
+
+#[test]
+fn stats_show_up_once_connected() {
+    let server = ServerState::new(ServerConfig::default(), PHRASE).expect("server should bind");
+    let addr = SocketAddr::from(([127, 0, 0, 1], server.local_addr().port()));
+    thread::spawn(move || {
+        let mut server = server;
+        server.run();
+    });
+
+    let client = ClientState::new(&addr.to_string(), protocol::DEFAULT_CHANNEL_ID, PHRASE).unwrap();
+    assert_eq!(client.stats(), ConnectionStats::default());
+    client.run(Mode::Gui).unwrap();
+
+    // the first second only sends the list request, the rtt lands in the next
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while client.stats().rtt_ms.is_none() && Instant::now() < deadline {
+        thread::sleep(Duration::from_millis(50));
+    }
+
+    let stats = client.stats();
+    assert!(stats.rtt_ms.is_some(), "no rtt after 5s");
+    // the list replies alone are traffic
+    assert!(stats.recv_kbps > 0.0);
+    // nobody talks, so nothing can go missing or fail to decode
+    assert_eq!(stats.loss, 0.0);
+    assert_eq!(stats.decode_errors, 0);
+
+    client.disconnect();
+}
+
+#[test]
+fn stats_read_well() {
+    let stats = ConnectionStats {
+        rtt_ms: Some(42),
+        loss: 0.025,
+        send_kbps: 32.0,
+        recv_kbps: 64.25,
+        decode_errors: 1,
+    };
+    assert_eq!(
+        stats.to_string(),
+        "rtt 42 ms, loss 2.5%, up 32.0 kbps, down 64.2 kbps, 1 decode error(s)"
+    );
+    assert!(
+        ConnectionStats::default()
+            .to_string()
+            .starts_with("rtt unknown")
+    );
+}
@@ -0,0 +1,109 @@
+use std::sync::Arc;
+
+use voudp::{
+    audit::AuditLog,
+    storage::{FileStorage, Storage},
+};
+
+mod common;
+
+use common::temp_dir;
+
+// every backend has to pass the same checks
+fn exercise(storage: &dyn Storage) {
+    assert_eq!(storage.get("bans", "alice").unwrap(), None);
+    assert!(storage.list("bans").unwrap().is_empty());
+
+    storage.put("bans", "alice", b"spam").unwrap();
+    storage.put("bans", "bob", b"").unwrap();
+    storage.put("history", "alice", b"hello").unwrap();
+    assert_eq!(
+        storage.get("bans", "alice").unwrap().as_deref(),
+        Some(&b"spam"[..])
+    );
+    assert_eq!(
+        storage.get("bans", "bob").unwrap().as_deref(),
+        Some(&b""[..])
+    );
+
+    // namespaces don't see each other
+    assert_eq!(storage.list("bans").unwrap(), vec!["alice", "bob"]);
+    assert_eq!(storage.list("history").unwrap(), vec!["alice"]);
+
+    storage.put("bans", "alice", b"spam again").unwrap();
+    assert_eq!(
+        storage.get("bans", "alice").unwrap().as_deref(),
+        Some(&b"spam again"[..])
+    );
+
+    assert!(storage.delete("bans", "alice").unwrap());
+    assert!(!storage.delete("bans", "alice").unwrap());
+    assert_eq!(storage.list("bans").unwrap(), vec!["bob"]);
+
+    // keys are arbitrary strings
+    for key in [
+        "127.0.0.1:4000",
+        "../../etc/passwd",
+        "名前",
+        "a b\tc",
+        ".tmp",
+        "%41",
+    ] {
+        storage.put("odd", key, key.as_bytes()).unwrap();
+        assert_eq!(
+            storage.get("odd", key).unwrap().as_deref(),
+            Some(key.as_bytes())
+        );
+    }
+    assert_eq!(storage.list("odd").unwrap().len(), 6);
+
+    assert!(storage.put("../escape", "key", b"").is_err());
+    assert!(storage.get("", "key").is_err());
+}
+
+#[test]
+fn file_storage() {
+    let dir = temp_dir("file");
+    exercise(&FileStorage::open(&dir));
+
+    // and it's all still there when reopened
+    let reopened = FileStorage::open(&dir);
+    assert_eq!(reopened.list("bans").unwrap(), vec!["bob"]);
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[cfg(feature = "sled")]
+#[test]
+fn sled_storage() {
+    let dir = temp_dir("sled");
+    {
+        let storage = voudp::storage::SledStorage::open(&dir).unwrap();
+        exercise(&storage);
+    }
+
+    let reopened = voudp::storage::SledStorage::open(&dir).unwrap();
+    assert_eq!(reopened.list("bans").unwrap(), vec!["bob"]);
+    drop(reopened);
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn audit_notes_survive_restart() {
+    let dir = temp_dir("audit");
+    let storage: Arc<dyn Storage> = Arc::new(FileStorage::open(&dir));
+    {
+        let mut audit = AuditLog::new(storage.clone());
+        audit.add_note("alice", "mod", "first\twarning");
+        audit.add_note("alice", "console", "second\nwarning");
+    }
+
+    let audit = AuditLog::new(storage);
+    let notes = audit.notes("alice");
+    assert_eq!(notes.len(), 2);
+    assert_eq!(notes[0].text, "first\twarning");
+    assert_eq!(notes[1].author, "console");
+    assert_eq!(notes[1].text, "second\nwarning");
+    assert!(audit.notes("bob").is_empty());
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
@@ -0,0 +1,133 @@
+use std::{
+    net::SocketAddr,
+    thread,
+    time::{Duration, Instant},
+};
+
+use opus2::{Application, Channels, Decoder, Encoder};
+use voudp::{
+    protocol::{self, ClientPacketType},
+    server::{ServerConfig, ServerState},
+    socket::{self, SecureUdpSocket},
+};
+
+mod common;
+
+use common::joined;
+
+const PHRASE: &[u8] = b"subscriptions-test";
+
+// a short burst of a 440Hz tone
+fn talk(socket: &SecureUdpSocket) {
+    let mut encoder = Encoder::new(48000, Channels::Stereo, Application::Audio).unwrap();
+    let tone = (0..960)
+        .flat_map(|n| {
+            let s = 0.3 * (2.0 * std::f32::consts::PI * 440.0 * n as f32 / 48000.0).sin();
+            [s, s]
+        })
+        .collect::<Vec<_>>();
+
+    let mut opus = vec![0u8; 400];
+    for _ in 0..15 {
+        let len = encoder.encode_float(&tone, &mut opus).unwrap();
+        socket
+            .send(&protocol::create_audio_packet(&opus[..len]))
+            .unwrap();
+        thread::sleep(Duration::from_millis(20));
+    }
+}
+
+// the loudest frame heard over the next second, `None` if nothing came
+fn loudest(socket: &SecureUdpSocket) -> Option<f32> {
+    let mut decoder = Decoder::new(48000, Channels::Stereo).unwrap();
+    let deadline = Instant::now() + Duration::from_secs(1);
+    let mut buf = [0u8; 2048];
+    let mut pcm = vec![0.0f32; 960 * 2];
+    let mut loudest = None::<f32>;
+
+    while Instant::now() < deadline {
+        match socket.recv_from(&mut buf) {
+            Ok((size, _)) if size > 5 && buf[0] == ClientPacketType::Audio as u8 => {
+                let len = decoder
+                    .decode_float(&buf[5..size], &mut pcm, false)
+                    .unwrap();
+                let rms =
+                    (pcm[..len * 2].iter().map(|s| s * s).sum::<f32>() / (len * 2) as f32).sqrt();
+                loudest = Some(loudest.map_or(rms, |l| l.max(rms)));
+            }
+            Ok(_) => {}
+            Err(_) => thread::sleep(Duration::from_millis(2)),
+        }
+    }
+
+    loudest
+}
+
+#[test]
+fn listeners_hear_subscribed_channels_quieter_and_only_talk_in_their_own() {
+    let config = ServerConfig {
+        max_joins_per_sec: 0,
+        // levels have to survive the output stage to be compared
+        should_normalize: false,
+        should_compress: false,
+        ..Default::default()
+    };
+    let server = ServerState::new(config, PHRASE).expect("server should bind");
+    let addr = SocketAddr::from(([127, 0, 0, 1], server.local_addr().port()));
+    thread::spawn(move || {
+        let mut server = server;
+        server.run();
+    });
+
+    let key = socket::derive_key_from_phrase(PHRASE, protocol::VOUDP_SALT);
+    let moderator = joined(key, addr, protocol::DEFAULT_CHANNEL_ID, None);
+    let talker = joined(key, addr, 2, None);
+    let member = joined(key, addr, 2, None);
+    thread::sleep(Duration::from_millis(100));
+
+    // not subscribed yet
+    let sender = thread::spawn({
+        let talker = talker.clone();
+        move || talk(&talker)
+    });
+    assert_eq!(loudest(&moderator), None);
+    sender.join().unwrap();
+
+    moderator
+        .send(&protocol::create_subscription_request(2, true))
+        .unwrap();
+    thread::sleep(Duration::from_millis(50));
+
+    let sender = thread::spawn({
+        let talker = talker.clone();
+        move || talk(&talker)
+    });
+    let heard = thread::spawn({
+        let member = member.clone();
+        move || loudest(&member)
+    });
+    let subscribed = loudest(&moderator).expect("subscribed channel was never heard");
+    let direct = heard
+        .join()
+        .unwrap()
+        .expect("member never heard the talker");
+    sender.join().unwrap();
+    let ratio = subscribed / direct;
+    assert!((0.35..0.65).contains(&ratio), "ratio {ratio}");
+
+    // the moderator's voice stays in their own channel
+    let sender = thread::spawn({
+        let moderator = moderator.clone();
+        move || talk(&moderator)
+    });
+    assert_eq!(loudest(&talker), None);
+    sender.join().unwrap();
+
+    moderator
+        .send(&protocol::create_subscription_request(2, false))
+        .unwrap();
+    thread::sleep(Duration::from_millis(50));
+    let sender = thread::spawn(move || talk(&talker));
+    assert_eq!(loudest(&moderator), None);
+    sender.join().unwrap();
+}
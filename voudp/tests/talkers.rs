@@ -0,0 +1,116 @@
+use std::{
+    net::SocketAddr,
+    thread,
+    time::{Duration, Instant},
+};
+
+use opus2::{Application, Channels, Encoder};
+use voudp::{
+    client::ClientState,
+    protocol::{self, ClientPacketType, FromPacket, IntoPacket},
+    server::{ServerConfig, ServerState},
+    socket::{self, SecureUdpSocket},
+    util::TalkersPacket,
+};
+
+mod common;
+
+use common::joined;
+
+const PHRASE: &[u8] = b"talkers-test";
+
+// waits for a talkers update matching `masks`, earlier ones are skipped
+fn recv_talkers(socket: &SecureUdpSocket, masks: &[&str]) -> bool {
+    let deadline = Instant::now() + Duration::from_secs(3);
+    let mut buf = [0u8; 2048];
+
+    while Instant::now() < deadline {
+        match socket.recv_from(&mut buf) {
+            Ok((size, _)) if size > 0 && buf[0] == ClientPacketType::Talkers as u8 => {
+                let talkers = TalkersPacket::deserialize(&buf[..size]).unwrap();
+                if talkers.masks == masks {
+                    return true;
+                }
+            }
+            Ok(_) => {}
+            Err(_) => thread::sleep(Duration::from_millis(2)),
+        }
+    }
+
+    false
+}
+
+#[test]
+fn talkers_packet_round_trip() {
+    let packet = TalkersPacket {
+        masks: vec!["alice".into(), "bob".into()],
+    };
+    assert_eq!(
+        TalkersPacket::deserialize(&packet.serialize()).unwrap(),
+        packet
+    );
+    assert_eq!(
+        TalkersPacket::deserialize(&TalkersPacket::default().serialize())
+            .unwrap()
+            .masks,
+        Vec::<String>::new()
+    );
+
+    // claims two talkers but only carries one
+    assert!(TalkersPacket::deserialize(&[ClientPacketType::Talkers as u8, 2, 1, b'a']).is_err());
+}
+
+#[test]
+fn server_announces_who_is_talking() {
+    let server = ServerState::new(ServerConfig::default(), PHRASE).expect("server should bind");
+    let addr = SocketAddr::from(([127, 0, 0, 1], server.local_addr().port()));
+    thread::spawn(move || {
+        let mut server = server;
+        server.run();
+    });
+
+    let key = socket::derive_key_from_phrase(PHRASE, protocol::VOUDP_SALT);
+    let alice = joined(key, addr, protocol::DEFAULT_CHANNEL_ID, Some("alice"));
+    let bob = joined(key, addr, protocol::DEFAULT_CHANNEL_ID, Some("bob"));
+    thread::sleep(Duration::from_millis(100));
+
+    let mut encoder = Encoder::new(48000, Channels::Stereo, Application::Audio).unwrap();
+    let tone = (0..960)
+        .flat_map(|n| {
+            let s = 0.3 * (2.0 * std::f32::consts::PI * 500.0 * n as f32 / 48000.0).sin();
+            [s, s]
+        })
+        .collect::<Vec<_>>();
+    let mut opus = vec![0u8; 400];
+    for _ in 0..10 {
+        let len = encoder.encode_float(&tone, &mut opus).unwrap();
+        alice
+            .send(&protocol::create_audio_packet(&opus[..len]))
+            .unwrap();
+        thread::sleep(Duration::from_millis(20));
+    }
+
+    assert!(recv_talkers(&bob, &["alice"]), "bob never heard alice");
+    // once she stops the channel hears that too
+    assert!(recv_talkers(&bob, &[]), "alice never went quiet");
+}
+
+#[test]
+fn user_volumes_are_remembered() {
+    let client = ClientState::new("127.0.0.1:37549", protocol::DEFAULT_CHANNEL_ID, PHRASE).unwrap();
+    assert_eq!(client.user_volume("bob"), 1.0);
+
+    assert_eq!(client.set_user_volume("bob", 0.0), 0.0);
+    assert_eq!(client.user_volume("bob"), 0.0);
+
+    // louder than the server allows gets clamped
+    assert_eq!(
+        client.set_user_volume("carol", 10.0),
+        protocol::MAX_TALKER_GAIN
+    );
+
+    client.set_user_volume("bob", 1.0);
+    let volumes = client.volumes.lock().unwrap();
+    assert!(!volumes.contains_key("bob"));
+    assert_eq!(volumes.len(), 1);
+}
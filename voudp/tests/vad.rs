@@ -0,0 +1,60 @@
+use voudp::{
+    util::AtomicF32,
+    vad::{self, VAD_HANGOVER_FRAMES, Vad},
+};
+
+const FRAME: usize = 1920; // 20ms of interleaved stereo
+
+fn tone(amplitude: f32) -> Vec<f32> {
+    (0..FRAME / 2)
+        .flat_map(|n| {
+            let s = amplitude * (2.0 * std::f32::consts::PI * 500.0 * n as f32 / 48000.0).sin();
+            [s, s]
+        })
+        .collect()
+}
+
+#[test]
+fn silence_is_never_sent() {
+    let mut vad = Vad::new(vad::DEFAULT_VAD_THRESHOLD);
+    for _ in 0..50 {
+        assert!(!vad.process(&vec![0.0; FRAME]));
+        // background hiss stays under the threshold too
+        assert!(!vad.process(&tone(0.01)));
+    }
+}
+
+#[test]
+fn speech_is_sent_with_a_hangover() {
+    let mut vad = Vad::new(vad::DEFAULT_VAD_THRESHOLD);
+    assert!(vad.process(&tone(0.3)));
+
+    let silence = vec![0.0; FRAME];
+    for _ in 0..VAD_HANGOVER_FRAMES {
+        assert!(vad.process(&silence));
+    }
+    assert!(!vad.process(&silence));
+
+    // speaking again restarts the hangover
+    assert!(vad.process(&tone(0.3)));
+    assert!(vad.process(&silence));
+}
+
+#[test]
+fn threshold_is_adjustable() {
+    let quiet = tone(0.02);
+    let mut vad = Vad::new(vad::DEFAULT_VAD_THRESHOLD);
+    assert!(!vad.process(&quiet));
+
+    vad.set_threshold(0.01);
+    assert!(vad.process(&quiet));
+
+    // zero sends everything, even silence
+    let mut open = Vad::new(0.0);
+    assert!(open.process(&vec![0.0; FRAME]));
+
+    let shared = AtomicF32::new(0.25);
+    shared.store(0.5);
+    assert_eq!(shared.load(), 0.5);
+    assert!((vad::rms(&tone(1.0)) - std::f32::consts::FRAC_1_SQRT_2).abs() < 1e-3);
+}